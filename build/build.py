@@ -19,6 +19,8 @@ def get_platform_lib_path(platform):
         lib_name = "libpython3.10.so"
     elif platform == "linux-aarch64":
         lib_name = "libpython3.10.so"
+    elif platform in ("linux-musl", "linux-musl-aarch64"):
+        lib_name = "libpython3.10.so"
     elif platform == "windows-amd64":
         lib_name = "python310.dll"
     else:
@@ -75,6 +77,10 @@ def get_platform_tag(platform):
         return "manylinux1_x86_64"
     elif platform == "linux-aarch64":
         return "manylinux2014_aarch64"
+    elif platform == "linux-musl":
+        return "musllinux_1_2_x86_64"
+    elif platform == "linux-musl-aarch64":
+        return "musllinux_1_2_aarch64"
     elif platform == "windows-amd64":
         return "win_amd64"
     return None
@@ -173,6 +179,7 @@ def build_rust_framework(out_dir):
         print("Building Rust framework for macOS ARM64 with cargo...")
         env = os.environ.copy()
         env["RUSTFLAGS"] = f"-L {os.path.abspath('libs/macos-arm64')}"
+        env["NOVENTA_PYTHON_LIB_DIR"] = os.path.abspath('libs/macos-arm64')
         run_command("PYO3_NO_PYTHON=1 cargo build --release --target aarch64-apple-darwin", cwd=framework_dir, env=env)
         
         macos_arm_out_dir = os.path.join(out_dir, "macos-arm64")
@@ -192,6 +199,7 @@ def build_rust_framework(out_dir):
         print("Building Rust framework for macOS x86_64 with cargo...")
         env = os.environ.copy()
         env["RUSTFLAGS"] = f"-L {os.path.abspath('libs/macos-x86_64')}"
+        env["NOVENTA_PYTHON_LIB_DIR"] = os.path.abspath('libs/macos-x86_64')
         run_command("PYO3_NO_PYTHON=1 cargo build --release --target x86_64-apple-darwin", cwd=framework_dir, env=env)
 
         macos_x86_out_dir = os.path.join(out_dir, "macos-x86_64")
@@ -213,6 +221,7 @@ def build_rust_framework(out_dir):
     if "DYLD_LIBRARY_PATH" in env:
         del env["DYLD_LIBRARY_PATH"]
     #env["RUSTFLAGS"] = f"-L {os.path.abspath('libs/linux')} -C link-arg=-Wl,--disable-new-dtags"
+    env["NOVENTA_PYTHON_LIB_DIR"] = os.path.abspath('libs/linux')
     run_command("cargo zigbuild --target x86_64-unknown-linux-gnu --release", cwd=framework_dir, env=env)
     
     linux_out_dir = os.path.join(out_dir, "linux")
@@ -234,6 +243,7 @@ def build_rust_framework(out_dir):
     if "DYLD_LIBRARY_PATH" in env:
         del env["DYLD_LIBRARY_PATH"]
     env["RUSTFLAGS"] = f"-L {os.path.abspath('libs/linux-aarch64')} -C link-arg=-Wl,--disable-new-dtags"
+    env["NOVENTA_PYTHON_LIB_DIR"] = os.path.abspath('libs/linux-aarch64')
     run_command("cargo zigbuild --target aarch64-unknown-linux-gnu --release", cwd=framework_dir, env=env)
 
     linux_aarch64_out_dir = os.path.join(out_dir, "linux-aarch64")
@@ -249,10 +259,39 @@ def build_rust_framework(out_dir):
     else:
         print(f"Warning: expected aarch64 binary at {binary_src_aarch64} not found")
 
+    # Cross-compile for Alpine and other musl distros, when their libpython is in libs/
+    for platform, target in [("linux-musl", "x86_64-unknown-linux-musl"), ("linux-musl-aarch64", "aarch64-unknown-linux-musl")]:
+        lib_dir = os.path.abspath(os.path.join("libs", platform))
+        if not os.path.isdir(lib_dir):
+            print(f"Skipping {target}: put musl's libpython3.10.so in {lib_dir} to build it.")
+            continue
+        print(f"Cross-compiling Rust framework for {target} with cargo zigbuild...")
+        env = os.environ.copy()
+        if "DYLD_LIBRARY_PATH" in env:
+            del env["DYLD_LIBRARY_PATH"]
+        # libpython is a shared library, so the binary can't be fully static.
+        env["RUSTFLAGS"] = "-C target-feature=-crt-static"
+        env["NOVENTA_PYTHON_LIB_DIR"] = lib_dir
+        run_command(f"PYO3_NO_PYTHON=1 cargo zigbuild --target {target} --release", cwd=framework_dir, env=env)
+
+        musl_out_dir = os.path.join(out_dir, platform)
+        os.makedirs(musl_out_dir, exist_ok=True)
+
+        binary_src_musl = os.path.join(framework_dir, "target", target, "release", "noventa")
+        if os.path.exists(binary_src_musl):
+            dest_musl = os.path.join(musl_out_dir, "noventa")
+            shutil.copy(binary_src_musl, dest_musl)
+            print(f"Copied {target} binary to {dest_musl}")
+            lib_path = get_platform_lib_path(platform)
+            package_wheel(out_dir, dest_musl, platform, dll_path=lib_path)
+        else:
+            print(f"Warning: expected {target} binary at {binary_src_musl} not found")
+
     # Cross-compile for Windows AMD64
     print("Cross-compiling Rust framework for Windows AMD64 with cargo xwin...")
     env = os.environ.copy()
     env["RUSTFLAGS"] = f"-L {os.path.abspath('libs/windows-amd64')}"
+    env["NOVENTA_PYTHON_LIB_DIR"] = os.path.abspath('libs/windows-amd64')
     run_command("PYO3_NO_PYTHON=1 cargo xwin build --target x86_64-pc-windows-msvc --release", cwd=framework_dir, env=env)
 
     windows_amd64_out_dir = os.path.join(out_dir, "windows-amd64")
@@ -291,7 +330,7 @@ if __name__ == "__main__":
     package_vscode_extension(output_directory)
 
     # Clean up platform-specific directories
-    for platform in ["macos-arm64", "macos-x86_64", "linux", "linux-aarch64", "windows-amd64"]:
+    for platform in ["macos-arm64", "macos-x86_64", "linux", "linux-aarch64", "linux-musl", "linux-musl-aarch64", "windows-amd64"]:
         platform_dir = os.path.join(output_directory, platform)
         if os.path.exists(platform_dir):
             shutil.rmtree(platform_dir)
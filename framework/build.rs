@@ -1,14 +1,22 @@
+// Release builds cross-compile against the libpython in build/libs/<platform>, which build.py
+// passes as NOVENTA_PYTHON_LIB_DIR. Local builds leave it unset and pyo3 links the Python it
+// detects (PYO3_PYTHON, or the one on PATH).
 fn main() {
+    println!("cargo:rerun-if-env-changed=NOVENTA_PYTHON_LIB_DIR");
+    println!("cargo:rerun-if-env-changed=NOVENTA_PYTHON_LIB");
     let target_os = std::env::var("CARGO_CFG_TARGET_OS").unwrap();
-    
+
+    if let Ok(lib_dir) = std::env::var("NOVENTA_PYTHON_LIB_DIR") {
+        let default_lib = if target_os == "windows" { "python310" } else { "python3.10" };
+        let lib = std::env::var("NOVENTA_PYTHON_LIB").unwrap_or_else(|_| default_lib.to_string());
+        println!("cargo:rustc-link-search=native={}", lib_dir);
+        println!("cargo:rustc-link-lib=dylib={}", lib);
+    }
+
+    // A libpython shipped next to the binary (or in lib/ beside it) is found without LD_LIBRARY_PATH.
     if target_os == "linux" {
-        println!("cargo:rustc-link-search=native=/Users/marcos/Downloads/python_linux/lib");
-        println!("cargo:rustc-link-lib=dylib=python3.10");
-    } else if target_os == "windows" {
-        println!("cargo:rustc-link-search=native=/Users/marcos/Downloads/python_windows/libs");
-        println!("cargo:rustc-link-lib=dylib=python310");
+        println!("cargo:rustc-link-arg-bins=-Wl,-rpath,$ORIGIN:$ORIGIN/lib");
     } else if target_os == "macos" {
-        println!("cargo:rustc-link-search=native=/Users/marcos/.conda/envs/py310/lib");
-        println!("cargo:rustc-link-lib=dylib=python3.10");
+        println!("cargo:rustc-link-arg-bins=-Wl,-rpath,@executable_path");
     }
 }
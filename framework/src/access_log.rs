@@ -0,0 +1,218 @@
+use crate::config::{self, AccessLogFormat};
+use actix_web::body::{BodySize, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{HeaderMap, HeaderName, HeaderValue};
+use actix_web::middleware::Next;
+use actix_web::HttpMessage;
+use chrono::{DateTime, Local};
+use once_cell::sync::Lazy;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::sync::Mutex;
+use std::time::Instant;
+
+// A line for every request, in Apache's combined format or as JSON, with the page that answered it,
+// how long it took and its request id. The id comes from the caller's `X-Request-Id` when it sends
+// a sensible one, and goes back in the response's.
+
+const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+// Put in the request's extensions.
+#[derive(Clone, Debug)]
+pub struct RequestId(pub String);
+
+// Put in a page's response by `routing::handle_page`: the template that rendered it, e.g.
+// `pages/products/[id].html`, which groups requests better than their paths do.
+#[derive(Clone, Debug)]
+pub struct Route(pub String);
+
+static FILE: Lazy<Option<Mutex<File>>> = Lazy::new(|| {
+    let path = config::CONFIG.access_log.as_ref()?.path.as_ref()?;
+    let path = config::BASE_PATH.join(path);
+    if let Some(folder) = path.parent() {
+        let _ = std::fs::create_dir_all(folder);
+    }
+    match OpenOptions::new().create(true).append(true).open(&path) {
+        Ok(file) => Some(Mutex::new(file)),
+        Err(e) => {
+            log::warn!("Heads up! The access log can't be written to {} ({}), so it goes out with the other logs instead.", path.display(), e);
+            None
+        }
+    }
+});
+
+pub fn enabled(dev_mode: bool) -> bool {
+    config::CONFIG.access_log.as_ref().and_then(|access_log| access_log.enabled).unwrap_or(!dev_mode)
+}
+
+fn format() -> AccessLogFormat {
+    config::CONFIG.access_log.as_ref().and_then(|access_log| access_log.format).unwrap_or_default()
+}
+
+fn request_id(headers: &HeaderMap) -> String {
+    headers
+        .get(&REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|id| !id.is_empty() && id.len() <= MAX_REQUEST_ID_LEN)
+        .filter(|id| id.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.')))
+        .map_or_else(|| uuid::Uuid::new_v4().to_string(), str::to_string)
+}
+
+struct Entry {
+    time: DateTime<Local>,
+    remote_addr: Option<String>,
+    method: String,
+    target: String,
+    version: String,
+    status: u16,
+    bytes: Option<u64>,
+    duration_ms: f64,
+    route: Option<String>,
+    referer: Option<String>,
+    user_agent: Option<String>,
+    request_id: String,
+}
+
+// Quotes in what the client sent would end the field early.
+fn quoted(value: Option<&str>) -> String {
+    match value {
+        Some(value) => format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\"")),
+        None => "\"-\"".to_string(),
+    }
+}
+
+impl Entry {
+    // e.g. `203.0.113.9 - - [16/Oct/2026:10:04:12 +0000] "GET /cart HTTP/1.1" 200 5120 "-" "curl/8.5" pages/cart.html 12.4ms 5f0c…`
+    fn combined(&self) -> String {
+        format!(
+            "{} - - [{}] {} {} {} {} {} {} {:.1}ms {}",
+            self.remote_addr.as_deref().unwrap_or("-"),
+            self.time.format("%d/%b/%Y:%H:%M:%S %z"),
+            quoted(Some(&format!("{} {} {}", self.method, self.target, self.version))),
+            self.status,
+            self.bytes.map_or_else(|| "-".to_string(), |bytes| bytes.to_string()),
+            quoted(self.referer.as_deref()),
+            quoted(self.user_agent.as_deref()),
+            self.route.as_deref().unwrap_or("-"),
+            self.duration_ms,
+            self.request_id,
+        )
+    }
+
+    fn json(&self) -> String {
+        serde_json::json!({
+            "time": self.time.to_rfc3339(),
+            "remote_addr": self.remote_addr,
+            "method": self.method,
+            "target": self.target,
+            "protocol": self.version,
+            "status": self.status,
+            "bytes": self.bytes,
+            "duration_ms": (self.duration_ms * 10.0).round() / 10.0,
+            "route": self.route,
+            "referer": self.referer,
+            "user_agent": self.user_agent,
+            "request_id": self.request_id,
+        })
+        .to_string()
+    }
+}
+
+fn write(line: &str) {
+    match FILE.as_ref() {
+        Some(file) => {
+            let mut file = file.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            let _ = writeln!(file, "{}", line);
+        }
+        None => log::info!(target: "noventa::access", "{}", line),
+    }
+}
+
+pub async fn access_log(req: ServiceRequest, next: Next<impl MessageBody>) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let started = Instant::now();
+    let time = Local::now();
+    let request_id = request_id(req.headers());
+    req.extensions_mut().insert(RequestId(request_id.clone()));
+    let header = |name: &str| req.headers().get(name).and_then(|value| value.to_str().ok()).map(str::to_string);
+    let (referer, user_agent) = (header("referer"), header("user-agent"));
+    let remote_addr = crate::proxy::client_info(req.request(), &crate::proxy::TRUSTED_PROXIES).remote_addr;
+    let method = req.method().to_string();
+    let target = req.uri().path_and_query().map_or_else(|| req.path().to_string(), |target| target.to_string());
+    let version = format!("{:?}", req.version());
+
+    let mut res = next.call(req).await?;
+    if let Ok(value) = HeaderValue::from_str(&request_id) {
+        res.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    let entry = Entry {
+        time,
+        remote_addr,
+        method,
+        target,
+        version,
+        status: res.status().as_u16(),
+        bytes: match res.response().body().size() {
+            BodySize::Sized(bytes) => Some(bytes),
+            BodySize::None => Some(0),
+            // Streamed, so not known yet.
+            BodySize::Stream => None,
+        },
+        duration_ms: started.elapsed().as_secs_f64() * 1000.0,
+        route: res.response().extensions().get::<Route>().map(|route| route.0.clone()),
+        referer,
+        user_agent,
+        request_id,
+    };
+    write(&match format() {
+        AccessLogFormat::Combined => entry.combined(),
+        AccessLogFormat::Json => entry.json(),
+    });
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn entry() -> Entry {
+        Entry {
+            time: Local.with_ymd_and_hms(2026, 10, 16, 10, 4, 12).unwrap(),
+            remote_addr: Some("203.0.113.9".to_string()),
+            method: "GET".to_string(),
+            target: "/cart?step=2".to_string(),
+            version: "HTTP/1.1".to_string(),
+            status: 200,
+            bytes: Some(5120),
+            duration_ms: 12.44,
+            route: Some("pages/cart.html".to_string()),
+            referer: None,
+            user_agent: Some("Mozilla \"5.0\"".to_string()),
+            request_id: "abc-123".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_formats() {
+        let line = entry().combined();
+        assert!(line.starts_with("203.0.113.9 - - [16/Oct/2026:10:04:12 "), "{}", line);
+        assert!(line.ends_with("] \"GET /cart?step=2 HTTP/1.1\" 200 5120 \"-\" \"Mozilla \\\"5.0\\\"\" pages/cart.html 12.4ms abc-123"), "{}", line);
+
+        let json: serde_json::Value = serde_json::from_str(&entry().json()).unwrap();
+        assert_eq!(json["route"], "pages/cart.html");
+        assert_eq!(json["duration_ms"], 12.4);
+        assert_eq!(json["referer"], serde_json::Value::Null);
+        assert_eq!(json["user_agent"], "Mozilla \"5.0\"");
+    }
+
+    #[test]
+    fn test_request_id() {
+        let mut headers = HeaderMap::new();
+        headers.insert(REQUEST_ID_HEADER, HeaderValue::from_static("lb-7f3a.2"));
+        assert_eq!(request_id(&headers), "lb-7f3a.2");
+        headers.insert(REQUEST_ID_HEADER, HeaderValue::from_static("no spaces or \"quotes\""));
+        assert_eq!(request_id(&headers).len(), 36);
+        assert_eq!(request_id(&HeaderMap::new()).len(), 36);
+    }
+}
@@ -1,11 +1,28 @@
 use actix::prelude::*;
 use actix_web_actors::ws;
 use crate::actors::ws_server::{WsServer, Connect, Disconnect};
+use serde::Deserialize;
 
 #[derive(Message)]
 #[rtype(result = "()")]
 pub struct ReloadMessage;
 
+// A serialized DetailedError to show in the browser's error overlay.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct ErrorMessage(pub String);
+
+// Console output, uncaught errors and failed requests reported by devws.js.
+#[derive(Debug, Deserialize)]
+struct BrowserLog {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    level: String,
+    #[serde(default)]
+    message: String,
+}
+
 pub struct DevWebSocket {
     server_addr: Addr<WsServer>,
 }
@@ -21,7 +38,8 @@ impl Actor for DevWebSocket {
 
     fn started(&mut self, ctx: &mut Self::Context) {
         let addr = ctx.address().recipient();
-        self.server_addr.do_send(Connect { addr });
+        let errors = ctx.address().recipient();
+        self.server_addr.do_send(Connect { addr, errors });
     }
 
     fn stopping(&mut self, ctx: &mut Self::Context) -> Running {
@@ -35,6 +53,7 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for DevWebSocket {
     fn handle(&mut self, msg: Result<ws::Message, ws::ProtocolError>, ctx: &mut Self::Context) {
         match msg {
             Ok(ws::Message::Ping(msg)) => ctx.pong(&msg),
+            Ok(ws::Message::Text(text)) => handle_browser_message(&text),
             Err(e) => log::error!("The live-reload connection failed: {:?}. Your browser might not auto-refresh when you save files. Try refreshing the page manually.", e),
             _ => (),
         }
@@ -49,6 +68,26 @@ impl Handler<ReloadMessage> for DevWebSocket {
     }
 }
 
+impl Handler<ErrorMessage> for DevWebSocket {
+    type Result = ();
+
+    fn handle(&mut self, msg: ErrorMessage, ctx: &mut Self::Context) {
+        ctx.text(error_payload(&msg.0));
+    }
+}
+
+fn handle_browser_message(text: &str) {
+    match serde_json::from_str::<BrowserLog>(text) {
+        Ok(log) if log.kind == "console" => crate::logger::log_browser_message(&log.level, &log.message),
+        Ok(log) => log::debug!("Ignoring unknown dev websocket message type '{}'", log.kind),
+        Err(e) => log::debug!("Ignoring malformed dev websocket message: {}", e),
+    }
+}
+
+fn error_payload(error_json: &str) -> String {
+    format!(r#"{{"type":"error","error":{}}}"#, error_json)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -128,6 +167,27 @@ mod tests {
         assert!(true);
     }
 
+    #[test]
+    fn test_error_payload_wraps_error_json() {
+        let payload = error_payload(r#"{"message":"boom"}"#);
+        let parsed: serde_json::Value = serde_json::from_str(&payload).unwrap();
+        assert_eq!(parsed["type"], "error");
+        assert_eq!(parsed["error"]["message"], "boom");
+    }
+
+    #[test]
+    fn test_browser_log_deserialization() {
+        let log: BrowserLog =
+            serde_json::from_str(r#"{"type":"console","level":"warn","message":"careful"}"#).unwrap();
+        assert_eq!(log.kind, "console");
+        assert_eq!(log.level, "warn");
+        assert_eq!(log.message, "careful");
+
+        let log: BrowserLog = serde_json::from_str(r#"{"type":"console"}"#).unwrap();
+        assert_eq!(log.level, "");
+        assert_eq!(log.message, "");
+    }
+
     // Test the message types
     #[test]
     fn test_reload_message_is_unit_struct() {
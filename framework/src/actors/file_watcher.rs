@@ -5,6 +5,10 @@ use crate::actors::ws_server::{WsServer, BroadcastReload};
 use crate::actors::router::{RouterActor, ReloadRoutes};
 use crate::actors::template_renderer::{TemplateRendererActor, UpdateComponents};
 use crate::actors::interpreter::{PythonInterpreterActor, ReloadInterpreter};
+use crate::dev_events::{self, EventKind};
+
+// An actor working through a change; awaited before the browsers are told to reload.
+type PendingUpdate = std::pin::Pin<Box<dyn std::future::Future<Output = std::result::Result<(), MailboxError>> + Send>>;
 
 pub struct FileWatcherActor {
     ws_server_addr: Addr<WsServer>,
@@ -69,23 +73,43 @@ impl Actor for FileWatcherActor {
                             return;
                         }
 
+                        if relative_path == Path::new(crate::lsp::PORT_FILE) {
+                            return;
+                        }
+
                         log::debug!("Detected a change in: {:?}", relative_path);
+                        let changed_at = std::time::Instant::now();
+                        let changed_path = crate::paths::to_slash(relative_path);
+                        dev_events::record(EventKind::FileChanged, Some(&changed_path), None, None);
+                        crate::lsp::files_changed();
+
+                        if [&pages_path, &components_path, &layouts_path].iter().any(|dir| relative_path.starts_with(dir))
+                            || relative_path.starts_with(crate::components::PACKS_DIR)
+                        {
+                            crate::lsp_index::invalidate();
+                        }
+
+                        // Templates, layouts and anything they include are compiled again on the next render.
+                        if relative_path.extension().is_none_or(|ext| ext != "py") {
+                            crate::actors::template_renderer::invalidate_templates();
+                        }
 
-                        let mut futures = Vec::new();
+                        let mut futures: Vec<(EventKind, PendingUpdate)> = Vec::new();
 
                         if relative_path.starts_with(&pages_path) {
                             log::debug!("A page has changed. Reloading the routes now!");
                             let future = router_addr.send(ReloadRoutes);
-                            futures.push(Box::pin(future) as std::pin::Pin<Box<dyn std::future::Future<Output = _> + Send>>);
-                        } else if relative_path.starts_with(&components_path) {
+                            futures.push((EventKind::RoutesReloaded, Box::pin(future) as PendingUpdate));
+                        } else if relative_path.starts_with(&components_path) || relative_path.starts_with(crate::components::PACKS_DIR) {
                             log::debug!("A component has changed. Rescanning all components now!");
-                            match crate::components::scan_components(&components_path) {
+                            match crate::components::scan_project_components(Path::new(".")) {
                                 Ok(components) => {
                                     let future = template_renderer_addr.send(UpdateComponents(components));
-                                    futures.push(Box::pin(future) as std::pin::Pin<Box<dyn std::future::Future<Output = _> + Send>>);
+                                    futures.push((EventKind::ComponentsRescanned, Box::pin(future) as PendingUpdate));
                                 }
                                 Err(e) => {
                                     log::error!("Failed to rescan components: {}", e);
+                                    dev_events::record(EventKind::ComponentsRescanned, None, Some(changed_at.elapsed()), Some(e.to_string()));
                                 }
                             }
                         }
@@ -93,15 +117,18 @@ impl Actor for FileWatcherActor {
                         if relative_path.extension().map_or(false, |ext| ext == "py") {
                             log::debug!("A Python file has changed. Reloading the interpreter now!");
                             let future = interpreter_addr.send(ReloadInterpreter);
-                            futures.push(Box::pin(future) as std::pin::Pin<Box<dyn std::future::Future<Output = _> + Send>>);
+                            futures.push((EventKind::InterpreterReloaded, Box::pin(future) as PendingUpdate));
                         }
 
                         // Block until all actor updates are complete
-                        for future in futures {
-                            if let Err(e) = futures::executor::block_on(future) {
+                        for (kind, future) in futures {
+                            let error = futures::executor::block_on(future).err().map(|e| {
                                 log::error!("Error waiting for actor to handle message: {}", e);
-                            }
+                                e.to_string()
+                            });
+                            dev_events::record(kind, None, Some(changed_at.elapsed()), error);
                         }
+                        dev_events::record(EventKind::BrowserReloaded, Some(&changed_path), Some(changed_at.elapsed()), None);
                     }
                     // Only broadcast reload after all updates are done
                     ws_server_addr.do_send(BroadcastReload);
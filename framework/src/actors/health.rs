@@ -1,8 +1,16 @@
 use actix::prelude::*;
+use crate::actors::load_shedding::{self, LoadSheddingStats};
+use crate::compressed_pages::{self, CompressedPageStats};
+use crate::interpreter_queue::{self, InterpreterQueueStats};
 use serde::Serialize;
-use std::collections::VecDeque;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fmt::Write;
 use std::time::{Duration, Instant};
 
+// How far back a route's latency percentiles look, and how many of its timings are kept for them.
+const ROUTE_WINDOW: Duration = Duration::from_secs(300);
+const MAX_ROUTE_SAMPLES: usize = 10_000;
+
 // --- Messages ---
 
 #[derive(Message)]
@@ -17,6 +25,17 @@ pub struct ReportTemplateLatency(pub f64);
 #[rtype(result = "()")]
 pub struct ReportRtt(pub f64);
 
+// A page request that was answered: which page, with what status, how long it took, and whether
+// Python raised on the way.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct ReportRequest {
+    pub route: String,
+    pub status: u16,
+    pub duration_ms: f64,
+    pub python_error: bool,
+}
+
 #[derive(Message)]
 #[rtype(result = "SystemHealth")]
 pub struct GetSystemHealth;
@@ -43,6 +62,86 @@ pub struct SystemHealth {
     pub thirty_seconds: TimeWindowMetrics,
     pub one_minute: TimeWindowMetrics,
     pub five_minutes: TimeWindowMetrics,
+    // Compressed page bodies served from memory instead of compressed again.
+    pub compressed_pages: CompressedPageStats,
+    // Calls waiting for a Python interpreter, and how long they waited.
+    pub python_queue: InterpreterQueueStats,
+    // Requests the load shedder turned away.
+    pub load_shedding: LoadSheddingStats,
+    // Every page that was asked for, slowest first.
+    pub routes: Vec<RouteStats>,
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct RouteStats {
+    // The page's template, e.g. `pages/products/[id].html`.
+    pub route: String,
+    // Counted since the server started.
+    pub requests: u64,
+    pub statuses: BTreeMap<u16, u64>,
+    pub python_errors: u64,
+    // The share of requests answered with a 5xx.
+    pub error_rate: f64,
+    // Over the last five minutes.
+    pub p50_ms: f64,
+    pub p95_ms: f64,
+    pub p99_ms: f64,
+    pub mean_ms: f64,
+}
+
+#[derive(Default)]
+struct RouteData {
+    requests: u64,
+    statuses: BTreeMap<u16, u64>,
+    python_errors: u64,
+    latencies: VecDeque<MetricDataPoint>,
+}
+
+impl RouteData {
+    fn record(&mut self, msg: &ReportRequest, now: Instant) {
+        self.requests += 1;
+        *self.statuses.entry(msg.status).or_default() += 1;
+        if msg.python_error {
+            self.python_errors += 1;
+        }
+        while self.latencies.front().is_some_and(|dp| now.duration_since(dp.timestamp) >= ROUTE_WINDOW)
+            || self.latencies.len() >= MAX_ROUTE_SAMPLES
+        {
+            self.latencies.pop_front();
+        }
+        self.latencies.push_back(MetricDataPoint { timestamp: now, value: msg.duration_ms });
+    }
+
+    fn stats(&self, route: &str, now: Instant) -> RouteStats {
+        let mut values: Vec<f64> = self
+            .latencies
+            .iter()
+            .filter(|dp| now.duration_since(dp.timestamp) < ROUTE_WINDOW)
+            .map(|dp| dp.value)
+            .collect();
+        values.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let server_errors: u64 = self.statuses.range(500..600).map(|(_, count)| count).sum();
+        RouteStats {
+            route: route.to_string(),
+            requests: self.requests,
+            statuses: self.statuses.clone(),
+            python_errors: self.python_errors,
+            error_rate: server_errors as f64 / self.requests.max(1) as f64,
+            p50_ms: percentile(&values, 0.50),
+            p95_ms: percentile(&values, 0.95),
+            p99_ms: percentile(&values, 0.99),
+            mean_ms: if values.is_empty() { 0.0 } else { values.iter().sum::<f64>() / values.len() as f64 },
+        }
+    }
+}
+
+// Of values sorted in ascending order.
+fn percentile(sorted: &[f64], quantile: f64) -> f64 {
+    if sorted.is_empty() {
+        return 0.0;
+    }
+    let index = (sorted.len() as f64 * quantile).floor() as usize;
+    sorted[index.min(sorted.len() - 1)]
 }
 
 struct MetricDataPoint {
@@ -56,6 +155,7 @@ pub struct HealthActor {
     rtt_data: VecDeque<MetricDataPoint>,
     python_latency_data: VecDeque<MetricDataPoint>,
     template_latency_data: VecDeque<MetricDataPoint>,
+    routes: HashMap<String, RouteData>,
 }
 
 impl HealthActor {
@@ -64,8 +164,16 @@ impl HealthActor {
             rtt_data: VecDeque::new(),
             python_latency_data: VecDeque::new(),
             template_latency_data: VecDeque::new(),
+            routes: HashMap::new(),
         }
     }
+
+    fn route_stats(&self) -> Vec<RouteStats> {
+        let now = Instant::now();
+        let mut routes: Vec<RouteStats> = self.routes.iter().map(|(route, data)| data.stats(route, now)).collect();
+        routes.sort_by(|a, b| b.p95_ms.partial_cmp(&a.p95_ms).unwrap().then_with(|| a.route.cmp(&b.route)));
+        routes
+    }
 }
 
 impl Actor for HealthActor {
@@ -104,6 +212,12 @@ impl Handler<ReportTemplateLatency> for HealthActor {
     }
 }
 
+impl Handler<ReportRequest> for HealthActor {
+    type Result = ();
+    fn handle(&mut self, msg: ReportRequest, _ctx: &mut Context<Self>) {
+        self.routes.entry(msg.route.clone()).or_default().record(&msg, Instant::now());
+    }
+}
 
 impl Handler<GetSystemHealth> for HealthActor {
     type Result = MessageResult<GetSystemHealth>;
@@ -117,6 +231,10 @@ impl Handler<GetSystemHealth> for HealthActor {
             thirty_seconds: thirty_seconds_metrics.clone(),
             one_minute: thirty_seconds_metrics.clone(), // Placeholder
             five_minutes: thirty_seconds_metrics, // Placeholder
+            compressed_pages: compressed_pages::stats(),
+            python_queue: interpreter_queue::stats(),
+            load_shedding: load_shedding::stats(),
+            routes: self.route_stats(),
         })
     }
 }
@@ -170,6 +288,46 @@ impl HealthActor {
     }
 }
 
+// /metrics, in Prometheus' text format.
+pub fn prometheus(health: &SystemHealth) -> String {
+    let mut out = String::new();
+    let label = |value: &str| value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n");
+    let window = &health.thirty_seconds;
+    let _ = writeln!(out, "# HELP noventa_latency_ms Latency over the last 30 seconds.");
+    let _ = writeln!(out, "# TYPE noventa_latency_ms gauge");
+    for (part, metrics) in [("request", &window.rtt), ("python", &window.python_interpreter), ("template", &window.template_renderer)] {
+        let _ = writeln!(out, "noventa_latency_ms{{part=\"{}\",stat=\"p95\"}} {}", part, metrics.p95_ms);
+        let _ = writeln!(out, "noventa_latency_ms{{part=\"{}\",stat=\"mean\"}} {}", part, metrics.mean_ms);
+    }
+    let _ = writeln!(out, "# HELP noventa_python_queue_depth Calls waiting for a Python interpreter.");
+    let _ = writeln!(out, "# TYPE noventa_python_queue_depth gauge");
+    let _ = writeln!(out, "noventa_python_queue_depth {}", health.python_queue.depth);
+    let _ = writeln!(out, "# HELP noventa_shed_requests_total Requests the load shedder turned away.");
+    let _ = writeln!(out, "# TYPE noventa_shed_requests_total counter");
+    let _ = writeln!(out, "noventa_shed_requests_total {}", health.load_shedding.dropped);
+
+    let _ = writeln!(out, "# HELP noventa_route_requests_total Page requests by route and status.");
+    let _ = writeln!(out, "# TYPE noventa_route_requests_total counter");
+    for route in &health.routes {
+        for (status, count) in &route.statuses {
+            let _ = writeln!(out, "noventa_route_requests_total{{route=\"{}\",status=\"{}\"}} {}", label(&route.route), status, count);
+        }
+    }
+    let _ = writeln!(out, "# HELP noventa_route_python_errors_total Page requests where Python raised.");
+    let _ = writeln!(out, "# TYPE noventa_route_python_errors_total counter");
+    for route in &health.routes {
+        let _ = writeln!(out, "noventa_route_python_errors_total{{route=\"{}\"}} {}", label(&route.route), route.python_errors);
+    }
+    let _ = writeln!(out, "# HELP noventa_route_latency_ms Page latency over the last five minutes.");
+    let _ = writeln!(out, "# TYPE noventa_route_latency_ms summary");
+    for route in &health.routes {
+        for (quantile, value) in [("0.5", route.p50_ms), ("0.95", route.p95_ms), ("0.99", route.p99_ms)] {
+            let _ = writeln!(out, "noventa_route_latency_ms{{route=\"{}\",quantile=\"{}\"}} {}", label(&route.route), quantile, value);
+        }
+    }
+    out
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -226,4 +384,27 @@ mod tests {
         assert_eq!(metrics.rtt.p95_ms, 20.0); // 95% of 21 is index 19 (0-based), value 20
         assert_eq!(metrics.rtt.mean_ms, 11.0); // mean of 1+2+...+21 = 231/21 = 11
     }
-}
\ No newline at end of file
+
+    #[actix_rt::test]
+    async fn test_route_stats() {
+        let addr = HealthActor::new().start();
+        for (status, duration_ms, python_error) in [(200, 10.0, false), (200, 20.0, false), (500, 90.0, true), (404, 5.0, false)] {
+            addr.do_send(ReportRequest { route: "pages/cart.html".to_string(), status, duration_ms, python_error });
+        }
+        addr.do_send(ReportRequest { route: "pages/index.html".to_string(), status: 200, duration_ms: 1.0, python_error: false });
+        time::sleep(Duration::from_millis(100)).await;
+
+        let health = addr.send(GetSystemHealth).await.unwrap();
+        let cart = &health.routes[0];
+        assert_eq!(cart.route, "pages/cart.html");
+        assert_eq!((cart.requests, cart.python_errors, cart.error_rate), (4, 1, 0.25));
+        assert_eq!(cart.statuses, BTreeMap::from([(200, 2), (404, 1), (500, 1)]));
+        assert_eq!((cart.p50_ms, cart.p95_ms, cart.p99_ms), (20.0, 90.0, 90.0));
+        assert_eq!(health.routes[1].route, "pages/index.html");
+
+        let metrics = prometheus(&health);
+        assert!(metrics.contains("noventa_route_requests_total{route=\"pages/cart.html\",status=\"500\"} 1\n"), "{}", metrics);
+        assert!(metrics.contains("noventa_route_python_errors_total{route=\"pages/cart.html\"} 1\n"));
+        assert!(metrics.contains("noventa_route_latency_ms{route=\"pages/index.html\",quantile=\"0.99\"} 1\n"));
+    }
+}
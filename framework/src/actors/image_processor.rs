@@ -0,0 +1,170 @@
+use actix::prelude::*;
+use image::codecs::jpeg::JpegEncoder;
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageFormat, ImageReader};
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OutputFormat {
+    Png,
+    Jpeg,
+    Webp,
+    Gif,
+}
+
+impl OutputFormat {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "png" => Some(Self::Png),
+            "jpeg" | "jpg" => Some(Self::Jpeg),
+            "webp" => Some(Self::Webp),
+            "gif" => Some(Self::Gif),
+            _ => None,
+        }
+    }
+
+    // Without `format=`, a variant keeps the format of its source, or becomes a PNG.
+    pub fn for_source(path: &Path) -> Self {
+        match ImageFormat::from_path(path) {
+            Ok(ImageFormat::Jpeg) => Self::Jpeg,
+            Ok(ImageFormat::WebP) => Self::Webp,
+            Ok(ImageFormat::Gif) => Self::Gif,
+            _ => Self::Png,
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Png => "png",
+            Self::Jpeg => "jpg",
+            Self::Webp => "webp",
+            Self::Gif => "gif",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ImageError {
+    // Not an image, or one in a format we can't decode.
+    Unreadable(String),
+    Io(std::io::Error),
+}
+
+impl std::fmt::Display for ImageError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ImageError::Unreadable(e) => write!(f, "{}", e),
+            ImageError::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+// Resizes `source` to `width` (never wider than it already is) and writes it to `destination`.
+#[derive(Message, Debug, Clone)]
+#[rtype(result = "Result<(), ImageError>")]
+pub struct TransformImage {
+    pub source: PathBuf,
+    pub destination: PathBuf,
+    pub width: Option<u32>,
+    pub format: OutputFormat,
+    pub quality: u8,
+}
+
+// Decoding and resizing keep a thread busy, so this runs in its own SyncArbiter.
+pub struct ImageProcessingActor;
+
+impl Actor for ImageProcessingActor {
+    type Context = SyncContext<Self>;
+}
+
+impl Handler<TransformImage> for ImageProcessingActor {
+    type Result = Result<(), ImageError>;
+
+    fn handle(&mut self, msg: TransformImage, _ctx: &mut Self::Context) -> Self::Result {
+        transform(&msg)
+    }
+}
+
+pub fn transform(msg: &TransformImage) -> Result<(), ImageError> {
+    let image = ImageReader::open(&msg.source)
+        .and_then(|reader| reader.with_guessed_format())
+        .map_err(ImageError::Io)?
+        .decode()
+        .map_err(|e| ImageError::Unreadable(e.to_string()))?;
+    let image = match msg.width {
+        Some(width) if width < image.width() => image.resize(width, u32::MAX, FilterType::Lanczos3),
+        _ => image,
+    };
+    let bytes = encode(&image, msg.format, msg.quality).map_err(|e| ImageError::Unreadable(e.to_string()))?;
+
+    // Written next to its final name and renamed, so a half-written variant is never served.
+    if let Some(parent) = msg.destination.parent() {
+        std::fs::create_dir_all(parent).map_err(ImageError::Io)?;
+    }
+    let partial = msg.destination.with_extension(format!("{}.tmp-{}", msg.format.extension(), uuid::Uuid::new_v4()));
+    std::fs::write(&partial, bytes).map_err(ImageError::Io)?;
+    std::fs::rename(&partial, &msg.destination).map_err(|e| {
+        let _ = std::fs::remove_file(&partial);
+        ImageError::Io(e)
+    })
+}
+
+fn encode(image: &DynamicImage, format: OutputFormat, quality: u8) -> image::ImageResult<Vec<u8>> {
+    let mut bytes = Vec::new();
+    match format {
+        // JPEG has no alpha channel and WebP only takes 8-bit pixels.
+        OutputFormat::Jpeg => JpegEncoder::new_with_quality(&mut bytes, quality).encode_image(&image.to_rgb8())?,
+        OutputFormat::Webp => DynamicImage::ImageRgba8(image.to_rgba8()).write_to(&mut Cursor::new(&mut bytes), ImageFormat::WebP)?,
+        OutputFormat::Gif => DynamicImage::ImageRgba8(image.to_rgba8()).write_to(&mut Cursor::new(&mut bytes), ImageFormat::Gif)?,
+        OutputFormat::Png => image.write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png)?,
+    }
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_transform_resizes_and_converts() {
+        let dir = tempdir().unwrap();
+        let source = dir.path().join("wide.png");
+        DynamicImage::new_rgba8(40, 20).save(&source).unwrap();
+
+        let mut msg = TransformImage {
+            source,
+            destination: dir.path().join("cache/wide.webp"),
+            width: Some(10),
+            format: OutputFormat::Webp,
+            quality: 80,
+        };
+        transform(&msg).unwrap();
+        let variant = image::open(&msg.destination).unwrap();
+        assert_eq!((variant.width(), variant.height()), (10, 5));
+
+        // Asking for more than the original keeps its size.
+        msg.width = Some(100);
+        msg.format = OutputFormat::Jpeg;
+        msg.destination = dir.path().join("cache/wide.jpg");
+        transform(&msg).unwrap();
+        assert_eq!(image::open(&msg.destination).unwrap().width(), 40);
+    }
+
+    #[test]
+    fn test_transform_refuses_non_images() {
+        let dir = tempdir().unwrap();
+        let source = dir.path().join("notes.png");
+        std::fs::write(&source, "not really a png").unwrap();
+        let msg = TransformImage {
+            source,
+            destination: dir.path().join("out.png"),
+            width: None,
+            format: OutputFormat::Png,
+            quality: 80,
+        };
+        assert!(matches!(transform(&msg), Err(ImageError::Unreadable(_))));
+        assert!(!msg.destination.exists());
+    }
+}
@@ -1,6 +1,12 @@
 use crate::actors::page_renderer::HttpRequestInfo;
+use crate::component_state;
+use crate::render_progress;
+use crate::telemetry::{self, TraceContext};
+use crate::events::{self, Event};
 use crate::config::CONFIG;
 use crate::dto::python_request::PyRequest;
+use crate::streaming::STREAM_KEY;
+use crate::template_helpers::TemplateHelper;
 use actix::prelude::*;
 use minijinja::Value;
 use pyo3::prelude::*;
@@ -8,6 +14,7 @@ use pyo3::types::{PyAnyMethods, PyDict, PyModule};
 use std::collections::HashMap;
 use std::ffi::CString;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::fmt;
 
 // Define the message for rendering a component
@@ -39,6 +46,11 @@ impl std::error::Error for PythonError {}
 #[derive(Debug, Clone, Serialize)]
 pub struct PythonFunctionResult {
     pub context: Value,
+    // What the function returned as `_stream`, to send as the response.
+    #[serde(skip)]
+    pub stream: Option<Arc<Py<PyAny>>>,
+    // What it emitted with `noventa_events.emit`.
+    pub events: Vec<Event>,
 }
 
 #[derive(Message, Clone)]
@@ -49,6 +61,12 @@ pub struct ExecuteFunction {
     pub request: Arc<HttpRequestInfo>,
     pub args: Option<HashMap<String, Value>>,
     pub session_manager: Addr<SessionManagerActor>,
+    // The component instance (`counter:1a2b3c4d-0`) whose `state` the function gets, if it takes one.
+    pub state: Option<String>,
+    // What the action emitted, for the component's `on_event_*` hooks to add to what it returns.
+    pub events: Vec<Event>,
+    // The span the call is traced under, e.g. the component's.
+    pub trace: Option<TraceContext>,
 }
 
 use uuid::Uuid;
@@ -57,6 +75,41 @@ use uuid::Uuid;
 #[rtype(result = "()")]
 pub struct ReloadInterpreter;
 
+// Lists the filters and globals tagged in the project's template_helpers.py.
+#[derive(Message)]
+#[rtype(result = "Result<Vec<TemplateHelper>, PythonError>")]
+pub struct ListTemplateHelpers;
+
+// Calls one of them with plain values; no request, session or db.
+#[derive(Message)]
+#[rtype(result = "Result<TemplateHelperResult, PythonError>")]
+pub struct CallTemplateHelper {
+    pub attribute: String,
+    pub args: Vec<serde_json::Value>,
+    pub kwargs: serde_json::Map<String, serde_json::Value>,
+}
+
+pub struct TemplateHelperResult {
+    pub value: serde_json::Value,
+    // The helper returned markup (anything with `__html__`, like markupsafe.Markup) that isn't escaped again.
+    pub safe: bool,
+}
+
+
+// Runs the functions in the project's context_processors.py for a request; what they returned, merged.
+#[derive(Message)]
+#[rtype(result = "Result<serde_json::Map<String, serde_json::Value>, PythonError>")]
+pub struct RunContextProcessors {
+    pub request: Arc<HttpRequestInfo>,
+    pub session_manager: Addr<SessionManagerActor>,
+}
+
+// Interpreters that have started and not stopped, for /health/ready.
+static RUNNING_INTERPRETERS: AtomicUsize = AtomicUsize::new(0);
+
+pub fn running_interpreters() -> usize {
+    RUNNING_INTERPRETERS.load(Ordering::Relaxed)
+}
 
 // Define the Python interpreter actor
 pub struct PythonInterpreterActor {
@@ -64,6 +117,8 @@ pub struct PythonInterpreterActor {
     modules: HashMap<String, Py<PyModule>>,
     db_instance: Option<Py<PyAny>>,
     dev_mode: bool,
+    // template_helpers.py as of a `template_helpers::generation()`, so it's imported once per change, not per call.
+    template_helpers: Option<(u64, Py<PyModule>)>,
 }
 
 impl PythonInterpreterActor {
@@ -73,6 +128,7 @@ impl PythonInterpreterActor {
             modules: HashMap::new(),
             db_instance: None,
             dev_mode,
+            template_helpers: None,
         }
     }
 
@@ -97,6 +153,68 @@ impl PythonInterpreterActor {
             source_code: None,
         }).map(|m| m.to_owned().into())
     }
+
+    // Imported again on every call in dev, so edits show up; once otherwise.
+    #[allow(clippy::result_large_err)]
+    fn load_module(&mut self, py: Python, module_path: &str) -> Result<Py<PyModule>, PythonError> {
+        if self.dev_mode {
+            return self.import_module(py, module_path);
+        }
+        if let Some(module) = self.modules.get(module_path) {
+            return Ok(module.clone_ref(py));
+        }
+        let module = self.import_module(py, module_path)?;
+        self.modules.insert(module_path.to_string(), module.clone_ref(py));
+        Ok(module)
+    }
+
+    fn template_helpers_module<'py>(&mut self, py: Python<'py>) -> PyResult<Bound<'py, PyModule>> {
+        let generation = crate::template_helpers::generation();
+        if let Some((loaded, module)) = &self.template_helpers
+            && *loaded == generation
+        {
+            return Ok(module.bind(py).clone());
+        }
+        let importlib = py.import("importlib")?;
+        importlib.call_method0("invalidate_caches")?;
+        let module = py.import(crate::template_helpers::MODULE)?;
+        let module = importlib.call_method1("reload", (module,))?.downcast_into::<PyModule>()?;
+        self.template_helpers = Some((generation, module.clone().unbind()));
+        Ok(module)
+    }
+
+    fn list_template_helpers(&mut self, py: Python) -> PyResult<Vec<TemplateHelper>> {
+        let module = self.template_helpers_module(py)?;
+        let mut helpers = Vec::new();
+        for attribute in module.dir()? {
+            let attribute: String = attribute.extract()?;
+            let Ok(tag) = module.getattr(attribute.as_str()).and_then(|value| value.getattr(crate::template_helpers::HELPER_ATTRIBUTE)) else {
+                continue;
+            };
+            match crate::template_helpers::helper_from(&attribute, &tag) {
+                Ok(Some(helper)) => helpers.push(helper),
+                Ok(None) => {}
+                Err(e) => log::warn!("Heads up! Couldn't tell what kind of template helper '{}' is: {}", attribute, e),
+            }
+        }
+        Ok(helpers)
+    }
+
+    fn call_template_helper(&mut self, py: Python, msg: &CallTemplateHelper) -> PyResult<TemplateHelperResult> {
+        let func = self.template_helpers_module(py)?.getattr(msg.attribute.as_str())?;
+        let to_python = |value: &serde_json::Value| pythonize::pythonize(py, value).map_err(PyErr::from);
+        let args = msg.args.iter().map(to_python).collect::<PyResult<Vec<_>>>()?;
+        let kwargs = PyDict::new(py);
+        for (name, value) in &msg.kwargs {
+            kwargs.set_item(name, to_python(value)?)?;
+        }
+        let result = func.call(pyo3::types::PyTuple::new(py, args)?, Some(&kwargs))?;
+        if result.hasattr("__html__")? {
+            let html: String = result.call_method0("__html__")?.extract()?;
+            return Ok(TemplateHelperResult { value: serde_json::Value::String(html), safe: true });
+        }
+        Ok(TemplateHelperResult { value: pythonize::depythonize(&result)?, safe: false })
+    }
 }
 
 impl Actor for PythonInterpreterActor {
@@ -108,6 +226,25 @@ impl Actor for PythonInterpreterActor {
             let path = sys.getattr("path").unwrap();
             path.call_method1("insert", (0, ".")).unwrap();
 
+            if let Err(e) = crate::auth::register_python_module(py) {
+                log::error!("Failed to set up the noventa_auth module: {}", e);
+            }
+            if let Err(e) = crate::signed_urls::register_python_module(py) {
+                log::error!("Failed to set up the noventa_urls module: {}", e);
+            }
+            if let Err(e) = crate::template_helpers::register_python_module(py) {
+                log::error!("Failed to set up the noventa_templates module: {}", e);
+            }
+            if let Err(e) = crate::events::register_python_module(py) {
+                log::error!("Failed to set up the noventa_events module: {}", e);
+            }
+            if let Err(e) = crate::redirects::register_python_module(py) {
+                log::error!("Failed to set up the noventa_responses module: {}", e);
+            }
+            if let Err(e) = crate::user_channels::register_python_module(py) {
+                log::error!("Failed to set up the noventa_channels module: {}", e);
+            }
+
             if let Some(db_url) = &CONFIG.database {
                 let db_code = CString::new(crate::scripts::python_embed::DB_PY).unwrap();
                 let db_filename = CString::new("db.py").unwrap();
@@ -133,6 +270,11 @@ impl Actor for PythonInterpreterActor {
             }
 
         });
+        RUNNING_INTERPRETERS.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn stopped(&mut self, _ctx: &mut Self::Context) {
+        RUNNING_INTERPRETERS.fetch_sub(1, Ordering::Relaxed);
     }
 }
 
@@ -148,21 +290,19 @@ impl Handler<ExecuteFunction> for PythonInterpreterActor {
             msg.function_name
         );
 
+        // A component's calls carry its `name:instance`.
+        let component = msg.state.as_deref().and_then(|state| state.rsplit_once(':')).map(|(name, _)| name);
+        let _running = render_progress::enter(msg.request.render_id, &msg.module_path, &msg.function_name, component);
+        let mut span = telemetry::child(msg.trace, format!("{}.{}", msg.module_path, msg.function_name));
+        span.set_attribute("code.namespace", &msg.module_path);
+        span.set_attribute("code.function", &msg.function_name);
+        span.set_attribute("noventa.interpreter", self.id);
         let py_request = PyRequest { inner: msg.request };
+        let session_manager = msg.session_manager.clone();
         let py_session = crate::dto::python_session::PySession::new(msg.session_manager);
 
-        let result_value: serde_json::Value = Python::attach(|py| {
-            let module = if self.dev_mode {
-                self.import_module(py, &msg.module_path)?
-            } else {
-                if let Some(module) = self.modules.get(&msg.module_path) {
-                    module.clone_ref(py)
-                } else {
-                    let module = self.import_module(py, &msg.module_path)?;
-                    self.modules.insert(msg.module_path.clone(), module.clone_ref(py));
-                    module
-                }
-            };
+        let outcome: Result<(serde_json::Value, _, _), PythonError> = Python::attach(|py| {
+            let module = self.load_module(py, &msg.module_path)?;
 
             let func = module.getattr(py, &msg.function_name).map_err(|e| pyerr_to_pyerror(e, py))?;
 
@@ -189,22 +329,80 @@ impl Handler<ExecuteFunction> for PythonInterpreterActor {
 
             let db_arg = self.db_instance.as_ref().map_or(py.None(), |db| db.clone_ref(py).into());
 
-            // Load the embedded Python utils from the new path
-            let utils_code = CString::new(crate::scripts::python_embed::UTILS_PY).map_err(|e| PythonError {
-                message: format!("Failed to create CString from embedded utils.py: {}", e),
-                ..Default::default()
-            })?;
-            let utils_filename = CString::new("_noventa_internal_dispatch.py").unwrap();
-            let utils_module_name = CString::new("_noventa_internal_dispatch").unwrap();
-            let utils_module = PyModule::from_code(py, &utils_code, &utils_filename, &utils_module_name)
-                .map_err(|e| pyerr_to_pyerror(e, py))?;
+            let utils_module = utils_module(py)?;
             let wrapper_func = utils_module.getattr("call_user_function")
                 .map_err(|e| pyerr_to_pyerror(e, py))?;
 
+            // The instance's state for this visitor, saved again if the function changed it.
+            let takes_state = |func: &Py<PyAny>| -> PyResult<bool> { utils_module.getattr("takes_state")?.call1((func,))?.extract() };
+            let state = match &msg.state {
+                Some(instance) if takes_state(&func).map_err(|e| pyerr_to_pyerror(e, py))? => {
+                    py.detach(|| component_state::visitor_id(&session_manager)).map(|visitor| component_state::key(&visitor, instance))
+                }
+                _ => None,
+            };
+            let state = match state {
+                Some(key) => {
+                    let before = py.detach(|| component_state::load(&key));
+                    let dict = pythonize::pythonize(py, &before).map_err(|e| PythonError { message: e.to_string(), ..Default::default() })?;
+                    py_args.set_item("state", &dict).map_err(|e| pyerr_to_pyerror(e, py))?;
+                    Some((key, before, dict))
+                }
+                None => None,
+            };
+
             // The user's function and its arguments are passed to the wrapper
-            let args_to_wrapper = (func, py_request_obj, py_session_obj, db_arg);
-            let result = wrapper_func.call(args_to_wrapper, Some(&py_args)).map_err(|e| pyerr_to_pyerror(e, py))?;
-            
+            events::take_emitted();
+            let result = match form_models_module(&crate::config::BASE_PATH, &msg.module_path, &msg.function_name) {
+                // Actions check their form against the component's models first.
+                Some(models_path) => {
+                    let models = self.load_module(py, &models_path)?;
+                    let call_action = utils_module.getattr("call_action").map_err(|e| pyerr_to_pyerror(e, py))?;
+                    call_action.call((&func, models, py_request_obj.clone_ref(py), py_session_obj.clone_ref(py), db_arg.clone_ref(py)), Some(&py_args))
+                }
+                None => wrapper_func.call((&func, py_request_obj.clone_ref(py), py_session_obj.clone_ref(py), db_arg.clone_ref(py)), Some(&py_args)),
+            }
+            .map_err(|e| pyerr_to_pyerror(e, py))?;
+            let emitted = events::take_emitted();
+
+            // The component's hooks for what was emitted add to its context, e.g. `on_event_cart_updated`.
+            for event in &msg.events {
+                let Ok(hook) = module.getattr(py, event.hook()) else {
+                    continue;
+                };
+                let hook_args = py_args.copy().map_err(|e| pyerr_to_pyerror(e, py))?;
+                let payload = pythonize::pythonize(py, &event.payload).map_err(|e| PythonError { message: e.to_string(), ..Default::default() })?;
+                hook_args.set_item("payload", payload).map_err(|e| pyerr_to_pyerror(e, py))?;
+                if state.is_some() && !takes_state(&hook).map_err(|e| pyerr_to_pyerror(e, py))? {
+                    hook_args.del_item("state").map_err(|e| pyerr_to_pyerror(e, py))?;
+                }
+                let added = wrapper_func
+                    .call((hook, py_request_obj.clone_ref(py), py_session_obj.clone_ref(py), db_arg.clone_ref(py)), Some(&hook_args))
+                    .map_err(|e| pyerr_to_pyerror(e, py))?;
+                if let (Ok(context), Ok(added)) = (result.downcast::<PyDict>(), added.downcast::<PyDict>()) {
+                    context.update(added.as_mapping()).map_err(|e| pyerr_to_pyerror(e, py))?;
+                }
+            }
+
+            if let Some((key, before, dict)) = state {
+                let after: serde_json::Value = pythonize::depythonize(&dict).map_err(|e| PythonError {
+                    message: format!("`state` must hold only what JSON can (dicts, lists, strings, numbers, booleans or None): {}", e),
+                    ..Default::default()
+                })?;
+                if after != before {
+                    py.detach(|| component_state::save(&key, &after));
+                }
+            }
+
+            // The iterator of a `_stream` stays in Python, to be read while the response goes out.
+            let stream = match result.downcast::<PyDict>().ok().and_then(|dict| dict.get_item(STREAM_KEY).ok().flatten()) {
+                Some(iterable) => {
+                    result.del_item(STREAM_KEY).map_err(|e| pyerr_to_pyerror(e, py))?;
+                    Some(Arc::new(iterable.try_iter().map_err(|e| pyerr_to_pyerror(e, py))?.into_any().unbind()))
+                }
+                None => None,
+            };
+
             let py_any = result;
             pythonize::depythonize(&py_any).map_err(|e| PythonError {
                 message: e.to_string(),
@@ -215,11 +413,15 @@ impl Handler<ExecuteFunction> for PythonInterpreterActor {
                 end_column_number: None,
                 filename: None,
                 source_code: None,
-            })
-        })?;
+            }).map(|value| (value, stream, emitted))
+        });
+        if let Err(e) = &outcome {
+            span.set_error(&e.message);
+        }
+        let (result_value, stream, emitted) = outcome?;
 
         let value = Value::from_serialize(&result_value);
-        Ok(PythonFunctionResult { context: value })
+        Ok(PythonFunctionResult { context: value, stream, events: emitted })
     }
 }
 
@@ -230,10 +432,73 @@ impl Handler<ReloadInterpreter> for PythonInterpreterActor {
     fn handle(&mut self, _msg: ReloadInterpreter, ctx: &mut Self::Context) -> Self::Result {
         log::debug!("Interpreter {} received reload request", self.id);
         self.modules.clear();
+        crate::template_helpers::reload();
         self.started(ctx);
     }
 }
 
+impl Handler<ListTemplateHelpers> for PythonInterpreterActor {
+    type Result = Result<Vec<TemplateHelper>, PythonError>;
+
+    fn handle(&mut self, _msg: ListTemplateHelpers, _ctx: &mut Self::Context) -> Self::Result {
+        let listed = Python::attach(|py| self.list_template_helpers(py));
+        listed.map_err(|e| Python::attach(|py| pyerr_to_pyerror(e, py)))
+    }
+}
+
+impl Handler<CallTemplateHelper> for PythonInterpreterActor {
+    type Result = Result<TemplateHelperResult, PythonError>;
+
+    fn handle(&mut self, msg: CallTemplateHelper, _ctx: &mut Self::Context) -> Self::Result {
+        let result = Python::attach(|py| self.call_template_helper(py, &msg));
+        result.map_err(|e| Python::attach(|py| pyerr_to_pyerror(e, py)))
+    }
+}
+
+impl Handler<RunContextProcessors> for PythonInterpreterActor {
+    type Result = Result<serde_json::Map<String, serde_json::Value>, PythonError>;
+
+    #[allow(clippy::result_large_err)]
+    fn handle(&mut self, msg: RunContextProcessors, _ctx: &mut Self::Context) -> Self::Result {
+        let _running = render_progress::enter(msg.request.render_id, crate::context_processors::MODULE, "run_context_processors", None);
+        let py_request = PyRequest { inner: msg.request };
+        let py_session = crate::dto::python_session::PySession::new(msg.session_manager);
+        Python::attach(|py| {
+            let module = self.load_module(py, crate::context_processors::MODULE)?;
+            let db_arg = self.db_instance.as_ref().map_or(py.None(), |db| db.clone_ref(py));
+            let run = utils_module(py)?.getattr("run_context_processors").map_err(|e| pyerr_to_pyerror(e, py))?;
+            let py_request_obj = Py::new(py, py_request).map_err(|e| pyerr_to_pyerror(e, py))?;
+            let py_session_obj = Py::new(py, py_session).map_err(|e| pyerr_to_pyerror(e, py))?;
+            let context = run.call1((module, py_request_obj, py_session_obj, db_arg)).map_err(|e| pyerr_to_pyerror(e, py))?;
+            pythonize::depythonize(&context).map_err(|e| PythonError {
+                message: format!("What the context processors returned must be JSON-serializable: {}", e),
+                ..Default::default()
+            })
+        })
+    }
+}
+
+// The embedded helpers the user's functions are called through.
+#[allow(clippy::result_large_err)]
+fn utils_module(py: Python) -> Result<Bound<PyModule>, PythonError> {
+    let utils_code = CString::new(crate::scripts::python_embed::UTILS_PY).map_err(|e| PythonError {
+        message: format!("Failed to create CString from embedded utils.py: {}", e),
+        ..Default::default()
+    })?;
+    let utils_filename = CString::new("_noventa_internal_dispatch.py").unwrap();
+    let utils_module_name = CString::new("_noventa_internal_dispatch").unwrap();
+    PyModule::from_code(py, &utils_code, &utils_filename, &utils_module_name).map_err(|e| pyerr_to_pyerror(e, py))
+}
+
+// `components.todo.todo_models` for the actions in `components.todo.todo_logic`, if the component has one.
+fn form_models_module(base: &std::path::Path, module_path: &str, function_name: &str) -> Option<String> {
+    if !function_name.starts_with("action_") {
+        return None;
+    }
+    let models_path = format!("{}_models", module_path.strip_suffix("_logic")?);
+    base.join(format!("{}.py", models_path.replace('.', "/"))).is_file().then_some(models_path)
+}
+
 fn pyerr_to_pyerror(e: PyErr, py: Python) -> PythonError {
     let mut filename = None;
     let mut line_number = None;
@@ -307,3 +572,21 @@ fn pyerr_to_pyerror(e: PyErr, py: Python) -> PythonError {
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_form_models_module() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("components/todo")).unwrap();
+        std::fs::write(dir.path().join("components/todo/todo_models.py"), "").unwrap();
+        assert_eq!(
+            form_models_module(dir.path(), "components.todo.todo_logic", "action_add").as_deref(),
+            Some("components.todo.todo_models")
+        );
+        assert_eq!(form_models_module(dir.path(), "components.todo.todo_logic", "load_template_context"), None);
+        assert_eq!(form_models_module(dir.path(), "components.cart.cart_logic", "action_add"), None);
+        assert_eq!(form_models_module(dir.path(), "pages._page", "action_add"), None);
+    }
+}
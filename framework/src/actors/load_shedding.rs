@@ -1,13 +1,81 @@
 use crate::actors::health::{HealthActor, ReportRtt};
 use crate::actors::page_renderer::{PageRendererActor, RenderMessage, RenderOutput};
+use crate::{config, interpreter_queue};
 use actix::prelude::*;
+use actix_web::HttpResponse;
+use lazy_static::lazy_static;
 use serde::Serialize;
 use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::time::{Duration, Instant};
 
 const METRICS_WINDOW: Duration = Duration::from_secs(30);
 const METRICS_CALCULATION_INTERVAL: Duration = Duration::from_secs(1);
 const LATENCY_THRESHOLD_MULTIPLIER: f64 = 2.0;
+const DEFAULT_RETRY_AFTER: u64 = 10;
+const BUSY: &str = "The server is busy right now. Please try again in a few seconds.";
+
+static SHEDDING: AtomicBool = AtomicBool::new(false);
+static DROPPED: AtomicU64 = AtomicU64::new(0);
+static CRITICAL_LET_THROUGH: AtomicU64 = AtomicU64::new(0);
+
+lazy_static! {
+    // `load_shedding.busy_page`, read once: it's sent when there's least time to spare.
+    static ref BUSY_PAGE: Option<String> = config::CONFIG.load_shedding.as_ref().and_then(|load_shedding| {
+        let page = load_shedding.busy_page.as_ref()?;
+        match std::fs::read_to_string(config::BASE_PATH.join(page)) {
+            Ok(html) => Some(html),
+            Err(e) => {
+                log::warn!("Heads up! The busy page {} couldn't be read ({}), so turned away requests get a plain message instead.", page, e);
+                None
+            }
+        }
+    });
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct LoadSheddingStats {
+    pub shedding: bool,
+    // Requests answered with a 503 since the server started.
+    pub dropped: u64,
+    // Requests to `load_shedding.critical_routes` let through while the rest were turned away.
+    pub critical_let_through: u64,
+}
+
+pub fn stats() -> LoadSheddingStats {
+    LoadSheddingStats {
+        shedding: SHEDDING.load(Ordering::Relaxed),
+        dropped: DROPPED.load(Ordering::Relaxed),
+        critical_let_through: CRITICAL_LET_THROUGH.load(Ordering::Relaxed),
+    }
+}
+
+// Health checks are how a load balancer learns the server is struggling, so they're never turned
+// away; nor are the routes config.yaml says are critical.
+fn is_critical(path: &str, critical_routes: &[String]) -> bool {
+    if path == "/health" || path.starts_with("/health/") {
+        return true;
+    }
+    critical_routes.iter().any(|route| match route.strip_suffix("/*") {
+        Some(prefix) => path == prefix || path.strip_prefix(prefix).is_some_and(|rest| rest.starts_with('/')),
+        None => path == route,
+    })
+}
+
+// What a turned away request gets: a 503 saying when to come back.
+pub fn shed_response() -> HttpResponse {
+    let retry_after = config::CONFIG
+        .load_shedding
+        .as_ref()
+        .and_then(|load_shedding| load_shedding.retry_after)
+        .unwrap_or(DEFAULT_RETRY_AFTER);
+    let mut response = HttpResponse::ServiceUnavailable();
+    response.insert_header(("Retry-After", retry_after.to_string())).insert_header(("Cache-Control", "no-store"));
+    match &*BUSY_PAGE {
+        Some(html) => response.content_type("text/html").body(html.clone()),
+        None => response.body(BUSY),
+    }
+}
 
 #[derive(Serialize, Clone, Copy, Debug)]
 pub enum HealthStatus {
@@ -61,33 +129,43 @@ impl LoadSheddingActor {
 
         if self.latency_data.is_empty() {
             self.current_p95_latency_ms = 0.0;
-            return;
-        }
-
-        // Calculate 95th percentile latency
-        let mut durations: Vec<f64> = self.latency_data.iter().map(|m| m.duration_ms).collect();
-        durations.sort_by(|a, b| a.partial_cmp(b).unwrap());
-        let p95_index = (durations.len() as f64 * 0.95).floor() as usize;
-        self.current_p95_latency_ms = durations[p95_index.min(durations.len() - 1)];
-
-        // Update baseline (simple moving average for now)
-        if self.baseline_latency_ms == 0.0 {
-            self.baseline_latency_ms = self.current_p95_latency_ms;
         } else {
-            self.baseline_latency_ms = (self.baseline_latency_ms * 0.9) + (self.current_p95_latency_ms * 0.1);
+            // Calculate 95th percentile latency
+            let mut durations: Vec<f64> = self.latency_data.iter().map(|m| m.duration_ms).collect();
+            durations.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            let p95_index = (durations.len() as f64 * 0.95).floor() as usize;
+            self.current_p95_latency_ms = durations[p95_index.min(durations.len() - 1)];
+
+            // Update baseline (simple moving average for now)
+            if self.baseline_latency_ms == 0.0 {
+                self.baseline_latency_ms = self.current_p95_latency_ms;
+            } else {
+                self.baseline_latency_ms = (self.baseline_latency_ms * 0.9) + (self.current_p95_latency_ms * 0.1);
+            }
         }
 
+        // Slow pages, or so many calls waiting for Python that they soon will be.
+        let slow = self.current_p95_latency_ms > self.baseline_latency_ms * LATENCY_THRESHOLD_MULTIPLIER && self.baseline_latency_ms > 0.0;
+        let saturation = interpreter_queue::saturation();
+        let saturated = saturation >= interpreter_queue::SATURATION_THRESHOLD;
+
         // Update state machine
-        if self.current_p95_latency_ms > self.baseline_latency_ms * LATENCY_THRESHOLD_MULTIPLIER && self.baseline_latency_ms > 0.0 {
+        if slow || saturated {
             if matches!(self.status, HealthStatus::Healthy) {
-                log::warn!("Hold on tight! The system is under high load (P95 Latency: {:.2}ms). We're activating defense mode to keep things running smoothly.", self.current_p95_latency_ms);
+                if saturated {
+                    log::warn!("Hold on tight! The Python interpreters can't keep up ({:.0}% of their queue is waiting). We're activating defense mode to keep things running smoothly.", saturation * 100.0);
+                } else {
+                    log::warn!("Hold on tight! The system is under high load (P95 Latency: {:.2}ms). We're activating defense mode to keep things running smoothly.", self.current_p95_latency_ms);
+                }
                 self.status = HealthStatus::Shedding;
+                SHEDDING.store(true, Ordering::Relaxed);
                 self.concurrency_limit = Some(self.active_requests);
             }
         } else {
             if matches!(self.status, HealthStatus::Shedding) {
                 log::info!("Phew! System load has returned to normal (P95 Latency: {:.2}ms). Deactivating defense mode.", self.current_p95_latency_ms);
                 self.status = HealthStatus::Healthy;
+                SHEDDING.store(false, Ordering::Relaxed);
                 self.concurrency_limit = None;
             }
         }
@@ -129,22 +207,15 @@ impl Handler<RenderMessage> for LoadSheddingActor {
     type Result = ResponseFuture<Result<RenderOutput, crate::errors::DetailedError>>;
 
     fn handle(&mut self, msg: RenderMessage, ctx: &mut Context<Self>) -> Self::Result {
-        if let Some(limit) = self.concurrency_limit {
-            if self.active_requests >= limit && msg.request_info.path != "/health" {
-                return Box::pin(async { Err(crate::errors::DetailedError {
-                    error_source: Some(crate::errors::ErrorSource::Python(crate::actors::interpreter::PythonError {
-                        message: "Timeout".to_string(),
-                        traceback: "".to_string(),
-                        line_number: None,
-                        column_number: None,
-                        end_line_number: None,
-                        end_column_number: None,
-                        filename: None,
-                        source_code: None,
-                    })),
-                    ..Default::default()
-                }) });
+        if let Some(limit) = self.concurrency_limit
+            && self.active_requests >= limit
+        {
+            let critical_routes = config::CONFIG.load_shedding.as_ref().and_then(|load_shedding| load_shedding.critical_routes.as_deref()).unwrap_or_default();
+            if !is_critical(&msg.request_info.path, critical_routes) {
+                DROPPED.fetch_add(1, Ordering::Relaxed);
+                return Box::pin(async { Ok(RenderOutput::Shed) });
             }
+            CRITICAL_LET_THROUGH.fetch_add(1, Ordering::Relaxed);
         }
 
         self.active_requests += 1;
@@ -185,4 +256,26 @@ impl Handler<RenderMessage> for LoadSheddingActor {
             }
         })
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_critical() {
+        let critical_routes = vec!["/checkout".to_string(), "/api/payments/*".to_string()];
+        for path in ["/health", "/health/ready", "/checkout", "/api/payments", "/api/payments/refund"] {
+            assert!(is_critical(path, &critical_routes), "{}", path);
+        }
+        for path in ["/", "/healthy", "/checkout/done", "/api/paymentsx", "/api"] {
+            assert!(!is_critical(path, &critical_routes), "{}", path);
+        }
+    }
+
+    #[test]
+    fn test_shed_response() {
+        let response = shed_response();
+        assert_eq!(response.status(), actix_web::http::StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.headers().get("Retry-After").unwrap(), "10");
+    }
+}
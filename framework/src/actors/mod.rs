@@ -6,6 +6,8 @@ pub mod load_shedding;
 pub mod dev_websockets;
 pub mod file_watcher;
 pub mod ws_server;
+pub mod user_websockets;
 pub mod router;
 pub mod session_manager;
-pub mod ssg;
\ No newline at end of file
+pub mod ssg;
+pub mod image_processor;
\ No newline at end of file
@@ -1,24 +1,47 @@
 use crate::actors::health::{HealthActor, ReportTemplateLatency};
 use crate::actors::session_manager::SessionManagerActor;
-use crate::actors::template_renderer::{RenderTemplate, TemplateRendererActor};
+use crate::actors::interpreter::PythonInterpreterActor;
+use crate::actors::template_renderer::{self, RenderTemplate, TemplateRendererActor};
+use crate::render_progress;
+use crate::telemetry::{self, TraceContext};
 use actix::prelude::*;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::path::PathBuf;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use actix_web::rt::time::timeout;
 
+// How long a page gets to render before the visitor gets an error instead, unless the page's
+// `timeout` or config.yaml's `render_timeout` says otherwise.
+pub const RENDER_TIMEOUT: Duration = Duration::from_secs(60);
+
+pub fn render_timeout(page_timeout: Option<u64>) -> Duration {
+    page_timeout
+        .or(crate::config::CONFIG.render_timeout)
+        .map_or(RENDER_TIMEOUT, Duration::from_secs)
+}
+
+// Unix time in milliseconds, which is how request deadlines are kept.
+pub fn now_ms() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_millis() as u64)
+}
+
 #[derive(Clone, Serialize, Deserialize)]
 pub enum FileData {
     InMemory(Vec<u8>),
     OnDisk(PathBuf),
+    // Key of an object in the upload bucket.
+    Remote(String),
 }
 
 #[derive(Clone, Serialize, Deserialize)]
 pub struct FilePart {
     pub filename: String,
+    // What the client said the file is.
     pub content_type: String,
+    // What the file's bytes say it is.
+    pub detected_type: String,
     pub headers: HashMap<String, String>,
     pub data: FileData,
 }
@@ -67,17 +90,37 @@ pub struct HttpRequestInfo {
     pub range: Option<String>,
     pub referrer: Option<String>,
     pub remote_user: Option<String>,
+    // The identity claims of a user who logged in through an `oidc` provider.
+    pub user: Option<serde_json::Value>,
+    // Put on every <script> Noventa injects so a strict Content-Security-Policy still allows them.
+    pub csp_nonce: Option<String>,
+    // The link came from `url_for_signed()` and hasn't expired.
+    pub is_signed: bool,
+    // The `{% fragment %}` asked for with `X-Fragment` or `?fragment=`; only that part is rendered.
+    pub fragment: Option<String>,
+    // When the render is given up on, in Unix milliseconds. Python sees the time left as `request.deadline_ms`.
+    pub deadline: Option<u64>,
+    // What it's running is kept under this in `render_progress`; 0 isn't tracked.
+    pub render_id: u64,
+    // The request's span in `telemetry`, if it's traced.
+    #[serde(skip)]
+    pub trace: Option<TraceContext>,
+    // frontend.js posted a form with `X-Noventa-Partial` and only needs the component it came from back.
+    pub component_only: bool,
 }
 
 pub struct PageRendererActor {
     template_renderer: Addr<TemplateRendererActor>,
+    // What the render waits on between its passes; see `template_renderer::render`.
+    interpreter: Addr<PythonInterpreterActor>,
     health_actor: Addr<HealthActor>,
 }
 
 impl PageRendererActor {
-    pub fn new(template_renderer: Addr<TemplateRendererActor>, health_actor: Addr<HealthActor>) -> Self {
+    pub fn new(template_renderer: Addr<TemplateRendererActor>, interpreter: Addr<PythonInterpreterActor>, health_actor: Addr<HealthActor>) -> Self {
         Self {
             template_renderer,
+            interpreter,
             health_actor,
         }
     }
@@ -90,7 +133,16 @@ impl Actor for PageRendererActor {
 #[derive(Clone)]
 pub enum RenderOutput {
     Html(String),
-    Redirect(String),
+    // Where to send the visitor, and with which redirect status.
+    Redirect(u16, String),
+    // A page sent with the status it asked for with `{{ status(410) }}` or a `_status`.
+    Status(u16, String),
+    // Just the component a form was posted from, to replace it in the page.
+    Component(String),
+    // What a Python iterator yields, sent as it's read.
+    Stream(crate::streaming::PythonStream),
+    // Turned away by the load shedder, and answered with a 503.
+    Shed,
 }
 
 #[derive(Message, Clone)]
@@ -106,47 +158,48 @@ impl Handler<RenderMessage> for PageRendererActor {
 
     fn handle(&mut self, msg: RenderMessage, _ctx: &mut Context<Self>) -> Self::Result {
         let template_renderer = self.template_renderer.clone();
+        let interpreter = self.interpreter.clone();
         let health_actor = self.health_actor.clone();
         Box::pin(async move {
+            let template_path = msg.template_path.clone();
+            let mut span = telemetry::child(msg.request_info.trace, format!("render {}", template_path));
             let render_msg = RenderTemplate {
                 template_name: msg.template_path,
                 request_info: msg.request_info.clone(),
                 session_manager: msg.session_manager,
+                trace: span.context(),
             };
 
             let start_time = std::time::Instant::now();
-            let future = template_renderer.send(render_msg);
-            let result = timeout(Duration::from_secs(60), future).await;
+            let future = template_renderer::render(&template_renderer, &interpreter, &health_actor, render_msg);
+            let time_left = msg.request_info.deadline.map_or(RENDER_TIMEOUT, |deadline| Duration::from_millis(deadline.saturating_sub(now_ms())));
+            let result = timeout(time_left, future).await;
             let duration_ms = start_time.elapsed().as_secs_f64() * 1000.0;
             health_actor.do_send(ReportTemplateLatency(duration_ms));
 
             match result {
-                Ok(inner) => match inner {
-                    Ok(render_res) => match render_res {
-                        Ok(rendered) => Ok(rendered),
-                        Err(e) => Err(e),
-                    },
-                    Err(mailbox_err) => {
-                        log::error!("Template renderer mailbox error: {}", mailbox_err);
-                        Err(crate::errors::DetailedError {
-                            error_source: None,
-                            ..Default::default()
-                        })
-                    }
-                },
+                Ok(Ok(rendered)) => Ok(rendered),
+                Ok(Err(e)) => {
+                    span.set_error(&e.message);
+                    Err(e)
+                }
                 Err(_) => {
-                    log::error!("The template renderer timed out. The server is taking too long to respond.");
+                    // What was still running says where the time went.
+                    let still_running = render_progress::running(msg.request_info.render_id);
+                    let allowed = Duration::from_secs(time_left.as_secs_f64().round() as u64);
+                    let message = render_progress::timeout_message(&template_path, allowed, &still_running);
+                    log::error!("Oh no! {}", message);
+                    span.set_error(&message);
+                    let slowest = still_running.first();
                     Err(crate::errors::DetailedError {
+                        message: message.clone(),
+                        file_path: slowest.map_or_else(|| template_path.clone(), |step| step.file_path()),
                         error_source: Some(crate::errors::ErrorSource::Python(crate::actors::interpreter::PythonError {
-                            message: "Timeout".to_string(),
-                            traceback: "".to_string(),
-                            line_number: None,
-                            column_number: None,
-                            end_line_number: None,
-                            end_column_number: None,
-                            filename: None,
-                            source_code: None,
+                            message,
+                            filename: slowest.map(|step| step.file_path()),
+                            ..Default::default()
                         })),
+                        component: slowest.and_then(|step| step.component.clone()).map(|name| crate::errors::ComponentInfo { name }),
                         ..Default::default()
                     })
                 }
@@ -178,7 +231,7 @@ mod tests {
         // Test that we can create and pattern match
         match file_data {
             FileData::InMemory(mem_data) => assert_eq!(mem_data, data),
-            FileData::OnDisk(_) => panic!("Expected InMemory"),
+            _ => panic!("Expected InMemory"),
         }
 
         // Test FileData::OnDisk
@@ -187,7 +240,7 @@ mod tests {
         
         match file_data {
             FileData::OnDisk(disk_path) => assert_eq!(disk_path, path),
-            FileData::InMemory(_) => panic!("Expected OnDisk"),
+            _ => panic!("Expected OnDisk"),
         }
     }
 
@@ -199,6 +252,7 @@ mod tests {
         let file_part = FilePart {
             filename: "test.txt".to_string(),
             content_type: "text/plain".to_string(),
+            detected_type: "text/plain".to_string(),
             headers: headers.clone(),
             data: FileData::InMemory(vec![1, 2, 3]),
         };
@@ -264,6 +318,14 @@ mod tests {
             range: None,
             referrer: Some("http://referrer.com".to_string()),
             remote_user: None,
+            user: None,
+            csp_nonce: None,
+            is_signed: false,
+            fragment: None,
+            deadline: None,
+            render_id: 0,
+            trace: None,
+            component_only: false,
         };
 
         assert_eq!(request_info.path, "/test");
@@ -286,9 +348,12 @@ mod tests {
         }
 
         // Test RenderOutput::Redirect
-        let redirect_output = RenderOutput::Redirect("/new-url".to_string());
+        let redirect_output = RenderOutput::Redirect(303, "/new-url".to_string());
         match redirect_output {
-            RenderOutput::Redirect(url) => assert_eq!(url, "/new-url"),
+            RenderOutput::Redirect(status, url) => {
+                assert_eq!(status, 303);
+                assert_eq!(url, "/new-url");
+            }
             _ => panic!("Expected Redirect variant"),
         }
     }
@@ -35,6 +35,7 @@ impl Handler<ReloadRoutes> for RouterActor {
         let new_routes = routing::get_compiled_routes(&pages_dir);
         let mut routes = self.routes.write().unwrap();
         *routes = new_routes;
+        crate::reverse_routes::clear();
         log::debug!("Routes have been successfully reloaded.");
     }
 }
@@ -48,29 +49,32 @@ impl Handler<MatchRoute> for RouterActor {
 
     fn handle(&mut self, msg: MatchRoute, _ctx: &mut Context<Self>) -> Self::Result {
         let routes = self.routes.read().unwrap();
-        let path = msg.0;
-
-        log::debug!("RouterActor checking {} routes for path: {}", routes.len(), path);
-        for route in routes.iter() {
-            if let Some(captures) = route.regex.captures(&path) {
-                let params: HashMap<String, String> = route
-                    .param_names
-                    .iter()
-                    .filter_map(|name| {
-                        captures
-                            .name(name)
-                            .map(|value| (name.clone(), value.as_str().to_string()))
-                    })
-                    .collect();
-
-                log::debug!("RouterActor matched route '{}' for path '{}', template: '{}', params: {:?}", route.route_pattern, path, route.template_path.display(), params);
-                let template_path_str = route.template_path.strip_prefix(&*config::BASE_PATH).unwrap_or(&route.template_path).to_str().unwrap().to_string();
-                return Some((template_path_str, params));
-            }
+        match_route(&routes, &msg.0)
+    }
+}
+
+// The page that answers `path`, as the template loader names it, and the values of its route params.
+pub fn match_route(routes: &[CompiledRoute], path: &str) -> Option<(String, HashMap<String, String>)> {
+    log::debug!("RouterActor checking {} routes for path: {}", routes.len(), path);
+    for route in routes.iter() {
+        if let Some(captures) = route.regex.captures(path) {
+            let params: HashMap<String, String> = route
+                .param_names
+                .iter()
+                .filter_map(|name| {
+                    captures
+                        .name(name)
+                        .map(|value| (name.clone(), value.as_str().to_string()))
+                })
+                .collect();
+
+            log::debug!("RouterActor matched route '{}' for path '{}', template: '{}', params: {:?}", route.route_pattern, path, route.template_path.display(), params);
+            let template_path_str = crate::paths::relative_name(&route.template_path, &config::BASE_PATH);
+            return Some((template_path_str, params));
         }
-        log::debug!("RouterActor found no match for path: {}", path);
-        None
     }
+    log::debug!("RouterActor found no match for path: {}", path);
+    None
 }
 
 #[cfg(test)]
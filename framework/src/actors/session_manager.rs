@@ -1,6 +1,19 @@
+use crate::config::{self, SessionBackend};
 use actix::prelude::*;
 use actix_session::Session;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
 use std::io::{Error, ErrorKind};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// Session keys that mean someone just logged in, unless `session.login_keys` says otherwise.
+const DEFAULT_LOGIN_KEYS: &[&str] = &["user_id"];
+
+// Browsers drop cookies bigger than this, and with them the whole session.
+const COOKIE_SIZE_LIMIT: usize = 4096;
+
+// So a session that outgrew its cookie is reported once, not on every request.
+static COOKIE_SIZE_WARNED: AtomicBool = AtomicBool::new(false);
 
 // Define the actor
 pub struct SessionManagerActor {
@@ -11,6 +24,47 @@ impl SessionManagerActor {
     pub fn new(session: Session) -> Self {
         Self { session }
     }
+
+    fn insert(&self, key: &str, value: &Value) -> Result<(), Error> {
+        // A new login gets a new session id, so an id planted before it (session fixation) is useless after.
+        if is_login_key(key) && self.session.get::<Value>(key).ok().flatten().as_ref() != Some(value) {
+            self.session.renew();
+        }
+        self.session.insert(key, value).map_err(|e| Error::new(ErrorKind::Other, e.to_string()))
+    }
+
+    // Cookie sessions carry everything in the cookie, so warn before browsers start dropping it.
+    fn check_cookie_size(&self) {
+        let cookie_backend = config::CONFIG
+            .session
+            .as_ref()
+            .is_none_or(|session| matches!(session.backend, SessionBackend::Cookie));
+        if !cookie_backend {
+            return;
+        }
+        let cookie_name = config::CONFIG.session.as_ref().map_or("noventa_session", |s| s.cookie_name.as_str());
+        let size = cookie_size(cookie_name, &self.session.entries());
+        if size > COOKIE_SIZE_LIMIT && !COOKIE_SIZE_WARNED.swap(true, Ordering::Relaxed) {
+            log::warn!(
+                "Heads up! The session cookie is now about {} bytes, over the {} browsers keep, so they'll drop it and the session with it. Store less in `session`, or set `session.backend` to redis or memory.",
+                size, COOKIE_SIZE_LIMIT
+            );
+        }
+    }
+}
+
+fn is_login_key(key: &str) -> bool {
+    match config::CONFIG.session.as_ref().and_then(|session| session.login_keys.as_ref()) {
+        Some(keys) => keys.iter().any(|login_key| login_key == key),
+        None => DEFAULT_LOGIN_KEYS.contains(&key),
+    }
+}
+
+// Roughly how big the cookie for `entries` is: the JSON of the session, encrypted (a 12 byte
+// nonce and a 16 byte tag) and base64-encoded.
+fn cookie_size(cookie_name: &str, entries: &HashMap<String, String>) -> usize {
+    let json = serde_json::to_string(entries).map_or(0, |json| json.len());
+    cookie_name.len() + 1 + (json + 28).div_ceil(3) * 4
 }
 
 impl Actor for SessionManagerActor {
@@ -19,7 +73,7 @@ impl Actor for SessionManagerActor {
 
 // Define messages
 #[derive(Message)]
-#[rtype(result = "Result<Option<String>, Error>")]
+#[rtype(result = "Result<Option<Value>, Error>")]
 pub struct GetSessionValue {
     pub key: String,
 }
@@ -28,9 +82,30 @@ pub struct GetSessionValue {
 #[rtype(result = "Result<(), Error>")]
 pub struct SetSessionValue {
     pub key: String,
-    pub value: String,
+    pub value: Value,
 }
 
+// The value at `key`, set to `value` first if there's none, like `dict.setdefault`. In one message, so
+// two requests for it at once get the same value.
+#[derive(Message)]
+#[rtype(result = "Result<Value, Error>")]
+pub struct SetDefaultSessionValue {
+    pub key: String,
+    pub value: Value,
+}
+
+// Sets several values at once, like `dict.update`.
+#[derive(Message)]
+#[rtype(result = "Result<(), Error>")]
+pub struct UpdateSession {
+    pub values: Map<String, Value>,
+}
+
+// Every key and value, sorted by key.
+#[derive(Message)]
+#[rtype(result = "Result<Vec<(String, Value)>, Error>")]
+pub struct GetSessionEntries;
+
 #[derive(Message)]
 #[rtype(result = "Result<(), Error>")]
 pub struct DeleteSessionValue {
@@ -55,9 +130,14 @@ pub struct SetPermanent {
 #[rtype(result = "Result<(), Error>")]
 pub struct MarkAsModified;
 
+// Moves the session's data to a new id and drops the old one.
+#[derive(Message, Copy, Clone)]
+#[rtype(result = "Result<(), Error>")]
+pub struct RegenerateId;
+
 // Define message handlers
 impl Handler<GetSessionValue> for SessionManagerActor {
-    type Result = Result<Option<String>, Error>;
+    type Result = Result<Option<Value>, Error>;
 
     fn handle(&mut self, msg: GetSessionValue, _ctx: &mut Context<Self>) -> Self::Result {
         self.session.get(&msg.key).map_err(|e| Error::new(ErrorKind::Other, e.to_string()))
@@ -68,7 +148,53 @@ impl Handler<SetSessionValue> for SessionManagerActor {
     type Result = Result<(), Error>;
 
     fn handle(&mut self, msg: SetSessionValue, _ctx: &mut Context<Self>) -> Self::Result {
-        self.session.insert(&msg.key, &msg.value).map_err(|e| Error::new(ErrorKind::Other, e.to_string()))
+        self.insert(&msg.key, &msg.value)?;
+        self.check_cookie_size();
+        Ok(())
+    }
+}
+
+impl Handler<SetDefaultSessionValue> for SessionManagerActor {
+    type Result = Result<Value, Error>;
+
+    fn handle(&mut self, msg: SetDefaultSessionValue, _ctx: &mut Context<Self>) -> Self::Result {
+        if let Some(existing) = self.session.get::<Value>(&msg.key).map_err(|e| Error::other(e.to_string()))? {
+            return Ok(existing);
+        }
+        self.insert(&msg.key, &msg.value)?;
+        self.check_cookie_size();
+        Ok(msg.value)
+    }
+}
+
+impl Handler<UpdateSession> for SessionManagerActor {
+    type Result = Result<(), Error>;
+
+    fn handle(&mut self, msg: UpdateSession, _ctx: &mut Context<Self>) -> Self::Result {
+        for (key, value) in &msg.values {
+            self.insert(key, value)?;
+        }
+        self.check_cookie_size();
+        Ok(())
+    }
+}
+
+impl Handler<GetSessionEntries> for SessionManagerActor {
+    type Result = Result<Vec<(String, Value)>, Error>;
+
+    fn handle(&mut self, _msg: GetSessionEntries, _ctx: &mut Context<Self>) -> Self::Result {
+        let mut entries = self
+            .session
+            .entries()
+            .iter()
+            .map(|(key, value)| {
+                serde_json::from_str(value)
+                    .map(|value| (key.clone(), value))
+                    .map_err(|e| Error::new(ErrorKind::InvalidData, e.to_string()))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+        Ok(entries)
     }
 }
 
@@ -122,6 +248,15 @@ impl Handler<MarkAsModified> for SessionManagerActor {
     }
 }
 
+impl Handler<RegenerateId> for SessionManagerActor {
+    type Result = Result<(), Error>;
+
+    fn handle(&mut self, _msg: RegenerateId, _ctx: &mut Context<Self>) -> Self::Result {
+        self.session.renew();
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -137,7 +272,7 @@ mod tests {
         let session_mock = SessionManagerActorMock::mock(Box::new(|msg, _ctx| {
             // Mock responses for different message types
             if let Some(_) = msg.downcast_ref::<GetSessionValue>() {
-                Box::new(Some(Ok::<Option<String>, std::io::Error>(None)))
+                Box::new(Some(Ok::<Option<Value>, std::io::Error>(None)))
             } else if let Some(_) = msg.downcast_ref::<SetSessionValue>() {
                 Box::new(Some(Ok::<(), std::io::Error>(())))
             } else if let Some(_) = msg.downcast_ref::<DeleteSessionValue>() {
@@ -164,12 +299,12 @@ mod tests {
         let session_mock = SessionManagerActorMock::mock(Box::new(|msg, _ctx| {
             if let Some(get_msg) = msg.downcast_ref::<GetSessionValue>() {
                 if get_msg.key == "test_key" {
-                    Box::new(Some(Ok::<Option<String>, std::io::Error>(Some("test_value".to_string()))))
+                    Box::new(Some(Ok::<Option<Value>, std::io::Error>(Some(Value::from("test_value")))))
                 } else {
-                    Box::new(Some(Ok::<Option<String>, std::io::Error>(None)))
+                    Box::new(Some(Ok::<Option<Value>, std::io::Error>(None)))
                 }
             } else {
-                Box::new(Some(Ok::<Option<String>, std::io::Error>(None)))
+                Box::new(Some(Ok::<Option<Value>, std::io::Error>(None)))
             }
         }));
 
@@ -200,7 +335,7 @@ mod tests {
         
         let set_msg = SetSessionValue { 
             key: "test_key".to_string(), 
-            value: "test_value".to_string() 
+            value: Value::from("test_value") 
         };
         let result = addr.send(set_msg).await;
         assert!(result.is_ok());
@@ -295,11 +430,69 @@ mod tests {
         assert!(result.is_ok());
     }
 
+    #[actix_rt::test]
+    async fn test_values_keep_their_types() {
+        use actix_session::SessionExt;
+
+        let session = actix_web::test::TestRequest::default().to_http_request().get_session();
+        let addr = SessionManagerActor::new(session).start();
+
+        let mut values = Map::new();
+        values.insert("user".to_string(), serde_json::json!({ "id": 1, "roles": ["admin"] }));
+        values.insert("theme".to_string(), Value::from("dark"));
+        addr.send(UpdateSession { values }).await.unwrap().unwrap();
+        addr.send(SetSessionValue { key: "visits".to_string(), value: Value::from(3) }).await.unwrap().unwrap();
+
+        let user = addr.send(GetSessionValue { key: "user".to_string() }).await.unwrap().unwrap();
+        assert_eq!(user, Some(serde_json::json!({ "id": 1, "roles": ["admin"] })));
+        let entries = addr.send(GetSessionEntries).await.unwrap().unwrap();
+        let keys: Vec<_> = entries.iter().map(|(key, _)| key.as_str()).collect();
+        assert_eq!(keys, ["theme", "user", "visits"]);
+        assert_eq!(entries[2].1, Value::from(3));
+    }
+
+    #[actix_rt::test]
+    async fn test_set_default_keeps_the_first_value() {
+        use actix_session::SessionExt;
+
+        let session = actix_web::test::TestRequest::default().to_http_request().get_session();
+        let addr = SessionManagerActor::new(session).start();
+
+        let first = addr.send(SetDefaultSessionValue { key: "visitor".to_string(), value: Value::from("a") }).await.unwrap().unwrap();
+        let second = addr.send(SetDefaultSessionValue { key: "visitor".to_string(), value: Value::from("b") }).await.unwrap().unwrap();
+        assert_eq!((first, second), (Value::from("a"), Value::from("a")));
+    }
+
+    #[actix_rt::test]
+    async fn test_logging_in_regenerates_the_id() {
+        use actix_session::{SessionExt, SessionStatus};
+
+        let session = actix_web::test::TestRequest::default().to_http_request().get_session();
+        let addr = SessionManagerActor::new(session).start();
+
+        addr.send(SetSessionValue { key: "theme".to_string(), value: Value::from("dark") }).await.unwrap().unwrap();
+        assert_eq!(addr.send(GetStatus).await.unwrap().unwrap(), SessionStatus::Changed);
+        addr.send(SetSessionValue { key: "user_id".to_string(), value: Value::from(7) }).await.unwrap().unwrap();
+        assert_eq!(addr.send(GetStatus).await.unwrap().unwrap(), SessionStatus::Renewed);
+
+        // The data moves over to the new id.
+        let theme = addr.send(GetSessionValue { key: "theme".to_string() }).await.unwrap().unwrap();
+        assert_eq!(theme, Some(Value::from("dark")));
+    }
+
+    #[test]
+    fn test_cookie_size() {
+        let mut entries = HashMap::new();
+        assert!(cookie_size("noventa_session", &entries) < 100);
+        entries.insert("big".to_string(), "x".repeat(4000));
+        assert!(cookie_size("noventa_session", &entries) > COOKIE_SIZE_LIMIT);
+    }
+
     #[test]
     fn test_message_types() {
         // Test that all message types can be created
         let _get_msg = GetSessionValue { key: "test".to_string() };
-        let _set_msg = SetSessionValue { key: "test".to_string(), value: "value".to_string() };
+        let _set_msg = SetSessionValue { key: "test".to_string(), value: Value::from("value") };
         let _delete_msg = DeleteSessionValue { key: "test".to_string() };
         let _clear_msg = ClearSession;
         let _status_msg = GetStatus;
@@ -39,6 +39,49 @@ fn copy_dir_all(src: impl AsRef<Path>, dst: impl AsRef<Path>) -> io::Result<()>
     Ok(())
 }
 
+// Where a crawled route is saved. Each URL segment becomes one path component, so pages land in
+// the right folders on Windows too, and `..` can't climb out of the output folder.
+fn output_file(output_path: &Path, route_path: &str) -> PathBuf {
+    let segments: Vec<String> = route_path
+        .split('/')
+        .filter(|segment| !segment.is_empty() && *segment != "." && *segment != "..")
+        .map(|segment| segment.replace('\\', ""))
+        .collect();
+    let mut file_path = output_path.to_path_buf();
+    file_path.extend(&segments);
+    if segments.last().is_none_or(|last| Path::new(last).extension().is_none()) {
+        file_path.push("index.html");
+    }
+    file_path
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;").replace('\'', "&apos;")
+}
+
+// One <url> per page, with `lastmod`, `changefreq` and `priority` from its frontmatter. Pages with
+// `sitemap: false` and ones that aren't HTML (feeds, robots.txt...) are left out.
+fn sitemap_xml(site_url: &str, pages: &[(String, serde_json::Map<String, serde_json::Value>)]) -> String {
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n");
+    for (route_path, frontmatter) in pages {
+        let is_page = route_path.rsplit('/').next().is_none_or(|last| Path::new(last).extension().is_none());
+        if !is_page || frontmatter.get("sitemap") == Some(&serde_json::Value::Bool(false)) {
+            continue;
+        }
+        xml.push_str(&format!("  <url>\n    <loc>{}{}</loc>\n", xml_escape(site_url.trim_end_matches('/')), xml_escape(route_path)));
+        for field in ["lastmod", "changefreq", "priority"] {
+            match frontmatter.get(field) {
+                Some(serde_json::Value::String(value)) => xml.push_str(&format!("    <{0}>{1}</{0}>\n", field, xml_escape(value))),
+                Some(value @ serde_json::Value::Number(_)) => xml.push_str(&format!("    <{0}>{1}</{0}>\n", field, value)),
+                _ => {}
+            }
+        }
+        xml.push_str("  </url>\n");
+    }
+    xml.push_str("</urlset>\n");
+    xml
+}
+
 impl Handler<SsgMessage> for SSGActor {
     type Result = ResponseFuture<io::Result<()>>;
 
@@ -65,7 +108,8 @@ impl Handler<SsgMessage> for SSGActor {
 
             let pages_dir = config::BASE_PATH.join("pages");
             let routes = routing::get_compiled_routes(&pages_dir);
-            for route in routes {
+            let mut saved = Vec::new();
+            for route in &routes {
                 if route.regex.captures_len() <= 1 { // captures_len is number of groups + 1
                     let route_path = route.regex.to_string().trim_start_matches('^').trim_end_matches('$').to_string();
                     to_visit.push_back(route_path);
@@ -97,31 +141,35 @@ impl Handler<SsgMessage> for SSGActor {
                     }
                 }
 
-                let mut file_path = msg.output_path.clone();
-                let route_path_trimmed = route_path.trim_start_matches('/');
-                let relative_path = PathBuf::from(route_path_trimmed);
-
-                if route_path == "/" {
-                    file_path.push("index.html");
-                } else if relative_path.extension().is_some() {
-                    file_path.push(relative_path);
-                }
-                else {
-                    file_path.push(relative_path.join("index.html"));
-                }
-
-                let file_path_str = file_path.to_str().unwrap_or_default().replace('\\', "");
-                let file_path = PathBuf::from(file_path_str);
+                let file_path = output_file(&msg.output_path, &route_path);
 
                 if let Some(parent) = file_path.parent() {
                     fs::create_dir_all(parent)?;
                 }
                 fs::write(&file_path, html_content_relative)?;
                 log::info!("Saved page to: {:?}", file_path);
+                saved.push(route_path);
+            }
+
+            match &crate::config::CONFIG.site_url {
+                Some(site_url) if !visited.contains("/sitemap.xml") => {
+                    let entries: Vec<(String, serde_json::Map<String, serde_json::Value>)> = saved
+                        .into_iter()
+                        .filter_map(|route_path| {
+                            let route = routes.iter().find(|route| route.regex.is_match(&route_path))?;
+                            let frontmatter = crate::page_meta::for_page(&route.template_path.to_string_lossy(), false).frontmatter;
+                            Some((route_path, frontmatter))
+                        })
+                        .collect();
+                    fs::write(msg.output_path.join("sitemap.xml"), sitemap_xml(site_url, &entries))?;
+                    log::info!("Saved sitemap.xml with {} pages.", entries.len());
+                }
+                Some(_) => log::info!("The site has its own /sitemap.xml, so we didn't make one."),
+                None => log::info!("Set `site_url` in config.yaml to get a sitemap.xml too."),
             }
 
             if let Some(static_path_str) = &crate::config::CONFIG.static_path {
-                let static_path = if static_path_str.starts_with('/') {
+                let static_path = if Path::new(static_path_str).is_absolute() {
                     Path::new(static_path_str).to_path_buf()
                 } else {
                     crate::config::BASE_PATH.join(static_path_str)
@@ -150,4 +198,34 @@ impl Handler<SsgMessage> for SSGActor {
             Ok(())
         })
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_output_file() {
+        let out = Path::new("dist");
+        assert_eq!(output_file(out, "/"), out.join("index.html"));
+        assert_eq!(output_file(out, "/blog/first-post"), out.join("blog").join("first-post").join("index.html"));
+        assert_eq!(output_file(out, "/feed.xml"), out.join("feed.xml"));
+        assert_eq!(output_file(out, "/../etc/passwd"), out.join("etc").join("passwd").join("index.html"));
+    }
+
+    #[test]
+    fn test_sitemap_xml() {
+        let frontmatter = |value: serde_json::Value| value.as_object().unwrap().clone();
+        let pages = vec![
+            ("/".to_string(), frontmatter(serde_json::json!({"title": "Home", "priority": 1.0}))),
+            ("/blog/a&b".to_string(), frontmatter(serde_json::json!({"lastmod": "2026-10-01", "changefreq": "weekly"}))),
+            ("/drafts".to_string(), frontmatter(serde_json::json!({"sitemap": false}))),
+            ("/feed.xml".to_string(), serde_json::Map::new()),
+        ];
+        let xml = sitemap_xml("https://example.com/", &pages);
+        assert!(xml.contains("<loc>https://example.com/</loc>\n    <priority>1.0</priority>"));
+        assert!(xml.contains("<loc>https://example.com/blog/a&amp;b</loc>\n    <lastmod>2026-10-01</lastmod>\n    <changefreq>weekly</changefreq>"));
+        assert!(!xml.contains("drafts"));
+        assert!(!xml.contains("feed.xml"));
+    }
+}
@@ -1,84 +1,480 @@
 use crate::actors::health::{HealthActor, ReportTemplateLatency, ReportPythonLatency};
-use crate::actors::interpreter::{ExecuteFunction, PythonInterpreterActor};
+use crate::actors::interpreter::{ExecuteFunction, PythonError, PythonFunctionResult, PythonInterpreterActor};
+use crate::template_helpers::{Prepared, TemplateHelper};
 use crate::actors::page_renderer::{HttpRequestInfo, RenderOutput};
 use crate::actors::session_manager::SessionManagerActor;
 use crate::components::Component;
-use crate::{config, static_assets};
+use crate::{config, context_processors, form_tokens, fragments, images, interpreter_queue, page_meta, paths, props, redirects, static_assets, template_ast, template_extensions, template_filters, template_helpers};
 use crate::errors::{ComponentInfo, DetailedError, ErrorSource};
+use crate::events::Event;
+use crate::streaming::PythonStream;
+use crate::telemetry::{self, TraceContext};
 use actix::prelude::*;
 use minijinja::{Environment, State, value::Kwargs, Value};
 use regex::Regex;
 use once_cell::sync::Lazy;
-use std::collections::HashMap;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::error::Error;
-use std::sync::{Arc, RwLock};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
 
-static FORM_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"(<form[^>]*>)").unwrap());
-static COMPONENT_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"\{\{\s*component\s*\(([^)]+)\)\s*\}\}").unwrap());
-static EXTENDS_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r#"\{%\s*extends\s*"([^"]+)"\s*%\}
-"#).unwrap());
+// `load_page_context(request, session)` in a `_page.py` loads the data for every page in its folder, for
+// the page itself and, as `page.data`, for its layouts and components. `_logic.py` is read the same way,
+// like a component's; `_page.py` wins if a folder has both.
+pub const PAGE_LOGIC_FILES: &[&str] = &["_page.py", "_logic.py"];
+
+// `{% from "noventa/forms.html" import field_errors %}`: macros showing what an action's form model
+// found wrong with a posted form.
+pub const FORM_MACROS: &str = "noventa/forms.html";
+
+// Bumped when `noventa serve` is asked to pick up newly deployed pages. Every renderer thread
+// compares it with the generation its environment was built for, so a single reload reaches all of them.
+static PAGES_GENERATION: AtomicU64 = AtomicU64::new(0);
+
+pub fn invalidate_pages() {
+    PAGES_GENERATION.fetch_add(1, Ordering::SeqCst);
+    invalidate_templates();
+}
+
+// Compiled templates are shared by every renderer thread and only parsed again once this is bumped:
+// by the file watcher when a file changes in dev, and by `invalidate_pages`.
+static TEMPLATES_GENERATION: AtomicU64 = AtomicU64::new(0);
+// The environment and the generation it was built for.
+type SharedEnvironment = Option<(u64, Arc<Environment<'static>>)>;
+static SHARED_ENVIRONMENT: Lazy<RwLock<SharedEnvironment>> = Lazy::new(|| RwLock::new(None));
+
+pub fn invalidate_templates() {
+    TEMPLATES_GENERATION.fetch_add(1, Ordering::SeqCst);
+}
+
+pub fn templates_generation() -> u64 {
+    TEMPLATES_GENERATION.load(Ordering::SeqCst)
+}
+
+fn shared_environment() -> Arc<Environment<'static>> {
+    let generation = TEMPLATES_GENERATION.load(Ordering::SeqCst);
+    if let Some((built_for, env)) = SHARED_ENVIRONMENT.read().unwrap().as_ref()
+        && *built_for == generation
+    {
+        return Arc::clone(env);
+    }
+    let mut shared = SHARED_ENVIRONMENT.write().unwrap();
+    match shared.as_ref() {
+        // Another renderer got here first.
+        Some((built_for, env)) if *built_for == generation => Arc::clone(env),
+        _ => {
+            let env = Arc::new(build_environment(&config::BASE_PATH));
+            *shared = Some((generation, Arc::clone(&env)));
+            env
+        }
+    }
+}
+
+// A request's copy of the environment only keeps what it compiles to itself, so the layouts,
+// partials and components it loaded for the first time are compiled into the shared one too.
+fn share_loaded_templates(shared: &Environment<'static>, env: &Environment<'static>) {
+    for (name, _) in env.templates() {
+        if let Err(e) = shared.get_template(name) {
+            log::debug!("Couldn't add {} to the shared templates: {}", name, e);
+        }
+    }
+}
+
+// The `component()` argument naming a fallback template, which isn't passed on as a prop.
+const ON_ERROR: &str = "on_error";
+
+static FORM_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"<form[^>]*>").unwrap());
+// `<form data-action="delete">` posts to the component's `action_delete`.
+static DATA_ACTION_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r#"\sdata-action\s*=\s*["']([A-Za-z0-9_]+)["']"#).unwrap());
+// The form field telling apart the times one component is rendered on a page, e.g. twice with
+// different arguments or in a loop.
+const INSTANCE_FIELD: &str = "component_instance";
+
+// Adds the hidden fields a component's forms post back with: which component, which instance of
+// it, and the `data-action` if the form has one. Forms of nested components already have theirs.
+fn mark_forms(html: &str, component: &str, instance: &str) -> String {
+    let mut marked = String::with_capacity(html.len());
+    let mut last = 0;
+    for form in FORM_REGEX.find_iter(html) {
+        marked.push_str(&html[last..form.end()]);
+        last = form.end();
+        if html[last..].starts_with(r#"<input type="hidden" name="component_id""#) {
+            continue;
+        }
+        marked.push_str(&format!(r#"<input type="hidden" name="component_id" value="{}">"#, component));
+        marked.push_str(&format!(r#"<input type="hidden" name="{}" value="{}">"#, INSTANCE_FIELD, instance));
+        if let Some(action) = DATA_ACTION_REGEX.captures(form.as_str()) {
+            marked.push_str(&format!(r#"<input type="hidden" name="action" value="{}">"#, &action[1]));
+        }
+        if form_tokens::enabled() {
+            marked.push_str(&format!(r#"<input type="hidden" name="{}" value="{}">"#, form_tokens::FIELD, FORM_TOKEN_PLACEHOLDER));
+        }
+    }
+    marked.push_str(&html[last..]);
+    marked
+}
+
+// Where a form's token goes until the last pass, which gives every form its own with `issue_form_tokens`.
+const FORM_TOKEN_PLACEHOLDER: &str = "__noventa_form_token__";
+
+fn issue_form_tokens(html: &str) -> String {
+    let mut parts = html.split(FORM_TOKEN_PLACEHOLDER);
+    let mut issued = parts.next().unwrap_or_default().to_string();
+    for part in parts {
+        issued.push_str(&form_tokens::issue());
+        issued.push_str(part);
+    }
+    issued
+}
+
+// A component with forms, with its hidden fields added and between comments naming the instance,
+// so frontend.js can find it again and swap in what a POST to it returns.
+fn mark_component(html: &str, component: &str, instance: &str) -> String {
+    let marked = mark_forms(html, component, instance);
+    if marked.len() == html.len() {
+        return marked;
+    }
+    format!("<!--noventa-component:{}:{}-->{}<!--/noventa-component-->", component, instance, marked)
+}
+
+// The same for the same arguments, whatever order they're written in.
+fn kwargs_hash(kwargs: &HashMap<String, Value>) -> String {
+    let sorted: BTreeMap<&String, &Value> = kwargs.iter().collect();
+    let hash = Sha256::digest(serde_json::to_string(&sorted).unwrap_or_default().as_bytes());
+    format!("{:x}", hash)[..8].to_string()
+}
+
+// How many times each component has been rendered with each set of arguments so far.
+type Instances = Arc<Mutex<HashMap<String, usize>>>;
+
+// The first `status()` a render reached, or `_status` a component returned, with the template to send.
+type RequestedStatus = Arc<Mutex<Option<(u16, Option<String>)>>>;
+
+// What a component's `load_template_context` or a helper from template_helpers.py came back with.
+#[derive(Clone)]
+enum LoadedCall {
+    Context(Result<Result<PythonFunctionResult, PythonError>, MailboxError>),
+    Helper(Result<Value, Box<DetailedError>>),
+}
+
+// Everything loaded for one render so far. A call is found by what it was called with: a component
+// by its instance (`card:1a2b3c4d-0`), a helper by its arguments.
+#[derive(Default)]
+struct Loaded {
+    calls: HashMap<String, LoadedCall>,
+    // For each slot (see `ComponentScope::next_slot`), what was loaded for it last and how many times.
+    slots: HashMap<String, (String, usize)>,
+    // What `flashed_messages()` gives, once the render has asked for it.
+    flashes: Option<Value>,
+}
+
+impl Loaded {
+    fn insert(&mut self, slot: Option<String>, key: String, call: LoadedCall) {
+        if let Some(slot) = slot {
+            let loads = self.slots.get(&slot).map_or(0, |(_, loads)| *loads);
+            self.slots.insert(slot, (key.clone(), loads + 1));
+        }
+        self.calls.insert(key, call);
+    }
+
+    // What was loaded for `key`. A slot that was already loaded twice and comes with new arguments yet
+    // again gets them from something that changes on every render, like the current time; loading it
+    // once more wouldn't catch up, so what it got last stands in.
+    fn get(&self, slot: &str, key: &str) -> Option<LoadedCall> {
+        if let Some(call) = self.calls.get(key) {
+            return Some(call.clone());
+        }
+        match self.slots.get(slot) {
+            Some((last, loads)) if *loads >= 2 => self.calls.get(last).cloned(),
+            _ => None,
+        }
+    }
+}
+
+// Something a pass reached before it was loaded.
+enum PendingCall {
+    // The components the page always shows are loaded before the first pass, without a slot.
+    Component { slot: Option<String>, instance: String, module_path: String, args: HashMap<String, Value> },
+    Helper { slot: String, call: template_helpers::HelperCall },
+    Flashes,
+}
+
+// The action a POST ran, whose result the instance that posted is rendered with.
+struct PostedAction {
+    component: String,
+    // Forms rendered before instances had IDs don't say; the result goes to every instance then.
+    instance: Option<String>,
+    context: Option<Value>,
+}
+
+// A component that failed and was replaced by its `on_error` fallback.
+struct Failure {
+    component: String,
+    fallback: String,
+    error: DetailedError,
+}
+
+// What the passes of one render share: what ran before the first one and what's been loaded since.
+#[derive(Clone)]
+struct RenderJob {
+    msg: RenderTemplate,
+    page_data: Value,
+    processed: Arc<serde_json::Map<String, serde_json::Value>>,
+    helpers: Arc<Vec<TemplateHelper>>,
+    // What the action emitted, for the components' `on_event_*` hooks.
+    events: Arc<Vec<Event>>,
+    posted: Arc<Option<PostedAction>>,
+    // The component posted to, when it's rendered without the page.
+    partial: Option<ComponentCall>,
+    // Where instances start being numbered: a component rendered alone keeps its number from the page.
+    first_instances: HashMap<String, usize>,
+    loaded: Arc<Mutex<Loaded>>,
+}
+
+// What the `component()` calls and helpers of one pass share.
+#[derive(Clone)]
+struct ComponentScope {
+    components: Arc<RwLock<Vec<Component>>>,
+    request_info: Arc<HttpRequestInfo>,
+    dev_mode: bool,
+    trace: Option<TraceContext>,
+    posted: Arc<Option<PostedAction>>,
+    first_instances: HashMap<String, usize>,
+    instances: Instances,
+    loaded: Arc<Mutex<Loaded>>,
+    // The slots of the components the render is inside, innermost last, and how many calls each
+    // slot has had so far.
+    parents: Arc<Mutex<Vec<String>>>,
+    slots: Arc<Mutex<HashMap<String, usize>>>,
+    pending: Arc<Mutex<Vec<PendingCall>>>,
+    status: RequestedStatus,
+    failures: Arc<Mutex<Vec<Failure>>>,
+}
+
+impl ComponentScope {
+    fn new(job: &RenderJob, components: &Arc<RwLock<Vec<Component>>>, dev_mode: bool) -> Self {
+        Self {
+            components: Arc::clone(components),
+            request_info: job.msg.request_info.clone(),
+            dev_mode,
+            trace: job.msg.trace,
+            posted: Arc::clone(&job.posted),
+            first_instances: job.first_instances.clone(),
+            instances: Instances::default(),
+            loaded: Arc::clone(&job.loaded),
+            parents: Arc::default(),
+            slots: Arc::default(),
+            pending: Arc::default(),
+            status: RequestedStatus::default(),
+            failures: Arc::default(),
+        }
+    }
+
+    // Every pass numbers instances and slots the same way, and asks for its own status.
+    fn start_pass(&self) {
+        *self.instances.lock().unwrap() = self.first_instances.clone();
+        self.parents.lock().unwrap().clear();
+        self.slots.lock().unwrap().clear();
+        self.pending.lock().unwrap().clear();
+        *self.status.lock().unwrap() = None;
+        self.failures.lock().unwrap().clear();
+    }
+
+    // `<slot of the component it's in>/<name>#<n>`: the nth call to `name` in there. A call lands on
+    // the same slot on every pass, whatever arguments it's given.
+    fn next_slot(&self, name: &str) -> String {
+        let parent = self.parents.lock().unwrap().last().cloned().unwrap_or_default();
+        let mut slots = self.slots.lock().unwrap();
+        let count = slots.entry(format!("{}/{}", parent, name)).or_insert(0);
+        *count += 1;
+        format!("{}/{}#{}", parent, name, *count - 1)
+    }
+
+    // Renders what's inside the component at `slot`.
+    fn inside<T>(&self, slot: &str, render: impl FnOnce() -> T) -> T {
+        self.parents.lock().unwrap().push(slot.to_string());
+        let rendered = render();
+        self.parents.lock().unwrap().pop();
+        rendered
+    }
+
+    // A filter or global from template_helpers.py: what it was loaded with, or nothing on this pass.
+    fn call_helper(&self, helper: &TemplateHelper, args: Vec<Value>, kwargs: Kwargs) -> Result<Value, minijinja::Error> {
+        let slot = self.next_slot(&format!("{}()", helper.name));
+        let call = match template_helpers::prepare(helper, args, kwargs)? {
+            Prepared::Cached(value) => return Ok(value),
+            Prepared::Call(call) => call,
+        };
+        let loaded = self.loaded.lock().unwrap().get(&slot, &call.key);
+        if let Some(LoadedCall::Helper(outcome)) = loaded {
+            return template_helpers::template_result(&helper.name, &outcome.map_err(|e| *e));
+        }
+        let mut pending = self.pending.lock().unwrap();
+        // Called again with the same arguments before it was loaded.
+        let waiting = pending.iter().any(|pending| matches!(pending, PendingCall::Helper { call: waiting, .. } if waiting.key == call.key));
+        if !waiting {
+            pending.push(PendingCall::Helper { slot, call });
+        }
+        Ok(Value::UNDEFINED)
+    }
+
+    fn want_flashes(&self) {
+        let mut pending = self.pending.lock().unwrap();
+        if !pending.iter().any(|pending| matches!(pending, PendingCall::Flashes)) {
+            pending.push(PendingCall::Flashes);
+        }
+    }
+
+    // The failures behind the fallbacks on the finished page, recorded once.
+    fn record_failures(&self) {
+        for failure in self.failures.lock().unwrap().drain(..) {
+            crate::errors::record_error(&failure.error);
+            log::error!(
+                "Oh no! The `{}` component failed on {}: {}. Showing {} in its place.",
+                failure.component,
+                self.request_info.path,
+                failure.error.message,
+                failure.fallback
+            );
+        }
+    }
+}
+
+// `<hash of the arguments>-<how many times they came before>`: stable from one render of the page to
+// the next, so a form posted from one instance is matched to that instance again.
+fn next_instance(instances: &Instances, component: &str, kwargs: &HashMap<String, Value>) -> String {
+    let hash = kwargs_hash(kwargs);
+    let mut instances = instances.lock().unwrap();
+    let count = instances.entry(format!("{}:{}", component, hash)).or_insert(0);
+    *count += 1;
+    format!("{}-{}", hash, *count - 1)
+}
+
+// The instances (`card:1a2b3c4d-0`) of the calls `prefetch_calls` loads, numbered the way the render
+// numbers them: the same component with the same arguments twice on a page is `-0`, then `-1`. Whichever
+// call the render reaches first with those arguments is the one that takes `-0`, so a result loaded for
+// an instance is right for the call that ends up with it.
+fn prefetch_instances(calls: &[ComponentCall]) -> Vec<(String, &ComponentCall)> {
+    let instances = Instances::default();
+    calls
+        .iter()
+        .filter(|call| call.prefetch)
+        .map(|call| (format!("{}:{}", call.name, next_instance(&instances, &call.name, &call.kwargs)), call))
+        .collect()
+}
 
 // Actor for rendering templates
 pub struct TemplateRendererActor {
-    env: Arc<Environment<'static>>,
-    interpreter: Addr<PythonInterpreterActor>,
     health_actor: Addr<HealthActor>,
     dev_mode: bool,
     components: Arc<RwLock<Vec<Component>>>,
     page_component_map: Arc<RwLock<HashMap<String, Vec<ComponentCall>>>>,
+    pages_generation: u64,
 }
 
 #[derive(Debug, Clone)]
 struct ComponentCall {
     name: String,
     kwargs: HashMap<String, Value>,
+    // Rendered on every request for the page, with all its arguments known, so its context can be
+    // loaded before the first pass. See `prefetch_calls`.
+    prefetch: bool,
+}
+
+// Where a template is on the page: whether it's rendered every time, and whether it's a layout, whose
+// blocks the template extending it may replace.
+#[derive(Debug, Clone, Copy)]
+struct Placement {
+    rendered: bool,
+    layout: bool,
+}
+
+// The action a POST runs before the page is rendered.
+struct PlannedAction {
+    call: ExecuteFunction,
+    component: String,
+    // Given back if the action fails, so sending the form again tries again.
+    form_token: Option<String>,
+}
+
+// What a render calls before its first pass, worked out from the request.
+struct RenderPlan {
+    // The page's `load_page_context`, and the file it's in.
+    page_data: Option<(ExecuteFunction, String)>,
+    action: Option<PlannedAction>,
+    posted: Option<PostedAction>,
+    // The component posted to, when frontend.js asked for only that and it can go alone, with its instance.
+    partial: Option<(ComponentCall, String)>,
+    prefetch: Vec<PendingCall>,
 }
 
 impl TemplateRendererActor {
     pub fn new(
-        interpreter: Addr<PythonInterpreterActor>,
         health_actor: Addr<HealthActor>,
         dev_mode: bool,
         components: Vec<Component>,
     ) -> Self {
-        let mut env = Environment::new();
-        minijinja_contrib::add_to_environment(&mut env);
-        env.add_filter("format", format_filter);
-        env.set_loader(minijinja::path_loader(config::BASE_PATH.to_str().unwrap()));
-
         Self {
-            env: Arc::new(env),
-            interpreter,
             health_actor,
             dev_mode,
             components: Arc::new(RwLock::new(components)),
             page_component_map: Arc::new(RwLock::new(HashMap::new())),
+            pages_generation: PAGES_GENERATION.load(Ordering::SeqCst),
         }
     }
 
+    // Drops cached templates and rescans components and pages after `invalidate_pages`.
+    fn refresh_if_stale(&mut self) {
+        let generation = PAGES_GENERATION.load(Ordering::SeqCst);
+        if generation == self.pages_generation {
+            return;
+        }
+        match crate::components::scan_project_components(std::path::Path::new(".")) {
+            Ok(components) => *self.components.write().unwrap() = components,
+            Err(e) => log::error!("Failed to rescan components: {}", e),
+        }
+        self.scan_and_cache_components();
+        self.pages_generation = generation;
+    }
+
     fn scan_and_cache_components(&mut self) {
         let mut page_component_map = self.page_component_map.write().unwrap();
         page_component_map.clear();
 
-        let pages_dir = config::BASE_PATH.join("pages");
-        if let Ok(entries) = std::fs::read_dir(pages_dir) {
-            for entry in entries.filter_map(Result::ok) {
-                let path = entry.path();
-                if path.is_file() {
-                    if let Some(template_name) = path.strip_prefix(&*config::BASE_PATH).ok().and_then(|p| p.to_str()) {
-                        let mut component_calls = Vec::new();
-                        if let Ok(template) = self.env.get_template(template_name) {
-                            if self.recursive_scan(template_name, template.source(), &mut component_calls).is_ok() {
-                                page_component_map.insert(template_name.to_string(), component_calls);
-                            }
-                        }
+        let env = shared_environment();
+        // Pages in subfolders post forms too.
+        let pages = walkdir::WalkDir::new(config::BASE_PATH.join("pages")).into_iter().filter_map(Result::ok).filter(|entry| entry.path().is_file());
+        for entry in pages {
+            if let Ok(relative) = entry.path().strip_prefix(&*config::BASE_PATH) {
+                let template_name = &paths::to_slash(relative);
+                let mut component_calls = Vec::new();
+                if let Ok(template) = env.get_template(template_name) {
+                    if self.recursive_scan(template_name, template.source(), &mut component_calls, &mut HashSet::new()).is_ok() {
+                        page_component_map.insert(template_name.to_string(), component_calls);
                     }
                 }
             }
         }
     }
 
-    fn handle_post_request(&mut self, msg: RenderTemplate) -> Result<RenderOutput, DetailedError> {
+    #[allow(clippy::result_large_err)]
+    fn plan(&self, msg: &RenderTemplate) -> Result<RenderPlan, DetailedError> {
+        let mut plan = RenderPlan {
+            page_data: self.page_data_call(msg)?,
+            action: None,
+            posted: None,
+            partial: None,
+            prefetch: self.prefetch_calls(msg),
+        };
+        if msg.request_info.method == "POST" {
+            self.plan_post(msg, &mut plan)?;
+        }
+        Ok(plan)
+    }
+
+    #[allow(clippy::result_large_err)]
+    fn plan_post(&self, msg: &RenderTemplate, plan: &mut RenderPlan) -> Result<(), DetailedError> {
         // Phase 1: Look up component calls from the cached map.
         let page_component_map = self.page_component_map.read().unwrap();
         let component_calls = page_component_map.get(&msg.template_name).ok_or_else(|| DetailedError {
@@ -94,13 +490,16 @@ impl TemplateRendererActor {
             })?;
         let form_component_id = form_data.get("component_id").cloned().unwrap_or_default();
         let action = form_data.get("action").cloned().unwrap_or_default();
+        // Forms rendered before instances had IDs don't say; the action's result goes to every instance then.
+        let form_instance: Option<String> = form_data.get(INSTANCE_FIELD).cloned();
+        // `form_tokens`: a form sent again (a refresh, the back button, a double click) doesn't run its action twice.
+        let form_token = form_data.get(form_tokens::FIELD).filter(|_| form_tokens::enabled()).cloned();
+        let replayed = form_token.as_deref().is_some_and(|token| !form_tokens::consume(token));
 
         log::debug!("Handling POST request for component '{}', action '{}'", form_component_id, action);
 
-    // Phase 2: Act & Cache - Execute the action handler for the target component *before* rendering.
+    // Phase 2: Act & Cache - Find the action handler for the target component, to run *before* rendering.
         // The unique context returned by the action is cached to be used in the final render.
-        let mut action_context = None;
-
         log::debug!("--- Debugging POST Request ---");
         log::debug!("Form Component ID: '{}'", form_component_id);
         log::debug!("Component Calls Found:");
@@ -108,11 +507,26 @@ impl TemplateRendererActor {
             log::debug!("  - Name: {}, Kwargs: {:?}", call.name, call.kwargs);
         }
 
-        let found_component = component_calls.iter().find(|c| c.name == form_component_id);
+        // Of the calls to the component, the one with the arguments the posting instance was rendered
+        // with. Arguments only known at render time don't show up in the scan, so the first call stands
+        // in for those.
+        let posted_hash = form_instance.as_deref().and_then(|instance| instance.split('-').next());
+        let found_component = component_calls
+            .iter()
+            .find(|c| c.name == form_component_id && posted_hash == Some(kwargs_hash(&c.kwargs).as_str()))
+            .or_else(|| component_calls.iter().find(|c| c.name == form_component_id));
+        // The component on its own, when frontend.js asked for only that and it can be rendered
+        // without the page: all its arguments are in the scan.
+        plan.partial = found_component
+            .filter(|call| msg.request_info.component_only && posted_hash == Some(kwargs_hash(&call.kwargs).as_str()))
+            .cloned()
+            .zip(form_instance.clone());
 
         if let Some(action_component_call) = found_component {
             log::debug!("Successfully found component to handle action: '{}'", action_component_call.name);
-            if !action.is_empty() {
+            if replayed {
+                log::info!("Skipped '{}' on {}: that form was already sent.", action, action_component_call.name);
+            } else if !action.is_empty() {
                 let mut form_data_value = HashMap::new();
                 for (k, v) in form_data {
                     form_data_value.insert(k.clone(), Value::from(v.clone()));
@@ -129,66 +543,26 @@ impl TemplateRendererActor {
                     message: format!("Component '{}' not found", action_component_call.name),
                     ..Default::default()
                 })?;
+                if let Some(component_props) = &component.props {
+                    props::check(component_props, &mut kwargs_map_post);
+                }
                 if let Some(logic_path) = &component.logic_path {
                     let module_path = path_to_module(logic_path).map_err(|e| DetailedError {
                         message: format!("Invalid module path: {}", e),
                         ..Default::default()
                     })?;
 
-                    let execute_fn_msg = ExecuteFunction {
+                    let call = ExecuteFunction {
                         module_path,
                         function_name: format!("action_{}", action),
                         request: msg.request_info.clone(),
                         args: Some(kwargs_map_post),
                         session_manager: msg.session_manager.clone(),
+                        state: form_instance.as_ref().map(|instance| format!("{}:{}", action_component_call.name, instance)),
+                        events: Vec::new(),
+                        trace: msg.trace,
                     };
-
-                    let result = futures::executor::block_on(self.interpreter.send(execute_fn_msg));
-                    match result {
-                        Ok(Ok(result)) => {
-                            if let Ok(redirect_url) = result.context.get_attr("_redirect") {
-                                if !redirect_url.is_undefined() && !redirect_url.is_none() {
-                                    if let Some(url_str) = redirect_url.as_str() {
-                                        return Ok(RenderOutput::Redirect(url_str.to_string()));
-                                    }
-                                }
-                            }
-                            action_context = Some(result.context);
-                        }
-                        Ok(Err(py_err)) => {
-                            return Err(DetailedError {
-                                component: Some(ComponentInfo {
-                                    name: action_component_call.name.clone(),
-                                }),
-                                error_source: Some(ErrorSource::Python(py_err.clone())),
-                                message: py_err.message.clone(),
-                                file_path: py_err.filename.clone().unwrap_or_default(),
-                                line: py_err.line_number.unwrap_or(0) as u32,
-                                column: py_err.column_number.unwrap_or(0) as u32,
-                                end_line: py_err.end_line_number.map(|l| l as u32),
-                                end_column: py_err.end_column_number.map(|c| c as u32),
-                                ..Default::default()
-                            });
-                        }
-                        Err(e) => {
-                            log::error!("A mailbox error occurred: {}. This might indicate a problem with the server's internal communication.", e);
-                            return Err(DetailedError {
-                                error_source: Some(ErrorSource::Python(
-                                    crate::actors::interpreter::PythonError {
-                                        message: e.to_string(),
-                                        traceback: format!("{:?}", e),
-                                        line_number: None,
-                                        column_number: None,
-                                        end_line_number: None,
-                                        end_column_number: None,
-                                        filename: None,
-                                        source_code: None,
-                                    },
-                                )),
-                                ..Default::default()
-                            });
-                        }
-                    }
+                    plan.action = Some(PlannedAction { call, component: action_component_call.name.clone(), form_token });
                 }
             }else{
                 return Err(DetailedError {
@@ -216,231 +590,161 @@ impl TemplateRendererActor {
             });
         }
 
-        // Phase 3: Render - Render the full page.
-        let mut env = if self.dev_mode {
-            let mut new_env = Environment::new();
-            minijinja_contrib::add_to_environment(&mut new_env);
-            new_env.set_loader(minijinja::path_loader("."));
-            new_env
-        } else {
-            (*self.env).clone()
-        };
-
-    let interpreter_clone = self.interpreter.clone();
-        let health_actor_clone = self.health_actor.clone();
-        let request_info_clone = msg.request_info.clone();
-        let session_manager_clone = msg.session_manager.clone();
-        let components_clone = Arc::clone(&self.components);
-        let action_context = Arc::new(action_context);
-        let form_component_id = form_component_id.clone();
-
-        env.add_function(
-            "component",
-            move |state: &State, name: String, kwargs: Kwargs| -> Result<Value, minijinja::Error> {
-                let name = name.replace(".", "/");
-                let kwargs_map: HashMap<String, Value> = kwargs
-                    .args()
-                    .filter_map(|k| kwargs.get::<Value>(k).ok().map(|v| (k.to_string(), v)))
-                    .collect();
-
-                let components = components_clone.read().unwrap();
-                let component = components.iter().find(|c| c.id == name).unwrap();
-                let context_result = if let Some(logic_path) = &component.logic_path {
-                    let module_path = path_to_module(logic_path).unwrap();
-                    let execute_fn_msg = ExecuteFunction {
-                        module_path,
-                        function_name: "load_template_context".to_string(),
-                        request: request_info_clone.clone(),
-                        args: Some(kwargs_map),
-                        session_manager: session_manager_clone.clone(),
-                    };
-
-                    let python_start_time = std::time::Instant::now();
-                    let future = interpreter_clone.send(execute_fn_msg);
-                    let result = futures::executor::block_on(future);
-                    let python_duration_ms = python_start_time.elapsed().as_secs_f64() * 1000.0;
-                    health_actor_clone.do_send(ReportPythonLatency(python_duration_ms));
-
-                    match result {
-                        Ok(Ok(res)) => Ok(res.context),
-                        Ok(Err(py_err)) => {
-                            let detailed_error = DetailedError {
-                                component: Some(ComponentInfo { name: name.clone() }),
-                                error_source: Some(ErrorSource::Python(py_err.clone())),
-                                message: py_err.message.clone(),
-                                file_path: py_err.filename.clone().unwrap_or_default(),
-                                line: py_err.line_number.unwrap_or(0) as u32,
-                                column: py_err.column_number.unwrap_or(0) as u32,
-                                end_line: py_err.end_line_number.map(|l| l as u32),
-                                end_column: py_err.end_column_number.map(|c| c as u32),
-                                ..Default::default()
-                            };
-                            let err = minijinja::Error::new(
-                                minijinja::ErrorKind::InvalidOperation,
-                                "Python function crashed",
-                            );
-                            Err(err.with_source(detailed_error))
-                        }
-                        Err(e) => {
-                            log::error!("A mailbox error occurred: {}. This might indicate a problem with the server's internal communication.", e);
-                            Err(minijinja::Error::new(minijinja::ErrorKind::InvalidOperation, "Mailbox error").with_source(e))
-                        }
-                    }
-                } else {
-                    // If there's no logic_path, there's no context to load.
-                    Ok(Value::from_serialize(serde_json::json!({})))
-                };
-
-                match context_result {
-                    Ok(context) => {
-                        let mut final_context = context;
-                        // If this is the component that handled the POST request, merge the action context.
-                        if name == form_component_id {
-                            if let Some(action_ctx) = action_context.as_ref().as_ref() {
-                                let get_ctx_result = serde_json::to_value(&final_context);
-                                let action_ctx_result = serde_json::to_value(action_ctx);
-
-                                let mut get_ctx_map: serde_json::Value = match get_ctx_result {
-                                    Ok(val) => val,
-                                    Err(e) => return Err(minijinja::Error::new(minijinja::ErrorKind::InvalidOperation, "Failed to serialize context").with_source(e)),
-                                };
-
-                                let action_ctx_map: serde_json::Value = match action_ctx_result {
-                                    Ok(val) => val,
-                                    Err(e) => return Err(minijinja::Error::new(minijinja::ErrorKind::InvalidOperation, "Failed to serialize action context").with_source(e)),
-                                };
-
-                                if let (Some(get_map), Some(action_map)) = (get_ctx_map.as_object_mut(), action_ctx_map.as_object()) {
-                                    for (k, v) in action_map.iter() {
-                                        get_map.insert(k.clone(), v.clone());
-                                    }
-                                }
-                                final_context = Value::from_serialize(get_ctx_map);
-                            }
-                        }
-
-                        let components = components_clone.read().unwrap();
-                        let component = components.iter().find(|c| c.id == name).ok_or_else(|| {
-                            minijinja::Error::new(minijinja::ErrorKind::TemplateNotFound, "Component not found")
-                        })?;
-                        let mut template_path = component.template_path.clone();
-                        if template_path.starts_with("./") {
-                            template_path = template_path[2..].to_string();
-                        }
-                        let tmpl = state.env().get_template(&template_path)?;
-                        let mut result = tmpl.render(final_context)?;
-
-                        let re = Regex::new(r"(<form[^>]*>)").unwrap();
-                        let replacement = format!(r#"$1<input type="hidden" name="component_id" value="{}">"#, name);
-                        result = re.replace_all(&result, replacement).to_string();
+        plan.posted = Some(PostedAction { component: form_component_id, instance: form_instance, context: None });
+        Ok(())
+    }
 
-                        Ok(Value::from_safe_string(result))
-                    }
-                    Err(e) => Err(e),
-                }
-            },
+    // One pass over the page, or just the component posted to when the job says it can go alone, with
+    // what's loaded so far. See `render`.
+    #[allow(clippy::result_large_err)]
+    fn render_pass(&self, job: &RenderJob) -> Result<Pass, DetailedError> {
+        let msg = &job.msg;
+        let scope = ComponentScope::new(job, &self.components, self.dev_mode);
+        let shared = shared_environment();
+        // Compiled in the shared environment first, so the copy already has the page.
+        let _ = shared.get_template(&msg.template_name);
+        let mut env = (*shared).clone();
+        context_processors::apply(&mut env, (*job.processed).clone());
+        let flashes = job.loaded.lock().unwrap().flashes.clone();
+        let wanting = scope.clone();
+        redirects::add_flash_global(&mut env, flashes, move || wanting.want_flashes());
+        env.add_global(
+            template_filters::LOCALE_GLOBAL,
+            template_filters::request_locale(&msg.request_info.accept_languages),
         );
+        // Lets pages add `nonce="{{ csp_nonce }}"` to their own inline scripts.
+        env.add_global("csp_nonce", msg.request_info.csp_nonce.clone().unwrap_or_default());
+        let calling = scope.clone();
+        template_helpers::apply(&mut env, &job.helpers, move |helper, args, kwargs| calling.call_helper(helper, args, kwargs));
+        // `{{ page.meta.title }}`, from the frontmatter of the page being rendered, in the page and its layouts,
+        // and `{{ page.data }}` from its `_page.py`.
+        let frontmatter = page_meta::for_page(&msg.template_name, self.dev_mode).frontmatter;
+        env.add_global("page", minijinja::context! { meta => Value::from_serialize(&frontmatter), data => job.page_data.clone() });
+        add_status_function(&mut env, &scope.status);
+        add_component_function(&mut env, scope.clone());
+
+        let start_time = std::time::Instant::now();
+        let passed = run_pass(&scope, || match &job.partial {
+            // Swapped into a page that already went out with its status.
+            Some(call) => env
+                .render_str("{{ component(name, **kwargs) }}", minijinja::context! { name => call.name, kwargs => call.kwargs })
+                .map(|html| (None, html)),
+            None => self
+                .render_page(&env, &msg.template_name, &msg.request_info, job.page_data.clone())
+                .and_then(|html| self.apply_status(&env, &scope, &msg.request_info, html, &job.page_data)),
+        });
+        share_loaded_templates(&shared, &env);
+        let (status, html) = match passed.map_err(|e| template_error(&msg.template_name, e))? {
+            Passed::Pending(calls) => return Ok(Pass::Pending(calls)),
+            Passed::Redirect(redirect) => return Ok(Pass::Redirect(redirect)),
+            Passed::Rendered(rendered) => rendered,
+        };
+        self.health_actor.do_send(ReportTemplateLatency(start_time.elapsed().as_secs_f64() * 1000.0));
+        let html = issue_form_tokens(&html);
+        if job.partial.is_some() {
+            return Ok(Pass::Done(RenderOutput::Component(html)));
+        }
+        Ok(Pass::Done(with_status(status, html)))
+    }
 
-        let rendered_page = self.render_page(&env, &msg.template_name).map_err(|e| {
-            if let Some(detailed_error) = e.source().and_then(|s| s.downcast_ref::<DetailedError>()) {
-                return detailed_error.clone();
+    // The page with the status it asked for, if it asked for one, and in place of it the template that
+    // goes with that status. A fragment keeps its own html; the template is a whole page.
+    fn apply_status(&self, env: &Environment, scope: &ComponentScope, request_info: &HttpRequestInfo, html: String, page_data: &Value) -> Result<(Option<u16>, String), minijinja::Error> {
+        let status = scope.status.lock().unwrap().take().or_else(|| requested_status(page_data));
+        match status {
+            Some((code, Some(template))) if request_info.fragment.is_none() => {
+                Ok((Some(code), self.render_page(env, &template, request_info, page_data.clone())?))
             }
-            let template_info = crate::errors::TemplateInfo {
-                name: e.name().unwrap_or(&msg.template_name).to_string(),
-                line: e.line().unwrap_or(0),
-                source: None,
-                source_code: {
-                    let filename = e.name().unwrap_or(&msg.template_name);
-                    if let Ok(contents) = std::fs::read_to_string(filename) {
-                        if let Some(ln) = e.line() {
-                            let start = (ln as isize - 7).max(0) as usize;
-                            let end = (ln + 6).min(contents.lines().count());
-                            Some(contents.lines().skip(start).take(end - start).collect::<Vec<_>>().join("\n"))
-                        } else {
-                            None
-                        }
-                    } else {
-                        None
-                    }
-                },
-                detail: e.detail().unwrap_or("").to_string(),
-                traceback: Some(format!("{:?}", e)),
+            status => Ok((status.map(|(code, _)| code), html)),
+        }
+    }
+
+    // The components the page renders every time with arguments known in advance (the header, the
+    // sidebar: not ones in loops, under an `{% if %}`, or with arguments from variables) are loaded
+    // before the first pass, so it already has them. See `render` for the rest.
+    fn prefetch_calls(&self, msg: &RenderTemplate) -> Vec<PendingCall> {
+        let page_component_map = self.page_component_map.read().unwrap();
+        let components = self.components.read().unwrap();
+        let calls = page_component_map.get(&msg.template_name).map(Vec::as_slice).unwrap_or_default();
+        let mut pending = Vec::new();
+        for (instance, call) in prefetch_instances(calls) {
+            let Some(component) = components.iter().find(|c| c.id == call.name) else {
+                continue;
             };
-            DetailedError {
-                page: Some(template_info.clone()),
-                error_source: Some(ErrorSource::Template(template_info.clone())),
-                file_path: e.name().unwrap_or(&msg.template_name).to_string(),
-                line: template_info.line as u32,
-                ..Default::default()
+            let Some(module_path) = component.logic_path.as_deref().and_then(|path| path_to_module(path).ok()) else {
+                continue;
+            };
+            let mut args = call.kwargs.clone();
+            if let Some(component_props) = &component.props {
+                props::check(component_props, &mut args);
             }
+            pending.push(PendingCall::Component { slot: None, instance, module_path, args });
+        }
+        pending
+    }
+
+    // The page's `load_page_context`, if it has a `_page.py`.
+    #[allow(clippy::result_large_err)]
+    fn page_data_call(&self, msg: &RenderTemplate) -> Result<Option<(ExecuteFunction, String)>, DetailedError> {
+        let Some(logic_path) = page_logic_path(&config::BASE_PATH, &msg.template_name) else {
+            return Ok(None);
+        };
+        let module_path = path_to_module(&logic_path).map_err(|e| DetailedError {
+            message: format!("Invalid module path: {}", e),
+            ..Default::default()
         })?;
-        Ok(RenderOutput::Html(rendered_page))
+        let call = ExecuteFunction {
+            module_path,
+            function_name: "load_page_context".to_string(),
+            request: msg.request_info.clone(),
+            args: None,
+            session_manager: msg.session_manager.clone(),
+            state: None,
+            events: Vec::new(),
+            trace: msg.trace,
+        };
+        Ok(Some((call, logic_path)))
     }
 
     // Recursively scans template files to find all `{{ component(...) }}` calls.
     // This builds a complete tree of all components on a page and their arguments,
     // without executing any of them.
-    fn recursive_scan(&self, template_name: &str, template_content: &str, calls: &mut Vec<ComponentCall>) -> Result<(), minijinja::Error> {
-        log::debug!("Scanning template: {}", template_name);
-
-        // First, check for an `extends` tag and scan the parent template.
-        if let Some(caps) = EXTENDS_REGEX.captures(template_content) {
-            if let Some(parent_template_name) = caps.get(1) {
-                let parent_name = parent_template_name.as_str();
-                log::debug!("Found extends tag, scanning parent: {}", parent_name);
-                let parent_template = self.env.get_template(parent_name)?;
-                self.recursive_scan(parent_name, parent_template.source(), calls)?;
-            }
-        }
-
-        // Now, scan the current template for component calls.
-        for cap in COMPONENT_REGEX.captures_iter(template_content) {
-            let args_str = &cap[1];
-            // Manual parsing of arguments from the template string.
-            let mut parts = args_str.split(',');
-            let name = parts.next().unwrap_or("").trim().replace("'", "").replace("\"", "");
-            let name = name.replace(".", "/");
-            let mut kwargs_map = HashMap::new();
-            for part in parts {
-                let mut kv = part.splitn(2, '=');
-                if let (Some(key), Some(val)) = (kv.next(), kv.next()) {
-                    let key = key.trim().to_string();
-                    let val_str = val.trim().to_string();
-                    // This is a simplification; it doesn't handle complex values like variables.
-                    // For now, we'll assume string literals.
-                    let value = Value::from(val_str.replace("'", "").replace("\"", ""));
-                    kwargs_map.insert(key, value);
-                }
-            }
-
-            let components = self.components.read().unwrap();
-            let component = components.iter().find(|c| c.id == name).ok_or_else(|| {
-                minijinja::Error::new(minijinja::ErrorKind::TemplateNotFound, "Component not found")
-            })?;
-
-            // Recurse into the component's own template to find nested components.
-            self.recursive_scan(&component.id, &component.template_content, calls)?;
-            calls.push(ComponentCall { name, kwargs: kwargs_map });
-        }
-
-        Ok(())
+    fn recursive_scan(&self, template_name: &str, template_content: &str, calls: &mut Vec<ComponentCall>, scanned: &mut HashSet<String>) -> Result<(), minijinja::Error> {
+        let components = self.components.read().unwrap();
+        let page = Placement { rendered: true, layout: false };
+        scan_component_calls(&shared_environment(), &components, template_name, template_content, page, calls, scanned)
     }
 
-    fn render_page(&self, env: &Environment, template_name: &str) -> Result<String, minijinja::Error> {
+    fn render_page(&self, env: &Environment, template_name: &str, request_info: &HttpRequestInfo, context: Value) -> Result<String, minijinja::Error> {
         let tmpl = env.get_template(template_name)?;
-        let start_time = std::time::Instant::now();
-        let mut result = tmpl.render(minijinja::context! {})?;
-        let duration_ms = start_time.elapsed().as_secs_f64() * 1000.0;
-        self.health_actor.do_send(ReportTemplateLatency(duration_ms));
+        let mut result = match &request_info.fragment {
+            // Only the `{% fragment %}` that was asked for; it's going into a page that already has the scripts.
+            Some(fragment) => {
+                let mut state = tmpl.eval_to_state(context)?;
+                state.render_block(&fragments::block_name(fragment)).map_err(|e| match e.kind() {
+                    minijinja::ErrorKind::UnknownBlock => minijinja::Error::new(
+                        minijinja::ErrorKind::UnknownBlock,
+                        format!("this page has no {{% fragment \"{}\" %}}", fragment),
+                    ),
+                    _ => e,
+                })?
+            }
+            None => tmpl.render(context)?,
+        };
 
-        if config::CONFIG.disable_script_injection.unwrap_or(false) {
+        if request_info.fragment.is_some() || config::CONFIG.disable_script_injection.unwrap_or(false) {
             return Ok(result);
         }
 
         if let Some(head_end_pos) = result.rfind("</head>") {
-            let mut scripts = static_assets::get_script_tags();
+            let nonce = request_info.csp_nonce.as_deref();
+            let mut scripts = static_assets::get_script_tags(nonce);
             if self.dev_mode {
-                scripts.push_str(&format!("<script>{}</script>\n", include_str!("../scripts/devws.js")));
+                scripts.push_str(&format!(
+                    "<script{}>{}</script>\n",
+                    static_assets::nonce_attribute(nonce),
+                    include_str!("../scripts/devws.js")
+                ));
             }
             result.insert_str(head_end_pos, &scripts);
         }
@@ -458,13 +762,31 @@ impl Actor for TemplateRendererActor {
     }
 }
 
-// Message for rendering a template
-#[derive(Message)]
-#[rtype(result = "Result<RenderOutput, DetailedError>")]
+// What a page is rendered for; see `render`.
+#[derive(Clone)]
 pub struct RenderTemplate {
     pub template_name: String,
     pub request_info: Arc<HttpRequestInfo>,
     pub session_manager: Addr<SessionManagerActor>,
+    // The span the render is traced under.
+    pub trace: Option<TraceContext>,
+}
+
+#[derive(Message)]
+#[rtype(result = "Result<RenderPlan, DetailedError>")]
+struct PlanRender(RenderTemplate);
+
+#[derive(Message)]
+#[rtype(result = "Result<Pass, DetailedError>")]
+struct RenderPass(RenderJob);
+
+// How a pass went, for `render`.
+enum Pass {
+    // What to load before the next one.
+    Pending(Vec<PendingCall>),
+    // A component answered with a redirect; its flash is still to be kept.
+    Redirect(redirects::Redirect),
+    Done(RenderOutput),
 }
 
 #[derive(Message, Clone)]
@@ -476,224 +798,534 @@ pub struct UpdateComponents(pub Vec<Component>);
 pub struct RescanComponents;
 
 
-impl Handler<RenderTemplate> for TemplateRendererActor {
-    type Result = Result<RenderOutput, DetailedError>;
+impl Handler<PlanRender> for TemplateRendererActor {
+    type Result = Result<RenderPlan, DetailedError>;
 
-    fn handle(&mut self, msg: RenderTemplate, _ctx: &mut Self::Context) -> Self::Result {
-        if msg.request_info.method == "POST" {
-            return self.handle_post_request(msg);
+    fn handle(&mut self, PlanRender(msg): PlanRender, _ctx: &mut Self::Context) -> Self::Result {
+        self.refresh_if_stale();
+        self.plan(&msg)
+    }
+}
+
+impl Handler<RenderPass> for TemplateRendererActor {
+    type Result = Result<Pass, DetailedError>;
+
+    fn handle(&mut self, RenderPass(job): RenderPass, _ctx: &mut Self::Context) -> Self::Result {
+        self.refresh_if_stale();
+        self.render_pass(&job)
+    }
+}
+
+impl Handler<UpdateComponents> for TemplateRendererActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: UpdateComponents, _ctx: &mut Self::Context) -> Self::Result {
+        let mut components = self.components.write().unwrap();
+        *components = msg.0;
+    }
+}
+
+impl Handler<RescanComponents> for TemplateRendererActor {
+    type Result = ();
+
+    fn handle(&mut self, _msg: RescanComponents, _ctx: &mut Self::Context) -> Self::Result {
+        self.scan_and_cache_components();
+    }
+}
+
+// A page's render, from the request to what goes back. A template renders on one thread from start to
+// end, and minijinja can't pause it where it reaches a `component()` whose `load_template_context`
+// hasn't run. Blocking the thread there until the interpreters answer would tie up a renderer per
+// component and, with more components waiting than `python_threads`, deadlock under load.
+//
+// So everything that waits on Python happens here, on the page renderer's event loop, and the renderer
+// threads only ever render what's already loaded:
+//
+// 1. `PlanRender` works out from the request what's called first: a POST's action, the page's
+//    `_page.py`, and the components the page always shows (`prefetch_calls`).
+// 2. Those are awaited, then the context processors.
+// 3. `RenderPass` renders with what's loaded. A component or template helper that isn't loaded yet
+//    renders as nothing and is written down; when the pass is over, all of them go to the interpreters
+//    at once (`load`) and the next pass renders them. Each pass reaches one level of nesting further.
+// 4. The pass that finds everything loaded is the page. Passes have no effects of their own, so only
+//    that one gives forms their tokens, records the failures behind `on_error` fallbacks and reports
+//    how long the template took.
+#[allow(clippy::result_large_err)]
+pub async fn render(
+    renderer: &Addr<TemplateRendererActor>,
+    interpreter: &Addr<PythonInterpreterActor>,
+    health_actor: &Addr<HealthActor>,
+    mut msg: RenderTemplate,
+) -> Result<RenderOutput, DetailedError> {
+    // Everything the render does is traced under this, until it returns.
+    let span = telemetry::child(msg.trace, format!("template {}", msg.template_name));
+    msg.trace = span.context();
+    let plan = renderer.send(PlanRender(msg.clone())).await.unwrap_or_else(|e| Err(renderer_mailbox_error(e)))?;
+
+    let mut posted = plan.posted;
+    let mut events = Vec::new();
+    if let Some(action) = plan.action {
+        match interpreter_queue::send(interpreter, action.call).await {
+            Ok(Ok(result)) => {
+                if let Some(redirect) = redirects::from_context(&result.context) {
+                    redirect.keep_flash(&msg.session_manager).await;
+                    return Ok(RenderOutput::Redirect(redirect.status, redirect.url));
+                }
+                if let Some(iterator) = result.stream {
+                    return Ok(RenderOutput::Stream(PythonStream::new(iterator, &result.context)));
+                }
+                if let Some(posted) = &mut posted {
+                    posted.context = Some(result.context);
+                }
+                events = result.events;
+            }
+            Ok(Err(py_err)) => {
+                if let Some(token) = &action.form_token {
+                    form_tokens::release(token);
+                }
+                return Err(DetailedError {
+                    component: Some(ComponentInfo {
+                        name: action.component,
+                    }),
+                    error_source: Some(ErrorSource::Python(py_err.clone())),
+                    message: py_err.message.clone(),
+                    file_path: py_err.filename.clone().unwrap_or_default(),
+                    line: py_err.line_number.unwrap_or(0) as u32,
+                    column: py_err.column_number.unwrap_or(0) as u32,
+                    end_line: py_err.end_line_number.map(|l| l as u32),
+                    end_column: py_err.end_column_number.map(|c| c as u32),
+                    ..Default::default()
+                });
+            }
+            Err(e) => {
+                log::error!("A mailbox error occurred: {}. This might indicate a problem with the server's internal communication.", e);
+                if let Some(token) = &action.form_token {
+                    form_tokens::release(token);
+                }
+                return Err(DetailedError {
+                    error_source: Some(ErrorSource::Python(
+                        crate::actors::interpreter::PythonError {
+                            message: e.to_string(),
+                            traceback: format!("{:?}", e),
+                            line_number: None,
+                            column_number: None,
+                            end_line_number: None,
+                            end_column_number: None,
+                            filename: None,
+                            source_code: None,
+                        },
+                    )),
+                    ..Default::default()
+                });
+            }
         }
+    }
 
-        let mut env = if self.dev_mode {
-            let mut new_env = Environment::new();
-            minijinja_contrib::add_to_environment(&mut new_env);
-            new_env.set_loader(minijinja::path_loader("."));
-            new_env
-        } else {
-            (*self.env).clone()
-        };
+    // The page's data is loaded after the action ran.
+    let page_data = match plan.page_data {
+        Some((call, logic_path)) => {
+            let loaded = load_page_data(interpreter, health_actor, &msg.template_name, call, logic_path).await?;
+            if let Some(answer) = answered_with(&loaded, &msg.session_manager).await {
+                return Ok(answer);
+            }
+            loaded.context
+        }
+        None => Value::from_serialize(serde_json::json!({})),
+    };
+    let processed = context_processors::load(interpreter, &msg.request_info, &msg.session_manager).await?;
+    let helpers = template_helpers::helpers(interpreter).await;
+
+    // Components elsewhere on the page may be subscribed to what the action emitted, so the whole page goes back.
+    let partial = plan.partial.filter(|_| events.is_empty());
+    let mut first_instances = HashMap::new();
+    // Rendered alone, the instance keeps the number it had on the page, and it's the only one there is to load.
+    if let Some((call, instance)) = &partial
+        && let Some(position) = instance.rsplit('-').next().and_then(|position| position.parse().ok())
+    {
+        first_instances.insert(format!("{}:{}", call.name, kwargs_hash(&call.kwargs)), position);
+    }
+    let job = RenderJob {
+        msg,
+        page_data,
+        processed: Arc::new(processed),
+        helpers,
+        events: Arc::new(events),
+        posted: Arc::new(posted),
+        partial: partial.map(|(call, _)| call),
+        first_instances,
+        loaded: Arc::default(),
+    };
+    if job.partial.is_none() {
+        load(interpreter, health_actor, &job, plan.prefetch).await;
+    }
 
-        let interpreter_clone = self.interpreter.clone();
-        let health_actor_clone = self.health_actor.clone();
-        let request_info_clone = msg.request_info.clone();
-        let session_manager_clone = msg.session_manager.clone();
-        let components_clone = Arc::clone(&self.components);
-
-        env.add_function(
-            "component",
-            move |state: &State, name: String, kwargs: Kwargs| -> Result<Value, minijinja::Error> {
-                let name = name.replace(".", "/");
-                let kwargs_map: HashMap<String, Value> = kwargs
-                    .args()
-                    .filter_map(|k| kwargs.get::<Value>(k).ok().map(|v| (k.to_string(), v)))
-                    .collect();
-
-                let components = components_clone.read().unwrap();
-                let component = components.iter().find(|c| c.id == name).ok_or_else(|| {
-                    minijinja::Error::new(minijinja::ErrorKind::TemplateNotFound, "Component not found")
-                })?;
-                if let Some(logic_path) = &component.logic_path {
-                    let module_path = path_to_module(logic_path).unwrap();
-                    let execute_fn_msg = ExecuteFunction {
-                        module_path,
-                        function_name: "load_template_context".to_string(),
-                        request: request_info_clone.clone(),
-                        args: Some(kwargs_map),
-                        session_manager: session_manager_clone.clone(),
-                    };
+    for _ in 0..MAX_RENDER_PASSES {
+        match renderer.send(RenderPass(job.clone())).await.unwrap_or_else(|e| Err(renderer_mailbox_error(e)))? {
+            Pass::Done(output) => return Ok(output),
+            Pass::Redirect(redirect) => {
+                redirect.keep_flash(&job.msg.session_manager).await;
+                return Ok(RenderOutput::Redirect(redirect.status, redirect.url));
+            }
+            Pass::Pending(calls) => load(interpreter, health_actor, &job, calls).await,
+        }
+    }
+    let message = format!("components were still waiting for their data after {} passes. Are they nested that deep?", MAX_RENDER_PASSES);
+    Err(DetailedError {
+        page: Some(crate::errors::TemplateInfo { name: job.msg.template_name.clone(), ..Default::default() }),
+        message,
+        file_path: job.msg.template_name.clone(),
+        ..Default::default()
+    })
+}
 
-                    let python_start_time = std::time::Instant::now();
-                    let future = interpreter_clone.send(execute_fn_msg);
-                    let result = futures::executor::block_on(future);
-                    let python_duration_ms = python_start_time.elapsed().as_secs_f64() * 1000.0;
-                    health_actor_clone.do_send(ReportPythonLatency(python_duration_ms));
-
-                    match result {
-                        Ok(Ok(result)) => {
-                            if let Ok(redirect_url) = result.context.get_attr("_redirect") {
-                                if !redirect_url.is_undefined() && !redirect_url.is_none() {
-                                    if let Some(url_str) = redirect_url.as_str() {
-                                        let redirect_marker = format!("<!-- REDIRECT:{} -->", url_str);
-                                        return Ok(Value::from_safe_string(redirect_marker));
-                                    }
-                                }
-                            }
-                            let components = components_clone.read().unwrap();
-                            let component =
-                                components.iter().find(|c| c.id == name).ok_or_else(|| {
-                                    minijinja::Error::new(
-                                        minijinja::ErrorKind::TemplateNotFound,
-                                        "Component not found",
-                                    )
-                                })?;
-                            let mut template_path = component.template_path.clone();
-                            if template_path.starts_with("./") {
-                                template_path = template_path[2..].to_string();
-                            }
-                            let tmpl = state.env().get_template(&template_path)?;
-                            let mut rendered_component = tmpl.render(result.context)?;
-
-                            let replacement = format!(
-                                r#"$1<input type="hidden" name="component_id" value="{}">"#,
-                                name
-                            );
-                            rendered_component = FORM_REGEX
-                                .replace_all(&rendered_component, replacement)
-                                .to_string();
-
-                            Ok(Value::from_safe_string(rendered_component))
-                        }
-                        Ok(Err(py_err)) => {
-                            let detailed_error = DetailedError {
-                                component: Some(ComponentInfo { name: name.clone() }),
-                                error_source: Some(ErrorSource::Python(py_err.clone())),
-                                message: py_err.message.clone(),
-                                file_path: py_err.filename.clone().unwrap_or_default(),
-                                line: py_err.line_number.unwrap_or(0) as u32,
-                                column: py_err.column_number.unwrap_or(0) as u32,
-                                end_line: py_err.end_line_number.map(|l| l as u32),
-                                end_column: py_err.end_column_number.map(|c| c as u32),
-                                ..Default::default()
-                            };
-                            let err = minijinja::Error::new(
-                                minijinja::ErrorKind::InvalidOperation,
-                                "Python function crashed",
-                            );
-                            Err(err.with_source(detailed_error))
-                        }
-                        Err(e) => {
-                            log::error!("A mailbox error occurred: {}. This might indicate a problem with the server's internal communication.", e);
-                            Err(minijinja::Error::new(
-                                minijinja::ErrorKind::InvalidOperation,
-                                "Mailbox error",
-                            )
-                            .with_source(e))
-                        }
-                    }
+fn renderer_mailbox_error(e: MailboxError) -> DetailedError {
+    log::error!("Template renderer mailbox error: {}", e);
+    DetailedError { message: e.to_string(), ..Default::default() }
+}
+
+// Sends every call to the interpreters at once and waits for them together. With several
+// `python_threads` they run side by side, as far as Python lets them: the GIL is let go while they
+// wait on the database or the network.
+async fn load(interpreter: &Addr<PythonInterpreterActor>, health_actor: &Addr<HealthActor>, job: &RenderJob, calls: Vec<PendingCall>) {
+    if calls.is_empty() {
+        return;
+    }
+    let count = calls.len();
+    let python_start_time = std::time::Instant::now();
+    futures::future::join_all(calls.into_iter().map(|call| load_call(interpreter, job, call))).await;
+    let python_duration_ms = python_start_time.elapsed().as_secs_f64() * 1000.0;
+    health_actor.do_send(ReportPythonLatency(python_duration_ms));
+    log::debug!("Loaded {} calls for {} at once in {:.1}ms", count, job.msg.request_info.path, python_duration_ms);
+}
+
+async fn load_call(interpreter: &Addr<PythonInterpreterActor>, job: &RenderJob, call: PendingCall) {
+    match call {
+        PendingCall::Component { slot, instance, module_path, args } => {
+            let result = interpreter_queue::send(interpreter, ExecuteFunction {
+                module_path,
+                function_name: "load_template_context".to_string(),
+                request: job.msg.request_info.clone(),
+                args: Some(args),
+                session_manager: job.msg.session_manager.clone(),
+                state: Some(instance.clone()),
+                events: job.events.to_vec(),
+                trace: job.msg.trace,
+            })
+            .await;
+            if let Err(e) = &result {
+                log::error!("A mailbox error occurred while loading {}: {}. This might indicate a problem with the server's internal communication.", instance, e);
+            }
+            job.loaded.lock().unwrap().insert(slot, instance, LoadedCall::Context(result));
+        }
+        PendingCall::Helper { slot, call } => {
+            let key = call.key.clone();
+            let outcome = template_helpers::run(interpreter, call).await.map_err(Box::new);
+            job.loaded.lock().unwrap().insert(Some(slot), key, LoadedCall::Helper(outcome));
+        }
+        PendingCall::Flashes => {
+            let flashes = redirects::take_flashes(&job.msg.session_manager).await;
+            job.loaded.lock().unwrap().flashes = Some(flashes);
+        }
+    }
+}
+
+// What the page's `_page.py` returned.
+#[allow(clippy::result_large_err)]
+async fn load_page_data(
+    interpreter: &Addr<PythonInterpreterActor>,
+    health_actor: &Addr<HealthActor>,
+    template_name: &str,
+    call: ExecuteFunction,
+    logic_path: String,
+) -> Result<PythonFunctionResult, DetailedError> {
+    let python_start_time = std::time::Instant::now();
+    let result = interpreter_queue::send(interpreter, call).await;
+    health_actor.do_send(ReportPythonLatency(python_start_time.elapsed().as_secs_f64() * 1000.0));
+
+    match result {
+        Ok(Ok(result)) => Ok(result),
+        Ok(Err(py_err)) => Err(DetailedError {
+            page: Some(crate::errors::TemplateInfo {
+                name: template_name.to_string(),
+                ..Default::default()
+            }),
+            error_source: Some(ErrorSource::Python(py_err.clone())),
+            message: py_err.message.clone(),
+            file_path: py_err.filename.clone().unwrap_or(logic_path),
+            line: py_err.line_number.unwrap_or(0) as u32,
+            column: py_err.column_number.unwrap_or(0) as u32,
+            end_line: py_err.end_line_number.map(|l| l as u32),
+            end_column: py_err.end_column_number.map(|c| c as u32),
+            ..Default::default()
+        }),
+        Err(e) => {
+            log::error!("A mailbox error occurred: {}. This might indicate a problem with the server's internal communication.", e);
+            Err(DetailedError {
+                message: e.to_string(),
+                file_path: logic_path,
+                ..Default::default()
+            })
+        }
+    }
+}
+
+// A template error as the error page shows it.
+fn template_error(template_name: &str, e: minijinja::Error) -> DetailedError {
+    if let Some(detailed_error) = e.source().and_then(|s| s.downcast_ref::<DetailedError>()) {
+        return detailed_error.clone();
+    }
+    let template_info = crate::errors::TemplateInfo {
+        name: e.name().unwrap_or(template_name).to_string(),
+        line: e.line().unwrap_or(0),
+        source: None,
+        source_code: {
+            let filename = e.name().unwrap_or(template_name);
+            if let Ok(contents) = std::fs::read_to_string(filename) {
+                if let Some(ln) = e.line() {
+                    let start = (ln as isize - 7).max(0) as usize;
+                    let end = (ln + 6).min(contents.lines().count());
+                    Some(contents.lines().skip(start).take(end - start).collect::<Vec<_>>().join("\n"))
                 } else {
-                    // If there's no logic_path, just render the template without context.
-                    let components = components_clone.read().unwrap();
-                    let component =
-                        components.iter().find(|c| c.id == name).ok_or_else(|| {
-                            minijinja::Error::new(
-                                minijinja::ErrorKind::TemplateNotFound,
-                                "Component not found",
-                            )
-                        })?;
-                    let mut template_path = component.template_path.clone();
-                    if template_path.starts_with("./") {
-                        template_path = template_path[2..].to_string();
-                    }
-                    let tmpl = state.env().get_template(&template_path)?;
-                    let mut rendered_component =
-                        tmpl.render(Value::from_serialize(serde_json::json!({})))?;
-
-                    let replacement = format!(
-                        r#"$1<input type="hidden" name="component_id" value="{}">"#,
-                        name
-                    );
-                    rendered_component = FORM_REGEX
-                        .replace_all(&rendered_component, replacement)
-                        .to_string();
-
-                    Ok(Value::from_safe_string(rendered_component))
+                    None
                 }
-            },
-        );
+            } else {
+                None
+            }
+        },
+        detail: e.detail().unwrap_or("").to_string(),
+        traceback: Some(format!("{:?}", e)),
+    };
+    DetailedError {
+        page: Some(template_info.clone()),
+        error_source: Some(ErrorSource::Template(template_info.clone())),
+        file_path: e.name().unwrap_or(template_name).to_string(),
+        line: template_info.line as u32,
+        ..Default::default()
+    }
+}
+
+
+// Fills in the defaults from the component's `component.yaml` and checks the arguments against it.
+// In dev a mismatch fails the render at the `component()` call; otherwise it's only logged.
+fn check_props(components: &RwLock<Vec<Component>>, name: &str, kwargs: &mut HashMap<String, Value>, dev_mode: bool) -> Result<(), minijinja::Error> {
+    let components = components.read().unwrap();
+    let Some(component_props) = components.iter().find(|c| c.id == name).and_then(|c| c.props.as_ref()) else {
+        return Ok(());
+    };
+    let problems = props::check(component_props, kwargs);
+    if problems.is_empty() {
+        return Ok(());
+    }
+    let message = format!("component('{}') doesn't match its {}: {}", name, props::FILE, problems.join("; "));
+    if dev_mode {
+        return Err(minijinja::Error::new(minijinja::ErrorKind::InvalidOperation, message));
+    }
+    log::warn!("Heads up! {}", message);
+    Ok(())
+}
 
-        let rendered_page = self.render_page(&env, &msg.template_name).map_err(|e| {
-            if let Some(detailed_error) = e.source().and_then(|s| s.downcast_ref::<DetailedError>()) {
-                return detailed_error.clone();
+// `component("cart", on_error="cart_error.html")`: outside dev mode, a component that fails is
+// replaced by its fallback, looked up next to its template first, and the rest of the page renders.
+// The failure is kept in the scope, to be recorded if the page is finished with it.
+fn error_boundary(state: &State, scope: &ComponentScope, name: &str, on_error: Option<&str>, error: minijinja::Error) -> Result<Value, minijinja::Error> {
+    // A redirect isn't a failure to stand in for.
+    let Some(fallback) = on_error.filter(|_| redirects::from_error(&error).is_none()) else {
+        return Err(error);
+    };
+    if scope.dev_mode {
+        return Err(error);
+    }
+    let mut detailed_error = error.source().and_then(|s| s.downcast_ref::<DetailedError>()).cloned().unwrap_or_else(|| DetailedError {
+        message: error.to_string(),
+        component: Some(ComponentInfo { name: name.to_string() }),
+        ..Default::default()
+    });
+    detailed_error.route = Some(scope.request_info.path.clone());
+    scope.failures.lock().unwrap().push(Failure { component: name.to_string(), fallback: fallback.to_string(), error: detailed_error });
+
+    let next_to_component = scope
+        .components
+        .read()
+        .unwrap()
+        .iter()
+        .find(|c| c.id == name)
+        .and_then(|c| std::path::Path::new(c.template_path.trim_start_matches("./")).parent().map(|dir| paths::to_slash(&dir.join(fallback))));
+    let tmpl = match next_to_component.and_then(|path| state.env().get_template(&path).ok()) {
+        Some(tmpl) => tmpl,
+        None => state.env().get_template(fallback)?,
+    };
+    Ok(Value::from_safe_string(tmpl.render(minijinja::context! { component => name })?))
+}
+
+// `{{ component("card", id=1) }}`. A component whose `load_template_context` isn't loaded yet renders
+// as nothing on this pass and is written down for the next one; see `render`.
+fn add_component_function(env: &mut Environment<'static>, scope: ComponentScope) {
+    env.add_function("component", move |state: &State, name: String, kwargs: Kwargs| -> Result<Value, minijinja::Error> {
+        let name = name.replace('.', "/");
+        let on_error: Option<String> = kwargs.get(ON_ERROR)?;
+        let mut kwargs_map: HashMap<String, Value> = kwargs
+            .args()
+            .filter(|k| *k != ON_ERROR)
+            .filter_map(|k| kwargs.get::<Value>(k).ok().map(|v| (k.to_string(), v)))
+            .collect();
+        let slot = scope.next_slot(&name);
+        let instance = next_instance(&scope.instances, &name, &kwargs_map);
+        check_props(&scope.components, &name, &mut kwargs_map, scope.dev_mode)?;
+        let key = format!("{}:{}", name, instance);
+
+        let files = scope
+            .components
+            .read()
+            .unwrap()
+            .iter()
+            .find(|c| c.id == name)
+            .map(|c| (c.logic_path.clone(), c.template_path.trim_start_matches("./").to_string()));
+        let loaded = match &files {
+            Some((Some(logic_path), _)) => {
+                let loaded = scope.loaded.lock().unwrap().get(&slot, &key);
+                let Some(LoadedCall::Context(loaded)) = loaded else {
+                    let module_path = path_to_module(logic_path).unwrap();
+                    scope.pending.lock().unwrap().push(PendingCall::Component { slot: Some(slot), instance: key, module_path, args: kwargs_map });
+                    return Ok(Value::from(""));
+                };
+                Some(loaded)
             }
-            let template_info = crate::errors::TemplateInfo {
-                name: e.name().unwrap_or(&msg.template_name).to_string(),
-                line: e.line().unwrap_or(0),
-                source: None,
-                source_code: {
-                    let filename = e.name().unwrap_or(&msg.template_name);
-                    if let Ok(contents) = std::fs::read_to_string(filename) {
-                        if let Some(ln) = e.line() {
-                            let start = (ln as isize - 7).max(0) as usize;
-                            let end = (ln + 6).min(contents.lines().count());
-                            Some(contents.lines().skip(start).take(end - start).collect::<Vec<_>>().join("\n"))
-                        } else {
-                            None
-                        }
-                    } else {
-                        None
-                    }
-                },
-                detail: e.detail().unwrap_or("").to_string(),
-                traceback: Some(format!("{:?}", e)),
+            _ => None,
+        };
+
+        let mut span = telemetry::child(scope.trace, format!("component {}", name));
+        span.set_attribute("noventa.instance", &instance);
+        let rendered = (|| -> Result<Value, minijinja::Error> {
+            let (_, template_path) = files.ok_or_else(|| {
+                minijinja::Error::new(minijinja::ErrorKind::TemplateNotFound, "Component not found")
+            })?;
+            let mut context = match loaded {
+                Some(loaded) => component_context(&name, loaded)?,
+                // If there's no logic_path, there's no context to load.
+                None => Value::from_serialize(serde_json::json!({})),
             };
-            DetailedError {
-                page: Some(template_info.clone()),
-                error_source: Some(ErrorSource::Template(template_info.clone())),
-                file_path: e.name().unwrap_or(&msg.template_name).to_string(),
-                line: template_info.line as u32,
-                ..Default::default()
+            // `render` keeps its flash once the pass is over.
+            if let Some(redirect) = redirects::from_context(&context) {
+                return Err(redirect.into_error());
             }
-        })?;
-
-        if rendered_page.contains("<!-- REDIRECT:") {
-            if let Some(caps) = Regex::new(r"<!-- REDIRECT:(.*?) -->").unwrap().captures(&rendered_page) {
-                if let Some(url) = caps.get(1) {
-                    return Ok(RenderOutput::Redirect(url.as_str().to_string()));
-                }
+            // If this is the component that handled the POST request, merge the action context.
+            if let Some(posted) = &*scope.posted
+                && posted.component == name
+                && posted.instance.as_ref().is_none_or(|posted_instance| *posted_instance == instance)
+                && let Some(action_context) = &posted.context
+            {
+                context = merge_contexts(&context, action_context)?;
+            }
+            if let Some(status) = requested_status(&context) {
+                scope.status.lock().unwrap().get_or_insert(status);
             }
+            let html = scope.inside(&slot, || state.env().get_template(&template_path)?.render(context))?;
+            Ok(Value::from_safe_string(mark_component(&html, &name, &instance)))
+        })();
+        // A redirect goes out as an error, but isn't one.
+        if let Err(error) = &rendered
+            && redirects::from_error(error).is_none()
+        {
+            span.set_error(error);
         }
+        rendered.or_else(|error| {
+            error_boundary(state, &scope, &name, on_error.as_deref(), error)
+        })
+    });
+}
 
-        Ok(RenderOutput::Html(rendered_page))
+// What a component's `load_template_context` returned, or the error its render fails with.
+fn component_context(name: &str, loaded: Result<Result<PythonFunctionResult, PythonError>, MailboxError>) -> Result<Value, minijinja::Error> {
+    match loaded {
+        Ok(Ok(result)) => Ok(result.context),
+        Ok(Err(py_err)) => {
+            let detailed_error = DetailedError {
+                component: Some(ComponentInfo { name: name.to_string() }),
+                error_source: Some(ErrorSource::Python(py_err.clone())),
+                message: py_err.message.clone(),
+                file_path: py_err.filename.clone().unwrap_or_default(),
+                line: py_err.line_number.unwrap_or(0) as u32,
+                column: py_err.column_number.unwrap_or(0) as u32,
+                end_line: py_err.end_line_number.map(|l| l as u32),
+                end_column: py_err.end_column_number.map(|c| c as u32),
+                ..Default::default()
+            };
+            Err(minijinja::Error::new(minijinja::ErrorKind::InvalidOperation, "Python function crashed").with_source(detailed_error))
+        }
+        Err(e) => Err(minijinja::Error::new(minijinja::ErrorKind::InvalidOperation, "Mailbox error").with_source(e)),
     }
 }
 
-impl Handler<UpdateComponents> for TemplateRendererActor {
-    type Result = ();
-
-    fn handle(&mut self, msg: UpdateComponents, _ctx: &mut Self::Context) -> Self::Result {
-        let mut components = self.components.write().unwrap();
-        *components = msg.0;
+// The action's result on top of what the component's `load_template_context` returned.
+fn merge_contexts(context: &Value, action_context: &Value) -> Result<Value, minijinja::Error> {
+    let mut merged = serde_json::to_value(context)
+        .map_err(|e| minijinja::Error::new(minijinja::ErrorKind::InvalidOperation, "Failed to serialize context").with_source(e))?;
+    let action = serde_json::to_value(action_context)
+        .map_err(|e| minijinja::Error::new(minijinja::ErrorKind::InvalidOperation, "Failed to serialize action context").with_source(e))?;
+    if let (Some(merged), Some(action)) = (merged.as_object_mut(), action.as_object()) {
+        for (k, v) in action {
+            merged.insert(k.clone(), v.clone());
+        }
     }
+    Ok(Value::from_serialize(merged))
 }
 
-impl Handler<RescanComponents> for TemplateRendererActor {
-    type Result = ();
+// A render that still finds calls waiting for data after this many passes is given up on.
+const MAX_RENDER_PASSES: usize = 10;
 
-    fn handle(&mut self, _msg: RescanComponents, _ctx: &mut Self::Context) -> Self::Result {
-        self.scan_and_cache_components();
+// How one pass went.
+enum Passed<T> {
+    Pending(Vec<PendingCall>),
+    Redirect(redirects::Redirect),
+    Rendered(T),
+}
+
+// Runs `render` as one pass with `scope`, and tells what's left to load, if anything.
+fn run_pass<T>(scope: &ComponentScope, render: impl FnOnce() -> Result<T, minijinja::Error>) -> Result<Passed<T>, minijinja::Error> {
+    scope.start_pass();
+    let rendered = render();
+    let pending = std::mem::take(&mut *scope.pending.lock().unwrap());
+    // A redirect comes from something loaded, so the next pass would stop there too.
+    if let Err(e) = &rendered
+        && let Some(redirect) = redirects::from_error(e)
+    {
+        return Ok(Passed::Redirect(redirect.clone()));
+    }
+    // Anything else that went wrong may be down to what isn't loaded yet; the next pass tells.
+    if !pending.is_empty() {
+        return Ok(Passed::Pending(pending));
     }
+    let rendered = rendered?;
+    scope.record_failures();
+    Ok(Passed::Rendered(rendered))
 }
 
+// Builds a template environment with the contrib filters, Noventa's own filters and functions and
+// every extension registered through `template_extensions`. `component()`, `status()` and the helpers from template_helpers.py are added per render, after these.
+fn build_environment(loader_root: &std::path::Path) -> Environment<'static> {
+    let mut env = Environment::new();
+    minijinja_contrib::add_to_environment(&mut env);
+    env.add_filter("format", format_filter);
+    template_filters::add_to_environment(&mut env);
+    env.add_function("srcset", images::srcset);
+    env.add_function("url_for", crate::reverse_routes::url_for_function);
+    env.add_function("url_for_signed", crate::signed_urls::url_for_signed_function);
+    template_extensions::apply(&mut env);
+    env.add_template(FORM_MACROS, include_str!("../templates/form_macros.html")).expect("the form macros template is valid");
+    let load = minijinja::path_loader(loader_root);
+    env.set_loader(move |name| Ok(load(name)?.map(|source| fragments::translate(&page_meta::template_source(&source)))));
+    env
+}
 
 fn path_to_module(path_str: &str) -> Result<String, std::io::Error> {
-    let path = std::path::Path::new(path_str);
+    // Windows separators become slashes first, so both kinds of paths give the same module
+    let module_str = paths::to_slash(std::path::Path::new(path_str));
 
     // Clean the path to remove "./"
-    let cleaned_path = path.strip_prefix("./").unwrap_or(path);
+    let cleaned_path = module_str.strip_prefix("./").unwrap_or(&module_str);
 
-    // Convert to string and remove the .py extension
-    let module_str = cleaned_path.to_str().ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidData, "Path contains invalid UTF-8"))?;
-    let module_str_no_ext = module_str.strip_suffix(".py").unwrap_or(module_str);
+    // Remove the .py extension
+    let module_str_no_ext = cleaned_path.strip_suffix(".py").unwrap_or(cleaned_path);
 
     // Replace slashes with dots for Python import syntax
     let module_path = module_str_no_ext.replace("/", ".");
@@ -701,6 +1333,52 @@ fn path_to_module(path_str: &str) -> Result<String, std::io::Error> {
     Ok(module_path)
 }
 
+// `pages/blog/_page.py` (or `_logic.py`), next to the page, if the page has one.
+pub fn page_logic_path(base: &std::path::Path, template_name: &str) -> Option<String> {
+    PAGE_LOGIC_FILES
+        .iter()
+        .map(|file| std::path::Path::new(template_name).with_file_name(file))
+        .find(|logic_path| base.join(logic_path).is_file())
+        .map(|logic_path| paths::to_slash(&logic_path))
+}
+
+// `{{ status(410) }}` sends the page with a 410 instead of a 200, and `{{ status(410, "errors/gone.html") }}`
+// sends that template in its place. `"_status": 410` and `"_status_template"` from a `_page.py` or a
+// component's `load_template_context` do the same. The first one the render reaches wins.
+fn add_status_function(env: &mut Environment<'static>, requested: &RequestedStatus) {
+    let requested = Arc::clone(requested);
+    env.add_function("status", move |code: i64, template: Option<String>| -> Result<Value, minijinja::Error> {
+        let code = u16::try_from(code).ok().filter(|code| (100..=599).contains(code)).ok_or_else(|| {
+            minijinja::Error::new(minijinja::ErrorKind::InvalidOperation, format!("{} isn't an HTTP status code", code))
+        })?;
+        requested.lock().unwrap().get_or_insert((code, template));
+        Ok(Value::from(""))
+    });
+}
+
+// The `_status` a Python function returned, with the template to send, if any.
+fn requested_status(context: &Value) -> Option<(u16, Option<String>)> {
+    let code = context.get_attr("_status").ok().and_then(|code| u16::try_from(code).ok()).filter(|code| (100..=599).contains(code))?;
+    let template = context.get_attr("_status_template").ok().and_then(|template| template.as_str().map(str::to_string));
+    Some((code, template))
+}
+
+fn with_status(status: Option<u16>, html: String) -> RenderOutput {
+    match status {
+        Some(code) => RenderOutput::Status(code, html),
+        None => RenderOutput::Html(html),
+    }
+}
+
+// The redirect or the `_stream` a Python function answered with instead of data for the page.
+async fn answered_with(result: &PythonFunctionResult, session_manager: &Addr<SessionManagerActor>) -> Option<RenderOutput> {
+    if let Some(redirect) = redirects::from_context(&result.context) {
+        redirect.keep_flash(session_manager).await;
+        return Some(RenderOutput::Redirect(redirect.status, redirect.url));
+    }
+    result.stream.clone().map(|iterator| RenderOutput::Stream(PythonStream::new(iterator, &result.context)))
+}
+
 fn format_filter(format_string: String, args: minijinja::value::Rest<Value>) -> Result<String, minijinja::Error> {
     let mut arg_iter = args.iter();
     let mut result = String::new();
@@ -768,10 +1446,178 @@ fn format_filter(format_string: String, args: minijinja::value::Rest<Value>) ->
 }
 
 
+// What `recursive_scan` does, with the templates coming from `env`.
+fn scan_component_calls(
+    env: &Environment<'static>,
+    components: &[Component],
+    template_name: &str,
+    template_content: &str,
+    placement: Placement,
+    calls: &mut Vec<ComponentCall>,
+    scanned: &mut HashSet<String>,
+) -> Result<(), minijinja::Error> {
+    if !scanned.insert(template_name.to_string()) {
+        return Ok(());
+    }
+    log::debug!("Scanning template: {}", template_name);
+    let refs = template_ast::scan(template_name, &fragments::translate(template_content))?;
+    // Whether what's at a spot in this template is rendered every time: not under a condition, not
+    // in a block that may be replaced and, in a template extending another, in one of its blocks.
+    let renders = |in_block: bool, conditional: bool| {
+        placement.rendered && !conditional && !(placement.layout && in_block) && (refs.extends.is_none() || in_block)
+    };
+
+    // Layouts, includes and imports first: their components are on the page too.
+    for name in &refs.templates {
+        let layout = refs.extends.as_ref() == Some(name);
+        let placement = Placement {
+            rendered: if layout { placement.rendered } else { renders(refs.block_templates.contains(name), refs.conditional_templates.contains(name)) },
+            layout,
+        };
+        match env.get_template(name) {
+            Ok(template) => scan_component_calls(env, components, name, template.source(), placement, calls, scanned)?,
+            Err(e) => log::debug!("Skipping {} while scanning {}: {}", name, template_name, e),
+        }
+    }
+
+    for call in refs.components {
+        let name = call.name.replace('.', "/");
+        let component = components.iter().find(|c| c.id == name).ok_or_else(|| {
+            minijinja::Error::new(minijinja::ErrorKind::TemplateNotFound, format!("Component '{}' not found", call.name))
+        })?;
+
+        // Recurse into the component's own template to find nested components.
+        let rendered = renders(call.in_block, call.conditional);
+        let inside = Placement { rendered, layout: false };
+        scan_component_calls(env, components, &component.template_path, &component.template_content, inside, calls, scanned)?;
+        let kwargs = call.kwargs.into_iter().filter(|(key, _)| key != ON_ERROR).collect();
+        calls.push(ComponentCall { name, kwargs, prefetch: rendered && call.constant_args });
+    }
+
+    Ok(())
+}
+
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_share_loaded_templates() {
+        let loads = Arc::new(AtomicU64::new(0));
+        let mut shared = Environment::new();
+        let counter = Arc::clone(&loads);
+        shared.set_loader(move |name| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            Ok(Some(format!("<p>{}</p>", name)))
+        });
+
+        let first = shared.clone();
+        first.get_template("layouts/base.html").unwrap();
+        share_loaded_templates(&shared, &first);
+        assert_eq!(loads.load(Ordering::SeqCst), 2);
+
+        // Later copies start out with it and never ask the loader again.
+        let second = shared.clone();
+        assert_eq!(second.get_template("layouts/base.html").unwrap().render(()).unwrap(), "<p>layouts/base.html</p>");
+        share_loaded_templates(&shared, &second);
+        assert_eq!(loads.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_scan_follows_includes_and_imports() {
+        let mut env = Environment::new();
+        env.add_template("layouts/base.html", "{% include \"partials/nav.html\" %}{% block content %}{% endblock %}").unwrap();
+        env.add_template("partials/nav.html", "<nav>{{ component('search', placeholder='Find, fast') }}</nav>").unwrap();
+        env.add_template("macros/forms.html", "{% macro signup() %}{{ component('newsletter') }}{% endmacro %}").unwrap();
+        let component = |id: &str, template_content: &str| Component {
+            id: id.to_string(),
+            logic_path: None,
+            template_path: format!("components/{}/{}_template.html", id, id),
+            template_content: template_content.to_string(),
+            props: None,
+        };
+        let components = vec![component("search", "<form></form>"), component("newsletter", "{{ component('search') }}")];
+
+        let page = "{% extends \"layouts/base.html\" %}{% from \"macros/forms.html\" import signup %}{% block content %}{{ signup() }}{% endblock %}";
+        let mut calls = Vec::new();
+        let placement = Placement { rendered: true, layout: false };
+        scan_component_calls(&env, &components, "pages/index.html", page, placement, &mut calls, &mut HashSet::new()).unwrap();
+        let names: Vec<&str> = calls.iter().map(|call| call.name.as_str()).collect();
+        assert_eq!(names, vec!["search", "search", "newsletter"]);
+        // The navigation is on every page; the signup form only where the macro is called.
+        let prefetched: Vec<bool> = calls.iter().map(|call| call.prefetch).collect();
+        assert_eq!(prefetched, vec![true, false, false]);
+        assert_eq!(calls[0].kwargs.get("placeholder").map(Value::to_string), Some("Find, fast".to_string()));
+    }
+
+    #[test]
+    fn test_mark_forms() {
+        let html = r#"<form method="post" data-action="delete"><button></button></form><form method="post"><input type="hidden" name="action" value="save"></form>"#;
+        let marked = mark_forms(html, "todos/item", "1a2b3c4d-2");
+        assert_eq!(
+            marked,
+            concat!(
+                r#"<form method="post" data-action="delete"><input type="hidden" name="component_id" value="todos/item"><input type="hidden" name="component_instance" value="1a2b3c4d-2"><input type="hidden" name="action" value="delete"><button></button></form>"#,
+                r#"<form method="post"><input type="hidden" name="component_id" value="todos/item"><input type="hidden" name="component_instance" value="1a2b3c4d-2"><input type="hidden" name="action" value="save"></form>"#,
+            )
+        );
+        // The list around the item keeps the item's forms as they are.
+        let list = format!("<section>{}<form></form></section>", marked);
+        let outer = mark_forms(&list, "todos", "00000000-0");
+        assert!(outer.starts_with(&format!("<section>{}", marked)));
+        assert!(outer.ends_with(r#"<form><input type="hidden" name="component_id" value="todos"><input type="hidden" name="component_instance" value="00000000-0"></form></section>"#));
+    }
+
+    #[test]
+    fn test_mark_component() {
+        assert_eq!(mark_component("<p>No forms</p>", "card", "00000000-0"), "<p>No forms</p>");
+        let marked = mark_component("<li><form></form></li>", "todos/item", "1a2b3c4d-1");
+        assert!(marked.starts_with("<!--noventa-component:todos/item:1a2b3c4d-1--><li><form><input"));
+        assert!(marked.ends_with("</form></li><!--/noventa-component-->"));
+    }
+
+    #[test]
+    fn test_instance_ids() {
+        let kwargs = |pairs: &[(&str, Value)]| -> HashMap<String, Value> { pairs.iter().map(|(key, value)| (key.to_string(), value.clone())).collect() };
+        let a = kwargs(&[("id", Value::from("a")), ("size", Value::from(2))]);
+        let b = kwargs(&[("id", Value::from("b")), ("size", Value::from(2))]);
+        assert_eq!(kwargs_hash(&a), kwargs_hash(&kwargs(&[("size", Value::from(2)), ("id", Value::from("a"))])));
+        assert_ne!(kwargs_hash(&a), kwargs_hash(&b));
+
+        let instances = Instances::default();
+        let ids: Vec<String> = [("counter", &a), ("counter", &b), ("counter", &a), ("other", &a)]
+            .iter()
+            .map(|(name, kwargs)| next_instance(&instances, name, kwargs))
+            .collect();
+        let hash_a = kwargs_hash(&a);
+        assert_eq!(ids[0], format!("{}-0", hash_a));
+        assert_eq!(ids[1], format!("{}-0", kwargs_hash(&b)));
+        assert_eq!(ids[2], format!("{}-1", hash_a));
+        assert_eq!(ids[3], format!("{}-0", hash_a));
+
+        // The scanned call with constant arguments hashes like the rendered one.
+        let refs = crate::template_ast::scan("page.html", "{{ component('counter', size=2, id='b') }}").unwrap();
+        let scanned: HashMap<String, Value> = refs.components[0].kwargs.iter().cloned().collect();
+        assert_eq!(kwargs_hash(&scanned), kwargs_hash(&b));
+    }
+
+    #[test]
+    fn test_prefetch_instances() {
+        let call = |name: &str, id: &str, prefetch: bool| ComponentCall {
+            name: name.to_string(),
+            kwargs: [("id".to_string(), Value::from(id))].into_iter().collect(),
+            prefetch,
+        };
+        let calls = vec![call("card", "a", true), call("card", "a", false), call("card", "b", true), call("card", "a", true), call("nav", "a", true)];
+        let hash = |id: &str| kwargs_hash(&call("card", id, true).kwargs);
+        let instances: Vec<String> = prefetch_instances(&calls).into_iter().map(|(instance, _)| instance).collect();
+        assert_eq!(
+            instances,
+            vec![format!("card:{}-0", hash("a")), format!("card:{}-0", hash("b")), format!("card:{}-1", hash("a")), format!("nav:{}-0", hash("a"))]
+        );
+    }
+
     #[test]
     fn test_path_to_module() {
         // Test basic conversion
@@ -789,6 +1635,213 @@ mod tests {
         // Test edge cases
         assert_eq!(path_to_module("single").unwrap(), "single");
         assert_eq!(path_to_module("a/b/c.py").unwrap(), "a.b.c");
+
+        // Test Windows paths
+        assert_eq!(path_to_module(".\\components\\blog\\card_logic.py").unwrap(), "components.blog.card_logic");
+    }
+
+    #[test]
+    fn test_page_logic_path() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("pages/blog")).unwrap();
+        std::fs::write(dir.path().join("pages/blog/_page.py"), "def load_page_context(request):\n    return {}\n").unwrap();
+        assert_eq!(page_logic_path(dir.path(), "pages/blog/[slug].html").as_deref(), Some("pages/blog/_page.py"));
+        assert_eq!(page_logic_path(dir.path(), "pages/index.html"), None);
+        std::fs::write(dir.path().join("pages/_logic.py"), "def load_page_context(request, session):\n    return {}\n").unwrap();
+        assert_eq!(page_logic_path(dir.path(), "pages/index.html").as_deref(), Some("pages/_logic.py"));
+        std::fs::write(dir.path().join("pages/blog/_logic.py"), "").unwrap();
+        assert_eq!(page_logic_path(dir.path(), "pages/blog/[slug].html").as_deref(), Some("pages/blog/_page.py"));
+        assert_eq!(path_to_module("pages/blog/_page.py").unwrap(), "pages.blog._page");
+
+        let redirect_to = |context| redirects::from_context(&Value::from_serialize(context)).map(|redirect| redirect.url);
+        assert_eq!(redirect_to(serde_json::json!({ "_redirect": "/login" })).as_deref(), Some("/login"));
+        assert_eq!(redirect_to(serde_json::json!({ "_redirect": null, "posts": [] })), None);
+    }
+
+    #[test]
+    fn test_status() {
+        let requested = RequestedStatus::default();
+        let mut env = Environment::new();
+        add_status_function(&mut env, &requested);
+        let html = env.render_str("{{ status(410) }}<h1>Gone</h1>{{ status(418, 'teapot.html') }}", ()).unwrap();
+        assert_eq!(html, "<h1>Gone</h1>");
+        assert_eq!(requested.lock().unwrap().take(), Some((410, None)));
+        assert!(env.render_str("{{ status(42) }}", ()).is_err());
+
+        let context = Value::from_serialize(serde_json::json!({ "_status": 410, "_status_template": "gone.html" }));
+        assert_eq!(requested_status(&context), Some((410, Some("gone.html".to_string()))));
+        assert_eq!(requested_status(&Value::from_serialize(serde_json::json!({ "_status": 1000 }))), None);
+    }
+
+    fn component_with_logic(id: &str) -> Component {
+        Component {
+            id: id.to_string(),
+            logic_path: Some(format!("components/{}/{}_logic.py", id, id)),
+            template_path: format!("./components/{}/{}_template.html", id, id),
+            template_content: String::new(),
+            props: None,
+        }
+    }
+
+    fn test_scope(components: Vec<Component>) -> ComponentScope {
+        let req = actix_web::test::TestRequest::default().to_http_request();
+        let request_info = crate::routing::build_http_request_info(&req, serde_json::Map::new(), HashMap::new(), HashMap::new(), None);
+        ComponentScope {
+            components: Arc::new(RwLock::new(components)),
+            request_info: Arc::new(request_info),
+            dev_mode: true,
+            trace: None,
+            posted: Arc::new(None),
+            first_instances: HashMap::new(),
+            instances: Instances::default(),
+            loaded: Arc::default(),
+            parents: Arc::default(),
+            slots: Arc::default(),
+            pending: Arc::default(),
+            status: RequestedStatus::default(),
+            failures: Arc::default(),
+        }
+    }
+
+    // Stands in for the interpreters: every component gets its arguments back as its context, with
+    // `extra` for the one titled "b", and every helper returns true.
+    fn load_arguments(scope: &ComponentScope, calls: Vec<PendingCall>, extra: serde_json::Value) {
+        let mut loaded = scope.loaded.lock().unwrap();
+        for call in calls {
+            match call {
+                PendingCall::Component { slot, instance, args, .. } => {
+                    let mut context = serde_json::to_value(&args).unwrap();
+                    if args.get("title").and_then(Value::as_str) == Some("b") {
+                        context.as_object_mut().unwrap().extend(extra.as_object().unwrap().clone());
+                    }
+                    let result = PythonFunctionResult { context: Value::from_serialize(context), stream: None, events: Vec::new() };
+                    loaded.insert(slot, instance, LoadedCall::Context(Ok(Ok(result))));
+                }
+                PendingCall::Helper { slot, call } => loaded.insert(Some(slot), call.key, LoadedCall::Helper(Ok(Value::from(true)))),
+                PendingCall::Flashes => loaded.flashes = Some(Value::from(vec!["Saved"])),
+            }
+        }
+    }
+
+    // Renders in passes the way `render` does, with `load` in place of the interpreters.
+    fn settle<T>(
+        scope: &ComponentScope,
+        mut render: impl FnMut() -> Result<T, minijinja::Error>,
+        mut load: impl FnMut(Vec<PendingCall>),
+    ) -> Result<T, minijinja::Error> {
+        for _ in 0..MAX_RENDER_PASSES {
+            match run_pass(scope, &mut render)? {
+                Passed::Rendered(rendered) => return Ok(rendered),
+                Passed::Redirect(redirect) => return Err(redirect.into_error()),
+                Passed::Pending(calls) => load(calls),
+            }
+        }
+        Err(minijinja::Error::new(minijinja::ErrorKind::InvalidOperation, "still waiting"))
+    }
+
+    #[actix_rt::test]
+    async fn test_render_in_passes() {
+        let scope = test_scope(vec![component_with_logic("card"), component_with_logic("badge")]);
+        let mut env = Environment::new();
+        env.add_template("components/card/card_template.html", "<div>{{ title }}{{ component('badge', label=title) }}</div>").unwrap();
+        env.add_template("components/badge/badge_template.html", "<b>{{ label }}</b>").unwrap();
+        env.add_template("page.html", "{% for title in ['a', 'b'] %}{{ component('card', title=title) }}{% endfor %}").unwrap();
+        add_component_function(&mut env, scope.clone());
+
+        // The cards in the loop are loaded together, then the badges inside them, each once.
+        let mut batches = Vec::new();
+        let page = settle(
+            &scope,
+            || env.get_template("page.html").unwrap().render(()),
+            |calls| {
+                batches.push(calls.len());
+                load_arguments(&scope, calls, serde_json::json!({ "_status": 410 }));
+            },
+        )
+        .unwrap();
+        assert_eq!(page, "<div>a<b>a</b></div><div>b<b>b</b></div>");
+        assert_eq!(batches, vec![2, 2]);
+        assert_eq!(scope.status.lock().unwrap().clone(), Some((410, None)));
+    }
+
+    #[actix_rt::test]
+    async fn test_render_in_passes_stops_on_redirect() {
+        let scope = test_scope(vec![component_with_logic("card")]);
+        let mut env = Environment::new();
+        env.add_template("components/card/card_template.html", "<div>{{ title }}</div>").unwrap();
+        add_component_function(&mut env, scope.clone());
+
+        let redirected = settle(
+            &scope,
+            || env.render_str("{{ component('card', title='a') }}{{ component('card', title='b') }}", ()),
+            |calls| load_arguments(&scope, calls, serde_json::json!({ "_redirect": "/login" })),
+        );
+        assert_eq!(redirects::from_error(&redirected.unwrap_err()).map(|redirect| redirect.url.as_str()), Some("/login"));
+    }
+
+    #[actix_rt::test]
+    async fn test_arguments_that_change_between_passes_settle() {
+        let scope = test_scope(vec![component_with_logic("card")]);
+        let mut env = Environment::new();
+        env.add_template("components/card/card_template.html", "<div>{{ title }}</div>").unwrap();
+        let ticks = Arc::new(AtomicU64::new(0));
+        let counter = Arc::clone(&ticks);
+        env.add_function("tick", move || counter.fetch_add(1, Ordering::SeqCst));
+        add_component_function(&mut env, scope.clone());
+
+        // Loaded for the first two passes' arguments; the third gives up on catching up and uses the last.
+        let mut loads = Vec::new();
+        let page = settle(&scope, || env.render_str("{{ component('card', title=tick()) }}{{ component('card', title='a') }}", ()), |calls| {
+            loads.push(calls.len());
+            load_arguments(&scope, calls, serde_json::json!({}));
+        })
+        .unwrap();
+        assert_eq!(page, "<div>1</div><div>a</div>");
+        assert_eq!(loads, vec![2, 1]);
+        assert_eq!(ticks.load(Ordering::SeqCst), 3);
+    }
+
+    #[actix_rt::test]
+    async fn test_helpers_and_flashes_load_between_passes() {
+        let scope = test_scope(vec![component_with_logic("card")]);
+        let mut env = Environment::new();
+        env.add_template("components/card/card_template.html", "<div>{{ title }}</div>").unwrap();
+        let helpers = [TemplateHelper { kind: template_helpers::HelperKind::Global, name: "flag".to_string(), attribute: "flag".to_string(), pure: false }];
+        let calling = scope.clone();
+        template_helpers::apply(&mut env, &helpers, move |helper, args, kwargs| calling.call_helper(helper, args, kwargs));
+        let wanting = scope.clone();
+        let flashes = scope.loaded.lock().unwrap().flashes.clone();
+        redirects::add_flash_global(&mut env, flashes, move || wanting.want_flashes());
+        add_component_function(&mut env, scope.clone());
+
+        // Until `flag()` is loaded the first card isn't reached, and the second takes its place at
+        // the slot; it's matched by its arguments, so neither gets the other's data.
+        let template = "{% if flag() %}{{ component('card', title='x') }}{% endif %}{{ component('card', title='y') }}{{ flag() }}";
+        let mut batches = Vec::new();
+        let page = settle(&scope, || env.render_str(template, ()), |calls| {
+            batches.push(calls.len());
+            load_arguments(&scope, calls, serde_json::json!({}));
+        })
+        .unwrap();
+        assert_eq!(page, "<div>x</div><div>y</div>true");
+        assert_eq!(batches, vec![2, 1]);
+
+        // `flashed_messages()` is taken from the session once, between passes.
+        let mut env = Environment::new();
+        let wanting = scope.clone();
+        redirects::add_flash_global(&mut env, None, move || wanting.want_flashes());
+        assert!(matches!(run_pass(&scope, || env.render_str("{{ flashed_messages() }}", ())).unwrap(), Passed::Pending(calls) if matches!(calls[..], [PendingCall::Flashes])));
+    }
+
+    #[test]
+    fn test_issue_form_tokens() {
+        let html = format!("<form>{}</form><form>{}</form>", FORM_TOKEN_PLACEHOLDER, FORM_TOKEN_PLACEHOLDER);
+        let issued = issue_form_tokens(&html);
+        assert!(!issued.contains(FORM_TOKEN_PLACEHOLDER));
+        let tokens: Vec<&str> = issued.split("<form>").skip(1).map(|form| form.trim_end_matches("</form>")).collect();
+        assert_eq!(tokens.len(), 2);
+        assert_ne!(tokens[0], tokens[1]);
+        assert_eq!(issue_form_tokens("<p>No forms</p>"), "<p>No forms</p>");
     }
 
     #[test]
@@ -809,4 +1862,69 @@ mod tests {
         let result = format_filter("{{}}".to_string(), minijinja::value::Rest(vec![])).unwrap();
         assert_eq!(result, "{}");
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_error_boundary() {
+        let mut scope = test_scope(vec![Component {
+            id: "cart".to_string(),
+            logic_path: None,
+            template_path: "./components/cart/cart_template.html".to_string(),
+            template_content: String::new(),
+            props: None,
+        }]);
+        scope.dev_mode = false;
+        let mut env = Environment::new();
+        env.add_template("components/cart/cart_error.html", "<p>The {{ component }} is unavailable.</p>").unwrap();
+        env.add_template("page.html", "<h1>Shop</h1>{{ failing(on_error) }}").unwrap();
+        let boundary_scope = scope.clone();
+        env.add_function("failing", move |state: &State, on_error: Option<String>| -> Result<Value, minijinja::Error> {
+            let error = minijinja::Error::new(minijinja::ErrorKind::InvalidOperation, "Python function crashed");
+            error_boundary(state, &boundary_scope, "cart", on_error.as_deref(), error)
+        });
+        let page = env.get_template("page.html").unwrap();
+
+        let rendered = page.render(minijinja::context! { on_error => "cart_error.html" }).unwrap();
+        assert_eq!(rendered, "<h1>Shop</h1><p>The cart is unavailable.</p>");
+        // Kept to be recorded once the page is done, not on every pass.
+        let failed: Vec<(String, String, Option<String>)> =
+            scope.failures.lock().unwrap().iter().map(|failure| (failure.component.clone(), failure.fallback.clone(), failure.error.route.clone())).collect();
+        assert_eq!(failed, vec![("cart".to_string(), "cart_error.html".to_string(), Some("/".to_string()))]);
+        assert!(page.render(minijinja::context! {}).is_err());
+    }
+
+    #[test]
+    fn test_registered_filter_renders_in_pages() {
+        template_extensions::register_filter("renderer_test_initials", |name: String| {
+            name.split_whitespace().filter_map(|word| word.chars().next()).collect::<String>()
+        })
+        .unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("page.html"), "<p>{{ 'Ada Lovelace'|renderer_test_initials }}</p>").unwrap();
+
+        let env = build_environment(dir.path());
+        assert_eq!(env.get_template("page.html").unwrap().render(()).unwrap(), "<p>AL</p>");
+    }
+
+    #[test]
+    fn test_form_macros() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut env = build_environment(dir.path());
+        env.add_template(
+            "signup.html",
+            r#"{% from "noventa/forms.html" import field_errors, form_errors, value %}<input value="{{ value(form, 'email') }}">{{ field_errors(form, "email") }}{{ form_errors(form, only_form=true) }}"#,
+        )
+        .unwrap();
+        let tmpl = env.get_template("signup.html").unwrap();
+
+        assert_eq!(tmpl.render(minijinja::context! {}).unwrap(), r#"<input value="">"#);
+        let form = serde_json::json!({
+            "valid": false,
+            "errors": { "email": ["Field required"], "__all__": ["Passwords don't match"] },
+            "values": { "email": "ana@" },
+        });
+        assert_eq!(
+            tmpl.render(minijinja::context! { form => Value::from_serialize(&form) }).unwrap(),
+            r#"<input value="ana@"><ul class="field-errors"><li>Field required</li></ul><ul class="form-errors"><li>Passwords don&#x27;t match</li></ul>"#
+        );
+    }
+}
@@ -0,0 +1,72 @@
+use actix::prelude::*;
+use actix_web_actors::ws;
+use crate::actors::ws_server::{ConnectUser, DisconnectUser, WsServer};
+use crate::user_channels::Identity;
+
+// For a logged-in user's connection: something Python broadcast to them, or the end of their login.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub enum UserMessage {
+    Broadcast(String),
+    LoggedOut,
+}
+
+// A page a logged-in user has open, connected to `/_noventa/ws`. It only listens.
+pub struct UserWebSocket {
+    server_addr: Addr<WsServer>,
+    identity: Identity,
+}
+
+impl UserWebSocket {
+    pub fn new(server_addr: Addr<WsServer>, identity: Identity) -> Self {
+        Self { server_addr, identity }
+    }
+}
+
+impl Actor for UserWebSocket {
+    type Context = ws::WebsocketContext<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        self.server_addr.do_send(ConnectUser {
+            addr: ctx.address().recipient(),
+            identity: self.identity.clone(),
+        });
+    }
+
+    fn stopping(&mut self, ctx: &mut Self::Context) -> Running {
+        self.server_addr.do_send(DisconnectUser { addr: ctx.address().recipient() });
+        Running::Stop
+    }
+}
+
+impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for UserWebSocket {
+    fn handle(&mut self, msg: Result<ws::Message, ws::ProtocolError>, ctx: &mut Self::Context) {
+        match msg {
+            Ok(ws::Message::Ping(msg)) => ctx.pong(&msg),
+            Ok(ws::Message::Close(reason)) => {
+                ctx.close(reason);
+                ctx.stop();
+            }
+            Err(e) => {
+                log::debug!("A user's WebSocket failed: {:?}", e);
+                ctx.stop();
+            }
+            _ => (),
+        }
+    }
+}
+
+impl Handler<UserMessage> for UserWebSocket {
+    type Result = ();
+
+    fn handle(&mut self, msg: UserMessage, ctx: &mut Self::Context) {
+        match msg {
+            UserMessage::Broadcast(text) => ctx.text(text),
+            UserMessage::LoggedOut => {
+                // 1008: the page shouldn't reconnect, the user has to log in again.
+                ctx.close(Some(ws::CloseReason { code: ws::CloseCode::Policy, description: Some("logged out".to_string()) }));
+                ctx.stop();
+            }
+        }
+    }
+}
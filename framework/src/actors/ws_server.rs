@@ -1,11 +1,16 @@
 use actix::prelude::*;
-use std::collections::HashSet;
-use crate::actors::dev_websockets::ReloadMessage;
+use std::collections::HashMap;
+use tokio::sync::broadcast::error::RecvError;
+use crate::actors::dev_websockets::{ErrorMessage, ReloadMessage};
+use crate::actors::user_websockets::UserMessage;
+use crate::errors::ERROR_CHANNEL;
+use crate::user_channels::{Identity, UserEvent, USER_CHANNEL};
 
 #[derive(Message)]
 #[rtype(result = "()")]
 pub struct Connect {
     pub addr: Recipient<ReloadMessage>,
+    pub errors: Recipient<ErrorMessage>,
 }
 
 #[derive(Message)]
@@ -14,31 +19,89 @@ pub struct Disconnect {
     pub addr: Recipient<ReloadMessage>,
 }
 
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct ConnectUser {
+    pub addr: Recipient<UserMessage>,
+    pub identity: Identity,
+}
+
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct DisconnectUser {
+    pub addr: Recipient<UserMessage>,
+}
+
 #[derive(Message)]
 #[rtype(result = "()")]
 pub struct BroadcastReload;
 
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct BroadcastError(pub String);
+
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct BroadcastToUsers(pub UserEvent);
+
 pub struct WsServer {
-    sessions: HashSet<Recipient<ReloadMessage>>,
+    // Each browser tab, keyed by its reload recipient, with the recipient used for error overlays.
+    sessions: HashMap<Recipient<ReloadMessage>, Recipient<ErrorMessage>>,
+    // Pages of logged-in users on `/_noventa/ws`, with who they belong to.
+    users: HashMap<Recipient<UserMessage>, Identity>,
 }
 
 impl WsServer {
     pub fn new() -> Self {
         WsServer {
-            sessions: HashSet::new(),
+            sessions: HashMap::new(),
+            users: HashMap::new(),
         }
     }
 }
 
 impl Actor for WsServer {
     type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        // Forward every render error to the connected browsers so they can show the overlay
+        // without waiting for a manual refresh.
+        let addr = ctx.address();
+        let mut error_rx = ERROR_CHANNEL.subscribe();
+        actix::spawn(async move {
+            loop {
+                match error_rx.recv().await {
+                    Ok(error_json) => addr.do_send(BroadcastError(error_json)),
+                    Err(RecvError::Lagged(skipped)) => {
+                        log::warn!("The error overlay fell behind and skipped {} errors.", skipped);
+                    }
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        });
+
+        // What Python broadcasts to `user:<id>` channels, and logins that ended.
+        let addr = ctx.address();
+        let mut user_rx = USER_CHANNEL.subscribe();
+        actix::spawn(async move {
+            loop {
+                match user_rx.recv().await {
+                    Ok(event) => addr.do_send(BroadcastToUsers(event)),
+                    Err(RecvError::Lagged(skipped)) => {
+                        log::warn!("Heads up! The WebSocket server fell behind and skipped {} user broadcasts.", skipped);
+                    }
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        });
+    }
 }
 
 impl Handler<Connect> for WsServer {
     type Result = ();
 
     fn handle(&mut self, msg: Connect, _: &mut Context<Self>) {
-        self.sessions.insert(msg.addr);
+        self.sessions.insert(msg.addr, msg.errors);
     }
 }
 
@@ -50,16 +113,65 @@ impl Handler<Disconnect> for WsServer {
     }
 }
 
+impl Handler<ConnectUser> for WsServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: ConnectUser, _: &mut Context<Self>) {
+        self.users.insert(msg.addr, msg.identity);
+    }
+}
+
+impl Handler<DisconnectUser> for WsServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: DisconnectUser, _: &mut Context<Self>) {
+        self.users.remove(&msg.addr);
+    }
+}
+
 impl Handler<BroadcastReload> for WsServer {
     type Result = ();
 
     fn handle(&mut self, _msg: BroadcastReload, _: &mut Context<Self>) {
-        for addr in &self.sessions {
+        for addr in self.sessions.keys() {
             addr.do_send(ReloadMessage);
         }
     }
 }
 
+impl Handler<BroadcastError> for WsServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: BroadcastError, _: &mut Context<Self>) {
+        for errors in self.sessions.values() {
+            errors.do_send(ErrorMessage(msg.0.clone()));
+        }
+    }
+}
+
+impl Handler<BroadcastToUsers> for WsServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: BroadcastToUsers, _: &mut Context<Self>) {
+        match msg.0 {
+            UserEvent::Message { user, message } => {
+                for (addr, identity) in &self.users {
+                    if identity.user == user {
+                        addr.do_send(UserMessage::Broadcast(message.clone()));
+                    }
+                }
+            }
+            UserEvent::LoggedOut { login } => self.users.retain(|addr, identity| {
+                if identity.login != login {
+                    return true;
+                }
+                addr.do_send(UserMessage::LoggedOut);
+                false
+            }),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -148,4 +260,76 @@ mod tests {
         let result = addr.send(BroadcastReload).await;
         assert!(result.is_ok());
     }
-}
\ No newline at end of file
+
+    #[actix_rt::test]
+    async fn test_errors_are_forwarded_to_connected_clients() {
+        use std::sync::{Arc, Mutex};
+        use crate::actors::dev_websockets::DevWebSocket;
+
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let received_clone = received.clone();
+        let client = Mocker::<DevWebSocket>::mock(Box::new(move |msg, _ctx| {
+            if let Some(ErrorMessage(json)) = msg.downcast_ref::<ErrorMessage>() {
+                received_clone.lock().unwrap().push(json.clone());
+            }
+            Box::new(Some(()))
+        }))
+        .start();
+
+        let server = WsServer::new().start();
+        server
+            .send(Connect {
+                addr: client.clone().recipient(),
+                errors: client.recipient(),
+            })
+            .await
+            .unwrap();
+
+        ERROR_CHANNEL.send("{\"message\":\"overlay-forwarding-test\"}".to_string()).unwrap();
+        actix_rt::time::sleep(std::time::Duration::from_millis(50)).await;
+
+        assert!(received
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|json| json.contains("overlay-forwarding-test")));
+    }
+
+    #[actix_rt::test]
+    async fn test_user_broadcasts_and_logouts() {
+        use std::sync::{Arc, Mutex};
+        use crate::actors::user_websockets::UserWebSocket;
+
+        // Every connection writes down what it was sent.
+        let connect = |received: Arc<Mutex<Vec<String>>>| {
+            Mocker::<UserWebSocket>::mock(Box::new(move |msg, _ctx| {
+                match msg.downcast_ref::<UserMessage>() {
+                    Some(UserMessage::Broadcast(text)) => received.lock().unwrap().push(text.clone()),
+                    Some(UserMessage::LoggedOut) => received.lock().unwrap().push("logged out".to_string()),
+                    None => {}
+                }
+                Box::new(Some(()))
+            }))
+            .start()
+        };
+        let identity = |user: &str, login: &str| Identity { user: user.to_string(), login: login.to_string() };
+
+        let laptop = Arc::new(Mutex::new(Vec::new()));
+        let phone = Arc::new(Mutex::new(Vec::new()));
+        let someone_else = Arc::new(Mutex::new(Vec::new()));
+        let server = WsServer::new().start();
+        server.send(ConnectUser { addr: connect(laptop.clone()).recipient(), identity: identity("7", "laptop") }).await.unwrap();
+        server.send(ConnectUser { addr: connect(phone.clone()).recipient(), identity: identity("7", "phone") }).await.unwrap();
+        server.send(ConnectUser { addr: connect(someone_else.clone()).recipient(), identity: identity("8", "other") }).await.unwrap();
+
+        let message = |user: &str, text: &str| BroadcastToUsers(UserEvent::Message { user: user.to_string(), message: text.to_string() });
+        server.send(message("7", "hello")).await.unwrap();
+        server.send(BroadcastToUsers(UserEvent::LoggedOut { login: "laptop".to_string() })).await.unwrap();
+        server.send(message("7", "still there?")).await.unwrap();
+        actix_rt::time::sleep(std::time::Duration::from_millis(50)).await;
+
+        assert_eq!(*laptop.lock().unwrap(), vec!["hello", "logged out"]);
+        assert_eq!(*phone.lock().unwrap(), vec!["hello", "still there?"]);
+        assert!(someone_else.lock().unwrap().is_empty());
+    }
+}
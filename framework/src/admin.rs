@@ -0,0 +1,232 @@
+use crate::actors::health::{GetSystemHealth, HealthActor};
+use crate::actors::router::RouterActor;
+use crate::cluster::{self, ClusterEvent};
+use crate::config;
+use crate::security_headers::CspNonce;
+use actix::Addr;
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::{web, HttpMessage, HttpRequest, HttpResponse};
+use serde::Deserialize;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+const DASHBOARD: &str = include_str!("templates/admin_dashboard.html");
+const MAINTENANCE_PAGE: &str = "<!DOCTYPE html><html><head><meta charset=\"UTF-8\"><title>Down for maintenance</title></head>\
+<body><h1>We'll be right back</h1><p>This site is down for maintenance. Please try again in a few minutes.</p></body></html>";
+
+// Flipped from the dashboard; every page answers 503 while it's on.
+static MAINTENANCE: AtomicBool = AtomicBool::new(false);
+
+pub struct AdminToken(pub String);
+
+pub fn token() -> Option<String> {
+    config::CONFIG.admin.as_ref().and_then(|admin| admin.token.clone())
+}
+
+pub fn maintenance_enabled() -> bool {
+    MAINTENANCE.load(Ordering::SeqCst)
+}
+
+pub fn set_maintenance(enabled: bool) {
+    MAINTENANCE.store(enabled, Ordering::SeqCst);
+    if enabled {
+        log::warn!("Maintenance mode is on. Pages answer 503 until it's turned off again.");
+    } else {
+        log::info!("✨ Maintenance mode is off. Back to business!");
+    }
+}
+
+// Compares every byte so the response time doesn't reveal how much of the token was right.
+pub(crate) fn tokens_match(given: &[u8], expected: &[u8]) -> bool {
+    given.len() == expected.len() && given.iter().zip(expected).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+pub(crate) fn is_authorized(req: &HttpRequest, token: &AdminToken) -> bool {
+    req.headers()
+        .get("authorization")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .is_some_and(|given| tokens_match(given.trim().as_bytes(), token.0.as_bytes()))
+}
+
+fn unauthorized() -> HttpResponse {
+    HttpResponse::Unauthorized()
+        .insert_header(("WWW-Authenticate", "Bearer"))
+        .json(serde_json::json!({ "error": "Send the admin token from config.yaml as `Authorization: Bearer <token>`." }))
+}
+
+// The page itself holds no data; it asks for the token and calls the API below.
+async fn dashboard(req: HttpRequest) -> HttpResponse {
+    let nonce = req.extensions().get::<CspNonce>().map(|nonce| nonce.0.clone()).unwrap_or_default();
+    HttpResponse::Ok()
+        .content_type("text/html")
+        .insert_header(("Cache-Control", "no-store"))
+        .body(DASHBOARD.replace("{nonce}", &nonce))
+}
+
+async fn status(
+    req: HttpRequest,
+    token: web::Data<AdminToken>,
+    health: Option<web::Data<Addr<HealthActor>>>,
+) -> HttpResponse {
+    if !is_authorized(&req, &token) {
+        return unauthorized();
+    }
+    let health = match health {
+        Some(health) => health.send(GetSystemHealth).await.ok(),
+        None => None,
+    };
+    HttpResponse::Ok().json(serde_json::json!({
+        "maintenance": maintenance_enabled(),
+        "recent_errors": crate::errors::recent_errors().len(),
+        "health": health,
+    }))
+}
+
+async fn errors(req: HttpRequest, token: web::Data<AdminToken>) -> HttpResponse {
+    if !is_authorized(&req, &token) {
+        return unauthorized();
+    }
+    HttpResponse::Ok().json(crate::errors::recent_errors())
+}
+
+async fn clear_caches(req: HttpRequest, token: web::Data<AdminToken>) -> HttpResponse {
+    if !is_authorized(&req, &token) {
+        return unauthorized();
+    }
+    crate::actors::template_renderer::invalidate_pages();
+    crate::page_meta::clear();
+    crate::reverse_routes::clear();
+    crate::compressed_pages::clear();
+    log::info!("✨ Template caches cleared from the admin dashboard.");
+    cluster::broadcast(ClusterEvent::ClearCaches);
+    HttpResponse::Ok().json(serde_json::json!({ "cleared": true }))
+}
+
+#[derive(Deserialize)]
+struct MaintenanceRequest {
+    enabled: bool,
+}
+
+async fn toggle_maintenance(
+    req: HttpRequest,
+    token: web::Data<AdminToken>,
+    body: web::Json<MaintenanceRequest>,
+) -> HttpResponse {
+    if !is_authorized(&req, &token) {
+        return unauthorized();
+    }
+    set_maintenance(body.enabled);
+    cluster::broadcast(ClusterEvent::Maintenance { enabled: body.enabled });
+    HttpResponse::Ok().json(serde_json::json!({ "maintenance": body.enabled }))
+}
+
+async fn reload_routes(
+    req: HttpRequest,
+    token: web::Data<AdminToken>,
+    router: Option<web::Data<Addr<RouterActor>>>,
+) -> HttpResponse {
+    if !is_authorized(&req, &token) {
+        return unauthorized();
+    }
+    match router {
+        Some(router) => {
+            crate::routing::reload_pages(&router);
+            cluster::broadcast(ClusterEvent::ReloadPages);
+            HttpResponse::Ok().json(serde_json::json!({ "reloaded": true }))
+        }
+        None => HttpResponse::Conflict()
+            .json(serde_json::json!({ "error": "This server compiled its routes at startup and can't reload them." })),
+    }
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig, token: String) {
+    cfg.service(
+        web::scope("/_noventa/admin")
+            .app_data(web::Data::new(AdminToken(token)))
+            .route("", web::get().to(dashboard))
+            .route("/api/status", web::get().to(status))
+            .route("/api/errors", web::get().to(errors))
+            .route("/api/caches/clear", web::post().to(clear_caches))
+            .route("/api/maintenance", web::post().to(toggle_maintenance))
+            .route("/api/routes/reload", web::post().to(reload_routes)),
+    );
+}
+
+// Answers 503 for everything but Noventa's own endpoints, so the dashboard stays reachable.
+pub async fn maintenance_mode(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, actix_web::Error> {
+    if maintenance_enabled() && !req.path().starts_with("/_noventa/") {
+        let res = HttpResponse::ServiceUnavailable()
+            .content_type("text/html")
+            .insert_header(("Retry-After", "120"))
+            .body(MAINTENANCE_PAGE);
+        return Ok(req.into_response(res).map_into_right_body());
+    }
+    next.call(req).await.map(ServiceResponse::map_into_left_body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{test as actix_test, App};
+
+    const TOKEN: &str = "0123456789abcdef0123456789abcdef";
+
+    #[test]
+    fn test_tokens_match() {
+        assert!(tokens_match(b"secret", b"secret"));
+        assert!(!tokens_match(b"secret", b"secreT"));
+        assert!(!tokens_match(b"secret", b"secret-but-longer"));
+    }
+
+    // Both the endpoints and the maintenance switch live in one test, since the switch is process-wide.
+    #[actix_rt::test]
+    async fn test_admin_api() {
+        let app = actix_test::init_service(
+            App::new()
+                .wrap(actix_web::middleware::from_fn(maintenance_mode))
+                .configure(|cfg| configure(cfg, TOKEN.to_string()))
+                .route("/", web::get().to(|| async { HttpResponse::Ok().body("home") })),
+        )
+        .await;
+
+        let req = actix_test::TestRequest::get().uri("/_noventa/admin/api/errors").to_request();
+        assert_eq!(actix_test::call_service(&app, req).await.status(), 401);
+        let req = actix_test::TestRequest::get()
+            .uri("/_noventa/admin/api/errors")
+            .insert_header(("authorization", "Bearer wrong"))
+            .to_request();
+        assert_eq!(actix_test::call_service(&app, req).await.status(), 401);
+
+        let req = actix_test::TestRequest::post()
+            .uri("/_noventa/admin/api/maintenance")
+            .insert_header(("authorization", format!("Bearer {}", TOKEN)))
+            .set_json(serde_json::json!({ "enabled": true }))
+            .to_request();
+        assert_eq!(actix_test::call_service(&app, req).await.status(), 200);
+        let req = actix_test::TestRequest::get().uri("/").to_request();
+        assert_eq!(actix_test::call_service(&app, req).await.status(), 503);
+        let req = actix_test::TestRequest::get().uri("/_noventa/admin").to_request();
+        assert_eq!(actix_test::call_service(&app, req).await.status(), 200);
+
+        let req = actix_test::TestRequest::post()
+            .uri("/_noventa/admin/api/maintenance")
+            .insert_header(("authorization", format!("Bearer {}", TOKEN)))
+            .set_json(serde_json::json!({ "enabled": false }))
+            .to_request();
+        assert_eq!(actix_test::call_service(&app, req).await.status(), 200);
+        let req = actix_test::TestRequest::get().uri("/").to_request();
+        assert_eq!(actix_test::call_service(&app, req).await.status(), 200);
+
+        // No router in this app, so there's nothing to reload.
+        let req = actix_test::TestRequest::post()
+            .uri("/_noventa/admin/api/routes/reload")
+            .insert_header(("authorization", format!("Bearer {}", TOKEN)))
+            .to_request();
+        assert_eq!(actix_test::call_service(&app, req).await.status(), 409);
+    }
+}
@@ -0,0 +1,193 @@
+use crate::config;
+use actix_session::Session;
+use actix_web::HttpRequest;
+use argon2::password_hash::rand_core::OsRng;
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::PyModule;
+use std::collections::HashMap;
+
+const DEFAULT_LOGIN_URL: &str = "/login";
+const DEFAULT_USER_KEY: &str = "user_id";
+// The logged-in user's roles, from `session.login_user(id, roles=[...])` or a login provider's `roles` claim.
+pub const ROLES_KEY: &str = "_noventa_roles";
+// A new id for every login, so the WebSockets opened during it can be closed when it ends.
+pub const LOGIN_KEY: &str = "_noventa_login";
+
+fn auth_config() -> Option<&'static config::AuthConfig> {
+    config::CONFIG.auth.as_ref()
+}
+
+pub fn login_url() -> &'static str {
+    auth_config().and_then(|auth| auth.login_url.as_deref()).unwrap_or(DEFAULT_LOGIN_URL)
+}
+
+pub fn user_key() -> &'static str {
+    auth_config().and_then(|auth| auth.user_key.as_deref()).unwrap_or(DEFAULT_USER_KEY)
+}
+
+pub fn is_logged_in(session: &Session) -> bool {
+    session.get::<serde_json::Value>(user_key()).ok().flatten().is_some_and(|id| !id.is_null())
+}
+
+// What a `{# requires: role=admin, permission=reports.view #}` page asks of its users. All of it must hold.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Requirement {
+    Role(String),
+    Permission(String),
+}
+
+pub fn parse_requirements(text: &str) -> Result<Vec<Requirement>, String> {
+    text.split(',')
+        .map(str::trim)
+        .filter(|part| !part.is_empty())
+        .map(|part| match part.split_once('=').map(|(kind, name)| (kind.trim(), name.trim())) {
+            Some(("role", name)) if !name.is_empty() => Ok(Requirement::Role(name.to_string())),
+            Some(("permission", name)) if !name.is_empty() => Ok(Requirement::Permission(name.to_string())),
+            _ => Err(format!("'{}' isn't `role=name` or `permission=name`", part)),
+        })
+        .collect()
+}
+
+pub fn roles(session: &Session) -> Vec<String> {
+    session.get::<Vec<String>>(ROLES_KEY).ok().flatten().unwrap_or_default()
+}
+
+fn granted(permissions: &HashMap<String, Vec<String>>, roles: &[String], permission: &str) -> bool {
+    roles
+        .iter()
+        .filter_map(|role| permissions.get(role))
+        .flatten()
+        .any(|granted| granted == "*" || granted == permission)
+}
+
+// Through any of `roles`, as listed under `auth.roles`.
+pub fn has_permission(roles: &[String], permission: &str) -> bool {
+    auth_config()
+        .and_then(|auth| auth.roles.as_ref())
+        .is_some_and(|permissions| granted(permissions, roles, permission))
+}
+
+pub fn meets(roles: &[String], requirements: &[Requirement]) -> bool {
+    requirements.iter().all(|requirement| match requirement {
+        Requirement::Role(role) => roles.contains(role),
+        Requirement::Permission(permission) => has_permission(roles, permission),
+    })
+}
+
+// A login provider's `roles` claim, when it sends a list of them.
+pub fn claim_roles(claims: &serde_json::Map<String, serde_json::Value>) -> Vec<String> {
+    claims
+        .get("roles")
+        .and_then(serde_json::Value::as_array)
+        .map(|roles| roles.iter().filter_map(|role| role.as_str().map(str::to_string)).collect())
+        .unwrap_or_default()
+}
+
+// Where to send a visitor who isn't logged in, with the page they wanted as `next`.
+pub fn login_redirect(req: &HttpRequest) -> String {
+    let next = req.uri().path_and_query().map_or("/", |path| path.as_str());
+    let query = serde_urlencoded::to_string([("next", next)]).unwrap_or_default();
+    format!("{}?{}", login_url(), query)
+}
+
+pub fn hash(password: &str) -> Result<String, String> {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|e| e.to_string())
+}
+
+// False for a wrong password and for anything that isn't an argon2 hash.
+pub fn verify(password: &str, hash: &str) -> bool {
+    PasswordHash::new(hash).is_ok_and(|hash| Argon2::default().verify_password(password.as_bytes(), &hash).is_ok())
+}
+
+// `from noventa_auth import hash_password, verify_password`. Store the hash, never the password.
+#[pyfunction]
+fn hash_password(py: Python, password: &str) -> PyResult<String> {
+    // Hashing is slow on purpose; other Python threads get to run meanwhile.
+    py.detach(|| hash(password)).map_err(PyValueError::new_err)
+}
+
+#[pyfunction]
+fn verify_password(py: Python, password: &str, hash: &str) -> bool {
+    py.detach(|| verify(password, hash))
+}
+
+pub fn register_python_module(py: Python) -> PyResult<()> {
+    let module = PyModule::new(py, "noventa_auth")?;
+    module.add_function(wrap_pyfunction!(hash_password, &module)?)?;
+    module.add_function(wrap_pyfunction!(verify_password, &module)?)?;
+    py.import("sys")?.getattr("modules")?.set_item("noventa_auth", module)
+}
+
+// Runs `auth.user_loader` with the logged-in user's id. None without a loader.
+pub fn load_user(py: Python, id: &Bound<PyAny>) -> PyResult<Py<PyAny>> {
+    let Some((module, function)) = auth_config().and_then(|auth| auth.user_loader.as_deref()).and_then(|loader| loader.rsplit_once('.')) else {
+        return Ok(py.None());
+    };
+    Ok(py.import(module)?.getattr(function)?.call1((id,))?.unbind())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_login_redirect_keeps_the_page() {
+        use actix_web::test::TestRequest;
+
+        let req = TestRequest::get().uri("/account/orders?page=2").to_http_request();
+        assert_eq!(login_redirect(&req), "/login?next=%2Faccount%2Forders%3Fpage%3D2");
+    }
+
+    #[test]
+    fn test_parse_requirements() {
+        assert_eq!(
+            parse_requirements("role=admin, permission = reports.view").unwrap(),
+            vec![Requirement::Role("admin".to_string()), Requirement::Permission("reports.view".to_string())]
+        );
+        assert!(parse_requirements("admin").is_err());
+        assert!(parse_requirements("role=").is_err());
+    }
+
+    #[test]
+    fn test_meets_roles() {
+        let roles = vec!["editor".to_string()];
+        assert!(meets(&roles, &[Requirement::Role("editor".to_string())]));
+        assert!(!meets(&roles, &[Requirement::Role("editor".to_string()), Requirement::Role("admin".to_string())]));
+        assert!(meets(&[], &[]));
+    }
+
+    #[test]
+    fn test_granted() {
+        let permissions = HashMap::from([
+            ("editor".to_string(), vec!["posts.edit".to_string()]),
+            ("admin".to_string(), vec!["*".to_string()]),
+        ]);
+        assert!(granted(&permissions, &["editor".to_string()], "posts.edit"));
+        assert!(!granted(&permissions, &["editor".to_string()], "users.delete"));
+        assert!(granted(&permissions, &["viewer".to_string(), "admin".to_string()], "users.delete"));
+        assert!(!granted(&permissions, &[], "posts.edit"));
+    }
+
+    #[test]
+    fn test_claim_roles() {
+        let claims = |value: serde_json::Value| value.as_object().unwrap().clone();
+        assert_eq!(claim_roles(&claims(serde_json::json!({ "roles": ["admin", 3] }))), vec!["admin".to_string()]);
+        assert!(claim_roles(&claims(serde_json::json!({ "roles": "admin" }))).is_empty());
+    }
+
+    #[test]
+    fn test_hash_and_verify() {
+        let hash = hash("correct horse").unwrap();
+        assert!(hash.starts_with("$argon2id$"));
+        assert!(verify("correct horse", &hash));
+        assert!(!verify("battery staple", &hash));
+        assert!(!verify("correct horse", "not-a-hash"));
+    }
+}
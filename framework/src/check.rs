@@ -0,0 +1,528 @@
+use crate::components::{scan_project_components, Component, PACKS_DIR};
+use crate::dependencies::{StaticFiles, ASSET_REF_REGEX};
+use crate::paths::relative_name;
+use minijinja::Environment;
+use once_cell::sync::Lazy;
+use pyo3::prelude::*;
+use regex::Regex;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+static EXTENDS_TARGET_REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r#"\{%-?\s*extends\s+["']([^"']+)["']"#).unwrap());
+// `<form data-action="subscribe">` or `<input type="hidden" name="action" value="subscribe">`.
+static ACTION_REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r#"\sdata-action\s*=\s*["'](\w+)["']|name\s*=\s*["']action["'][^>]*?\bvalue\s*=\s*["'](\w+)["']"#).unwrap());
+
+// What every template can use besides minijinja's own globals.
+const NOVENTA_GLOBALS: &[&str] = &[
+    "component", "url_for", "url_for_signed", "srcset", "status", "csp_nonce", "page", "flashed_messages",
+    crate::template_filters::LOCALE_GLOBAL,
+];
+
+const TEMPLATE_DIRS: [&str; 3] = ["pages", "layouts", "components"];
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum IssueKind {
+    TemplateSyntax,
+    UnknownComponent,
+    MissingExtendsTarget,
+    MissingStaticAsset,
+    MissingAction,
+    UndefinedVariable,
+    PythonSyntax,
+    UnusedComponent,
+    UnusedLayout,
+}
+
+impl IssueKind {
+    fn label(&self) -> &'static str {
+        match self {
+            IssueKind::TemplateSyntax => "template syntax",
+            IssueKind::UnknownComponent => "unknown component",
+            IssueKind::MissingExtendsTarget => "missing layout",
+            IssueKind::MissingStaticAsset => "missing asset",
+            IssueKind::MissingAction => "missing action",
+            IssueKind::UndefinedVariable => "undefined variable",
+            IssueKind::PythonSyntax => "python syntax",
+            IssueKind::UnusedComponent => "unused component",
+            IssueKind::UnusedLayout => "unused layout",
+        }
+    }
+
+    // Dead code is worth knowing about, but it doesn't break anything, so it doesn't fail the check.
+    pub fn is_warning(&self) -> bool {
+        matches!(self, IssueKind::UnusedComponent | IssueKind::UnusedLayout)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CheckIssue {
+    pub kind: IssueKind,
+    pub file: String,
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct CheckReport {
+    pub templates_checked: usize,
+    pub python_files_checked: usize,
+    pub issues: Vec<CheckIssue>,
+}
+
+impl CheckReport {
+    pub fn is_ok(&self) -> bool {
+        self.issues.iter().all(|issue| issue.kind.is_warning())
+    }
+}
+
+fn files_with_extension(dir: &Path, extension: &str) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = WalkDir::new(dir)
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter(|e| e.path().is_file() && e.path().extension().and_then(|s| s.to_str()) == Some(extension))
+        .map(|e| e.into_path())
+        .collect();
+    files.sort();
+    files
+}
+
+
+fn line_of(source: &str, byte_offset: usize) -> usize {
+    source[..byte_offset].matches('\n').count() + 1
+}
+
+// The template's name and source, once it's been added to `env` (or why it couldn't be).
+fn compile_template(root: &Path, path: &Path, env: &mut Environment<'static>, issues: &mut Vec<CheckIssue>) -> Option<(String, String)> {
+    let name = relative_name(path, root);
+    let source = match std::fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(e) => {
+            issues.push(CheckIssue {
+                kind: IssueKind::TemplateSyntax,
+                file: name,
+                line: None,
+                message: format!("Couldn't read the template: {}", e),
+            });
+            return None;
+        }
+    };
+
+    if let Err(e) = env.add_template_owned(name.clone(), crate::fragments::translate(&crate::page_meta::template_source(&source))) {
+        issues.push(CheckIssue {
+            kind: IssueKind::TemplateSyntax,
+            file: name.clone(),
+            line: e.line(),
+            message: e.detail().unwrap_or("invalid template syntax").to_string(),
+        });
+    }
+    Some((name, source))
+}
+
+// Just the templates that don't compile, for /health/ready.
+pub fn template_syntax_issues(root: &Path) -> Vec<CheckIssue> {
+    let mut env = Environment::new();
+    minijinja_contrib::add_to_environment(&mut env);
+    let mut issues = Vec::new();
+    for dir in TEMPLATE_DIRS {
+        for path in files_with_extension(&root.join(dir), "html") {
+            compile_template(root, &path, &mut env, &mut issues);
+        }
+    }
+    issues
+}
+
+fn check_template(
+    root: &Path,
+    path: &Path,
+    components: &[Component],
+    static_files: Option<&StaticFiles>,
+    env: &mut Environment<'static>,
+    issues: &mut Vec<CheckIssue>,
+) {
+    let Some((name, source)) = compile_template(root, path, env, issues) else {
+        return;
+    };
+
+    // From the syntax tree, so a `component("…")` in a comment or a string isn't taken for a call.
+    // A template that doesn't parse was already reported above.
+    let compiled = crate::fragments::translate(&crate::page_meta::template_source(&source));
+    let calls = crate::template_ast::scan(&name, &compiled).map(|refs| refs.components).unwrap_or_default();
+    for call in calls {
+        let component_id = call.name.replace('.', "/");
+        if !components.iter().any(|c| c.id == component_id) {
+            issues.push(CheckIssue {
+                kind: IssueKind::UnknownComponent,
+                file: name.clone(),
+                line: Some(call.line),
+                message: format!("Component '{}' doesn't exist in the components folder", call.name),
+            });
+        }
+    }
+
+    for caps in EXTENDS_TARGET_REGEX.captures_iter(&source) {
+        let matched = caps.get(1).unwrap();
+        if !root.join(matched.as_str()).is_file() {
+            issues.push(CheckIssue {
+                kind: IssueKind::MissingExtendsTarget,
+                file: name.clone(),
+                line: Some(line_of(&source, matched.start())),
+                message: format!("The template extends '{}', but that file doesn't exist", matched.as_str()),
+            });
+        }
+    }
+
+    if let Some(static_files) = static_files {
+        let prefix = format!("{}/", static_files.url_prefix.trim_end_matches('/'));
+        for caps in ASSET_REF_REGEX.captures_iter(&source) {
+            let matched = caps.get(1).unwrap();
+            // noventa-static/ is Noventa's own, served from memory.
+            let Some(asset) = matched.as_str().strip_prefix(&prefix).filter(|asset| !asset.starts_with("noventa-static/")) else {
+                continue;
+            };
+            if !static_files.dir.join(asset).is_file() {
+                issues.push(CheckIssue {
+                    kind: IssueKind::MissingStaticAsset,
+                    file: name.clone(),
+                    line: Some(line_of(&source, matched.start())),
+                    message: format!("'{}' isn't in the static folder", matched.as_str()),
+                });
+            }
+        }
+    }
+
+    if let Some(component) = components.iter().find(|c| relative_name(Path::new(c.template_path.trim_start_matches("./")), root) == name) {
+        check_actions(root, &name, &source, component, issues);
+        if component.logic_path.is_none() {
+            check_undefined_variables(root, &name, &source, env, issues);
+        }
+    }
+}
+
+// Every action a component's forms post to needs an `action_<name>` in its logic file.
+fn check_actions(root: &Path, name: &str, source: &str, component: &Component, issues: &mut Vec<CheckIssue>) {
+    let functions = component.logic_path.as_deref().map(|logic_path| crate::lsp_index::python_functions(Path::new(logic_path))).unwrap_or_default();
+    for caps in ACTION_REGEX.captures_iter(source) {
+        let matched = caps.get(1).or_else(|| caps.get(2)).unwrap();
+        let function = format!("action_{}", matched.as_str());
+        if functions.iter().any(|f| f.name == function) {
+            continue;
+        }
+        let message = match &component.logic_path {
+            Some(logic_path) => format!(
+                "The form posts to '{}', but {} has no `{}`",
+                matched.as_str(),
+                relative_name(Path::new(logic_path.trim_start_matches("./")), root),
+                function
+            ),
+            None => format!("The form posts to '{}', but the component has no logic file to define `{}` in", matched.as_str(), function),
+        };
+        issues.push(CheckIssue { kind: IssueKind::MissingAction, file: name.to_string(), line: Some(line_of(source, matched.start())), message });
+    }
+}
+
+// A component without a logic file renders with nothing in its context, so whatever it uses has to
+// be a global. Context processors and template helpers add globals only Python knows about, so
+// projects with either aren't checked.
+fn check_undefined_variables(root: &Path, name: &str, source: &str, env: &Environment<'static>, issues: &mut Vec<CheckIssue>) {
+    if crate::context_processors::exists(root) || root.join(format!("{}.py", crate::template_helpers::MODULE)).is_file() {
+        return;
+    }
+    let Ok(template) = env.get_template(name) else {
+        return;
+    };
+    let globals: HashSet<&str> = env.globals().map(|(global, _)| global).chain(NOVENTA_GLOBALS.iter().copied()).collect();
+    let mut undefined: Vec<String> = template.undeclared_variables(false).into_iter().filter(|variable| !globals.contains(variable.as_str())).collect();
+    undefined.sort();
+    for variable in undefined {
+        let line = Regex::new(&format!(r"\b{}\b", regex::escape(&variable))).ok().and_then(|word| word.find(source)).map(|found| line_of(source, found.start()));
+        issues.push(CheckIssue {
+            kind: IssueKind::UndefinedVariable,
+            file: name.to_string(),
+            line,
+            message: format!("'{}' is never defined: the component has no logic file to load it, so it's always empty", variable),
+        });
+    }
+}
+
+// Components and layouts that no page reaches through its layouts, includes and component calls.
+fn check_unused(root: &Path, components: &[Component], issues: &mut Vec<CheckIssue>) {
+    let graph = crate::graph::build_graph(root);
+    for name in &graph.unused_components {
+        let id = name.replace('.', "/");
+        let component = components.iter().find(|c| c.id == id);
+        // A pack's components are there to be picked from; using only some of them is fine.
+        if component.is_some_and(|c| Path::new(&c.template_path).starts_with(root.join(PACKS_DIR))) {
+            continue;
+        }
+        let file = component
+            .map(|c| relative_name(Path::new(c.template_path.trim_start_matches("./")), root))
+            .unwrap_or_else(|| format!("components/{}", id));
+        issues.push(CheckIssue {
+            kind: IssueKind::UnusedComponent,
+            file,
+            line: None,
+            message: format!("No page uses the '{}' component", name),
+        });
+    }
+
+    let used: HashSet<&str> = graph.nodes.iter().map(|node| node.id.as_str()).collect();
+    for path in files_with_extension(&root.join("layouts"), "html") {
+        let name = relative_name(&path, root);
+        if !used.contains(name.as_str()) {
+            issues.push(CheckIssue {
+                kind: IssueKind::UnusedLayout,
+                file: name,
+                line: None,
+                message: "No page extends or includes this layout".to_string(),
+            });
+        }
+    }
+}
+
+fn check_python_file(root: &Path, path: &Path, issues: &mut Vec<CheckIssue>) {
+    let name = relative_name(path, root);
+    let source = match std::fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(e) => {
+            issues.push(CheckIssue {
+                kind: IssueKind::PythonSyntax,
+                file: name,
+                line: None,
+                message: format!("Couldn't read the file: {}", e),
+            });
+            return;
+        }
+    };
+
+    Python::attach(|py| {
+        let compiled = py
+            .import("builtins")
+            .and_then(|builtins| builtins.getattr("compile"))
+            .and_then(|compile| compile.call1((source.as_str(), name.as_str(), "exec")));
+        if let Err(e) = compiled {
+            let value = e.value(py);
+            let line = value.getattr("lineno").ok().and_then(|l| l.extract::<usize>().ok());
+            let message = value
+                .getattr("msg")
+                .ok()
+                .and_then(|m| m.extract::<String>().ok())
+                .unwrap_or_else(|| e.to_string());
+            issues.push(CheckIssue {
+                kind: IssueKind::PythonSyntax,
+                file: name,
+                line,
+                message,
+            });
+        }
+    });
+}
+
+// The static folder and the URL prefix it's served under. Without one there's nothing to check asset
+// links against.
+fn static_folder(root: &Path) -> Option<(String, PathBuf)> {
+    let config = crate::config::Config::from_file(&root.join("config.yaml").to_string_lossy()).ok()?;
+    let url_prefix = config.static_url_prefix.clone().unwrap_or_else(|| "/static".to_string());
+    let static_path = config.static_path?;
+    Some((url_prefix, root.join(static_path.trim_start_matches("./"))))
+}
+
+fn is_logic_file(path: &Path) -> bool {
+    path.file_name().and_then(|n| n.to_str()).is_some_and(|n| n.ends_with("_logic.py") || crate::actors::template_renderer::PAGE_LOGIC_FILES.contains(&n))
+}
+
+// Validates every template and logic file under `root` without starting the server.
+pub fn run_check(root: &Path) -> CheckReport {
+    let mut report = CheckReport::default();
+
+    let components: Vec<Component> = scan_project_components(root).unwrap_or_default();
+    let static_folder = static_folder(root);
+    let static_files = static_folder.as_ref().map(|(url_prefix, dir)| StaticFiles { url_prefix, dir: dir.clone() });
+
+    let mut env = Environment::new();
+    minijinja_contrib::add_to_environment(&mut env);
+    for dir in TEMPLATE_DIRS {
+        for path in files_with_extension(&root.join(dir), "html") {
+            check_template(root, &path, &components, static_files.as_ref(), &mut env, &mut report.issues);
+            report.templates_checked += 1;
+        }
+    }
+    check_unused(root, &components, &mut report.issues);
+
+    for dir in TEMPLATE_DIRS {
+        for path in files_with_extension(&root.join(dir), "py") {
+            if is_logic_file(&path) {
+                check_python_file(root, &path, &mut report.issues);
+                report.python_files_checked += 1;
+            }
+        }
+    }
+
+    report
+}
+
+// The same checks for a single template or logic file under `root`, as the editor saves it; what's
+// unused only shows up in `noventa check`.
+pub fn check_file(root: &Path, path: &Path) -> Vec<CheckIssue> {
+    let mut issues = Vec::new();
+    let Ok(relative) = path.strip_prefix(root) else {
+        return issues;
+    };
+    if !TEMPLATE_DIRS.iter().any(|dir| relative.starts_with(dir)) && !relative.starts_with(PACKS_DIR) {
+        return issues;
+    }
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("html") => {
+            let components: Vec<Component> = scan_project_components(root).unwrap_or_default();
+            let static_folder = static_folder(root);
+            let static_files = static_folder.as_ref().map(|(url_prefix, dir)| StaticFiles { url_prefix, dir: dir.clone() });
+            let mut env = Environment::new();
+            minijinja_contrib::add_to_environment(&mut env);
+            check_template(root, path, &components, static_files.as_ref(), &mut env, &mut issues);
+        }
+        Some("py") if is_logic_file(path) => check_python_file(root, path, &mut issues),
+        _ => {}
+    }
+    issues
+}
+
+pub fn print_report(report: &CheckReport) {
+    for issue in &report.issues {
+        let location = match issue.line {
+            Some(line) => format!("{}:{}", issue.file, line),
+            None => issue.file.clone(),
+        };
+        let mark = if issue.kind.is_warning() { "⚠" } else { "✗" };
+        println!("{} {} [{}] {}", mark, location, issue.kind.label(), issue.message);
+    }
+    let warnings = report.issues.iter().filter(|issue| issue.kind.is_warning()).count();
+    let problems = report.issues.len() - warnings;
+
+    println!(
+        "\nChecked {} templates and {} Python files.",
+        report.templates_checked, report.python_files_checked
+    );
+    match (problems, warnings) {
+        (0, 0) => println!("✨ No problems found. Happy coding!"),
+        (0, _) => println!("✨ No problems found, but {} thing(s) look unused.", warnings),
+        _ => println!("Found {} problem(s) and {} unused thing(s).", problems, warnings),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    fn write(root: &Path, relative: &str, content: &str) {
+        let path = root.join(relative);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    #[test]
+    fn test_clean_project_has_no_issues() {
+        let dir = tempdir().unwrap();
+        write(dir.path(), "layouts/base.html", "<html>{% block content %}{% endblock %}</html>");
+        write(dir.path(), "pages/index.html", "{% extends \"layouts/base.html\" %}{% block content %}{{ component('cards.pricing') }}{% endblock %}");
+        write(dir.path(), "components/cards/pricing/pricing_template.html", "<div>{{ price }}</div>");
+        write(dir.path(), "components/cards/pricing/pricing_logic.py", "def load_template_context(request):\n    return {}\n");
+
+        let report = run_check(dir.path());
+        assert!(report.is_ok(), "unexpected issues: {:?}", report.issues);
+        assert_eq!(report.templates_checked, 3);
+        assert_eq!(report.python_files_checked, 1);
+    }
+
+    #[test]
+    fn test_reports_every_kind_of_problem() {
+        let dir = tempdir().unwrap();
+        write(dir.path(), "pages/broken.html", "{% if %}");
+        write(dir.path(), "pages/missing.html", "{% extends \"layouts/nope.html\" %}\n{{ component('ghost') }}");
+        write(dir.path(), "pages/commented.html", "{# {{ component('retired') }} #}\n<p>\"component('quoted')\"</p>");
+        write(dir.path(), "components/widget/widget_template.html", "<p></p>");
+        write(dir.path(), "components/widget/widget_logic.py", "def load_template_context(:\n");
+
+        let report = run_check(dir.path());
+        let kinds: Vec<IssueKind> = report.issues.iter().map(|i| i.kind.clone()).collect();
+        assert!(kinds.contains(&IssueKind::TemplateSyntax));
+        assert!(kinds.contains(&IssueKind::MissingExtendsTarget));
+        assert!(kinds.contains(&IssueKind::UnknownComponent));
+        assert!(kinds.contains(&IssueKind::PythonSyntax));
+
+        let unknown = report.issues.iter().find(|i| i.kind == IssueKind::UnknownComponent).unwrap();
+        assert_eq!(unknown.file, "pages/missing.html");
+        assert_eq!(unknown.line, Some(2));
+        // Only real calls count, not ones in comments or text.
+        assert!(!report.issues.iter().any(|i| i.file == "pages/commented.html"));
+    }
+
+    #[test]
+    fn test_reports_unused_code_and_missing_assets() {
+        let dir = tempdir().unwrap();
+        write(dir.path(), "config.yaml", "static_path: \"./files\"\nstatic_url_prefix: \"/files\"\n");
+        write(dir.path(), "files/site.css", "");
+        write(dir.path(), "layouts/base.html", "<link href=\"/files/site.css\">\n<script src=\"/files/app.js\"></script>{% block content %}{% endblock %}");
+        write(dir.path(), "layouts/old.html", "{% block content %}{% endblock %}");
+        write(dir.path(), "pages/index.html", "{% extends \"layouts/base.html\" %}{% block content %}{{ component('menu') }}{% endblock %}");
+        write(dir.path(), "components/menu/menu_template.html", "<nav></nav>");
+        write(dir.path(), "components/legacy/legacy_template.html", "<p></p>");
+
+        let report = run_check(dir.path());
+        let issue = |kind: IssueKind| report.issues.iter().filter(|i| i.kind == kind).map(|i| (i.file.as_str(), i.line)).collect::<Vec<_>>();
+        assert_eq!(issue(IssueKind::MissingStaticAsset), vec![("layouts/base.html", Some(2))]);
+        assert_eq!(issue(IssueKind::UnusedComponent), vec![("components/legacy/legacy_template.html", None)]);
+        assert_eq!(issue(IssueKind::UnusedLayout), vec![("layouts/old.html", None)]);
+        assert!(!report.is_ok());
+
+        write(dir.path(), "files/app.js", "");
+        assert!(run_check(dir.path()).is_ok(), "unused code alone shouldn't fail the check");
+    }
+
+    #[test]
+    fn test_reports_missing_actions_and_undefined_variables() {
+        let dir = tempdir().unwrap();
+        write(dir.path(), "pages/index.html", "{{ component('newsletter') }}{{ component('banner') }}");
+        write(
+            dir.path(),
+            "components/newsletter/newsletter_template.html",
+            "<form data-action=\"subscribe\"></form>\n<form><input type=\"hidden\" name=\"action\" value=\"unsubscribe\"></form>",
+        );
+        write(dir.path(), "components/newsletter/newsletter_logic.py", "def load_template_context(request):\n    return {}\n\ndef action_subscribe(request):\n    pass\n");
+        write(
+            dir.path(),
+            "components/banner/banner_template.html",
+            "{% set tone = 'info' %}<p class=\"{{ tone }}\">{{ url_for('index') }}</p>\n{% for item in range(3) %}{{ loop.index }}{% endfor %}\n<h1>{{ headline }}</h1>",
+        );
+
+        let report = run_check(dir.path());
+        let issue = |kind: IssueKind| report.issues.iter().filter(|i| i.kind == kind).map(|i| (i.file.as_str(), i.line, i.message.as_str())).collect::<Vec<_>>();
+        assert_eq!(
+            issue(IssueKind::MissingAction),
+            vec![(
+                "components/newsletter/newsletter_template.html",
+                Some(2),
+                "The form posts to 'unsubscribe', but components/newsletter/newsletter_logic.py has no `action_unsubscribe`"
+            )]
+        );
+        let undefined = issue(IssueKind::UndefinedVariable);
+        assert_eq!(undefined.len(), 1, "{:?}", undefined);
+        assert_eq!((undefined[0].0, undefined[0].1), ("components/banner/banner_template.html", Some(3)));
+        assert!(!report.is_ok());
+
+        // One file at a time, for the editor.
+        let issues = check_file(dir.path(), &dir.path().join("components/banner/banner_template.html"));
+        assert_eq!(issues.iter().map(|i| i.kind.clone()).collect::<Vec<_>>(), [IssueKind::UndefinedVariable]);
+        assert!(check_file(dir.path(), &dir.path().join("pages/index.html")).is_empty());
+        assert!(check_file(dir.path(), &dir.path().join("config.yaml")).is_empty());
+
+        // Context processors can add anything, so nothing is called undefined then.
+        write(dir.path(), "context_processors.py", "def site(request):\n    return {'headline': 'Hi'}\n");
+        assert!(check_file(dir.path(), &dir.path().join("components/banner/banner_template.html")).is_empty());
+    }
+}
@@ -0,0 +1,239 @@
+use crate::actors::router::RouterActor;
+use crate::config::{self, ClusterConfig};
+use crate::user_channels::{UserEvent, USER_CHANNEL};
+use actix::Addr;
+use deadpool_redis::redis::{self, AsyncCommands};
+use deadpool_redis::{Pool, Runtime};
+use futures_util::StreamExt;
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+const DEFAULT_CHANNEL: &str = "noventa";
+const REDIS_TIMEOUT: Duration = Duration::from_millis(500);
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(30);
+
+lazy_static! {
+    // Set when config.yaml has a `cluster` section; None means every instance keeps to itself.
+    pub static ref CLUSTER: Option<Cluster> = config::CONFIG.cluster.as_ref().and_then(|cluster| {
+        Cluster::new(cluster)
+            .map_err(|e| log::error!("Oh no! The cluster Redis at `cluster.redis_url` can't be used: {}", e))
+            .ok()
+    });
+}
+
+// The server's runtime, for broadcasts made from threads without one, like the Python interpreters'.
+static RUNTIME: OnceLock<tokio::runtime::Handle> = OnceLock::new();
+
+// Something one instance did that every other instance should do too.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum ClusterEvent {
+    ReloadPages,
+    ClearCaches,
+    Maintenance { enabled: bool },
+    // What `broadcast("user:<id>", ...)` sent, for that user's WebSockets on every instance.
+    UserMessage { user: String, message: String },
+    // A login ended; its WebSockets close wherever they're connected.
+    UserLoggedOut { login: String },
+}
+
+impl From<UserEvent> for ClusterEvent {
+    fn from(event: UserEvent) -> Self {
+        match event {
+            UserEvent::Message { user, message } => ClusterEvent::UserMessage { user, message },
+            UserEvent::LoggedOut { login } => ClusterEvent::UserLoggedOut { login },
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct Envelope {
+    // The instance that sent it, which already applied the event and skips its own message.
+    origin: String,
+    #[serde(flatten)]
+    event: ClusterEvent,
+}
+
+pub struct Cluster {
+    pool: Pool,
+    client: redis::Client,
+    prefix: String,
+    instance_id: String,
+}
+
+impl Cluster {
+    fn new(config: &ClusterConfig) -> Result<Self, String> {
+        let mut redis_config = deadpool_redis::Config::from_url(&config.redis_url);
+        // Requests wait on Redis for rate limits, so an unreachable one must fail fast.
+        redis_config.pool = Some(deadpool_redis::PoolConfig {
+            timeouts: deadpool_redis::Timeouts {
+                wait: Some(REDIS_TIMEOUT),
+                create: Some(REDIS_TIMEOUT),
+                recycle: Some(REDIS_TIMEOUT),
+            },
+            ..Default::default()
+        });
+        let pool = redis_config.create_pool(Some(Runtime::Tokio1)).map_err(|e| e.to_string())?;
+        let client = redis::Client::open(config.redis_url.as_str()).map_err(|e| e.to_string())?;
+        Ok(Self {
+            pool,
+            client,
+            prefix: config.channel.clone().unwrap_or_else(|| DEFAULT_CHANNEL.to_string()),
+            instance_id: uuid::Uuid::new_v4().to_string(),
+        })
+    }
+
+    pub fn pool(&self) -> &Pool {
+        &self.pool
+    }
+
+    // Every key this app stores in Redis starts with the channel name.
+    pub fn key(&self, suffix: &str) -> String {
+        format!("{}:{}", self.prefix, suffix)
+    }
+
+    fn events_channel(&self) -> String {
+        self.key("events")
+    }
+}
+
+fn apply(event: &ClusterEvent, router: Option<&Addr<RouterActor>>) {
+    match event {
+        ClusterEvent::ReloadPages => match router {
+            Some(router) => crate::routing::reload_pages(router),
+            None => {
+                crate::actors::template_renderer::invalidate_pages();
+                crate::page_meta::clear();
+                crate::reverse_routes::clear();
+            }
+        },
+        ClusterEvent::ClearCaches => {
+            crate::actors::template_renderer::invalidate_pages();
+            crate::page_meta::clear();
+            crate::reverse_routes::clear();
+            crate::compressed_pages::clear();
+        }
+        ClusterEvent::Maintenance { enabled } => crate::admin::set_maintenance(*enabled),
+        // Without receivers nobody is connected here, which is fine.
+        ClusterEvent::UserMessage { user, message } => {
+            let _ = USER_CHANNEL.send(UserEvent::Message { user: user.clone(), message: message.clone() });
+        }
+        ClusterEvent::UserLoggedOut { login } => {
+            let _ = USER_CHANNEL.send(UserEvent::LoggedOut { login: login.clone() });
+        }
+    }
+}
+
+// Tells the other instances about `event`. The caller has already applied it here.
+pub fn broadcast(event: ClusterEvent) {
+    let Some(cluster) = CLUSTER.as_ref() else {
+        return;
+    };
+    let envelope = Envelope { origin: cluster.instance_id.clone(), event };
+    let payload = serde_json::to_string(&envelope).unwrap_or_default();
+    let Some(runtime) = tokio::runtime::Handle::try_current().ok().or_else(|| RUNTIME.get().cloned()) else {
+        log::warn!("Couldn't tell the other instances about {:?}: the server isn't running yet.", envelope.event);
+        return;
+    };
+    runtime.spawn(async move {
+        let published = async {
+            let mut connection = cluster.pool.get().await.map_err(|e| e.to_string())?;
+            connection
+                .publish::<_, _, ()>(cluster.events_channel(), payload)
+                .await
+                .map_err(|e| e.to_string())
+        };
+        if let Err(e) = published.await {
+            log::warn!("Couldn't tell the other instances about {:?} through Redis: {}", envelope.event, e);
+        }
+    });
+}
+
+// Applies what the other instances broadcast, reconnecting whenever Redis goes away.
+pub fn listen(router: Option<Addr<RouterActor>>) {
+    let Some(cluster) = CLUSTER.as_ref() else {
+        return;
+    };
+    let _ = RUNTIME.set(tokio::runtime::Handle::current());
+    actix_rt::spawn(async move {
+        let mut delay = Duration::from_secs(1);
+        loop {
+            match subscribe(cluster, router.as_ref()).await {
+                Ok(()) => {
+                    delay = Duration::from_secs(1);
+                    log::warn!("The Redis connection for cluster events closed. Reconnecting...");
+                }
+                Err(e) => log::warn!(
+                    "Couldn't listen for cluster events on Redis: {}. Retrying in {}s.",
+                    e,
+                    delay.as_secs()
+                ),
+            }
+            tokio::time::sleep(delay).await;
+            delay = (delay * 2).min(MAX_RECONNECT_DELAY);
+        }
+    });
+}
+
+async fn subscribe(cluster: &Cluster, router: Option<&Addr<RouterActor>>) -> redis::RedisResult<()> {
+    let mut pubsub = cluster.client.get_async_pubsub().await?;
+    pubsub.subscribe(cluster.events_channel()).await?;
+    log::info!("✨ Listening for cluster events on Redis channel '{}'.", cluster.events_channel());
+
+    let mut messages = pubsub.on_message();
+    while let Some(message) = messages.next().await {
+        let payload: String = message.get_payload()?;
+        match serde_json::from_str::<Envelope>(&payload) {
+            Ok(envelope) if envelope.origin == cluster.instance_id => {}
+            Ok(envelope) => {
+                // User broadcasts are app traffic, and their payloads aren't for the logs.
+                if !matches!(envelope.event, ClusterEvent::UserMessage { .. } | ClusterEvent::UserLoggedOut { .. }) {
+                    log::info!("Another instance asked for {:?}.", envelope.event);
+                }
+                apply(&envelope.event, router);
+            }
+            Err(e) => log::warn!("Ignoring a cluster event we don't understand ({}): {}", e, payload),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_envelope_format() {
+        let envelope = Envelope { origin: "a".to_string(), event: ClusterEvent::Maintenance { enabled: true } };
+        let json = serde_json::to_string(&envelope).unwrap();
+        assert_eq!(json, r#"{"origin":"a","event":"maintenance","enabled":true}"#);
+        assert_eq!(serde_json::from_str::<Envelope>(&json).unwrap(), envelope);
+
+        let reload: Envelope = serde_json::from_str(r#"{"origin":"b","event":"reload_pages"}"#).unwrap();
+        assert_eq!(reload.event, ClusterEvent::ReloadPages);
+
+        let logout = Envelope { origin: "c".to_string(), event: UserEvent::LoggedOut { login: "abc".to_string() }.into() };
+        let json = serde_json::to_string(&logout).unwrap();
+        assert_eq!(json, r#"{"origin":"c","event":"user_logged_out","login":"abc"}"#);
+        assert_eq!(serde_json::from_str::<Envelope>(&json).unwrap(), logout);
+    }
+
+    #[test]
+    fn test_user_events_reach_this_instances_sockets() {
+        let mut received = USER_CHANNEL.subscribe();
+        apply(&ClusterEvent::UserMessage { user: "cluster-test".to_string(), message: "{}".to_string() }, None);
+        // Other tests broadcast too; only this one's message matters.
+        assert!(std::iter::from_fn(|| received.try_recv().ok())
+            .any(|event| event == UserEvent::Message { user: "cluster-test".to_string(), message: "{}".to_string() }));
+    }
+
+    #[test]
+    fn test_keys_use_the_channel() {
+        let config = ClusterConfig { redis_url: "redis://127.0.0.1:6379".to_string(), channel: Some("shop".to_string()) };
+        let cluster = Cluster::new(&config).unwrap();
+        assert_eq!(cluster.key("ratelimit:1.2.3.4"), "shop:ratelimit:1.2.3.4");
+        assert_eq!(cluster.events_channel(), "shop:events");
+    }
+}
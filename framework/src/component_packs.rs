@@ -0,0 +1,172 @@
+use crate::components::{scan_components, PACKS_DIR};
+use serde::Deserialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+// `noventa add component-pack ./ui-kit` or `... https://github.com/acme/ui-kit.git`: copies a folder of
+// components into component_packs/<namespace>/, where they're called as `component('ui.button')`.
+// The pack's `static/` folder goes into the project's static folder under `packs/<namespace>/`.
+
+// An optional `pack.yaml` at the root of a pack, naming the namespace it wants.
+const MANIFEST: &str = "pack.yaml";
+
+#[derive(Deserialize, Default)]
+struct Manifest {
+    name: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct InstalledPack {
+    pub namespace: String,
+    pub components: Vec<String>,
+    // Where its static files went, relative to the project, if it had any.
+    pub assets: Option<PathBuf>,
+}
+
+fn is_git_url(source: &str) -> bool {
+    source.starts_with("https://") || source.starts_with("http://") || source.starts_with("git@") || source.starts_with("ssh://") || source.ends_with(".git")
+}
+
+// `ui-kit.git` -> `ui_kit`: namespaces end up in Python module paths.
+fn namespace_from(name: &str) -> String {
+    name.trim_end_matches('/').trim_end_matches(".git").rsplit(['/', ':']).next().unwrap_or_default().replace(['-', '.'], "_").to_lowercase()
+}
+
+fn is_valid_namespace(namespace: &str) -> bool {
+    namespace.chars().next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_') && namespace.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+// Everything but the pack's git history and its static files, which are collected separately.
+fn copy_pack(src: &Path, dst: &Path, top_level: bool) -> std::io::Result<()> {
+    fs::create_dir_all(dst)?;
+    for entry in fs::read_dir(src)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        if name == ".git" || name == "__pycache__" || (top_level && name == "static") {
+            continue;
+        }
+        if entry.file_type()?.is_dir() {
+            copy_pack(&entry.path(), &dst.join(&name), false)?;
+        } else {
+            fs::copy(entry.path(), dst.join(&name))?;
+        }
+    }
+    Ok(())
+}
+
+fn static_dir(root: &Path) -> PathBuf {
+    let configured = crate::config::Config::from_file(&root.join("config.yaml").to_string_lossy()).ok().and_then(|config| config.static_path);
+    root.join(configured.as_deref().unwrap_or("static").trim_start_matches("./"))
+}
+
+pub fn install(root: &Path, source: &str, name: Option<&str>) -> Result<InstalledPack, String> {
+    let cloned = is_git_url(source).then(|| std::env::temp_dir().join(format!("noventa-pack-{}", uuid::Uuid::new_v4().simple())));
+    let pack_dir = match &cloned {
+        Some(checkout) => {
+            let status = Command::new("git")
+                .args(["clone", "--depth", "1", "--quiet", source])
+                .arg(checkout)
+                .status()
+                .map_err(|e| format!("couldn't run git: {}", e))?;
+            if !status.success() {
+                return Err(format!("git couldn't clone {}", source));
+            }
+            checkout.clone()
+        }
+        None => PathBuf::from(source),
+    };
+    let installed = install_from(root, &pack_dir, source, name);
+    if let Some(checkout) = cloned {
+        let _ = fs::remove_dir_all(checkout);
+    }
+    installed
+}
+
+// The namespace is the one passed with --name, else the one in pack.yaml, else the source's name.
+fn install_from(root: &Path, pack_dir: &Path, source: &str, name: Option<&str>) -> Result<InstalledPack, String> {
+    if !pack_dir.is_dir() {
+        return Err(format!("{} isn't a folder", pack_dir.display()));
+    }
+    let manifest: Manifest = match fs::read_to_string(pack_dir.join(MANIFEST)) {
+        Ok(source) => serde_yaml::from_str(&source).map_err(|e| format!("its {} is invalid: {}", MANIFEST, e))?,
+        Err(_) => Manifest::default(),
+    };
+    let namespace = name.map(str::to_string).or(manifest.name).unwrap_or_else(|| namespace_from(source));
+    if !is_valid_namespace(&namespace) {
+        return Err(format!("'{}' can't be a namespace; pass one with --name (letters, digits and _)", namespace));
+    }
+    let components: Vec<String> = scan_components(pack_dir).map_err(|e| e.to_string())?.into_iter().map(|c| c.id).filter(|id| !id.is_empty() && id != "static" && !id.starts_with("static/")).collect();
+    if components.is_empty() {
+        return Err(format!("{} has no components in it", pack_dir.display()));
+    }
+
+    // Installing again replaces the old version.
+    let target = root.join(PACKS_DIR).join(&namespace);
+    if target.exists() {
+        fs::remove_dir_all(&target).map_err(|e| e.to_string())?;
+    }
+    copy_pack(pack_dir, &target, true).map_err(|e| e.to_string())?;
+
+    let assets = if pack_dir.join("static").is_dir() {
+        let assets = static_dir(root).join("packs").join(&namespace);
+        if assets.exists() {
+            fs::remove_dir_all(&assets).map_err(|e| e.to_string())?;
+        }
+        copy_pack(&pack_dir.join("static"), &assets, false).map_err(|e| e.to_string())?;
+        Some(assets.strip_prefix(root).map(Path::to_path_buf).unwrap_or(assets))
+    } else {
+        None
+    };
+
+    let mut components: Vec<String> = components.into_iter().map(|id| format!("{}.{}", namespace, id.replace('/', "."))).collect();
+    components.sort();
+    Ok(InstalledPack { namespace, components, assets })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn write(root: &Path, relative: &str, content: &str) {
+        let path = root.join(relative);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    #[test]
+    fn test_install_from_folder() {
+        let pack = tempdir().unwrap();
+        write(pack.path(), "button/button_template.html", "<button>{{ label }}</button>");
+        write(pack.path(), "forms/input/input_template.html", "<input>");
+        write(pack.path(), "forms/input/input_logic.py", "def load_template_context(request, **props):\n    return props\n");
+        write(pack.path(), "static/ui.css", "button {}");
+        write(pack.path(), ".git/HEAD", "ref: refs/heads/main");
+
+        let project = tempdir().unwrap();
+        write(project.path(), "config.yaml", "static_path: \"./files\"\n");
+        let installed = install_from(project.path(), pack.path(), "../ui-kit", None).unwrap();
+        assert_eq!(installed.namespace, "ui_kit");
+        assert_eq!(installed.components, vec!["ui_kit.button", "ui_kit.forms.input"]);
+        assert_eq!(installed.assets, Some(PathBuf::from("files/packs/ui_kit")));
+        assert!(project.path().join("files/packs/ui_kit/ui.css").is_file());
+        assert!(!project.path().join("component_packs/ui_kit/static").exists());
+        assert!(!project.path().join("component_packs/ui_kit/.git").exists());
+
+        let mut ids: Vec<String> = crate::components::scan_project_components(project.path()).unwrap().into_iter().map(|c| c.id).collect();
+        ids.sort();
+        assert_eq!(ids, vec!["ui_kit/button", "ui_kit/forms/input"]);
+
+        assert!(install_from(project.path(), pack.path(), "../ui-kit", Some("ui-kit")).is_err());
+        write(pack.path(), "pack.yaml", "name: ui\n");
+        assert_eq!(install_from(project.path(), pack.path(), "../ui-kit", None).unwrap().namespace, "ui");
+    }
+
+    #[test]
+    fn test_namespace_from() {
+        assert_eq!(namespace_from("https://github.com/acme/ui-kit.git"), "ui_kit");
+        assert_eq!(namespace_from("git@github.com:acme/Charts.git"), "charts");
+        assert_eq!(namespace_from("../packs/forms/"), "forms");
+    }
+}
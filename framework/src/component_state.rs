@@ -0,0 +1,164 @@
+use crate::actors::session_manager::{SessionManagerActor, SetDefaultSessionValue};
+use crate::config::{self, StateBackend};
+use actix::Addr;
+use dashmap::DashMap;
+use deadpool_redis::redis::{self, Commands};
+use lazy_static::lazy_static;
+use serde_json::Value;
+use std::cell::RefCell;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+// A component keeps state between requests by taking a `state` argument:
+//
+//     def action_increment(request, state):
+//         state["count"] = state.get("count", 0) + 1
+//
+// It's a dict kept on the server for each instance of the component and each visitor, so a counter or
+// the step of a wizard survives re-renders without hidden inputs.
+
+// The session key holding the id a visitor's states are kept under.
+const SESSION_KEY: &str = "_noventa_state";
+const DEFAULT_TTL_SECS: u64 = 24 * 60 * 60;
+const DEFAULT_PREFIX: &str = "noventa";
+const REDIS_TIMEOUT: Duration = Duration::from_millis(500);
+// Past this many states, the expired ones are dropped.
+const MEMORY_CLEANUP_THRESHOLD: usize = 10_000;
+
+enum Store {
+    Memory(DashMap<String, (Instant, Value)>),
+    Redis { client: redis::Client, prefix: String },
+}
+
+lazy_static! {
+    static ref STORE: Store = open_store();
+}
+
+thread_local! {
+    // Each interpreter thread keeps its connection instead of opening one per call.
+    static CONNECTION: RefCell<Option<redis::Connection>> = const { RefCell::new(None) };
+}
+
+// So a Redis outage is logged once, not on every call.
+static REDIS_FAILING: AtomicBool = AtomicBool::new(false);
+
+fn open_store() -> Store {
+    let settings = config::CONFIG.component_state.clone().unwrap_or_default();
+    let cluster = config::CONFIG.cluster.as_ref();
+    let backend = settings.backend.unwrap_or(if cluster.is_some() { StateBackend::Redis } else { StateBackend::Memory });
+    if backend == StateBackend::Memory {
+        return Store::Memory(DashMap::new());
+    }
+    let url = settings.redis_url.or_else(|| cluster.map(|cluster| cluster.redis_url.clone())).unwrap_or_default();
+    match redis::Client::open(url.as_str()) {
+        Ok(client) => Store::Redis {
+            client,
+            prefix: cluster.and_then(|cluster| cluster.channel.clone()).unwrap_or_else(|| DEFAULT_PREFIX.to_string()),
+        },
+        Err(e) => {
+            log::error!("Oh no! The Redis for `component_state` can't be used ({}), so states are kept in memory.", e);
+            Store::Memory(DashMap::new())
+        }
+    }
+}
+
+fn ttl() -> Duration {
+    Duration::from_secs(config::CONFIG.component_state.as_ref().and_then(|state| state.ttl_secs).unwrap_or(DEFAULT_TTL_SECS))
+}
+
+fn with_connection<T>(client: &redis::Client, command: impl FnOnce(&mut redis::Connection) -> redis::RedisResult<T>) -> Option<T> {
+    CONNECTION.with(|connection| {
+        let mut connection = connection.borrow_mut();
+        if connection.is_none() {
+            *connection = client.get_connection_with_timeout(REDIS_TIMEOUT).map_err(|e| report_redis(&e)).ok();
+        }
+        let result = command(connection.as_mut()?);
+        match result {
+            Ok(value) => {
+                if REDIS_FAILING.swap(false, Ordering::SeqCst) {
+                    log::info!("✨ Redis is back; component states are kept there again.");
+                }
+                Some(value)
+            }
+            Err(e) => {
+                // Opened again on the next call.
+                *connection = None;
+                report_redis(&e);
+                None
+            }
+        }
+    })
+}
+
+fn report_redis(e: &redis::RedisError) {
+    if !REDIS_FAILING.swap(true, Ordering::SeqCst) {
+        log::warn!("Component states can't reach Redis ({}), so components start from an empty `state` for now.", e);
+    }
+}
+
+// The id this visitor's states are kept under, given to their session the first time it's needed.
+// Components loading at the same time on a visitor's first request all get the one that was given.
+pub fn visitor_id(session_manager: &Addr<SessionManagerActor>) -> Option<String> {
+    let id = Value::String(uuid::Uuid::new_v4().simple().to_string());
+    let id = futures::executor::block_on(session_manager.send(SetDefaultSessionValue { key: SESSION_KEY.to_string(), value: id })).ok()?.ok()?;
+    id.as_str().map(str::to_string)
+}
+
+// `instance` is the component and which of its instances, e.g. `counter:1a2b3c4d-0`.
+pub fn key(visitor: &str, instance: &str) -> String {
+    format!("{}:{}", visitor, instance)
+}
+
+// What the instance's state was left at, or an empty dict.
+pub fn load(key: &str) -> Value {
+    let state = match &*STORE {
+        Store::Memory(states) => states.get(key).filter(|entry| entry.0 > Instant::now()).map(|entry| entry.1.clone()),
+        Store::Redis { client, prefix } => {
+            let stored: Option<Option<String>> = with_connection(client, |connection| connection.get(format!("{}:state:{}", prefix, key)));
+            stored.flatten().and_then(|json| serde_json::from_str(&json).ok())
+        }
+    };
+    state.unwrap_or_else(|| Value::Object(Default::default()))
+}
+
+// Keeps `state` for another `ttl_secs`. An emptied state is removed.
+pub fn save(key: &str, state: &Value) {
+    let emptied = state.as_object().is_some_and(|state| state.is_empty());
+    match &*STORE {
+        Store::Memory(states) => {
+            if emptied {
+                states.remove(key);
+                return;
+            }
+            if states.len() > MEMORY_CLEANUP_THRESHOLD {
+                let now = Instant::now();
+                states.retain(|_, (expires, _)| *expires > now);
+            }
+            states.insert(key.to_string(), (Instant::now() + ttl(), state.clone()));
+        }
+        Store::Redis { client, prefix } => {
+            let redis_key = format!("{}:state:{}", prefix, key);
+            let _: Option<()> = if emptied {
+                with_connection(client, |connection| connection.del(&redis_key))
+            } else {
+                with_connection(client, |connection| connection.set_ex(&redis_key, state.to_string(), ttl().as_secs()))
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_memory_state() {
+        let key = key("visitor", "counter:1a2b3c4d-0");
+        assert_eq!(load(&key), serde_json::json!({}));
+        save(&key, &serde_json::json!({ "count": 2 }));
+        assert_eq!(load(&key), serde_json::json!({ "count": 2 }));
+        assert_eq!(load("visitor:counter:1a2b3c4d-1"), serde_json::json!({}));
+        save(&key, &serde_json::json!({}));
+        assert_eq!(load(&key), serde_json::json!({}));
+    }
+}
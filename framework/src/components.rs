@@ -1,3 +1,4 @@
+use crate::paths;
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 use walkdir::WalkDir;
@@ -8,10 +9,50 @@ pub struct Component {
     pub logic_path: Option<String>,
     pub template_path: String,
     pub template_content: String,
+    // From the component's `component.yaml`, if it has one.
+    pub props: Option<Vec<crate::props::Prop>>,
+}
+
+fn load_props(path: &Path) -> Option<Vec<crate::props::Prop>> {
+    match crate::props::load(path) {
+        Ok(props) => Some(props),
+        Err(e) => {
+            log::warn!("Heads up! We couldn't read {}: {}. The component's props won't be checked.", path.display(), e);
+            None
+        }
+    }
+}
+
+// Where `noventa add component-pack` installs packs, each in a folder named after its namespace.
+pub const PACKS_DIR: &str = "component_packs";
+
+// The project's own components and those of every installed pack: `ui/button` is the `button` of the
+// `ui` pack. The project's own component wins when both have one by the same name.
+pub fn scan_project_components(root: &Path) -> std::io::Result<Vec<Component>> {
+    let mut components = scan_components(&root.join("components"))?;
+    let Ok(entries) = std::fs::read_dir(root.join(PACKS_DIR)) else {
+        return Ok(components);
+    };
+    let mut packs: Vec<PathBuf> = entries.filter_map(Result::ok).map(|entry| entry.path()).filter(|path| path.is_dir()).collect();
+    packs.sort();
+    for pack in packs {
+        let Some(namespace) = pack.file_name().and_then(|name| name.to_str()).map(str::to_string) else {
+            continue;
+        };
+        for mut component in scan_components(&pack)? {
+            component.id = format!("{}/{}", namespace, component.id).trim_end_matches('/').to_string();
+            if components.iter().any(|own| own.id == component.id) {
+                log::warn!("Heads up! components/{} has the same name as a component of the {} pack, so the pack's isn't used.", component.id, namespace);
+                continue;
+            }
+            components.push(component);
+        }
+    }
+    Ok(components)
 }
 
 pub fn scan_components(dir: &Path) -> std::io::Result<Vec<Component>> {
-    let mut components_map: HashMap<String, (Option<PathBuf>, Option<(PathBuf, String)>)> = HashMap::new();
+    let mut components_map: HashMap<String, (Option<PathBuf>, Option<(PathBuf, String)>, Option<PathBuf>)> = HashMap::new();
 
     for entry in WalkDir::new(dir).into_iter().filter_map(Result::ok) {
         let path = entry.path();
@@ -19,7 +60,7 @@ pub fn scan_components(dir: &Path) -> std::io::Result<Vec<Component>> {
             let file_name = path.file_name().unwrap().to_string_lossy();
             let parent_dir = path.parent().unwrap();
             let component_name = parent_dir.strip_prefix(dir).unwrap();
-            let component_id = component_name.to_string_lossy().to_string();
+            let component_id = paths::to_slash(component_name);
 
             if file_name.ends_with("_logic.py") {
                 let entry = components_map.entry(component_id).or_default();
@@ -28,18 +69,22 @@ pub fn scan_components(dir: &Path) -> std::io::Result<Vec<Component>> {
                 let content = std::fs::read_to_string(path)?;
                 let entry = components_map.entry(component_id).or_default();
                 entry.1 = Some((path.to_path_buf(), content));
+            } else if file_name == crate::props::FILE {
+                let entry = components_map.entry(component_id).or_default();
+                entry.2 = Some(path.to_path_buf());
             }
         }
     }
 
     let components = components_map
         .into_iter()
-        .filter_map(|(id, (logic_path, template_data))| {
+        .filter_map(|(id, (logic_path, template_data, props_path))| {
             template_data.map(|(template_path, template_content)| Component {
                 id,
                 logic_path: logic_path.map(|p| p.to_string_lossy().into_owned()),
                 template_path: template_path.to_string_lossy().into_owned(),
                 template_content,
+                props: props_path.as_deref().and_then(load_props),
             })
         })
         .collect();
@@ -49,11 +94,12 @@ pub fn scan_components(dir: &Path) -> std::io::Result<Vec<Component>> {
 
 pub fn scan_single_component(path: &Path, base_path: &Path) -> std::io::Result<Component> {
     let parent_dir = path.parent().ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, "Component parent directory not found"))?;
-    let component_id = parent_dir.strip_prefix(base_path).map_err(|_| std::io::Error::new(std::io::ErrorKind::InvalidInput, "Component path is not relative to the components directory"))?.to_str().ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidData, "Component path contains invalid UTF-8"))?.to_string();
+    let component_id = paths::to_slash(parent_dir.strip_prefix(base_path).map_err(|_| std::io::Error::new(std::io::ErrorKind::InvalidInput, "Component path is not relative to the components directory"))?);
 
     let mut logic_path = None;
     let mut template_path = None;
     let mut template_content = None;
+    let mut props = None;
 
     for entry in WalkDir::new(parent_dir).into_iter().filter_map(Result::ok) {
         let path = entry.path();
@@ -64,6 +110,8 @@ pub fn scan_single_component(path: &Path, base_path: &Path) -> std::io::Result<C
             } else if file_name.ends_with(".html") {
                 template_path = Some(path.to_string_lossy().into_owned());
                 template_content = Some(std::fs::read_to_string(path)?);
+            } else if file_name == crate::props::FILE {
+                props = load_props(path);
             }
         }
     }
@@ -74,6 +122,7 @@ pub fn scan_single_component(path: &Path, base_path: &Path) -> std::io::Result<C
             logic_path,
             template_path: tp,
             template_content: tc,
+            props,
         }),
         _ => Err(std::io::Error::new(
             std::io::ErrorKind::NotFound,
@@ -0,0 +1,203 @@
+use crate::config;
+use actix_http::encoding::Encoder;
+use actix_web::http::header::{self, AcceptEncoding, ContentEncoding, Encoding};
+use actix_web::http::StatusCode;
+use actix_web::{HttpMessage, HttpRequest, HttpResponse};
+use actix_web::web::Bytes;
+use lazy_static::lazy_static;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+// Compressed bodies kept in memory, oldest dropped first.
+const MAX_BYTES: usize = 32 * 1024 * 1024;
+// Pages seen once, remembered so a second render of the same HTML gets cached.
+const MAX_SEEN: usize = 10_000;
+// Smaller pages compress quickly enough that caching them isn't worth the memory.
+const MIN_SIZE: usize = 1024;
+// The same order Compress negotiates in, so a cached body is what it would have sent.
+const SUPPORTED: &[Encoding] = &[Encoding::identity(), Encoding::brotli(), Encoding::gzip(), Encoding::deflate(), Encoding::zstd()];
+
+type Key = (String, ContentEncoding);
+
+#[derive(Default)]
+struct Cache {
+    bodies: HashMap<Key, Bytes>,
+    order: VecDeque<Key>,
+    bytes: usize,
+    seen: HashSet<String>,
+}
+
+impl Cache {
+    fn get(&self, key: &Key) -> Option<Bytes> {
+        self.bodies.get(key).cloned()
+    }
+
+    // True the second time the same HTML comes by, when it's worth compressing once and keeping.
+    fn is_hot(&mut self, hash: &str) -> bool {
+        if self.seen.contains(hash) {
+            return true;
+        }
+        if self.seen.len() >= MAX_SEEN {
+            self.seen.clear();
+        }
+        self.seen.insert(hash.to_string());
+        false
+    }
+
+    fn insert(&mut self, key: Key, body: Bytes) {
+        if body.len() > MAX_BYTES || self.bodies.contains_key(&key) {
+            return;
+        }
+        while self.bytes + body.len() > MAX_BYTES {
+            let Some(oldest) = self.order.pop_front() else {
+                break;
+            };
+            if let Some(evicted) = self.bodies.remove(&oldest) {
+                self.bytes -= evicted.len();
+            }
+        }
+        self.bytes += body.len();
+        self.order.push_back(key.clone());
+        self.bodies.insert(key, body);
+    }
+}
+
+lazy_static! {
+    static ref CACHE: Mutex<Cache> = Mutex::new(Cache::default());
+}
+
+static HITS: AtomicU64 = AtomicU64::new(0);
+static MISSES: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct CompressedPageStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub entries: usize,
+    pub bytes: usize,
+}
+
+pub fn stats() -> CompressedPageStats {
+    let cache = CACHE.lock().unwrap();
+    CompressedPageStats {
+        hits: HITS.load(Ordering::Relaxed),
+        misses: MISSES.load(Ordering::Relaxed),
+        entries: cache.bodies.len(),
+        bytes: cache.bytes,
+    }
+}
+
+pub fn clear() {
+    *CACHE.lock().unwrap() = Cache::default();
+}
+
+fn negotiate(req: &HttpRequest) -> Option<ContentEncoding> {
+    match req.get_header::<AcceptEncoding>()?.negotiate(SUPPORTED.iter())? {
+        Encoding::Known(ContentEncoding::Identity) => None,
+        Encoding::Known(encoding) => Some(encoding),
+        Encoding::Unknown(_) => None,
+    }
+}
+
+async fn compress(encoding: ContentEncoding, html: &str) -> Option<Bytes> {
+    let mut head = actix_http::ResponseHead::new(StatusCode::OK);
+    let encoder = Encoder::response(encoding, &mut head, Bytes::copy_from_slice(html.as_bytes()));
+    actix_web::body::to_bytes(encoder).await.ok()
+}
+
+fn encoded_response(encoding: ContentEncoding, body: Bytes) -> HttpResponse {
+    // Compress leaves responses that already have a Content-Encoding alone.
+    HttpResponse::Ok()
+        .content_type("text/html")
+        .insert_header((header::CONTENT_ENCODING, encoding.as_str()))
+        .insert_header((header::VARY, "accept-encoding"))
+        .body(body)
+}
+
+// The response for a rendered page. With `compression` on, pages rendered to the same HTML more
+// than once are compressed once per encoding and then served from memory.
+pub async fn html_response(req: &HttpRequest, html: String) -> HttpResponse {
+    let plain = |html: String| HttpResponse::Ok().content_type("text/html").body(html);
+    if !config::CONFIG.compression.unwrap_or(false) || html.len() < MIN_SIZE {
+        return plain(html);
+    }
+    let Some(encoding) = negotiate(req) else {
+        return plain(html);
+    };
+
+    let hash = format!("{:x}", Sha256::digest(html.as_bytes()));
+    let key = (hash, encoding);
+    if let Some(body) = CACHE.lock().unwrap().get(&key) {
+        HITS.fetch_add(1, Ordering::Relaxed);
+        return encoded_response(encoding, body);
+    }
+    MISSES.fetch_add(1, Ordering::Relaxed);
+    if !CACHE.lock().unwrap().is_hot(&key.0) {
+        return plain(html);
+    }
+    match compress(encoding, &html).await {
+        Some(body) => {
+            CACHE.lock().unwrap().insert(key, body.clone());
+            encoded_response(encoding, body)
+        }
+        None => plain(html),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(hash: &str) -> Key {
+        (hash.to_string(), ContentEncoding::Gzip)
+    }
+
+    #[test]
+    fn test_only_repeated_pages_are_hot() {
+        let mut cache = Cache::default();
+        assert!(!cache.is_hot("a"));
+        assert!(cache.is_hot("a"));
+        assert!(!cache.is_hot("b"));
+    }
+
+    #[test]
+    fn test_insert_drops_the_oldest_bodies_past_the_limit() {
+        let mut cache = Cache::default();
+        let half = Bytes::from(vec![0u8; MAX_BYTES / 2]);
+        cache.insert(key("a"), half.clone());
+        cache.insert(key("b"), half.clone());
+        cache.insert(key("c"), Bytes::from_static(b"x"));
+
+        assert!(cache.get(&key("a")).is_none());
+        assert!(cache.get(&key("b")).is_some());
+        assert!(cache.get(&key("c")).is_some());
+        assert_eq!(cache.bytes, MAX_BYTES / 2 + 1);
+    }
+
+    #[actix_rt::test]
+    async fn test_compress_round_trips() {
+        use std::io::Read;
+
+        let html = "<p>hello</p>".repeat(200);
+        let body = compress(ContentEncoding::Gzip, &html).await.unwrap();
+        assert!(body.len() < html.len());
+
+        let mut decoded = String::new();
+        flate2::read::GzDecoder::new(&body[..]).read_to_string(&mut decoded).unwrap();
+        assert_eq!(decoded, html);
+    }
+
+    #[test]
+    fn test_negotiate() {
+        use actix_web::test::TestRequest;
+
+        let req = TestRequest::get().insert_header(("accept-encoding", "gzip, br")).to_http_request();
+        assert_eq!(negotiate(&req), Some(ContentEncoding::Brotli));
+        let req = TestRequest::get().insert_header(("accept-encoding", "gzip")).to_http_request();
+        assert_eq!(negotiate(&req), Some(ContentEncoding::Gzip));
+        assert_eq!(negotiate(&TestRequest::get().to_http_request()), None);
+    }
+}
@@ -1,6 +1,8 @@
 use cfg_if::cfg_if;
 use lazy_static::lazy_static;
+use once_cell::sync::OnceCell;
 use serde::Deserialize;
+use std::collections::HashMap;
 use std::fs;
 
 use std::fmt;
@@ -9,6 +11,8 @@ use std::fmt;
 pub enum ConfigError {
     Io(std::io::Error),
     Parse(serde_yaml::Error),
+    // Every problem found in an otherwise readable file, so they can all be fixed in one go.
+    Invalid(Vec<String>),
 }
 
 impl fmt::Display for ConfigError {
@@ -16,6 +20,7 @@ impl fmt::Display for ConfigError {
         match self {
             ConfigError::Io(err) => write!(f, "I/O error: {}", err),
             ConfigError::Parse(err) => write!(f, "Parse error: {}", err),
+            ConfigError::Invalid(problems) => write!(f, "Invalid configuration: {}", problems.join("; ")),
         }
     }
 }
@@ -34,7 +39,7 @@ impl From<serde_yaml::Error> for ConfigError {
     }
 }
 
-#[derive(Deserialize, Clone, Copy, Debug)]
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
 #[serde(rename_all = "kebab-case")]
 pub enum SessionBackend {
     Cookie,
@@ -42,10 +47,47 @@ pub enum SessionBackend {
     Redis,
 }
 
+impl SessionBackend {
+    pub fn name(self) -> &'static str {
+        match self {
+            SessionBackend::Cookie => "cookie",
+            SessionBackend::Memory => "memory",
+            SessionBackend::Redis => "redis",
+        }
+    }
+}
+
+impl std::str::FromStr for SessionBackend {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "cookie" => Ok(SessionBackend::Cookie),
+            "memory" => Ok(SessionBackend::Memory),
+            "redis" => Ok(SessionBackend::Redis),
+            _ => Err(format!("'{}' isn't a session backend; use cookie, memory or redis", name)),
+        }
+    }
+}
+
+// What requests get while the Redis session backend can't be reached.
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum RedisFallback {
+    // A "try again soon" page.
+    #[default]
+    Error,
+    // Pages work with an empty session, and nothing stored in it is kept.
+    ReadOnly,
+}
+
 #[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
 pub struct SessionConfig {
     pub backend: SessionBackend,
     pub secret_key: String,
+    // Keys `secret_key` replaced. Cookies signed with them are still accepted and re-signed with `secret_key`.
+    pub old_secret_keys: Option<Vec<String>>,
     pub cookie_name: String,
     pub cookie_secure: bool,
     pub cookie_http_only: bool,
@@ -54,30 +96,373 @@ pub struct SessionConfig {
     pub cookie_max_age: Option<i64>,
     pub redis_url: Option<String>,
     pub redis_pool_size: Option<usize>,
+    pub redis_fallback: Option<RedisFallback>,
+    // How many times to try Redis at startup before starting without it.
+    pub redis_connect_attempts: Option<u32>,
+    // Setting one of these (say, on login) gives the session a new id. Defaults to `user_id`.
+    pub login_keys: Option<Vec<String>>,
+    // The backend sessions are moving away from. Each one is copied over on its next request.
+    pub migrate_from: Option<SessionBackend>,
+    // `module.function` returning the id of each new session, for the memory and Redis backends.
+    pub id_generator: Option<String>,
+    // Routes (say `/admin`) that keep a session of their own, in a cookie of their own.
+    pub scopes: Option<Vec<SessionScope>>,
+}
+
+// One of `session.scopes`. What isn't set comes from the main session cookie.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct SessionScope {
+    // `/admin` covers `/admin` and everything under it.
+    pub path_prefix: String,
+    pub cookie_name: String,
+    // Defaults to `path_prefix`, so browsers only send the cookie there.
+    pub cookie_path: Option<String>,
+    pub cookie_secure: Option<bool>,
+    pub cookie_http_only: Option<bool>,
+    // strict, lax or none.
+    pub cookie_same_site: Option<String>,
+    pub cookie_max_age: Option<i64>,
 }
 
 #[derive(Deserialize, Clone, Debug, Default)]
+#[serde(deny_unknown_fields)]
 pub struct CoreAllocation {
     pub python_threads: Option<usize>,
     pub template_renderer_threads: Option<usize>,
     pub actix_web_threads: Option<usize>,
+    // Calls that may wait for a Python interpreter before new ones are turned away.
+    pub python_queue_limit: Option<usize>,
+}
+
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct DiscoConfig {
+    pub tools_dir: Option<String>,
+    // The only folder Disco's file tools may touch. Defaults to the project itself.
+    pub workspace_root: Option<String>,
+}
+
+// Shares state between several `noventa serve` instances through Redis.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct ClusterConfig {
+    pub redis_url: String,
+    // Pub/sub channel and key prefix, so several apps can share one Redis.
+    pub channel: Option<String>,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum StateBackend {
+    Memory,
+    Redis,
+}
+
+// Where components that take a `state` argument keep it. Without a backend, it's Redis when there's
+// a `cluster` section and memory when there isn't.
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct ComponentStateConfig {
+    pub backend: Option<StateBackend>,
+    // Defaults to `cluster.redis_url`.
+    pub redis_url: Option<String>,
+    // How long a state is kept after it last changed. Defaults to a day.
+    pub ttl_secs: Option<u64>,
+}
+
+// What `adaptive_shedding` does once it's turning requests away.
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct LoadSheddingConfig {
+    // Paths that are always let through, e.g. `/checkout`, or `/api/payments/*` for everything under it.
+    pub critical_routes: Option<Vec<String>>,
+    // An HTML file sent as it is with the 503, e.g. `static/busy.html`.
+    pub busy_page: Option<String>,
+    // Seconds clients are told to wait before trying again. Defaults to 10.
+    pub retry_after: Option<u64>,
+}
+
+// Where traces of each page render go, over OTLP/HTTP, e.g. to an OpenTelemetry collector or Jaeger.
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct TelemetryConfig {
+    // e.g. `http://localhost:4318`. Defaults to OTEL_EXPORTER_OTLP_ENDPOINT; tracing is off without either.
+    pub otlp_endpoint: Option<String>,
+    // Defaults to `noventa`.
+    pub service_name: Option<String>,
+    // The share of requests traced, from 0 to 1. Defaults to 1.
+    pub sample_ratio: Option<f64>,
+    // Sent with every export, e.g. an API key for a hosted collector.
+    pub headers: Option<HashMap<String, String>>,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum AccessLogFormat {
+    // Apache's combined format, plus the page, the time taken and the request id.
+    #[default]
+    Combined,
+    // One JSON object per line, for log shippers.
+    Json,
+}
+
+// A line for every request. On by default with `noventa serve`, off with `noventa dev`.
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct AccessLogConfig {
+    pub enabled: Option<bool>,
+    pub format: Option<AccessLogFormat>,
+    // A file the lines are appended to instead of going out with the other logs, e.g. `logs/access.log`.
+    pub path: Option<String>,
+}
+
+// Where Python and template errors are reported, e.g. Sentry. Only with `noventa serve`.
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct ErrorReportingConfig {
+    // e.g. `https://<key>@o123.ingest.sentry.io/456`. Defaults to SENTRY_DSN; nothing is reported without either.
+    pub sentry_dsn: Option<String>,
+    // e.g. `production`. Defaults to SENTRY_ENVIRONMENT.
+    pub environment: Option<String>,
+    // e.g. a git sha. Defaults to SENTRY_RELEASE.
+    pub release: Option<String>,
+    // Errors sent at most each minute; the same error is sent once a minute at most. Defaults to 30.
+    pub max_per_minute: Option<u32>,
+}
+
+// Where the editor extension's language server listens while `noventa dev` runs.
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct LspConfig {
+    // Defaults to 127.0.0.1.
+    pub address: Option<String>,
+    // Defaults to 9090; when another project has it, the next free one of the ten after it.
+    pub port: Option<u16>,
+}
+
+// At most `requests` per client address in each window.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct RateLimitConfig {
+    pub requests: u64,
+    pub window_secs: Option<u64>,
+}
+
+// Samples real traffic into a file `noventa loadtest --replay` plays back. Only its shape is kept:
+// routes, parameter names and kinds, and timing. No values, addresses or cookies.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct RecordingConfig {
+    // The share of clients whose requests are recorded, from 0 to 1.
+    pub sample_rate: f64,
+    // Defaults to `recordings/requests.jsonl`.
+    pub file: Option<String>,
+}
+
+// Enables `/_noventa/admin` when a token is set; requests must send it as `Authorization: Bearer <token>`.
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct AdminConfig {
+    pub token: Option<String>,
+}
+
+// Login support: `{# login_required #}` pages send visitors without a user to `login_url`.
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct AuthConfig {
+    // Defaults to `/login`. The page they asked for is passed along as `?next=`.
+    pub login_url: Option<String>,
+    // `module.function` that turns the id stored by `session.login_user()` into `session.current_user`.
+    pub user_loader: Option<String>,
+    // The session key holding the logged-in user's id. Defaults to `user_id`.
+    pub user_key: Option<String>,
+    // Each role's permissions, e.g. `editor: [posts.edit, posts.publish]`. `*` grants them all.
+    pub roles: Option<HashMap<String, Vec<String>>>,
+    // Template rendered, with a 403, for users a `{# requires: ... #}` page turns away.
+    pub forbidden_page: Option<String>,
+}
+
+// An OpenID Connect provider users can log in with at `/auth/{name}/login`.
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct OidcProviderConfig {
+    // e.g. `https://accounts.google.com`; its endpoints are read from `/.well-known/openid-configuration`.
+    pub issuer: String,
+    pub client_id: String,
+    pub client_secret: Option<String>,
+    // Defaults to openid, email and profile.
+    pub scopes: Option<Vec<String>>,
+    // Where the provider sends users back. Defaults to `/auth/{name}/callback` on the host they came in on.
+    pub redirect_url: Option<String>,
+}
+
+// Headers added to every response. Leave a value empty (or false) to drop that header.
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct SecurityHeadersConfig {
+    pub hsts_max_age: Option<u64>,
+    pub hsts_include_subdomains: Option<bool>,
+    pub content_type_options: Option<bool>,
+    pub referrer_policy: Option<String>,
+    pub frame_ancestors: Option<String>,
+    // `{nonce}` is replaced with the per-request nonce Noventa also puts on its own scripts.
+    pub content_security_policy: Option<String>,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum UploadBackendKind {
+    #[default]
+    Disk,
+    S3,
 }
 
+// Where uploads bigger than `max_memory_size` go. `s3` covers any S3-compatible store through `endpoint`.
 #[derive(Deserialize, Clone, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct UploadStorageConfig {
+    #[serde(default)]
+    pub backend: UploadBackendKind,
+    pub bucket: Option<String>,
+    pub region: Option<String>,
+    pub endpoint: Option<String>,
+    // Prepended to every object key, e.g. `uploads/`.
+    pub prefix: Option<String>,
+    // Fall back to AWS_ACCESS_KEY_ID / AWS_SECRET_ACCESS_KEY.
+    pub access_key_id: Option<String>,
+    pub secret_access_key: Option<String>,
+    // Base URL of a CDN or public bucket for `.url()`; without it the links are presigned.
+    pub public_url: Option<String>,
+}
+
+// Which uploads a form field accepts. Types are the ones the file's bytes reveal, not what the
+// client claims, and may end in `/*` (e.g. `image/*`).
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct UploadRulesConfig {
+    pub allow: Option<Vec<String>>,
+    pub deny: Option<Vec<String>>,
+    // In pixels, for PNG, JPEG, GIF, WebP and BMP images.
+    pub max_width: Option<u32>,
+    pub max_height: Option<u32>,
+}
+
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct UploadValidationConfig {
+    pub allow: Option<Vec<String>>,
+    pub deny: Option<Vec<String>>,
+    pub max_width: Option<u32>,
+    pub max_height: Option<u32>,
+    // Per form field; each setting given there replaces the one above.
+    pub fields: Option<HashMap<String, UploadRulesConfig>>,
+    // A scanner that reads the file on stdin and exits 0 when it's clean and 1 when it isn't.
+    pub scan_command: Option<Vec<String>>,
+    pub scan_timeout: Option<u64>,
+}
+
+// Which Python install the embedded interpreter uses for its standard library and packages.
+// Without it, it's whatever libpython finds on its own (or PYTHONHOME, which wins over both).
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct PythonConfig {
+    // e.g. `/srv/app/.venv/bin/python`; asked once at startup where its files are.
+    pub executable: Option<String>,
+    // The install's prefix, for when there's no executable to ask.
+    pub home: Option<String>,
+}
+
+// Serves resized copies of the images under `static_path` at `/img/{path}?w=640&format=webp`.
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct ImagesConfig {
+    // Where resized copies are kept between requests; defaults to a folder in the system's temp dir.
+    pub cache_dir: Option<String>,
+    // When set, only these widths are served, which bounds how many copies can pile up.
+    pub widths: Option<Vec<u32>>,
+    pub max_width: Option<u32>,
+    // JPEG quality, 1 to 100.
+    pub quality: Option<u8>,
+}
+
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(deny_unknown_fields)]
 pub struct Config {
     pub server_address: Option<String>,
     pub port: Option<u32>,
     pub core_allocation: Option<CoreAllocation>,
     pub max_memory_size: Option<usize>,
+    pub max_request_size: Option<usize>,
+    pub max_field_size: Option<usize>,
+    pub max_file_size: Option<usize>,
+    // Overrides of `max_field_size` (plain fields) or `max_file_size` (files), by field name.
+    pub field_size_limits: Option<HashMap<String, usize>>,
+    pub request_read_timeout: Option<u64>,
+    pub resumable_uploads: Option<bool>,
+    pub upload_storage: Option<UploadStorageConfig>,
+    pub upload_validation: Option<UploadValidationConfig>,
     pub temp_dir: Option<String>,
     pub adaptive_shedding: Option<bool>,
+    pub load_shedding: Option<LoadSheddingConfig>,
+    // Seconds a page gets to render before the visitor gets an error. Defaults to 60; a page's
+    // `timeout` wins over it.
+    pub render_timeout: Option<u64>,
+    pub telemetry: Option<TelemetryConfig>,
+    pub access_log: Option<AccessLogConfig>,
+    pub error_reporting: Option<ErrorReportingConfig>,
     pub database: Option<String>,
+    pub python: Option<PythonConfig>,
     pub static_path: Option<String>,
     pub static_url_prefix: Option<String>,
+    pub images: Option<ImagesConfig>,
     pub session: Option<SessionConfig>,
     pub log_level: Option<String>,
     pub disable_script_injection: Option<bool>,
     pub compression: Option<bool>,
+    pub disco: Option<DiscoConfig>,
+    pub lsp: Option<LspConfig>,
+    // `unix:/path/to.sock` serves over a unix socket instead of `server_address:port`.
+    pub bind: Option<String>,
+    pub unix_socket_mode: Option<String>,
+    // Proxies (IPs, CIDR ranges or `unix`) whose Forwarded/X-Forwarded-* headers are believed.
+    pub trusted_proxies: Option<Vec<String>>,
+    // Lets `noventa serve` pick up new pages on SIGHUP or POST /_noventa/reload instead of needing a restart.
+    pub reload_pages: Option<bool>,
+    // Paths `noventa serve` renders once at startup, before /health/ready says it's ready.
+    pub warm_routes: Option<Vec<String>>,
+    // Where the site lives publicly, e.g. `https://example.com`; `noventa ssg` builds sitemap.xml from it.
+    pub site_url: Option<String>,
+    // Every rendered form gets a one-time token, and a form sent twice only runs its action once.
+    pub form_tokens: Option<bool>,
+    pub security_headers: Option<SecurityHeadersConfig>,
+    pub admin: Option<AdminConfig>,
+    pub auth: Option<AuthConfig>,
+    // Login providers by name, e.g. `google`.
+    pub oidc: Option<HashMap<String, OidcProviderConfig>>,
+    pub cluster: Option<ClusterConfig>,
+    pub rate_limit: Option<RateLimitConfig>,
+    pub component_state: Option<ComponentStateConfig>,
+    pub recording: Option<RecordingConfig>,
+    // Several instances run behind a load balancer; `noventa serve` then warns about per-process state.
+    pub multi_instance: Option<bool>,
+}
+
+// Values passed on the command line (e.g. `noventa dev --port 3000`). They win over config.yaml.
+#[derive(Debug, Clone, Default)]
+pub struct ConfigOverrides {
+    pub server_address: Option<String>,
+    pub port: Option<u32>,
+    pub log_level: Option<String>,
+}
+
+static OVERRIDES: OnceCell<ConfigOverrides> = OnceCell::new();
+
+// Must run before CONFIG is first used, which is why main calls it right after parsing the CLI.
+pub fn set_overrides(overrides: ConfigOverrides) {
+    if OVERRIDES.set(overrides).is_err() {
+        log::warn!("Command line overrides were already applied; ignoring the new ones.");
+    }
 }
 
 lazy_static! {
@@ -96,11 +481,560 @@ fn find_config_file() -> std::path::PathBuf {
     }
 }
 
+// Keep these in sync with the structs above; they drive the unknown-key report.
+const CONFIG_KEYS: &[&str] = &[
+    "server_address", "port", "core_allocation", "max_memory_size", "max_request_size", "max_field_size",
+    "max_file_size", "field_size_limits", "request_read_timeout", "resumable_uploads", "upload_storage", "upload_validation", "temp_dir",
+    "adaptive_shedding", "load_shedding", "render_timeout", "telemetry", "access_log", "error_reporting", "database", "python", "static_path", "static_url_prefix", "images", "session", "log_level", "disable_script_injection", "compression",
+    "disco", "lsp", "bind", "unix_socket_mode", "trusted_proxies",
+    "reload_pages", "security_headers", "admin", "auth", "oidc", "cluster", "rate_limit", "component_state", "recording",
+    "multi_instance", "warm_routes", "site_url", "form_tokens",
+];
+const SESSION_KEYS: &[&str] = &[
+    "backend", "secret_key", "old_secret_keys", "cookie_name", "cookie_secure", "cookie_http_only", "cookie_path", "cookie_domain",
+    "cookie_max_age", "redis_url", "redis_pool_size", "redis_fallback", "redis_connect_attempts",
+    "login_keys", "migrate_from", "id_generator", "scopes",
+];
+const SESSION_SCOPE_KEYS: &[&str] = &[
+    "path_prefix", "cookie_name", "cookie_path", "cookie_secure", "cookie_http_only", "cookie_same_site", "cookie_max_age",
+];
+const CORE_ALLOCATION_KEYS: &[&str] = &["python_threads", "template_renderer_threads", "actix_web_threads", "python_queue_limit"];
+const DISCO_KEYS: &[&str] = &["tools_dir", "workspace_root"];
+const LSP_KEYS: &[&str] = &["address", "port"];
+const ADMIN_KEYS: &[&str] = &["token"];
+const AUTH_KEYS: &[&str] = &["login_url", "user_loader", "user_key", "roles", "forbidden_page"];
+const OIDC_PROVIDER_KEYS: &[&str] = &["issuer", "client_id", "client_secret", "scopes", "redirect_url"];
+const CLUSTER_KEYS: &[&str] = &["redis_url", "channel"];
+const UPLOAD_STORAGE_KEYS: &[&str] = &[
+    "backend", "bucket", "region", "endpoint", "prefix", "access_key_id", "secret_access_key", "public_url",
+];
+const UPLOAD_RULES_KEYS: &[&str] = &["allow", "deny", "max_width", "max_height"];
+const UPLOAD_VALIDATION_KEYS: &[&str] =
+    &["allow", "deny", "max_width", "max_height", "fields", "scan_command", "scan_timeout"];
+const RATE_LIMIT_KEYS: &[&str] = &["requests", "window_secs"];
+const ACCESS_LOG_KEYS: &[&str] = &["enabled", "format", "path"];
+const TELEMETRY_KEYS: &[&str] = &["otlp_endpoint", "service_name", "sample_ratio", "headers"];
+const ERROR_REPORTING_KEYS: &[&str] = &["sentry_dsn", "environment", "release", "max_per_minute"];
+const LOAD_SHEDDING_KEYS: &[&str] = &["critical_routes", "busy_page", "retry_after"];
+const COMPONENT_STATE_KEYS: &[&str] = &["backend", "redis_url", "ttl_secs"];
+const RECORDING_KEYS: &[&str] = &["sample_rate", "file"];
+const PYTHON_KEYS: &[&str] = &["executable", "home"];
+const IMAGES_KEYS: &[&str] = &["cache_dir", "widths", "max_width", "quality"];
+const SECURITY_HEADERS_KEYS: &[&str] = &[
+    "hsts_max_age", "hsts_include_subdomains", "content_type_options", "referrer_policy", "frame_ancestors",
+    "content_security_policy",
+];
+
+// actix-web refuses to sign cookies with anything shorter.
+const MIN_SECRET_KEY_LENGTH: usize = 64;
+const MIN_ADMIN_TOKEN_LENGTH: usize = 32;
+
+// Sets `section.key` in config.yaml to `value` (YAML, e.g. a JSON-quoted string), adding the
+// section if needed and leaving the rest of the file, comments included, alone.
+pub fn set_value(config_path: &std::path::Path, section: &str, key: &str, value: &str) -> std::io::Result<()> {
+    let text = fs::read_to_string(config_path)?;
+    let line = format!("  {}: {}", key, value);
+    let mut lines: Vec<String> = text.lines().map(str::to_string).collect();
+    let header = format!("{}:", section);
+
+    match lines.iter().position(|l| l.trim_end() == header) {
+        Some(start) => {
+            let end = lines[start + 1..]
+                .iter()
+                .position(|l| !l.is_empty() && !l.starts_with(' ') && !l.starts_with('#'))
+                .map_or(lines.len(), |offset| start + 1 + offset);
+            let prefix = format!("{}:", key);
+            match (start + 1..end).find(|i| lines[*i].starts_with("  ") && lines[*i].trim_start().starts_with(&prefix)) {
+                Some(i) => lines[i] = line,
+                None => lines.insert(start + 1, line),
+            }
+        }
+        None => {
+            if lines.last().is_some_and(|l| !l.is_empty()) {
+                lines.push(String::new());
+            }
+            lines.push(header);
+            lines.push(line);
+        }
+    }
+    fs::write(config_path, lines.join("\n") + "\n")
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+// Removes keys the structs don't know about and describes each one, suggesting the closest known key.
+fn take_unknown_keys(value: &mut serde_yaml::Value, known: &[&str], prefix: &str, problems: &mut Vec<String>) {
+    let Some(mapping) = value.as_mapping_mut() else {
+        return;
+    };
+    let unknown: Vec<serde_yaml::Value> = mapping
+        .keys()
+        .filter(|key| !key.as_str().is_some_and(|k| known.contains(&k)))
+        .cloned()
+        .collect();
+    for key in unknown {
+        mapping.remove(&key);
+        let name = key.as_str().map(str::to_string).unwrap_or_else(|| format!("{:?}", key));
+        let suggestion = known
+            .iter()
+            .map(|k| (edit_distance(&name, k), k))
+            .filter(|(distance, _)| *distance <= 2)
+            .min()
+            .map(|(_, k)| format!(" Did you mean `{}{}`?", prefix, k))
+            .unwrap_or_default();
+        problems.push(format!("`{}{}` isn't a known setting.{}", prefix, name, suggestion));
+    }
+}
+
 impl Config {
+    pub fn apply_overrides(&mut self, overrides: &ConfigOverrides) {
+        if let Some(server_address) = &overrides.server_address {
+            self.server_address = Some(server_address.clone());
+        }
+        if let Some(port) = overrides.port {
+            self.port = Some(port);
+        }
+        if let Some(log_level) = &overrides.log_level {
+            self.log_level = Some(log_level.clone());
+        }
+    }
+
     pub fn from_file(path: &str) -> Result<Self, ConfigError> {
         let content = fs::read_to_string(path)?;
-        let config = serde_yaml::from_str(&content)?;
-        Ok(config)
+        let mut value: serde_yaml::Value = serde_yaml::from_str(&content)?;
+
+        let mut problems = Vec::new();
+        take_unknown_keys(&mut value, CONFIG_KEYS, "", &mut problems);
+        if let Some(session) = value.get_mut("session") {
+            take_unknown_keys(session, SESSION_KEYS, "session.", &mut problems);
+            if let Some(scopes) = session.get_mut("scopes").and_then(|scopes| scopes.as_sequence_mut()) {
+                for (i, scope) in scopes.iter_mut().enumerate() {
+                    take_unknown_keys(scope, SESSION_SCOPE_KEYS, &format!("session.scopes[{}].", i), &mut problems);
+                }
+            }
+        }
+        if let Some(core_allocation) = value.get_mut("core_allocation") {
+            take_unknown_keys(core_allocation, CORE_ALLOCATION_KEYS, "core_allocation.", &mut problems);
+        }
+        if let Some(disco) = value.get_mut("disco") {
+            take_unknown_keys(disco, DISCO_KEYS, "disco.", &mut problems);
+        }
+        if let Some(lsp) = value.get_mut("lsp") {
+            take_unknown_keys(lsp, LSP_KEYS, "lsp.", &mut problems);
+        }
+        if let Some(security_headers) = value.get_mut("security_headers") {
+            take_unknown_keys(security_headers, SECURITY_HEADERS_KEYS, "security_headers.", &mut problems);
+        }
+        if let Some(admin) = value.get_mut("admin") {
+            take_unknown_keys(admin, ADMIN_KEYS, "admin.", &mut problems);
+        }
+        if let Some(auth) = value.get_mut("auth") {
+            take_unknown_keys(auth, AUTH_KEYS, "auth.", &mut problems);
+        }
+        if let Some(providers) = value.get_mut("oidc").and_then(|oidc| oidc.as_mapping_mut()) {
+            for (name, provider) in providers.iter_mut() {
+                let prefix = format!("oidc.{}.", name.as_str().unwrap_or_default());
+                take_unknown_keys(provider, OIDC_PROVIDER_KEYS, &prefix, &mut problems);
+            }
+        }
+        if let Some(cluster) = value.get_mut("cluster") {
+            take_unknown_keys(cluster, CLUSTER_KEYS, "cluster.", &mut problems);
+        }
+        if let Some(upload_storage) = value.get_mut("upload_storage") {
+            take_unknown_keys(upload_storage, UPLOAD_STORAGE_KEYS, "upload_storage.", &mut problems);
+        }
+        if let Some(upload_validation) = value.get_mut("upload_validation") {
+            take_unknown_keys(upload_validation, UPLOAD_VALIDATION_KEYS, "upload_validation.", &mut problems);
+            if let Some(fields) = upload_validation.get_mut("fields").and_then(|fields| fields.as_mapping_mut()) {
+                for (name, rules) in fields.iter_mut() {
+                    let prefix = format!("upload_validation.fields.{}.", name.as_str().unwrap_or_default());
+                    take_unknown_keys(rules, UPLOAD_RULES_KEYS, &prefix, &mut problems);
+                }
+            }
+        }
+        if let Some(load_shedding) = value.get_mut("load_shedding") {
+            take_unknown_keys(load_shedding, LOAD_SHEDDING_KEYS, "load_shedding.", &mut problems);
+        }
+        if let Some(access_log) = value.get_mut("access_log") {
+            take_unknown_keys(access_log, ACCESS_LOG_KEYS, "access_log.", &mut problems);
+        }
+        if let Some(error_reporting) = value.get_mut("error_reporting") {
+            take_unknown_keys(error_reporting, ERROR_REPORTING_KEYS, "error_reporting.", &mut problems);
+        }
+        if let Some(telemetry) = value.get_mut("telemetry") {
+            take_unknown_keys(telemetry, TELEMETRY_KEYS, "telemetry.", &mut problems);
+        }
+        if let Some(rate_limit) = value.get_mut("rate_limit") {
+            take_unknown_keys(rate_limit, RATE_LIMIT_KEYS, "rate_limit.", &mut problems);
+        }
+        if let Some(component_state) = value.get_mut("component_state") {
+            take_unknown_keys(component_state, COMPONENT_STATE_KEYS, "component_state.", &mut problems);
+        }
+        if let Some(recording) = value.get_mut("recording") {
+            take_unknown_keys(recording, RECORDING_KEYS, "recording.", &mut problems);
+        }
+        if let Some(python) = value.get_mut("python") {
+            take_unknown_keys(python, PYTHON_KEYS, "python.", &mut problems);
+        }
+        if let Some(images) = value.get_mut("images") {
+            take_unknown_keys(images, IMAGES_KEYS, "images.", &mut problems);
+        }
+
+        let config: Config = match serde_yaml::from_value(value) {
+            Ok(config) => config,
+            Err(e) if problems.is_empty() => return Err(ConfigError::Parse(e)),
+            Err(e) => {
+                problems.push(e.to_string());
+                return Err(ConfigError::Invalid(problems));
+            }
+        };
+
+        problems.extend(config.validate());
+        if problems.is_empty() {
+            Ok(config)
+        } else {
+            Err(ConfigError::Invalid(problems))
+        }
+    }
+
+    // Checks values that parse fine but would fail (or silently misbehave) once the server starts.
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+
+        if let Some(port) = self.port
+            && !(1..=65535).contains(&port)
+        {
+            problems.push(format!("`port` must be between 1 and 65535, but it's {}.", port));
+        }
+
+        if let Some(core_allocation) = &self.core_allocation {
+            for (name, threads) in [
+                ("python_threads", core_allocation.python_threads),
+                ("template_renderer_threads", core_allocation.template_renderer_threads),
+                ("actix_web_threads", core_allocation.actix_web_threads),
+                ("python_queue_limit", core_allocation.python_queue_limit),
+            ] {
+                if threads == Some(0) {
+                    problems.push(format!("`core_allocation.{}` must be at least 1.", name));
+                }
+            }
+        }
+
+        for (name, value) in [
+            ("max_request_size", self.max_request_size.map(|v| v as u64)),
+            ("max_field_size", self.max_field_size.map(|v| v as u64)),
+            ("max_file_size", self.max_file_size.map(|v| v as u64)),
+            ("request_read_timeout", self.request_read_timeout),
+            ("render_timeout", self.render_timeout),
+        ] {
+            if value == Some(0) {
+                problems.push(format!("`{}` must be at least 1.", name));
+            }
+        }
+        for (field, limit) in self.field_size_limits.iter().flatten() {
+            if *limit == 0 {
+                problems.push(format!("`field_size_limits.{}` must be at least 1.", field));
+            }
+        }
+
+        if let Some(storage) = &self.upload_storage
+            && storage.backend == UploadBackendKind::S3
+        {
+            if storage.bucket.as_deref().unwrap_or_default().is_empty() {
+                problems.push("`upload_storage.bucket` is required when `upload_storage.backend` is s3.".to_string());
+            }
+            if let Some(endpoint) = &storage.endpoint
+                && !endpoint.starts_with("https://")
+                && !endpoint.starts_with("http://")
+            {
+                problems.push("`upload_storage.endpoint` must be an http:// or https:// URL.".to_string());
+            }
+        }
+
+        if let Some(validation) = &self.upload_validation {
+            if validation.scan_command.as_ref().is_some_and(|command| command.is_empty()) {
+                problems.push("`upload_validation.scan_command` needs at least the program to run.".to_string());
+            }
+            if validation.scan_timeout == Some(0) {
+                problems.push("`upload_validation.scan_timeout` must be at least 1.".to_string());
+            }
+        }
+
+        if let Some(images) = &self.images {
+            if self.static_path.is_none() {
+                problems.push("`images` needs `static_path`, which is where the images are read from.".to_string());
+            }
+            if images.widths.as_ref().is_some_and(|widths| widths.is_empty() || widths.contains(&0)) {
+                problems.push("`images.widths` needs at least one width, and each must be at least 1.".to_string());
+            }
+            if images.max_width == Some(0) {
+                problems.push("`images.max_width` must be at least 1.".to_string());
+            }
+            if images.quality.is_some_and(|quality| !(1..=100).contains(&quality)) {
+                problems.push("`images.quality` must be between 1 and 100.".to_string());
+            }
+        }
+
+        if let Some(prefix) = &self.static_url_prefix
+            && !prefix.starts_with('/')
+        {
+            problems.push(format!("`static_url_prefix` must start with a slash, e.g. `/{}`.", prefix));
+        }
+
+        for route in self.warm_routes.iter().flatten() {
+            if !route.starts_with('/') {
+                problems.push(format!("`warm_routes` entries must be paths on this site, e.g. `/`, but one is '{}'.", route));
+            }
+        }
+
+        if let Some(site_url) = &self.site_url
+            && !site_url.starts_with("https://")
+            && !site_url.starts_with("http://")
+        {
+            problems.push(format!("`site_url` must be a full URL like `https://example.com`, but it's '{}'.", site_url));
+        }
+
+        if let Some(bind) = &self.bind
+            && let Err(problem) = crate::listener::parse_bind(bind)
+        {
+            problems.push(problem);
+        }
+        if let Some(mode) = &self.unix_socket_mode
+            && let Err(problem) = crate::listener::parse_socket_mode(mode)
+        {
+            problems.push(problem);
+        }
+
+        for entry in self.trusted_proxies.iter().flatten() {
+            if let Err(problem) = crate::proxy::validate_entry(entry) {
+                problems.push(problem);
+            }
+        }
+
+        if let Some(token) = self.admin.as_ref().and_then(|admin| admin.token.as_ref())
+            && token.len() < MIN_ADMIN_TOKEN_LENGTH
+        {
+            problems.push(format!(
+                "`admin.token` must be at least {} characters long, but it has {}.",
+                MIN_ADMIN_TOKEN_LENGTH,
+                token.len()
+            ));
+        }
+
+        if let Some(auth) = &self.auth {
+            if auth.login_url.as_ref().is_some_and(|url| !url.starts_with('/')) {
+                problems.push("`auth.login_url` must be a path on this site, e.g. `/login`.".to_string());
+            }
+            if let Some(loader) = &auth.user_loader
+                && !loader.rsplit_once('.').is_some_and(|(module, function)| !module.is_empty() && !function.is_empty())
+            {
+                problems.push(format!("`auth.user_loader` must be `module.function`, e.g. `auth.load_user`, but it's '{}'.", loader));
+            }
+            if let Some(page) = &auth.forbidden_page
+                && !page.ends_with(".html")
+            {
+                problems.push(format!("`auth.forbidden_page` must be a template, e.g. `pages/forbidden.html`, but it's '{}'.", page));
+            }
+            let mut roles: Vec<_> = auth.roles.iter().flatten().collect();
+            roles.sort_by_key(|(role, _)| role.as_str());
+            for (role, permissions) in roles {
+                if permissions.iter().any(|permission| permission.trim().is_empty()) {
+                    problems.push(format!("`auth.roles.{}` has an empty permission.", role));
+                }
+            }
+        }
+
+        let mut providers: Vec<_> = self.oidc.iter().flatten().collect();
+        providers.sort_by_key(|(name, _)| name.as_str());
+        for (name, provider) in providers {
+            if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+                problems.push(format!("`oidc.{}` needs a name made of letters, digits, - and _, since it goes in URLs.", name));
+            }
+            if !provider.issuer.starts_with("https://") && !provider.issuer.starts_with("http://") {
+                problems.push(format!("`oidc.{}.issuer` must be an http:// or https:// URL.", name));
+            }
+            if provider.client_id.is_empty() {
+                problems.push(format!("`oidc.{}.client_id` can't be empty.", name));
+            }
+        }
+        if self.oidc.as_ref().is_some_and(|providers| !providers.is_empty()) && self.session.is_none() {
+            problems.push("`oidc` needs a `session` section: that's where logged-in users are remembered.".to_string());
+        }
+
+        if let Some(load_shedding) = &self.load_shedding {
+            for route in load_shedding.critical_routes.iter().flatten() {
+                if !route.starts_with('/') {
+                    problems.push(format!("`load_shedding.critical_routes` entries must be paths on this site, e.g. `/checkout`, but one is '{}'.", route));
+                }
+            }
+            if let Some(page) = &load_shedding.busy_page
+                && !page.ends_with(".html")
+            {
+                problems.push(format!("`load_shedding.busy_page` must be an HTML file, e.g. `static/busy.html`, but it's '{}'.", page));
+            }
+            if load_shedding.retry_after == Some(0) {
+                problems.push("`load_shedding.retry_after` must be at least 1.".to_string());
+            }
+        }
+        if let Some(telemetry) = &self.telemetry {
+            if let Some(endpoint) = &telemetry.otlp_endpoint
+                && !endpoint.starts_with("http://")
+                && !endpoint.starts_with("https://")
+            {
+                problems.push(format!("`telemetry.otlp_endpoint` must be an http(s) URL, e.g. `http://localhost:4318`, but it's '{}'.", endpoint));
+            }
+            if telemetry.sample_ratio.is_some_and(|ratio| !(0.0..=1.0).contains(&ratio)) {
+                problems.push("`telemetry.sample_ratio` must be between 0 and 1.".to_string());
+            }
+        }
+        if let Some(error_reporting) = &self.error_reporting {
+            if let Some(dsn) = &error_reporting.sentry_dsn
+                && crate::error_reporting::parse_dsn(dsn).is_none()
+            {
+                problems.push(format!("`error_reporting.sentry_dsn` must look like `https://<key>@o123.ingest.sentry.io/456`, but it's '{}'.", dsn));
+            }
+            if error_reporting.max_per_minute == Some(0) {
+                problems.push("`error_reporting.max_per_minute` must be at least 1.".to_string());
+            }
+        }
+        if let Some(lsp) = &self.lsp {
+            if let Some(address) = &lsp.address
+                && address.parse::<std::net::IpAddr>().is_err()
+            {
+                problems.push(format!("`lsp.address` must be an IP address, e.g. `127.0.0.1`, but it's '{}'.", address));
+            }
+            if lsp.port == Some(0) {
+                problems.push("`lsp.port` must be between 1 and 65535.".to_string());
+            }
+        }
+        if let Some(rate_limit) = &self.rate_limit {
+            if rate_limit.requests == 0 {
+                problems.push("`rate_limit.requests` must be at least 1.".to_string());
+            }
+            if rate_limit.window_secs == Some(0) {
+                problems.push("`rate_limit.window_secs` must be at least 1.".to_string());
+            }
+        }
+        if let Some(component_state) = &self.component_state {
+            if component_state.backend == Some(StateBackend::Redis) && component_state.redis_url.is_none() && self.cluster.is_none() {
+                problems.push("`component_state.backend: redis` needs a `component_state.redis_url` or a `cluster` section.".to_string());
+            }
+            if component_state.ttl_secs == Some(0) {
+                problems.push("`component_state.ttl_secs` must be at least 1.".to_string());
+            }
+        }
+        if let Some(recording) = &self.recording
+            && !(recording.sample_rate > 0.0 && recording.sample_rate <= 1.0)
+        {
+            problems.push("`recording.sample_rate` must be more than 0 and at most 1, e.g. 0.05 for one client in twenty.".to_string());
+        }
+        if let Some(cluster) = &self.cluster
+            && !cluster.redis_url.starts_with("redis://")
+            && !cluster.redis_url.starts_with("rediss://")
+        {
+            problems.push("`cluster.redis_url` must be a redis:// or rediss:// URL.".to_string());
+        }
+
+        if let Some(log_level) = &self.log_level {
+            for directive in log_level.split(',').filter(|d| !d.is_empty()) {
+                let level = directive.rsplit('=').next().unwrap_or(directive);
+                if level.parse::<log::LevelFilter>().is_err() {
+                    problems.push(format!(
+                        "`log_level` has an unknown level '{}'. Use one of error, warn, info, debug or trace.",
+                        level
+                    ));
+                }
+            }
+        }
+
+        if let Some(session) = &self.session {
+            if session.secret_key.len() < MIN_SECRET_KEY_LENGTH {
+                problems.push(format!(
+                    "`session.secret_key` must be at least {} characters long, but it has {}.",
+                    MIN_SECRET_KEY_LENGTH,
+                    session.secret_key.len()
+                ));
+            }
+            for (i, old_key) in session.old_secret_keys.iter().flatten().enumerate() {
+                if old_key.len() < MIN_SECRET_KEY_LENGTH {
+                    problems.push(format!(
+                        "`session.old_secret_keys` entry {} must be at least {} characters long, but it has {}.",
+                        i + 1,
+                        MIN_SECRET_KEY_LENGTH,
+                        old_key.len()
+                    ));
+                }
+                if *old_key == session.secret_key {
+                    problems.push(format!("`session.old_secret_keys` entry {} is the same as `session.secret_key`.", i + 1));
+                }
+            }
+            if matches!(session.backend, SessionBackend::Redis) && session.redis_url.is_none() {
+                problems.push("`session.redis_url` is required when `session.backend` is redis.".to_string());
+            }
+            if session.redis_pool_size == Some(0) {
+                problems.push("`session.redis_pool_size` must be at least 1.".to_string());
+            }
+            match session.migrate_from {
+                Some(SessionBackend::Memory) => problems.push(
+                    "`session.migrate_from` can't be memory: those sessions are gone once the server stops.".to_string(),
+                ),
+                Some(from) if from == session.backend => {
+                    problems.push("`session.migrate_from` is the same as `session.backend`.".to_string())
+                }
+                Some(SessionBackend::Redis) if session.redis_url.is_none() => problems.push(
+                    "`session.redis_url` is required to migrate sessions from redis; keep the old one there.".to_string(),
+                ),
+                _ => {}
+            }
+            if session.redis_connect_attempts == Some(0) {
+                problems.push("`session.redis_connect_attempts` must be at least 1.".to_string());
+            }
+            if !session.cookie_path.starts_with('/') {
+                problems.push("`session.cookie_path` must start with a slash.".to_string());
+            }
+            if session.cookie_max_age.is_some_and(|age| age < 0) {
+                problems.push("`session.cookie_max_age` can't be negative.".to_string());
+            }
+            if session.id_generator.as_ref().is_some_and(|generator| !generator.contains('.')) {
+                problems.push("`session.id_generator` must be `module.function`.".to_string());
+            }
+            let mut cookie_names = vec![session.cookie_name.as_str()];
+            for (i, scope) in session.scopes.iter().flatten().enumerate() {
+                let name = format!("session.scopes[{}]", i);
+                if !scope.path_prefix.starts_with('/') || scope.path_prefix.trim_end_matches('/').is_empty() {
+                    problems.push(format!("`{}.path_prefix` must start with a slash and can't be the whole site.", name));
+                }
+                if scope.cookie_path.as_ref().is_some_and(|path| !path.starts_with('/')) {
+                    problems.push(format!("`{}.cookie_path` must start with a slash.", name));
+                }
+                if cookie_names.contains(&scope.cookie_name.as_str()) {
+                    problems.push(format!("`{}.cookie_name` is already used by another session cookie.", name));
+                }
+                cookie_names.push(&scope.cookie_name);
+                if let Some(same_site) = &scope.cookie_same_site
+                    && !["strict", "lax", "none"].contains(&same_site.to_lowercase().as_str())
+                {
+                    problems.push(format!("`{}.cookie_same_site` must be strict, lax or none.", name));
+                }
+                if scope.cookie_max_age.is_some_and(|age| age < 0) {
+                    problems.push(format!("`{}.cookie_max_age` can't be negative.", name));
+                }
+            }
+        }
+
+        problems
     }
 }
 
@@ -113,7 +1047,17 @@ cfg_if! {
         lazy_static! {
             pub static ref CONFIG: Config = {
                 let config_path = BASE_PATH.join("config.yaml");
-                match Config::from_file(config_path.to_str().unwrap()) {
+                let loaded = Config::from_file(config_path.to_str().unwrap()).and_then(|mut config| {
+                    if let Some(overrides) = OVERRIDES.get() {
+                        config.apply_overrides(overrides);
+                        let problems = config.validate();
+                        if !problems.is_empty() {
+                            return Err(ConfigError::Invalid(problems));
+                        }
+                    }
+                    Ok(config)
+                });
+                match loaded {
                     Ok(config) => config,
                     Err(e) => {
                         match e {
@@ -124,6 +1068,12 @@ cfg_if! {
                                 println!("There seems to be a syntax error in your `config.yaml` file. Please check the formatting.");
                                 println!("Details: {}", err);
                             }
+                            ConfigError::Invalid(problems) => {
+                                println!("Oh no! Your `config.yaml` has {} problem(s) to fix before we can start:", problems.len());
+                                for problem in problems {
+                                    println!("  - {}", problem);
+                                }
+                            }
                         }
                         std::process::exit(1);
                     }
@@ -161,14 +1111,15 @@ static_path: /static
 static_url_prefix: /static-prefix
 session:
   backend: cookie
-  secret_key: a-very-secret-key
+  secret_key: a-very-secret-key-that-is-long-enough-to-sign-cookies-with-actix-web-0123456789
   cookie_name: my-session
   cookie_secure: true
   cookie_http_only: true
   cookie_path: /
   cookie_domain: example.com
   cookie_max_age: 3600
-  compression: true
+  redis_fallback: read-only
+compression: true
 ",
         )
         .unwrap();
@@ -197,13 +1148,14 @@ session:
 
         let session = config.session.unwrap();
         assert!(matches!(session.backend, SessionBackend::Cookie));
-        assert_eq!(session.secret_key, "a-very-secret-key");
+        assert_eq!(session.secret_key, "a-very-secret-key-that-is-long-enough-to-sign-cookies-with-actix-web-0123456789");
         assert_eq!(session.cookie_name, "my-session");
         assert!(session.cookie_secure);
         assert!(session.cookie_http_only);
         assert_eq!(session.cookie_path, "/");
         assert_eq!(session.cookie_domain, Some("example.com".to_string()));
         assert_eq!(session.cookie_max_age, Some(3600));
+        assert_eq!(session.redis_fallback, Some(RedisFallback::ReadOnly));
     }
 
     #[test]
@@ -223,6 +1175,344 @@ session:
         assert!(matches!(result, Err(ConfigError::Io(_))));
     }
 
+    #[test]
+    fn test_config_reports_every_problem() {
+        let dir = tempdir().unwrap();
+        let config_path = dir.path().join("config.yaml");
+        std::fs::write(
+            &config_path,
+            "
+prot: 8080
+port: 70000
+static_url_prefix: static
+log_level: loud
+session:
+  backend: redis
+  secret_key: short
+  cookie_name: s
+  cookie_secure: false
+  cookie_http_only: true
+  cookie_path: /
+  cookie_max_agee: 10
+",
+        )
+        .unwrap();
+
+        let Err(ConfigError::Invalid(problems)) = Config::from_file(config_path.to_str().unwrap()) else {
+            panic!("expected an invalid config");
+        };
+        assert_eq!(problems.len(), 7, "{:?}", problems);
+        assert!(problems.contains(&"`prot` isn't a known setting. Did you mean `port`?".to_string()));
+        assert!(problems.contains(&"`session.cookie_max_agee` isn't a known setting. Did you mean `session.cookie_max_age`?".to_string()));
+        assert!(problems.iter().any(|p| p.starts_with("`port` must be between 1 and 65535")));
+        assert!(problems.iter().any(|p| p.starts_with("`static_url_prefix` must start with a slash")));
+        assert!(problems.iter().any(|p| p.contains("unknown level 'loud'")));
+        assert!(problems.iter().any(|p| p.starts_with("`session.secret_key` must be at least 64")));
+        assert!(problems.iter().any(|p| p.starts_with("`session.redis_url` is required")));
+    }
+
+    #[test]
+    fn test_config_type_errors_are_reported_with_unknown_keys() {
+        let dir = tempdir().unwrap();
+        let config_path = dir.path().join("config.yaml");
+        std::fs::write(&config_path, "port: eighty
+extra: true
+").unwrap();
+
+        let Err(ConfigError::Invalid(problems)) = Config::from_file(config_path.to_str().unwrap()) else {
+            panic!("expected an invalid config");
+        };
+        assert_eq!(problems.len(), 2);
+        assert!(problems[0].contains("`extra`"));
+    }
+
+    #[test]
+    fn test_validate_bind() {
+        let config = Config {
+            bind: Some("unix:/run/noventa.sock".to_string()),
+            unix_socket_mode: Some("0660".to_string()),
+            ..Default::default()
+        };
+        assert!(config.validate().is_empty());
+
+        let config = Config {
+            bind: Some("0.0.0.0:8080".to_string()),
+            unix_socket_mode: Some("rw".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(config.validate().len(), 2);
+    }
+
+    #[test]
+    fn test_validate_site_url() {
+        let config = Config { site_url: Some("https://example.com".to_string()), ..Default::default() };
+        assert!(config.validate().is_empty());
+        let config = Config { site_url: Some("example.com".to_string()), ..Default::default() };
+        assert_eq!(config.validate().len(), 1);
+    }
+
+    #[test]
+    fn test_validate_warm_routes() {
+        let config = Config {
+            warm_routes: Some(vec!["/".to_string(), "/products?page=1".to_string(), "pricing".to_string()]),
+            ..Default::default()
+        };
+        let problems = config.validate();
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].contains("'pricing'"));
+    }
+
+    #[test]
+    fn test_validate_load_shedding() {
+        let config = Config {
+            load_shedding: Some(LoadSheddingConfig {
+                critical_routes: Some(vec!["/checkout".to_string(), "/api/payments/*".to_string(), "login".to_string()]),
+                busy_page: Some("static/busy.txt".to_string()),
+                retry_after: Some(0),
+            }),
+            ..Default::default()
+        };
+        let problems = config.validate();
+        assert_eq!(problems.len(), 3);
+        assert!(problems[0].contains("'login'"));
+        assert!(problems[1].contains("`load_shedding.busy_page`"));
+        assert!(problems[2].contains("`load_shedding.retry_after`"));
+    }
+
+    #[test]
+    fn test_validate_telemetry() {
+        let config = Config {
+            telemetry: Some(TelemetryConfig {
+                otlp_endpoint: Some("localhost:4318".to_string()),
+                sample_ratio: Some(1.5),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let problems = config.validate();
+        assert_eq!(problems.len(), 2);
+        assert!(problems[0].contains("'localhost:4318'"));
+        assert!(problems[1].contains("`telemetry.sample_ratio`"));
+    }
+
+    #[test]
+    fn test_validate_error_reporting() {
+        let config = Config {
+            error_reporting: Some(ErrorReportingConfig {
+                sentry_dsn: Some("https://o123.ingest.sentry.io/456".to_string()),
+                max_per_minute: Some(0),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let problems = config.validate();
+        assert_eq!(problems.len(), 2);
+        assert!(problems[0].contains("'https://o123.ingest.sentry.io/456'"));
+        assert!(problems[1].contains("`error_reporting.max_per_minute`"));
+    }
+
+    #[test]
+    fn test_validate_lsp() {
+        let config = Config {
+            lsp: Some(LspConfig { address: Some("localhost".to_string()), port: Some(0) }),
+            ..Default::default()
+        };
+        let problems = config.validate();
+        assert_eq!(problems.len(), 2);
+        assert!(problems[0].contains("'localhost'"));
+        assert!(problems[1].contains("`lsp.port`"));
+    }
+
+    #[test]
+    fn test_validate_trusted_proxies() {
+        let config = Config {
+            trusted_proxies: Some(vec!["10.0.0.0/8".to_string(), "unix".to_string(), "my-lb".to_string()]),
+            ..Default::default()
+        };
+        let problems = config.validate();
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].contains("'my-lb'"));
+    }
+
+    #[test]
+    fn test_validate_component_state() {
+        let mut config = Config {
+            component_state: Some(ComponentStateConfig { backend: Some(StateBackend::Redis), ..Default::default() }),
+            ..Default::default()
+        };
+        let problems = config.validate();
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].contains("component_state.redis_url"));
+
+        config.cluster = Some(ClusterConfig { redis_url: "redis://127.0.0.1:6379".to_string(), channel: None });
+        assert!(config.validate().is_empty());
+    }
+
+    #[test]
+    fn test_validate_admin_token() {
+        let config = Config {
+            admin: Some(AdminConfig { token: Some("hunter2".to_string()) }),
+            ..Default::default()
+        };
+        let problems = config.validate();
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].contains("`admin.token`"));
+    }
+
+    #[test]
+    fn test_validate_auth() {
+        let config = Config {
+            auth: Some(AuthConfig {
+                login_url: Some("login".to_string()),
+                user_loader: Some("load_user".to_string()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let problems = config.validate();
+        assert_eq!(problems.len(), 2);
+        assert!(problems[0].contains("`auth.login_url`"));
+        assert!(problems[1].contains("'load_user'"));
+
+        let config = Config {
+            auth: Some(AuthConfig {
+                roles: Some(HashMap::from([("editor".to_string(), vec!["posts.edit".to_string(), " ".to_string()])])),
+                forbidden_page: Some("pages/forbidden".to_string()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let problems = config.validate();
+        assert_eq!(problems.len(), 2);
+        assert!(problems[0].contains("`auth.forbidden_page`"));
+        assert!(problems[1].contains("`auth.roles.editor`"));
+
+        let config = Config {
+            auth: Some(AuthConfig { user_loader: Some("models.users.load_user".to_string()), ..Default::default() }),
+            ..Default::default()
+        };
+        assert!(config.validate().is_empty());
+    }
+
+    #[test]
+    fn test_validate_oidc() {
+        let provider = |issuer: &str| OidcProviderConfig {
+            issuer: issuer.to_string(),
+            client_id: "app".to_string(),
+            ..Default::default()
+        };
+        let config = Config {
+            oidc: Some(HashMap::from([
+                ("google".to_string(), provider("https://accounts.google.com")),
+                ("my idp".to_string(), provider("accounts.example.com")),
+            ])),
+            ..Default::default()
+        };
+        let problems = config.validate();
+        assert_eq!(problems.len(), 3);
+        assert!(problems[0].contains("`oidc.my idp` needs a name"));
+        assert!(problems[1].contains("`oidc.my idp.issuer`"));
+        assert!(problems[2].contains("needs a `session` section"));
+    }
+
+    #[test]
+    fn test_validate_upload_storage() {
+        let config = Config {
+            upload_storage: Some(UploadStorageConfig {
+                backend: UploadBackendKind::S3,
+                endpoint: Some("storage.googleapis.com".to_string()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let problems = config.validate();
+        assert_eq!(problems.len(), 2);
+        assert!(problems[0].contains("`upload_storage.bucket`"));
+        assert!(problems[1].contains("`upload_storage.endpoint`"));
+    }
+
+    #[test]
+    fn test_validate_images() {
+        let config = Config {
+            images: Some(ImagesConfig { widths: Some(vec![]), quality: Some(0), ..Default::default() }),
+            ..Default::default()
+        };
+        let problems = config.validate();
+        assert_eq!(problems.len(), 3);
+        assert!(problems[0].contains("`static_path`"));
+        assert!(problems[1].contains("`images.widths`"));
+        assert!(problems[2].contains("`images.quality`"));
+    }
+
+    #[test]
+    fn test_validate_session_migration() {
+        let mut session: SessionConfig = serde_yaml::from_str(
+            "backend: redis\nsecret_key: x\ncookie_name: s\ncookie_secure: false\ncookie_http_only: true\ncookie_path: /\nredis_url: redis://127.0.0.1/\nmigrate_from: memory",
+        )
+        .unwrap();
+        let config = |session: &SessionConfig| Config { session: Some(session.clone()), ..Default::default() };
+        assert!(config(&session).validate().iter().any(|p| p.contains("can't be memory")));
+        session.migrate_from = Some(SessionBackend::Redis);
+        assert!(config(&session).validate().iter().any(|p| p.contains("same as `session.backend`")));
+        session.migrate_from = Some(SessionBackend::Cookie);
+        assert!(!config(&session).validate().iter().any(|p| p.contains("migrate_from")));
+    }
+
+    #[test]
+    fn test_validate_session_scopes() {
+        let mut session: SessionConfig = serde_yaml::from_str(&format!(
+            "backend: memory\nsecret_key: {}\ncookie_name: s\ncookie_secure: false\ncookie_http_only: true\ncookie_path: /\nscopes:\n  - path_prefix: /admin\n    cookie_name: s_admin\n    cookie_same_site: strict",
+            "a".repeat(64)
+        ))
+        .unwrap();
+        let config = |session: &SessionConfig| Config { session: Some(session.clone()), ..Default::default() };
+        assert!(config(&session).validate().is_empty());
+
+        let scope = &mut session.scopes.as_mut().unwrap()[0];
+        scope.cookie_name = "s".to_string();
+        scope.path_prefix = "/".to_string();
+        scope.cookie_same_site = Some("sometimes".to_string());
+        session.id_generator = Some("new_id".to_string());
+        let problems = config(&session).validate();
+        assert_eq!(problems.len(), 4);
+        assert!(problems[0].contains("`session.id_generator`"));
+        assert!(problems[1].contains("`session.scopes[0].path_prefix`"));
+        assert!(problems[2].contains("`session.scopes[0].cookie_name` is already used"));
+        assert!(problems[3].contains("`session.scopes[0].cookie_same_site`"));
+    }
+
+    #[test]
+    fn test_validate_old_secret_keys() {
+        let mut session: SessionConfig = serde_yaml::from_str(&format!(
+            "backend: cookie\nsecret_key: {}\ncookie_name: s\ncookie_secure: false\ncookie_http_only: true\ncookie_path: /",
+            "a".repeat(64)
+        ))
+        .unwrap();
+        session.old_secret_keys = Some(vec!["short".to_string(), "a".repeat(64), "b".repeat(64)]);
+        let problems = Config { session: Some(session), ..Default::default() }.validate();
+        assert_eq!(problems.len(), 2);
+        assert!(problems[0].contains("entry 1 must be at least 64"));
+        assert!(problems[1].contains("entry 2 is the same"));
+    }
+
+    #[test]
+    fn test_apply_overrides() {
+        let mut config = Config {
+            server_address: Some("127.0.0.1".to_string()),
+            port: Some(8080),
+            log_level: Some("info".to_string()),
+            ..Default::default()
+        };
+        config.apply_overrides(&ConfigOverrides {
+            port: Some(3001),
+            log_level: Some("debug".to_string()),
+            ..Default::default()
+        });
+        assert_eq!(config.server_address.as_deref(), Some("127.0.0.1"));
+        assert_eq!(config.port, Some(3001));
+        assert_eq!(config.log_level.as_deref(), Some("debug"));
+    }
+
     #[test]
     fn test_find_config_file() {
         // Test with current directory (should work regardless of config.yaml presence)
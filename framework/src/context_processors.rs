@@ -0,0 +1,76 @@
+use crate::actors::interpreter::{PythonInterpreterActor, RunContextProcessors};
+use crate::actors::page_renderer::HttpRequestInfo;
+use crate::actors::session_manager::SessionManagerActor;
+use crate::errors::{DetailedError, ErrorSource};
+use crate::interpreter_queue;
+use actix::Addr;
+use minijinja::{Environment, Value};
+use std::path::Path;
+use std::sync::Arc;
+
+// The project's `context_processors.py`. Every function in it is called once per request, with the
+// request (and `session` and `db` if it takes them), and the dicts they return are available in every
+// page and component, e.g. {{ current_user.name }} without each `_logic.py` loading it.
+pub const MODULE: &str = "context_processors";
+
+pub fn exists(base: &Path) -> bool {
+    base.join(format!("{}.py", MODULE)).is_file()
+}
+
+// What the context processors returned for this request, or nothing if the project has none.
+#[allow(clippy::result_large_err)]
+pub async fn load(
+    interpreter: &Addr<PythonInterpreterActor>,
+    request: &Arc<HttpRequestInfo>,
+    session_manager: &Addr<SessionManagerActor>,
+) -> Result<serde_json::Map<String, serde_json::Value>, DetailedError> {
+    if !exists(&crate::config::BASE_PATH) {
+        return Ok(serde_json::Map::new());
+    }
+    let message = RunContextProcessors { request: Arc::clone(request), session_manager: session_manager.clone() };
+    match interpreter_queue::send(interpreter, message).await {
+        Ok(Ok(context)) => Ok(context),
+        Ok(Err(py_err)) => Err(DetailedError {
+            error_source: Some(ErrorSource::Python(py_err.clone())),
+            message: py_err.message.clone(),
+            file_path: py_err.filename.clone().unwrap_or_else(|| format!("{}.py", MODULE)),
+            line: py_err.line_number.unwrap_or(0) as u32,
+            column: py_err.column_number.unwrap_or(0) as u32,
+            end_line: py_err.end_line_number.map(|l| l as u32),
+            end_column: py_err.end_column_number.map(|c| c as u32),
+            ..Default::default()
+        }),
+        Err(e) => {
+            log::error!("A mailbox error occurred while running the context processors: {}", e);
+            Err(DetailedError { message: e.to_string(), file_path: format!("{}.py", MODULE), ..Default::default() })
+        }
+    }
+}
+
+// Globals, so the page's and each component's own context still wins over them.
+pub fn apply(env: &mut Environment<'static>, context: serde_json::Map<String, serde_json::Value>) {
+    for (name, value) in context {
+        env.add_global(name, Value::from_serialize(value));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply() {
+        let mut env = Environment::new();
+        env.add_template("page.html", "{{ site.name }} {{ current_user }}").unwrap();
+        let context = serde_json::json!({ "site": { "name": "Shop" }, "current_user": "ana" });
+        apply(&mut env, context.as_object().unwrap().clone());
+        let page = env.get_template("page.html").unwrap();
+        assert_eq!(page.render(minijinja::context! {}).unwrap(), "Shop ana");
+        assert_eq!(page.render(minijinja::context! { current_user => "bo" }).unwrap(), "Shop bo");
+
+        let project = tempfile::tempdir().unwrap();
+        assert!(!exists(project.path()));
+        std::fs::write(project.path().join("context_processors.py"), "").unwrap();
+        assert!(exists(project.path()));
+    }
+}
@@ -0,0 +1,235 @@
+use crate::components::{scan_project_components, Component};
+use crate::routing::resolve_routes;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use std::collections::{BTreeSet, HashSet};
+use std::path::{Path, PathBuf};
+
+pub(crate) static TEMPLATE_REF_REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r#"\{%-?\s*(?:extends|include|import|from)\s+["']([^"']+)["']"#).unwrap());
+pub(crate) static COMPONENT_REF_REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r#"component\s*\(\s*["']([^"']+)["']"#).unwrap());
+pub(crate) static ASSET_REF_REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r#"(?:src|href)\s*=\s*["']([^"'#?]+)"#).unwrap());
+
+// Everything a page needs to render, as paths relative to the project root.
+#[derive(Debug, Default)]
+pub struct PageDependencies {
+    pub page: String,
+    pub route: Option<String>,
+    pub layouts: BTreeSet<String>,
+    pub partials: BTreeSet<String>,
+    pub components: BTreeSet<String>,
+    pub component_files: BTreeSet<String>,
+    pub logic_modules: BTreeSet<String>,
+    pub static_assets: BTreeSet<String>,
+    pub missing: BTreeSet<String>,
+}
+
+// Where static files live and the URL prefix they're served under.
+pub struct StaticFiles<'a> {
+    pub url_prefix: &'a str,
+    pub dir: PathBuf,
+}
+
+fn relative(root: &Path, path: &Path) -> String {
+    let path = path.strip_prefix("./").unwrap_or(path);
+    crate::paths::relative_name(path, root)
+}
+
+fn module_name(relative_path: &str) -> String {
+    relative_path.strip_suffix(".py").unwrap_or(relative_path).replace('/', ".")
+}
+
+// Accepts a page file (`pages/blog/[id].html`), a route pattern (`/blog/{id}`) or a concrete URL (`/blog/42`).
+pub fn resolve_page(root: &Path, page_or_route: &str) -> Option<(PathBuf, Option<String>)> {
+    let (routes, _) = resolve_routes(&root.join("pages"));
+
+    // Anything starting with a slash is a route; page files are always relative to the project.
+    let as_file = root.join(page_or_route.trim_start_matches("./"));
+    if !page_or_route.starts_with('/') && as_file.is_file() {
+        let route = routes
+            .iter()
+            .find(|r| r.template_path == as_file)
+            .map(|r| r.route_pattern.clone());
+        return Some((as_file, route));
+    }
+
+    let path = if page_or_route.starts_with('/') {
+        page_or_route.to_string()
+    } else {
+        format!("/{}", page_or_route)
+    };
+    routes
+        .iter()
+        .find(|r| r.route_pattern == path)
+        .or_else(|| routes.iter().find(|r| r.regex.is_match(&path)))
+        .map(|r| (r.template_path.clone(), Some(r.route_pattern.clone())))
+}
+
+struct Collector<'a> {
+    root: &'a Path,
+    components: Vec<Component>,
+    static_files: Option<StaticFiles<'a>>,
+    visited: HashSet<String>,
+    deps: PageDependencies,
+}
+
+impl Collector<'_> {
+    fn scan_template(&mut self, name: &str, source: &str) {
+        if !self.visited.insert(name.to_string()) {
+            return;
+        }
+
+        for caps in TEMPLATE_REF_REGEX.captures_iter(source) {
+            let target = caps[1].to_string();
+            match std::fs::read_to_string(self.root.join(&target)) {
+                Ok(content) => {
+                    if target.starts_with("layouts/") {
+                        self.deps.layouts.insert(target.clone());
+                    } else {
+                        self.deps.partials.insert(target.clone());
+                    }
+                    self.scan_template(&target, &content);
+                }
+                Err(_) => {
+                    self.deps.missing.insert(target);
+                }
+            }
+        }
+
+        for caps in COMPONENT_REF_REGEX.captures_iter(source) {
+            let id = caps[1].replace('.', "/");
+            let Some(component) = self.components.iter().find(|c| c.id == id).cloned() else {
+                self.deps.missing.insert(format!("component '{}'", &caps[1]));
+                continue;
+            };
+            self.deps.components.insert(caps[1].replace('/', "."));
+
+            let template_path = relative(self.root, Path::new(&component.template_path));
+            self.deps.component_files.insert(template_path.clone());
+            if let Some(logic_path) = &component.logic_path {
+                let logic_path = relative(self.root, Path::new(logic_path));
+                self.deps.logic_modules.insert(module_name(&logic_path));
+                self.deps.component_files.insert(logic_path);
+            }
+            if let Some(component_dir) = Path::new(&component.template_path).parent()
+                && let Ok(entries) = std::fs::read_dir(component_dir)
+            {
+                for entry in entries.filter_map(Result::ok) {
+                    if entry.file_name().to_string_lossy().ends_with("_models.py") {
+                        let models_path = relative(self.root, &entry.path());
+                        self.deps.logic_modules.insert(module_name(&models_path));
+                        self.deps.component_files.insert(models_path);
+                    }
+                }
+            }
+            self.scan_template(&template_path, &component.template_content);
+        }
+
+        if let Some(static_files) = &self.static_files {
+            let prefix = format!("{}/", static_files.url_prefix.trim_end_matches('/'));
+            for caps in ASSET_REF_REGEX.captures_iter(source) {
+                if let Some(asset) = caps[1].strip_prefix(&prefix) {
+                    if asset.starts_with("noventa-static/") {
+                        continue;
+                    }
+                    let asset_path = static_files.dir.join(asset);
+                    if asset_path.is_file() {
+                        self.deps.static_assets.insert(relative(self.root, &asset_path));
+                    } else {
+                        self.deps.missing.insert(caps[1].to_string());
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Walks the page's layouts, includes and component tree to find every file it depends on.
+pub fn collect_page_dependencies(
+    root: &Path,
+    page_or_route: &str,
+    static_files: Option<StaticFiles>,
+) -> Result<PageDependencies, String> {
+    let (page_path, route) = resolve_page(root, page_or_route)
+        .ok_or_else(|| format!("No page matches '{}'. Pass a file inside pages/ or a route like /about.", page_or_route))?;
+    let source = std::fs::read_to_string(&page_path).map_err(|e| format!("Failed to read the page: {}", e))?;
+
+    let mut collector = Collector {
+        root,
+        components: scan_project_components(root).unwrap_or_default(),
+        static_files,
+        visited: HashSet::new(),
+        deps: PageDependencies {
+            page: relative(root, &page_path),
+            route,
+            ..Default::default()
+        },
+    };
+    let page_name = collector.deps.page.clone();
+    // A `layout:` in the frontmatter counts like an `{% extends %}`.
+    collector.scan_template(&page_name, &crate::page_meta::template_source(&source));
+    Ok(collector.deps)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    fn write(root: &Path, relative: &str, content: &str) {
+        let path = root.join(relative);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    fn project() -> tempfile::TempDir {
+        let dir = tempdir().unwrap();
+        let root = dir.path();
+        write(root, "layouts/base.html", "<link href=\"/static/site.css\">{% include \"layouts/nav.html\" %}{% block content %}{% endblock %}");
+        write(root, "layouts/nav.html", "{{ component('menu') }}");
+        write(root, "files/site.css", "body {}");
+        write(root, "pages/blog/[slug].html", "{% extends \"layouts/base.html\" %}{% block content %}{{ component('cards.post', slug='x') }}<img src=\"/static/missing.png\">{% endblock %}");
+        write(root, "components/menu/menu_template.html", "<nav></nav>");
+        write(root, "components/cards/post/post_template.html", "{{ component('cards.author') }}");
+        write(root, "components/cards/post/post_logic.py", "def load_template_context(request): return {}");
+        write(root, "components/cards/post/post_models.py", "");
+        write(root, "components/cards/author/author_template.html", "<p></p>");
+        dir
+    }
+
+    #[test]
+    fn test_collect_page_dependencies() {
+        let dir = project();
+        let root = dir.path();
+        let static_files = StaticFiles { url_prefix: "/static", dir: root.join("files") };
+        let deps = collect_page_dependencies(root, "/blog/hello", Some(static_files)).unwrap();
+
+        assert_eq!(deps.page, "pages/blog/[slug].html");
+        assert_eq!(deps.route.as_deref(), Some("/blog/{slug}"));
+        assert!(deps.layouts.contains("layouts/base.html"));
+        assert!(deps.layouts.contains("layouts/nav.html"));
+        assert_eq!(
+            deps.components.iter().cloned().collect::<Vec<_>>(),
+            vec!["cards.author", "cards.post", "menu"]
+        );
+        assert!(deps.component_files.contains("components/cards/post/post_logic.py"));
+        assert!(deps.component_files.contains("components/cards/post/post_models.py"));
+        assert!(deps.logic_modules.contains("components.cards.post.post_logic"));
+        assert!(deps.static_assets.contains("files/site.css"));
+        assert!(deps.missing.contains("/static/missing.png"));
+    }
+
+    #[test]
+    fn test_resolve_page_by_file_and_route() {
+        let dir = project();
+        let root = dir.path();
+        let (path, route) = resolve_page(root, "pages/blog/[slug].html").unwrap();
+        assert_eq!(path, root.join("pages/blog/[slug].html"));
+        assert_eq!(route.as_deref(), Some("/blog/{slug}"));
+        assert!(resolve_page(root, "/blog/{slug}").is_some());
+        assert!(resolve_page(root, "/nowhere/at/all").is_none());
+    }
+}
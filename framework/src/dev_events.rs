@@ -0,0 +1,107 @@
+use actix_web::{HttpRequest, HttpResponse};
+use lazy_static::lazy_static;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// How many events `/_noventa/events` remembers; older ones drop off.
+const MAX_EVENTS: usize = 500;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    // The watcher saw a file change.
+    FileChanged,
+    RoutesReloaded,
+    ComponentsRescanned,
+    InterpreterReloaded,
+    // Everything was up to date again and the browsers were told to reload.
+    BrowserReloaded,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DevEvent {
+    // Increases by one per event, so pollers can ask for what came after the last one they saw.
+    pub seq: u64,
+    // Unix time in milliseconds.
+    pub at: u64,
+    pub kind: EventKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    // From the file change to this step being done.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration_ms: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+struct EventLog {
+    next_seq: u64,
+    events: VecDeque<DevEvent>,
+}
+
+lazy_static! {
+    static ref EVENTS: Mutex<EventLog> = Mutex::new(EventLog { next_seq: 1, events: VecDeque::new() });
+}
+
+pub fn record(kind: EventKind, path: Option<&str>, duration: Option<Duration>, error: Option<String>) {
+    let mut log = EVENTS.lock().unwrap();
+    let event = DevEvent {
+        seq: log.next_seq,
+        at: SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_millis() as u64),
+        kind,
+        path: path.map(str::to_string),
+        duration_ms: duration.map(|duration| duration.as_secs_f64() * 1000.0),
+        error,
+    };
+    log::trace!("Dev event: {:?}", event);
+    log.next_seq += 1;
+    if log.events.len() == MAX_EVENTS {
+        log.events.pop_front();
+    }
+    log.events.push_back(event);
+}
+
+fn since(seq: u64) -> Vec<DevEvent> {
+    EVENTS.lock().unwrap().events.iter().filter(|event| event.seq > seq).cloned().collect()
+}
+
+// `GET /_noventa/events?since=42`: the build activity after event 42 as JSON lines, for editor
+// extensions and the dev toolbar. Only the dev server has it.
+pub async fn events_handler(req: HttpRequest) -> HttpResponse {
+    let after = serde_urlencoded::from_str::<Vec<(String, String)>>(req.query_string())
+        .unwrap_or_default()
+        .into_iter()
+        .find(|(name, _)| name == "since")
+        .and_then(|(_, value)| value.parse().ok())
+        .unwrap_or(0);
+    let body: String = since(after)
+        .iter()
+        .filter_map(|event| serde_json::to_string(event).ok())
+        .map(|line| line + "\n")
+        .collect();
+    HttpResponse::Ok().content_type("application/x-ndjson").body(body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_events_since() {
+        record(EventKind::FileChanged, Some("pages/index.html"), None, None);
+        let last_seen = since(0).last().unwrap().seq;
+        record(EventKind::RoutesReloaded, None, Some(Duration::from_millis(12)), None);
+        record(EventKind::ComponentsRescanned, None, None, Some("bad component".to_string()));
+
+        let newer = since(last_seen);
+        assert_eq!(newer.len(), 2);
+        assert_eq!(newer[0].kind, EventKind::RoutesReloaded);
+        assert_eq!(newer[0].duration_ms, Some(12.0));
+        let line = serde_json::to_value(&newer[1]).unwrap();
+        assert_eq!(line["kind"], "components_rescanned");
+        assert_eq!(line["error"], "bad component");
+        assert!(line.get("path").is_none());
+    }
+}
@@ -0,0 +1,305 @@
+// framework/src/disco/explain.rs
+use crate::errors::{DetailedError, ErrorSource};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use std::path::{Path, PathBuf};
+
+// Lines shown above and below the failing line.
+const CONTEXT_LINES: usize = 5;
+
+static MISSING_ATTRIBUTE_REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"module '([\w.]+)' has no attribute '(\w+)'").unwrap());
+static MISSING_MODULE_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"No module named '([\w.]+)'").unwrap());
+static MISSING_COMPONENT_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"Component '([^']+)' not found").unwrap());
+
+pub struct ImplicatedFile {
+    pub path: String,
+    pub line: Option<usize>,
+    pub excerpt: Option<String>,
+}
+
+pub struct Explanation {
+    pub summary: String,
+    pub files: Vec<ImplicatedFile>,
+    pub hints: Vec<String>,
+}
+
+fn relative(root: &Path, path: &str) -> String {
+    let path = Path::new(path);
+    path.strip_prefix(root).unwrap_or(path).to_string_lossy().replace('\\', "/")
+}
+
+// Numbered lines around `line`, with the failing one marked.
+fn excerpt(source: &str, line: usize) -> String {
+    let lines: Vec<&str> = source.lines().collect();
+    let line = line.clamp(1, lines.len().max(1));
+    let start = line.saturating_sub(CONTEXT_LINES + 1);
+    let end = (line + CONTEXT_LINES).min(lines.len());
+    lines[start..end]
+        .iter()
+        .enumerate()
+        .map(|(i, text)| {
+            let number = start + i + 1;
+            let marker = if number == line { ">" } else { " " };
+            format!("{} {:>4} | {}", marker, number, text)
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// Only files inside the project are read; anything else is listed without its source.
+fn implicate(root: &Path, files: &mut Vec<ImplicatedFile>, path: &str, line: Option<usize>, fallback_source: Option<&str>) {
+    if path.is_empty() {
+        return;
+    }
+    let absolute: PathBuf = if Path::new(path).is_absolute() { PathBuf::from(path) } else { root.join(path) };
+    let path = relative(root, path);
+    if files.iter().any(|f| f.path == path && f.line == line) {
+        return;
+    }
+    let source = if absolute.starts_with(root) { std::fs::read_to_string(&absolute).ok() } else { None };
+    let excerpt = match (line, source.as_deref().or(fallback_source)) {
+        (Some(line), Some(source)) if line > 0 => Some(excerpt(source, line)),
+        _ => None,
+    };
+    files.push(ImplicatedFile { path, line, excerpt });
+}
+
+fn component_files(root: &Path, name: &str) -> Vec<String> {
+    let dir = root.join("components").join(name.replace('.', "/"));
+    let mut files: Vec<String> = std::fs::read_dir(&dir)
+        .map(|entries| {
+            entries
+                .filter_map(Result::ok)
+                .map(|e| e.path())
+                .filter(|p| p.is_file())
+                .map(|p| relative(root, &p.to_string_lossy()))
+                .collect()
+        })
+        .unwrap_or_default();
+    files.sort();
+    files
+}
+
+fn hints_for(error: &DetailedError, root: &Path) -> Vec<String> {
+    let mut hints = Vec::new();
+    let message = &error.message;
+    let component = error.component.as_ref().map(|c| c.name.replace('/', "."));
+    let traceback = match &error.error_source {
+        Some(ErrorSource::Python(py)) => py.traceback.as_str(),
+        Some(ErrorSource::Template(info)) => info.traceback.as_deref().unwrap_or(""),
+        None => "",
+    };
+    let text = format!("{}\n{}", message, traceback);
+
+    if let Some(caps) = MISSING_ATTRIBUTE_REGEX.captures(&text) {
+        let (module, function) = (&caps[1], &caps[2]);
+        let owner = component.clone().unwrap_or_else(|| module.to_string());
+        if let Some(action) = function.strip_prefix("action_") {
+            hints.push(format!(
+                "The form posted action '{}', but component '{}' has no `{}` function. Add `def {}(request, session, db, **props):` to {}, or fix the hidden `action` input in its template.",
+                action, owner, function, function, module.replace('.', "/") + ".py"
+            ));
+        } else if function == "load_template_context" {
+            hints.push(format!(
+                "Component '{}' has a logic file without `load_template_context`. Add `def load_template_context(request, session, db, **props):` to {} and return a dict.",
+                owner, module.replace('.', "/") + ".py"
+            ));
+        } else {
+            hints.push(format!("`{}` doesn't define `{}`. Check the spelling or add it.", module, function));
+        }
+    }
+
+    if let Some(caps) = MISSING_MODULE_REGEX.captures(&text) {
+        let module = &caps[1];
+        if root.join(module.replace('.', "/")).exists() || root.join(format!("{}.py", module.replace('.', "/"))).exists() {
+            hints.push(format!(
+                "'{}' exists in the project but can't be imported. Make sure every folder on the way has an __init__.py.",
+                module
+            ));
+        } else {
+            hints.push(format!(
+                "Python can't find the '{}' package. Add it to requirements.txt and install it in the environment noventa runs with.",
+                module.split('.').next().unwrap_or(module)
+            ));
+        }
+    }
+
+    if let Some(caps) = MISSING_COMPONENT_REGEX.captures(message) {
+        hints.push(format!(
+            "There's no folder for component '{}' under components/. Create it with `noventa new:component {}` or fix the name in the template.",
+            &caps[1],
+            caps[1].replace('/', ".")
+        ));
+    }
+
+    if message.contains("requires an action to be specified") {
+        hints.push(format!(
+            "A form in {} posted without an action. Add `<input type=\"hidden\" name=\"action\" value=\"...\">` so the matching `action_...` function runs.",
+            component.as_deref().map(|c| format!("component '{}'", c)).unwrap_or_else(|| "this page".to_string())
+        ));
+    }
+
+    if message.contains("No component found for the given component_id") {
+        hints.push("The POST didn't name a component rendered on this page. Keep the form inside the component's own template so noventa can tag it with the right component_id.".to_string());
+    }
+
+    if message.contains("Component map not found for page") {
+        hints.push("The page isn't registered yet. Make sure the file lives under pages/ and restart the dev server if it was just created.".to_string());
+    }
+
+    let lower = text.to_lowercase();
+    if lower.contains("template not found") || lower.contains("template does not exist") {
+        hints.push("A layout or include points to a file that doesn't exist. Template paths are relative to the project root, e.g. `{% extends \"layouts/main.html\" %}`.".to_string());
+    }
+    if lower.contains("undefined") && matches!(error.error_source, Some(ErrorSource::Template(_))) {
+        hints.push("The template uses a value that wasn't provided. Return it from the component's `load_template_context`, or guard it with `{% if value is defined %}`.".to_string());
+    }
+    if lower.contains("syntaxerror") || lower.contains("syntax error") {
+        hints.push("Fix the syntax error first; `noventa check` lists every template and logic file that fails to parse.".to_string());
+    }
+
+    hints
+}
+
+// Turns a DetailedError into the files to look at and what probably went wrong.
+pub fn explain(error: &DetailedError, root: &Path) -> Explanation {
+    let mut files = Vec::new();
+
+    match &error.error_source {
+        Some(ErrorSource::Python(py)) => {
+            if let Some(filename) = &py.filename {
+                implicate(root, &mut files, filename, py.line_number, py.source_code.as_deref());
+            }
+        }
+        Some(ErrorSource::Template(info)) => {
+            let line = (info.line > 0).then_some(info.line);
+            implicate(root, &mut files, &info.name, line, info.source_code.as_deref().or(info.source.as_deref()));
+        }
+        None => {}
+    }
+    implicate(root, &mut files, &error.file_path, (error.line > 0).then_some(error.line as usize), None);
+    if let Some(page) = &error.page {
+        implicate(root, &mut files, &page.name, (page.line > 0).then_some(page.line), page.source.as_deref());
+    }
+    if let Some(component) = &error.component {
+        for path in component_files(root, &component.name) {
+            implicate(root, &mut files, &path, None, None);
+        }
+    }
+
+    let mut summary = error.message.clone();
+    if let Some(route) = &error.route {
+        summary.push_str(&format!(" (while rendering {})", route));
+    }
+
+    Explanation {
+        summary,
+        files,
+        hints: hints_for(error, root),
+    }
+}
+
+pub fn format_explanation(explanation: &Explanation) -> String {
+    let mut result = format!("Error: {}\n", explanation.summary);
+    if !explanation.hints.is_empty() {
+        result.push_str("\nLikely cause:\n");
+        for hint in &explanation.hints {
+            result.push_str(&format!("- {}\n", hint));
+        }
+    }
+    if !explanation.files.is_empty() {
+        result.push_str("\nFiles involved:\n");
+        for file in &explanation.files {
+            match file.line {
+                Some(line) => result.push_str(&format!("- {}:{}\n", file.path, line)),
+                None => result.push_str(&format!("- {}\n", file.path)),
+            }
+            if let Some(excerpt) = &file.excerpt {
+                result.push_str(&format!("```\n{}\n```\n", excerpt));
+            }
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::actors::interpreter::PythonError;
+    use crate::errors::{ComponentInfo, TemplateInfo};
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_explain_missing_action() {
+        let dir = tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("components/signup")).unwrap();
+        fs::write(root.join("components/signup/signup_logic.py"), "import os\n\ndef load_template_context(request):\n    return {}\n").unwrap();
+        fs::write(root.join("components/signup/signup_template.html"), "<form></form>").unwrap();
+
+        let logic_path = root.join("components/signup/signup_logic.py").to_string_lossy().to_string();
+        let error = DetailedError {
+            message: "module 'components.signup.signup_logic' has no attribute 'action_save'".to_string(),
+            component: Some(ComponentInfo { name: "signup".to_string() }),
+            error_source: Some(ErrorSource::Python(PythonError {
+                message: "module 'components.signup.signup_logic' has no attribute 'action_save'".to_string(),
+                traceback: String::new(),
+                line_number: Some(3),
+                column_number: None,
+                end_line_number: None,
+                end_column_number: None,
+                filename: Some(logic_path),
+                source_code: None,
+            })),
+            route: Some("/".to_string()),
+            ..Default::default()
+        };
+
+        let explanation = explain(&error, root);
+        assert!(explanation.hints[0].contains("`action_save`"));
+        assert!(explanation.hints[0].contains("components/signup/signup_logic.py"));
+        assert_eq!(explanation.files[0].path, "components/signup/signup_logic.py");
+        assert!(explanation.files[0].excerpt.as_ref().unwrap().contains(">    3 | def load_template_context"));
+        assert!(explanation.files.iter().any(|f| f.path == "components/signup/signup_template.html"));
+
+        let text = format_explanation(&explanation);
+        assert!(text.starts_with("Error: module 'components.signup.signup_logic' has no attribute 'action_save' (while rendering /)"));
+    }
+
+    #[test]
+    fn test_explain_template_error_uses_embedded_source() {
+        let dir = tempdir().unwrap();
+        let error = DetailedError {
+            message: "undefined value".to_string(),
+            error_source: Some(ErrorSource::Template(TemplateInfo {
+                name: "pages/missing.html".to_string(),
+                line: 2,
+                source_code: Some("<h1>\n{{ user.name }}\n</h1>".to_string()),
+                ..Default::default()
+            })),
+            ..Default::default()
+        };
+
+        let explanation = explain(&error, dir.path());
+        assert_eq!(explanation.files.len(), 1);
+        assert!(explanation.files[0].excerpt.as_ref().unwrap().contains(">    2 | {{ user.name }}"));
+        assert!(explanation.hints.iter().any(|h| h.contains("load_template_context")));
+    }
+
+    #[test]
+    fn test_files_outside_the_project_are_not_read() {
+        let dir = tempdir().unwrap();
+        let error = DetailedError {
+            message: "No module named 'stripe'".to_string(),
+            file_path: "/etc/hostname".to_string(),
+            line: 1,
+            ..Default::default()
+        };
+
+        let explanation = explain(&error, dir.path());
+        assert!(explanation.files[0].excerpt.is_none());
+        assert!(explanation.hints[0].contains("requirements.txt"));
+    }
+}
@@ -1,7 +1,8 @@
 // framework/src/disco/interactive_tools/parser.rs
+use crate::config::Config;
 use crate::disco::interactive_tools::models::InteractiveTool;
-use once_cell::sync::Lazy;
 use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 
 // const API_INTEGRATION_HELPER: &str =
 //     include_str!("tools_yaml/api_integration_helper.yaml");
@@ -20,9 +21,11 @@ use std::collections::HashMap;
 // const STATIC_FILE_GUIDE: &str =
 //     include_str!("tools_yaml/static_file_guide.yaml");
 
-static TOOLS: Lazy<HashMap<String, InteractiveTool>> = Lazy::new(|| {
-    let mut tools = HashMap::new();
-    let tool_files = vec![
+// Project-local guided workflows live here unless `disco.tools_dir` says otherwise.
+pub const DEFAULT_TOOLS_DIR: &str = "disco_tools";
+
+fn builtin_tool_files() -> Vec<&'static str> {
+    vec![
         // API_INTEGRATION_HELPER,
         // COMPONENT_GENERATOR,
         // CONFIG_EXPLAINER,
@@ -31,29 +34,138 @@ static TOOLS: Lazy<HashMap<String, InteractiveTool>> = Lazy::new(|| {
         // NEW_PAGE_CREATOR,
         // ONBOARDING_GUIDE,
         // STATIC_FILE_GUIDE,
-    ];
+    ]
+}
+
+// Every step a tool can reach must exist, otherwise the runner has nowhere to go.
+fn validate_tool(tool: &InteractiveTool) -> Result<(), String> {
+    if !tool.steps.contains_key(&tool.initial_step) {
+        return Err(format!("initial_step '{}' isn't one of its steps", tool.initial_step));
+    }
+    for (step_name, step) in &tool.steps {
+        for option in step.options.iter().flatten() {
+            if option.next_step != "[END]" && !tool.steps.contains_key(&option.next_step) {
+                return Err(format!(
+                    "step '{}' points to '{}', which doesn't exist",
+                    step_name, option.next_step
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn parse_tool(content: &str) -> Result<InteractiveTool, String> {
+    let tool: InteractiveTool = serde_yaml::from_str(content).map_err(|e| e.to_string())?;
+    validate_tool(&tool)?;
+    Ok(tool)
+}
+
+// Resolves the tools folder from config.yaml, read leniently so Disco still starts without one.
+pub fn project_tools_dir(root: &Path) -> PathBuf {
+    let tools_dir = Config::from_file(&root.join("config.yaml").to_string_lossy())
+        .ok()
+        .and_then(|config| config.disco)
+        .and_then(|disco| disco.tools_dir)
+        .unwrap_or_else(|| DEFAULT_TOOLS_DIR.to_string());
+    root.join(tools_dir)
+}
+
+// Reads every .yaml/.yml file in `dir`. Broken files are skipped and reported, never fatal.
+pub fn load_project_tools(dir: &Path) -> (HashMap<String, InteractiveTool>, Vec<String>) {
+    let mut tools = HashMap::new();
+    let mut errors = Vec::new();
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return (tools, errors);
+    };
+
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(Result::ok)
+        .map(|e| e.path())
+        .filter(|p| matches!(p.extension().and_then(|e| e.to_str()), Some("yaml" | "yml")))
+        .collect();
+    paths.sort();
 
-    for content in tool_files {
-        let tool: InteractiveTool = serde_yaml::from_str(content)
-            .expect("Failed to parse tool file");
+    for path in paths {
+        let parsed = std::fs::read_to_string(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|content| parse_tool(&content));
+        match parsed {
+            Ok(tool) => {
+                tools.insert(tool.name.clone(), tool);
+            }
+            Err(e) => errors.push(format!("{}: {}", path.display(), e)),
+        }
+    }
+    (tools, errors)
+}
+
+// Built-in tools plus the project's own; a project tool with the same name replaces the built-in one.
+pub fn load_tools(project_tools_dir: &Path) -> HashMap<String, InteractiveTool> {
+    let mut tools = HashMap::new();
+    for content in builtin_tool_files() {
+        let tool = parse_tool(content).expect("Failed to parse tool file");
         tools.insert(tool.name.clone(), tool);
     }
-    tools
-});
 
-pub fn load_tools() -> &'static HashMap<String, InteractiveTool> {
-    &TOOLS
+    let (project_tools, errors) = load_project_tools(project_tools_dir);
+    for error in errors {
+        log::warn!("Skipping interactive tool {}", error);
+    }
+    tools.extend(project_tools);
+    tools
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use tempfile::tempdir;
+
+    const GUIDE: &str = r#"
+name: "deploy_guide"
+description: "Walks through deploying this project."
+initial_step: "start"
+steps:
+  start:
+    text: "Where are you deploying?"
+    options:
+      - label: "Fly.io"
+        next_step: "fly"
+      - label: "Exit"
+        next_step: "[END]"
+  fly:
+    text: "Run fly launch."
+"#;
 
     #[test]
     fn test_load_tools() {
-        let tools = load_tools();
-        assert!(!tools.is_empty());
-        // Since only DATABASE_MIGRATION_HELPER is included
-        assert!(tools.contains_key("database_migration_helper"));
+        let dir = tempdir().unwrap();
+        std::fs::write(dir.path().join("deploy.yaml"), GUIDE).unwrap();
+        std::fs::write(dir.path().join("notes.txt"), "not a tool").unwrap();
+
+        let tools = load_tools(dir.path());
+        assert!(tools.contains_key("deploy_guide"));
+        assert_eq!(tools["deploy_guide"].steps.len(), 2);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_broken_tools_are_skipped() {
+        let dir = tempdir().unwrap();
+        std::fs::write(dir.path().join("a.yaml"), GUIDE).unwrap();
+        std::fs::write(dir.path().join("b.yml"), "name: [").unwrap();
+        std::fs::write(dir.path().join("c.yaml"), GUIDE.replace("next_step: \"fly\"", "next_step: \"nowhere\"")).unwrap();
+
+        let (tools, errors) = load_project_tools(dir.path());
+        assert_eq!(tools.len(), 1);
+        assert_eq!(errors.len(), 2);
+        assert!(errors[1].contains("'nowhere'"));
+    }
+
+    #[test]
+    fn test_project_tools_dir() {
+        let dir = tempdir().unwrap();
+        assert_eq!(project_tools_dir(dir.path()), dir.path().join(DEFAULT_TOOLS_DIR));
+        std::fs::write(dir.path().join("config.yaml"), "disco:\n  tools_dir: agents/flows\n").unwrap();
+        assert_eq!(project_tools_dir(dir.path()), dir.path().join("agents/flows"));
+    }
+}
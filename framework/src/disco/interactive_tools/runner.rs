@@ -2,34 +2,57 @@
 use crate::disco::interactive_tools::models::{InteractiveTool, Step};
 use crate::disco::interactive_tools::session::SessionManager;
 use std::collections::HashMap;
+use std::sync::RwLock;
 
 pub struct ToolRunner {
-    pub tools: HashMap<String, InteractiveTool>,
+    // Behind a lock so project tools can be reloaded while Disco is running.
+    tools: RwLock<HashMap<String, InteractiveTool>>,
     session_manager: SessionManager,
 }
 
 impl ToolRunner {
     pub fn new(tools: HashMap<String, InteractiveTool>, session_manager: SessionManager) -> Self {
         Self {
-            tools,
+            tools: RwLock::new(tools),
             session_manager,
         }
     }
 
-    pub fn run_tool(&self, tool_name: &str, user_input: Option<usize>) -> String {
-        let tool = match self.tools.get(tool_name) {
-            Some(t) => t,
+    pub fn has_tool(&self, tool_name: &str) -> bool {
+        self.tools.read().unwrap().contains_key(tool_name)
+    }
+
+    pub fn tools(&self) -> Vec<InteractiveTool> {
+        let mut tools: Vec<InteractiveTool> = self.tools.read().unwrap().values().cloned().collect();
+        tools.sort_by(|a, b| a.name.cmp(&b.name));
+        tools
+    }
+
+    // Swaps in a freshly loaded set of tools. Sessions on a tool or step that disappeared are dropped.
+    pub fn replace_tools(&self, tools: HashMap<String, InteractiveTool>) {
+        self.session_manager.retain(|session| {
+            tools
+                .get(&session.tool_name)
+                .is_some_and(|tool| tool.steps.contains_key(&session.current_step))
+        });
+        *self.tools.write().unwrap() = tools;
+    }
+
+    // `conversation` keeps each client's place in the tool separate from everyone else's.
+    pub fn run_tool(&self, conversation: &str, tool_name: &str, user_input: Option<usize>) -> String {
+        let tool = match self.tools.read().unwrap().get(tool_name) {
+            Some(t) => t.clone(),
             None => return "Unknown tool".to_string(),
         };
 
-        let session_existed = self.session_manager.get_session().map_or(false, |s| s.tool_name == tool_name);
+        let session_existed = self.session_manager.get_session(conversation).map_or(false, |s| s.tool_name == tool_name);
 
-        let mut session = match self.session_manager.get_session() {
+        let mut session = match self.session_manager.get_session(conversation) {
             Some(s) if s.tool_name == tool_name => s,
             _ => {
-                self.session_manager.end_session();
+                self.session_manager.end_session(conversation);
                 self.session_manager
-                    .create_session(tool_name, &tool.initial_step)
+                    .create_session(conversation, tool_name, &tool.initial_step)
             }
         };
 
@@ -40,11 +63,11 @@ impl ToolRunner {
                     if input_index > 0 {
                         if let Some(selected_option) = options.get(input_index - 1) {
                             if selected_option.next_step == "[END]" {
-                                self.session_manager.end_session();
+                                self.session_manager.end_session(conversation);
                                 return "Session ended.".to_string();
                             }
                             session.current_step = selected_option.next_step.clone();
-                            self.session_manager.update_session(&session.current_step);
+                            self.session_manager.update_session(conversation, &session.current_step);
                         } else {
                             return "Invalid option.".to_string();
                         }
@@ -52,7 +75,7 @@ impl ToolRunner {
                         return "Invalid option.".to_string();
                     }
                 } else {
-                    self.session_manager.end_session();
+                    self.session_manager.end_session(conversation);
                     return "Session ended.".to_string();
                 }
             }
@@ -62,7 +85,7 @@ impl ToolRunner {
 
         let response = self.format_step(step_def, tool_name);
         if step_def.options.is_none() {
-            self.session_manager.end_session();
+            self.session_manager.end_session(conversation);
         }
 
         response
@@ -74,7 +97,7 @@ impl ToolRunner {
             for (i, option) in options.iter().enumerate() {
                 response.push_str(&format!("\n{}. {}", i + 1, option.label));
             }
-            response.push_str(&format!("\n\nReply calling the tool ({}) and passing your numerical option in user_input", tool_name));
+            response.push_str(&format!("\n\nReply calling the tool ({}) and passing your numerical option in user_input and the same user_id", tool_name));
         }
         response
     }
@@ -84,6 +107,7 @@ impl ToolRunner {
 mod tests {
     use super::*;
     use crate::disco::interactive_tools::models::{InteractiveTool, Step, OptionDef};
+    use crate::disco::interactive_tools::session::DEFAULT_CONVERSATION;
     use std::collections::HashMap;
 
     fn create_test_tool() -> InteractiveTool {
@@ -119,7 +143,20 @@ mod tests {
         let tools = HashMap::new();
         let session_manager = SessionManager::new();
         let runner = ToolRunner::new(tools, session_manager);
-        assert!(runner.tools.is_empty());
+        assert!(runner.tools().is_empty());
+    }
+
+    #[test]
+    fn test_replace_tools_ends_stale_session() {
+        let mut tools = HashMap::new();
+        tools.insert("test_tool".to_string(), create_test_tool());
+        let runner = ToolRunner::new(tools, SessionManager::new());
+        runner.run_tool(DEFAULT_CONVERSATION, "test_tool", None);
+        assert!(runner.session_manager.get_session(DEFAULT_CONVERSATION).is_some());
+
+        runner.replace_tools(HashMap::new());
+        assert!(!runner.has_tool("test_tool"));
+        assert!(runner.session_manager.get_session(DEFAULT_CONVERSATION).is_none());
     }
 
     #[test]
@@ -127,7 +164,7 @@ mod tests {
         let tools = HashMap::new();
         let session_manager = SessionManager::new();
         let runner = ToolRunner::new(tools, session_manager);
-        let result = runner.run_tool("unknown", None);
+        let result = runner.run_tool(DEFAULT_CONVERSATION, "unknown", None);
         assert_eq!(result, "Unknown tool");
     }
 
@@ -137,7 +174,7 @@ mod tests {
         tools.insert("test_tool".to_string(), create_test_tool());
         let session_manager = SessionManager::new();
         let runner = ToolRunner::new(tools, session_manager);
-        let result = runner.run_tool("test_tool", None);
+        let result = runner.run_tool(DEFAULT_CONVERSATION, "test_tool", None);
         assert!(result.contains("Welcome to the test tool"));
         assert!(result.contains("1. Option 1"));
         assert!(result.contains("2. End"));
@@ -151,10 +188,10 @@ mod tests {
         let runner = ToolRunner::new(tools, session_manager);
         
         // Start session
-        runner.run_tool("test_tool", None);
+        runner.run_tool(DEFAULT_CONVERSATION, "test_tool", None);
         
         // Choose option 1
-        let result = runner.run_tool("test_tool", Some(1));
+        let result = runner.run_tool(DEFAULT_CONVERSATION, "test_tool", Some(1));
         assert_eq!(result, "You chose option 1");
     }
 
@@ -166,10 +203,10 @@ mod tests {
         let runner = ToolRunner::new(tools, session_manager);
         
         // Start session
-        runner.run_tool("test_tool", None);
+        runner.run_tool(DEFAULT_CONVERSATION, "test_tool", None);
         
         // Choose end option
-        let result = runner.run_tool("test_tool", Some(2));
+        let result = runner.run_tool(DEFAULT_CONVERSATION, "test_tool", Some(2));
         assert_eq!(result, "Session ended.");
     }
 
@@ -181,10 +218,10 @@ mod tests {
         let runner = ToolRunner::new(tools, session_manager);
         
         // Start session
-        runner.run_tool("test_tool", None);
+        runner.run_tool(DEFAULT_CONVERSATION, "test_tool", None);
         
         // Choose invalid option
-        let result = runner.run_tool("test_tool", Some(10));
+        let result = runner.run_tool(DEFAULT_CONVERSATION, "test_tool", Some(10));
         assert_eq!(result, "Invalid option.");
     }
 
@@ -229,4 +266,17 @@ mod tests {
         let result = runner.format_step(&step, "test_tool");
         assert_eq!(result, "Final message");
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_conversations_do_not_share_steps() {
+        let mut tools = HashMap::new();
+        tools.insert("test_tool".to_string(), create_test_tool());
+        let runner = ToolRunner::new(tools, SessionManager::new());
+
+        runner.run_tool("alice", "test_tool", None);
+        runner.run_tool("bob", "test_tool", None);
+        assert_eq!(runner.run_tool("alice", "test_tool", Some(1)), "You chose option 1");
+        // Bob is still on the first step, so his choice is read against its options.
+        assert_eq!(runner.run_tool("bob", "test_tool", Some(2)), "Session ended.");
+    }
+}
@@ -1,46 +1,76 @@
 // framework/src/disco/interactive_tools/session.rs
+use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// Conversations that go quiet for this long start over on their next call.
+pub const SESSION_TTL: Duration = Duration::from_secs(30 * 60);
+// Used when the client doesn't send a `user_id`.
+pub const DEFAULT_CONVERSATION: &str = "default";
 
 #[derive(Debug, Clone)]
 pub struct Session {
     pub current_step: String,
     pub tool_name: String,
+    last_used: Instant,
 }
 
+// One session per conversation, so concurrent MCP clients don't move each other's steps.
 pub struct SessionManager {
-    session: Arc<Mutex<Option<Session>>>,
+    sessions: Arc<Mutex<HashMap<String, Session>>>,
+    ttl: Duration,
 }
 
 impl SessionManager {
     pub fn new() -> Self {
+        Self::with_ttl(SESSION_TTL)
+    }
+
+    pub fn with_ttl(ttl: Duration) -> Self {
         Self {
-            session: Arc::new(Mutex::new(None)),
+            sessions: Arc::new(Mutex::new(HashMap::new())),
+            ttl,
         }
     }
 
-    pub fn get_session(&self) -> Option<Session> {
-        self.session.lock().unwrap().clone()
+    fn purge_expired(&self, sessions: &mut HashMap<String, Session>) {
+        let ttl = self.ttl;
+        sessions.retain(|_, session| session.last_used.elapsed() < ttl);
+    }
+
+    pub fn get_session(&self, conversation: &str) -> Option<Session> {
+        let mut sessions = self.sessions.lock().unwrap();
+        self.purge_expired(&mut sessions);
+        sessions.get(conversation).cloned()
     }
 
-    pub fn create_session(&self, tool_name: &str, initial_step: &str) -> Session {
-        let mut session_guard = self.session.lock().unwrap();
+    pub fn create_session(&self, conversation: &str, tool_name: &str, initial_step: &str) -> Session {
+        let mut sessions = self.sessions.lock().unwrap();
+        self.purge_expired(&mut sessions);
         let new_session = Session {
             current_step: initial_step.to_string(),
             tool_name: tool_name.to_string(),
+            last_used: Instant::now(),
         };
-        *session_guard = Some(new_session.clone());
+        sessions.insert(conversation.to_string(), new_session.clone());
         new_session
     }
 
-    pub fn update_session(&self, next_step: &str) {
-        let mut session_guard = self.session.lock().unwrap();
-        if let Some(session) = session_guard.as_mut() {
+    pub fn update_session(&self, conversation: &str, next_step: &str) {
+        let mut sessions = self.sessions.lock().unwrap();
+        if let Some(session) = sessions.get_mut(conversation) {
             session.current_step = next_step.to_string();
+            session.last_used = Instant::now();
         }
     }
 
-    pub fn end_session(&self) {
-        *self.session.lock().unwrap() = None;
+    pub fn end_session(&self, conversation: &str) {
+        self.sessions.lock().unwrap().remove(conversation);
+    }
+
+    // Drops every session for which `keep` returns false.
+    pub fn retain(&self, keep: impl Fn(&Session) -> bool) {
+        self.sessions.lock().unwrap().retain(|_, session| keep(session));
     }
 }
 
@@ -51,32 +81,51 @@ mod tests {
     #[test]
     fn test_session_manager_new() {
         let manager = SessionManager::new();
-        assert!(manager.get_session().is_none());
+        assert!(manager.get_session(DEFAULT_CONVERSATION).is_none());
     }
 
     #[test]
     fn test_session_manager_create_session() {
         let manager = SessionManager::new();
-        let session = manager.create_session("test_tool", "step1");
+        let session = manager.create_session(DEFAULT_CONVERSATION, "test_tool", "step1");
         assert_eq!(session.tool_name, "test_tool");
         assert_eq!(session.current_step, "step1");
-        assert_eq!(manager.get_session().unwrap().tool_name, "test_tool");
+        assert_eq!(manager.get_session(DEFAULT_CONVERSATION).unwrap().tool_name, "test_tool");
     }
 
     #[test]
     fn test_session_manager_update_session() {
         let manager = SessionManager::new();
-        manager.create_session("test_tool", "step1");
-        manager.update_session("step2");
-        assert_eq!(manager.get_session().unwrap().current_step, "step2");
+        manager.create_session(DEFAULT_CONVERSATION, "test_tool", "step1");
+        manager.update_session(DEFAULT_CONVERSATION, "step2");
+        assert_eq!(manager.get_session(DEFAULT_CONVERSATION).unwrap().current_step, "step2");
     }
 
     #[test]
     fn test_session_manager_end_session() {
         let manager = SessionManager::new();
-        manager.create_session("test_tool", "step1");
-        assert!(manager.get_session().is_some());
-        manager.end_session();
-        assert!(manager.get_session().is_none());
+        manager.create_session(DEFAULT_CONVERSATION, "test_tool", "step1");
+        assert!(manager.get_session(DEFAULT_CONVERSATION).is_some());
+        manager.end_session(DEFAULT_CONVERSATION);
+        assert!(manager.get_session(DEFAULT_CONVERSATION).is_none());
+    }
+
+    #[test]
+    fn test_sessions_are_isolated_per_conversation() {
+        let manager = SessionManager::new();
+        manager.create_session("alice", "test_tool", "step1");
+        manager.create_session("bob", "test_tool", "step1");
+        manager.update_session("alice", "step2");
+        manager.end_session("bob");
+
+        assert_eq!(manager.get_session("alice").unwrap().current_step, "step2");
+        assert!(manager.get_session("bob").is_none());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_sessions_expire() {
+        let manager = SessionManager::with_ttl(Duration::ZERO);
+        manager.create_session("alice", "test_tool", "step1");
+        assert!(manager.get_session("alice").is_none());
+    }
+}
@@ -1,8 +1,13 @@
 pub mod server;
 // framework/src/disco/mod.rs
 
+pub mod explain;
 pub mod models;
+pub mod patch;
+pub mod preview;
 pub mod tools;
+pub mod trash;
+pub mod workspace;
 pub mod interactive_tools;
 
 // This module will be configured in the main.rs server setup.
\ No newline at end of file
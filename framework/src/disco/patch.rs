@@ -0,0 +1,214 @@
+// framework/src/disco/patch.rs
+use sha2::{Digest, Sha256};
+
+// Edits to one file for `edit_file`: checked against the whole file first and applied together, so an
+// edit that doesn't fit leaves the file as it was.
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Replacement {
+    pub search: String,
+    pub replace: String,
+    // Replace every occurrence instead of insisting the search text is there exactly once.
+    pub replace_all: bool,
+}
+
+pub fn hash(contents: &str) -> String {
+    format!("{:x}", Sha256::digest(contents.as_bytes()))
+}
+
+// Files written on Windows keep their `\r\n`, even when the edit was written with `\n`.
+fn with_line_endings(text: &str, original: &str) -> String {
+    if original.contains("\r\n") && !text.contains("\r\n") {
+        text.replace('\n', "\r\n")
+    } else {
+        text.to_string()
+    }
+}
+
+pub fn apply_replacements(original: &str, replacements: &[Replacement]) -> Result<String, String> {
+    if replacements.is_empty() {
+        return Err("There are no edits to apply.".to_string());
+    }
+    let mut contents = original.to_string();
+    for (index, replacement) in replacements.iter().enumerate() {
+        if replacement.search.is_empty() {
+            return Err(format!("Edit {}: the search text is empty.", index + 1));
+        }
+        let (search, replace) = if contents.contains(&replacement.search) {
+            (replacement.search.clone(), replacement.replace.clone())
+        } else {
+            (with_line_endings(&replacement.search, original), with_line_endings(&replacement.replace, original))
+        };
+        match contents.matches(&search).count() {
+            0 => {
+                return Err(format!(
+                    "Edit {}: the search text isn't in the file. Read the file again and copy the lines exactly, including indentation.",
+                    index + 1
+                ));
+            }
+            1 => contents = contents.replacen(&search, &replace, 1),
+            _ if replacement.replace_all => contents = contents.replace(&search, &replace),
+            count => {
+                return Err(format!(
+                    "Edit {}: the search text is in the file {} times. Include more of the surrounding lines so it's found once, or set replace_all.",
+                    index + 1,
+                    count
+                ));
+            }
+        }
+    }
+    Ok(contents)
+}
+
+#[derive(Debug, Default)]
+struct Hunk {
+    header: String,
+    // From 1, as the `@@ -start,count` says; 0 for a hunk that adds to an empty file.
+    old_start: usize,
+    old: Vec<String>,
+    new: Vec<String>,
+    // `\ No newline at end of file` after the new side's last line.
+    new_without_newline: bool,
+}
+
+fn parse_hunks(diff: &str) -> Result<Vec<Hunk>, String> {
+    let mut hunks: Vec<Hunk> = Vec::new();
+    // What the last line belonged to, for a `\ No newline at end of file` after it.
+    let mut last_kind = ' ';
+    for line in diff.lines() {
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        if let Some(range) = line.strip_prefix("@@ ") {
+            let old_range = range.split_whitespace().next().and_then(|old| old.strip_prefix('-')).ok_or_else(|| format!("'{}' isn't a hunk header.", line))?;
+            let old_start = old_range.split(',').next().and_then(|start| start.parse().ok()).ok_or_else(|| format!("'{}' isn't a hunk header.", line))?;
+            hunks.push(Hunk { header: line.to_string(), old_start, ..Hunk::default() });
+            continue;
+        }
+        let Some(hunk) = hunks.last_mut() else {
+            // `diff --git`, `---`, `+++` and anything else before the first hunk.
+            continue;
+        };
+        match line.chars().next() {
+            Some(' ') => {
+                hunk.old.push(line[1..].to_string());
+                hunk.new.push(line[1..].to_string());
+                last_kind = ' ';
+            }
+            // A context line whose single space was trimmed away.
+            None => {
+                hunk.old.push(String::new());
+                hunk.new.push(String::new());
+                last_kind = ' ';
+            }
+            Some('-') => {
+                hunk.old.push(line[1..].to_string());
+                last_kind = '-';
+            }
+            Some('+') => {
+                hunk.new.push(line[1..].to_string());
+                last_kind = '+';
+            }
+            Some('\\') => hunk.new_without_newline |= last_kind != '-',
+            // The next file's `diff --git` header: only one file is edited at a time.
+            _ => break,
+        }
+    }
+    if hunks.is_empty() {
+        return Err("The diff has no hunks. Each change starts with a line like '@@ -12,4 +12,5 @@'.".to_string());
+    }
+    Ok(hunks)
+}
+
+// Where `hunk.old` is in `lines`, from `from` on, preferring the line the hunk says it starts at.
+fn find_hunk(lines: &[String], old: &[String], from: usize, expected: usize) -> Option<usize> {
+    if old.is_empty() {
+        return Some(expected.clamp(from, lines.len()));
+    }
+    let fits = |start: usize| start + old.len() <= lines.len() && lines[start..start + old.len()].iter().zip(old).all(|(line, old)| line.trim_end() == old.trim_end());
+    (from..=lines.len().saturating_sub(old.len())).filter(|&start| fits(start)).min_by_key(|&start| start.abs_diff(expected))
+}
+
+pub fn apply_unified_diff(original: &str, diff: &str) -> Result<String, String> {
+    let hunks = parse_hunks(diff)?;
+    let line_ending = if original.contains("\r\n") { "\r\n" } else { "\n" };
+    let mut trailing_newline = original.is_empty() || original.ends_with('\n');
+    let mut lines: Vec<String> = original.lines().map(str::to_string).collect();
+
+    // Lines added or removed by the hunks so far, to know where the next one should start.
+    let mut offset: isize = 0;
+    // Hunks apply in order and don't overlap.
+    let mut from = 0;
+    for (index, hunk) in hunks.iter().enumerate() {
+        let expected = (hunk.old_start.saturating_sub(1) as isize + offset).max(0) as usize;
+        let start = find_hunk(&lines, &hunk.old, from, expected).ok_or_else(|| {
+            format!(
+                "Hunk {} ({}) doesn't match the file: these lines aren't in it{}:\n{}\nRead the file again and make the diff from what's there now.",
+                index + 1,
+                hunk.header,
+                if from > 0 { " after the previous hunk" } else { "" },
+                hunk.old.join("\n")
+            )
+        })?;
+        let at_end = start + hunk.old.len() == lines.len();
+        lines.splice(start..start + hunk.old.len(), hunk.new.iter().cloned());
+        offset += hunk.new.len() as isize - hunk.old.len() as isize;
+        from = start + hunk.new.len();
+        if at_end {
+            trailing_newline = !hunk.new_without_newline;
+        }
+    }
+
+    let mut contents = lines.join(line_ending);
+    if trailing_newline && !lines.is_empty() {
+        contents.push_str(line_ending);
+    }
+    Ok(contents)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LOGIC: &str = "def load_template_context(request, **props):\n    return {}\n\ndef action_subscribe(request, **props):\n    pass\n";
+
+    fn replacement(search: &str, replace: &str) -> Replacement {
+        Replacement { search: search.to_string(), replace: replace.to_string(), replace_all: false }
+    }
+
+    #[test]
+    fn test_apply_replacements() {
+        let edited = apply_replacements(LOGIC, &[replacement("    return {}", "    return {\"plans\": []}"), replacement("    pass", "    return {}")]).unwrap();
+        assert_eq!(edited, "def load_template_context(request, **props):\n    return {\"plans\": []}\n\ndef action_subscribe(request, **props):\n    return {}\n");
+
+        assert!(apply_replacements(LOGIC, &[replacement("(request, **props)", "(request)")]).unwrap_err().contains("2 times"));
+        let everywhere = Replacement { replace_all: true, ..replacement("(request, **props)", "(request)") };
+        assert_eq!(apply_replacements(LOGIC, &[everywhere]).unwrap().matches("(request)").count(), 2);
+        assert!(apply_replacements(LOGIC, &[replacement("    return {}", "    return None"), replacement("missing", "")]).unwrap_err().starts_with("Edit 2"));
+        assert_eq!(apply_replacements("a\r\nb\r\n", &[replacement("a\nb", "a\nc")]).unwrap(), "a\r\nc\r\n");
+    }
+
+    #[test]
+    fn test_apply_unified_diff() {
+        let diff = "--- a/components/pricing/pricing_logic.py\n+++ b/components/pricing/pricing_logic.py\n@@ -1,2 +1,3 @@\n def load_template_context(request, **props):\n-    return {}\n+    plans = [\"free\", \"pro\"]\n+    return {\"plans\": plans}\n@@ -4,2 +5,2 @@\n def action_subscribe(request, **props):\n-    pass\n+    return {\"subscribed\": True}\n";
+        assert_eq!(
+            apply_unified_diff(LOGIC, diff).unwrap(),
+            "def load_template_context(request, **props):\n    plans = [\"free\", \"pro\"]\n    return {\"plans\": plans}\n\ndef action_subscribe(request, **props):\n    return {\"subscribed\": True}\n"
+        );
+
+        // Line numbers that are off still apply where the lines are.
+        let moved = "@@ -40,2 +40,2 @@\n def action_subscribe(request, **props):\n-    pass\n+    return {}\n";
+        assert!(apply_unified_diff(LOGIC, moved).unwrap().ends_with("def action_subscribe(request, **props):\n    return {}\n"));
+
+        let stale = "@@ -1,2 +1,2 @@\n def load_template_context(request):\n-    return {}\n+    return None\n";
+        assert!(apply_unified_diff(LOGIC, stale).unwrap_err().starts_with("Hunk 1"));
+        assert!(apply_unified_diff(LOGIC, "just some text").is_err());
+
+        let no_newline = "@@ -5 +5 @@\n-    pass\n+    pass\n\\ No newline at end of file\n";
+        assert!(apply_unified_diff(LOGIC, no_newline).unwrap().ends_with("    pass"));
+        assert_eq!(apply_unified_diff("", "@@ -0,0 +1,2 @@\n+one\n+two\n").unwrap(), "one\ntwo\n");
+    }
+
+    #[test]
+    fn test_hash() {
+        assert_eq!(hash(""), "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855");
+    }
+}
@@ -0,0 +1,122 @@
+// framework/src/disco/preview.rs
+use actix::prelude::*;
+use actix_session::SessionExt;
+use once_cell::sync::Lazy;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{mpsc, Arc};
+use tokio::sync::mpsc::UnboundedSender;
+
+use crate::actors::interpreter::PythonInterpreterActor;
+use crate::actors::page_renderer::{self, RenderMessage, RenderOutput};
+use crate::actors::session_manager::SessionManagerActor;
+use crate::actors::template_renderer::{self, TemplateRendererActor, UpdateComponents};
+use crate::config;
+use crate::errors::DetailedError;
+
+// Renders pages for `render_page` with the same actors `noventa dev` uses, so what an agent sees is
+// what a browser would get. The MCP server blocks on stdin, so they live on a thread of their own,
+// started the first time a page is asked for.
+
+pub struct PreviewRequest {
+    // e.g. `/blog/hello?page=2`.
+    pub path: String,
+    pub method: String,
+    pub form_data: Map<String, Value>,
+}
+
+pub enum Preview {
+    // The template that rendered it, e.g. `pages/blog/[slug].html`, and what it rendered.
+    Rendered(String, RenderOutput),
+    Failed(String, Box<DetailedError>),
+    NotFound,
+}
+
+type Job = (PreviewRequest, mpsc::Sender<Result<Preview, String>>);
+
+struct Renderers {
+    renderer: Recipient<RenderMessage>,
+    template_renderer: Addr<TemplateRendererActor>,
+    // Kept so the interpreter isn't stopped while pages are still being asked for.
+    _interpreter: Addr<PythonInterpreterActor>,
+}
+
+// Started once: the logger and the interpreter pool can't be set up twice in one process.
+static PREVIEWER: Lazy<Result<UnboundedSender<Job>, String>> = Lazy::new(start);
+
+pub fn render(request: PreviewRequest) -> Result<Preview, String> {
+    let previewer = PREVIEWER.as_ref().map_err(Clone::clone)?;
+    let (reply, answer) = mpsc::channel();
+    previewer.send((request, reply)).map_err(|_| "The page renderer has stopped. Restart Disco to render pages again.".to_string())?;
+    answer.recv().map_err(|_| "The page renderer stopped before the page was rendered. Restart Disco to render pages again.".to_string())?
+}
+
+fn start() -> Result<UnboundedSender<Job>, String> {
+    let (jobs, mut received) = tokio::sync::mpsc::unbounded_channel::<Job>();
+    let (ready, started) = mpsc::channel();
+    std::thread::spawn(move || {
+        System::new().block_on(async move {
+            let renderers = match crate::configure_server(true) {
+                Ok((_, renderer, interpreter, template_renderer, _)) => {
+                    Renderers { renderer: renderer.get_ref().clone(), template_renderer, _interpreter: interpreter }
+                }
+                Err(e) => {
+                    let _ = ready.send(Err(format!("The page renderer couldn't start: {}", e)));
+                    return;
+                }
+            };
+            let _ = ready.send(Ok(()));
+            while let Some((request, reply)) = received.recv().await {
+                let _ = reply.send(render_page(&renderers, request).await);
+            }
+        });
+    });
+    started.recv().map_err(|_| "The page renderer couldn't start.".to_string())??;
+    Ok(jobs)
+}
+
+async fn render_page(renderers: &Renderers, request: PreviewRequest) -> Result<Preview, String> {
+    // There's no file watcher here, so whatever changed since the last page is picked up now.
+    template_renderer::invalidate_templates();
+    crate::template_helpers::reload();
+    crate::reverse_routes::clear();
+    let components = crate::components::scan_project_components(Path::new(".")).map_err(|e| format!("Failed to read the components: {}", e))?;
+    renderers.template_renderer.send(UpdateComponents(components)).await.map_err(|e| e.to_string())?;
+
+    let routes = crate::routing::get_compiled_routes(&config::BASE_PATH.join("pages"));
+    let (path, _) = request.path.split_once('?').unwrap_or((&request.path, ""));
+    let Some((template_path, path_params)) = crate::actors::router::match_route(&routes, path) else {
+        return Ok(Preview::NotFound);
+    };
+
+    let method = actix_web::http::Method::from_bytes(request.method.to_uppercase().as_bytes()).map_err(|_| format!("'{}' isn't an HTTP method.", request.method))?;
+    let mut builder = actix_web::test::TestRequest::default()
+        .method(method.clone())
+        .uri(&request.path)
+        .insert_header(("host", format!("localhost:{}", config::CONFIG.port.unwrap_or(8080))))
+        .insert_header(("user-agent", "noventa-disco"));
+    if method == actix_web::http::Method::POST {
+        builder = builder.insert_header(("content-type", "application/x-www-form-urlencoded"));
+    }
+    let req = builder.to_http_request();
+    let session = req.get_session();
+
+    let page_meta = crate::page_meta::for_page(&template_path, true);
+    let mut request_info = crate::routing::build_http_request_info(&req, request.form_data, HashMap::new(), path_params, Some(&session));
+    request_info.deadline = Some(page_renderer::now_ms() + page_renderer::render_timeout(page_meta.render_timeout).as_millis() as u64);
+    request_info.render_id = crate::render_progress::next_id();
+
+    let render_msg = RenderMessage {
+        template_path: template_path.clone(),
+        request_info: Arc::new(request_info),
+        session_manager: SessionManagerActor::new(session).start(),
+    };
+    match renderers.renderer.send(render_msg).await.map_err(|e| e.to_string())? {
+        Ok(output) => Ok(Preview::Rendered(template_path, output)),
+        Err(mut detailed_error) => {
+            detailed_error.route = Some(path.to_string());
+            Ok(Preview::Failed(template_path, Box::new(detailed_error)))
+        }
+    }
+}
@@ -1,6 +1,9 @@
 use std::io::{self, BufRead};
+use std::path::PathBuf;
 use std::sync::Arc;
 
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+
 use serde_json::Value;
 
 use crate::disco::interactive_tools::{parser, runner::ToolRunner, session::SessionManager};
@@ -16,12 +19,44 @@ enum ServerState {
     Initialized,
 }
 
+// Reloads the project's interactive tools whenever a file in `dir` changes and tells the client the list changed.
+fn watch_project_tools(dir: PathBuf, tool_runner: Arc<ToolRunner>) -> Option<RecommendedWatcher> {
+    if !dir.is_dir() {
+        return None;
+    }
+    let watched_dir = dir.clone();
+    let mut watcher = notify::recommended_watcher(move |res: notify::Result<notify::Event>| {
+        let Ok(event) = res else {
+            return;
+        };
+        if !(event.kind.is_create() || event.kind.is_modify() || event.kind.is_remove()) {
+            return;
+        }
+        tool_runner.replace_tools(parser::load_tools(&watched_dir));
+        let notification = serde_json::json!({
+            "jsonrpc": "2.0",
+            "method": "notifications/tools/list_changed"
+        });
+        println!("{}", notification);
+    })
+    .map_err(|e| log::warn!("Couldn't watch {} for tool changes: {}", dir.display(), e))
+    .ok()?;
+
+    watcher
+        .watch(&dir, RecursiveMode::NonRecursive)
+        .map_err(|e| log::warn!("Couldn't watch {} for tool changes: {}", dir.display(), e))
+        .ok()?;
+    Some(watcher)
+}
+
 pub async fn run_disco_server() -> std::io::Result<()> {
     let mut state = ServerState::Uninitialized;
-    let interactive_tools = parser::load_tools().clone();
+    let tools_dir = parser::project_tools_dir(&std::env::current_dir()?);
+    let interactive_tools = parser::load_tools(&tools_dir);
     let session_manager = SessionManager::new();
     let tool_runner = Arc::new(ToolRunner::new(interactive_tools, session_manager));
     let tool_manager = Arc::new(ToolManager::new());
+    let _tools_watcher = watch_project_tools(tools_dir, tool_runner.clone());
 
     let stdin = io::stdin();
     for line in stdin.lock().lines() {
@@ -48,7 +83,7 @@ pub async fn run_disco_server() -> std::io::Result<()> {
                                             },
                                             capabilities: Capabilities {
                                                 tools: ToolCapability {
-                                                    list_changed: false,
+                                                    list_changed: true,
                                                 },
                                             },
                                         };
@@ -86,18 +121,21 @@ pub async fn run_disco_server() -> std::io::Result<()> {
                                             input_schema: tool.input_schema(),
                                         })
                                         .collect();
-                                    let tool_runner_clone = tool_runner.clone();
-                                    for tool in tool_runner_clone.tools.values() {
+                                    for tool in tool_runner.tools() {
                                         tools.push(ToolDefinition {
-                                            name: tool.name.clone(),
-                                            description: tool.description.clone(),
+                                            name: tool.name,
+                                            description: tool.description,
                                             input_schema: serde_json::json!({
                                                 "type": "object",
                                                 "properties": {
                                                     "user_input": {
                                                         "type": "integer",
                                                         "description": "The numerical option selected by the user."
-                                                     }
+                                                     },
+                                                    "user_id": {
+                                                        "type": "string",
+                                                        "description": "A stable id for this conversation, so each one keeps its own place in the tool."
+                                                    }
                                                 }
                                             }),
                                         });
@@ -122,7 +160,7 @@ pub async fn run_disco_server() -> std::io::Result<()> {
                                             tool_manager.get_tool(tool_name)
                                         {
                                             tool.run(arguments)
-                                        } else if tool_runner_clone.tools.contains_key(tool_name) {
+                                        } else if tool_runner_clone.has_tool(tool_name) {
                                             crate::disco::tools::run_interactive_tool(
                                                 &tool_runner_clone,
                                                 tool_name,
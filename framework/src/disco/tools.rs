@@ -3,18 +3,11 @@ use serde_json::{json, Value};
 use std::collections::HashMap;
 use std::fs;
 use std::sync::Arc;
-use path_clean::PathClean;
-
-fn is_path_safe(path: &std::path::Path) -> Result<bool, String> {
-    let current_dir = std::env::current_dir().map_err(|e| format!("Failed to get current directory: {}", e))?;
-    let absolute_path = if path.is_absolute() {
-        path.to_path_buf()
-    } else {
-        current_dir.join(path)
-    };
-    let cleaned_path = absolute_path.clean();
-    Ok(cleaned_path.starts_with(&current_dir))
-}
+use crate::dependencies;
+use crate::disco::workspace::Workspace;
+use crate::actors::page_renderer::RenderOutput;
+use crate::disco::preview::{self, Preview};
+use crate::disco::{explain, patch, trash};
 
 pub trait Tool: Send + Sync {
     fn name(&self) -> String;
@@ -23,6 +16,32 @@ pub trait Tool: Send + Sync {
     fn run(&self, args: &Value) -> Result<Value, String>;
 }
 
+// Lines returned when the caller doesn't pass a limit, so big files don't flood the context window.
+const DEFAULT_READ_LIMIT: usize = 2000;
+// How much of a file is sniffed to decide whether it's binary.
+const BINARY_SNIFF_BYTES: usize = 8192;
+
+fn is_binary(bytes: &[u8]) -> bool {
+    let sample = &bytes[..bytes.len().min(BINARY_SNIFF_BYTES)];
+    if sample.contains(&0) {
+        return true;
+    }
+    // A multi-byte character cut off at the end of the sample is still text.
+    match std::str::from_utf8(sample) {
+        Ok(_) => false,
+        Err(e) => e.error_len().is_some(),
+    }
+}
+
+// Returns the requested window of lines (offset is 1-based) and the file's total line count.
+fn paginate(contents: &str, offset: usize, limit: usize) -> (String, usize, usize, usize) {
+    let total = contents.lines().count();
+    let start = offset.max(1);
+    let selected: Vec<&str> = contents.lines().skip(start - 1).take(limit).collect();
+    let end = start + selected.len().saturating_sub(1);
+    (selected.join("\n"), start, end, total)
+}
+
 struct ReadFileTool;
 
 impl Tool for ReadFileTool {
@@ -31,7 +50,10 @@ impl Tool for ReadFileTool {
     }
 
     fn description(&self) -> String {
-        "Use this tool to read the contents of a file. It will also provide helpful information about the file's purpose in a Noventa project, such as identifying it as a component, a page, or a layout.".to_string()
+        format!(
+            "Use this tool to read the contents of a file. It will also provide helpful information about the file's purpose in a Noventa project, such as identifying it as a component, a page, or a layout. Large files are returned {} lines at a time; use offset and limit to read the rest. Binary files like images return their size and type instead of their contents.",
+            DEFAULT_READ_LIMIT
+        )
     }
 
     fn input_schema(&self) -> Value {
@@ -41,6 +63,14 @@ impl Tool for ReadFileTool {
                 "path": {
                     "type": "string",
                     "description": "The path to the file."
+                },
+                "offset": {
+                    "type": "integer",
+                    "description": "The line number to start reading from, starting at 1."
+                },
+                "limit": {
+                    "type": "integer",
+                    "description": format!("How many lines to read. Defaults to {}.", DEFAULT_READ_LIMIT)
                 }
             },
             "required": ["path"]
@@ -52,21 +82,45 @@ impl Tool for ReadFileTool {
             .get("path")
             .and_then(Value::as_str)
             .ok_or("Missing or invalid 'path' argument".to_string())?;
+        let offset = args.get("offset").and_then(Value::as_u64).unwrap_or(1) as usize;
+        let limit = args.get("limit").and_then(Value::as_u64).map(|l| l as usize).unwrap_or(DEFAULT_READ_LIMIT);
 
-        let path = std::path::Path::new(path_str);
+        let workspace = Workspace::current()?;
+        let path = workspace.resolve(path_str)?;
 
-        if !is_path_safe(path)? {
-            return Err("Error: Access to paths outside the current working directory is not allowed.".to_string());
-        }
-
-        let contents = fs::read_to_string(path)
+        let bytes = fs::read(&path)
             .map_err(|e| format!("Failed to read file: {}", e))?;
 
-        let metadata = get_file_metadata(path, true);
+        if is_binary(&bytes) {
+            let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("unknown");
+            return Ok(Value::String(format!(
+                "'{}' is a binary file ({} bytes, .{}), so its contents aren't shown.",
+                path_str,
+                bytes.len(),
+                extension
+            )));
+        }
+
+        let contents = String::from_utf8_lossy(&bytes);
+        let (window, start, end, total) = paginate(&contents, offset, limit);
+        let metadata = get_file_metadata(&workspace.relative(&path), true);
+
+        let range = if total == 0 {
+            "(empty file)".to_string()
+        } else if start > total {
+            format!("(offset {} is past the end; the file has {} lines)", start, total)
+        } else if end < total {
+            format!("(lines {}-{} of {}; pass offset={} to continue)", start, end, total, end + 1)
+        } else if start == 1 {
+            format!("({} lines)", total)
+        } else {
+            format!("(lines {}-{} of {})", start, end, total)
+        };
 
         let response = format!(
-            "```\n{}\n```\n{}",
-            contents,
+            "{}\n```\n{}\n```\n{}",
+            range,
+            window,
             metadata.unwrap_or_default()
         );
         Ok(Value::String(response))
@@ -167,6 +221,55 @@ fn get_file_metadata(path: &std::path::Path, full_metadata: bool) -> Option<Stri
     }
 }
 
+// Upper bounds for recursive listings, so a single call can't return the whole disk.
+const DEFAULT_LIST_DEPTH: usize = 5;
+const MAX_LIST_DEPTH: usize = 10;
+const MAX_LIST_ENTRIES: usize = 500;
+
+// Walks `base_path` and returns matching entries relative to it, sorted, plus whether the cap was hit.
+fn collect_entries(
+    base_path: &std::path::Path,
+    max_depth: usize,
+    glob: Option<&str>,
+) -> Result<(Vec<std::path::PathBuf>, bool), String> {
+    let mut walker = ignore::WalkBuilder::new(base_path);
+    walker
+        .max_depth(Some(max_depth))
+        .hidden(false)
+        // Only honour .gitignore when going deep, a plain listing shows everything like before.
+        .git_ignore(max_depth > 1)
+        .filter_entry(|entry| {
+            let name = entry.file_name();
+            name != ".git" && name != trash::TRASH_DIR
+        });
+    if let Some(glob) = glob {
+        let overrides = ignore::overrides::OverrideBuilder::new(base_path)
+            .add(glob)
+            .and_then(|builder| builder.build())
+            .map_err(|e| format!("Invalid glob '{}': {}", glob, e))?;
+        walker.overrides(overrides);
+    }
+
+    let mut entries = Vec::new();
+    let mut truncated = false;
+    for entry in walker.build().filter_map(Result::ok) {
+        if entry.depth() == 0 {
+            continue;
+        }
+        // With a glob, directories are only walked through, not listed.
+        if glob.is_some() && entry.file_type().is_some_and(|t| t.is_dir()) {
+            continue;
+        }
+        if entries.len() == MAX_LIST_ENTRIES {
+            truncated = true;
+            break;
+        }
+        entries.push(entry.into_path());
+    }
+    entries.sort();
+    Ok((entries, truncated))
+}
+
 struct ListDirectoryTool;
 
 impl Tool for ListDirectoryTool {
@@ -175,7 +278,10 @@ impl Tool for ListDirectoryTool {
     }
 
     fn description(&self) -> String {
-        "Use this tool to see what's inside a directory. It will show you a list of files and folders, and it will tell you if they are special Noventa files like components or pages.".to_string()
+        format!(
+            "Use this tool to see what's inside a directory. It will show you a list of files and folders, and it will tell you if they are special Noventa files like components or pages. Set recursive to list nested folders in one call and glob (e.g. '*_logic.py') to only show matching files. Results are capped at {} entries.",
+            MAX_LIST_ENTRIES
+        )
     }
 
     fn input_schema(&self) -> Value {
@@ -185,6 +291,18 @@ impl Tool for ListDirectoryTool {
                 "path": {
                     "type": "string",
                     "description": "The path to the directory to list."
+                },
+                "recursive": {
+                    "type": "boolean",
+                    "description": "List nested folders too. Files ignored by .gitignore are skipped."
+                },
+                "glob": {
+                    "type": "string",
+                    "description": "Only show files matching this pattern, e.g. '*.html' or 'components/**/*_logic.py'."
+                },
+                "max_depth": {
+                    "type": "integer",
+                    "description": format!("How many levels deep to go when recursive. Defaults to {}, at most {}.", DEFAULT_LIST_DEPTH, MAX_LIST_DEPTH)
                 }
             },
             "required": ["path"]
@@ -196,40 +314,50 @@ impl Tool for ListDirectoryTool {
             .get("path")
             .and_then(Value::as_str)
             .ok_or("Missing or invalid 'path' argument".to_string())?;
+        let recursive = args.get("recursive").and_then(Value::as_bool).unwrap_or(false);
+        let glob = args.get("glob").and_then(Value::as_str).filter(|g| !g.is_empty());
+        let max_depth = if recursive {
+            args.get("max_depth")
+                .and_then(Value::as_u64)
+                .map(|d| (d as usize).clamp(1, MAX_LIST_DEPTH))
+                .unwrap_or(DEFAULT_LIST_DEPTH)
+        } else {
+            1
+        };
 
-        let base_path = std::path::Path::new(path_str);
-
-        if !is_path_safe(base_path)? {
-            return Err("Error: Access to paths outside the current working directory is not allowed.".to_string());
+        let workspace = Workspace::current()?;
+        let base_path = workspace.resolve(path_str)?;
+        if !base_path.is_dir() {
+            return Err(format!("Failed to read directory: '{}' isn't a directory", path_str));
         }
 
-        let entries = fs::read_dir(base_path)
-            .map_err(|e| format!("Failed to read directory: {}", e))?;
+        let (entries, truncated) = collect_entries(&base_path, max_depth, glob)?;
 
         let mut output_table = Vec::new();
         output_table.push(vec!["Path".to_string(), "Type".to_string()]);
 
-        for entry in entries {
-            if let Ok(entry) = entry {
-                let path = entry.path();
-                let relative_path = path.strip_prefix(base_path).unwrap_or(&path);
-                let path_str = relative_path.to_str().unwrap_or_default().to_string();
-
-                let path_type = get_path_type(&path);
-                let type_str = match path_type {
-                    PathType::ComponentLogic(_) => "Component Logic",
-                    PathType::ComponentTemplate(_) => "Component Template",
-                    PathType::ComponentModel(_) => "Component Model",
-                    PathType::PageTemplate(_) => "Page Template",
-                    PathType::PageLayout => "PageLayout",
-                    PathType::File => "File",
-                    PathType::Directory => "Directory",
-                };
+        for path in entries {
+            let relative_path = path.strip_prefix(&base_path).unwrap_or(&path);
+            let path_str = relative_path.to_str().unwrap_or_default().to_string();
 
-                output_table.push(vec![path_str, type_str.to_string()]);
-            }
-        }
+            // Classified by where it sits in the project, not by folders above it.
+            let path_type = if path.is_dir() {
+                PathType::Directory
+            } else {
+                get_path_type(&workspace.relative(&path))
+            };
+            let type_str = match path_type {
+                PathType::ComponentLogic(_) => "Component Logic",
+                PathType::ComponentTemplate(_) => "Component Template",
+                PathType::ComponentModel(_) => "Component Model",
+                PathType::PageTemplate(_) => "Page Template",
+                PathType::PageLayout => "PageLayout",
+                PathType::File => "File",
+                PathType::Directory => "Directory",
+            };
 
+            output_table.push(vec![path_str, type_str.to_string()]);
+        }
         let mut col_widths = vec![0; 2];
         for row in &output_table {
             for (i, cell) in row.iter().enumerate() {
@@ -253,6 +381,16 @@ impl Tool for ListDirectoryTool {
             }
         }
 
+        if output_table.len() == 1 {
+            result.push_str("(nothing found)\n");
+        }
+        if truncated {
+            result.push_str(&format!(
+                "\nOnly the first {} entries are shown. Narrow the path or add a glob to see the rest.\n",
+                MAX_LIST_ENTRIES
+            ));
+        }
+
         Ok(Value::String(result))
     }
 }
@@ -288,12 +426,9 @@ impl Tool for CreateDirectoryTool {
             .ok_or("Missing or invalid 'path' argument".to_string())?;
 
         let path = std::path::Path::new(path_str);
+        let resolved = Workspace::current()?.resolve(path_str)?;
 
-        if !is_path_safe(path)? {
-            return Err("Error: Access to paths outside the current working directory is not allowed.".to_string());
-        }
-
-        fs::create_dir_all(path)
+        fs::create_dir_all(&resolved)
             .map_err(|e| format!("Failed to create directory: {}", e))?;
 
         let parent = path.parent().unwrap_or(path);
@@ -356,20 +491,18 @@ impl Tool for WriteFileTool {
             .ok_or("Missing or invalid 'content' argument".to_string())?;
 
         let path = std::path::Path::new(path_str);
+        let workspace = Workspace::current()?;
+        let resolved = workspace.resolve(path_str)?;
 
-        if !is_path_safe(path)? {
-            return Err("Error: Writing to paths outside the current working directory is not allowed.".to_string());
-        }
-
-        if let Some(parent) = path.parent() {
+        if let Some(parent) = resolved.parent() {
             fs::create_dir_all(parent)
                 .map_err(|e| format!("Failed to create parent directories: {}", e))?;
         }
 
-        fs::write(path, content)
+        fs::write(&resolved, content)
             .map_err(|e| format!("Failed to write to file: {}", e))?;
 
-        let path_type = get_path_type(path);
+        let path_type = get_path_type(&workspace.relative(&resolved));
         let parent_path = path.parent().unwrap_or(path);
         let parent_path_str = parent_path.to_str().unwrap_or_default();
 
@@ -420,6 +553,7 @@ impl Tool for WriteFileTool {
 
 
 use crate::disco::interactive_tools::runner::ToolRunner;
+use crate::disco::interactive_tools::session::DEFAULT_CONVERSATION;
 
 struct DeleteDirectoryTool;
 
@@ -429,7 +563,7 @@ impl Tool for DeleteDirectoryTool {
     }
 
     fn description(&self) -> String {
-        "Use this tool to delete a directory and everything inside it.".to_string()
+        "Use this tool to delete a directory and everything inside it. Deleted directories are moved to the project trash and can be brought back with restore_file.".to_string()
     }
 
     fn input_schema(&self) -> Value {
@@ -451,13 +585,10 @@ impl Tool for DeleteDirectoryTool {
             .and_then(Value::as_str)
             .ok_or("Missing or invalid 'path' argument".to_string())?;
 
-        let path = std::path::Path::new(path_str);
-
-        // Security check: Ensure the path is within the current working directory.
-        let current_dir = std::env::current_dir().map_err(|e| format!("Failed to get current directory: {}", e))?;
-        let absolute_path = path.canonicalize().map_err(|e| format!("Failed to resolve path: {}", e))?;
-        if !absolute_path.starts_with(&current_dir) {
-            return Err("Error: Deletion of paths outside the current working directory is not allowed.".to_string());
+        let workspace = Workspace::current()?;
+        let absolute_path = workspace.resolve(path_str)?;
+        if !absolute_path.exists() {
+            return Err(format!("Error: '{}' doesn't exist.", path_str));
         }
 
         // Guard against deleting protected directories.
@@ -466,10 +597,16 @@ impl Tool for DeleteDirectoryTool {
             return Err(format!("Error: The directory '{}' is protected and cannot be deleted.", path_str));
         }
 
-        fs::remove_dir_all(path)
-            .map_err(|e| format!("Failed to delete directory: {}", e))?;
+        if !absolute_path.is_dir() {
+            return Err(format!("Error: '{}' is not a directory.", path_str));
+        }
+
+        let entry = soft_delete(&workspace, &absolute_path)?;
 
-        Ok(Value::String(format!("Successfully deleted directory '{}'.", path_str)))
+        Ok(Value::String(format!(
+            "Successfully deleted directory '{}'. It was moved to the trash as '{}' and can be restored with restore_file.",
+            path_str, entry.id
+        )))
     }
 }
 
@@ -481,7 +618,7 @@ impl Tool for DeleteFileTool {
     }
 
     fn description(&self) -> String {
-        "Use this tool to delete a file.".to_string()
+        "Use this tool to delete a file. Deleted files are moved to the project trash and can be brought back with restore_file.".to_string()
     }
 
     fn input_schema(&self) -> Value {
@@ -503,19 +640,797 @@ impl Tool for DeleteFileTool {
             .and_then(Value::as_str)
             .ok_or("Missing or invalid 'path' argument".to_string())?;
 
-        let path = std::path::Path::new(path_str);
+        let workspace = Workspace::current()?;
+        let absolute_path = workspace.resolve(path_str)?;
+        if !absolute_path.exists() {
+            return Err(format!("Error: '{}' doesn't exist.", path_str));
+        }
 
-        // Security check: Ensure the path is within the current working directory.
-        let current_dir = std::env::current_dir().map_err(|e| format!("Failed to get current directory: {}", e))?;
-        let absolute_path = path.canonicalize().map_err(|e| format!("Failed to resolve path: {}", e))?;
-        if !absolute_path.starts_with(&current_dir) {
-            return Err("Error: Deletion of paths outside the current working directory is not allowed.".to_string());
+        if absolute_path.is_dir() {
+            return Err(format!("Error: '{}' is a directory. Use delete_directory instead.", path_str));
         }
 
-        fs::remove_file(path)
-            .map_err(|e| format!("Failed to delete file: {}", e))?;
+        let entry = soft_delete(&workspace, &absolute_path)?;
+
+        Ok(Value::String(format!(
+            "Successfully deleted file '{}'. It was moved to the trash as '{}' and can be restored with restore_file.",
+            path_str, entry.id
+        )))
+    }
+}
+
+// Moves a path into the project trash, purging anything past the retention window on the way.
+fn soft_delete(workspace: &Workspace, absolute_path: &std::path::Path) -> Result<trash::TrashEntry, String> {
+    let root = workspace.root();
+    let relative = absolute_path.strip_prefix(root).unwrap_or(absolute_path);
+    if relative.as_os_str().is_empty() || absolute_path == workspace.project_dir() {
+        return Err("Error: The project root itself cannot be deleted.".to_string());
+    }
+    if relative.starts_with(trash::TRASH_DIR) {
+        return Err("Error: Items in the trash cannot be deleted with this tool.".to_string());
+    }
+
+    trash::purge_expired(root, chrono::Utc::now().timestamp());
+    trash::move_to_trash(root, relative)
+}
+
+struct RestoreFileTool;
+
+impl Tool for RestoreFileTool {
+    fn name(&self) -> String {
+        "restore_file".to_string()
+    }
+
+    fn description(&self) -> String {
+        format!(
+            "Use this tool to bring back a file or directory removed with delete_file or delete_directory. Call it without a path to list what's in the trash. Deleted items are kept for {} days.",
+            trash::RETENTION_SECS / 86_400
+        )
+    }
+
+    fn input_schema(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "path": {
+                    "type": "string",
+                    "description": "The original path of the deleted file or directory, or its trash id. Leave empty to list the trash."
+                }
+            }
+        })
+    }
+
+    fn run(&self, args: &Value) -> Result<Value, String> {
+        let workspace = Workspace::current()?;
+
+        let Some(path_str) = args.get("path").and_then(Value::as_str).filter(|p| !p.is_empty()) else {
+            let entries = trash::list_entries(workspace.root());
+            if entries.is_empty() {
+                return Ok(Value::String("The trash is empty.".to_string()));
+            }
+            let mut result = String::from("Items in the trash (newest first):\n");
+            for entry in entries {
+                let deleted_at = chrono::DateTime::from_timestamp(entry.deleted_at, 0)
+                    .map(|d| d.format("%Y-%m-%d %H:%M:%S UTC").to_string())
+                    .unwrap_or_default();
+                let kind = if entry.is_directory { "directory" } else { "file" };
+                // Shown the way `path` takes it, relative to the project.
+                let path = crate::paths::to_slash(&workspace.relative(&workspace.root().join(&entry.original_path)));
+                result.push_str(&format!("- {} ({}, id: {}, deleted {})\n", path, kind, entry.id, deleted_at));
+            }
+            return Ok(Value::String(result));
+        };
 
-        Ok(Value::String(format!("Successfully deleted file '{}'.", path_str)))
+        let destination = restore_from_trash(&workspace, path_str)?;
+        Ok(Value::String(format!("Successfully restored '{}'.", crate::paths::to_slash(&workspace.relative(&destination)))))
+    }
+}
+
+// Restores `path_str` (a path relative to the project, or a trash id) and returns where it went.
+fn restore_from_trash(workspace: &Workspace, path_str: &str) -> Result<std::path::PathBuf, String> {
+    let root = workspace.root();
+    // The trash keeps paths relative to the workspace root, which is what a resolved path is compared as.
+    let resolved = workspace.resolve(path_str)?;
+    let entry = trash::find(root, path_str, resolved.strip_prefix(root).unwrap_or(&resolved))
+        .ok_or_else(|| format!("There's nothing in the trash for '{}'.", path_str))?;
+    // A manifest edited to point outside the workspace is refused like any other path.
+    let destination = if entry.id == path_str {
+        workspace.resolve(&root.join(&entry.original_path).to_string_lossy())?
+    } else {
+        resolved
+    };
+    trash::restore(root, &entry, &destination)?;
+    Ok(destination)
+}
+
+struct GetPageDependenciesTool;
+
+impl Tool for GetPageDependenciesTool {
+    fn name(&self) -> String {
+        "get_page_dependencies".to_string()
+    }
+
+    fn description(&self) -> String {
+        "Use this tool before changing a page. Given a page file or a route, it lists every layout, partial, component, logic module and static asset the page depends on, so you can update all of them instead of only the page template.".to_string()
+    }
+
+    fn input_schema(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "page": {
+                    "type": "string",
+                    "description": "A page file like 'pages/blog/[slug].html', a route pattern like '/blog/{slug}' or a URL like '/blog/hello'."
+                }
+            },
+            "required": ["page"]
+        })
+    }
+
+    fn run(&self, args: &Value) -> Result<Value, String> {
+        let page = args.get("page").and_then(Value::as_str).ok_or("Missing 'page' argument")?;
+        let workspace = Workspace::current()?;
+        if !page.starts_with('/') {
+            workspace.resolve(page)?;
+        }
+        let project_dir = workspace.project_dir();
+
+        // Read the static settings ourselves so a missing config.yaml doesn't stop the lookup.
+        let config = crate::config::Config::from_file(&project_dir.join("config.yaml").to_string_lossy()).ok();
+        let url_prefix = config.as_ref().and_then(|c| c.static_url_prefix.clone()).unwrap_or_else(|| "/static".to_string());
+        let static_files = config.as_ref().and_then(|c| c.static_path.clone()).map(|static_path| dependencies::StaticFiles {
+            url_prefix: &url_prefix,
+            dir: project_dir.join(static_path.trim_start_matches("./")),
+        });
+
+        let deps = dependencies::collect_page_dependencies(project_dir, page, static_files)?;
+
+        let mut result = format!("Page: {}\n", deps.page);
+        if let Some(route) = &deps.route {
+            result.push_str(&format!("Route: {}\n", route));
+        }
+        let sections = [
+            ("Layouts", &deps.layouts),
+            ("Partials", &deps.partials),
+            ("Components", &deps.components),
+            ("Component files", &deps.component_files),
+            ("Logic modules", &deps.logic_modules),
+            ("Static assets", &deps.static_assets),
+            ("Referenced but missing", &deps.missing),
+        ];
+        for (title, items) in sections {
+            if items.is_empty() {
+                continue;
+            }
+            result.push_str(&format!("\n{}:\n", title));
+            for item in items {
+                result.push_str(&format!("- {}\n", item));
+            }
+        }
+        Ok(Value::String(result))
+    }
+}
+
+struct GetComponentGraphTool;
+
+impl Tool for GetComponentGraphTool {
+    fn name(&self) -> String {
+        "get_component_graph".to_string()
+    }
+
+    fn description(&self) -> String {
+        "Use this tool to see how the whole project fits together: which pages extend which layouts, include which partials and call which components, and which components no page uses anymore (safe to delete). Returns JSON by default, or Graphviz DOT.".to_string()
+    }
+
+    fn input_schema(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "format": {
+                    "type": "string",
+                    "enum": ["json", "dot"],
+                    "description": "'json' (the default) or 'dot' for Graphviz."
+                }
+            }
+        })
+    }
+
+    fn run(&self, args: &Value) -> Result<Value, String> {
+        let workspace = Workspace::current()?;
+        let graph = crate::graph::build_graph(workspace.project_dir());
+        match args.get("format").and_then(Value::as_str).unwrap_or("json") {
+            "json" => serde_json::to_string_pretty(&graph).map(Value::String).map_err(|e| e.to_string()),
+            "dot" => Ok(Value::String(crate::graph::to_dot(&graph))),
+            other => Err(format!("Unknown format '{}'. Use 'json' or 'dot'.", other)),
+        }
+    }
+}
+
+struct ExplainErrorTool;
+
+impl Tool for ExplainErrorTool {
+    fn name(&self) -> String {
+        "explain_error".to_string()
+    }
+
+    fn description(&self) -> String {
+        "Use this tool when a page fails to render. Pass the error JSON shown in the dev server's error overlay or logs, and it returns the files involved with the source around the failing line, plus Noventa-specific hints about the likely cause.".to_string()
+    }
+
+    fn input_schema(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "error": {
+                    "type": ["object", "string"],
+                    "description": "The error as reported by the dev server, either as a JSON object or a JSON string."
+                }
+            },
+            "required": ["error"]
+        })
+    }
+
+    fn run(&self, args: &Value) -> Result<Value, String> {
+        let error = args.get("error").ok_or("Missing 'error' argument")?;
+        let error: crate::errors::DetailedError = match error {
+            Value::String(raw) => serde_json::from_str(raw),
+            other => serde_json::from_value(other.clone()),
+        }
+        .map_err(|e| format!("The error isn't in the format the dev server reports: {}", e))?;
+
+        let workspace = Workspace::current()?;
+        let explanation = explain::explain(&error, workspace.root());
+        Ok(Value::String(explain::format_explanation(&explanation)))
+    }
+}
+
+// How a created file is shown back: relative to the project, with forward slashes.
+fn shown(workspace: &Workspace, path: &std::path::Path) -> String {
+    crate::paths::to_slash(&workspace.relative(path))
+}
+
+struct CreateComponentTool;
+
+impl Tool for CreateComponentTool {
+    fn name(&self) -> String {
+        "create_component".to_string()
+    }
+
+    fn description(&self) -> String {
+        "Use this tool to create a new component instead of writing its files yourself. It creates the component's folder with a correctly named <name>_logic.py and <name>_template.html (and optionally <name>_models.py), the same files `noventa new:component` creates, and tells you how to call it from a template.".to_string()
+    }
+
+    fn input_schema(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "name": {
+                    "type": "string",
+                    "description": "The component's name in lowercase letters, digits and underscores. Use dots for subcomponents, e.g. 'cards.pricing'."
+                },
+                "models": {
+                    "type": "boolean",
+                    "description": "Also create a <name>_models.py file for SQLAlchemy models."
+                }
+            },
+            "required": ["name"]
+        })
+    }
+
+    fn run(&self, args: &Value) -> Result<Value, String> {
+        let name = args.get("name").and_then(Value::as_str).ok_or("Missing or invalid 'name' argument")?;
+        let models = args.get("models").and_then(Value::as_bool).unwrap_or(false);
+
+        let workspace = Workspace::current()?;
+        workspace.resolve(&format!("components/{}", name.replace('.', "/")))?;
+        let files = crate::generators::generate_component(workspace.project_dir(), name, models).map_err(|e| format!("Error: {}", e))?;
+
+        let mut result = format!("Created the '{}' component:\n", name.replace('/', "."));
+        for file in &files {
+            result.push_str(&format!("- {}\n", shown(&workspace, file)));
+        }
+        result.push_str(&format!("\nUse it in a template with {{{{ component(\"{}\") }}}}.", name.replace('/', ".")));
+        Ok(Value::String(result))
+    }
+}
+
+struct CreatePageTool;
+
+impl Tool for CreatePageTool {
+    fn name(&self) -> String {
+        "create_page".to_string()
+    }
+
+    fn description(&self) -> String {
+        "Use this tool to create a new page instead of writing its file yourself. Give it the route the page should answer on and it creates the page template in the right place under pages/ (e.g. '/blog/{slug}' becomes pages/blog/[slug].html), the same file `noventa new:page` creates, and returns the route it's served at.".to_string()
+    }
+
+    fn input_schema(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "route": {
+                    "type": "string",
+                    "description": "The route, e.g. '/about', '/blog/{slug}' or '/docs/[section]/'."
+                }
+            },
+            "required": ["route"]
+        })
+    }
+
+    fn run(&self, args: &Value) -> Result<Value, String> {
+        let route = args.get("route").and_then(Value::as_str).ok_or("Missing or invalid 'route' argument")?;
+
+        let workspace = Workspace::current()?;
+        let page_path = crate::generators::route_to_page_path(route).map_err(|e| format!("Error: {}", e))?;
+        workspace.resolve(&page_path.to_string_lossy())?;
+        let (file, resolved_route) = crate::generators::generate_page(workspace.project_dir(), route).map_err(|e| format!("Error: {}", e))?;
+
+        Ok(Value::String(format!("Created {}. It's served at {}.", shown(&workspace, &file), resolved_route)))
+    }
+}
+
+struct AddActionTool;
+
+impl Tool for AddActionTool {
+    fn name(&self) -> String {
+        "add_action".to_string()
+    }
+
+    fn description(&self) -> String {
+        "Use this tool to add a form action to a component. It adds an `action_<name>` function to the component's logic file (creating the file if the component has none) and tells you the form to put in the component's template to call it.".to_string()
+    }
+
+    fn input_schema(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "component": {
+                    "type": "string",
+                    "description": "The component's name, e.g. 'cards.pricing'."
+                },
+                "action_name": {
+                    "type": "string",
+                    "description": "The action's name in lowercase letters, digits and underscores, e.g. 'subscribe' for `action_subscribe`."
+                }
+            },
+            "required": ["component", "action_name"]
+        })
+    }
+
+    fn run(&self, args: &Value) -> Result<Value, String> {
+        let component = args.get("component").and_then(Value::as_str).ok_or("Missing or invalid 'component' argument")?;
+        let action = args.get("action_name").and_then(Value::as_str).ok_or("Missing or invalid 'action_name' argument")?;
+        let action = action.strip_prefix("action_").unwrap_or(action);
+
+        let workspace = Workspace::current()?;
+        workspace.resolve(&format!("components/{}", component.replace('.', "/")))?;
+        let logic_path = crate::generators::add_action(workspace.project_dir(), component, action).map_err(|e| format!("Error: {}", e))?;
+
+        Ok(Value::String(format!(
+            "Added `action_{}` to {}.\n\nCall it from the component's template with a form like:\n<form method=\"post\" data-action=\"{}\">\n    <button type=\"submit\">Send</button>\n</form>",
+            action,
+            shown(&workspace, &logic_path),
+            action
+        )))
+    }
+}
+
+struct ListRoutesTool;
+
+impl Tool for ListRoutesTool {
+    fn name(&self) -> String {
+        "list_routes".to_string()
+    }
+
+    fn description(&self) -> String {
+        "Use this tool to see the project's routes: every URL pattern with the page template that serves it, its params and the methods it accepts, plus routes that clash or shadow each other. It's the same table `noventa routes` prints.".to_string()
+    }
+
+    fn input_schema(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {}
+        })
+    }
+
+    fn run(&self, _args: &Value) -> Result<Value, String> {
+        let workspace = Workspace::current()?;
+        let table = crate::route_table::build_route_table(workspace.project_dir());
+        if table.rows.is_empty() {
+            return Ok(Value::String("There are no pages yet. Use create_page to add the first one.".to_string()));
+        }
+
+        let mut result = String::from("Routes, as `route -> template [params] (methods)`:\n");
+        for row in &table.rows {
+            let params = if row.params.is_empty() { String::new() } else { format!(" [{}]", row.params.join(", ")) };
+            result.push_str(&format!("- {} -> {}{} ({})\n", row.route_pattern, crate::paths::to_slash(std::path::Path::new(&row.template)), params, row.methods.join(", ")));
+        }
+        for shadowed in &table.shadowed {
+            result.push_str(&format!(
+                "\nNote: '{}' is served by its own page, so '{}' never receives that URL.",
+                shadowed.static_route, shadowed.dynamic_route
+            ));
+        }
+        for conflict in &table.conflicts {
+            result.push_str(&format!(
+                "\nConflict: {} ({}) clashes with {}, which is the one that gets registered.",
+                conflict.route_pattern,
+                shown(&workspace, &conflict.template_path),
+                shown(&workspace, &conflict.conflicts_with)
+            ));
+        }
+        Ok(Value::String(result))
+    }
+}
+
+struct RenderPageTool;
+
+impl Tool for RenderPageTool {
+    fn name(&self) -> String {
+        "render_page".to_string()
+    }
+
+    fn description(&self) -> String {
+        "Use this tool to check that a page actually renders after you change it, before telling the user you're done. It renders the page in-process with the project's real templates, components and Python logic, as the dev server would, and returns the HTML, or the error the dev server would show (pass it to explain_error to find the cause). Post form_data with method POST to try a component's action.".to_string()
+    }
+
+    fn input_schema(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "route": {
+                    "type": "string",
+                    "description": "A route as list_routes shows it, e.g. '/blog/{slug}', a URL path like '/blog/hello', or a page as url_for names it, e.g. 'blog/[slug]'."
+                },
+                "params": {
+                    "type": "object",
+                    "description": "Values for the route's params, e.g. {\"slug\": \"hello\"}. Any the route doesn't have go in the query string."
+                },
+                "method": {
+                    "type": "string",
+                    "description": "The HTTP method, GET unless you're posting a form."
+                },
+                "form_data": {
+                    "type": "object",
+                    "description": "The posted form's fields, e.g. {\"action\": \"subscribe\", \"email\": \"ada@example.com\"}."
+                }
+            },
+            "required": ["route"]
+        })
+    }
+
+    fn run(&self, args: &Value) -> Result<Value, String> {
+        let route = args.get("route").and_then(Value::as_str).ok_or("Missing or invalid 'route' argument")?;
+        let params = args
+            .get("params")
+            .and_then(Value::as_object)
+            .map(|params| {
+                params
+                    .iter()
+                    .map(|(name, value)| (name.clone(), value.as_str().map_or_else(|| value.to_string(), str::to_string)))
+                    .collect()
+            })
+            .unwrap_or_default();
+        let method = args.get("method").and_then(Value::as_str).unwrap_or("GET").to_uppercase();
+        let form_data = args.get("form_data").and_then(Value::as_object).cloned().unwrap_or_default();
+
+        let path = crate::reverse_routes::url_for_route(route, &params).map_err(|e| format!("Error: {}", e))?;
+        let request = format!("{} {}", method, path);
+        let preview = preview::render(preview::PreviewRequest { path, method, form_data })?;
+
+        match preview {
+            Preview::Rendered(template, output) => Ok(Value::String(match output {
+                RenderOutput::Html(html) => format!("{} rendered {} (200):\n\n{}", request, template, html),
+                RenderOutput::Status(status, html) => format!("{} rendered {} with status {}:\n\n{}", request, template, status, html),
+                RenderOutput::Redirect(status, url) => format!("{} ({}) redirects to {} ({}).", request, template, url, status),
+                RenderOutput::Component(html) => format!("{} ({}) re-rendered the component the form was posted from:\n\n{}", request, template, html),
+                RenderOutput::Stream(_) => format!("{} ({}) streams its response from a Python iterator, so there's no HTML to show.", request, template),
+                RenderOutput::Shed => format!("{} ({}) was turned away by the load shedder. Try again.", request, template),
+            })),
+            Preview::Failed(template, error) => Err(format!(
+                "{} failed to render {}. This is the error the dev server would show; pass it to explain_error to find the cause:\n\n{}",
+                request,
+                template,
+                serde_json::to_string_pretty(&error).unwrap_or_default()
+            )),
+            Preview::NotFound => Err(format!("{} doesn't match any page (404). Use list_routes to see the routes.", request)),
+        }
+    }
+}
+
+const DEFAULT_SEARCH_RESULTS: usize = 100;
+const MAX_SEARCH_RESULTS: usize = 500;
+// Files bigger than this are build output or data, not code worth searching.
+const MAX_SEARCH_FILE_SIZE: u64 = 1024 * 1024;
+// Long lines (minified files) are cut, so one match doesn't flood the context window.
+const MAX_MATCH_LINE_LEN: usize = 200;
+
+#[derive(Debug, PartialEq)]
+struct SearchMatch {
+    path: std::path::PathBuf,
+    // From 1, as editors show them.
+    line: usize,
+    text: String,
+}
+
+// Where a file's matches go in the results: component logic first, then templates, then the rest.
+fn search_rank(relative: &std::path::Path) -> (usize, &'static str) {
+    match get_path_type(relative) {
+        PathType::ComponentLogic(_) => (0, "Component Logic"),
+        PathType::ComponentTemplate(_) => (1, "Component Template"),
+        PathType::PageTemplate(_) => (1, "Page Template"),
+        PathType::PageLayout => (1, "Page Layout"),
+        PathType::ComponentModel(_) => (2, "Component Model"),
+        PathType::File | PathType::Directory => (2, "File"),
+    }
+}
+
+// Every line under `base_path` that `pattern` matches, skipping what .gitignore ignores and binary files.
+fn search_matches(base_path: &std::path::Path, pattern: &regex::Regex, glob: Option<&str>) -> Result<Vec<SearchMatch>, String> {
+    let mut walker = ignore::WalkBuilder::new(base_path);
+    walker.hidden(false).filter_entry(|entry| {
+        let name = entry.file_name();
+        name != ".git" && name != trash::TRASH_DIR
+    });
+    if let Some(glob) = glob {
+        let overrides = ignore::overrides::OverrideBuilder::new(base_path)
+            .add(glob)
+            .and_then(|builder| builder.build())
+            .map_err(|e| format!("Invalid glob '{}': {}", glob, e))?;
+        walker.overrides(overrides);
+    }
+
+    let mut matches = Vec::new();
+    for entry in walker.build().filter_map(Result::ok) {
+        if !entry.file_type().is_some_and(|t| t.is_file()) || entry.metadata().is_ok_and(|m| m.len() > MAX_SEARCH_FILE_SIZE) {
+            continue;
+        }
+        let Ok(bytes) = fs::read(entry.path()) else {
+            continue;
+        };
+        if is_binary(&bytes) {
+            continue;
+        }
+        for (index, line) in String::from_utf8_lossy(&bytes).lines().enumerate() {
+            if pattern.is_match(line) {
+                let line = line.trim();
+                let text = match line.char_indices().nth(MAX_MATCH_LINE_LEN) {
+                    Some((cut, _)) => format!("{}…", &line[..cut]),
+                    None => line.to_string(),
+                };
+                matches.push(SearchMatch { path: entry.path().to_path_buf(), line: index + 1, text });
+            }
+        }
+    }
+    Ok(matches)
+}
+
+struct SearchProjectTool;
+
+impl Tool for SearchProjectTool {
+    fn name(&self) -> String {
+        "search_project".to_string()
+    }
+
+    fn description(&self) -> String {
+        "Use this tool to find where something is in the project instead of reading files one by one. It searches the project's pages, components, layouts, Python code and other text files (skipping what .gitignore ignores) and returns the matching lines with their line numbers, grouped by file, component logic first, then templates, then everything else.".to_string()
+    }
+
+    fn input_schema(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "query": {
+                    "type": "string",
+                    "description": "The text to look for, e.g. 'action_subscribe' or 'url_for(\"blog'."
+                },
+                "regex": {
+                    "type": "boolean",
+                    "description": "Treat the query as a regular expression, e.g. 'def (load_template_context|action_\\w+)'."
+                },
+                "case_sensitive": {
+                    "type": "boolean",
+                    "description": "Match upper and lower case exactly. Off by default."
+                },
+                "path": {
+                    "type": "string",
+                    "description": "Only search this folder, e.g. 'components'. Defaults to the whole project."
+                },
+                "glob": {
+                    "type": "string",
+                    "description": "Only search files matching this pattern, e.g. '*.py' or 'components/**/*.html'."
+                },
+                "max_results": {
+                    "type": "integer",
+                    "description": format!("How many matching lines to return. Defaults to {}, at most {}.", DEFAULT_SEARCH_RESULTS, MAX_SEARCH_RESULTS)
+                }
+            },
+            "required": ["query"]
+        })
+    }
+
+    fn run(&self, args: &Value) -> Result<Value, String> {
+        let query = args.get("query").and_then(Value::as_str).filter(|q| !q.is_empty()).ok_or("Missing or invalid 'query' argument")?;
+        let is_regex = args.get("regex").and_then(Value::as_bool).unwrap_or(false);
+        let case_sensitive = args.get("case_sensitive").and_then(Value::as_bool).unwrap_or(false);
+        let glob = args.get("glob").and_then(Value::as_str).filter(|g| !g.is_empty());
+        let max_results = args
+            .get("max_results")
+            .and_then(Value::as_u64)
+            .map(|m| (m as usize).clamp(1, MAX_SEARCH_RESULTS))
+            .unwrap_or(DEFAULT_SEARCH_RESULTS);
+
+        let pattern = regex::RegexBuilder::new(&if is_regex { query.to_string() } else { regex::escape(query) })
+            .case_insensitive(!case_sensitive)
+            .build()
+            .map_err(|e| format!("Invalid regex '{}': {}", query, e))?;
+        let workspace = Workspace::current()?;
+        let base_path = match args.get("path").and_then(Value::as_str) {
+            Some(path) => workspace.resolve(path)?,
+            None => workspace.project_dir().to_path_buf(),
+        };
+        if !base_path.is_dir() {
+            return Err(format!("Failed to search: '{}' isn't a directory", base_path.display()));
+        }
+
+        let mut matches = search_matches(&base_path, &pattern, glob)?;
+        if matches.is_empty() {
+            return Ok(Value::String(format!("No matches for '{}'.", query)));
+        }
+        let total = matches.len();
+        let files = matches.iter().map(|m| &m.path).collect::<std::collections::HashSet<_>>().len();
+        matches.sort_by_cached_key(|m| (search_rank(&workspace.relative(&m.path)).0, m.path.clone(), m.line));
+        matches.truncate(max_results);
+
+        let mut result = format!("{} matching lines for '{}' in {} files, most relevant first:\n", total, query, files);
+        let mut current = None;
+        for m in &matches {
+            if current != Some(&m.path) {
+                let relative = workspace.relative(&m.path);
+                result.push_str(&format!("\n{} ({})\n", crate::paths::to_slash(&relative), search_rank(&relative).1));
+                current = Some(&m.path);
+            }
+            result.push_str(&format!("{:>5}: {}\n", m.line, m.text));
+        }
+        if total > matches.len() {
+            result.push_str(&format!(
+                "\nOnly the first {} of {} matching lines are shown. Narrow the query, path or glob to see the rest.\n",
+                matches.len(),
+                total
+            ));
+        }
+        Ok(Value::String(result))
+    }
+}
+
+// Written next to the file and renamed over it, so it's never left half-written.
+fn write_atomically(path: &std::path::Path, contents: &str) -> Result<(), String> {
+    let file_name = path.file_name().and_then(|name| name.to_str()).unwrap_or("file");
+    let partial = path.with_file_name(format!(".{}.tmp-{}", file_name, uuid::Uuid::new_v4().simple()));
+    fs::write(&partial, contents).map_err(|e| format!("Failed to write the file: {}", e))?;
+    if let Ok(metadata) = fs::metadata(path) {
+        let _ = fs::set_permissions(&partial, metadata.permissions());
+    }
+    fs::rename(&partial, path).map_err(|e| {
+        let _ = fs::remove_file(&partial);
+        format!("Failed to write the file: {}", e)
+    })
+}
+
+struct EditFileTool;
+
+impl Tool for EditFileTool {
+    fn name(&self) -> String {
+        "edit_file".to_string()
+    }
+
+    fn description(&self) -> String {
+        "Use this tool to change part of an existing file instead of rewriting all of it. Pass either a unified diff or a list of search/replace edits. Every edit is checked against the file first and they're applied together, so if one doesn't match nothing is changed and you're told which one. Returns the file's new sha256; pass it as expected_hash next time to make sure the file hasn't changed in between.".to_string()
+    }
+
+    fn input_schema(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "path": {
+                    "type": "string",
+                    "description": "The path of the file to edit."
+                },
+                "diff": {
+                    "type": "string",
+                    "description": "A unified diff of the file, with '@@ -12,4 +12,5 @@' hunks, like `git diff` prints. The line numbers can be approximate; the context lines have to match."
+                },
+                "edits": {
+                    "type": "array",
+                    "description": "Search/replace edits, applied in order. Each search text must be in the file exactly once, unless replace_all is set.",
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "search": {
+                                "type": "string",
+                                "description": "The exact text to replace, including indentation."
+                            },
+                            "replace": {
+                                "type": "string",
+                                "description": "What to put in its place."
+                            },
+                            "replace_all": {
+                                "type": "boolean",
+                                "description": "Replace every occurrence of the search text."
+                            }
+                        },
+                        "required": ["search", "replace"]
+                    }
+                },
+                "expected_hash": {
+                    "type": "string",
+                    "description": "The sha256 the file should have now, from a previous edit_file. The edit is refused if the file changed since."
+                }
+            },
+            "required": ["path"]
+        })
+    }
+
+    fn run(&self, args: &Value) -> Result<Value, String> {
+        let path_str = args.get("path").and_then(Value::as_str).ok_or("Missing or invalid 'path' argument")?;
+        let diff = args.get("diff").and_then(Value::as_str);
+        let edits = match args.get("edits") {
+            Some(edits) => Some(
+                edits
+                    .as_array()
+                    .ok_or("Invalid 'edits' argument: it must be a list")?
+                    .iter()
+                    .enumerate()
+                    .map(|(index, edit)| {
+                        let text = |key: &str| edit.get(key).and_then(Value::as_str).map(str::to_string).ok_or(format!("Edit {} needs a '{}' text", index + 1, key));
+                        Ok(patch::Replacement {
+                            search: text("search")?,
+                            replace: text("replace")?,
+                            replace_all: edit.get("replace_all").and_then(Value::as_bool).unwrap_or(false),
+                        })
+                    })
+                    .collect::<Result<Vec<_>, String>>()?,
+            ),
+            None => None,
+        };
+
+        let workspace = Workspace::current()?;
+        let resolved = workspace.resolve(path_str)?;
+        if resolved.is_dir() {
+            return Err(format!("'{}' is a directory, not a file.", path_str));
+        }
+        let original = fs::read_to_string(&resolved).map_err(|e| format!("Failed to read file: {}", e))?;
+        let original_hash = patch::hash(&original);
+        if let Some(expected) = args.get("expected_hash").and_then(Value::as_str)
+            && !expected.eq_ignore_ascii_case(&original_hash)
+        {
+            return Err(format!(
+                "'{}' changed since you last saw it (its sha256 is now {}). Read it again before editing.",
+                path_str, original_hash
+            ));
+        }
+
+        let edited = match (diff, edits) {
+            (Some(diff), None) => patch::apply_unified_diff(&original, diff)?,
+            (None, Some(edits)) => patch::apply_replacements(&original, &edits)?,
+            _ => return Err("Pass either 'diff' or 'edits', not both or neither.".to_string()),
+        };
+        let relative = workspace.relative(&resolved);
+        let shown_path = crate::paths::to_slash(&relative);
+        if edited == original {
+            return Ok(Value::String(format!("{} already looks like that, so it wasn't changed. Its sha256 is {}.", shown_path, original_hash)));
+        }
+        write_atomically(&resolved, &edited)?;
+
+        let role = get_file_metadata(&relative, false).map(|role| format!(" ({})", role)).unwrap_or_default();
+        Ok(Value::String(format!(
+            "Edited {}{}: it now has {} lines. Its sha256 is now {}.",
+            shown_path,
+            role,
+            edited.lines().count(),
+            patch::hash(&edited)
+        )))
     }
 }
 
@@ -532,8 +1447,19 @@ impl ToolManager {
         manager.register_tool(Arc::new(ListDirectoryTool));
         // manager.register_tool(Arc::new(CreateDirectoryTool));
         // manager.register_tool(Arc::new(WriteFileTool));
-        // manager.register_tool(Arc::new(DeleteDirectoryTool));
-        // manager.register_tool(Arc::new(DeleteFileTool));
+        manager.register_tool(Arc::new(DeleteDirectoryTool));
+        manager.register_tool(Arc::new(DeleteFileTool));
+        manager.register_tool(Arc::new(RestoreFileTool));
+        manager.register_tool(Arc::new(GetPageDependenciesTool));
+        manager.register_tool(Arc::new(GetComponentGraphTool));
+        manager.register_tool(Arc::new(ExplainErrorTool));
+        manager.register_tool(Arc::new(CreateComponentTool));
+        manager.register_tool(Arc::new(CreatePageTool));
+        manager.register_tool(Arc::new(AddActionTool));
+        manager.register_tool(Arc::new(ListRoutesTool));
+        manager.register_tool(Arc::new(RenderPageTool));
+        manager.register_tool(Arc::new(SearchProjectTool));
+        manager.register_tool(Arc::new(EditFileTool));
         manager
     }
 
@@ -560,7 +1486,13 @@ pub fn run_interactive_tool(
         .and_then(Value::as_u64)
         .map(|u| u as usize);
 
-    let response = tool_runner.run_tool(tool_name, user_input);
+    let conversation = args
+        .get("user_id")
+        .and_then(Value::as_str)
+        .filter(|id| !id.is_empty())
+        .unwrap_or(DEFAULT_CONVERSATION);
+
+    let response = tool_runner.run_tool(conversation, tool_name, user_input);
     Ok(Value::String(response))
 }
 
@@ -569,30 +1501,6 @@ mod tests {
     use super::*;
     use std::path::Path;
 
-    #[test]
-    fn test_is_path_safe_safe_paths() {
-        // Test safe relative paths
-        assert!(is_path_safe(Path::new("file.txt")).unwrap());
-        assert!(is_path_safe(Path::new("subdir/file.txt")).unwrap());
-        assert!(is_path_safe(Path::new("./file.txt")).unwrap());
-        
-        // Test safe absolute paths within current directory
-        let current_dir = std::env::current_dir().unwrap();
-        assert!(is_path_safe(&current_dir.join("file.txt")).unwrap());
-    }
-
-    #[test]
-    fn test_is_path_safe_unsafe_paths() {
-        // Test paths that try to escape the current directory
-        assert!(!is_path_safe(Path::new("../file.txt")).unwrap());
-        assert!(!is_path_safe(Path::new("../../file.txt")).unwrap());
-        assert!(!is_path_safe(Path::new("../../../etc/passwd")).unwrap());
-        
-        // Test absolute paths outside current directory
-        assert!(!is_path_safe(Path::new("/etc/passwd")).unwrap());
-        assert!(!is_path_safe(Path::new("/tmp/file.txt")).unwrap());
-    }
-
     #[test]
     fn test_get_path_type_directory() {
         let temp_dir = tempfile::tempdir().unwrap();
@@ -718,4 +1626,96 @@ mod tests {
         assert_eq!(get_file_metadata(path, false), None);
         assert_eq!(get_file_metadata(path, true), None);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_is_binary() {
+        assert!(!is_binary(b"<h1>Hello</h1>"));
+        assert!(!is_binary("héllo".as_bytes()));
+        assert!(is_binary(&[0x89, b'P', b'N', b'G', 0x00, 0x1a]));
+        assert!(is_binary(&[0xff, 0xfe, 0xfd, b'a']));
+    }
+
+    #[test]
+    fn test_paginate() {
+        let contents = "one\ntwo\nthree\nfour\nfive";
+        assert_eq!(paginate(contents, 1, 2000), ("one\ntwo\nthree\nfour\nfive".to_string(), 1, 5, 5));
+        assert_eq!(paginate(contents, 2, 2), ("two\nthree".to_string(), 2, 3, 5));
+        assert_eq!(paginate(contents, 0, 1), ("one".to_string(), 1, 1, 5));
+        assert_eq!(paginate(contents, 9, 5).0, "");
+    }
+
+    #[test]
+    fn test_collect_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("components/cards/pricing")).unwrap();
+        fs::write(root.join("components/cards/pricing/pricing_logic.py"), "").unwrap();
+        fs::write(root.join("components/cards/pricing/pricing_template.html"), "").unwrap();
+        fs::write(root.join("index.html"), "").unwrap();
+
+        let (flat, _) = collect_entries(root, 1, None).unwrap();
+        assert_eq!(flat, vec![root.join("components"), root.join("index.html")]);
+
+        let (deep, truncated) = collect_entries(root, MAX_LIST_DEPTH, None).unwrap();
+        assert_eq!(deep.len(), 6);
+        assert!(!truncated);
+
+        let (logic, _) = collect_entries(root, MAX_LIST_DEPTH, Some("*_logic.py")).unwrap();
+        assert_eq!(logic, vec![root.join("components/cards/pricing/pricing_logic.py")]);
+
+        let (shallow, _) = collect_entries(root, 2, Some("*.html")).unwrap();
+        assert_eq!(shallow, vec![root.join("index.html")]);
+    }
+
+    #[test]
+    fn test_search_matches() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("components/pricing")).unwrap();
+        fs::create_dir_all(root.join("pages")).unwrap();
+        fs::write(root.join("components/pricing/pricing_logic.py"), "import json\n\ndef action_subscribe(request, **props):\n    pass\n").unwrap();
+        fs::write(root.join("components/pricing/pricing_template.html"), "<form data-action=\"subscribe\"></form>").unwrap();
+        fs::write(root.join("pages/index.html"), "{{ component(\"pricing\") }}\n<p>Subscribe today</p>").unwrap();
+        fs::write(root.join("logo.png"), [0x89, b'P', b'N', b'G', 0x00, b's', b'u', b'b', b's', b'c', b'r', b'i', b'b', b'e']).unwrap();
+
+        let pattern = regex::RegexBuilder::new("subscribe").case_insensitive(true).build().unwrap();
+        let mut matches = search_matches(root, &pattern, None).unwrap();
+        matches.sort_by_key(|m| (search_rank(m.path.strip_prefix(root).unwrap()).0, m.path.clone(), m.line));
+        let found: Vec<(String, usize)> =
+            matches.iter().map(|m| (crate::paths::to_slash(m.path.strip_prefix(root).unwrap()), m.line)).collect();
+        assert_eq!(
+            found,
+            [
+                ("components/pricing/pricing_logic.py".to_string(), 3),
+                ("components/pricing/pricing_template.html".to_string(), 1),
+                ("pages/index.html".to_string(), 2),
+            ]
+        );
+        assert_eq!(matches[0].text, "def action_subscribe(request, **props):");
+
+        let logic_only = search_matches(root, &pattern, Some("*.py")).unwrap();
+        assert_eq!(logic_only.len(), 1);
+    }
+
+    #[test]
+    fn test_restore_from_trash() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        fs::create_dir_all(root.join("apps/site/pages")).unwrap();
+        fs::write(root.join("apps/site/pages/about.html"), "about").unwrap();
+        let workspace = Workspace::new(root.clone(), root.join("apps/site"));
+
+        // Deleted and restored with the same path, relative to the project rather than the workspace root.
+        soft_delete(&workspace, &workspace.resolve("pages/about.html").unwrap()).unwrap();
+        assert_eq!(restore_from_trash(&workspace, "./pages/about.html").unwrap(), root.join("apps/site/pages/about.html"));
+        assert_eq!(fs::read_to_string(root.join("apps/site/pages/about.html")).unwrap(), "about");
+
+        // A manifest pointing outside the workspace isn't followed.
+        let entry = soft_delete(&workspace, &workspace.resolve("pages/about.html").unwrap()).unwrap();
+        let manifest = root.join(trash::TRASH_DIR).join(&entry.id).join("manifest.json");
+        let tampered = trash::TrashEntry { original_path: "../escaped.html".to_string(), ..entry.clone() };
+        fs::write(&manifest, serde_json::to_string(&tampered).unwrap()).unwrap();
+        assert!(restore_from_trash(&workspace, &entry.id).unwrap_err().contains("outside the workspace"));
+        assert!(!root.parent().unwrap().join("escaped.html").exists());
+    }
+}
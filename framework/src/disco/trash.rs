@@ -0,0 +1,204 @@
+// framework/src/disco/trash.rs
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Deleted files and directories are moved here instead of being removed, so agent mistakes can be undone.
+pub const TRASH_DIR: &str = ".noventa-trash";
+// Trashed items older than this are purged the next time something is deleted.
+pub const RETENTION_SECS: i64 = 7 * 24 * 60 * 60;
+
+const PAYLOAD_NAME: &str = "payload";
+const MANIFEST_NAME: &str = "manifest.json";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrashEntry {
+    pub id: String,
+    pub original_path: String,
+    pub deleted_at: i64,
+    pub is_directory: bool,
+}
+
+fn trash_root(root: &Path) -> PathBuf {
+    root.join(TRASH_DIR)
+}
+
+fn relative_to_root(root: &Path, path: &Path) -> PathBuf {
+    path.strip_prefix(root).map(Path::to_path_buf).unwrap_or_else(|_| path.to_path_buf())
+}
+
+// Moves `path` (relative to `root`) into the trash and returns the entry describing it.
+pub fn move_to_trash(root: &Path, path: &Path) -> Result<TrashEntry, String> {
+    let source = root.join(path);
+    let metadata = fs::symlink_metadata(&source).map_err(|e| format!("Failed to find '{}': {}", path.display(), e))?;
+
+    let now = chrono::Utc::now().timestamp();
+    let id = format!("{}-{}", now, &uuid::Uuid::new_v4().simple().to_string()[..8]);
+    let entry_dir = trash_root(root).join(&id);
+    fs::create_dir_all(&entry_dir).map_err(|e| format!("Failed to create the trash folder: {}", e))?;
+
+    let entry = TrashEntry {
+        id,
+        original_path: relative_to_root(root, path).to_string_lossy().replace('\\', "/"),
+        deleted_at: now,
+        is_directory: metadata.is_dir(),
+    };
+
+    fs::rename(&source, entry_dir.join(PAYLOAD_NAME)).map_err(|e| {
+        let _ = fs::remove_dir_all(&entry_dir);
+        format!("Failed to move '{}' to the trash: {}", path.display(), e)
+    })?;
+    let manifest = serde_json::to_string_pretty(&entry).map_err(|e| e.to_string())?;
+    fs::write(entry_dir.join(MANIFEST_NAME), manifest).map_err(|e| format!("Failed to write the trash manifest: {}", e))?;
+
+    Ok(entry)
+}
+
+// Every trashed item, newest first. The id always comes from the entry's folder name, never from the
+// manifest, so an edited manifest can't point `restore` or `purge_expired` outside the trash.
+pub fn list_entries(root: &Path) -> Vec<TrashEntry> {
+    let mut entries: Vec<TrashEntry> = fs::read_dir(trash_root(root))
+        .map(|dir| {
+            dir.filter_map(Result::ok)
+                .filter(|e| e.file_type().map(|t| t.is_dir()).unwrap_or(false))
+                .filter_map(|e| {
+                    let id = e.file_name().into_string().ok()?;
+                    let manifest = fs::read_to_string(e.path().join(MANIFEST_NAME)).ok()?;
+                    let entry: TrashEntry = serde_json::from_str(&manifest).ok()?;
+                    Some(TrashEntry { id, ..entry })
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    entries.sort_by(|a, b| b.deleted_at.cmp(&a.deleted_at).then(b.id.cmp(&a.id)));
+    entries
+}
+
+// The most recently trashed copy of `path` (relative to `root`, like `original_path`), or the entry with id `id`.
+pub fn find(root: &Path, id: &str, path: &Path) -> Option<TrashEntry> {
+    list_entries(root).into_iter().find(|e| e.id == id || Path::new(&e.original_path) == path)
+}
+
+// Moves `entry` back to `destination`. The manifest is a file like any other, so `original_path` is
+// only a hint: the caller resolves it through the workspace and passes where it really goes.
+pub fn restore(root: &Path, entry: &TrashEntry, destination: &Path) -> Result<(), String> {
+    if destination.exists() {
+        return Err(format!(
+            "'{}' already exists. Move or delete it first, then restore again.",
+            entry.original_path
+        ));
+    }
+    if let Some(parent) = destination.parent() {
+        fs::create_dir_all(parent).map_err(|e| format!("Failed to recreate parent directories: {}", e))?;
+    }
+
+    let entry_dir = trash_root(root).join(&entry.id);
+    fs::rename(entry_dir.join(PAYLOAD_NAME), destination)
+        .map_err(|e| format!("Failed to restore '{}': {}", entry.original_path, e))?;
+    let _ = fs::remove_dir_all(&entry_dir);
+
+    Ok(())
+}
+
+// Permanently removes trashed items older than the retention window. Returns how many were purged.
+pub fn purge_expired(root: &Path, now: i64) -> usize {
+    let mut purged = 0;
+    for entry in list_entries(root) {
+        if now - entry.deleted_at > RETENTION_SECS
+            && fs::remove_dir_all(trash_root(root).join(&entry.id)).is_ok()
+        {
+            purged += 1;
+        }
+    }
+    purged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_trash_and_restore_file() {
+        let dir = tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("pages")).unwrap();
+        fs::write(root.join("pages/about.html"), "about").unwrap();
+
+        let entry = move_to_trash(root, Path::new("pages/about.html")).unwrap();
+        assert!(!root.join("pages/about.html").exists());
+        assert_eq!(entry.original_path, "pages/about.html");
+        assert!(!entry.is_directory);
+        assert_eq!(list_entries(root).len(), 1);
+
+        let found = find(root, "", Path::new("pages/about.html")).unwrap();
+        assert_eq!(found.id, entry.id);
+        restore(root, &found, &root.join("pages/about.html")).unwrap();
+        assert_eq!(fs::read_to_string(root.join("pages/about.html")).unwrap(), "about");
+        assert!(list_entries(root).is_empty());
+    }
+
+    #[test]
+    fn test_trash_and_restore_directory_by_id() {
+        let dir = tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("components/card")).unwrap();
+        fs::write(root.join("components/card/card_template.html"), "<div></div>").unwrap();
+
+        let entry = move_to_trash(root, Path::new("components/card")).unwrap();
+        assert!(entry.is_directory);
+        assert!(!root.join("components/card").exists());
+
+        let found = find(root, &entry.id, Path::new("")).unwrap();
+        restore(root, &found, &root.join(&found.original_path)).unwrap();
+        assert!(root.join("components/card/card_template.html").exists());
+    }
+
+    #[test]
+    fn test_restore_refuses_to_overwrite() {
+        let dir = tempdir().unwrap();
+        let root = dir.path();
+        fs::write(root.join("notes.txt"), "old").unwrap();
+        let entry = move_to_trash(root, Path::new("notes.txt")).unwrap();
+        fs::write(root.join("notes.txt"), "new").unwrap();
+
+        assert!(restore(root, &entry, &root.join("notes.txt")).is_err());
+        assert_eq!(fs::read_to_string(root.join("notes.txt")).unwrap(), "new");
+        assert!(find(root, "missing.txt", Path::new("missing.txt")).is_none());
+    }
+
+    #[test]
+    fn test_purge_expired() {
+        let dir = tempdir().unwrap();
+        let root = dir.path();
+        fs::write(root.join("old.txt"), "old").unwrap();
+        let entry = move_to_trash(root, Path::new("old.txt")).unwrap();
+
+        assert_eq!(purge_expired(root, entry.deleted_at + 60), 0);
+        assert_eq!(purge_expired(root, entry.deleted_at + RETENTION_SECS + 1), 1);
+        assert!(list_entries(root).is_empty());
+    }
+
+    #[test]
+    fn test_tampered_manifest_id_is_ignored() {
+        let dir = tempdir().unwrap();
+        let root = dir.path().join("project");
+        fs::create_dir_all(&root).unwrap();
+        fs::create_dir_all(dir.path().join("outside")).unwrap();
+        fs::write(root.join("old.txt"), "old").unwrap();
+        let entry = move_to_trash(&root, Path::new("old.txt")).unwrap();
+
+        let manifest_path = trash_root(&root).join(&entry.id).join(MANIFEST_NAME);
+        let tampered = TrashEntry { id: "../../outside".to_string(), ..entry.clone() };
+        fs::write(&manifest_path, serde_json::to_string(&tampered).unwrap()).unwrap();
+
+        let listed = list_entries(&root);
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].id, entry.id);
+        assert!(find(&root, "../../outside", Path::new("")).is_none());
+
+        assert_eq!(purge_expired(&root, entry.deleted_at + RETENTION_SECS + 1), 1);
+        assert!(dir.path().join("outside").exists());
+        assert!(list_entries(&root).is_empty());
+    }
+}
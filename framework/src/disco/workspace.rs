@@ -0,0 +1,155 @@
+// framework/src/disco/workspace.rs
+use crate::config::Config;
+use path_clean::PathClean;
+use std::path::{Path, PathBuf};
+
+// The folder Disco's tools may touch. Everything they read, write or delete must resolve inside it.
+pub struct Workspace {
+    root: PathBuf,
+    // The project Disco runs in; relative paths from tool calls start here.
+    project_dir: PathBuf,
+}
+
+impl Workspace {
+    // Both paths must already be canonical, otherwise nothing would ever match them.
+    pub fn new(root: PathBuf, project_dir: PathBuf) -> Self {
+        Self { root, project_dir }
+    }
+
+    // `disco.workspace_root` from config.yaml (relative to the project), or the current directory.
+    pub fn current() -> Result<Self, String> {
+        let current_dir = std::env::current_dir().map_err(|e| format!("Failed to get current directory: {}", e))?;
+        let configured = Config::from_file(&current_dir.join("config.yaml").to_string_lossy())
+            .ok()
+            .and_then(|config| config.disco)
+            .and_then(|disco| disco.workspace_root);
+        let project_dir = current_dir
+            .canonicalize()
+            .map_err(|e| format!("Failed to resolve the current directory: {}", e))?;
+        let root = match &configured {
+            Some(configured) => project_dir.join(configured).canonicalize().map_err(|e| {
+                format!("The workspace root '{}' set in `disco.workspace_root` can't be used: {}", configured, e)
+            })?,
+            None => project_dir.clone(),
+        };
+        Ok(Self::new(root, project_dir))
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn project_dir(&self) -> &Path {
+        &self.project_dir
+    }
+
+    // Turns a path from a tool call into an absolute one inside the workspace.
+    // Symlinks are resolved first, so a link inside the project can't lead outside it, and paths
+    // that don't exist yet (files about to be written) are checked through their closest existing parent.
+    pub fn resolve(&self, path: &str) -> Result<PathBuf, String> {
+        let requested = Path::new(path);
+        let joined = if requested.is_absolute() { requested.to_path_buf() } else { self.project_dir.join(requested) }.clean();
+
+        let mut existing = joined.as_path();
+        let mut missing = Vec::new();
+        let canonical = loop {
+            match existing.canonicalize() {
+                Ok(canonical) => break canonical,
+                // It's there but can't be followed, e.g. a symlink pointing nowhere.
+                Err(e) if existing.symlink_metadata().is_ok() => {
+                    return Err(format!("Failed to resolve path '{}': {}", path, e));
+                }
+                Err(_) => match (existing.file_name(), existing.parent()) {
+                    (Some(name), Some(parent)) => {
+                        missing.push(name.to_os_string());
+                        existing = parent;
+                    }
+                    _ => return Err(format!("Failed to resolve path '{}'", path)),
+                },
+            }
+        };
+        let resolved = missing.iter().rev().fold(canonical, |resolved, name| resolved.join(name));
+
+        if !resolved.starts_with(&self.root) {
+            return Err(format!(
+                "Error: Access to paths outside the workspace ({}) is not allowed.",
+                self.root.display()
+            ));
+        }
+        Ok(resolved)
+    }
+
+    // `resolved` relative to the project, which is how pages and components are recognised.
+    pub fn relative(&self, resolved: &Path) -> PathBuf {
+        resolved
+            .strip_prefix(&self.project_dir)
+            .or_else(|_| resolved.strip_prefix(&self.root))
+            .unwrap_or(resolved)
+            .to_path_buf()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    fn workspace(dir: &tempfile::TempDir) -> Workspace {
+        let root = dir.path().canonicalize().unwrap();
+        Workspace::new(root.clone(), root)
+    }
+
+    #[test]
+    fn test_resolve_inside_workspace() {
+        let dir = tempdir().unwrap();
+        let workspace = workspace(&dir);
+        fs::create_dir_all(workspace.root().join("pages")).unwrap();
+
+        assert_eq!(workspace.resolve("pages").unwrap(), workspace.root().join("pages"));
+        assert_eq!(workspace.resolve("./pages/new/index.html").unwrap(), workspace.root().join("pages/new/index.html"));
+        assert_eq!(workspace.resolve("pages/../config.yaml").unwrap(), workspace.root().join("config.yaml"));
+        let absolute = workspace.root().join("layouts/main.html");
+        assert_eq!(workspace.resolve(absolute.to_str().unwrap()).unwrap(), absolute);
+        assert_eq!(workspace.relative(&absolute), PathBuf::from("layouts/main.html"));
+    }
+
+    #[test]
+    fn test_resolve_outside_workspace() {
+        let dir = tempdir().unwrap();
+        let workspace = workspace(&dir);
+
+        assert!(workspace.resolve("../file.txt").is_err());
+        assert!(workspace.resolve("../../../etc/passwd").is_err());
+        assert!(workspace.resolve("/etc/passwd").is_err());
+        assert!(workspace.resolve("/tmp/file.txt").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_resolve_rejects_symlink_escapes() {
+        let outside = tempdir().unwrap();
+        let dir = tempdir().unwrap();
+        let workspace = workspace(&dir);
+        std::os::unix::fs::symlink(outside.path(), workspace.root().join("linked")).unwrap();
+        std::os::unix::fs::symlink(outside.path().join("gone"), workspace.root().join("dangling")).unwrap();
+
+        // Whether or not the target exists, the link leads outside.
+        assert!(workspace.resolve("linked").is_err());
+        assert!(workspace.resolve("linked/new_file.txt").is_err());
+        assert!(workspace.resolve("dangling").is_err());
+    }
+
+    #[test]
+    fn test_project_inside_a_larger_workspace() {
+        let dir = tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        fs::create_dir_all(root.join("apps/site")).unwrap();
+        let workspace = Workspace::new(root.clone(), root.join("apps/site"));
+
+        assert_eq!(workspace.resolve("pages/index.html").unwrap(), root.join("apps/site/pages/index.html"));
+        assert_eq!(workspace.resolve("../../shared/base.html").unwrap(), root.join("shared/base.html"));
+        assert!(workspace.resolve("../../../elsewhere").is_err());
+        assert_eq!(workspace.relative(&root.join("apps/site/pages/index.html")), PathBuf::from("pages/index.html"));
+    }
+}
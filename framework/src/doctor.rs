@@ -0,0 +1,361 @@
+use crate::config::{Config, ConfigError, SessionBackend};
+use crate::python_env;
+use pyo3::prelude::*;
+use std::path::Path;
+use std::time::Duration;
+
+const MIN_PYTHON: (u8, u8) = (3, 10);
+// Import name and pip package of everything the framework imports at runtime.
+const REQUIRED_PACKAGES: [(&str, &str); 3] = [("sqlalchemy", "SQLAlchemy"), ("alembic", "alembic"), ("werkzeug", "werkzeug")];
+const PROJECT_DIRS: [&str; 3] = ["pages", "components", "layouts"];
+const REDIS_TIMEOUT: Duration = Duration::from_secs(3);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Status {
+    Ok,
+    Warning,
+    Failed,
+    Skipped,
+}
+
+#[derive(Debug)]
+pub struct Diagnosis {
+    pub name: String,
+    pub status: Status,
+    pub detail: String,
+    pub fix: Option<String>,
+}
+
+impl Diagnosis {
+    fn ok(name: &str, detail: impl Into<String>) -> Self {
+        Self { name: name.to_string(), status: Status::Ok, detail: detail.into(), fix: None }
+    }
+
+    fn failed(name: &str, detail: impl Into<String>, fix: impl Into<String>) -> Self {
+        Self { name: name.to_string(), status: Status::Failed, detail: detail.into(), fix: Some(fix.into()) }
+    }
+
+    fn warning(name: &str, detail: impl Into<String>, fix: impl Into<String>) -> Self {
+        Self { name: name.to_string(), status: Status::Warning, detail: detail.into(), fix: Some(fix.into()) }
+    }
+
+    fn skipped(name: &str, detail: impl Into<String>) -> Self {
+        Self { name: name.to_string(), status: Status::Skipped, detail: detail.into(), fix: None }
+    }
+}
+
+fn check_python() -> Vec<Diagnosis> {
+    Python::attach(|py| {
+        let mut results = Vec::new();
+        let version = py.version_info();
+        if (version.major, version.minor) >= MIN_PYTHON {
+            results.push(Diagnosis::ok("Python", format!("{}.{}.{}", version.major, version.minor, version.patch)));
+        } else {
+            results.push(Diagnosis::failed(
+                "Python",
+                format!("{}.{}.{} is too old", version.major, version.minor, version.patch),
+                format!("Install Python {}.{} or newer and run noventa from that environment.", MIN_PYTHON.0, MIN_PYTHON.1),
+            ));
+        }
+
+        for (module, package) in REQUIRED_PACKAGES {
+            let name = format!("Python package {}", package);
+            match py.import(module) {
+                Ok(imported) => {
+                    let version = imported
+                        .getattr("__version__")
+                        .and_then(|v| v.extract::<String>())
+                        .unwrap_or_else(|_| "installed".to_string());
+                    results.push(Diagnosis::ok(&name, version));
+                }
+                Err(e) => results.push(Diagnosis::failed(
+                    &name,
+                    format!("can't be imported: {}", e),
+                    format!("Run `pip install {}` in the Python environment noventa uses.", package),
+                )),
+            }
+        }
+        results
+    })
+}
+
+fn check_python_install(config: &Config) -> Diagnosis {
+    let Some(executable) = config.python.as_ref().and_then(|python| python.executable.as_deref()) else {
+        return Diagnosis::skipped("Python install", "`python.executable` isn't set, so libpython finds its own files");
+    };
+    let fix = "Run `noventa doctor --fix-python` to find a Python that matches.";
+    match python_env::inspect(Path::new(executable)) {
+        Err(e) => Diagnosis::failed("Python install", e, fix),
+        Ok(install) => match python_env::library_version() {
+            Some(linked) if linked != install.minor_version() => Diagnosis::failed(
+                "Python install",
+                format!("{} is Python {}.{}, but noventa runs Python {}.{}", executable, install.version.0, install.version.1, linked.0, linked.1),
+                fix,
+            ),
+            _ => Diagnosis::ok("Python install", format!("{} ({}.{}.{})", executable, install.version.0, install.version.1, install.version.2)),
+        },
+    }
+}
+
+fn check_config(root: &Path) -> (Diagnosis, Option<Config>) {
+    let config_path = root.join("config.yaml");
+    match Config::from_file(&config_path.to_string_lossy()) {
+        Ok(config) => (Diagnosis::ok("config.yaml", "found and valid"), Some(config)),
+        Err(ConfigError::Io(e)) => (
+            Diagnosis::failed(
+                "config.yaml",
+                format!("couldn't be read: {}", e),
+                "Run noventa from your project folder, or create a new project with `noventa new`.",
+            ),
+            None,
+        ),
+        Err(ConfigError::Parse(e)) => (
+            Diagnosis::failed("config.yaml", format!("isn't valid: {}", e), "Fix the setting mentioned above and run the doctor again."),
+            None,
+        ),
+        Err(ConfigError::Invalid(problems)) => (
+            Diagnosis::failed(
+                "config.yaml",
+                format!("has {} problem(s):\n      {}", problems.len(), problems.join("\n      ")),
+                "Fix the settings listed above and run the doctor again.",
+            ),
+            None,
+        ),
+    }
+}
+
+fn check_project_dirs(root: &Path) -> Vec<Diagnosis> {
+    PROJECT_DIRS
+        .iter()
+        .map(|dir| {
+            let name = format!("{}/ folder", dir);
+            if root.join(dir).is_dir() {
+                Diagnosis::ok(&name, "present")
+            } else {
+                Diagnosis::failed(&name, "missing", format!("Create it with `mkdir {}`.", dir))
+            }
+        })
+        .collect()
+}
+
+fn check_port(config: &Config) -> Diagnosis {
+    let address = config.server_address.as_deref().unwrap_or("127.0.0.1");
+    let port = config.port.unwrap_or(8080);
+    match std::net::TcpListener::bind((address, port as u16)) {
+        Ok(_) => Diagnosis::ok("Port", format!("{}:{} is free", address, port)),
+        Err(e) => Diagnosis::failed(
+            "Port",
+            format!("can't listen on {}:{}: {}", address, port, e),
+            "Stop whatever is using that port, or change `port` in config.yaml.",
+        ),
+    }
+}
+
+fn check_database(config: &Config) -> Diagnosis {
+    let Some(url) = &config.database else {
+        return Diagnosis::skipped("Database", "no `database` configured");
+    };
+    Python::attach(|py| {
+        let Ok(sqlalchemy) = py.import("sqlalchemy") else {
+            return Diagnosis::skipped("Database", "needs SQLAlchemy to connect");
+        };
+        let connected = sqlalchemy
+            .getattr("create_engine")
+            .and_then(|create_engine| create_engine.call1((url.as_str(),)))
+            .and_then(|engine| {
+                let connection = engine.call_method0("connect")?;
+                connection.call_method0("close")?;
+                engine.call_method0("dispose")
+            });
+        match connected {
+            Ok(_) => Diagnosis::ok("Database", "connected"),
+            Err(e) => Diagnosis::failed(
+                "Database",
+                format!("couldn't connect: {}", e.value(py)),
+                "Check the `database` URL in config.yaml and that the database server is running.",
+            ),
+        }
+    })
+}
+
+fn check_redis(config: &Config) -> Diagnosis {
+    let Some(session) = config.session.as_ref().filter(|s| matches!(s.backend, SessionBackend::Redis)) else {
+        return Diagnosis::skipped("Redis", "not used by the session backend");
+    };
+    let Some(url) = &session.redis_url else {
+        return Diagnosis::failed(
+            "Redis",
+            "the redis session backend is enabled without a `redis_url`",
+            "Add `redis_url: redis://127.0.0.1:6379` under `session` in config.yaml.",
+        );
+    };
+    match ping_redis(url) {
+        Ok(_) => Diagnosis::ok("Redis", format!("{} answered", url)),
+        Err(e) => Diagnosis::failed(
+            "Redis",
+            format!("couldn't reach {}: {}", url, e),
+            "Start Redis or fix `session.redis_url` in config.yaml.",
+        ),
+    }
+}
+
+fn ping_redis(url: &str) -> deadpool_redis::redis::RedisResult<String> {
+    deadpool_redis::redis::Client::open(url)
+        .and_then(|client| client.get_connection_with_timeout(REDIS_TIMEOUT))
+        .and_then(|mut connection| deadpool_redis::redis::cmd("PING").query::<String>(&mut connection))
+}
+
+fn check_cluster_redis(config: &Config) -> Diagnosis {
+    let Some(cluster) = &config.cluster else {
+        return Diagnosis::skipped("Cluster Redis", "no `cluster` section");
+    };
+    match ping_redis(&cluster.redis_url) {
+        Ok(_) => Diagnosis::ok("Cluster Redis", format!("{} answered", cluster.redis_url)),
+        Err(e) => Diagnosis::failed(
+            "Cluster Redis",
+            format!("couldn't reach {}: {}", cluster.redis_url, e),
+            "Start Redis or fix `cluster.redis_url` in config.yaml. Until then every instance works on its own.",
+        ),
+    }
+}
+
+fn check_multi_instance(config: &Config) -> Vec<Diagnosis> {
+    const NAME: &str = "Multiple instances";
+    let issues = crate::scaling::multi_instance_issues(config);
+    if issues.is_empty() {
+        return vec![Diagnosis::ok(NAME, "no state is kept inside a single instance")];
+    }
+    if !crate::scaling::expects_multiple_instances(config) {
+        return vec![Diagnosis::ok(
+            NAME,
+            format!("fine for one instance; set `multi_instance: true` to check {} setting(s) for load balancing", issues.len()),
+        )];
+    }
+    issues
+        .into_iter()
+        .map(|issue| Diagnosis::warning(NAME, format!("`{}`: {}", issue.setting, issue.problem), issue.fix))
+        .collect()
+}
+
+// Runs every check against the project in `root` without starting the server.
+pub fn run_doctor(root: &Path) -> Vec<Diagnosis> {
+    let mut results = check_python();
+    let (config_result, config) = check_config(root);
+    results.push(config_result);
+    results.extend(check_project_dirs(root));
+
+    match config {
+        Some(config) => {
+            if config.session.as_ref().is_some_and(|s| s.secret_key.contains("REPLACE-ME")) {
+                results.push(Diagnosis::warning(
+                    "Session secret",
+                    "still uses the placeholder value",
+                    "Set `session.secret_key` in config.yaml to a long random string.",
+                ));
+            }
+            results.push(check_python_install(&config));
+            results.push(check_port(&config));
+            results.push(check_database(&config));
+            results.push(check_redis(&config));
+            results.push(check_cluster_redis(&config));
+            results.extend(check_multi_instance(&config));
+        }
+        None => {
+            for name in ["Port", "Database", "Redis"] {
+                results.push(Diagnosis::skipped(name, "needs a valid config.yaml"));
+            }
+        }
+    }
+    results
+}
+
+pub fn print_diagnoses(results: &[Diagnosis]) {
+    for result in results {
+        let icon = match result.status {
+            Status::Ok => "✓",
+            Status::Warning => "!",
+            Status::Failed => "✗",
+            Status::Skipped => "-",
+        };
+        println!("{} {}: {}", icon, result.name, result.detail);
+        if let Some(fix) = &result.fix {
+            println!("    → {}", fix);
+        }
+    }
+
+    let failed = results.iter().filter(|r| r.status == Status::Failed).count();
+    if failed == 0 {
+        println!("\n✨ Everything looks good. Happy coding!");
+    } else {
+        println!("\nFound {} problem(s). Follow the hints above and run `noventa doctor` again.", failed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_missing_config_skips_dependent_checks() {
+        let dir = tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("pages")).unwrap();
+
+        let results = run_doctor(dir.path());
+        let find = |name: &str| results.iter().find(|r| r.name == name).unwrap();
+        assert_eq!(find("config.yaml").status, Status::Failed);
+        assert_eq!(find("pages/ folder").status, Status::Ok);
+        assert_eq!(find("layouts/ folder").status, Status::Failed);
+        assert_eq!(find("Database").status, Status::Skipped);
+    }
+
+    #[test]
+    fn test_reports_invalid_config() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join("config.yaml"), "port: [not a number").unwrap();
+        let (result, config) = check_config(dir.path());
+        assert_eq!(result.status, Status::Failed);
+        assert!(config.is_none());
+    }
+
+    #[test]
+    fn test_port_in_use() {
+        let listener = std::net::TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let config = Config {
+            port: Some(listener.local_addr().unwrap().port() as u32),
+            ..Default::default()
+        };
+        assert_eq!(check_port(&config).status, Status::Failed);
+    }
+
+    #[test]
+    fn test_redis_without_url() {
+        let config: Config = serde_yaml::from_str(
+            "session:\n  backend: redis\n  secret_key: x\n  cookie_name: s\n  cookie_secure: false\n  cookie_http_only: true\n  cookie_path: /\n",
+        )
+        .unwrap();
+        assert_eq!(check_redis(&config).status, Status::Failed);
+        assert_eq!(check_redis(&Config::default()).status, Status::Skipped);
+    }
+
+    #[test]
+    fn test_python_install() {
+        assert_eq!(check_python_install(&Config::default()).status, Status::Skipped);
+        let config: Config = serde_yaml::from_str("python:\n  executable: /no/such/python\n").unwrap();
+        assert_eq!(check_python_install(&config).status, Status::Failed);
+    }
+
+    #[test]
+    fn test_multi_instance_only_warns_when_declared() {
+        let mut config = Config { reload_pages: Some(true), ..Default::default() };
+        let results = check_multi_instance(&config);
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].status, Status::Ok);
+
+        config.multi_instance = Some(true);
+        let results = check_multi_instance(&config);
+        assert_eq!(results.len(), 2);
+        assert!(results.iter().all(|r| r.status == Status::Warning));
+    }
+}
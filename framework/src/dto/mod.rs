@@ -1,2 +1,3 @@
 pub mod python_request;
-pub mod python_session;
\ No newline at end of file
+pub mod python_session;
+pub mod python_user;
\ No newline at end of file
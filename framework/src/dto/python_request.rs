@@ -1,5 +1,8 @@
-use crate::actors::page_renderer::{FileData, HttpRequestInfo};
-use pyo3::{prelude::*, exceptions::PyNotImplementedError};
+use crate::actors::page_renderer::{now_ms, FileData, HttpRequestInfo};
+use crate::fileupload::ResumableStore;
+use crate::object_storage::{Bucket, BUCKET};
+use pyo3::exceptions::{PyIOError, PyNotImplementedError, PyRuntimeError, PyTimeoutError};
+use pyo3::prelude::*;
 use pyo3::types::PyDict;
 use serde_pyobject::to_pyobject;
 use std::io::Write;
@@ -11,9 +14,20 @@ pub struct PyFileStorage {
     filename: String,
     #[pyo3(get, set)]
     content_type: String,
+    // Sniffed from the file's first bytes, unlike `content_type` which the browser sends.
+    #[pyo3(get)]
+    detected_type: String,
     #[pyo3(get, set)]
     headers: Py<PyDict>,
     data: Arc<FileData>,
+    // Set once `save_to_bucket()` has stored the file for good.
+    saved_key: Option<String>,
+}
+
+fn bucket() -> PyResult<&'static Bucket> {
+    BUCKET
+        .as_ref()
+        .ok_or_else(|| PyRuntimeError::new_err("No upload bucket is configured. Set `upload_storage` in config.yaml."))
 }
 
 #[pymethods]
@@ -22,9 +36,11 @@ impl PyFileStorage {
     fn new(filename: String, content_type: String, headers: Py<PyDict>) -> Self {
         PyFileStorage {
             filename,
+            detected_type: content_type.clone(),
             content_type,
             headers,
             data: Arc::new(FileData::InMemory(Vec::new())),
+            saved_key: None,
         }
     }
 
@@ -37,6 +53,9 @@ impl PyFileStorage {
             FileData::OnDisk(path) => {
                 std::fs::copy(path, destination)?;
             }
+            FileData::Remote(_) => {
+                file.write_all(&self.read()?)?;
+            }
         }
         Ok(())
     }
@@ -45,9 +64,42 @@ impl PyFileStorage {
         match &*self.data {
             FileData::InMemory(bytes) => Ok(bytes.clone()),
             FileData::OnDisk(path) => Ok(std::fs::read(path)?),
+            FileData::Remote(key) => bucket()?.get_blocking(key).map_err(PyIOError::new_err),
         }
     }
 
+    // Keeps the file in the upload bucket under `key` (by default a fresh one) and returns the key.
+    #[pyo3(signature = (key=None))]
+    fn save_to_bucket(&mut self, key: Option<String>) -> PyResult<String> {
+        let bucket = bucket()?;
+        let key = key.unwrap_or_else(|| bucket.key_for(&self.filename));
+        let data = Arc::clone(&self.data);
+        match &*data {
+            FileData::InMemory(bytes) => bucket.put_blocking(&key, bytes.clone().into(), &self.content_type),
+            FileData::OnDisk(path) => bucket.put_blocking(&key, std::fs::File::open(path)?.into(), &self.content_type),
+            // Big uploads are already in the bucket; move them out of `incoming/`.
+            FileData::Remote(incoming) if *incoming != key => bucket.copy_blocking(incoming, &key).map(|()| {
+                if let Err(e) = bucket.delete_blocking(incoming) {
+                    log::warn!("Couldn't delete the incoming upload '{}': {}", incoming, e);
+                }
+                self.data = Arc::new(FileData::Remote(key.clone()));
+            }),
+            FileData::Remote(_) => Ok(()),
+        }
+        .map_err(PyIOError::new_err)?;
+        self.saved_key = Some(key.clone());
+        Ok(key)
+    }
+
+    // A link to the file in the bucket, or None while it only exists on this server.
+    fn url(&self) -> PyResult<Option<String>> {
+        let key = match (&self.saved_key, &*self.data) {
+            (Some(key), _) | (None, FileData::Remote(key)) => key,
+            _ => return Ok(None),
+        };
+        Ok(Some(bucket()?.url(key)))
+    }
+
     fn stream<'a>(&self, py: Python<'a>) -> PyResult<Py<pyo3::types::PyBytes>> {
         let bytes = self.read()?;
         Ok(pyo3::types::PyBytes::new(py, &bytes).into())
@@ -56,10 +108,21 @@ impl PyFileStorage {
 
 impl Drop for PyFileStorage {
     fn drop(&mut self) {
-        if let FileData::OnDisk(path) = &*self.data {
-            if let Err(e) = std::fs::remove_file(path) {
-                log::error!("We couldn't delete a temporary file: {}. This might indicate a permissions issue.", e);
+        match &*self.data {
+            FileData::OnDisk(path) => {
+                if let Err(e) = std::fs::remove_file(path) {
+                    log::error!("We couldn't delete a temporary file: {}. This might indicate a permissions issue.", e);
+                }
+            }
+            // Uploads nobody saved don't stay in the bucket either.
+            FileData::Remote(key) if self.saved_key.as_ref() != Some(key) => {
+                if let Some(bucket) = BUCKET.as_ref()
+                    && let Err(e) = bucket.delete_blocking(key)
+                {
+                    log::error!("We couldn't delete the upload '{}' from the bucket: {}", key, e);
+                }
             }
+            _ => {}
         }
     }
 }
@@ -118,6 +181,14 @@ impl PyRequest {
                 range: None,
                 referrer: None,
                 remote_user: None,
+                user: None,
+                csp_nonce: None,
+                is_signed: false,
+                fragment: None,
+                deadline: None,
+                render_id: 0,
+                trace: None,
+                component_only: false,
             }),
         }
     }
@@ -163,8 +234,10 @@ impl PyRequest {
                 PyFileStorage {
                     filename: value.filename.clone(),
                     content_type: value.content_type.clone(),
+                    detected_type: value.detected_type.clone(),
                     headers: headers_dict.into(),
                     data: Arc::new(value.data.clone()),
+                    saved_key: None,
                 },
             )?;
             dict.set_item(key, file_storage)?;
@@ -172,6 +245,33 @@ impl PyRequest {
         Ok(dict.into())
     }
 
+    // A finished resumable upload, by the id the form submitted. Each upload can be claimed once.
+    fn upload(&self, py: Python, upload_id: &str) -> PyResult<Option<Py<PyFileStorage>>> {
+        let Some((info, path)) = ResumableStore::from_config().claim(upload_id) else {
+            return Ok(None);
+        };
+        let file_storage = Py::new(
+            py,
+            PyFileStorage {
+                filename: info.filename,
+                content_type: info.content_type,
+                detected_type: crate::upload_validation::sniff_file(&path).to_string(),
+                headers: PyDict::new(py).into(),
+                data: Arc::new(FileData::OnDisk(path)),
+                saved_key: None,
+            },
+        )?;
+        Ok(Some(file_storage))
+    }
+
+    // {"received", "size", "complete", "filename"} for an upload still in flight, or None.
+    fn upload_progress(&self, py: Python, upload_id: &str) -> PyResult<Option<Py<PyAny>>> {
+        match ResumableStore::from_config().progress(upload_id) {
+            Ok(progress) => Ok(Some(to_pyobject(py, &progress)?.unbind())),
+            Err(_) => Ok(None),
+        }
+    }
+
     #[getter]
     fn headers(&self, py: Python) -> PyResult<Py<PyDict>> {
         let dict = PyDict::new(py);
@@ -359,6 +459,52 @@ impl PyRequest {
         self.inner.remote_user.clone()
     }
 
+    // The claims (`sub`, `email`, `name`...) of a user logged in with `/auth/{provider}/login`, or None.
+    #[getter]
+    fn user(&self, py: Python) -> PyResult<Option<Py<PyAny>>> {
+        match &self.inner.user {
+            Some(claims) => Ok(Some(to_pyobject(py, claims)?.unbind())),
+            None => Ok(None),
+        }
+    }
+
+    // True when the link came from `url_for_signed()`, unchanged and not yet expired.
+    #[getter]
+    fn is_signed(&self) -> bool {
+        self.inner.is_signed
+    }
+
+    // The `{% fragment %}` this request asked for (an htmx partial), or None for the whole page.
+    #[getter]
+    fn fragment(&self) -> Option<&str> {
+        self.inner.fragment.as_deref()
+    }
+
+    // Milliseconds left before the page render is given up on, or None outside of a page render.
+    #[getter]
+    fn deadline_ms(&self) -> Option<u64> {
+        self.inner.deadline.map(|deadline| deadline.saturating_sub(now_ms()))
+    }
+
+    // Raises TimeoutError once the render has run out of time, so long-running work can stop
+    // instead of producing a result nobody will see.
+    fn check_deadline(&self) -> PyResult<()> {
+        if self.inner.deadline.is_some_and(|deadline| now_ms() >= deadline) {
+            return Err(PyTimeoutError::new_err("The page ran out of time to render, so this work would be thrown away."));
+        }
+        Ok(())
+    }
+
+    // `request.url_for("blog/[slug]", slug=post.slug)` links to a page by its file in pages/.
+    #[pyo3(signature = (target, **params))]
+    fn url_for(&self, target: &str, params: Option<Bound<PyDict>>) -> PyResult<String> {
+        let mut values = std::collections::BTreeMap::new();
+        for (name, value) in params.iter().flat_map(|params| params.iter()) {
+            values.insert(name.str()?.to_string(), value.str()?.to_string());
+        }
+        Ok(crate::reverse_routes::url_for(target, &values))
+    }
+
     #[getter]
     fn charset(&self) -> String {
         self.inner.content_type.as_deref().unwrap_or("").split(';').nth(1).and_then(|s| s.trim().split('=').nth(1)).unwrap_or("").to_string()
@@ -516,4 +662,23 @@ impl PyRequest {
     fn get_json(&self) -> PyResult<()> {
         Err(PyNotImplementedError::new_err("Notice: This attribute is not implemented on purpose. Please find a workaround coding in other way"))
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deadline() {
+        let mut request = PyRequest::new();
+        assert_eq!(request.deadline_ms(), None);
+        assert!(request.check_deadline().is_ok());
+
+        Arc::make_mut(&mut request.inner).deadline = Some(now_ms() + 5_000);
+        assert!(request.deadline_ms().is_some_and(|left| left > 4_000 && left <= 5_000));
+        assert!(request.check_deadline().is_ok());
+
+        Arc::make_mut(&mut request.inner).deadline = Some(now_ms() - 1);
+        assert_eq!(request.deadline_ms(), Some(0));
+        Python::attach(|py| assert!(request.check_deadline().unwrap_err().is_instance_of::<PyTimeoutError>(py)));
+    }
+}
@@ -1,23 +1,65 @@
 use crate::actors::session_manager::{
-    ClearSession, DeleteSessionValue, GetSessionValue, GetStatus, MarkAsModified, SessionManagerActor,
-    SetPermanent, SetSessionValue,
+    ClearSession, DeleteSessionValue, GetSessionEntries, GetSessionValue, GetStatus, MarkAsModified,
+    RegenerateId, SessionManagerActor, SetPermanent, SetSessionValue, UpdateSession,
 };
-use actix::Addr;
+use crate::dto::python_user::PyUser;
+use actix::{Addr, Handler, Message};
 use actix_session::SessionStatus;
-use pyo3::exceptions::{PyAttributeError, PyKeyError};
+use pyo3::exceptions::{PyAttributeError, PyKeyError, PyTypeError};
 use pyo3::prelude::*;
-use serde_json;
+use pyo3::types::{PyDict, PyIterator, PyList};
+use serde_json::Value;
 
 #[pyclass]
-#[derive(Clone)]
 pub struct PySession {
     session_manager: Addr<SessionManagerActor>,
+    // What `auth.user_loader` returned, so it runs at most once per call into Python.
+    current_user: Option<Py<PyAny>>,
 }
 
 impl PySession {
     pub fn new(session_manager: Addr<SessionManagerActor>) -> Self {
-        PySession { session_manager }
+        PySession { session_manager, current_user: None }
     }
+
+    // Sends `msg` to the session with the GIL released, turning failures into KeyErrors.
+    fn ask<M, T>(&self, py: Python, msg: M) -> PyResult<T>
+    where
+        M: Message<Result = Result<T, std::io::Error>> + Send + 'static,
+        T: Send + 'static,
+        SessionManagerActor: Handler<M>,
+    {
+        match py.detach(|| futures::executor::block_on(self.session_manager.send(msg))) {
+            Ok(Ok(value)) => Ok(value),
+            Ok(Err(e)) => Err(PyKeyError::new_err(e.to_string())),
+            Err(e) => Err(PyKeyError::new_err(e.to_string())),
+        }
+    }
+
+    fn value(&self, py: Python, key: &str) -> PyResult<Option<Value>> {
+        self.ask(py, GetSessionValue { key: key.to_string() })
+    }
+
+    fn entries(&self, py: Python) -> PyResult<Vec<(String, Value)>> {
+        self.ask(py, GetSessionEntries)
+    }
+
+    fn login_id(&self, py: Python) -> PyResult<Option<String>> {
+        Ok(self.value(py, crate::auth::LOGIN_KEY)?.and_then(|login| login.as_str().map(str::to_string)))
+    }
+}
+
+fn to_python(py: Python, value: &Value) -> PyResult<Py<PyAny>> {
+    pythonize::pythonize(py, value)
+        .map(Bound::unbind)
+        .map_err(|e| PyKeyError::new_err(e.to_string()))
+}
+
+// Session values are stored as JSON, so only what JSON can hold is accepted.
+fn from_python(value: &Bound<PyAny>) -> PyResult<Value> {
+    pythonize::depythonize(value).map_err(|e| {
+        PyTypeError::new_err(format!("Session values must be JSON-serializable (dicts, lists, strings, numbers, booleans or None): {}", e))
+    })
 }
 
 #[pymethods]
@@ -74,156 +116,167 @@ impl PySession {
     }
 
     fn __getitem__(&self, py: Python, key: &str) -> PyResult<Py<PyAny>> {
-        let msg = GetSessionValue {
-            key: key.to_string(),
-        };
-
-        match futures::executor::block_on(self.session_manager.send(msg)) {
-            Ok(Ok(Some(value))) => {
-                let deserialized: serde_json::Value = serde_json::from_str(&value)
-                    .map_err(|e| PyKeyError::new_err(e.to_string()))?;
-                let py_obj = pythonize::pythonize(py, &deserialized)
-                    .map_err(|e| PyKeyError::new_err(e.to_string()))?;
-                Ok(py_obj.into())
-            }
-            Ok(Ok(None)) => Err(PyKeyError::new_err(key.to_string())),
-            Ok(Err(e)) => Err(PyKeyError::new_err(e.to_string())),
-            Err(e) => Err(PyKeyError::new_err(e.to_string())),
+        match self.value(py, key)? {
+            Some(value) => to_python(py, &value),
+            None => Err(PyKeyError::new_err(key.to_string())),
         }
     }
 
-fn __setitem__(&mut self, py: Python, key: &str, value: Py<PyAny>) -> PyResult<()> {
-    // In newer PyO3, bind() is used to get a Bound reference
-    let bound_value = value.bind(py);
+    fn __setitem__(&mut self, py: Python, key: &str, value: Bound<PyAny>) -> PyResult<()> {
+        let value = from_python(&value)?;
+        self.ask(py, SetSessionValue { key: key.to_string(), value })
+    }
 
-    let serialized_value: serde_json::Value = pythonize::depythonize(bound_value)
-        .map_err(|e| PyKeyError::new_err(e.to_string()))?;
-    let json_value = serde_json::to_string(&serialized_value)
-        .map_err(|e| PyKeyError::new_err(e.to_string()))?;
+    fn __delitem__(&mut self, py: Python, key: &str) -> PyResult<()> {
+        self.ask(py, DeleteSessionValue { key: key.to_string() })
+    }
 
-    let msg = SetSessionValue {
-        key: key.to_string(),
-        value: json_value,
-    };
+    fn __contains__(&self, py: Python, key: &str) -> PyResult<bool> {
+        Ok(self.value(py, key).ok().flatten().is_some())
+    }
 
-    // Release the GIL before blocking
-    let result = py.detach(|| {
-        futures::executor::block_on(self.session_manager.send(msg))
-    });
+    fn __len__(&self, py: Python) -> PyResult<usize> {
+        Ok(self.entries(py)?.len())
+    }
 
-    match result {
-        Ok(Ok(_)) => Ok(()),
-        Ok(Err(e)) => Err(PyKeyError::new_err(e.to_string())),
-        Err(e) => Err(PyKeyError::new_err(e.to_string())),
+    fn __iter__(&self, py: Python) -> PyResult<Py<PyIterator>> {
+        let keys = PyList::new(py, self.entries(py)?.into_iter().map(|(key, _)| key))?;
+        Ok(keys.as_any().try_iter()?.unbind())
     }
-}
 
-    fn __delitem__(&mut self, key: &str) -> PyResult<()> {
-        let msg = DeleteSessionValue {
-            key: key.to_string(),
-        };
+    fn keys(&self, py: Python) -> PyResult<Vec<String>> {
+        Ok(self.entries(py)?.into_iter().map(|(key, _)| key).collect())
+    }
 
-        match futures::executor::block_on(self.session_manager.send(msg)) {
-            Ok(Ok(_)) => Ok(()),
-            Ok(Err(e)) => Err(PyKeyError::new_err(e.to_string())),
-            Err(e) => Err(PyKeyError::new_err(e.to_string())),
+    fn values(&self, py: Python) -> PyResult<Vec<Py<PyAny>>> {
+        self.entries(py)?.iter().map(|(_, value)| to_python(py, value)).collect()
+    }
+
+    fn items(&self, py: Python) -> PyResult<Vec<(String, Py<PyAny>)>> {
+        self.entries(py)?
+            .into_iter()
+            .map(|(key, value)| Ok((key, to_python(py, &value)?)))
+            .collect()
+    }
+
+    // `session.update({"user_id": 1}, theme="dark")`, in one round trip.
+    #[pyo3(signature = (values = None, **kwargs))]
+    fn update(&mut self, py: Python, values: Option<Bound<PyAny>>, kwargs: Option<Bound<PyDict>>) -> PyResult<()> {
+        let mut map = serde_json::Map::new();
+        for source in values.into_iter().chain(kwargs.map(Bound::into_any)) {
+            match from_python(&source)? {
+                Value::Object(object) => map.extend(object),
+                _ => return Err(PyTypeError::new_err("session.update() takes a dict.")),
+            }
         }
+        if map.is_empty() {
+            return Ok(());
+        }
+        self.ask(py, UpdateSession { values: map })
     }
-    fn __contains__(&self, key: &str) -> PyResult<bool> {
-        let msg = GetSessionValue {
-            key: key.to_string(),
-        };
 
-        match futures::executor::block_on(self.session_manager.send(msg)) {
-            Ok(Ok(Some(_))) => Ok(true),
-            Ok(Ok(None)) => Ok(false),
-            Ok(Err(_)) => Ok(false),
-            Err(_) => Ok(false),
+    // Call after a login or privilege change: the data stays, the session id changes.
+    // Setting a `session.login_keys` key (`user_id` by default) already does this.
+    fn regenerate_id(&mut self, py: Python) -> PyResult<()> {
+        self.ask(py, RegenerateId)
+    }
+
+    fn regenerate(&mut self, py: Python) -> PyResult<()> {
+        self.regenerate_id(py)
+    }
+
+    // Logs `user_id` in: it's stored under `auth.user_key` and the session gets a new id.
+    // `roles` are what `{# requires: role=... #}` pages and `current_user.has_permission()` check.
+    #[pyo3(signature = (user_id, roles=None))]
+    fn login_user(&mut self, py: Python, user_id: Bound<PyAny>, roles: Option<Vec<String>>) -> PyResult<()> {
+        let previous = self.login_id(py)?;
+        let mut values = serde_json::Map::new();
+        values.insert(crate::auth::user_key().to_string(), from_python(&user_id)?);
+        values.insert(crate::auth::ROLES_KEY.to_string(), Value::from(roles.unwrap_or_default()));
+        values.insert(crate::auth::LOGIN_KEY.to_string(), Value::from(crate::user_channels::new_login()));
+        self.ask(py, UpdateSession { values })?;
+        // Whoever was logged in on this session before doesn't get their messages here anymore.
+        if let Some(previous) = previous {
+            crate::user_channels::logged_out(previous);
         }
+        self.current_user = None;
+        self.regenerate_id(py)
     }
 
-    fn clear(&mut self) -> PyResult<()> {
-        let msg = ClearSession;
+    // Forgets everything in the session, not just the user, and gives it a new id.
+    // The user's WebSockets opened with this session are closed.
+    fn logout_user(&mut self, py: Python) -> PyResult<()> {
+        let login = self.login_id(py)?;
+        self.ask(py, ClearSession)?;
+        if let Some(login) = login {
+            crate::user_channels::logged_out(login);
+        }
+        self.current_user = None;
+        self.regenerate_id(py)
+    }
 
-        match futures::executor::block_on(self.session_manager.send(msg)) {
-            Ok(Ok(_)) => Ok(()),
-            Ok(Err(e)) => Err(PyKeyError::new_err(e.to_string())),
-            Err(e) => Err(PyKeyError::new_err(e.to_string())),
+    // The logged-in user's id, or None.
+    #[getter]
+    fn user_id(&self, py: Python) -> PyResult<Py<PyAny>> {
+        match self.value(py, crate::auth::user_key())? {
+            Some(id) => to_python(py, &id),
+            None => Ok(py.None()),
         }
     }
 
+    // The logged-in user, with what `auth.user_loader` returns for them; None when nobody is.
+    #[getter]
+    fn current_user(&mut self, py: Python) -> PyResult<Py<PyAny>> {
+        if let Some(user) = &self.current_user {
+            return Ok(user.clone_ref(py));
+        }
+        let id = self.user_id(py)?;
+        if id.is_none(py) {
+            return Ok(id);
+        }
+        let roles = self
+            .value(py, crate::auth::ROLES_KEY)?
+            .and_then(|roles| serde_json::from_value(roles).ok())
+            .unwrap_or_default();
+        let record = crate::auth::load_user(py, id.bind(py))?;
+        let user = Py::new(py, PyUser::new(id, roles, record))?.into_any();
+        self.current_user = Some(user.clone_ref(py));
+        Ok(user)
+    }
+
+    fn clear(&mut self, py: Python) -> PyResult<()> {
+        self.ask(py, ClearSession)
+    }
+
     #[pyo3(signature = (key, default = None))]
     fn get(&self, py: Python, key: &str, default: Option<Py<PyAny>>) -> PyResult<Py<PyAny>> {
-        let msg = GetSessionValue {
-            key: key.to_string(),
-        };
-
-        match futures::executor::block_on(self.session_manager.send(msg)) {
-            Ok(Ok(Some(value))) => {
-                let deserialized: serde_json::Value = serde_json::from_str(&value)
-                    .map_err(|e| PyKeyError::new_err(e.to_string()))?;
-                let py_obj = pythonize::pythonize(py, &deserialized)
-                    .map_err(|e| PyKeyError::new_err(e.to_string()))?;
-                Ok(py_obj.into())
-            }
-            Ok(Ok(None)) => Ok(default.unwrap_or_else(|| py.None())),
-            Ok(Err(e)) => Err(PyKeyError::new_err(e.to_string())),
-            Err(e) => Err(PyKeyError::new_err(e.to_string())),
+        match self.value(py, key)? {
+            Some(value) => to_python(py, &value),
+            None => Ok(default.unwrap_or_else(|| py.None())),
         }
     }
 
     #[pyo3(signature = (key, default = None))]
     fn pop(&mut self, py: Python, key: &str, default: Option<Py<PyAny>>) -> PyResult<Py<PyAny>> {
-        let get_msg = GetSessionValue {
-            key: key.to_string(),
-        };
-
-        let value = match futures::executor::block_on(self.session_manager.send(get_msg)) {
-            Ok(Ok(value)) => value,
-            Ok(Err(e)) => return Err(PyKeyError::new_err(e.to_string())),
-            Err(e) => return Err(PyKeyError::new_err(e.to_string())),
-        };
-
-        if let Some(val_str) = value {
-            let del_msg = DeleteSessionValue {
-                key: key.to_string(),
-            };
-            match futures::executor::block_on(self.session_manager.send(del_msg)) {
-                Ok(Ok(_)) => {
-                    let deserialized: serde_json::Value = serde_json::from_str(&val_str)
-                        .map_err(|e| PyKeyError::new_err(e.to_string()))?;
-                    let py_obj = pythonize::pythonize(py, &deserialized)
-                        .map_err(|e| PyKeyError::new_err(e.to_string()))?;
-                    return Ok(py_obj.into());
-                }
-                Ok(Err(e)) => return Err(PyKeyError::new_err(e.to_string())),
-                Err(e) => return Err(PyKeyError::new_err(e.to_string())),
+        match self.value(py, key)? {
+            Some(value) => {
+                self.ask(py, DeleteSessionValue { key: key.to_string() })?;
+                to_python(py, &value)
             }
+            None => Ok(default.unwrap_or_else(|| py.None())),
         }
-
-        Ok(default.unwrap_or_else(|| py.None()))
     }
 
-    fn setdefault(&mut self, key: &str, default: &str) -> PyResult<String> {
-        let get_msg = GetSessionValue {
-            key: key.to_string(),
-        };
-
-        match futures::executor::block_on(self.session_manager.send(get_msg)) {
-            Ok(Ok(Some(value))) => Ok(value),
-            Ok(Ok(None)) => {
-                let set_msg = SetSessionValue {
-                    key: key.to_string(),
-                    value: default.to_string(),
-                };
-                match futures::executor::block_on(self.session_manager.send(set_msg)) {
-                    Ok(Ok(_)) => Ok(default.to_string()),
-                    Ok(Err(e)) => Err(PyKeyError::new_err(e.to_string())),
-                    Err(e) => Err(PyKeyError::new_err(e.to_string())),
-                }
-            }
-            Ok(Err(e)) => Err(PyKeyError::new_err(e.to_string())),
-            Err(e) => Err(PyKeyError::new_err(e.to_string())),
+    #[pyo3(signature = (key, default = None))]
+    fn setdefault(&mut self, py: Python, key: &str, default: Option<Bound<PyAny>>) -> PyResult<Py<PyAny>> {
+        if let Some(value) = self.value(py, key)? {
+            return to_python(py, &value);
         }
+        let value = match &default {
+            Some(default) => from_python(default)?,
+            None => Value::Null,
+        };
+        self.ask(py, SetSessionValue { key: key.to_string(), value: value.clone() })?;
+        to_python(py, &value)
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,44 @@
+use pyo3::prelude::*;
+
+// `session.current_user`: the logged-in user's id and roles, in front of whatever `auth.user_loader`
+// returned for them. Attributes and keys it doesn't have itself come from that record.
+#[pyclass]
+pub struct PyUser {
+    #[pyo3(get)]
+    id: Py<PyAny>,
+    #[pyo3(get)]
+    roles: Vec<String>,
+    // What `auth.user_loader` returned; None without a loader.
+    #[pyo3(get)]
+    record: Py<PyAny>,
+}
+
+impl PyUser {
+    pub fn new(id: Py<PyAny>, roles: Vec<String>, record: Py<PyAny>) -> Self {
+        PyUser { id, roles, record }
+    }
+}
+
+#[pymethods]
+impl PyUser {
+    fn has_role(&self, role: &str) -> bool {
+        self.roles.iter().any(|own| own == role)
+    }
+
+    // Through any of the user's roles, as listed under `auth.roles` in config.yaml.
+    fn has_permission(&self, permission: &str) -> bool {
+        crate::auth::has_permission(&self.roles, permission)
+    }
+
+    fn __getattr__(&self, py: Python, name: &str) -> PyResult<Py<PyAny>> {
+        Ok(self.record.bind(py).getattr(name)?.unbind())
+    }
+
+    fn __getitem__(&self, py: Python, key: Bound<PyAny>) -> PyResult<Py<PyAny>> {
+        Ok(self.record.bind(py).get_item(key)?.unbind())
+    }
+
+    fn __repr__(&self, py: Python) -> PyResult<String> {
+        Ok(format!("<User {} roles={:?}>", self.id.bind(py).repr()?, self.roles))
+    }
+}
@@ -0,0 +1,389 @@
+use crate::config;
+use crate::errors::{DetailedError, ErrorSource};
+use once_cell::sync::OnceCell;
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::time::{Duration, Instant};
+
+// Python and template errors, sent to an error tracker with the code around them, the page and
+// component they came from, and the release. Sentry is built in (`error_reporting.sentry_dsn`);
+// other trackers implement `ErrorReporter`.
+//
+// Errors go out from a thread of their own, and only so many a minute: a page that fails on every
+// request shouldn't turn into a request to Sentry for each of them.
+
+const SEND_TIMEOUT: Duration = Duration::from_secs(5);
+// Errors waiting to be sent; more than that and they're dropped.
+const QUEUE_SIZE: usize = 100;
+const WINDOW: Duration = Duration::from_secs(60);
+const DEFAULT_MAX_PER_MINUTE: u32 = 30;
+// Lines of code around the failing one, as Sentry shows them.
+const CONTEXT_LINES: usize = 5;
+
+pub trait ErrorReporter: Send {
+    // e.g. `Sentry`, for the logs.
+    fn name(&self) -> &str;
+    fn report(&self, error: &DetailedError) -> Result<(), String>;
+}
+
+struct Reporting {
+    sender: SyncSender<DetailedError>,
+    limiter: Mutex<Limiter>,
+}
+
+static REPORTING: OnceCell<Reporting> = OnceCell::new();
+
+// Starts sending errors to whichever trackers are set up. Not with `noventa dev`, where errors are on
+// the page already.
+pub fn start(dev_mode: bool) {
+    if dev_mode {
+        return;
+    }
+    let mut reporters: Vec<Box<dyn ErrorReporter>> = Vec::new();
+    if let Some(sentry) = Sentry::from_config() {
+        reporters.push(Box::new(sentry));
+    }
+    start_with(reporters);
+}
+
+pub fn start_with(reporters: Vec<Box<dyn ErrorReporter>>) {
+    if reporters.is_empty() {
+        return;
+    }
+    let max_per_minute = config::CONFIG
+        .error_reporting
+        .as_ref()
+        .and_then(|error_reporting| error_reporting.max_per_minute)
+        .unwrap_or(DEFAULT_MAX_PER_MINUTE);
+    let (sender, receiver) = mpsc::sync_channel(QUEUE_SIZE);
+    let names: Vec<&str> = reporters.iter().map(|reporter| reporter.name()).collect();
+    log::info!("Errors will be reported to {}.", names.join(" and "));
+    if REPORTING.set(Reporting { sender, limiter: Mutex::new(Limiter::new(max_per_minute)) }).is_ok() {
+        std::thread::spawn(move || run_reporters(reporters, receiver));
+    }
+}
+
+// Called for every error a visitor runs into, by `errors::record_error`.
+pub fn report(error: &DetailedError) {
+    let Some(reporting) = REPORTING.get() else {
+        return;
+    };
+    let allowed = reporting.limiter.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).allow(&fingerprint(error), Instant::now());
+    if !allowed {
+        return;
+    }
+    if let Err(TrySendError::Full(_)) = reporting.sender.try_send(error.clone()) {
+        log::debug!("Too many errors waiting to be reported; this one was dropped.");
+    }
+}
+
+fn run_reporters(reporters: Vec<Box<dyn ErrorReporter>>, receiver: Receiver<DetailedError>) {
+    // Warned about once, not for every error while the tracker is down.
+    let mut failing = vec![false; reporters.len()];
+    for error in receiver {
+        for (reporter, failing) in reporters.iter().zip(failing.iter_mut()) {
+            match reporter.report(&error) {
+                Ok(()) => *failing = false,
+                Err(e) => {
+                    if !std::mem::replace(failing, true) {
+                        log::warn!("Heads up! An error couldn't be reported to {} ({}), so it was dropped.", reporter.name(), e);
+                    }
+                }
+            }
+        }
+    }
+}
+
+// The same error is the same message from the same place.
+fn fingerprint(error: &DetailedError) -> String {
+    format!("{}:{}:{}", error.file_path, error.line, error.message)
+}
+
+// At most `max_per_minute` errors a minute, and each distinct error once a minute.
+struct Limiter {
+    max_per_minute: u32,
+    window_started: Option<Instant>,
+    sent: u32,
+    last_sent: HashMap<String, Instant>,
+}
+
+impl Limiter {
+    fn new(max_per_minute: u32) -> Self {
+        Limiter { max_per_minute, window_started: None, sent: 0, last_sent: HashMap::new() }
+    }
+
+    fn allow(&mut self, fingerprint: &str, now: Instant) -> bool {
+        if self.window_started.is_none_or(|started| now.duration_since(started) >= WINDOW) {
+            self.window_started = Some(now);
+            self.sent = 0;
+            self.last_sent.retain(|_, sent| now.duration_since(*sent) < WINDOW);
+        }
+        if self.sent >= self.max_per_minute {
+            return false;
+        }
+        if let Some(sent) = self.last_sent.get(fingerprint)
+            && now.duration_since(*sent) < WINDOW
+        {
+            return false;
+        }
+        self.sent += 1;
+        self.last_sent.insert(fingerprint.to_string(), now);
+        true
+    }
+}
+
+// Where a DSN like `https://<key>@o123.ingest.sentry.io/456` sends events, and its key.
+pub fn parse_dsn(dsn: &str) -> Option<(String, String)> {
+    let url = reqwest::Url::parse(dsn.trim()).ok()?;
+    if !matches!(url.scheme(), "http" | "https") || url.username().is_empty() {
+        return None;
+    }
+    let path = url.path().trim_end_matches('/');
+    let (prefix, project) = path.rsplit_once('/')?;
+    if project.is_empty() {
+        return None;
+    }
+    let port = url.port().map(|port| format!(":{}", port)).unwrap_or_default();
+    let endpoint = format!("{}://{}{}{}/api/{}/envelope/", url.scheme(), url.host_str()?, port, prefix, project);
+    Some((endpoint, url.username().to_string()))
+}
+
+pub struct Sentry {
+    dsn: String,
+    endpoint: String,
+    key: String,
+    environment: Option<String>,
+    release: Option<String>,
+    // Made on the reporting thread: a blocking client can't be made inside the server's runtime.
+    client: OnceCell<reqwest::blocking::Client>,
+}
+
+impl Sentry {
+    fn from_config() -> Option<Self> {
+        let error_reporting = config::CONFIG.error_reporting.clone().unwrap_or_default();
+        let setting = |value: Option<String>, variable: &str| value.or_else(|| std::env::var(variable).ok()).filter(|value| !value.trim().is_empty());
+        let dsn = setting(error_reporting.sentry_dsn, "SENTRY_DSN")?;
+        let Some((endpoint, key)) = parse_dsn(&dsn) else {
+            log::warn!("Heads up! SENTRY_DSN isn't a DSN Sentry would give out, so errors won't be reported.");
+            return None;
+        };
+        Some(Sentry {
+            dsn,
+            endpoint,
+            key,
+            environment: setting(error_reporting.environment, "SENTRY_ENVIRONMENT"),
+            release: setting(error_reporting.release, "SENTRY_RELEASE"),
+            client: OnceCell::new(),
+        })
+    }
+
+    fn event(&self, error: &DetailedError, event_id: &str) -> serde_json::Value {
+        sentry_event(error, event_id, self.environment.as_deref(), self.release.as_deref())
+    }
+}
+
+impl ErrorReporter for Sentry {
+    fn name(&self) -> &str {
+        "Sentry"
+    }
+
+    fn report(&self, error: &DetailedError) -> Result<(), String> {
+        let event_id = uuid::Uuid::new_v4().simple().to_string();
+        // An envelope: its header, the item's header and the event, a line each.
+        let body = format!(
+            "{}\n{}\n{}\n",
+            json!({ "event_id": event_id, "dsn": self.dsn }),
+            json!({ "type": "event" }),
+            self.event(error, &event_id),
+        );
+        self.client
+            .get_or_init(|| reqwest::blocking::Client::builder().timeout(SEND_TIMEOUT).build().unwrap_or_default())
+            .post(&self.endpoint)
+            .header(reqwest::header::CONTENT_TYPE, "application/x-sentry-envelope")
+            .header(
+                "X-Sentry-Auth",
+                format!("Sentry sentry_version=7, sentry_client=noventa/{}, sentry_key={}", env!("CARGO_PKG_VERSION"), self.key),
+            )
+            .body(body)
+            .send()
+            .and_then(|response| response.error_for_status())
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+}
+
+// `ValueError: price can't be negative` as an exception type and value.
+fn exception_type(message: &str) -> Option<(&str, &str)> {
+    let (kind, value) = message.split_once(": ")?;
+    let is_name = !kind.is_empty() && kind.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.');
+    is_name.then_some((kind, value))
+}
+
+// A stack frame with the lines around `line`, from a snippet of the file that starts
+// `CONTEXT_LINES + 1` lines before it, as `PythonError` and `TemplateInfo` keep them.
+fn frame(filename: &str, line: usize, column: Option<usize>, snippet: Option<&str>) -> serde_json::Value {
+    let mut frame = json!({ "filename": filename, "abs_path": filename, "lineno": line, "in_app": true });
+    if let Some(column) = column {
+        frame["colno"] = json!(column);
+    }
+    if let Some(snippet) = snippet
+        && line > 0
+    {
+        let first = line.saturating_sub(CONTEXT_LINES + 1).max(1);
+        let lines: Vec<&str> = snippet.lines().collect();
+        if let Some(context_line) = lines.get(line - first) {
+            let before = &lines[(line - first).saturating_sub(CONTEXT_LINES)..line - first];
+            let after = &lines[(line - first + 1)..lines.len().min(line - first + 1 + CONTEXT_LINES)];
+            frame["context_line"] = json!(context_line);
+            frame["pre_context"] = json!(before);
+            frame["post_context"] = json!(after);
+        }
+    }
+    frame
+}
+
+// A Sentry event for an error, with the code where it happened.
+fn sentry_event(error: &DetailedError, event_id: &str, environment: Option<&str>, release: Option<&str>) -> serde_json::Value {
+    let (source, kind, value, frame, traceback) = match &error.error_source {
+        Some(ErrorSource::Python(python)) => {
+            let (kind, value) = exception_type(&python.message).unwrap_or(("PythonError", &python.message));
+            let filename = python.filename.as_deref().unwrap_or(&error.file_path);
+            let line = python.line_number.unwrap_or(error.line as usize);
+            let frame = frame(filename, line, python.column_number, python.source_code.as_deref());
+            ("python", kind, value, frame, Some(python.traceback.clone()))
+        }
+        Some(ErrorSource::Template(template)) => {
+            let frame = frame(&template.name, template.line, None, template.source_code.as_deref());
+            let value = if error.message.is_empty() { template.detail.as_str() } else { error.message.as_str() };
+            ("template", "TemplateError", value, frame, template.traceback.clone())
+        }
+        None => ("noventa", "Error", error.message.as_str(), frame(&error.file_path, error.line as usize, None, None), None),
+    };
+    let mut tags = json!({ "source": source });
+    if let Some(route) = &error.route {
+        tags["route"] = json!(route);
+    }
+    if let Some(component) = &error.component {
+        tags["component"] = json!(component.name);
+    }
+    if let Some(page) = &error.page {
+        tags["page"] = json!(page.name);
+    }
+    let mut event = json!({
+        "event_id": event_id,
+        "timestamp": chrono::Utc::now().to_rfc3339(),
+        "platform": if source == "python" { "python" } else { "other" },
+        "level": "error",
+        "logger": "noventa",
+        "server_name": std::env::var("HOSTNAME").unwrap_or_default(),
+        "tags": tags,
+        "exception": { "values": [{ "type": kind, "value": value, "stacktrace": { "frames": [frame] } }] },
+        "extra": { "traceback": traceback },
+    });
+    if let Some(route) = &error.route {
+        event["transaction"] = json!(route);
+    }
+    if let Some(environment) = environment {
+        event["environment"] = json!(environment);
+    }
+    if let Some(release) = release {
+        event["release"] = json!(release);
+    }
+    event
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::actors::interpreter::PythonError;
+    use crate::errors::ComponentInfo;
+
+    #[test]
+    fn test_parse_dsn() {
+        assert_eq!(
+            parse_dsn("https://abc123@o42.ingest.sentry.io/456"),
+            Some(("https://o42.ingest.sentry.io/api/456/envelope/".to_string(), "abc123".to_string()))
+        );
+        assert_eq!(
+            parse_dsn("http://key@sentry.internal:9000/errors/7"),
+            Some(("http://sentry.internal:9000/errors/api/7/envelope/".to_string(), "key".to_string()))
+        );
+        assert_eq!(parse_dsn("https://o42.ingest.sentry.io/456"), None);
+        assert_eq!(parse_dsn("https://key@o42.ingest.sentry.io/"), None);
+        assert_eq!(parse_dsn("not a dsn"), None);
+    }
+
+    #[test]
+    fn test_sentry_event() {
+        let source: Vec<String> = (5..=15).map(|line| format!("line {}", line)).collect();
+        let error = DetailedError {
+            message: "ValueError: price can't be negative".to_string(),
+            file_path: "components/cart/cart_logic.py".to_string(),
+            line: 11,
+            route: Some("pages/cart.html".to_string()),
+            component: Some(ComponentInfo { name: "cart".to_string() }),
+            error_source: Some(ErrorSource::Python(PythonError {
+                message: "ValueError: price can't be negative".to_string(),
+                traceback: "Traceback (most recent call last): ...".to_string(),
+                line_number: Some(11),
+                column_number: Some(4),
+                filename: Some("/app/components/cart/cart_logic.py".to_string()),
+                source_code: Some(source.join("\n")),
+                ..Default::default()
+            })),
+            ..Default::default()
+        };
+        let event = sentry_event(&error, "0123", Some("production"), Some("v1.2.3"));
+        assert_eq!(event["release"], "v1.2.3");
+        assert_eq!(event["environment"], "production");
+        assert_eq!(event["tags"]["route"], "pages/cart.html");
+        assert_eq!(event["tags"]["component"], "cart");
+        assert_eq!(event["tags"]["source"], "python");
+        let exception = &event["exception"]["values"][0];
+        assert_eq!(exception["type"], "ValueError");
+        assert_eq!(exception["value"], "price can't be negative");
+        let frame = &exception["stacktrace"]["frames"][0];
+        assert_eq!(frame["lineno"], 11);
+        assert_eq!(frame["context_line"], "line 11");
+        assert_eq!(frame["pre_context"], json!(["line 6", "line 7", "line 8", "line 9", "line 10"]));
+        assert_eq!(frame["post_context"], json!(["line 12", "line 13", "line 14", "line 15"]));
+
+        let error = DetailedError {
+            file_path: "pages/index.html".to_string(),
+            line: 2,
+            error_source: Some(ErrorSource::Template(crate::errors::TemplateInfo {
+                name: "pages/index.html".to_string(),
+                line: 2,
+                source_code: Some("<h1>\n{{ missing.name }}\n</h1>".to_string()),
+                detail: "undefined value".to_string(),
+                ..Default::default()
+            })),
+            ..Default::default()
+        };
+        let event = sentry_event(&error, "0123", None, None);
+        assert_eq!(event["tags"]["source"], "template");
+        assert_eq!(event["exception"]["values"][0]["value"], "undefined value");
+        assert!(event.get("release").is_none());
+        let frame = &event["exception"]["values"][0]["stacktrace"]["frames"][0];
+        assert_eq!(frame["context_line"], "{{ missing.name }}");
+        assert_eq!(frame["pre_context"], json!(["<h1>"]));
+    }
+
+    #[test]
+    fn test_limiter() {
+        let mut limiter = Limiter::new(3);
+        let now = Instant::now();
+        assert!(limiter.allow("a", now));
+        // The same error again within the minute.
+        assert!(!limiter.allow("a", now + Duration::from_secs(1)));
+        assert!(limiter.allow("b", now));
+        assert!(limiter.allow("c", now));
+        assert!(!limiter.allow("d", now + Duration::from_secs(59)));
+
+        let later = now + WINDOW;
+        assert!(limiter.allow("a", later));
+        assert!(limiter.allow("d", later));
+    }
+}
@@ -1,10 +1,88 @@
 use crate::actors::interpreter::PythonError;
 use lazy_static::lazy_static;
 use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::Mutex;
 use tokio::sync::broadcast;
 
+// How many errors the admin dashboard can look back on.
+const RECENT_ERRORS_LIMIT: usize = 50;
+// How many errors `/_noventa/errors` keeps, code and tracebacks included.
+const ERROR_HISTORY_LIMIT: usize = 100;
+
 lazy_static! {
     pub static ref ERROR_CHANNEL: broadcast::Sender<String> = broadcast::channel(100).0;
+    static ref RECENT_ERRORS: Mutex<VecDeque<RecentError>> = Mutex::new(VecDeque::with_capacity(RECENT_ERRORS_LIMIT));
+    static ref ERROR_HISTORY: Mutex<VecDeque<PastError>> = Mutex::new(VecDeque::with_capacity(ERROR_HISTORY_LIMIT));
+}
+
+// A page error as shown in `/_noventa/admin`, without the source code and tracebacks.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct RecentError {
+    pub time: String,
+    pub route: Option<String>,
+    pub message: String,
+    pub file_path: String,
+    pub line: u32,
+}
+
+pub fn record_error(error: &DetailedError) {
+    crate::error_reporting::report(error);
+    let mut recent = RECENT_ERRORS.lock().unwrap();
+    if recent.len() == RECENT_ERRORS_LIMIT {
+        recent.pop_back();
+    }
+    recent.push_front(RecentError {
+        time: chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+        route: error.route.clone(),
+        message: error.message.clone(),
+        file_path: error.file_path.clone(),
+        line: error.line,
+    });
+}
+
+// Newest first.
+pub fn recent_errors() -> Vec<RecentError> {
+    RECENT_ERRORS.lock().unwrap().iter().cloned().collect()
+}
+
+// An error `noventa dev` showed, kept for `/_noventa/errors` whether or not an editor or browser
+// was listening on ERROR_CHANNEL at the time.
+#[derive(Debug, Serialize, Clone)]
+pub struct PastError {
+    pub time: String,
+    // The same error again straight after is counted instead of kept twice, e.g. while a reload
+    // keeps hitting it.
+    pub count: u32,
+    pub error: DetailedError,
+}
+
+pub fn remember_error(error: &DetailedError) {
+    let time = chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
+    remember_in(&mut ERROR_HISTORY.lock().unwrap(), error, time);
+}
+
+fn remember_in(history: &mut VecDeque<PastError>, error: &DetailedError, time: String) {
+    if let Some(newest) = history.front_mut()
+        && newest.error.message == error.message
+        && newest.error.file_path == error.file_path
+        && newest.error.line == error.line
+        && newest.error.route == error.route
+    {
+        newest.time = time;
+        newest.count += 1;
+        newest.error = error.clone();
+        return;
+    }
+    if history.len() == ERROR_HISTORY_LIMIT {
+        history.pop_back();
+    }
+    history.push_front(PastError { time, count: 1, error: error.clone() });
+}
+
+// Newest first.
+pub fn error_history() -> Vec<PastError> {
+    ERROR_HISTORY.lock().unwrap().iter().cloned().collect()
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -96,6 +174,24 @@ mod tests {
         assert!(json.contains("test.rs"));
     }
 
+    #[test]
+    fn test_remember_error() {
+        let error = |message: &str| DetailedError { message: message.to_string(), file_path: "pages/index.html".to_string(), ..Default::default() };
+        let mut history = VecDeque::new();
+        remember_in(&mut history, &error("first"), "10:00".to_string());
+        remember_in(&mut history, &error("second"), "10:01".to_string());
+        remember_in(&mut history, &error("second"), "10:02".to_string());
+        assert_eq!(history.len(), 2);
+        assert_eq!((history[0].error.message.as_str(), history[0].count, history[0].time.as_str()), ("second", 2, "10:02"));
+        assert_eq!((history[1].error.message.as_str(), history[1].count), ("first", 1));
+
+        for i in 0..ERROR_HISTORY_LIMIT {
+            remember_in(&mut history, &error(&i.to_string()), "10:03".to_string());
+        }
+        assert_eq!(history.len(), ERROR_HISTORY_LIMIT);
+        assert_eq!(history.back().unwrap().error.message, "0");
+    }
+
     #[test]
     fn test_detailed_error_default() {
         let error = DetailedError::default();
@@ -157,6 +253,21 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_recent_errors_keeps_the_newest() {
+        for i in 0..RECENT_ERRORS_LIMIT + 5 {
+            record_error(&DetailedError {
+                message: format!("Error {}", i),
+                route: Some("/broken".to_string()),
+                ..Default::default()
+            });
+        }
+        let recent = recent_errors();
+        assert_eq!(recent.len(), RECENT_ERRORS_LIMIT);
+        assert_eq!(recent[0].message, format!("Error {}", RECENT_ERRORS_LIMIT + 4));
+        assert_eq!(recent[0].route.as_deref(), Some("/broken"));
+    }
+
     #[test]
     fn test_component_info_default() {
         let info = ComponentInfo::default();
@@ -0,0 +1,74 @@
+use pyo3::exceptions::{PyTypeError, PyValueError};
+use pyo3::prelude::*;
+use serde::Serialize;
+use std::cell::RefCell;
+
+// `from noventa_events import emit` in an action: `emit("cart_updated", {"count": 3})` renders the page
+// again with every component's `on_event_cart_updated(request, payload, **props)` adding to its context.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Event {
+    pub name: String,
+    pub payload: serde_json::Value,
+}
+
+impl Event {
+    // The function a component subscribes to it with.
+    pub fn hook(&self) -> String {
+        format!("on_event_{}", self.name)
+    }
+}
+
+thread_local! {
+    // What the Python function running on this interpreter thread emitted so far.
+    static EMITTED: RefCell<Vec<Event>> = const { RefCell::new(Vec::new()) };
+}
+
+#[pyfunction]
+#[pyo3(name = "emit", signature = (name, payload=None))]
+fn py_emit(name: String, payload: Option<Bound<PyAny>>) -> PyResult<()> {
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return Err(PyValueError::new_err(format!("'{}' can't be an event name: components subscribe with on_event_<name>, so use letters, digits and _", name)));
+    }
+    let payload = match payload {
+        Some(payload) => pythonize::depythonize(&payload).map_err(|e| {
+            PyTypeError::new_err(format!("Event payloads must be JSON-serializable (dicts, lists, strings, numbers, booleans or None): {}", e))
+        })?,
+        None => serde_json::Value::Null,
+    };
+    EMITTED.with(|emitted| emitted.borrow_mut().push(Event { name, payload }));
+    Ok(())
+}
+
+// The events emitted since the last call, which are forgotten.
+pub fn take_emitted() -> Vec<Event> {
+    EMITTED.with(|emitted| emitted.take())
+}
+
+pub fn register_python_module(py: Python) -> PyResult<()> {
+    let module = PyModule::new(py, "noventa_events")?;
+    module.add_function(wrap_pyfunction!(py_emit, &module)?)?;
+    py.import("sys")?.getattr("modules")?.set_item("noventa_events", module)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::CString;
+
+    #[test]
+    fn test_emit() {
+        Python::attach(|py| {
+            register_python_module(py).unwrap();
+            take_emitted();
+            let code = CString::new("from noventa_events import emit\nemit('cart_updated', {'count': 3})\nemit('cleared')\n").unwrap();
+            py.run(&code, None, None).unwrap();
+            let bad = CString::new("from noventa_events import emit\nemit('cart-updated')\n").unwrap();
+            assert!(py.run(&bad, None, None).is_err());
+        });
+        let emitted = take_emitted();
+        assert_eq!(emitted.len(), 2);
+        assert_eq!(emitted[0], Event { name: "cart_updated".to_string(), payload: serde_json::json!({ "count": 3 }) });
+        assert_eq!(emitted[1].hook(), "on_event_cleared");
+        assert!(take_emitted().is_empty());
+    }
+}
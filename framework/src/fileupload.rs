@@ -1,21 +1,136 @@
 use crate::actors::page_renderer::{FileData, FilePart};
-use crate::config::CONFIG;
-use actix_multipart::Multipart;
-use futures_util::stream::StreamExt;
+use crate::config::{Config, CONFIG};
+use crate::object_storage::{Bucket, MultipartUpload, BUCKET};
+use crate::upload_validation::{self, Rejection, SNIFF_BYTES};
+use actix::prelude::*;
+use actix_multipart::{Field, Multipart};
+use actix_web::error::PayloadError;
+use actix_web::web::{self, Bytes};
+use actix_web::{HttpRequest, HttpResponse};
+use actix_web_actors::ws;
+use dashmap::DashMap;
+use futures_util::stream::{Stream, StreamExt};
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::io::Write;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use path_clean::PathClean;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::time::Instant;
 
-pub async fn handle_multipart(
-    mut multipart: Multipart,
-) -> (
-    serde_json::Map<String, serde_json::Value>,
-    HashMap<String, FilePart>,
-) {
-    let mut form_data = serde_json::Map::new();
-    let mut files = HashMap::new();
-    
-    let temp_dir = match &CONFIG.temp_dir {
+// Defaults for the body limits in config.yaml.
+const DEFAULT_MAX_REQUEST_SIZE: usize = 32 * 1024 * 1024; // 32 MB
+const DEFAULT_MAX_FIELD_SIZE: usize = 1024 * 1024; // 1 MB
+const DEFAULT_REQUEST_READ_TIMEOUT_SECS: u64 = 60;
+
+// How much of a request body we're willing to receive, and for how long.
+#[derive(Debug, Clone)]
+pub struct BodyLimits {
+    pub max_request_size: usize,
+    // Plain (non-file) form fields.
+    pub max_field_size: usize,
+    pub max_file_size: usize,
+    // Overrides of `max_field_size` or `max_file_size` for single fields, by field name.
+    pub field_limits: HashMap<String, usize>,
+    // The whole body has to arrive within this, so a slow client can't hold a worker forever.
+    pub read_timeout: Duration,
+}
+
+impl BodyLimits {
+    pub fn from_config(config: &Config) -> Self {
+        let max_request_size = config.max_request_size.unwrap_or(DEFAULT_MAX_REQUEST_SIZE);
+        Self {
+            max_request_size,
+            max_field_size: config.max_field_size.unwrap_or(DEFAULT_MAX_FIELD_SIZE),
+            max_file_size: config.max_file_size.unwrap_or(max_request_size),
+            field_limits: config.field_size_limits.clone().unwrap_or_default(),
+            read_timeout: Duration::from_secs(config.request_read_timeout.unwrap_or(DEFAULT_REQUEST_READ_TIMEOUT_SECS)),
+        }
+    }
+
+    // How big `field_name` may get, and the setting that says so.
+    fn field_limit(&self, field_name: &str, default: usize, setting: &str) -> (usize, String) {
+        match self.field_limits.get(field_name) {
+            Some(limit) => (*limit, format!("field_size_limits.{}", field_name)),
+            None => (default, setting.to_string()),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum BodyError {
+    TooLarge(String),
+    TimedOut,
+    Malformed(String),
+    // The upload bucket refused a file; the details are logged, not shown.
+    Storage(String),
+    // A file couldn't be written to the upload directory; the details are logged, not shown.
+    Disk(String),
+    // A file broke the `upload_validation` rules or didn't pass the scan.
+    Rejected(String),
+    // The scanner couldn't say whether a file is safe.
+    ScanFailed(String),
+}
+
+impl BodyError {
+    pub fn to_response(&self) -> HttpResponse {
+        match self {
+            BodyError::TooLarge(message) => HttpResponse::PayloadTooLarge().body(message.clone()),
+            BodyError::TimedOut => HttpResponse::RequestTimeout().body("The request body took too long to arrive."),
+            BodyError::Malformed(message) => HttpResponse::BadRequest().body(message.clone()),
+            BodyError::Storage(_) => HttpResponse::BadGateway().body("We couldn't store the uploaded file. Please try again."),
+            BodyError::Disk(_) => HttpResponse::InternalServerError().body("We couldn't save the uploaded file. Please try again."),
+            BodyError::Rejected(message) => HttpResponse::UnprocessableEntity().body(message.clone()),
+            BodyError::ScanFailed(_) => {
+                HttpResponse::ServiceUnavailable().body("We couldn't check the uploaded file right now. Please try again.")
+            }
+        }
+    }
+}
+
+impl From<Rejection> for BodyError {
+    fn from(rejection: Rejection) -> Self {
+        match rejection {
+            Rejection::Refused(message) => BodyError::Rejected(message),
+            Rejection::ScanFailed(e) => {
+                log::error!("Oh no! The upload scanner failed, so the file was refused: {}", e);
+                BodyError::ScanFailed(e)
+            }
+        }
+    }
+}
+
+fn too_large(limit: usize, setting: &str) -> BodyError {
+    BodyError::TooLarge(format!("The request is larger than the {} bytes allowed by `{}`.", limit, setting))
+}
+
+// The next item of a body stream, as long as the request's deadline hasn't passed.
+pub async fn next_before<S: Stream + Unpin>(stream: &mut S, deadline: Instant) -> Result<Option<S::Item>, BodyError> {
+    tokio::time::timeout_at(deadline, stream.next()).await.map_err(|_| BodyError::TimedOut)
+}
+
+// Counts every byte of the body against `max_request_size`.
+struct BodyReader<'a> {
+    limits: &'a BodyLimits,
+    deadline: Instant,
+    received: usize,
+}
+
+impl BodyReader<'_> {
+    fn count(&mut self, chunk: &[u8]) -> Result<(), BodyError> {
+        self.received += chunk.len();
+        if self.received > self.limits.max_request_size {
+            return Err(too_large(self.limits.max_request_size, "max_request_size"));
+        }
+        Ok(())
+    }
+}
+
+fn temp_dir() -> PathBuf {
+    match &CONFIG.temp_dir {
         Some(dir) if !dir.is_empty() => {
             let path = std::path::PathBuf::from(dir);
             let cleaned_path = path.clean();
@@ -26,23 +141,141 @@ pub async fn handle_multipart(
             }
         }
         _ => std::env::temp_dir(),
-    };
+    }
+}
+
+// Where files bigger than `max_memory_size` end up while the body is read.
+pub enum UploadBackend {
+    Disk(PathBuf),
+    Bucket(&'static Bucket),
+}
+
+impl UploadBackend {
+    pub fn from_config() -> Self {
+        match BUCKET.as_ref() {
+            Some(bucket) => UploadBackend::Bucket(bucket),
+            None => UploadBackend::Disk(temp_dir()),
+        }
+    }
+}
+
+// A file too big for memory, on its way to disk or to the bucket.
+enum Spill {
+    Disk(std::fs::File, PathBuf),
+    Bucket(MultipartUpload),
+}
+
+fn storage_error(filename: &str, e: String) -> BodyError {
+    log::error!("Oh no! We couldn't stream '{}' to the upload bucket: {}", filename, e);
+    BodyError::Storage(e)
+}
+
+fn disk_error(filename: &str, e: std::io::Error) -> BodyError {
+    log::error!("Oh no! We couldn't write '{}' to the upload directory: {}", filename, e);
+    BodyError::Disk(e.to_string())
+}
+
+impl Spill {
+    async fn start(backend: &UploadBackend, filename: &str) -> Result<Self, BodyError> {
+        match backend {
+            UploadBackend::Disk(temp_dir) => {
+                let temp_file_path = temp_dir.join(uuid::Uuid::new_v4().to_string());
+                let absolute_path = std::fs::canonicalize(&temp_file_path).unwrap_or_else(|_| temp_file_path.clone());
+                log::info!("Receiving file '{}' and streaming it to disk at: {}", filename, absolute_path.display());
+                let file = std::fs::File::create(&temp_file_path).map_err(|e| disk_error(filename, e))?;
+                Ok(Spill::Disk(file, temp_file_path))
+            }
+            UploadBackend::Bucket(bucket) => {
+                log::info!("Receiving file '{}' and streaming it to bucket '{}'", filename, bucket.name());
+                let upload = MultipartUpload::start(bucket).await.map_err(|e| storage_error(filename, e))?;
+                Ok(Spill::Bucket(upload))
+            }
+        }
+    }
+
+    async fn write(&mut self, chunk: &[u8], filename: &str) -> Result<(), BodyError> {
+        match self {
+            Spill::Disk(file, _) => file.write_all(chunk).map_err(|e| disk_error(filename, e)),
+            Spill::Bucket(upload) => upload.write(chunk).await.map_err(|e| storage_error(filename, e)),
+        }
+    }
 
-    if let Err(e) = std::fs::create_dir_all(&temp_dir) {
+    async fn finish(self, filename: &str) -> Result<FileData, BodyError> {
+        match self {
+            Spill::Disk(_, path) => Ok(FileData::OnDisk(path)),
+            Spill::Bucket(upload) => upload.finish().await.map(FileData::Remote).map_err(|e| storage_error(filename, e)),
+        }
+    }
+
+    async fn discard(self) {
+        match self {
+            Spill::Disk(_, path) => {
+                let _ = std::fs::remove_file(path);
+            }
+            Spill::Bucket(upload) => upload.abort().await,
+        }
+    }
+}
+
+pub async fn handle_multipart(
+    mut multipart: Multipart,
+    limits: &BodyLimits,
+) -> Result<
+    (
+        serde_json::Map<String, serde_json::Value>,
+        HashMap<String, FilePart>,
+    ),
+    BodyError,
+> {
+    let mut form_data = serde_json::Map::new();
+    let mut files = HashMap::new();
+    let mut reader = BodyReader { limits, deadline: Instant::now() + limits.read_timeout, received: 0 };
+
+    let backend = UploadBackend::from_config();
+    if let UploadBackend::Disk(temp_dir) = &backend
+        && let Err(e) = std::fs::create_dir_all(temp_dir)
+    {
         log::error!("We couldn't create the temporary directory '{}': {}. Please check your permissions.", temp_dir.display(), e);
     }
 
-    while let Some(item) = multipart.next().await {
-        let mut field = item.unwrap();
-        let content_disposition = field.content_disposition().unwrap();
-        let field_name = content_disposition.get_name().unwrap().to_string();
+    let result = read_fields(&mut multipart, &mut reader, &backend, &mut form_data, &mut files).await;
+    if let Err(e) = result {
+        // Nobody will see the files that already made it to disk or the bucket, so don't leave them behind.
+        for file_part in files.values() {
+            match (&file_part.data, &backend) {
+                (FileData::OnDisk(path), _) => {
+                    let _ = std::fs::remove_file(path);
+                }
+                (FileData::Remote(key), UploadBackend::Bucket(bucket)) => {
+                    let _ = bucket.delete(key).await;
+                }
+                _ => {}
+            }
+        }
+        return Err(e);
+    }
+    Ok((form_data, files))
+}
+
+async fn read_fields(
+    multipart: &mut Multipart,
+    reader: &mut BodyReader<'_>,
+    backend: &UploadBackend,
+    form_data: &mut serde_json::Map<String, serde_json::Value>,
+    files: &mut HashMap<String, FilePart>,
+) -> Result<(), BodyError> {
+    while let Some(item) = next_before(multipart, reader.deadline).await? {
+        let mut field = item.map_err(|e| BodyError::Malformed(format!("The multipart body is malformed: {}", e)))?;
+        let content_disposition = field
+            .content_disposition()
+            .ok_or_else(|| BodyError::Malformed("A multipart field has no Content-Disposition header.".to_string()))?;
+        let field_name = content_disposition
+            .get_name()
+            .ok_or_else(|| BodyError::Malformed("A multipart field has no name.".to_string()))?
+            .to_string();
 
         if let Some(filename) = content_disposition.get_filename() {
             let filename = filename.to_string();
-            let mut buffer = Vec::new();
-            let mut file_data: Option<FileData> = None;
-            let mut temp_file: Option<std::fs::File> = None;
-
             let content_type = field
                 .content_type()
                 .map(|mime| mime.to_string())
@@ -51,54 +284,439 @@ pub async fn handle_multipart(
             let headers = field
                 .headers()
                 .iter()
-                .map(|(name, value)| (name.to_string(), value.to_str().unwrap().to_string()))
+                .map(|(name, value)| (name.to_string(), value.to_str().unwrap_or_default().to_string()))
                 .collect();
 
-            while let Some(chunk) = field.next().await {
-                let chunk = chunk.unwrap();
-                if file_data.is_none() {
+            let (data, head) = read_file(&mut field, reader, backend, &field_name, &filename).await?;
+            let detected_type = upload_validation::sniff(&head).to_string();
+            // In `files` before it's checked, so a refused file is cleaned up with the rest.
+            files.insert(
+                field_name.clone(),
+                FilePart {
+                    filename,
+                    content_type,
+                    detected_type,
+                    headers,
+                    data,
+                },
+            );
+            if let Some(validation) = &CONFIG.upload_validation
+                && let Some(file_part) = files.get(&field_name)
+            {
+                upload_validation::check(validation, Some(&field_name), &file_part.filename, &file_part.detected_type, &head)?;
+                upload_validation::scan(validation, &file_part.filename, &file_part.data).await?;
+            }
+        } else {
+            let mut buffer = Vec::new();
+            let (max_size, setting) = reader.limits.field_limit(&field_name, reader.limits.max_field_size, "max_field_size");
+            while let Some(chunk) = next_before(&mut field, reader.deadline).await? {
+                let chunk = chunk.map_err(|e| BodyError::Malformed(format!("Failed to read field '{}': {}", field_name, e)))?;
+                reader.count(&chunk)?;
+                if buffer.len() + chunk.len() > max_size {
+                    return Err(too_large(max_size, &setting));
+                }
+                buffer.extend_from_slice(&chunk);
+            }
+            let value = String::from_utf8(buffer)
+                .map_err(|_| BodyError::Malformed(format!("The form field '{}' isn't valid UTF-8.", field_name)))?;
+            form_data.insert(field_name, serde_json::Value::String(value));
+        }
+    }
+    Ok(())
+}
+
+// Keeps small files in memory and streams bigger ones to the upload backend. Also returns the
+// file's first bytes, to tell what it really is.
+async fn read_file(
+    field: &mut Field,
+    reader: &mut BodyReader<'_>,
+    backend: &UploadBackend,
+    field_name: &str,
+    filename: &str,
+) -> Result<(FileData, Vec<u8>), BodyError> {
+    let mut buffer = Vec::new();
+    let mut head = Vec::new();
+    let mut spill: Option<Spill> = None;
+    let mut size = 0;
+    let (max_size, setting) = reader.limits.field_limit(field_name, reader.limits.max_file_size, "max_file_size");
+
+    let result = async {
+        while let Some(chunk) = next_before(field, reader.deadline).await? {
+            let chunk = chunk.map_err(|e| BodyError::Malformed(format!("Failed to read file '{}': {}", filename, e)))?;
+            reader.count(&chunk)?;
+            size += chunk.len();
+            if size > max_size {
+                return Err(too_large(max_size, &setting));
+            }
+            if head.len() < SNIFF_BYTES {
+                head.extend_from_slice(&chunk[..chunk.len().min(SNIFF_BYTES - head.len())]);
+            }
+
+            match spill.as_mut() {
+                Some(spill) => spill.write(&chunk, filename).await?,
+                None => {
                     let max_size = CONFIG.max_memory_size.unwrap_or(500 * 1024); // 500 KB default
                     if buffer.len() + chunk.len() > max_size {
-                        let temp_file_path = temp_dir.join(uuid::Uuid::new_v4().to_string());
-                        let absolute_path = std::fs::canonicalize(&temp_file_path).unwrap_or_else(|_| temp_file_path.clone());
-                        log::info!("Receiving file '{}' and streaming it to disk at: {}", filename, absolute_path.display());
-                        let mut file = std::fs::File::create(&temp_file_path).unwrap();
-                        file.write_all(&buffer).unwrap();
-                        file.write_all(&chunk).unwrap();
-                        temp_file = Some(file);
-                        file_data = Some(FileData::OnDisk(temp_file_path));
+                        let started = spill.insert(Spill::start(backend, filename).await?);
+                        started.write(&buffer, filename).await?;
+                        started.write(&chunk, filename).await?;
                         buffer.clear();
                     } else {
                         buffer.extend_from_slice(&chunk);
                     }
-                } else {
-                    temp_file.as_mut().unwrap().write_all(&chunk).unwrap();
                 }
             }
+        }
+        Ok(())
+    }
+    .await;
 
-            let final_file_data = file_data.unwrap_or(FileData::InMemory(buffer));
+    match (result, spill) {
+        (Ok(()), Some(spill)) => Ok((spill.finish(filename).await?, head)),
+        (Ok(()), None) => Ok((FileData::InMemory(buffer), head)),
+        (Err(e), spill) => {
+            if let Some(spill) = spill {
+                spill.discard().await;
+            }
+            Err(e)
+        }
+    }
+}
 
-            files.insert(
-                field_name,
-                FilePart {
-                    filename,
-                    content_type,
-                    headers,
-                    data: final_file_data,
-                },
-            );
-        } else {
-            let mut buffer = Vec::new();
-            while let Some(chunk) = field.next().await {
-                buffer.extend_from_slice(&chunk.unwrap());
+// An urlencoded (or any non-multipart) body, read up to `max_request_size`.
+pub async fn read_body<S>(payload: &mut S, limits: &BodyLimits) -> Result<Vec<u8>, BodyError>
+where
+    S: Stream<Item = Result<Bytes, PayloadError>> + Unpin,
+{
+    let mut reader = BodyReader { limits, deadline: Instant::now() + limits.read_timeout, received: 0 };
+    let mut body = Vec::new();
+    while let Some(chunk) = next_before(payload, reader.deadline).await? {
+        let chunk = chunk.map_err(|e| BodyError::Malformed(format!("Failed to read the request body: {}", e)))?;
+        reader.count(&chunk)?;
+        body.extend_from_slice(&chunk);
+    }
+    Ok(body)
+}
+
+// Resumable uploads, for files too big to send in one request.
+//
+// The browser creates an upload with `POST /_noventa/uploads`, then sends the file in chunks with
+// `PATCH /_noventa/uploads/{id}` and an `Upload-Offset` header. After a dropped connection it asks
+// `GET /_noventa/uploads/{id}` how much arrived and carries on from there. The form then submits the
+// upload id, and the page's Python code claims the file with `request.upload(id)`.
+
+const RESUMABLE_DIR: &str = "noventa-resumable";
+// Unfinished uploads nobody touched for a day are removed.
+const STALE_UPLOAD_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+lazy_static! {
+    // Every accepted chunk, for the progress websockets.
+    pub static ref UPLOAD_PROGRESS: broadcast::Sender<UploadProgress> = broadcast::channel(256).0;
+    // One writer per upload at a time, so two chunks for the same offset can't interleave.
+    static ref UPLOAD_LOCKS: DashMap<String, Arc<Mutex<()>>> = DashMap::new();
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct UploadInfo {
+    pub filename: String,
+    #[serde(default = "default_content_type")]
+    pub content_type: String,
+    pub size: u64,
+}
+
+fn default_content_type() -> String {
+    "application/octet-stream".to_string()
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct UploadProgress {
+    pub id: String,
+    pub filename: String,
+    pub received: u64,
+    pub size: u64,
+    pub complete: bool,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ResumableError {
+    NotFound,
+    // The chunk doesn't start where the upload left off; carries the offset to resume from.
+    OffsetMismatch(u64),
+    TooLarge(u64),
+    Io(String),
+}
+
+impl ResumableError {
+    fn to_response(&self) -> HttpResponse {
+        match self {
+            ResumableError::NotFound => HttpResponse::NotFound().json(serde_json::json!({ "error": "There's no upload with this id." })),
+            ResumableError::OffsetMismatch(offset) => HttpResponse::Conflict()
+                .insert_header(("Upload-Offset", offset.to_string()))
+                .json(serde_json::json!({ "error": "The chunk doesn't start where the upload left off.", "offset": offset })),
+            ResumableError::TooLarge(size) => HttpResponse::PayloadTooLarge()
+                .json(serde_json::json!({ "error": format!("The upload is larger than its declared size of {} bytes.", size) })),
+            ResumableError::Io(e) => {
+                log::error!("Oh no! A resumable upload couldn't be stored: {}", e);
+                HttpResponse::InternalServerError().json(serde_json::json!({ "error": "The upload couldn't be stored." }))
             }
-            form_data.insert(
-                field_name,
-                serde_json::Value::String(String::from_utf8(buffer).unwrap()),
-            );
         }
     }
-    (form_data, files)
+}
+
+impl From<std::io::Error> for ResumableError {
+    fn from(e: std::io::Error) -> Self {
+        ResumableError::Io(e.to_string())
+    }
+}
+
+// Each upload is `<id>.part` (the bytes so far) next to `<id>.json` (what the client announced).
+pub struct ResumableStore {
+    dir: PathBuf,
+}
+
+impl ResumableStore {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    // Under `temp_dir`, so uploads survive a restart and can be resumed afterwards.
+    pub fn from_config() -> Self {
+        Self::new(temp_dir().join(RESUMABLE_DIR))
+    }
+
+    // Ids are UUIDs we handed out; anything else never touches the filesystem.
+    fn paths(&self, id: &str) -> Result<(PathBuf, PathBuf), ResumableError> {
+        let id = uuid::Uuid::parse_str(id).map_err(|_| ResumableError::NotFound)?;
+        Ok((self.dir.join(format!("{}.part", id)), self.dir.join(format!("{}.json", id))))
+    }
+
+    fn info(&self, id: &str) -> Result<UploadInfo, ResumableError> {
+        let (_, info_path) = self.paths(id)?;
+        let info = std::fs::read_to_string(info_path).map_err(|_| ResumableError::NotFound)?;
+        serde_json::from_str(&info).map_err(|e| ResumableError::Io(e.to_string()))
+    }
+
+    pub fn create(&self, info: &UploadInfo) -> Result<String, ResumableError> {
+        std::fs::create_dir_all(&self.dir)?;
+        self.remove_stale(STALE_UPLOAD_AGE);
+        let id = uuid::Uuid::new_v4().to_string();
+        let (part_path, info_path) = self.paths(&id)?;
+        std::fs::File::create(part_path)?;
+        std::fs::write(info_path, serde_json::to_string(info).unwrap_or_default())?;
+        Ok(id)
+    }
+
+    pub fn progress(&self, id: &str) -> Result<UploadProgress, ResumableError> {
+        let info = self.info(id)?;
+        let (part_path, _) = self.paths(id)?;
+        let received = std::fs::metadata(part_path).map_err(|_| ResumableError::NotFound)?.len();
+        Ok(UploadProgress {
+            id: id.to_string(),
+            filename: info.filename,
+            received,
+            size: info.size,
+            complete: received == info.size,
+        })
+    }
+
+    pub fn append(&self, id: &str, offset: u64, chunk: &[u8]) -> Result<UploadProgress, ResumableError> {
+        let lock = UPLOAD_LOCKS.entry(id.to_string()).or_default().clone();
+        let _guard = lock.lock().unwrap();
+
+        let progress = self.progress(id)?;
+        if offset != progress.received {
+            return Err(ResumableError::OffsetMismatch(progress.received));
+        }
+        if progress.received + chunk.len() as u64 > progress.size {
+            return Err(ResumableError::TooLarge(progress.size));
+        }
+        let (part_path, _) = self.paths(id)?;
+        std::fs::OpenOptions::new().append(true).open(part_path)?.write_all(chunk)?;
+
+        let received = progress.received + chunk.len() as u64;
+        let progress = UploadProgress { received, complete: received == progress.size, ..progress };
+        if progress.complete {
+            UPLOAD_LOCKS.remove(id);
+        }
+        let _ = UPLOAD_PROGRESS.send(progress.clone());
+        Ok(progress)
+    }
+
+    // Hands a finished upload over to the page. It can only be claimed once.
+    pub fn claim(&self, id: &str) -> Option<(UploadInfo, PathBuf)> {
+        let progress = self.progress(id).ok().filter(|progress| progress.complete)?;
+        let info = self.info(id).ok()?;
+        let (part_path, info_path) = self.paths(id).ok()?;
+        std::fs::remove_file(info_path).ok()?;
+        log::debug!("Claimed resumable upload {} ({} bytes)", id, progress.size);
+        Some((info, part_path))
+    }
+
+    // Forgets an upload and its bytes, e.g. once `upload_validation` refused it.
+    pub fn discard(&self, id: &str) {
+        if let Ok((part_path, info_path)) = self.paths(id) {
+            let _ = std::fs::remove_file(part_path);
+            let _ = std::fs::remove_file(info_path);
+        }
+    }
+
+    // A complete upload goes through the same checks as a regular one before anyone can claim it.
+    async fn validate(&self, id: &str, config: &crate::config::UploadValidationConfig) -> Result<(), Rejection> {
+        let (info, (part_path, _)) = match (self.info(id), self.paths(id)) {
+            (Ok(info), Ok(paths)) => (info, paths),
+            _ => return Ok(()),
+        };
+        let head = upload_validation::read_head(&part_path);
+        upload_validation::check(config, None, &info.filename, upload_validation::sniff(&head), &head)?;
+        upload_validation::scan(config, &info.filename, &FileData::OnDisk(part_path)).await
+    }
+
+    pub fn remove_stale(&self, max_age: Duration) {
+        let Ok(entries) = std::fs::read_dir(&self.dir) else {
+            return;
+        };
+        for entry in entries.filter_map(Result::ok) {
+            let stale = entry
+                .metadata()
+                .and_then(|metadata| metadata.modified())
+                .ok()
+                .and_then(|modified| modified.elapsed().ok())
+                .is_some_and(|age| age > max_age);
+            if stale {
+                let _ = std::fs::remove_file(entry.path());
+            }
+        }
+    }
+}
+
+async fn create_upload(info: web::Json<UploadInfo>) -> HttpResponse {
+    let limits = BodyLimits::from_config(&CONFIG);
+    if info.size > limits.max_file_size as u64 {
+        return HttpResponse::PayloadTooLarge()
+            .json(serde_json::json!({ "error": format!("Files can be at most {} bytes (`max_file_size`).", limits.max_file_size) }));
+    }
+    match ResumableStore::from_config().create(&info) {
+        Ok(id) => {
+            log::info!("Receiving '{}' ({} bytes) as resumable upload {}", info.filename, info.size, id);
+            HttpResponse::Created()
+                .insert_header(("Location", format!("/_noventa/uploads/{}", id)))
+                .json(serde_json::json!({ "id": id, "offset": 0, "size": info.size }))
+        }
+        Err(e) => e.to_response(),
+    }
+}
+
+async fn upload_status(id: web::Path<String>) -> HttpResponse {
+    match ResumableStore::from_config().progress(&id) {
+        Ok(progress) => HttpResponse::Ok().insert_header(("Upload-Offset", progress.received.to_string())).json(progress),
+        Err(e) => e.to_response(),
+    }
+}
+
+async fn upload_chunk(req: HttpRequest, id: web::Path<String>, mut payload: web::Payload) -> HttpResponse {
+    let Some(offset) = req.headers().get("upload-offset").and_then(|v| v.to_str().ok()).and_then(|v| v.parse::<u64>().ok()) else {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": "Send the chunk's position in an `Upload-Offset` header." }));
+    };
+    let chunk = match read_body(&mut payload, &BodyLimits::from_config(&CONFIG)).await {
+        Ok(chunk) => chunk,
+        Err(e) => return e.to_response(),
+    };
+    let store = ResumableStore::from_config();
+    let progress = match store.append(&id, offset, &chunk) {
+        Ok(progress) => progress,
+        Err(e) => return e.to_response(),
+    };
+    if progress.complete
+        && let Some(validation) = &CONFIG.upload_validation
+        && let Err(rejection) = store.validate(&id, validation).await
+    {
+        store.discard(&id);
+        return match BodyError::from(rejection) {
+            BodyError::Rejected(message) => HttpResponse::UnprocessableEntity().json(serde_json::json!({ "error": message })),
+            _ => HttpResponse::ServiceUnavailable()
+                .json(serde_json::json!({ "error": "We couldn't check the uploaded file right now. Please try again." })),
+        };
+    }
+    HttpResponse::Ok().insert_header(("Upload-Offset", progress.received.to_string())).json(progress)
+}
+
+async fn upload_events(req: HttpRequest, id: web::Path<String>, stream: web::Payload) -> Result<HttpResponse, actix_web::Error> {
+    let progress = ResumableStore::from_config().progress(&id).map_err(|_| actix_web::error::ErrorNotFound("There's no upload with this id."))?;
+    ws::start(UploadProgressSocket { progress }, &req, stream)
+}
+
+pub fn configure_resumable(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/_noventa/uploads")
+            .route("", web::post().to(create_upload))
+            .route("/{id}", web::get().to(upload_status))
+            .route("/{id}", web::patch().to(upload_chunk))
+            .route("/{id}/events", web::get().to(upload_events)),
+    );
+}
+
+// Pushes an upload's progress to the page showing it, then closes once the upload is complete.
+struct UploadProgressSocket {
+    progress: UploadProgress,
+}
+
+#[derive(Message)]
+#[rtype(result = "()")]
+struct ProgressMessage(UploadProgress);
+
+impl Actor for UploadProgressSocket {
+    type Context = ws::WebsocketContext<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        ctx.text(serde_json::to_string(&self.progress).unwrap_or_default());
+        if self.progress.complete {
+            ctx.close(None);
+            ctx.stop();
+            return;
+        }
+        let addr = ctx.address();
+        let id = self.progress.id.clone();
+        let mut progress_rx = UPLOAD_PROGRESS.subscribe();
+        actix::spawn(async move {
+            loop {
+                match progress_rx.recv().await {
+                    Ok(progress) if progress.id == id => {
+                        let complete = progress.complete;
+                        if addr.try_send(ProgressMessage(progress)).is_err() || complete {
+                            break;
+                        }
+                    }
+                    Ok(_) | Err(RecvError::Lagged(_)) => {}
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        });
+    }
+}
+
+impl Handler<ProgressMessage> for UploadProgressSocket {
+    type Result = ();
+
+    fn handle(&mut self, msg: ProgressMessage, ctx: &mut Self::Context) {
+        ctx.text(serde_json::to_string(&msg.0).unwrap_or_default());
+        if msg.0.complete {
+            ctx.close(None);
+            ctx.stop();
+        }
+    }
+}
+
+impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for UploadProgressSocket {
+    fn handle(&mut self, msg: Result<ws::Message, ws::ProtocolError>, ctx: &mut Self::Context) {
+        match msg {
+            Ok(ws::Message::Ping(msg)) => ctx.pong(&msg),
+            Ok(ws::Message::Close(reason)) => {
+                ctx.close(reason);
+                ctx.stop();
+            }
+            _ => (),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -139,7 +757,7 @@ fn test_handle_multipart_in_memory() {
         let payload = actix_http::Payload::from(Box::pin(stream) as Pin<Box<dyn futures_util::Stream<Item = Result<Bytes, PayloadError>>>>);
 
         let multipart = Multipart::new(&headers, payload);
-        let (form_data, files) = handle_multipart(multipart).await;
+        let (form_data, files) = handle_multipart(multipart, &BodyLimits::from_config(&Config::default())).await.unwrap();
 
         assert_eq!(form_data.len(), 1);
         assert_eq!(
@@ -189,7 +807,7 @@ fn test_handle_multipart_on_disk() {
         let payload = actix_http::Payload::from(Box::pin(stream) as Pin<Box<dyn futures_util::Stream<Item = Result<Bytes, PayloadError>>>>);
 
         let multipart = Multipart::new(&headers, payload);
-        let (_form_data, files) = handle_multipart(multipart).await;
+        let (_form_data, files) = handle_multipart(multipart, &BodyLimits::from_config(&Config::default())).await.unwrap();
 
         assert_eq!(files.len(), 1);
         let file_part = files.get("file1").unwrap();
@@ -204,4 +822,111 @@ fn test_handle_multipart_on_disk() {
         }
     });
 }
-}
\ No newline at end of file
+
+fn multipart_from(body: Vec<u8>) -> Multipart {
+    let mut headers = HeaderMap::new();
+    headers.insert(CONTENT_TYPE, HeaderValue::from_static("multipart/form-data; boundary=boundary"));
+    let stream = iter(vec![Ok::<_, PayloadError>(Bytes::from(body))]);
+    let payload = actix_http::Payload::from(Box::pin(stream) as Pin<Box<dyn futures_util::Stream<Item = Result<Bytes, PayloadError>>>>);
+    Multipart::new(&headers, payload)
+}
+
+fn limits(max_request_size: usize, max_field_size: usize, max_file_size: usize) -> BodyLimits {
+    BodyLimits { max_request_size, max_field_size, max_file_size, field_limits: HashMap::new(), read_timeout: Duration::from_secs(5) }
+}
+
+#[actix_rt::test]
+async fn test_handle_multipart_limits() {
+    let body = b"--boundary\r\n\
+        Content-Disposition: form-data; name=\"note\"\r\n\r\n\
+        0123456789\r\n\
+        --boundary\r\n\
+        Content-Disposition: form-data; name=\"file1\"; filename=\"test.txt\"\r\n\r\n\
+        0123456789012345678901234567890123456789\r\n\
+        --boundary--\r\n"
+        .to_vec();
+
+    assert!(handle_multipart(multipart_from(body.clone()), &limits(1000, 10, 40)).await.is_ok());
+    assert_eq!(
+        handle_multipart(multipart_from(body.clone()), &limits(1000, 9, 40)).await.err(),
+        Some(BodyError::TooLarge("The request is larger than the 9 bytes allowed by `max_field_size`.".to_string()))
+    );
+    assert!(matches!(
+        handle_multipart(multipart_from(body.clone()), &limits(1000, 10, 39)).await,
+        Err(BodyError::TooLarge(message)) if message.contains("max_file_size")
+    ));
+    assert!(matches!(
+        handle_multipart(multipart_from(body.clone()), &limits(45, 10, 40)).await,
+        Err(BodyError::TooLarge(message)) if message.contains("max_request_size")
+    ));
+
+    // Per-field limits win over the general ones, both ways.
+    let field_limits = HashMap::from([("note".to_string(), 20), ("file1".to_string(), 30)]);
+    let per_field = BodyLimits { field_limits, ..limits(1000, 5, 100) };
+    assert_eq!(
+        handle_multipart(multipart_from(body.clone()), &per_field).await.err(),
+        Some(BodyError::TooLarge("The request is larger than the 30 bytes allowed by `field_size_limits.file1`.".to_string()))
+    );
+    let field_limits = HashMap::from([("note".to_string(), 20), ("file1".to_string(), 40)]);
+    assert!(handle_multipart(multipart_from(body), &BodyLimits { field_limits, ..limits(1000, 5, 10) }).await.is_ok());
+}
+
+#[actix_rt::test]
+async fn test_unwritable_upload_dir() {
+    let dir = tempfile::tempdir().unwrap();
+    let backend = UploadBackend::Disk(dir.path().join("missing"));
+    let error = Spill::start(&backend, "big.bin").await.err().unwrap();
+    assert!(matches!(error, BodyError::Disk(_)));
+    assert_eq!(error.to_response().status(), 500);
+}
+
+#[actix_rt::test]
+async fn test_read_body() {
+    let mut payload = iter(vec![Ok::<_, PayloadError>(Bytes::from_static(b"name=noventa"))]);
+    assert_eq!(read_body(&mut payload, &limits(100, 10, 10)).await.unwrap(), b"name=noventa");
+
+    let mut payload = iter(vec![Ok::<_, PayloadError>(Bytes::from_static(b"name=noventa"))]);
+    assert!(matches!(read_body(&mut payload, &limits(5, 10, 10)).await, Err(BodyError::TooLarge(_))));
+
+    // A client that sends a little and then goes quiet.
+    let mut payload = iter(vec![Ok::<_, PayloadError>(Bytes::from_static(b"name="))])
+        .chain(futures_util::stream::pending());
+    let slow = BodyLimits { read_timeout: Duration::from_millis(50), ..limits(100, 10, 10) };
+    assert_eq!(read_body(&mut payload, &slow).await.unwrap_err(), BodyError::TimedOut);
+}
+
+#[test]
+fn test_resumable_upload() {
+    let dir = tempfile::tempdir().unwrap();
+    let store = ResumableStore::new(dir.path().to_path_buf());
+    let info = UploadInfo { filename: "video.mp4".to_string(), content_type: "video/mp4".to_string(), size: 10 };
+    let id = store.create(&info).unwrap();
+
+    assert_eq!(store.append(&id, 0, b"01234").unwrap().received, 5);
+    // A retried chunk after a dropped connection is refused with the offset to resume from.
+    assert_eq!(store.append(&id, 0, b"01234"), Err(ResumableError::OffsetMismatch(5)));
+    assert_eq!(store.append(&id, 5, b"56789abc"), Err(ResumableError::TooLarge(10)));
+    assert!(store.claim(&id).is_none());
+
+    let progress = store.append(&id, 5, b"56789").unwrap();
+    assert!(progress.complete);
+    let (claimed, path) = store.claim(&id).unwrap();
+    assert_eq!(claimed, info);
+    assert_eq!(std::fs::read(path).unwrap(), b"0123456789");
+    assert!(store.claim(&id).is_none());
+}
+
+#[test]
+fn test_resumable_upload_ids() {
+    let dir = tempfile::tempdir().unwrap();
+    let store = ResumableStore::new(dir.path().to_path_buf());
+    assert_eq!(store.progress("../../etc/passwd"), Err(ResumableError::NotFound));
+    assert_eq!(store.progress(&uuid::Uuid::new_v4().to_string()), Err(ResumableError::NotFound));
+
+    let info = UploadInfo { filename: "a.txt".to_string(), content_type: "text/plain".to_string(), size: 1 };
+    let id = store.create(&info).unwrap();
+    std::thread::sleep(Duration::from_millis(20));
+    store.remove_stale(Duration::ZERO);
+    assert_eq!(store.progress(&id), Err(ResumableError::NotFound));
+}
+}
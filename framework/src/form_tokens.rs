@@ -0,0 +1,58 @@
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// The hidden field every form Noventa renders carries when `form_tokens` is on.
+pub const FIELD: &str = "form_token";
+// How long a used token is remembered. A form sent again after that goes through again.
+const REMEMBER_FOR: Duration = Duration::from_secs(24 * 60 * 60);
+// Past this many remembered tokens, the expired ones are dropped.
+const PRUNE_AT: usize = 10_000;
+
+// Tokens of forms that were submitted, and when. Per process, like the rate limits.
+static USED: Lazy<Mutex<HashMap<String, Instant>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+pub fn enabled() -> bool {
+    crate::config::CONFIG.form_tokens.unwrap_or(false)
+}
+
+pub fn issue() -> String {
+    uuid::Uuid::new_v4().simple().to_string()
+}
+
+// Marks `token` as used. False when it already was: the form is being sent again, by a refresh,
+// the back button or a double click.
+pub fn consume(token: &str) -> bool {
+    let now = Instant::now();
+    let mut used = USED.lock().unwrap();
+    if used.len() >= PRUNE_AT {
+        used.retain(|_, at| now.duration_since(*at) < REMEMBER_FOR);
+    }
+    if used.get(token).is_some_and(|at| now.duration_since(*at) < REMEMBER_FOR) {
+        return false;
+    }
+    used.insert(token.to_string(), now);
+    true
+}
+
+// The action failed, so sending the form again should try again.
+pub fn release(token: &str) {
+    USED.lock().unwrap().remove(token);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tokens_are_used_once() {
+        let token = issue();
+        assert_eq!(token.len(), 32);
+        assert_ne!(token, issue());
+        assert!(consume(&token));
+        assert!(!consume(&token));
+        release(&token);
+        assert!(consume(&token));
+    }
+}
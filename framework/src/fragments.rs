@@ -0,0 +1,143 @@
+use actix_web::HttpRequest;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+
+// `{% fragment "row" %}`, optionally with `cache=300`, `cache=false` or `cache="private, max-age=60"`.
+static FRAGMENT_TAG: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r#"\{%(-?)\s*fragment\s+["']([A-Za-z0-9_]+)["']\s*(?:cache\s*=\s*("[^"]*"|'[^']*'|[0-9]+|false)\s*)?(-?)%\}"#).unwrap()
+});
+static ENDFRAGMENT_TAG: Lazy<Regex> = Lazy::new(|| Regex::new(r"\{%(-?)\s*endfragment\s*(-?)%\}").unwrap());
+
+// The block `{% fragment "row" %}` turns into, so it can be rendered on its own.
+pub fn block_name(fragment: &str) -> String {
+    format!("fragment_{}", fragment)
+}
+
+// `{% fragment "row" %}...{% endfragment %}` is a block under another name: it renders in place as
+// usual, and on its own when a request asks for it. Lines stay where they were, for error messages.
+pub fn translate(source: &str) -> String {
+    if !source.contains("fragment") {
+        return source.to_string();
+    }
+    let source = FRAGMENT_TAG.replace_all(source, "{%$1 block fragment_$2 $4%}");
+    ENDFRAGMENT_TAG.replace_all(&source, "{%$1 endblock $2%}").into_owned()
+}
+
+// The Cache-Control header of each fragment that says `cache=`, for when it's requested on its own.
+pub fn cache_settings(template_path: &str, source: &str) -> HashMap<String, String> {
+    FRAGMENT_TAG
+        .captures_iter(source)
+        .filter_map(|caps| {
+            let setting = caps.get(3)?.as_str();
+            let value = match setting.strip_prefix(['"', '\'']) {
+                Some(quoted) => serde_json::Value::String(quoted[..quoted.len() - 1].to_string()),
+                None => serde_json::from_str(setting).ok()?,
+            };
+            let cache_control = crate::page_meta::cache_control(template_path, &value)?;
+            Some((caps[2].to_string(), cache_control))
+        })
+        .collect()
+}
+
+// A strong validator for a fragment response. The query string is part of it, since that's where
+// the fragment's arguments come from.
+pub fn etag(fragment: &str, query: &str, body: &str) -> String {
+    let mut hasher = Sha256::new();
+    for part in [fragment, query, body] {
+        hasher.update(part.as_bytes());
+        hasher.update([0]);
+    }
+    format!("\"{:.32}\"", format!("{:x}", hasher.finalize()))
+}
+
+// Whether the client's `If-None-Match` already has `etag`, so a 304 will do.
+pub fn not_modified(req: &HttpRequest, etag: &str) -> bool {
+    req.headers()
+        .get_all(actix_web::http::header::IF_NONE_MATCH)
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|tag| tag.trim().trim_start_matches("W/"))
+        .any(|tag| tag == etag || tag == "*")
+}
+
+// htmx-style partial requests name the fragment they want in `X-Fragment` or `?fragment=`.
+pub fn requested(req: &HttpRequest) -> Option<String> {
+    let from_header = req.headers().get("x-fragment").and_then(|value| value.to_str().ok()).map(str::to_string);
+    let from_query = || {
+        serde_urlencoded::from_str::<Vec<(String, String)>>(req.query_string())
+            .ok()?
+            .into_iter()
+            .find(|(name, _)| name == "fragment")
+            .map(|(_, value)| value)
+    };
+    from_header
+        .or_else(from_query)
+        .map(|fragment| fragment.trim().to_string())
+        .filter(|fragment| !fragment.is_empty() && fragment.chars().all(|c| c.is_ascii_alphanumeric() || c == '_'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    #[test]
+    fn test_translate() {
+        assert_eq!(
+            translate("<table>{% for row in rows %}{% fragment \"row\" %}<tr>{{ row }}</tr>{% endfragment %}{% endfor %}</table>"),
+            "<table>{% for row in rows %}{% block fragment_row %}<tr>{{ row }}</tr>{% endblock %}{% endfor %}</table>"
+        );
+        assert_eq!(translate("{%- fragment 'count' -%}3{%- endfragment -%}"), "{%- block fragment_count -%}3{%- endblock -%}");
+        assert_eq!(translate("<p>fragments of {{ text }}</p>"), "<p>fragments of {{ text }}</p>");
+    }
+
+    #[test]
+    fn test_cache_settings() {
+        let source = "{% fragment \"trending\" cache=300 %}{% endfragment %}\n\
+                      {%- fragment 'cart' cache=false -%}{% endfragment %}\n\
+                      {% fragment \"feed\" cache=\"private, max-age=60\" %}{% endfragment %}\n\
+                      {% fragment \"row\" %}{% endfragment %}";
+        let settings = cache_settings("pages/index.html", source);
+        assert_eq!(settings.len(), 3);
+        assert_eq!(settings["trending"], "public, max-age=300");
+        assert_eq!(settings["cart"], "no-store");
+        assert_eq!(settings["feed"], "private, max-age=60");
+        assert_eq!(translate("{% fragment \"trending\" cache=300 %}x{% endfragment %}"), "{% block fragment_trending %}x{% endblock %}");
+    }
+
+    #[test]
+    fn test_etag_and_not_modified() {
+        let tag = etag("row", "fragment=row&id=1", "<tr>1</tr>");
+        assert_eq!(tag.len(), 34);
+        assert_ne!(tag, etag("row", "fragment=row&id=2", "<tr>1</tr>"));
+        assert_eq!(tag, etag("row", "fragment=row&id=1", "<tr>1</tr>"));
+
+        let req = TestRequest::get().insert_header(("If-None-Match", format!("\"other\", W/{}", tag))).to_http_request();
+        assert!(not_modified(&req, &tag));
+        assert!(!not_modified(&TestRequest::get().to_http_request(), &tag));
+    }
+
+    #[test]
+    fn test_translated_fragment_renders_alone() {
+        let mut env = minijinja::Environment::new();
+        let source = translate("<h1>Cart</h1>\n{% fragment \"total\" %}<b>{{ total }}</b>{% endfragment %}\n<footer></footer>");
+        env.add_template_owned("cart.html", source).unwrap();
+        let template = env.get_template("cart.html").unwrap();
+        assert_eq!(template.render(minijinja::context! { total => 3 }).unwrap(), "<h1>Cart</h1>\n<b>3</b>\n<footer></footer>");
+        let mut state = template.eval_to_state(minijinja::context! { total => 3 }).unwrap();
+        assert_eq!(state.render_block(&block_name("total")).unwrap(), "<b>3</b>");
+    }
+
+    #[test]
+    fn test_requested() {
+        let req = TestRequest::get().uri("/cart?fragment=total").to_http_request();
+        assert_eq!(requested(&req), Some("total".to_string()));
+        let req = TestRequest::get().uri("/cart?fragment=x").insert_header(("X-Fragment", "row")).to_http_request();
+        assert_eq!(requested(&req), Some("row".to_string()));
+        let req = TestRequest::get().uri("/cart?fragment=../etc").to_http_request();
+        assert_eq!(requested(&req), None);
+        assert_eq!(requested(&TestRequest::get().uri("/cart").to_http_request()), None);
+    }
+}
@@ -0,0 +1,315 @@
+use crate::routing::path_to_route;
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+
+const LOGIC_TEMPLATE: &str = r#"def load_template_context(request, session, db, **props):
+    # Runs when a page containing this component is loaded.
+    # Whatever you return here is available in {name}_template.html.
+    return {
+        "title": "{name}",
+    }
+
+
+def action_submit(request, session, db, **props):
+    # Runs when a form in this component posts with action=submit.
+    return load_template_context(request, session, db, **props)
+"#;
+
+const TEMPLATE_TEMPLATE: &str = r#"<div>
+    <h2>{{ title }}</h2>
+    <form method="post">
+        <input type="hidden" name="action" value="submit">
+        <button type="submit">Submit</button>
+    </form>
+</div>
+"#;
+
+const ACTION_TEMPLATE: &str = r#"def action_{name}(request, session, db, **props):
+    # Runs when a form in this component posts with action={name}.
+    # Whatever you return here is added to what load_template_context returned.
+    return {}
+"#;
+
+// For a component that had no logic file: the action needs one, and the component still renders as before.
+const ACTION_LOGIC_TEMPLATE: &str = r#"def load_template_context(request, session, db, **props):
+    return {}
+"#;
+
+const MODELS_TEMPLATE: &str = r#"from sqlalchemy import Integer, String
+from sqlalchemy.orm import DeclarativeBase, Mapped, mapped_column
+
+
+class Base(DeclarativeBase):
+    pass
+
+
+class {class_name}(Base):
+    __tablename__ = "{name}"
+
+    id: Mapped[int] = mapped_column(Integer, primary_key=True)
+    name: Mapped[str] = mapped_column(String(255))
+"#;
+
+const PAGE_TEMPLATE: &str = r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta charset="UTF-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1.0">
+    <title>{title}</title>
+</head>
+<body>
+    <main>
+        <h1>{title}</h1>
+    </main>
+</body>
+</html>
+"#;
+
+fn invalid(message: String) -> Error {
+    Error::new(ErrorKind::InvalidInput, message)
+}
+
+// Component folders become Python packages, so every segment must be a valid identifier.
+fn validate_identifier(segment: &str) -> Result<(), Error> {
+    let mut chars = segment.chars();
+    let valid_start = chars.next().is_some_and(|c| c.is_ascii_lowercase() || c == '_');
+    if !valid_start || !chars.all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_') {
+        return Err(invalid(format!(
+            "'{}' isn't a valid component name. Use lowercase letters, digits and underscores, e.g. 'pricing_card'.",
+            segment
+        )));
+    }
+    Ok(())
+}
+
+fn to_class_name(name: &str) -> String {
+    name.split('_')
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            let mut chars = part.chars();
+            chars
+                .next()
+                .map(|c| c.to_ascii_uppercase().to_string() + chars.as_str())
+                .unwrap_or_default()
+        })
+        .collect()
+}
+
+fn write_new_file(path: &Path, content: &str) -> Result<(), Error> {
+    if path.exists() {
+        return Err(Error::new(
+            ErrorKind::AlreadyExists,
+            format!("{} already exists, so we left it untouched.", path.display()),
+        ));
+    }
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(path, content)
+}
+
+// Creates `components/<path>/<name>_logic.py` and `<name>_template.html` (and optionally `<name>_models.py`).
+// Accepts dotted names like `cards.pricing` for subcomponents.
+pub fn generate_component(root: &Path, name: &str, with_models: bool) -> Result<Vec<PathBuf>, Error> {
+    let segments: Vec<&str> = name.split(['.', '/']).collect();
+    for segment in &segments {
+        validate_identifier(segment)?;
+    }
+    let leaf = *segments.last().unwrap();
+    let component_dir = segments.iter().fold(root.join("components"), |dir, s| dir.join(s));
+
+    let mut files = vec![
+        (component_dir.join(format!("{}_logic.py", leaf)), LOGIC_TEMPLATE.replace("{name}", leaf)),
+        (component_dir.join(format!("{}_template.html", leaf)), TEMPLATE_TEMPLATE.to_string()),
+    ];
+    if with_models {
+        files.push((
+            component_dir.join(format!("{}_models.py", leaf)),
+            MODELS_TEMPLATE.replace("{class_name}", &to_class_name(leaf)).replace("{name}", leaf),
+        ));
+    }
+
+    // Check everything first so a clash never leaves a half-generated component behind.
+    if let Some((existing, _)) = files.iter().find(|(path, _)| path.exists()) {
+        return Err(Error::new(
+            ErrorKind::AlreadyExists,
+            format!("{} already exists, so we left the component untouched.", existing.display()),
+        ));
+    }
+    for (path, content) in &files {
+        write_new_file(path, content)?;
+    }
+
+    Ok(files.into_iter().map(|(path, _)| path).collect())
+}
+
+// Adds `action_<action>` to a component's logic file, creating the file if the component has none,
+// and returns the file. `component` is dotted like `cards.pricing`.
+pub fn add_action(root: &Path, component: &str, action: &str) -> Result<PathBuf, Error> {
+    let action = action.strip_prefix("action_").unwrap_or(action);
+    validate_identifier(action).map_err(|_| invalid(format!("'{}' isn't a valid action name. Use lowercase letters, digits and underscores, e.g. 'subscribe'.", action)))?;
+    let id = component.replace('.', "/");
+    let found = crate::components::scan_project_components(root)?
+        .into_iter()
+        .find(|c| c.id == id)
+        .ok_or_else(|| Error::new(ErrorKind::NotFound, format!("There's no '{}' component. Create it first.", component)))?;
+
+    let function = format!("action_{}", action);
+    let code = ACTION_TEMPLATE.replace("{name}", action);
+    let Some(logic_path) = found.logic_path.map(PathBuf::from) else {
+        let template_path = Path::new(&found.template_path);
+        let leaf = id.rsplit('/').next().unwrap_or(&id);
+        let logic_path = template_path.parent().unwrap_or(root).join(format!("{}_logic.py", leaf));
+        write_new_file(&logic_path, &format!("{}
+
+{}", ACTION_LOGIC_TEMPLATE, code))?;
+        return Ok(logic_path);
+    };
+    if crate::lsp_index::python_functions(&logic_path).iter().any(|f| f.name == function) {
+        return Err(Error::new(
+            ErrorKind::AlreadyExists,
+            format!("{} already has `{}`, so we left it untouched.", logic_path.display(), function),
+        ));
+    }
+    let existing = std::fs::read_to_string(&logic_path)?;
+    let separator = if existing.trim().is_empty() { "" } else if existing.ends_with('\n') { "\n\n" } else { "\n\n\n" };
+    std::fs::write(&logic_path, format!("{}{}{}", existing, separator, code))?;
+    Ok(logic_path)
+}
+
+// Maps a route like `/blog/{id}`, `/blog/:id` or `blog/[id]` to the page file that serves it.
+pub fn route_to_page_path(route: &str) -> Result<PathBuf, Error> {
+    let mut path = PathBuf::from("pages");
+    let segments: Vec<&str> = route.trim_matches('/').split('/').filter(|s| !s.is_empty()).collect();
+    if segments.is_empty() {
+        return Ok(path.join("index.html"));
+    }
+
+    for (i, segment) in segments.iter().enumerate() {
+        let param = segment
+            .strip_prefix('{')
+            .and_then(|s| s.strip_suffix('}'))
+            .or_else(|| segment.strip_prefix(':'))
+            .or_else(|| segment.strip_prefix('[').and_then(|s| s.strip_suffix(']')));
+        let segment = match param {
+            Some(name) => format!("[{}]", name),
+            None => segment.to_string(),
+        };
+        if segment.contains(['{', '}', '\\', '.']) || segment == "[]" {
+            return Err(invalid(format!("'{}' isn't a valid route segment.", segment)));
+        }
+        if i == segments.len() - 1 && !route.ends_with('/') {
+            path.push(format!("{}.html", segment));
+        } else {
+            path.push(segment);
+        }
+    }
+    if route.ends_with('/') {
+        path.push("index.html");
+    }
+    Ok(path)
+}
+
+// Creates the page file for `route` and returns it together with the route it will be served on.
+pub fn generate_page(root: &Path, route: &str) -> Result<(PathBuf, String), Error> {
+    let page_path = root.join(route_to_page_path(route)?);
+    let title = page_path
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .map(|s| if s == "index" { "Home" } else { s })
+        .unwrap_or("Page")
+        .trim_matches(['[', ']'])
+        .replace(['-', '_'], " ");
+    write_new_file(&page_path, &PAGE_TEMPLATE.replace("{title}", &title))?;
+
+    let resolved_route = path_to_route(&page_path, &root.join("pages"));
+    Ok((page_path, resolved_route))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_generate_component() {
+        let dir = tempdir().unwrap();
+        let files = generate_component(dir.path(), "cards.pricing_table", true).unwrap();
+        let component_dir = dir.path().join("components/cards/pricing_table");
+        assert_eq!(
+            files,
+            vec![
+                component_dir.join("pricing_table_logic.py"),
+                component_dir.join("pricing_table_template.html"),
+                component_dir.join("pricing_table_models.py"),
+            ]
+        );
+
+        let logic = std::fs::read_to_string(&files[0]).unwrap();
+        assert!(logic.contains("def load_template_context(request, session, db, **props):"));
+        assert!(logic.contains("\"title\": \"pricing_table\","));
+        let template = std::fs::read_to_string(&files[1]).unwrap();
+        assert!(template.contains("{{ title }}"));
+        let models = std::fs::read_to_string(&files[2]).unwrap();
+        assert!(models.contains("class PricingTable(Base):"));
+
+        // The generated component is picked up by the scanner under its dotted id.
+        let components = crate::components::scan_components(&dir.path().join("components")).unwrap();
+        assert!(components.iter().any(|c| c.id == "cards/pricing_table" && c.logic_path.is_some()));
+
+        // Running it again refuses to overwrite anything.
+        assert_eq!(
+            generate_component(dir.path(), "cards.pricing_table", false).unwrap_err().kind(),
+            ErrorKind::AlreadyExists
+        );
+    }
+
+    #[test]
+    fn test_generate_component_rejects_invalid_names() {
+        let dir = tempdir().unwrap();
+        assert!(generate_component(dir.path(), "Pricing-Card", false).is_err());
+        assert!(generate_component(dir.path(), "cards..pricing", false).is_err());
+        assert!(generate_component(dir.path(), "9lives", false).is_err());
+    }
+
+    #[test]
+    fn test_add_action() {
+        let dir = tempdir().unwrap();
+        generate_component(dir.path(), "newsletter", false).unwrap();
+        let logic_path = add_action(dir.path(), "newsletter", "subscribe").unwrap();
+        assert_eq!(logic_path, dir.path().join("components/newsletter/newsletter_logic.py"));
+        let logic = std::fs::read_to_string(&logic_path).unwrap();
+        assert!(logic.contains("    return load_template_context(request, session, db, **props)\n\n\ndef action_subscribe(request, session, db, **props):\n"));
+        assert_eq!(add_action(dir.path(), "newsletter", "action_subscribe").unwrap_err().kind(), ErrorKind::AlreadyExists);
+        assert_eq!(add_action(dir.path(), "ghost", "subscribe").unwrap_err().kind(), ErrorKind::NotFound);
+        assert_eq!(add_action(dir.path(), "newsletter", "Sign Up").unwrap_err().kind(), ErrorKind::InvalidInput);
+
+        // A component with only a template gets a logic file.
+        std::fs::create_dir_all(dir.path().join("components/cards/badge")).unwrap();
+        std::fs::write(dir.path().join("components/cards/badge/badge_template.html"), "<span></span>").unwrap();
+        let logic_path = add_action(dir.path(), "cards.badge", "dismiss").unwrap();
+        assert_eq!(logic_path, dir.path().join("components/cards/badge/badge_logic.py"));
+        let logic = std::fs::read_to_string(&logic_path).unwrap();
+        assert!(logic.starts_with("def load_template_context(request, session, db, **props):\n    return {}\n\n\ndef action_dismiss("));
+    }
+
+    #[test]
+    fn test_route_to_page_path() {
+        assert_eq!(route_to_page_path("/").unwrap(), PathBuf::from("pages/index.html"));
+        assert_eq!(route_to_page_path("/about").unwrap(), PathBuf::from("pages/about.html"));
+        assert_eq!(route_to_page_path("/blog/{id}").unwrap(), PathBuf::from("pages/blog/[id].html"));
+        assert_eq!(route_to_page_path("blog/:id").unwrap(), PathBuf::from("pages/blog/[id].html"));
+        assert_eq!(route_to_page_path("/docs/[section]/").unwrap(), PathBuf::from("pages/docs/[section]/index.html"));
+        assert!(route_to_page_path("/../etc").is_err());
+    }
+
+    #[test]
+    fn test_generate_page() {
+        let dir = tempdir().unwrap();
+        let (path, route) = generate_page(dir.path(), "/users/{user_id}").unwrap();
+        assert_eq!(path, dir.path().join("pages/users/[user_id].html"));
+        assert_eq!(route, "/users/{user-id}");
+        assert!(std::fs::read_to_string(&path).unwrap().contains("<title>user id</title>"));
+        assert!(generate_page(dir.path(), "/users/{user_id}").is_err());
+    }
+}
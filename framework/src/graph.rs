@@ -0,0 +1,204 @@
+use crate::components::{scan_project_components, Component};
+use crate::dependencies::{COMPONENT_REF_REGEX, TEMPLATE_REF_REGEX};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::path::Path;
+use walkdir::WalkDir;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NodeKind {
+    Page,
+    Layout,
+    Partial,
+    Component,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Node {
+    // A template's path in the project, or a component's dotted name.
+    pub id: String,
+    pub kind: NodeKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub route: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub struct Edge {
+    pub from: String,
+    pub to: String,
+}
+
+// Which pages use which layouts, partials and components, and which components nothing uses.
+#[derive(Debug, Default, Serialize)]
+pub struct DependencyGraph {
+    pub nodes: Vec<Node>,
+    pub edges: Vec<Edge>,
+    pub unused_components: Vec<String>,
+}
+
+struct Builder<'a> {
+    root: &'a Path,
+    components: Vec<Component>,
+    nodes: BTreeMap<String, Node>,
+    edges: BTreeSet<Edge>,
+    scanned: HashSet<String>,
+}
+
+impl Builder<'_> {
+    fn add_node(&mut self, id: &str, kind: NodeKind) {
+        self.nodes.entry(id.to_string()).or_insert_with(|| Node { id: id.to_string(), kind, route: None });
+    }
+
+    fn scan_template(&mut self, name: &str, source: &str) {
+        if !self.scanned.insert(name.to_string()) {
+            return;
+        }
+
+        for caps in TEMPLATE_REF_REGEX.captures_iter(source) {
+            let target = caps[1].to_string();
+            // Missing targets are `noventa check`'s business; the graph only has what exists.
+            let Ok(content) = std::fs::read_to_string(self.root.join(&target)) else {
+                continue;
+            };
+            self.add_node(&target, if target.starts_with("layouts/") { NodeKind::Layout } else { NodeKind::Partial });
+            self.edges.insert(Edge { from: name.to_string(), to: target.clone() });
+            self.scan_template(&target, &content);
+        }
+
+        for caps in COMPONENT_REF_REGEX.captures_iter(source) {
+            let id = caps[1].replace('.', "/");
+            let Some(component) = self.components.iter().find(|c| c.id == id).cloned() else {
+                continue;
+            };
+            let component_name = id.replace('/', ".");
+            self.add_node(&component_name, NodeKind::Component);
+            self.edges.insert(Edge { from: name.to_string(), to: component_name.clone() });
+            self.scan_template(&component_name, &component.template_content);
+        }
+    }
+}
+
+// Scans every page under `root` and follows its extends, includes and component calls.
+pub fn build_graph(root: &Path) -> DependencyGraph {
+    let (routes, _) = crate::routing::resolve_routes(&root.join("pages"));
+    let mut builder = Builder {
+        root,
+        components: scan_project_components(root).unwrap_or_default(),
+        nodes: BTreeMap::new(),
+        edges: BTreeSet::new(),
+        scanned: HashSet::new(),
+    };
+
+    let mut pages: Vec<_> = WalkDir::new(root.join("pages"))
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter(|entry| entry.path().is_file() && entry.path().extension().is_some_and(|ext| ext == "html"))
+        .map(|entry| entry.into_path())
+        .collect();
+    pages.sort();
+    for path in pages {
+        let Ok(source) = std::fs::read_to_string(&path) else {
+            continue;
+        };
+        let name = crate::paths::relative_name(&path, root);
+        let route = routes.iter().find(|r| r.template_path == path).map(|r| r.route_pattern.clone());
+        builder.nodes.insert(name.clone(), Node { id: name.clone(), kind: NodeKind::Page, route });
+        builder.scan_template(&name, &crate::page_meta::template_source(&source));
+    }
+
+    let mut unused_components: Vec<String> = builder
+        .components
+        .iter()
+        .map(|c| c.id.replace('/', "."))
+        .filter(|name| !builder.nodes.contains_key(name))
+        .collect();
+    unused_components.sort();
+    for name in &unused_components {
+        builder.add_node(name, NodeKind::Component);
+    }
+
+    let mut nodes: Vec<Node> = builder.nodes.into_values().collect();
+    nodes.sort_by(|a, b| (a.kind, &a.id).cmp(&(b.kind, &b.id)));
+    DependencyGraph { nodes, edges: builder.edges.into_iter().collect(), unused_components }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn quoted(id: &str) -> String {
+    format!("\"{}\"", escape(id))
+}
+
+// Graphviz: `noventa graph | dot -Tsvg > graph.svg`. Unused components are greyed out.
+pub fn to_dot(graph: &DependencyGraph) -> String {
+    let mut dot = String::from("digraph noventa {\n    rankdir=LR;\n    node [fontname=\"Helvetica\"];\n");
+    for node in &graph.nodes {
+        let label = match &node.route {
+            Some(route) => format!("\"{}\\n{}\"", escape(&node.id), escape(route)),
+            None => quoted(&node.id),
+        };
+        let style = match node.kind {
+            NodeKind::Page => "shape=box, style=filled, fillcolor=\"#dbeafe\"",
+            NodeKind::Layout => "shape=box, style=\"rounded,filled\", fillcolor=\"#ede9fe\"",
+            NodeKind::Partial => "shape=note",
+            NodeKind::Component if graph.unused_components.contains(&node.id) => "shape=ellipse, style=dashed, color=grey, fontcolor=grey",
+            NodeKind::Component => "shape=ellipse, style=filled, fillcolor=\"#dcfce7\"",
+        };
+        dot.push_str(&format!("    {} [label={}, {}];\n", quoted(&node.id), label, style));
+    }
+    for edge in &graph.edges {
+        dot.push_str(&format!("    {} -> {};\n", quoted(&edge.from), quoted(&edge.to)));
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    fn write(root: &Path, relative: &str, content: &str) {
+        let path = root.join(relative);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    #[test]
+    fn test_build_graph() {
+        let dir = tempdir().unwrap();
+        let root = dir.path();
+        write(root, "layouts/base.html", "{% include \"partials/nav.html\" %}{% block content %}{% endblock %}");
+        write(root, "partials/nav.html", "{{ component('menu') }}");
+        write(root, "pages/index.html", "{% extends \"layouts/base.html\" %}{% block content %}{{ component('cards.post') }}{% endblock %}");
+        write(root, "pages/about.html", "{% extends \"layouts/base.html\" %}{{ component('ghost') }}");
+        write(root, "components/menu/menu_template.html", "<nav></nav>");
+        write(root, "components/cards/post/post_template.html", "{{ component('cards.author') }}");
+        write(root, "components/cards/author/author_template.html", "<p></p>");
+        write(root, "components/old/old_template.html", "<p></p>");
+
+        let graph = build_graph(root);
+        let edge = |from: &str, to: &str| graph.edges.contains(&Edge { from: from.to_string(), to: to.to_string() });
+        assert!(edge("pages/index.html", "layouts/base.html"));
+        assert!(edge("pages/about.html", "layouts/base.html"));
+        assert!(edge("layouts/base.html", "partials/nav.html"));
+        assert!(edge("partials/nav.html", "menu"));
+        assert!(edge("pages/index.html", "cards.post"));
+        assert!(edge("cards.post", "cards.author"));
+        assert!(!graph.edges.iter().any(|e| e.to == "ghost"));
+        assert_eq!(graph.unused_components, vec!["old"]);
+
+        let index = graph.nodes.iter().find(|n| n.id == "pages/index.html").unwrap();
+        assert_eq!((index.kind, index.route.as_deref()), (NodeKind::Page, Some("/")));
+        assert_eq!(graph.nodes.iter().find(|n| n.id == "partials/nav.html").unwrap().kind, NodeKind::Partial);
+
+        let dot = to_dot(&graph);
+        assert!(dot.starts_with("digraph noventa {"));
+        assert!(dot.contains("\"pages/index.html\" [label=\"pages/index.html\\n/\""));
+        assert!(dot.contains("\"cards.post\" -> \"cards.author\";"));
+        assert!(dot.contains("\"old\" [label=\"old\", shape=ellipse, style=dashed"));
+    }
+}
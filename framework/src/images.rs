@@ -0,0 +1,266 @@
+use crate::actors::image_processor::{ImageError, ImageProcessingActor, OutputFormat, TransformImage};
+use crate::config::{self, ImagesConfig};
+use actix::{Addr, SyncArbiter};
+use actix_files::NamedFile;
+use actix_web::http::header;
+use actix_web::{HttpRequest, HttpResponse, web};
+use minijinja::value::{Kwargs, Value};
+use minijinja::{Error, ErrorKind};
+use path_clean::PathClean;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+
+pub const URL_PREFIX: &str = "/img";
+const DEFAULT_WIDTHS: &[u32] = &[320, 640, 960, 1280, 1920];
+const DEFAULT_MAX_WIDTH: u32 = 4096;
+const DEFAULT_QUALITY: u8 = 80;
+
+#[derive(Deserialize)]
+pub struct ImageParams {
+    w: Option<u32>,
+    format: Option<String>,
+}
+
+pub fn start_processor() -> Addr<ImageProcessingActor> {
+    SyncArbiter::start((num_cpus::get() / 4).max(1), || ImageProcessingActor)
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig, processor: Addr<ImageProcessingActor>) {
+    cfg.app_data(web::Data::new(processor))
+        .route(&format!("{}/{{path:.*}}", URL_PREFIX), web::get().to(serve_image));
+}
+
+fn static_root() -> Option<PathBuf> {
+    let static_path = config::CONFIG.static_path.as_deref()?;
+    Some(if Path::new(static_path).is_absolute() {
+        PathBuf::from(static_path).clean()
+    } else {
+        config::BASE_PATH.join(static_path).clean()
+    })
+}
+
+fn cache_dir(images: &ImagesConfig) -> PathBuf {
+    match &images.cache_dir {
+        Some(dir) if Path::new(dir).is_absolute() => PathBuf::from(dir),
+        Some(dir) => config::BASE_PATH.join(dir),
+        None => std::env::temp_dir().join("noventa-images"),
+    }
+}
+
+// The file `path` names under `root`, as long as it doesn't escape it (symlinks included).
+fn resolve_source(root: &Path, path: &str) -> Option<PathBuf> {
+    let root = root.canonicalize().ok()?;
+    let source = root.join(path.trim_start_matches('/')).canonicalize().ok()?;
+    (source.starts_with(&root) && source.is_file()).then_some(source)
+}
+
+fn check_width(images: &ImagesConfig, width: Option<u32>) -> Result<Option<u32>, String> {
+    let Some(width) = width else {
+        return Ok(None);
+    };
+    let max_width = images.max_width.unwrap_or(DEFAULT_MAX_WIDTH);
+    if width == 0 || width > max_width {
+        return Err(format!("`w` must be between 1 and {}.", max_width));
+    }
+    if let Some(widths) = &images.widths
+        && !widths.contains(&width)
+    {
+        return Err(format!("`w` must be one of {:?}.", widths));
+    }
+    Ok(Some(width))
+}
+
+// Named after everything that changes the output, so editing the source makes a new variant.
+fn variant_path(cache_dir: &Path, source: &Path, width: Option<u32>, format: OutputFormat, quality: u8) -> std::io::Result<PathBuf> {
+    let metadata = std::fs::metadata(source)?;
+    let modified = metadata
+        .modified()?
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    let mut hasher = Sha256::new();
+    hasher.update(format!(
+        "{}\0{}\0{}\0{:?}\0{}\0{}",
+        source.display(),
+        modified,
+        metadata.len(),
+        width,
+        format.extension(),
+        quality
+    ));
+    let hash = format!("{:x}", hasher.finalize());
+    Ok(cache_dir.join(format!("{}.{}", &hash[..32], format.extension())))
+}
+
+async fn serve_image(
+    req: HttpRequest,
+    path: web::Path<String>,
+    params: web::Query<ImageParams>,
+    processor: web::Data<Addr<ImageProcessingActor>>,
+) -> HttpResponse {
+    let (Some(images), Some(root)) = (&config::CONFIG.images, static_root()) else {
+        return HttpResponse::NotFound().finish();
+    };
+    let Some(source) = resolve_source(&root, &path) else {
+        return HttpResponse::NotFound().body("No such image.");
+    };
+    let width = match check_width(images, params.w) {
+        Ok(width) => width,
+        Err(problem) => return HttpResponse::BadRequest().body(problem),
+    };
+    let format = match params.format.as_deref() {
+        None => OutputFormat::for_source(&source),
+        Some(name) => match OutputFormat::parse(name) {
+            Some(format) => format,
+            None => return HttpResponse::BadRequest().body("`format` must be webp, png, jpeg or gif."),
+        },
+    };
+    let quality = images.quality.unwrap_or(DEFAULT_QUALITY);
+    let destination = match variant_path(&cache_dir(images), &source, width, format, quality) {
+        Ok(destination) => destination,
+        Err(_) => return HttpResponse::NotFound().body("No such image."),
+    };
+
+    if !destination.exists() {
+        let msg = TransformImage { source: source.clone(), destination: destination.clone(), width, format, quality };
+        match processor.send(msg).await {
+            Ok(Ok(())) => {}
+            Ok(Err(ImageError::Unreadable(e))) => {
+                log::warn!("Couldn't resize {}: {}", source.display(), e);
+                return HttpResponse::UnsupportedMediaType().body("That file can't be read as an image.");
+            }
+            Ok(Err(ImageError::Io(e))) => {
+                log::error!("Oh no! Couldn't save a resized copy of {} to {}: {}", source.display(), destination.display(), e);
+                return HttpResponse::InternalServerError().finish();
+            }
+            Err(e) => {
+                log::error!("Oh no! The image processor is unavailable: {}", e);
+                return HttpResponse::ServiceUnavailable().finish();
+            }
+        }
+    }
+
+    match NamedFile::open_async(&destination).await {
+        Ok(file) => {
+            let mut response = file.into_response(&req);
+            response
+                .headers_mut()
+                .insert(header::CACHE_CONTROL, header::HeaderValue::from_static("public, max-age=86400"));
+            response
+        }
+        Err(e) => {
+            log::error!("Oh no! Couldn't open the resized image {}: {}", destination.display(), e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+// The `/img` URL of an image under `static_path`. Paths may also start with `static_url_prefix`,
+// so `/files/cat.jpg` and `cat.jpg` name the same image.
+fn image_url(path: &str, width: u32, format: Option<OutputFormat>) -> String {
+    let static_prefix = config::CONFIG.static_url_prefix.as_deref().unwrap_or("/static");
+    let path = path.strip_prefix(static_prefix).filter(|rest| rest.starts_with('/')).unwrap_or(path);
+    let path = crate::object_storage::uri_encode(path.trim_start_matches('/'), false);
+    match format {
+        Some(format) => format!("{}/{}?w={}&format={}", URL_PREFIX, path, width, format.extension()),
+        None => format!("{}/{}?w={}", URL_PREFIX, path, width),
+    }
+}
+
+fn srcset_for(images: &ImagesConfig, path: &str, widths: Option<Vec<u32>>, format: Option<OutputFormat>) -> String {
+    let max_width = images.max_width.unwrap_or(DEFAULT_MAX_WIDTH);
+    let mut widths = widths
+        .or_else(|| images.widths.clone())
+        .unwrap_or_else(|| DEFAULT_WIDTHS.to_vec());
+    widths.retain(|width| *width > 0 && *width <= max_width);
+    widths.sort_unstable();
+    widths.dedup();
+    widths
+        .iter()
+        .map(|width| format!("{} {}w", image_url(path, *width, format), width))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+// `{{ srcset("photos/cat.jpg", widths=[320, 640], format="webp") }}` for an <img srcset>.
+// The URLs are already percent-encoded, so they don't need HTML escaping.
+pub fn srcset(path: String, kwargs: Kwargs) -> Result<Value, Error> {
+    let widths: Option<Vec<u32>> = kwargs.get("widths")?;
+    let format: Option<String> = kwargs.get("format")?;
+    kwargs.assert_all_used()?;
+    let Some(images) = &config::CONFIG.images else {
+        return Err(Error::new(
+            ErrorKind::InvalidOperation,
+            "srcset() needs an `images` section in config.yaml.",
+        ));
+    };
+    let format = match format.as_deref() {
+        Some(name) => Some(OutputFormat::parse(name).ok_or_else(|| {
+            Error::new(ErrorKind::InvalidOperation, format!("srcset() can't make '{}' images; use webp, png, jpeg or gif.", name))
+        })?),
+        None => None,
+    };
+    Ok(Value::from_safe_string(srcset_for(images, &path, widths, format)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_resolve_source_stays_inside_root() {
+        let dir = tempdir().unwrap();
+        let root = dir.path().join("files");
+        std::fs::create_dir_all(root.join("photos")).unwrap();
+        std::fs::write(root.join("photos/cat.png"), b"png").unwrap();
+        std::fs::write(dir.path().join("secret.png"), b"png").unwrap();
+
+        assert!(resolve_source(&root, "photos/cat.png").is_some());
+        assert!(resolve_source(&root, "/photos/cat.png").is_some());
+        assert!(resolve_source(&root, "../secret.png").is_none());
+        assert!(resolve_source(&root, "photos").is_none());
+        assert!(resolve_source(&root, "photos/dog.png").is_none());
+    }
+
+    #[test]
+    fn test_check_width() {
+        let mut images = ImagesConfig { max_width: Some(2000), ..Default::default() };
+        assert_eq!(check_width(&images, None), Ok(None));
+        assert_eq!(check_width(&images, Some(640)), Ok(Some(640)));
+        assert!(check_width(&images, Some(0)).is_err());
+        assert!(check_width(&images, Some(2001)).is_err());
+
+        images.widths = Some(vec![320, 640]);
+        assert!(check_width(&images, Some(500)).is_err());
+        assert_eq!(check_width(&images, Some(320)), Ok(Some(320)));
+    }
+
+    #[test]
+    fn test_variant_path_changes_with_the_request_and_source() {
+        let dir = tempdir().unwrap();
+        let source = dir.path().join("cat.png");
+        std::fs::write(&source, b"one").unwrap();
+        let cache = dir.path().join("cache");
+
+        let webp = variant_path(&cache, &source, Some(320), OutputFormat::Webp, 80).unwrap();
+        assert!(webp.starts_with(&cache));
+        assert_eq!(webp.extension().unwrap(), "webp");
+        assert_eq!(webp, variant_path(&cache, &source, Some(320), OutputFormat::Webp, 80).unwrap());
+        assert_ne!(webp, variant_path(&cache, &source, Some(640), OutputFormat::Webp, 80).unwrap());
+
+        std::fs::write(&source, b"edited").unwrap();
+        assert_ne!(webp, variant_path(&cache, &source, Some(320), OutputFormat::Webp, 80).unwrap());
+    }
+
+    #[test]
+    fn test_srcset_for() {
+        let images = ImagesConfig { widths: Some(vec![640, 320]), max_width: Some(1000), ..Default::default() };
+        assert_eq!(
+            srcset_for(&images, "photos/my cat.jpg", None, Some(OutputFormat::Webp)),
+            "/img/photos/my%20cat.jpg?w=320&format=webp 320w, /img/photos/my%20cat.jpg?w=640&format=webp 640w"
+        );
+        assert_eq!(srcset_for(&images, "/cat.jpg", Some(vec![200, 5000]), None), "/img/cat.jpg?w=200 200w");
+    }
+}
@@ -0,0 +1,227 @@
+use crate::actors::interpreter::{CallTemplateHelper, ExecuteFunction, PythonInterpreterActor, RunContextProcessors};
+use actix::prelude::*;
+use lazy_static::lazy_static;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+// A render's calls to the Python interpreters go through `send`. The SyncArbiter's mailbox has no
+// bound, so when the interpreters fall behind, calls would pile up until every page times out.
+// Instead, once `core_allocation.python_queue_limit` calls are waiting, new ones are turned away at
+// once, and the load shedder starts refusing requests before the queue gets there.
+
+// How many calls may wait for each interpreter when the limit isn't set.
+pub const DEFAULT_LIMIT_PER_THREAD: usize = 64;
+// How full the queue gets before the load shedder steps in.
+pub const SATURATION_THRESHOLD: f64 = 0.75;
+const WAIT_WINDOW: Duration = Duration::from_secs(30);
+const MAX_WAITS: usize = 10_000;
+
+struct Queue {
+    limit: AtomicUsize,
+    depth: AtomicUsize,
+    rejected: AtomicU64,
+    // Set while calls are being turned away, so that's logged once rather than for every call.
+    overflowing: AtomicBool,
+    waits: Mutex<VecDeque<(Instant, f64)>>,
+}
+
+impl Queue {
+    fn new(limit: usize) -> Self {
+        Self {
+            limit: AtomicUsize::new(limit),
+            depth: AtomicUsize::new(0),
+            rejected: AtomicU64::new(0),
+            overflowing: AtomicBool::new(false),
+            waits: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    fn acquire(&'static self) -> Option<Slot> {
+        let limit = self.limit.load(Ordering::Relaxed);
+        match self.depth.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |depth| (depth < limit).then_some(depth + 1)) {
+            Ok(_) => Some(Slot(self)),
+            Err(depth) => {
+                self.rejected.fetch_add(1, Ordering::Relaxed);
+                if !self.overflowing.swap(true, Ordering::Relaxed) {
+                    log::warn!("Hold on tight! The Python interpreters' queue is full ({} waiting), so new calls are being turned away. Raise `core_allocation.python_threads` or `python_queue_limit` if this keeps happening.", depth);
+                }
+                None
+            }
+        }
+    }
+
+    fn record_wait(&self, wait: Duration) {
+        let now = Instant::now();
+        let mut waits = self.waits.lock().unwrap();
+        while waits.front().is_some_and(|(at, _)| now.duration_since(*at) >= WAIT_WINDOW) || waits.len() >= MAX_WAITS {
+            waits.pop_front();
+        }
+        waits.push_back((now, wait.as_secs_f64() * 1000.0));
+    }
+
+    fn saturation(&self) -> f64 {
+        match self.limit.load(Ordering::Relaxed) {
+            usize::MAX => 0.0,
+            limit => self.depth.load(Ordering::Relaxed) as f64 / limit.max(1) as f64,
+        }
+    }
+
+    fn stats(&self) -> InterpreterQueueStats {
+        let now = Instant::now();
+        let mut waits: Vec<f64> = self
+            .waits
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(at, _)| now.duration_since(*at) < WAIT_WINDOW)
+            .map(|(_, wait)| *wait)
+            .collect();
+        waits.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let (wait_p95_ms, wait_mean_ms) = if waits.is_empty() {
+            (0.0, 0.0)
+        } else {
+            let p95_index = (waits.len() as f64 * 0.95).floor() as usize;
+            (waits[p95_index.min(waits.len() - 1)], waits.iter().sum::<f64>() / waits.len() as f64)
+        };
+        let limit = self.limit.load(Ordering::Relaxed);
+        InterpreterQueueStats {
+            depth: self.depth.load(Ordering::Relaxed),
+            limit: (limit != usize::MAX).then_some(limit),
+            saturation: self.saturation(),
+            rejected: self.rejected.load(Ordering::Relaxed),
+            wait_p95_ms,
+            wait_mean_ms,
+        }
+    }
+}
+
+lazy_static! {
+    static ref QUEUE: Queue = Queue::new(usize::MAX);
+}
+
+// A place in the queue, given back when an interpreter picks the call up (or it's dropped unsent).
+struct Slot(&'static Queue);
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        if self.0.depth.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.0.overflowing.store(false, Ordering::Relaxed);
+        }
+    }
+}
+
+// The messages a render sends the interpreters.
+pub trait Queueable: Message + Send + 'static {
+    fn answer(self, interpreter: &mut PythonInterpreterActor, ctx: &mut SyncContext<PythonInterpreterActor>) -> Self::Result;
+}
+
+macro_rules! queueable {
+    ($($message:ty),*) => {
+        $(impl Queueable for $message {
+            fn answer(self, interpreter: &mut PythonInterpreterActor, ctx: &mut SyncContext<PythonInterpreterActor>) -> Self::Result {
+                Handler::handle(interpreter, self, ctx)
+            }
+        })*
+    };
+}
+
+queueable!(ExecuteFunction, CallTemplateHelper, RunContextProcessors);
+
+// One of them, with when it started waiting.
+pub struct Queued<M> {
+    message: M,
+    enqueued: Instant,
+    slot: Slot,
+}
+
+impl<M: Message> Message for Queued<M> {
+    type Result = M::Result;
+}
+
+impl<M> Handler<Queued<M>> for PythonInterpreterActor
+where
+    M: Queueable,
+    M::Result: Send,
+{
+    type Result = MessageResult<Queued<M>>;
+
+    fn handle(&mut self, msg: Queued<M>, ctx: &mut Self::Context) -> Self::Result {
+        let Queued { message, enqueued, slot } = msg;
+        drop(slot);
+        QUEUE.record_wait(enqueued.elapsed());
+        MessageResult(message.answer(self, ctx))
+    }
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct InterpreterQueueStats {
+    // Calls waiting for an interpreter right now.
+    pub depth: usize,
+    pub limit: Option<usize>,
+    pub saturation: f64,
+    // Calls turned away because the queue was full.
+    pub rejected: u64,
+    // How long calls waited for an interpreter over the last 30 seconds.
+    pub wait_p95_ms: f64,
+    pub wait_mean_ms: f64,
+}
+
+pub fn set_limit(limit: usize) {
+    QUEUE.limit.store(limit, Ordering::Relaxed);
+}
+
+// How full the queue is, from 0 to 1; always 0 without a limit.
+pub fn saturation() -> f64 {
+    QUEUE.saturation()
+}
+
+pub fn stats() -> InterpreterQueueStats {
+    QUEUE.stats()
+}
+
+// Sends a message to the interpreters, or answers with `MailboxError::Timeout` right away if too many
+// calls are already waiting for one.
+pub fn send<M>(interpreter: &Addr<PythonInterpreterActor>, message: M) -> impl Future<Output = Result<M::Result, MailboxError>> + use<M>
+where
+    M: Queueable,
+    M::Result: Send,
+{
+    let request = QUEUE
+        .acquire()
+        .map(|slot| interpreter.send(Queued { message, enqueued: Instant::now(), slot }));
+    async move {
+        match request {
+            Some(request) => request.await,
+            None => Err(MailboxError::Timeout),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_queue_limit() {
+        let queue: &'static Queue = Box::leak(Box::new(Queue::new(2)));
+        let first = queue.acquire().unwrap();
+        let second = queue.acquire().unwrap();
+        assert!(queue.acquire().is_none());
+        assert_eq!(queue.saturation(), 1.0);
+
+        drop(first);
+        queue.record_wait(Duration::from_millis(4));
+        queue.record_wait(Duration::from_millis(8));
+        let stats = queue.stats();
+        assert_eq!((stats.depth, stats.limit, stats.rejected), (1, Some(2), 1));
+        assert_eq!((stats.wait_mean_ms, stats.wait_p95_ms), (6.0, 8.0));
+        assert!(queue.acquire().is_some());
+
+        drop(second);
+        assert_eq!(queue.stats().depth, 0);
+        assert_eq!(Queue::new(usize::MAX).saturation(), 0.0);
+    }
+}
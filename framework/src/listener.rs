@@ -0,0 +1,167 @@
+use std::io;
+use std::path::PathBuf;
+
+// systemd hands over sockets starting at this file descriptor (SD_LISTEN_FDS_START).
+#[cfg(unix)]
+const LISTEN_FDS_START: i32 = 3;
+// Lets nginx/caddy (same group) connect without opening the socket to everyone.
+pub const DEFAULT_SOCKET_MODE: u32 = 0o660;
+
+// A socket the server was handed or created itself, instead of binding `server_address:port`.
+pub enum Listener {
+    Tcp(std::net::TcpListener),
+    #[cfg(unix)]
+    Unix(std::os::unix::net::UnixListener, String),
+}
+
+impl Listener {
+    // How the address is shown in the banner.
+    pub fn describe(&self) -> String {
+        match self {
+            Listener::Tcp(listener) => listener
+                .local_addr()
+                .map(|addr| format!("http://{}", addr))
+                .unwrap_or_else(|_| "inherited TCP socket".to_string()),
+            #[cfg(unix)]
+            Listener::Unix(_, name) => name.clone(),
+        }
+    }
+}
+
+// `bind: unix:/run/noventa.sock` -> `/run/noventa.sock`.
+pub fn parse_bind(bind: &str) -> Result<PathBuf, String> {
+    match bind.strip_prefix("unix:") {
+        Some(path) if !path.trim().is_empty() => Ok(PathBuf::from(path.trim())),
+        Some(_) => Err("`bind` needs a socket path after `unix:`, e.g. `unix:/run/noventa.sock`.".to_string()),
+        None => Err(format!(
+            "`bind` only supports unix sockets like `unix:/run/noventa.sock`, but it's '{}'. Use `server_address` and `port` for TCP.",
+            bind
+        )),
+    }
+}
+
+// Socket permissions are written the way chmod takes them, e.g. "0660".
+pub fn parse_socket_mode(mode: &str) -> Result<u32, String> {
+    u32::from_str_radix(mode.trim_start_matches("0o"), 8)
+        .ok()
+        .filter(|mode| *mode <= 0o777)
+        .ok_or_else(|| format!("`unix_socket_mode` must be an octal permission like \"0660\", but it's '{}'.", mode))
+}
+
+// The socket systemd passed us through socket activation, if any.
+#[cfg(unix)]
+pub fn systemd_listener() -> io::Result<Option<Listener>> {
+    use std::os::unix::io::{FromRawFd, IntoRawFd};
+
+    let for_us = std::env::var("LISTEN_PID").ok().and_then(|pid| pid.parse::<u32>().ok()) == Some(std::process::id());
+    let count = std::env::var("LISTEN_FDS").ok().and_then(|fds| fds.parse::<i32>().ok()).unwrap_or(0);
+    if !for_us || count < 1 {
+        return Ok(None);
+    }
+    if count > 1 {
+        log::warn!("systemd passed {} sockets; only the first one is used.", count);
+    }
+
+    // Safety: LISTEN_PID matches this process, so systemd guarantees the descriptor is open and ours.
+    let unix = unsafe { std::os::unix::net::UnixListener::from_raw_fd(LISTEN_FDS_START) };
+    if let Ok(addr) = unix.local_addr() {
+        let name = match addr.as_pathname() {
+            Some(path) => format!("unix:{}", path.display()),
+            None => "inherited unix socket".to_string(),
+        };
+        return Ok(Some(Listener::Unix(unix, name)));
+    }
+    let tcp = unsafe { std::net::TcpListener::from_raw_fd(unix.into_raw_fd()) };
+    tcp.local_addr().map_err(|e| {
+        io::Error::new(e.kind(), format!("the socket passed by systemd is neither TCP nor a unix socket: {}", e))
+    })?;
+    Ok(Some(Listener::Tcp(tcp)))
+}
+
+#[cfg(not(unix))]
+pub fn systemd_listener() -> io::Result<Option<Listener>> {
+    Ok(None)
+}
+
+// Creates the unix socket at `path`, replacing a stale one left behind by a previous run.
+#[cfg(unix)]
+pub fn unix_listener(path: &std::path::Path, mode: u32) -> io::Result<Listener> {
+    use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+    use std::os::unix::net::{UnixListener, UnixStream};
+
+    if let Ok(metadata) = std::fs::symlink_metadata(path) {
+        if !metadata.file_type().is_socket() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} exists and isn't a socket", path.display()),
+            ));
+        }
+        if UnixStream::connect(path).is_ok() {
+            return Err(io::Error::new(
+                io::ErrorKind::AddrInUse,
+                format!("another server is listening on {}", path.display()),
+            ));
+        }
+        std::fs::remove_file(path)?;
+    }
+
+    let listener = UnixListener::bind(path)?;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))?;
+    Ok(Listener::Unix(listener, format!("unix:{}", path.display())))
+}
+
+#[cfg(not(unix))]
+pub fn unix_listener(path: &std::path::Path, _mode: u32) -> io::Result<Listener> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!("unix sockets aren't available on this platform ({})", path.display()),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_bind() {
+        assert_eq!(parse_bind("unix:/run/noventa.sock").unwrap(), PathBuf::from("/run/noventa.sock"));
+        assert!(parse_bind("unix:").is_err());
+        assert!(parse_bind("0.0.0.0:8080").unwrap_err().contains("server_address"));
+    }
+
+    #[test]
+    fn test_parse_socket_mode() {
+        assert_eq!(parse_socket_mode("0660").unwrap(), 0o660);
+        assert_eq!(parse_socket_mode("0o600").unwrap(), 0o600);
+        assert!(parse_socket_mode("rw-rw----").is_err());
+        assert!(parse_socket_mode("7777").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_unix_listener_replaces_stale_socket() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("noventa.sock");
+
+        // A socket nobody listens on any more, like the one a crashed server leaves behind.
+        drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+        let listener = unix_listener(&path, 0o600).unwrap();
+        assert_eq!(listener.describe(), format!("unix:{}", path.display()));
+        assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+
+        // The live one is left alone.
+        assert_eq!(unix_listener(&path, 0o600).err().unwrap().kind(), io::ErrorKind::AddrInUse);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_unix_listener_refuses_regular_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.yaml");
+        std::fs::write(&path, "port: 8080").unwrap();
+        assert!(unix_listener(&path, DEFAULT_SOCKET_MODE).is_err());
+        assert!(path.exists());
+    }
+}
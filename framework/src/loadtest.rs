@@ -0,0 +1,208 @@
+use crate::recording::{ParamKind, RecordedRequest};
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+pub struct ReplayOptions {
+    // e.g. `http://127.0.0.1:8080`.
+    pub target: String,
+    // 2.0 replays twice as fast, with half the think time.
+    pub speed: f64,
+    // Values for route and query parameters, instead of made-up ones.
+    pub params: HashMap<String, String>,
+}
+
+#[derive(Default, Debug)]
+pub struct ReplayReport {
+    pub sent: usize,
+    // Recorded POSTs and the like, which are left out: their bodies weren't recorded.
+    pub skipped: usize,
+    pub failed: usize,
+    pub statuses: BTreeMap<u16, usize>,
+    pub latencies_ms: Vec<u64>,
+    pub elapsed: Duration,
+}
+
+impl ReplayReport {
+    pub fn percentile(&self, percent: f64) -> Option<u64> {
+        let mut latencies = self.latencies_ms.clone();
+        latencies.sort_unstable();
+        let index = ((latencies.len() as f64 * percent / 100.0).ceil() as usize).checked_sub(1)?;
+        latencies.get(index.min(latencies.len() - 1)).copied()
+    }
+
+    fn merge(&mut self, other: ReplayReport) {
+        self.sent += other.sent;
+        self.skipped += other.skipped;
+        self.failed += other.failed;
+        for (status, count) in other.statuses {
+            *self.statuses.entry(status).or_default() += count;
+        }
+        self.latencies_ms.extend(other.latencies_ms);
+    }
+}
+
+pub fn read_recording(path: &Path) -> Result<Vec<RecordedRequest>, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("couldn't read {}: {}", path.display(), e))?;
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| serde_json::from_str(line).map_err(|e| format!("line {} of {} isn't a recorded request: {}", i + 1, path.display(), e)))
+        .collect()
+}
+
+fn made_up_value(kind: ParamKind) -> String {
+    match kind {
+        ParamKind::Int => rand::random::<u16>().to_string(),
+        ParamKind::Uuid => uuid::Uuid::new_v4().to_string(),
+        ParamKind::Text => "loadtest".to_string(),
+    }
+}
+
+fn url_for(request: &RecordedRequest, options: &ReplayOptions) -> String {
+    let value = |name: &str, kind: ParamKind| options.params.get(name).cloned().unwrap_or_else(|| made_up_value(kind));
+    let mut path = request.route.clone();
+    for (name, kind) in &request.params {
+        path = path.replace(&format!("{{{}}}", name), &value(name, *kind));
+    }
+    let query: Vec<(String, String)> = request.query.iter().map(|(name, kind)| (name.clone(), value(name, *kind))).collect();
+    let mut url = format!("{}{}", options.target.trim_end_matches('/'), path);
+    if !query.is_empty() {
+        url.push('?');
+        url.push_str(&serde_urlencoded::to_string(query).unwrap_or_default());
+    }
+    url
+}
+
+// Each client's requests, in the order they were made.
+fn by_client(requests: Vec<RecordedRequest>) -> Vec<Vec<RecordedRequest>> {
+    let mut order = Vec::new();
+    let mut clients: HashMap<String, Vec<RecordedRequest>> = HashMap::new();
+    for request in requests {
+        if !clients.contains_key(&request.client) {
+            order.push(request.client.clone());
+        }
+        clients.entry(request.client.clone()).or_default().push(request);
+    }
+    order.into_iter().filter_map(|client| clients.remove(&client)).collect()
+}
+
+async fn replay_client(client: &reqwest::Client, requests: Vec<RecordedRequest>, options: &ReplayOptions) -> ReplayReport {
+    let mut report = ReplayReport::default();
+    for request in requests {
+        if request.method != "GET" && request.method != "HEAD" {
+            report.skipped += 1;
+            continue;
+        }
+        let think_time = Duration::from_millis(request.think_time_ms).div_f64(options.speed);
+        actix_rt::time::sleep(think_time).await;
+        let started = Instant::now();
+        let method = reqwest::Method::from_bytes(request.method.as_bytes()).unwrap_or(reqwest::Method::GET);
+        match client.request(method, url_for(&request, options)).send().await {
+            Ok(response) => {
+                let status = response.status().as_u16();
+                // Reading the body is part of what the server is asked to do.
+                let _ = response.bytes().await;
+                report.latencies_ms.push(started.elapsed().as_millis() as u64);
+                *report.statuses.entry(status).or_default() += 1;
+                report.sent += 1;
+            }
+            Err(_) => report.failed += 1,
+        }
+    }
+    report
+}
+
+// Plays the recorded clients back against `target` all at once, each with its own think times,
+// so the server sees the same mix and pacing of traffic that was recorded.
+pub async fn replay(requests: Vec<RecordedRequest>, options: &ReplayOptions) -> Result<ReplayReport, String> {
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(60))
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .map_err(|e| e.to_string())?;
+    let started = Instant::now();
+    let clients = by_client(requests);
+    let reports = futures::future::join_all(clients.into_iter().map(|requests| replay_client(&client, requests, options))).await;
+    let mut report = ReplayReport::default();
+    for client_report in reports {
+        report.merge(client_report);
+    }
+    report.elapsed = started.elapsed();
+    Ok(report)
+}
+
+pub fn print_report(report: &ReplayReport) {
+    let seconds = report.elapsed.as_secs_f64();
+    println!("✨ Replayed {} requests in {:.1}s ({:.1} per second).", report.sent, seconds, report.sent as f64 / seconds.max(0.001));
+    for (status, count) in &report.statuses {
+        println!("   {}: {}", status, count);
+    }
+    if let (Some(p50), Some(p95), Some(p99)) = (report.percentile(50.0), report.percentile(95.0), report.percentile(99.0)) {
+        println!("   Latency: p50 {} ms, p95 {} ms, p99 {} ms", p50, p95, p99);
+    }
+    if report.failed > 0 {
+        println!("   {} requests got no answer (connection errors or timeouts).", report.failed);
+    }
+    if report.skipped > 0 {
+        println!("   {} requests were skipped: only GET and HEAD are replayed, since bodies aren't recorded.", report.skipped);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(client: &str, route: &str) -> RecordedRequest {
+        RecordedRequest {
+            client: client.to_string(),
+            think_time_ms: 0,
+            method: "GET".to_string(),
+            route: route.to_string(),
+            params: BTreeMap::new(),
+            query: BTreeMap::new(),
+            status: 200,
+            duration_ms: 5,
+        }
+    }
+
+    #[test]
+    fn test_url_for() {
+        let mut recorded = request("a", "/users/{id}/posts/{slug}");
+        recorded.params.insert("id".to_string(), ParamKind::Int);
+        recorded.params.insert("slug".to_string(), ParamKind::Text);
+        recorded.query.insert("page".to_string(), ParamKind::Int);
+        let options = ReplayOptions {
+            target: "http://127.0.0.1:8080/".to_string(),
+            speed: 1.0,
+            params: HashMap::from([("id".to_string(), "7".to_string()), ("page".to_string(), "2".to_string())]),
+        };
+        assert_eq!(url_for(&recorded, &options), "http://127.0.0.1:8080/users/7/posts/loadtest?page=2");
+    }
+
+    #[test]
+    fn test_by_client_keeps_each_clients_order() {
+        let clients = by_client(vec![request("a", "/1"), request("b", "/2"), request("a", "/3")]);
+        let routes: Vec<Vec<&str>> = clients.iter().map(|requests| requests.iter().map(|r| r.route.as_str()).collect()).collect();
+        assert_eq!(routes, vec![vec!["/1", "/3"], vec!["/2"]]);
+    }
+
+    #[test]
+    fn test_percentile() {
+        let report = ReplayReport { latencies_ms: (1..=100).rev().collect(), ..Default::default() };
+        assert_eq!(report.percentile(50.0), Some(50));
+        assert_eq!(report.percentile(99.0), Some(99));
+        assert_eq!(ReplayReport::default().percentile(50.0), None);
+    }
+
+    #[test]
+    fn test_read_recording() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("requests.jsonl");
+        let line = serde_json::to_string(&request("a", "/")).unwrap();
+        std::fs::write(&path, format!("{}\n\n{}\n", line, line)).unwrap();
+        assert_eq!(read_recording(&path).unwrap().len(), 2);
+        std::fs::write(&path, "nope\n").unwrap();
+        assert!(read_recording(&path).unwrap_err().contains("line 1"));
+    }
+}
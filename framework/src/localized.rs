@@ -0,0 +1,147 @@
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{self, HeaderValue};
+use actix_web::middleware::Next;
+use std::path::{Path, PathBuf};
+
+// `about.de.html` next to `about.html` is the German about page, and `hero.pt-BR.png` next to
+// `hero.png` the Brazilian hero image. Visitors who prefer those locales get them; everyone else
+// gets the original.
+
+// A locale as it can appear in a file name: `de`, `pt-BR`, `zh-Hant`. Two-letter languages only,
+// so `app.min.js` and `app.js.map` aren't taken for anything.
+fn is_locale(tag: &str) -> bool {
+    let mut parts = tag.split('-');
+    let language = parts.next().unwrap_or_default();
+    language.len() == 2
+        && language.chars().all(|c| c.is_ascii_lowercase())
+        && parts.all(|part| (2..=8).contains(&part.len()) && part.chars().all(|c| c.is_ascii_alphanumeric()))
+}
+
+// `pages/about.html` -> ("pages/about", ".html").
+fn split_extension(path: &str) -> (&str, &str) {
+    let name_start = path.rfind('/').map_or(0, |slash| slash + 1);
+    match path[name_start..].rfind('.') {
+        Some(dot) if dot > 0 => path.split_at(name_start + dot),
+        _ => (path, ""),
+    }
+}
+
+// The locales there's a variant of `path` for, sorted.
+pub fn variants(path: &Path) -> Vec<String> {
+    let (Some(dir), Some(file_name)) = (path.parent(), path.file_name().and_then(|name| name.to_str())) else {
+        return Vec::new();
+    };
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let (stem, extension) = split_extension(file_name);
+    let mut locales: Vec<String> = entries
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            let locale = name.strip_prefix(stem)?.strip_prefix('.')?.strip_suffix(extension)?;
+            (is_locale(locale) && entry.path().is_file()).then(|| locale.to_string())
+        })
+        .collect();
+    locales.sort();
+    locales
+}
+
+// Which of the `available` variants suits `locale`: `pt-BR` if there is one, else `pt`.
+pub fn pick<'a>(available: &'a [String], locale: &str) -> Option<&'a str> {
+    let language = locale.split('-').next().unwrap_or_default();
+    available
+        .iter()
+        .find(|variant| variant.eq_ignore_ascii_case(locale))
+        .or_else(|| available.iter().find(|variant| variant.eq_ignore_ascii_case(language)))
+        .map(String::as_str)
+}
+
+// `pages/about.html` in `de` -> `pages/about.de.html`.
+pub fn variant_path(path: &str, locale: &str) -> String {
+    let (stem, extension) = split_extension(path);
+    format!("{}.{}{}", stem, locale, extension)
+}
+
+// A page that stands in for another one, and so gets no route of its own.
+pub fn is_variant(path: &Path) -> bool {
+    let Some(file_name) = path.file_name().and_then(|name| name.to_str()) else {
+        return false;
+    };
+    let (stem, extension) = split_extension(file_name);
+    let Some((base, locale)) = stem.rsplit_once('.') else {
+        return false;
+    };
+    is_locale(locale) && path.with_file_name(format!("{}{}", base, extension)).is_file()
+}
+
+fn static_dir() -> Option<PathBuf> {
+    let static_path = crate::config::CONFIG.static_path.as_deref()?;
+    Some(if Path::new(static_path).is_absolute() { PathBuf::from(static_path) } else { crate::config::BASE_PATH.join(static_path) })
+}
+
+// Serves `static/img/hero.de.png` for `/static/img/hero.png` to visitors who prefer German.
+pub async fn localized_static(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let prefix = crate::config::CONFIG.static_url_prefix.as_deref().unwrap_or("/static");
+    let relative = req.path().strip_prefix(prefix).and_then(|path| path.strip_prefix('/')).map(str::to_string);
+    let available = match (static_dir(), relative.as_deref()) {
+        (Some(dir), Some(relative)) if !relative.starts_with("noventa-static/") && !relative.contains("..") => variants(&dir.join(relative)),
+        _ => Vec::new(),
+    };
+    if available.is_empty() {
+        return next.call(req).await;
+    }
+
+    let accept_languages: Vec<String> = req
+        .headers()
+        .get_all(header::ACCEPT_LANGUAGE)
+        .flat_map(|value| value.to_str().unwrap_or("").split(','))
+        .map(|language| language.trim().to_string())
+        .collect();
+    if let (Some(relative), Some(locale)) = (&relative, pick(&available, &crate::template_filters::request_locale(&accept_languages))) {
+        let query = req.uri().query().map(|query| format!("?{}", query)).unwrap_or_default();
+        if let Ok(uri) = format!("{}/{}{}", prefix, variant_path(relative, locale), query).parse::<actix_web::http::Uri>() {
+            req.match_info_mut().get_mut().update(&uri);
+            req.head_mut().uri = uri;
+        }
+    }
+    let mut res = next.call(req).await?;
+    // The same URL is a different file depending on the language.
+    res.headers_mut().append(header::VARY, HeaderValue::from_static("Accept-Language"));
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_variants_and_pick() {
+        let dir = tempdir().unwrap();
+        for name in ["about.html", "about.de.html", "about.pt-BR.html", "about.min.css", "about-us.de.html", "jquery.min.js", "jquery.js"] {
+            fs::write(dir.path().join(name), "").unwrap();
+        }
+        let about = dir.path().join("about.html");
+        assert_eq!(variants(&about), vec!["de", "pt-BR"]);
+        assert!(variants(&dir.path().join("jquery.js")).is_empty());
+
+        let available = variants(&about);
+        assert_eq!(pick(&available, "de-AT"), Some("de"));
+        assert_eq!(pick(&available, "pt-br"), Some("pt-BR"));
+        assert_eq!(pick(&available, "pt-PT"), None);
+        assert_eq!(pick(&available, "en-US"), None);
+
+        assert_eq!(variant_path("pages/about.html", "de"), "pages/about.de.html");
+        assert_eq!(variant_path("img/v1.2/hero", "de"), "img/v1.2/hero.de");
+        assert!(is_variant(&dir.path().join("about.de.html")));
+        assert!(!is_variant(&about));
+        assert!(!is_variant(&dir.path().join("about-us.de.html")));
+        assert!(!is_variant(&dir.path().join("jquery.min.js")));
+    }
+}
@@ -25,10 +25,27 @@ pub fn init_logger(log_level: &str) {
     // Filter out logs from actix_server and actix_web
     builder.filter(Some("actix_server"), log::LevelFilter::Warn);
     builder.filter(Some("actix_web"), log::LevelFilter::Warn);
+    // `access_log.enabled` decides whether there's an access log, not `log_level`.
+    builder.filter(Some("noventa::access"), log::LevelFilter::Info);
 
     builder.init();
 }
 
+// Maps a browser console level ("log", "warn", ...) to the matching log level.
+fn browser_log_level(level: &str) -> Level {
+    match level {
+        "error" => Level::Error,
+        "warn" => Level::Warn,
+        "debug" | "trace" => Level::Debug,
+        _ => Level::Info,
+    }
+}
+
+// Prints a message forwarded by devws.js so browser output shows up next to the server logs.
+pub fn log_browser_message(level: &str, message: &str) {
+    log::log!(browser_log_level(level), "{} {}", "[browser]".truecolor(255, 64, 129), message);
+}
+
 fn format_log(buf: &mut Formatter, record: &Record) -> std::io::Result<()> {
     let level_style = match record.level() {
         Level::Error => "ERROR".truecolor(255, 0, 0), // Bright Red for high visibility
@@ -48,7 +65,7 @@ fn format_log(buf: &mut Formatter, record: &Record) -> std::io::Result<()> {
     writeln!(buf, "{}", message)
 }
 
-pub fn print_banner(host: &str, port: u16, dev_mode: bool) {
+pub fn print_banner(address: &str, dev_mode: bool) {
     // Define the gradient colors based on the image
     let pink = (255, 64, 129);    // Vibrant Pink
     let mid_pink = (224, 80, 149);
@@ -101,7 +118,7 @@ pub fn print_banner(host: &str, port: u16, dev_mode: bool) {
         }
     }
 
-    println!("{}", format!("   - Address: http://{}:{}", host, port).cyan());
+    println!("{}", format!("   - Address: {}", address).cyan());
     println!("{}", "   - Happy coding!".cyan());
     println!("{}", border.purple());
 }
@@ -114,8 +131,8 @@ mod tests {
     fn test_print_banner() {
         // Test that print_banner doesn't panic and produces output
         // We can't easily capture stdout in tests, so we just ensure it runs
-        print_banner("localhost", 3000, false);
-        print_banner("127.0.0.1", 8080, true);
+        print_banner("http://localhost:3000", false);
+        print_banner("unix:/run/noventa.sock", true);
         // If we get here without panicking, the test passes
     }
 
@@ -136,6 +153,16 @@ mod tests {
         assert!(INSPIRING_PHRASES.iter().any(|p| p.contains("web")));
     }
 
+    #[test]
+    fn test_browser_log_level() {
+        assert_eq!(browser_log_level("error"), Level::Error);
+        assert_eq!(browser_log_level("warn"), Level::Warn);
+        assert_eq!(browser_log_level("debug"), Level::Debug);
+        assert_eq!(browser_log_level("log"), Level::Info);
+        assert_eq!(browser_log_level("info"), Level::Info);
+        assert_eq!(browser_log_level("something-else"), Level::Info);
+    }
+
     #[test]
     fn test_init_logger() {
         // Test that init_logger doesn't panic with valid log levels
@@ -1,6 +1,8 @@
+use crate::lsp_index::WorkspaceIndex;
 use actix::prelude::*;
 use dashmap::DashMap;
 use lazy_static::lazy_static;
+use regex::Regex;
 use tower_lsp::jsonrpc::Result;
 use tower_lsp::lsp_types::*;
 use tower_lsp::{Client, LanguageServer, LspService, Server};
@@ -8,8 +10,15 @@ use tower_lsp::{Client, LanguageServer, LspService, Server};
 // --- Global State ---
 
 lazy_static! {
-    static ref FILES_WITH_DIAGNOSTICS: DashMap<Url, ()> = DashMap::new();
+    // The last runtime error in each file, until it's edited.
+    static ref FILES_WITH_DIAGNOSTICS: DashMap<Url, Diagnostic> = DashMap::new();
+    // What `noventa check` finds in each open file.
+    static ref CHECK_DIAGNOSTICS: DashMap<Url, Vec<Diagnostic>> = DashMap::new();
+    static ref FILES_CHANGED: tokio::sync::Notify = tokio::sync::Notify::new();
+    // The text of the files open in the editor, which completions are worked out from.
+    static ref DOCUMENTS: DashMap<Url, String> = DashMap::new();
 }
+use std::net::{IpAddr, Ipv4Addr};
 use std::sync::atomic::{AtomicUsize, Ordering};
 
 lazy_static! {
@@ -19,6 +28,14 @@ static CLIENT_COUNTER: AtomicUsize = AtomicUsize::new(1);
 
 // --- Actor Definition ---
 
+const DEFAULT_PORT: u16 = 9090;
+// Saving often touches several files at once, so they're checked again once it's done.
+const CHECK_DELAY: std::time::Duration = std::time::Duration::from_millis(200);
+// Ports tried after the configured one, when another project's `noventa dev` already has it.
+const FALLBACK_PORTS: u16 = 10;
+// Where the port the server ended up on is written, for the editor extension to find.
+pub const PORT_FILE: &str = ".noventa-lsp-port";
+
 pub struct LspActor;
 
 impl Actor for LspActor {
@@ -27,23 +44,13 @@ impl Actor for LspActor {
     fn started(&mut self, _ctx: &mut Self::Context) {
         // Spawn the single, global error listener
         tokio::spawn(listen_for_errors());
+        tokio::spawn(check_on_change());
 
         // Spawn the server to accept client connections
         tokio::spawn(async {
-            log::info!("Noventa's VisualStudio Extension server started on 127.0.0.1:9090");
-            let listener = tokio::net::TcpListener::bind("127.0.0.1:9090").await.unwrap();
-            loop {
-                let (stream, _) = listener.accept().await.unwrap();
-                log::info!("Noventa's Extension client connected");
-                let (read, write) = tokio::io::split(stream);
-
-                let (service, socket) = LspService::new(|client| {
-                    let id = CLIENT_COUNTER.fetch_add(1, Ordering::SeqCst);
-                    ALL_CLIENTS.insert(id, client.clone());
-                    Backend::new(client, id)
-                });
-
-                tokio::spawn(Server::new(read, write, socket).serve(service));
+            match bind().await {
+                Ok(listener) => serve_tcp(listener).await,
+                Err(e) => log::warn!("Heads up! The editor extension's server couldn't start ({}), so the editor won't show completions or errors.", e),
             }
         });
     }
@@ -53,6 +60,72 @@ impl Actor for LspActor {
     }
 }
 
+// `noventa lsp`: the language server on its own, for editors that start one themselves. Over stdin
+// and stdout with `--stdio`, where nothing else may be printed; over TCP otherwise.
+pub async fn run_standalone(stdio: bool) -> std::io::Result<()> {
+    tokio::spawn(check_on_change());
+    if stdio {
+        let (service, socket) = service();
+        Server::new(tokio::io::stdin(), tokio::io::stdout(), socket).serve(service).await;
+        return Ok(());
+    }
+    serve_tcp(bind().await?).await;
+    Ok(())
+}
+
+fn service() -> (LspService<Backend>, tower_lsp::ClientSocket) {
+    LspService::new(|client| {
+        let id = CLIENT_COUNTER.fetch_add(1, Ordering::SeqCst);
+        ALL_CLIENTS.insert(id, client.clone());
+        Backend::new(client, id)
+    })
+}
+
+// The configured port, or the first free one after it.
+async fn bind() -> std::io::Result<tokio::net::TcpListener> {
+    let lsp = crate::config::CONFIG.lsp.clone().unwrap_or_default();
+    let address: IpAddr = lsp.address.as_deref().and_then(|address| address.parse().ok()).unwrap_or(Ipv4Addr::LOCALHOST.into());
+    let port = lsp.port.unwrap_or(DEFAULT_PORT);
+    let listener = bind_from(address, port).await?;
+    let bound = listener.local_addr()?;
+    if bound.port() != port {
+        log::warn!("Heads up! Port {} is taken (another project running?), so the editor extension's server is on {} instead.", port, bound.port());
+    }
+    if let Err(e) = std::fs::write(crate::config::BASE_PATH.join(PORT_FILE), bound.port().to_string()) {
+        log::debug!("Couldn't write {}: {}", PORT_FILE, e);
+    }
+    log::info!("Noventa's VisualStudio Extension server started on {}", bound);
+    Ok(listener)
+}
+
+async fn bind_from(address: IpAddr, port: u16) -> std::io::Result<tokio::net::TcpListener> {
+    let mut taken = None;
+    for port in (0..=FALLBACK_PORTS).filter_map(|offset| port.checked_add(offset)) {
+        match tokio::net::TcpListener::bind((address, port)).await {
+            Ok(listener) => return Ok(listener),
+            Err(e) if e.kind() == std::io::ErrorKind::AddrInUse => taken = Some(e),
+            Err(e) => return Err(e),
+        }
+    }
+    Err(taken.unwrap_or_else(|| std::io::ErrorKind::AddrInUse.into()))
+}
+
+async fn serve_tcp(listener: tokio::net::TcpListener) {
+    loop {
+        let stream = match listener.accept().await {
+            Ok((stream, _)) => stream,
+            Err(e) => {
+                log::debug!("An editor couldn't connect: {}", e);
+                continue;
+            }
+        };
+        log::info!("Noventa's Extension client connected");
+        let (read, write) = tokio::io::split(stream);
+        let (service, socket) = service();
+        tokio::spawn(Server::new(read, write, socket).serve(service));
+    }
+}
+
 // --- LSP Backend ---
 
 #[derive(Debug)]
@@ -103,12 +176,8 @@ async fn listen_for_errors() {
 
             match Url::from_file_path(&normalized_path) {
                 Ok(uri) => {
-                    FILES_WITH_DIAGNOSTICS.insert(uri.clone(), ());
-                    for client in ALL_CLIENTS.iter() {
-                        client
-                            .publish_diagnostics(uri.clone(), vec![diagnostic.clone()], None)
-                            .await;
-                    }
+                    FILES_WITH_DIAGNOSTICS.insert(uri.clone(), diagnostic);
+                    publish(&uri).await;
                 }
                 Err(e) => {
                     log::error!("Failed to create URI from path {}: {:?}", normalized_path, e);
@@ -118,6 +187,337 @@ async fn listen_for_errors() {
     }
 }
 
+// --- Diagnostics ---
+
+// A file's runtime error, if it has one, and what `noventa check` finds in it.
+async fn publish(uri: &Url) {
+    let mut diagnostics: Vec<Diagnostic> = FILES_WITH_DIAGNOSTICS.get(uri).map(|diagnostic| vec![diagnostic.clone()]).unwrap_or_default();
+    diagnostics.extend(CHECK_DIAGNOSTICS.get(uri).map(|checked| checked.clone()).unwrap_or_default());
+    let clients: Vec<Client> = ALL_CLIENTS.iter().map(|client| client.clone()).collect();
+    for client in clients {
+        client.publish_diagnostics(uri.clone(), diagnostics.clone(), None).await;
+    }
+}
+
+// Called by the file watcher: what one file defines can fix or break another, so the open files are
+// checked again.
+pub fn files_changed() {
+    FILES_CHANGED.notify_one();
+}
+
+async fn check_on_change() {
+    loop {
+        FILES_CHANGED.notified().await;
+        tokio::time::sleep(CHECK_DELAY).await;
+        let open: Vec<Url> = DOCUMENTS.iter().map(|document| document.key().clone()).collect();
+        for uri in open {
+            check_document(&uri).await;
+        }
+    }
+}
+
+async fn check_document(uri: &Url) {
+    let root = crate::config::BASE_PATH.clone();
+    let Some(path) = path_in_project(&root, uri) else {
+        return;
+    };
+    let diagnostics = tokio::task::spawn_blocking(move || check_diagnostics(&root, &path)).await.unwrap_or_default();
+    CHECK_DIAGNOSTICS.insert(uri.clone(), diagnostics);
+    publish(uri).await;
+}
+
+// The file as `check` expects it: under the project's folder, however the editor spelled it.
+fn path_in_project(root: &std::path::Path, uri: &Url) -> Option<std::path::PathBuf> {
+    let path = uri.to_file_path().ok()?;
+    if path.starts_with(root) {
+        return Some(path);
+    }
+    let relative = path.canonicalize().ok()?.strip_prefix(root.canonicalize().ok()?).ok()?.to_path_buf();
+    Some(root.join(relative))
+}
+
+fn check_diagnostics(root: &std::path::Path, path: &std::path::Path) -> Vec<Diagnostic> {
+    let text = std::fs::read_to_string(path).unwrap_or_default();
+    crate::check::check_file(root, path)
+        .into_iter()
+        .map(|issue| {
+            // The whole line: `check` knows the line, not where on it.
+            let line = issue.line.unwrap_or(1).saturating_sub(1) as u32;
+            let width = text.lines().nth(line as usize).map_or(0, str::len) as u32;
+            Diagnostic {
+                range: Range::new(Position::new(line, 0), Position::new(line, width)),
+                severity: Some(if issue.kind.is_warning() { DiagnosticSeverity::WARNING } else { DiagnosticSeverity::ERROR }),
+                source: Some("noventa check".to_string()),
+                message: issue.message,
+                ..Diagnostic::default()
+            }
+        })
+        .collect()
+}
+
+// --- Documents ---
+
+// Where `position` is in `text`. Positions are in UTF-8 bytes, as `initialize` tells the editor.
+fn offset(text: &str, position: Position) -> usize {
+    let mut offset = 0;
+    for (number, line) in text.split_inclusive('\n').enumerate() {
+        if number == position.line as usize {
+            let mut column = (position.character as usize).min(line.trim_end_matches(['\r', '\n']).len());
+            while !line.is_char_boundary(column) {
+                column -= 1;
+            }
+            return offset + column;
+        }
+        offset += line.len();
+    }
+    text.len()
+}
+
+// The line the cursor is on, and where on it.
+fn line_at(text: &str, position: Position) -> (&str, usize) {
+    let line_start = offset(text, Position { line: position.line, character: 0 });
+    let line_end = text[line_start..].find('\n').map_or(text.len(), |end| line_start + end);
+    (&text[line_start..line_end], offset(text, position) - line_start)
+}
+
+fn apply_change(text: &mut String, change: TextDocumentContentChangeEvent) {
+    match change.range {
+        Some(range) => {
+            let (start, end) = (offset(text, range.start), offset(text, range.end));
+            text.replace_range(start..end.max(start), &change.text);
+        }
+        None => *text = change.text,
+    }
+}
+
+// --- Completions ---
+
+lazy_static! {
+    // What's typed so far on the line, up to the cursor, in each place something can be completed.
+    static ref COMPONENT_NAME: Regex = Regex::new(r#"component\(\s*["']([\w./-]*)$"#).unwrap();
+    static ref COMPONENT_PROP: Regex = Regex::new(r#"component\(\s*["']([\w./-]+)["']\s*,([^()]*?)(\w*)$"#).unwrap();
+    static ref TEMPLATE_NAME: Regex = Regex::new(r#"\{%-?\s*(?:extends|include|import|from)\s+["']([^"']*)$"#).unwrap();
+    static ref PAGE_NAME: Regex = Regex::new(r#"url_for\(\s*["']([^"']*)$"#).unwrap();
+    static ref PAGE_PARAM: Regex = Regex::new(r#"url_for\(\s*["']([^"']+)["']\s*,([^()]*?)(\w*)$"#).unwrap();
+}
+
+// Replaces what's been typed of the name, so names with dots and slashes complete in one go.
+fn completion(label: &str, kind: CompletionItemKind, typed: &str, position: Position, detail: Option<String>, documentation: Option<String>) -> CompletionItem {
+    let start = Position { line: position.line, character: position.character.saturating_sub(typed.len() as u32) };
+    CompletionItem {
+        label: label.to_string(),
+        kind: Some(kind),
+        detail,
+        documentation: documentation.map(|value| Documentation::MarkupContent(MarkupContent { kind: MarkupKind::Markdown, value })),
+        text_edit: Some(CompletionTextEdit::Edit(TextEdit { range: Range { start, end: position }, new_text: label.to_string() })),
+        ..CompletionItem::default()
+    }
+}
+
+// Arguments already given, e.g. `plan="pro", ` before the one being typed; None while inside a string.
+fn given_arguments(arguments: &str) -> Option<Vec<&str>> {
+    if arguments.matches('"').count() % 2 == 1 || arguments.matches('\'').count() % 2 == 1 {
+        return None;
+    }
+    Some(arguments.split(',').filter_map(|argument| argument.split_once('=')).map(|(name, _)| name.trim()).collect())
+}
+
+fn props_documentation(props: &[crate::props::Prop]) -> String {
+    props
+        .iter()
+        .map(|prop| {
+            let default = prop.default.as_ref().map(|value| format!(" = `{}`", value)).unwrap_or_default();
+            let required = if prop.required { " (required)" } else { "" };
+            format!("- `{}`: {}{}{}", prop.name, prop.kind, default, required)
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// What can go where the cursor is: component names in `component("…")` and then their props, template
+// names after `extends` and `include`, and page names in `url_for("…")` and then their route params.
+fn completions(index: &WorkspaceIndex, text: &str, position: Position) -> Vec<CompletionItem> {
+    let (line, column) = line_at(text, position);
+    let before = &line[..column];
+
+    if let Some(captures) = COMPONENT_NAME.captures(before) {
+        let typed = &captures[1];
+        return index
+            .components
+            .iter()
+            .map(|component| {
+                let documentation = component.props.as_deref().map(props_documentation);
+                completion(&component.name, CompletionItemKind::MODULE, typed, position, Some(crate::paths::relative_name(&component.template_path, &index.root)), documentation)
+            })
+            .collect();
+    }
+    if let Some(captures) = COMPONENT_PROP.captures(before)
+        && let Some(component) = index.component(&captures[1])
+        && let Some(given) = given_arguments(&captures[2])
+    {
+        let typed = &captures[3];
+        return component
+            .props
+            .iter()
+            .flatten()
+            .filter(|prop| !given.contains(&prop.name.as_str()))
+            .map(|prop| {
+                let mut item = completion(&prop.name, CompletionItemKind::PROPERTY, typed, position, Some(prop.kind.to_string()), None);
+                if let Some(CompletionTextEdit::Edit(edit)) = &mut item.text_edit {
+                    edit.new_text = format!("{}=", prop.name);
+                }
+                item
+            })
+            .collect();
+    }
+    if let Some(captures) = TEMPLATE_NAME.captures(before) {
+        let typed = &captures[1];
+        return index.templates.iter().map(|name| completion(name, CompletionItemKind::FILE, typed, position, None, None)).collect();
+    }
+    if let Some(captures) = PAGE_NAME.captures(before) {
+        let typed = &captures[1];
+        return index
+            .pages
+            .iter()
+            .map(|page| completion(&page.name, CompletionItemKind::FILE, typed, position, Some(page.route.clone()), None))
+            .collect();
+    }
+    if let Some(captures) = PAGE_PARAM.captures(before)
+        && let Some(page) = index.page(&captures[1])
+        && let Some(given) = given_arguments(&captures[2])
+    {
+        let typed = &captures[3];
+        return page
+            .params
+            .iter()
+            .filter(|param| !given.contains(&param.as_str()))
+            .map(|param| {
+                let mut item = completion(param, CompletionItemKind::VARIABLE, typed, position, Some(page.route.clone()), None);
+                if let Some(CompletionTextEdit::Edit(edit)) = &mut item.text_edit {
+                    edit.new_text = format!("{}=", param);
+                }
+                item
+            })
+            .collect();
+    }
+    Vec::new()
+}
+
+// --- Definitions ---
+
+lazy_static! {
+    // Names ctrl-click can follow, in the template or logic file being edited.
+    static ref COMPONENT_REF: Regex = Regex::new(r#"component\s*\(\s*["']([^"']+)["']"#).unwrap();
+    static ref TEMPLATE_REF: Regex = Regex::new(r#"\{%-?\s*(?:extends|include|import|from)\s+["']([^"']+)["']"#).unwrap();
+    static ref PAGE_REF: Regex = Regex::new(r#"url_for\(\s*["']([^"']+)["']"#).unwrap();
+    // `<form data-action="subscribe">`, `<input type="hidden" name="action" value="subscribe">` or `action_subscribe`.
+    static ref ACTION_REF: Regex =
+        Regex::new(r#"data-action\s*=\s*["'](\w+)["']|name\s*=\s*["']action["'][^>]*?\bvalue\s*=\s*["'](\w+)["']|\b(action_\w+)\b"#).unwrap();
+}
+
+// What `regex` captured where the cursor is on the line.
+fn name_at<'a>(regex: &Regex, line: &'a str, column: usize) -> Option<&'a str> {
+    regex.captures_iter(line).find_map(|captures| {
+        captures.iter().skip(1).flatten().find(|name| name.start() <= column && column <= name.end()).map(|name| name.as_str())
+    })
+}
+
+fn location(path: &std::path::Path, line: u32) -> Option<Location> {
+    let uri = Url::from_file_path(path).ok()?;
+    Some(Location { uri, range: Range { start: Position::new(line, 0), end: Position::new(line, 0) } })
+}
+
+// Where what's under the cursor is defined: a component's template, the template `extends` and
+// `include` name, the page `url_for` links to, or the `action_` function a form posts to. A form's
+// action is looked for in its own component first; from a page or layout, any component's will do.
+fn definitions(index: &WorkspaceIndex, text: &str, position: Position, path: Option<&std::path::Path>) -> Vec<Location> {
+    let (line, column) = line_at(text, position);
+
+    if let Some(name) = name_at(&COMPONENT_REF, line, column) {
+        return index.component(name).and_then(|component| location(&component.template_path, 0)).into_iter().collect();
+    }
+    if let Some(name) = name_at(&TEMPLATE_REF, line, column) {
+        let template = index.root.join(name);
+        return if template.is_file() { location(&template, 0).into_iter().collect() } else { Vec::new() };
+    }
+    if let Some(name) = name_at(&PAGE_REF, line, column) {
+        return index.page(name).and_then(|page| location(&page.template_path, 0)).into_iter().collect();
+    }
+    if let Some(action) = name_at(&ACTION_REF, line, column) {
+        let function = if action.starts_with("action_") { action.to_string() } else { format!("action_{}", action) };
+        let defined_in = |component: &crate::lsp_index::IndexedComponent| {
+            location(component.logic_path.as_ref()?, component.function(&function)?.line)
+        };
+        if let Some(own) = path.and_then(|path| index.component_of(path)).and_then(defined_in) {
+            return vec![own];
+        }
+        return index.components.iter().filter_map(defined_in).collect();
+    }
+    Vec::new()
+}
+
+// --- Hover ---
+
+fn component_hover(index: &WorkspaceIndex, component: &crate::lsp_index::IndexedComponent) -> String {
+    // Each its own paragraph, so they're not run together.
+    let mut paragraphs = vec![format!("**Component `{}`**", component.name), format!("Template: `{}`", crate::paths::relative_name(&component.template_path, &index.root))];
+    if let Some(logic_path) = &component.logic_path {
+        paragraphs.push(format!("Logic: `{}`", crate::paths::relative_name(logic_path, &index.root)));
+    }
+    let actions: Vec<String> = component.functions.iter().filter(|function| function.name.starts_with("action_")).map(|function| format!("`{}`", function.name)).collect();
+    if !actions.is_empty() {
+        paragraphs.push(format!("Actions: {}", actions.join(", ")));
+    }
+    paragraphs.push(match component.props.as_deref() {
+        Some([]) | None => "No props declared in `component.yaml`.".to_string(),
+        Some(props) => format!("Props:\n{}", props_documentation(props)),
+    });
+    paragraphs.join("\n\n")
+}
+
+fn page_hover(index: &WorkspaceIndex, page: &crate::lsp_index::IndexedPage) -> String {
+    let mut paragraphs = vec![format!("**Page `{}`**", page.name), format!("Route: `{}`", page.route)];
+    if !page.params.is_empty() {
+        paragraphs.push(format!("Params: {}", page.params.iter().map(|param| format!("`{}`", param)).collect::<Vec<_>>().join(", ")));
+    }
+    paragraphs.push(format!("Template: `{}`", crate::paths::relative_name(&page.template_path, &index.root)));
+    if let Some(logic_path) = &page.logic_path {
+        paragraphs.push(format!("Logic: `{}`", crate::paths::relative_name(logic_path, &index.root)));
+    }
+    paragraphs.join("\n\n")
+}
+
+// What's under the cursor, in Markdown: a component's props with their defaults and its files, or a
+// page's route. In a page's own template, its first line tells where it's served.
+fn hover_text(index: &WorkspaceIndex, text: &str, position: Position, path: Option<&std::path::Path>) -> Option<String> {
+    let (line, column) = line_at(text, position);
+    if let Some(name) = name_at(&COMPONENT_REF, line, column) {
+        return index.component(name).map(|component| component_hover(index, component));
+    }
+    if let Some(name) = name_at(&PAGE_REF, line, column) {
+        return index.page(name).map(|page| page_hover(index, page));
+    }
+    if let Some(name) = name_at(&TEMPLATE_REF, line, column) {
+        let template = index.root.join(name);
+        return Some(if template.is_file() { format!("`{}`", name) } else { format!("`{}` doesn't exist.", name) });
+    }
+    if let Some(action) = name_at(&ACTION_REF, line, column) {
+        let function = if action.starts_with("action_") { action.to_string() } else { format!("action_{}", action) };
+        let defined_in: Vec<String> = definitions(index, text, position, path)
+            .iter()
+            .filter_map(|location| Some(format!("`{}`, line {}", crate::paths::relative_name(&location.uri.to_file_path().ok()?, &index.root), location.range.start.line + 1)))
+            .collect();
+        return Some(match defined_in.as_slice() {
+            [] => format!("No component defines `{}`.", function),
+            _ => format!("`{}` in {}", function, defined_in.join("; ")),
+        });
+    }
+    if position.line == 0 {
+        return index.page_at(path?).map(|page| page_hover(index, page));
+    }
+    None
+}
+
 #[tower_lsp::async_trait]
 impl LanguageServer for Backend {
     async fn initialize(&self, _: InitializeParams) -> Result<InitializeResult> {
@@ -144,6 +544,12 @@ impl LanguageServer for Backend {
                     }),
                     file_operations: None,
                 }),
+                definition_provider: Some(OneOf::Left(true)),
+                hover_provider: Some(HoverProviderCapability::Simple(true)),
+                completion_provider: Some(CompletionOptions {
+                    trigger_characters: Some(["\"", "'", ".", "/", ",", " "].map(String::from).to_vec()),
+                    ..CompletionOptions::default()
+                }),
                 ..ServerCapabilities::default()
             },
         })
@@ -161,30 +567,79 @@ impl LanguageServer for Backend {
         Ok(())
     }
 
-    async fn did_open(&self, _: DidOpenTextDocumentParams) {
+    async fn did_open(&self, params: DidOpenTextDocumentParams) {
+        let uri = params.text_document.uri;
+        DOCUMENTS.insert(uri.clone(), params.text_document.text);
+        check_document(&uri).await;
     }
 
     async fn did_save(&self, params: DidSaveTextDocumentParams) {
+        // `noventa lsp` runs without the file watcher that otherwise notices new components and pages.
+        crate::lsp_index::invalidate();
         let uri = params.text_document.uri;
-        if FILES_WITH_DIAGNOSTICS.contains_key(&uri) {
-            for client in ALL_CLIENTS.iter() {
-                client.publish_diagnostics(uri.clone(), vec![], None).await;
-            }
-            FILES_WITH_DIAGNOSTICS.remove(&uri);
+        if FILES_WITH_DIAGNOSTICS.remove(&uri).is_some() {
+            publish(&uri).await;
         }
+        files_changed();
     }
 
     async fn did_change(&self, params: DidChangeTextDocumentParams) {
         let uri = params.text_document.uri;
-        if FILES_WITH_DIAGNOSTICS.contains_key(&uri) {
-            for client in ALL_CLIENTS.iter() {
-                client.publish_diagnostics(uri.clone(), vec![], None).await;
+        if let Some(mut text) = DOCUMENTS.get_mut(&uri) {
+            for change in params.content_changes {
+                apply_change(&mut text, change);
             }
-            FILES_WITH_DIAGNOSTICS.remove(&uri);
+        }
+        if FILES_WITH_DIAGNOSTICS.remove(&uri).is_some() {
+            publish(&uri).await;
         }
     }
 
-    async fn did_close(&self, _: DidCloseTextDocumentParams) {}
+    async fn did_close(&self, params: DidCloseTextDocumentParams) {
+        let uri = params.text_document.uri;
+        DOCUMENTS.remove(&uri);
+        if CHECK_DIAGNOSTICS.remove(&uri).is_some() {
+            publish(&uri).await;
+        }
+    }
+
+    async fn completion(&self, params: CompletionParams) -> Result<Option<CompletionResponse>> {
+        let position = params.text_document_position;
+        let Some(text) = DOCUMENTS.get(&position.text_document.uri).map(|text| text.clone()) else {
+            return Ok(None);
+        };
+        let index = tokio::task::spawn_blocking(crate::lsp_index::get).await.unwrap_or_default();
+        let items = completions(&index, &text, position.position);
+        Ok((!items.is_empty()).then_some(CompletionResponse::Array(items)))
+    }
+
+    async fn hover(&self, params: HoverParams) -> Result<Option<Hover>> {
+        let position = params.text_document_position_params;
+        let Some(text) = DOCUMENTS.get(&position.text_document.uri).map(|text| text.clone()) else {
+            return Ok(None);
+        };
+        let path = position.text_document.uri.to_file_path().ok();
+        let index = tokio::task::spawn_blocking(crate::lsp_index::get).await.unwrap_or_default();
+        Ok(hover_text(&index, &text, position.position, path.as_deref()).map(|value| Hover {
+            contents: HoverContents::Markup(MarkupContent { kind: MarkupKind::Markdown, value }),
+            range: None,
+        }))
+    }
+
+    async fn goto_definition(&self, params: GotoDefinitionParams) -> Result<Option<GotoDefinitionResponse>> {
+        let position = params.text_document_position_params;
+        let Some(text) = DOCUMENTS.get(&position.text_document.uri).map(|text| text.clone()) else {
+            return Ok(None);
+        };
+        let path = position.text_document.uri.to_file_path().ok();
+        let index = tokio::task::spawn_blocking(crate::lsp_index::get).await.unwrap_or_default();
+        let mut locations = definitions(&index, &text, position.position, path.as_deref());
+        Ok(match locations.len() {
+            0 => None,
+            1 => Some(GotoDefinitionResponse::Scalar(locations.remove(0))),
+            _ => Some(GotoDefinitionResponse::Array(locations)),
+        })
+    }
 }
 
 #[cfg(test)]
@@ -193,6 +648,32 @@ mod tests {
     use tower_lsp::lsp_types::*;
     use std::sync::Arc;
 
+    #[test]
+    fn test_check_diagnostics() {
+        let project = tempfile::tempdir().unwrap();
+        let root = project.path();
+        std::fs::create_dir_all(root.join("pages")).unwrap();
+        std::fs::write(root.join("pages/index.html"), "<h1>Hi</h1>\n{{ component('ghost') }}").unwrap();
+        let diagnostics = check_diagnostics(root, &root.join("pages/index.html"));
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].range, Range::new(Position::new(1, 0), Position::new(1, 24)));
+        assert_eq!(diagnostics[0].severity, Some(DiagnosticSeverity::ERROR));
+        assert!(diagnostics[0].message.contains("'ghost'"));
+
+        let uri = Url::from_file_path(root.join("pages/index.html")).unwrap();
+        assert_eq!(path_in_project(root, &uri), Some(root.join("pages/index.html")));
+        assert_eq!(path_in_project(root, &Url::parse("file:///elsewhere/index.html").unwrap()), None);
+    }
+
+    #[actix_rt::test]
+    async fn test_bind_from() {
+        let taken = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = taken.local_addr().unwrap().port();
+        let listener = bind_from(Ipv4Addr::LOCALHOST.into(), port).await.unwrap();
+        let bound = listener.local_addr().unwrap().port();
+        assert!(bound > port && bound <= port + FALLBACK_PORTS, "{} after {}", bound, port);
+    }
+
     #[test]
     fn test_backend_new() {
         // Test that Backend::new creates a backend with the correct client_id
@@ -214,7 +695,7 @@ mod tests {
         assert!(!FILES_WITH_DIAGNOSTICS.contains_key(&url));
         
         // Insert something
-        FILES_WITH_DIAGNOSTICS.insert(url.clone(), ());
+        FILES_WITH_DIAGNOSTICS.insert(url.clone(), Diagnostic::default());
         assert!(FILES_WITH_DIAGNOSTICS.contains_key(&url));
         
         // Remove it
@@ -291,6 +772,134 @@ mod tests {
         assert!(result.capabilities.workspace.is_some());
     }
 
+    #[test]
+    fn test_apply_change() {
+        let mut text = "<h1>{{ title }}</h1>\n<p>é {{ body }}</p>\n".to_string();
+        let change = |start: (u32, u32), end: (u32, u32), new_text: &str| TextDocumentContentChangeEvent {
+            range: Some(Range { start: Position::new(start.0, start.1), end: Position::new(end.0, end.1) }),
+            range_length: None,
+            text: new_text.to_string(),
+        };
+        apply_change(&mut text, change((0, 7), (0, 12), "heading"));
+        apply_change(&mut text, change((1, 5), (1, 5), "!"));
+        assert_eq!(text, "<h1>{{ heading }}</h1>\n<p>é! {{ body }}</p>\n");
+        apply_change(&mut text, TextDocumentContentChangeEvent { range: None, range_length: None, text: "new".to_string() });
+        assert_eq!(text, "new");
+    }
+
+    #[test]
+    fn test_completions() {
+        use crate::lsp_index::{IndexedComponent, IndexedPage};
+        let index = WorkspaceIndex {
+            root: "/project".into(),
+            components: vec![IndexedComponent {
+                name: "cards.pricing".to_string(),
+                template_path: "/project/components/cards/pricing/template.html".into(),
+                logic_path: None,
+                functions: Vec::new(),
+                props: Some(vec![
+                    crate::props::Prop { name: "plan".to_string(), kind: crate::props::PropType::String, required: true, default: None },
+                    crate::props::Prop { name: "yearly".to_string(), kind: crate::props::PropType::Bool, required: false, default: None },
+                ]),
+            }],
+            templates: vec!["layouts/base.html".to_string()],
+            pages: vec![IndexedPage {
+                name: "blog/[slug]".to_string(),
+                route: "/blog/{slug}".to_string(),
+                params: vec!["slug".to_string()],
+                template_path: "/project/pages/blog/[slug].html".into(),
+                logic_path: None,
+            }],
+        };
+        let at_end = |text: &str| {
+            let lines: Vec<&str> = text.split('\n').collect();
+            completions(&index, text, Position::new(lines.len() as u32 - 1, lines.last().unwrap().len() as u32))
+        };
+        let labels = |items: Vec<CompletionItem>| items.into_iter().map(|item| item.label).collect::<Vec<_>>();
+
+        let items = at_end("<div>\n  {{ component(\"cards.");
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].label, "cards.pricing");
+        let Some(CompletionTextEdit::Edit(edit)) = &items[0].text_edit else { panic!() };
+        assert_eq!(edit.range.start, Position::new(1, 16));
+        assert!(matches!(&items[0].documentation, Some(Documentation::MarkupContent(docs)) if docs.value.contains("`plan`: a string (required)")));
+
+        assert_eq!(labels(at_end("{{ component('cards.pricing', plan=\"pro\", ")), ["yearly"]);
+        assert!(at_end("{{ component('cards.pricing', plan=\"pr").is_empty());
+        assert_eq!(labels(at_end("{% extends \"lay")), ["layouts/base.html"]);
+        assert_eq!(labels(at_end("<a href=\"{{ url_for('blog")), ["blog/[slug]"]);
+        assert_eq!(labels(at_end("return request.url_for(\"blog/[slug]\", s")), ["slug"]);
+        assert!(at_end("{{ title }}").is_empty());
+    }
+
+    #[test]
+    fn test_definitions() {
+        let project = tempfile::tempdir().unwrap();
+        let root = project.path();
+        let write = |relative: &str, content: &str| {
+            std::fs::create_dir_all(root.join(relative).parent().unwrap()).unwrap();
+            std::fs::write(root.join(relative), content).unwrap();
+        };
+        write("components/cards/pricing/template.html", "<form data-action=\"subscribe\"></form>");
+        write("components/cards/pricing/pricing_logic.py", "def load_template_context(request):\n    return {}\n\ndef action_subscribe(request):\n    pass\n");
+        write("components/newsletter/template.html", "<input type=\"hidden\" name=\"action\" value=\"subscribe\">");
+        write("components/newsletter/newsletter_logic.py", "def action_subscribe(request):\n    pass\n");
+        write("layouts/base.html", "");
+        write("pages/blog/[slug].html", "");
+        let index = crate::lsp_index::build(root);
+        let follow = |text: &str, column: u32, path: &str| {
+            definitions(&index, text, Position::new(0, column), Some(&root.join(path)))
+                .into_iter()
+                .map(|location| (crate::paths::relative_name(&location.uri.to_file_path().unwrap(), root), location.range.start.line))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(follow("{{ component('cards.pricing') }}", 18, "pages/index.html"), [("components/cards/pricing/template.html".to_string(), 0)]);
+        assert_eq!(follow("{% extends \"layouts/base.html\" %}", 15, "pages/index.html"), [("layouts/base.html".to_string(), 0)]);
+        assert_eq!(follow("{{ url_for('blog/[slug]', slug=1) }}", 14, "pages/index.html"), [("pages/blog/[slug].html".to_string(), 0)]);
+        // Each form's own component.
+        assert_eq!(follow("<form data-action=\"subscribe\"></form>", 21, "components/cards/pricing/template.html"), [("components/cards/pricing/pricing_logic.py".to_string(), 3)]);
+        assert_eq!(follow("<input type=\"hidden\" name=\"action\" value=\"subscribe\">", 43, "components/newsletter/template.html"), [("components/newsletter/newsletter_logic.py".to_string(), 0)]);
+        // From a page, every component with that action.
+        assert_eq!(follow("{# posts to action_subscribe #}", 20, "pages/index.html").len(), 2);
+        assert!(follow("{{ component('cards.pricing') }}", 1, "pages/index.html").is_empty());
+    }
+
+    #[test]
+    fn test_hover() {
+        let project = tempfile::tempdir().unwrap();
+        let root = project.path();
+        let write = |relative: &str, content: &str| {
+            std::fs::create_dir_all(root.join(relative).parent().unwrap()).unwrap();
+            std::fs::write(root.join(relative), content).unwrap();
+        };
+        write("components/cards/pricing/template.html", "<div></div>");
+        write("components/cards/pricing/pricing_logic.py", "def action_subscribe(request):\n    pass\n");
+        write("components/cards/pricing/component.yaml", "props:\n  plan: string\n  yearly:\n    type: bool\n    default: false\n");
+        write("pages/blog/[slug].html", "{% extends \"layouts/base.html\" %}\n<h1>{{ title }}</h1>");
+        write("pages/blog/_page.py", "");
+        let index = crate::lsp_index::build(root);
+        let hover = |text: &str, position: Position, path: &str| hover_text(&index, text, position, Some(&root.join(path)));
+
+        let component = hover("{{ component('cards.pricing') }}", Position::new(0, 18), "pages/index.html").unwrap();
+        assert!(component.contains("Template: `components/cards/pricing/template.html`"), "{}", component);
+        assert!(component.contains("Logic: `components/cards/pricing/pricing_logic.py`"));
+        assert!(component.contains("Actions: `action_subscribe`"));
+        assert!(component.contains("- `plan`: a string (required)"));
+        assert!(component.contains("- `yearly`: a bool = `false`"));
+
+        let page = hover("{{ url_for('blog/[slug]', slug=1) }}", Position::new(0, 14), "pages/index.html").unwrap();
+        assert!(page.contains("Route: `/blog/{slug}`"));
+        assert!(page.contains("Params: `slug`"));
+        assert!(page.contains("Logic: `pages/blog/_page.py`"));
+
+        let text = "{% extends \"layouts/base.html\" %}\n<h1>{{ title }}</h1>";
+        assert!(hover(text, Position::new(0, 1), "pages/blog/[slug].html").unwrap().contains("Route: `/blog/{slug}`"));
+        assert!(hover(text, Position::new(1, 8), "pages/blog/[slug].html").is_none());
+        assert!(hover(text, Position::new(0, 15), "pages/blog/[slug].html").unwrap().contains("doesn't exist"));
+        assert!(hover("<form data-action=\"subscribe\">", Position::new(0, 21), "pages/index.html").unwrap().contains("pricing_logic.py`, line 1"));
+    }
+
     #[tokio::test]
     async fn test_did_close_handler() {
         // This test ensures the did_close handler exists and can be called.
@@ -0,0 +1,219 @@
+use crate::props::Prop;
+use crate::{components, config, paths, routing};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use walkdir::WalkDir;
+
+// What the editor extension knows about the project: its components with their logic files and the
+// functions in them, the templates `extends` and `include` can name, and its pages with their routes.
+// Built the first time the editor asks and again after the file watcher sees a page, component or
+// layout change.
+
+// Folders of templates, relative to the project.
+const TEMPLATE_DIRS: &[&str] = &["layouts", "pages", "components", components::PACKS_DIR];
+
+// A top-level `def` or `async def` in a logic file.
+static PYTHON_FUNCTION_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?m)^(?:async\s+)?def\s+(\w+)\s*\(").unwrap());
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PythonFunction {
+    pub name: String,
+    // From 0, as editors count them.
+    pub line: u32,
+}
+
+#[derive(Clone, Debug)]
+pub struct IndexedComponent {
+    // As templates call it, e.g. `cards.pricing` or `ui.button`.
+    pub name: String,
+    pub template_path: PathBuf,
+    pub logic_path: Option<PathBuf>,
+    pub functions: Vec<PythonFunction>,
+    pub props: Option<Vec<Prop>>,
+}
+
+impl IndexedComponent {
+    // e.g. `action_submit`.
+    pub fn function(&self, name: &str) -> Option<&PythonFunction> {
+        self.functions.iter().find(|function| function.name == name)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct IndexedPage {
+    // As `url_for` takes it, e.g. `blog/[slug]`.
+    pub name: String,
+    pub route: String,
+    // As `url_for` takes them, e.g. `post_id` for `[post-id]`.
+    pub params: Vec<String>,
+    pub template_path: PathBuf,
+    // Its `_page.py` (or `_logic.py`), if it has one.
+    pub logic_path: Option<PathBuf>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct WorkspaceIndex {
+    pub root: PathBuf,
+    pub components: Vec<IndexedComponent>,
+    // As the template loader takes them, e.g. `layouts/base.html`.
+    pub templates: Vec<String>,
+    pub pages: Vec<IndexedPage>,
+}
+
+impl WorkspaceIndex {
+    pub fn component(&self, name: &str) -> Option<&IndexedComponent> {
+        let name = name.replace('/', ".");
+        self.components.iter().find(|component| component.name == name)
+    }
+
+    pub fn page(&self, name: &str) -> Option<&IndexedPage> {
+        let name = name.trim_start_matches('/').trim_end_matches(".html");
+        self.pages.iter().find(|page| page.name == name)
+    }
+
+    pub fn page_at(&self, path: &Path) -> Option<&IndexedPage> {
+        self.pages.iter().find(|page| same_path(&page.template_path, path))
+    }
+
+    // The component a file belongs to: the one whose folder it's in.
+    pub fn component_of(&self, path: &Path) -> Option<&IndexedComponent> {
+        let folder = path.parent()?;
+        self.components.iter().find(|component| component.template_path.parent().is_some_and(|own| same_path(own, folder)))
+    }
+}
+
+// The same file or folder, however the editor and the index happened to spell it.
+pub fn same_path(a: &Path, b: &Path) -> bool {
+    a == b || matches!((a.canonicalize(), b.canonicalize()), (Ok(a), Ok(b)) if a == b)
+}
+
+pub fn python_functions(path: &Path) -> Vec<PythonFunction> {
+    let Ok(source) = std::fs::read_to_string(path) else {
+        return Vec::new();
+    };
+    PYTHON_FUNCTION_REGEX
+        .captures_iter(&source)
+        .map(|captures| PythonFunction {
+            name: captures[1].to_string(),
+            line: source[..captures.get(0).unwrap().start()].matches('\n').count() as u32,
+        })
+        .collect()
+}
+
+static INDEX: RwLock<Option<Arc<WorkspaceIndex>>> = RwLock::new(None);
+
+pub fn build(root: &Path) -> WorkspaceIndex {
+    let mut components: Vec<IndexedComponent> = components::scan_project_components(root)
+        .unwrap_or_else(|e| {
+            log::warn!("Heads up! The editor extension couldn't read the components: {}", e);
+            Vec::new()
+        })
+        .into_iter()
+        .map(|component| {
+            let logic_path = component.logic_path.map(PathBuf::from);
+            IndexedComponent {
+                name: component.id.replace('/', "."),
+                template_path: PathBuf::from(component.template_path),
+                functions: logic_path.as_deref().map(python_functions).unwrap_or_default(),
+                logic_path,
+                props: component.props,
+            }
+        })
+        .collect();
+    components.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let mut templates: Vec<String> = TEMPLATE_DIRS
+        .iter()
+        .flat_map(|dir| WalkDir::new(root.join(dir)).into_iter().filter_map(Result::ok))
+        .filter(|entry| entry.file_type().is_file() && entry.path().extension().is_some_and(|ext| ext == "html"))
+        .filter_map(|entry| entry.path().strip_prefix(root).ok().map(paths::to_slash))
+        .collect();
+    templates.sort();
+
+    let pages_dir = root.join("pages");
+    let (routes, _) = routing::resolve_routes(&pages_dir);
+    let mut pages: Vec<IndexedPage> = routes
+        .into_iter()
+        .filter_map(|route| {
+            let name = paths::to_slash(route.template_path.strip_prefix(&pages_dir).ok()?);
+            let logic_path = crate::actors::template_renderer::page_logic_path(root, &format!("pages/{}", name)).map(|logic_path| root.join(logic_path));
+            Some(IndexedPage {
+                name: name.strip_suffix(".html").unwrap_or(&name).to_string(),
+                route: route.route_pattern,
+                params: route.param_names.iter().map(|param| param.replace('-', "_")).collect(),
+                template_path: route.template_path,
+                logic_path,
+            })
+        })
+        .collect();
+    pages.sort_by(|a, b| a.name.cmp(&b.name));
+
+    WorkspaceIndex { root: root.to_path_buf(), components, templates, pages }
+}
+
+// The project's index, built now if something changed since it was last asked for.
+pub fn get() -> Arc<WorkspaceIndex> {
+    if let Some(index) = INDEX.read().unwrap().as_ref() {
+        return index.clone();
+    }
+    let index = Arc::new(build(&config::BASE_PATH));
+    *INDEX.write().unwrap() = Some(index.clone());
+    index
+}
+
+// Called by the file watcher when pages, components or layouts change.
+pub fn invalidate() {
+    *INDEX.write().unwrap() = None;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_build() {
+        let project = tempfile::tempdir().unwrap();
+        let root = project.path();
+        fs::create_dir_all(root.join("components/cards/pricing")).unwrap();
+        fs::write(root.join("components/cards/pricing/template.html"), "<div>{{ plan }}</div>").unwrap();
+        fs::write(
+            root.join("components/cards/pricing/pricing_logic.py"),
+            "import json\n\ndef load_template_context(request, **props):\n    return props\n\nasync def action_subscribe(request, **props):\n    pass\n",
+        )
+        .unwrap();
+        fs::write(root.join("components/cards/pricing/component.yaml"), "props:\n  plan:\n    type: string\n").unwrap();
+        fs::create_dir_all(root.join("component_packs/ui/button")).unwrap();
+        fs::write(root.join("component_packs/ui/button/template.html"), "<button></button>").unwrap();
+        fs::create_dir_all(root.join("layouts")).unwrap();
+        fs::write(root.join("layouts/base.html"), "{% block body %}{% endblock %}").unwrap();
+        fs::create_dir_all(root.join("pages/blog")).unwrap();
+        fs::write(root.join("pages/index.html"), "").unwrap();
+        fs::write(root.join("pages/blog/[post-id].html"), "").unwrap();
+        fs::write(root.join("pages/blog/_page.py"), "").unwrap();
+
+        let index = build(root);
+        assert_eq!(index.components.iter().map(|component| component.name.as_str()).collect::<Vec<_>>(), ["cards.pricing", "ui.button"]);
+        let pricing = index.component("cards/pricing").unwrap();
+        assert!(pricing.template_path.ends_with("cards/pricing/template.html"));
+        assert!(pricing.logic_path.as_ref().unwrap().ends_with("pricing_logic.py"));
+        assert_eq!(pricing.function("action_subscribe"), Some(&PythonFunction { name: "action_subscribe".to_string(), line: 5 }));
+        assert_eq!(index.component_of(&root.join("components/cards/pricing/pricing_logic.py")).unwrap().name, "cards.pricing");
+        assert!(index.component_of(&root.join("layouts/base.html")).is_none());
+        assert_eq!(pricing.props.as_ref().unwrap()[0].name, "plan");
+        assert_eq!(
+            index.templates,
+            ["component_packs/ui/button/template.html", "components/cards/pricing/template.html", "layouts/base.html", "pages/blog/[post-id].html", "pages/index.html"]
+        );
+        let post = index.page("blog/[post-id]").unwrap();
+        assert_eq!(post.route, "/blog/{post-id}");
+        assert_eq!(post.params, ["post_id"]);
+        assert!(post.template_path.ends_with("pages/blog/[post-id].html"));
+        assert!(post.logic_path.as_ref().unwrap().ends_with("pages/blog/_page.py"));
+        assert_eq!(index.page_at(&root.join("pages/blog/[post-id].html")).unwrap().name, "blog/[post-id]");
+        assert!(index.page("index").unwrap().logic_path.is_none());
+        assert!(index.page("/index.html").is_some());
+    }
+}
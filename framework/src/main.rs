@@ -8,28 +8,79 @@ use actix_session::{
     SessionMiddleware,
 };
 use actix_web_actors::ws;
-use deadpool_redis::{Config, Runtime};
 use actix_files::Files;
 use std::path::Path;
-use std::process::Command;
 use path_clean::PathClean;
-use std::env;
 use std::collections::HashMap;
 use crate::actors::page_renderer::RenderMessage;
 
+mod access_log;
 mod actors;
+mod admin;
+mod auth;
+mod check;
+mod cluster;
+mod component_packs;
+mod component_state;
+mod compressed_pages;
 pub mod components;
 mod config;
+mod context_processors;
+mod dependencies;
+mod dev_events;
+mod doctor;
 mod dto;
+mod events;
 mod fileupload;
+mod form_tokens;
+mod fragments;
+mod generators;
+mod graph;
+mod images;
+mod interpreter_queue;
+mod proxy;
+mod rate_limit;
+mod render_progress;
+mod readiness;
+mod reverse_routes;
+mod recording;
+mod redirects;
+mod security_headers;
 mod routing;
+mod scaling;
+mod route_table;
 mod disco;
 mod session;
+mod signed_urls;
+mod telemetry;
+mod session_migration;
+mod loadtest;
 mod logger;
 mod templates;
+mod upload_validation;
+mod user_channels;
+mod warmup;
+mod error_reporting;
 mod errors;
+mod listener;
+mod localized;
 mod lsp;
+mod lsp_index;
+mod object_storage;
+mod oidc;
+mod onboarding;
+mod page_limits;
+mod page_meta;
+mod paths;
+mod props;
+mod python_env;
+mod starter;
 mod static_assets;
+mod streaming;
+mod template_ast;
+pub mod template_extensions;
+mod template_filters;
+mod template_helpers;
 
 use actors::health::HealthActor;
 use actors::interpreter::PythonInterpreterActor;
@@ -59,14 +110,50 @@ struct Cli {
     starter: Option<String>,
 }
 
+// Lets two servers run side by side without keeping a config.yaml for each.
+#[derive(clap::Args)]
+struct ServerArgs {
+    /// Address to listen on, overriding `server_address` in config.yaml
+    #[clap(long)]
+    host: Option<String>,
+    /// Port to listen on, overriding `port` in config.yaml
+    #[clap(long, value_parser = clap::value_parser!(u32).range(1..=65535))]
+    port: Option<u32>,
+    /// Log level (error, warn, info, debug, trace), overriding `log_level` in config.yaml
+    #[clap(long)]
+    log_level: Option<String>,
+}
+
+impl ServerArgs {
+    fn to_overrides(&self) -> config::ConfigOverrides {
+        config::ConfigOverrides {
+            server_address: self.host.clone(),
+            port: self.port,
+            log_level: self.log_level.clone(),
+        }
+    }
+}
+
 #[derive(clap::Subcommand)]
 enum Commands {
     /// Runs the development web server
-    Dev,
+    Dev {
+        #[command(flatten)]
+        server: ServerArgs,
+    },
     /// Runs the production web server
-    Serve,
+    Serve {
+        #[command(flatten)]
+        server: ServerArgs,
+    },
     /// Runs the MCP server
     Disco,
+    /// Runs the editor extension's language server on its own, for editors that start it themselves
+    Lsp {
+        /// Talk over stdin and stdout instead of TCP (`lsp.port` in config.yaml)
+        #[clap(long, action)]
+        stdio: bool,
+    },
     /// Create a new project
     New {
         #[clap(long, action)]
@@ -75,35 +162,158 @@ enum Commands {
     Ssg {
         #[clap(long, action)]
         path: String,
-    }
+    },
+    /// Creates a new component folder with its logic and template files
+    #[command(name = "new:component")]
+    NewComponent {
+        /// Component name, use dots for subcomponents (e.g. cards.pricing)
+        name: String,
+        /// Also create a <name>_models.py file
+        #[clap(long, action)]
+        models: bool,
+    },
+    /// Creates a new page for the given route (e.g. /blog/{id})
+    #[command(name = "new:page")]
+    NewPage {
+        route: String,
+    },
+    /// Checks Python, config.yaml, the database, Redis and the project layout
+    Doctor {
+        /// Find a Python install matching the one noventa runs on and save it as `python.executable`
+        #[clap(long, action)]
+        fix_python: bool,
+    },
+    /// Prints the route table resolved from the pages folder
+    Routes,
+    /// Checks templates, component references, static asset links and logic files for errors, and lists unused components and layouts
+    Check {
+        /// Print the report as JSON
+        #[clap(long, action)]
+        json: bool,
+    },
+    /// Prints which pages use which layouts, partials and components, as Graphviz DOT
+    Graph {
+        /// Print the graph as JSON instead
+        #[clap(long, action)]
+        json: bool,
+    },
+    /// Moves sessions to another backend, or exports and imports Redis sessions
+    Sessions {
+        #[command(subcommand)]
+        action: SessionsAction,
+    },
+    /// Adds something to the project
+    Add {
+        #[command(subcommand)]
+        what: AddCommand,
+    },
+    /// Replays traffic recorded with `recording` in config.yaml against a server
+    Loadtest {
+        /// The recording to play back
+        #[clap(long)]
+        replay: std::path::PathBuf,
+        /// The server to send it to
+        #[clap(long, default_value = "http://127.0.0.1:8080")]
+        target: String,
+        /// How much faster than recorded, e.g. 2 for twice the rate
+        #[clap(long, default_value_t = 1.0)]
+        speed: f64,
+        /// A value for a route or query parameter, e.g. --param id=42 (made up otherwise)
+        #[clap(long = "param")]
+        params: Vec<String>,
+    },
+}
+
+#[derive(clap::Subcommand)]
+enum AddCommand {
+    /// Installs a pack of components from a folder or a git URL, called as `component("<namespace>.<name>")`
+    #[command(name = "component-pack")]
+    ComponentPack {
+        /// A folder or a git URL
+        source: String,
+        /// The namespace to call its components under, instead of the pack's own name
+        #[clap(long)]
+        name: Option<String>,
+    },
+}
+
+#[derive(clap::Subcommand)]
+enum SessionsAction {
+    /// Switches `session.backend`; each session moves over the next time its user visits
+    Migrate {
+        #[clap(long)]
+        from: config::SessionBackend,
+        #[clap(long)]
+        to: config::SessionBackend,
+    },
+    /// Writes the sessions stored in Redis as JSON
+    Export {
+        /// File to write to, instead of printing them
+        #[clap(long)]
+        output: Option<std::path::PathBuf>,
+    },
+    /// Loads sessions written by `export` into Redis
+    Import {
+        file: std::path::PathBuf,
+    },
 }
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     let cli = Cli::parse();
+    if let Some(Commands::Dev { server } | Commands::Serve { server }) = &cli.command {
+        config::set_overrides(server.to_overrides());
+    }
+
+    // The interpreter reads PYTHONHOME and PYTHONPATH when it starts, so they're set up first.
+    // `check` and `doctor` report config problems themselves, so they don't go through CONFIG.
+    match &cli.command {
+        Some(Commands::Dev { .. } | Commands::Serve { .. } | Commands::Ssg { .. } | Commands::Lsp { .. }) => {
+            python_env::prepare(config::CONFIG.python.as_ref());
+        }
+        Some(Commands::Check { .. } | Commands::Disco | Commands::Doctor { fix_python: false }) => {
+            python_env::prepare(lenient_config().and_then(|config| config.python).as_ref());
+        }
+        _ => {}
+    }
 
     let (_dev_mode, command) = match &cli.command {
-        Some(Commands::Dev) => (true, cli.command.as_ref()),
-        Some(Commands::Serve) => (false, cli.command.as_ref()),
+        Some(Commands::Dev { .. }) => (true, cli.command.as_ref()),
+        Some(Commands::Serve { .. }) => (false, cli.command.as_ref()),
         Some(Commands::Disco) => (false, cli.command.as_ref()),
+        Some(Commands::Lsp { .. }) => (true, cli.command.as_ref()),
         Some(Commands::New { .. }) => (false, cli.command.as_ref()),
         Some(Commands::Ssg { .. }) => (true, cli.command.as_ref()),
+        Some(Commands::NewComponent { .. }) => (false, cli.command.as_ref()),
+        Some(Commands::NewPage { .. }) => (false, cli.command.as_ref()),
+        Some(Commands::Routes) => (false, cli.command.as_ref()),
+        Some(Commands::Check { .. }) => (false, cli.command.as_ref()),
+        Some(Commands::Graph { .. }) => (false, cli.command.as_ref()),
+        Some(Commands::Doctor { .. }) => (false, cli.command.as_ref()),
+        Some(Commands::Sessions { .. }) => (false, cli.command.as_ref()),
+        Some(Commands::Loadtest { .. }) => (false, cli.command.as_ref()),
+        Some(Commands::Add { .. }) => (false, cli.command.as_ref()),
         None => (false, None),
     };
 
     match command {
-        Some(Commands::Dev) => {
+        Some(Commands::Dev { .. }) => {
             let server = run_dev_server().await?;
             server.await
         }
-        Some(Commands::Serve) => {
-            let server = run_prod_server().await?;
+        Some(Commands::Serve { .. }) => {
+            let server = run_prod_server(false).await?;
             server.await
         }
         Some(Commands::Disco) => disco::server::run_disco_server().await,
+        Some(Commands::Lsp { stdio }) => {
+            // Logs go to stderr, out of the way of the editor's messages on stdout.
+            init_logging(true);
+            lsp::run_standalone(*stdio).await
+        }
         Some(Commands::New { no_input }) => create_new_project(cli.starter.as_deref(), *no_input),
         Some(Commands::Ssg { path }) => {
-            let srv = run_prod_server().await?;
+            let srv = run_prod_server(true).await?;
             let srv_handle = srv.handle();
             let ssg_actor = SSGActor::new().start();
 
@@ -119,6 +329,119 @@ async fn main() -> std::io::Result<()> {
             log::info!("Server stopped. Exiting.");
             Ok(())
         }
+        Some(Commands::NewComponent { name, models }) => {
+            let files = generators::generate_component(&config::BASE_PATH, name, *models).unwrap_or_else(|e| {
+                println!("Oh no! We couldn't create the component: {}", e);
+                std::process::exit(1);
+            });
+            for file in files {
+                println!("  created {}", file.strip_prefix(&*config::BASE_PATH).unwrap_or(&file).display());
+            }
+            println!("✨ Use it in a template with {{{{ component(\"{}\") }}}}", name.replace('/', "."));
+            Ok(())
+        }
+        Some(Commands::NewPage { route }) => {
+            let (file, resolved_route) = generators::generate_page(&config::BASE_PATH, route).unwrap_or_else(|e| {
+                println!("Oh no! We couldn't create the page: {}", e);
+                std::process::exit(1);
+            });
+            println!("  created {}", file.strip_prefix(&*config::BASE_PATH).unwrap_or(&file).display());
+            println!("✨ Your page will be served at {}", resolved_route);
+            Ok(())
+        }
+        Some(Commands::Add { what: AddCommand::ComponentPack { source, name } }) => {
+            let pack = component_packs::install(&config::BASE_PATH, source, name.as_deref()).unwrap_or_else(|e| {
+                println!("Oh no! We couldn't add the component pack: {}.", e);
+                std::process::exit(1);
+            });
+            println!("  installed {}/{}", components::PACKS_DIR, pack.namespace);
+            if let Some(assets) = &pack.assets {
+                println!("  copied its static files to {}", assets.display());
+            }
+            for component in &pack.components {
+                println!("  {{{{ component(\"{}\") }}}}", component);
+            }
+            println!("✨ The {} pack is ready to use!", pack.namespace);
+            Ok(())
+        }
+        Some(Commands::Routes) => {
+            let table = route_table::build_route_table(&config::BASE_PATH);
+            route_table::print_route_table(&table);
+            if !table.conflicts.is_empty() {
+                std::process::exit(1);
+            }
+            Ok(())
+        }
+        Some(Commands::Doctor { fix_python: true }) => {
+            let configured = lenient_config().and_then(|config| config.python).and_then(|python| python.executable);
+            match python_env::fix(&config::BASE_PATH, configured.as_deref()) {
+                Ok(install) => {
+                    println!(
+                        "✨ noventa will use {} (Python {}.{}.{}). It's saved as `python.executable` in config.yaml.",
+                        install.executable.display(),
+                        install.version.0,
+                        install.version.1,
+                        install.version.2
+                    );
+                    Ok(())
+                }
+                Err(e) => {
+                    println!("Oh no! {}.", e);
+                    println!("Install that version (or activate a virtualenv that has it) and run this again, or set `python.executable` in config.yaml yourself.");
+                    std::process::exit(1);
+                }
+            }
+        }
+        Some(Commands::Doctor { .. }) => {
+            let results = doctor::run_doctor(&config::BASE_PATH);
+            doctor::print_diagnoses(&results);
+            if results.iter().any(|r| r.status == doctor::Status::Failed) {
+                std::process::exit(1);
+            }
+            Ok(())
+        }
+        Some(Commands::Check { json }) => {
+            let report = check::run_check(&config::BASE_PATH);
+            if *json {
+                println!("{}", serde_json::to_string_pretty(&report).unwrap_or_default());
+            } else {
+                check::print_report(&report);
+            }
+            if !report.is_ok() {
+                std::process::exit(1);
+            }
+            Ok(())
+        }
+        Some(Commands::Graph { json }) => {
+            let graph = graph::build_graph(&config::BASE_PATH);
+            if *json {
+                println!("{}", serde_json::to_string_pretty(&graph).unwrap_or_default());
+            } else {
+                print!("{}", graph::to_dot(&graph));
+                if !graph.unused_components.is_empty() {
+                    eprintln!("Heads up! No page uses these components: {}", graph.unused_components.join(", "));
+                }
+            }
+            Ok(())
+        }
+        Some(Commands::Loadtest { replay, target, speed, params }) => {
+            run_loadtest(replay, target, *speed, params).await.unwrap_or_else(|e| {
+                println!("Oh no! {}.", e);
+                std::process::exit(1);
+            });
+            Ok(())
+        }
+        Some(Commands::Sessions { action }) => {
+            let Some(session_config) = config::CONFIG.session.as_ref() else {
+                println!("Oh no! config.yaml has no `session` section, so sessions are kept in a temporary cookie.");
+                std::process::exit(1);
+            };
+            manage_sessions(session_config, action).await.unwrap_or_else(|e| {
+                println!("Oh no! {}.", e);
+                std::process::exit(1);
+            });
+            Ok(())
+        }
         None => {
             use clap::CommandFactory;
             Cli::command().print_help()?;
@@ -127,42 +450,69 @@ async fn main() -> std::io::Result<()> {
     }
 }
 
-fn create_new_project(starter_path: Option<&str>, no_input: bool) -> std::io::Result<()> {
-    let template_path = if let Some(path) = starter_path {
-        Path::new(path).to_path_buf()
-    } else {
-        // Fallback for local development: find the `starter` directory relative to the executable.
-        let mut exe_path = env::current_exe()?;
-        exe_path.pop();
-        exe_path.join("starter")
-    };
-
-    if !template_path.exists() {
-        return Err(std::io::Error::new(
-            std::io::ErrorKind::NotFound,
-            format!("Template directory not found at: {:?}", template_path),
-        ));
+async fn manage_sessions(session_config: &config::SessionConfig, action: &SessionsAction) -> Result<(), String> {
+    match action {
+        SessionsAction::Migrate { from, to } => {
+            session_migration::migrate(&config::BASE_PATH, session_config, *from, *to)?;
+            println!("✨ Sessions now live in {}. Each one moves over from {} the next time its user visits.", to.name(), from.name());
+            let max_age = session_config.cookie_max_age.unwrap_or(86400);
+            println!("Once {} seconds (`cookie_max_age`) have passed, remove `session.migrate_from` from config.yaml.", max_age);
+        }
+        SessionsAction::Export { output } => {
+            let export = session_migration::export(session_config).await?;
+            let json = serde_json::to_string_pretty(&export).map_err(|e| e.to_string())?;
+            match output {
+                Some(path) => {
+                    std::fs::write(path, json).map_err(|e| format!("couldn't write {}: {}", path.display(), e))?;
+                    println!("✨ Exported {} sessions to {}.", export.sessions.len(), path.display());
+                }
+                None => println!("{}", json),
+            }
+        }
+        SessionsAction::Import { file } => {
+            let text = std::fs::read_to_string(file).map_err(|e| format!("couldn't read {}: {}", file.display(), e))?;
+            let export = serde_json::from_str(&text).map_err(|e| format!("{} isn't a session export: {}", file.display(), e))?;
+            let (imported, skipped) = session_migration::import(session_config, &export).await?;
+            println!("✨ Imported {} sessions ({} were already there).", imported, skipped);
+        }
     }
+    Ok(())
+}
 
-    let output_dir = ".";
-
-    let mut command = Command::new("python");
-    command.arg("-m").arg("cookiecutter").arg(template_path).arg("--output-dir").arg(output_dir);
-
-    if no_input {
-        command.arg("--no-input");
+async fn run_loadtest(replay: &Path, target: &str, speed: f64, params: &[String]) -> Result<(), String> {
+    if speed <= 0.0 {
+        return Err("--speed must be more than 0".to_string());
     }
+    let params = params
+        .iter()
+        .map(|param| param.split_once('=').map(|(name, value)| (name.to_string(), value.to_string())))
+        .collect::<Option<HashMap<_, _>>>()
+        .ok_or("--param takes name=value")?;
+    let requests = loadtest::read_recording(replay)?;
+    println!("Replaying {} recorded requests against {}...", requests.len(), target);
+    let options = loadtest::ReplayOptions { target: target.to_string(), speed, params };
+    let report = loadtest::replay(requests, &options).await?;
+    loadtest::print_report(&report);
+    Ok(())
+}
 
-    let status = command.status()?;
+// config.yaml if it loads, without exiting on errors the way CONFIG does.
+fn lenient_config() -> Option<config::Config> {
+    config::Config::from_file(config::BASE_PATH.join("config.yaml").to_str()?).ok()
+}
 
-    if !status.success() {
-        return Err(std::io::Error::new(
-            std::io::ErrorKind::Other,
-            "Failed to execute cookiecutter command",
-        ));
-    }
+fn create_new_project(starter_path: Option<&str>, no_input: bool) -> std::io::Result<()> {
+    let project_dir = starter::create_project(
+        starter_path.map(Path::new),
+        Path::new("."),
+        no_input,
+        &mut std::io::stdin().lock(),
+    )?;
 
-    println!("✨ Your new project has been created successfully! Happy coding!");
+    println!(
+        "✨ Your new project has been created successfully in {}! Happy coding!",
+        project_dir.strip_prefix(".").unwrap_or(&project_dir).display()
+    );
     Ok(())
 }
 
@@ -173,9 +523,8 @@ async fn run_dev_server() -> std::io::Result<actix_web::dev::Server> {
         interpreters_addr,
         template_renderer_addr,
         actix_web_threads,
-        runtime_store,
-        runtime_secret,
-    ) = configure_server(true).await?;
+    ) = configure_server(true)?;
+    let (runtime_store, runtime_secret) = configure_sessions(false).await;
 
     let router_addr = RouterActor::new().start();
     let ws_server = WsServer::new().start();
@@ -187,6 +536,7 @@ async fn run_dev_server() -> std::io::Result<actix_web::dev::Server> {
     )
     .start();
     let lsp_actor = lsp::LspActor.start();
+    let image_processor = config::CONFIG.images.is_some().then(images::start_processor);
 
     let server_state = web::Data::new(DevServerState {
         watcher,
@@ -196,6 +546,21 @@ async fn run_dev_server() -> std::io::Result<actix_web::dev::Server> {
     let noventa_static_route = format!("{}/noventa-static/{{filename:.*}}", config::CONFIG.static_url_prefix.as_deref().unwrap_or("/static"));
     let server = HttpServer::new(move || {
         let mut app = App::new()
+            .wrap(actix_web::middleware::from_fn(localized::localized_static))
+            .wrap(actix_web::middleware::from_fn(signed_urls::check_signature))
+            .wrap(actix_web::middleware::from_fn(session::reissue_sessions))
+            .wrap(actix_web::middleware::from_fn(rate_limit::rate_limit))
+            .wrap(actix_web::middleware::from_fn(admin::maintenance_mode))
+            .wrap(actix_web::middleware::from_fn(session::sessions_unavailable))
+            .wrap(actix_web::middleware::from_fn(security_headers::security_headers))
+            .wrap(actix_web::middleware::Condition::new(
+                access_log::enabled(true),
+                actix_web::middleware::from_fn(access_log::access_log),
+            ))
+            .wrap(actix_web::middleware::Condition::new(
+                config::CONFIG.recording.is_some(),
+                actix_web::middleware::from_fn(recording::record),
+            ))
             .wrap(actix_web::middleware::Condition::new(
                 config::CONFIG.compression.unwrap_or(false),
                 actix_web::middleware::Compress::default(),
@@ -204,13 +569,35 @@ async fn run_dev_server() -> std::io::Result<actix_web::dev::Server> {
             .app_data(renderer_data.clone())
             .app_data(web::Data::new(health_actor_addr.clone()))
             .app_data(web::Data::new(true))
-            //.route("/health", web::get().to(routing::health_check))
+            .route("/health", web::get().to(routing::health_check))
+            .route("/health/live", web::get().to(routing::liveness_check))
+            .route("/health/ready", web::get().to(routing::readiness_check))
+            .route("/metrics", web::get().to(routing::metrics))
+            .route("/_noventa/stats", web::get().to(routing::stats_dashboard))
+            .route("/_noventa/errors", web::get().to(routing::error_history))
             .app_data(web::Data::new(router_addr.clone()))
             .app_data(web::Data::new(ws_server.clone()))
             .route("/devws", web::get().to(dev_ws))
+            .route("/_noventa/events", web::get().to(dev_events::events_handler))
             .route(&noventa_static_route, web::get().to(serve_embedded_file))
             .default_service(web::route().to(routing::dynamic_route_handler));
 
+        if let Some(token) = admin::token() {
+            app = app.configure(|cfg| admin::configure(cfg, token));
+        }
+        if oidc::enabled() {
+            app = app.configure(oidc::configure);
+        }
+        if user_channels::enabled() {
+            app = app.configure(|cfg| user_channels::configure(cfg, ws_server.clone()));
+        }
+        if let Some(processor) = image_processor.clone() {
+            app = app.configure(|cfg| images::configure(cfg, processor));
+        }
+        if config::CONFIG.resumable_uploads.unwrap_or(false) {
+            app = app.configure(fileupload::configure_resumable);
+        }
+
         if let Some(static_path_str) = &config::CONFIG.static_path {
             let static_path = if std::path::Path::new(static_path_str).is_absolute() {
                 std::path::PathBuf::from(static_path_str).clean()
@@ -275,6 +662,8 @@ async fn run_dev_server() -> std::io::Result<actix_web::dev::Server> {
                 )
                 .build(),
         )
+        .wrap(actix_web::middleware::from_fn(session::accept_old_secret_keys))
+        .wrap(actix_web::middleware::from_fn(session::scoped_sessions))
     })
     .workers(actix_web_threads)
     .keep_alive(std::time::Duration::from_secs(30))
@@ -304,33 +693,40 @@ async fn run_dev_server() -> std::io::Result<actix_web::dev::Server> {
     })?;
 
     logger::print_banner(
-        config::CONFIG.server_address.as_deref().unwrap_or("127.0.0.1"),
-        config::CONFIG.port.unwrap_or(8080) as u16,
+        &format!(
+            "http://{}:{}",
+            config::CONFIG.server_address.as_deref().unwrap_or("127.0.0.1"),
+            config::CONFIG.port.unwrap_or(8080)
+        ),
         true,
     );
 
     Ok(server.run())
 }
 
-async fn configure_server(
-    dev_mode: bool,
-) -> std::io::Result<(
-    Addr<HealthActor>,
-    web::Data<Recipient<RenderMessage>>,
-    Addr<PythonInterpreterActor>,
-    Addr<TemplateRendererActor>,
-    usize,
-    session::RuntimeSessionStore,
-    Key,
-)> {
+fn init_logging(dev_mode: bool) {
     let log_level = config::CONFIG
         .log_level
         .as_deref()
         .unwrap_or(if dev_mode { "info" } else { "warn" });
     logger::init_logger(log_level);
+}
+
+// The actors behind page rendering, plus how many threads are left for actix.
+type Renderers = (
+    Addr<HealthActor>,
+    web::Data<Recipient<RenderMessage>>,
+    Addr<PythonInterpreterActor>,
+    Addr<TemplateRendererActor>,
+    usize,
+);
 
-    let components_dir = Path::new("./components");
-    let components = components::scan_components(components_dir)?;
+// Starts the Python interpreters and template renderers. Only the commands that render pages
+// call this; the rest of the CLI never pays for the interpreter pool.
+fn configure_server(dev_mode: bool) -> std::io::Result<Renderers> {
+    init_logging(dev_mode);
+
+    let components = components::scan_project_components(Path::new("."))?;
     log::debug!("Found {} components. Ready to roll!", components.len());
 
     let total_cores = num_cpus::get();
@@ -377,15 +773,23 @@ async fn configure_server(
         template_renderer_threads
     );
 
+    let python_queue_limit = config::CONFIG
+        .core_allocation
+        .as_ref()
+        .and_then(|core_config| core_config.python_queue_limit)
+        .unwrap_or(python_threads * interpreter_queue::DEFAULT_LIMIT_PER_THREAD);
+    interpreter_queue::set_limit(python_queue_limit);
+    readiness::expect_interpreters(python_threads);
+    error_reporting::start(dev_mode);
+    log::debug!("Up to {} calls may wait for a Python interpreter.", python_queue_limit);
+
     let health_actor_addr = HealthActor::new().start();
     let interpreters_addr =
         SyncArbiter::start(python_threads, move || PythonInterpreterActor::new(dev_mode));
     let value = health_actor_addr.clone();
     let components_clone_for_template_renderer = components.clone();
-    let interpreters_addr_clone = interpreters_addr.clone();
     let template_renderer_addr = SyncArbiter::start(template_renderer_threads, move || {
         TemplateRendererActor::new(
-            interpreters_addr_clone.clone(),
             value.clone(),
             dev_mode,
             components_clone_for_template_renderer.clone(),
@@ -393,7 +797,7 @@ async fn configure_server(
     });
 
     let page_renderer_addr =
-        PageRendererActor::new(template_renderer_addr.clone(), health_actor_addr.clone()).start();
+        PageRendererActor::new(template_renderer_addr.clone(), interpreters_addr.clone(), health_actor_addr.clone()).start();
     let load_shedding_actor =
         LoadSheddingActor::new(page_renderer_addr.clone(), health_actor_addr.clone()).start();
 
@@ -406,84 +810,176 @@ async fn configure_server(
             web::Data::new(page_renderer_addr.recipient())
         };
 
-    use std::sync::Arc as StdArc;
-    let (runtime_store, runtime_secret): (session::RuntimeSessionStore, Key) =
-        if let Some(session_config) = &config::CONFIG.session {
-            let secret_key_bytes = session_config.secret_key.as_bytes();
-            let secret_key = match Key::try_from(secret_key_bytes) {
-                Ok(key) => key,
-                Err(e) => {
-                    println!("Your `secret_key` in `config.yaml` is not long enough. It needs to be at least 64 characters long for security. Please generate a new, longer key.");
-                    println!("Details: {}", e);
-                    std::process::exit(1);
-                }
-            };
-            let store = match session_config.backend {
-                config::SessionBackend::Cookie => session::RuntimeSessionStore::Cookie(
-                    StdArc::new(CookieSessionStore::default()),
-                ),
-                config::SessionBackend::Memory => {
-                    session::RuntimeSessionStore::InMemory(session::InMemoryBackend::new())
-                }
-                config::SessionBackend::Redis => {
-                    let redis_url = session_config
-                        .redis_url
-                        .as_ref()
-                        .expect("redis_url is required for redis session backend");
-                    let redis_pool_size = session_config.redis_pool_size.unwrap_or(10) as usize;
-                    let mut redis_cfg = Config::from_url(redis_url);
-                    redis_cfg.pool = Some(deadpool_redis::PoolConfig {
-                        max_size: redis_pool_size,
-                        ..Default::default()
-                    });
-                    let redis_pool = redis_cfg
-                        .create_pool(Some(Runtime::Tokio1))
-                        .expect("Failed to create redis pool");
-                    let store = RedisSessionStore::new_pooled(redis_pool)
-                        .await
-                        .expect("Failed to create Redis session store");
-                    session::RuntimeSessionStore::Redis(store)
-                }
-            };
-            (store, secret_key)
-        } else {
-            let secret_key = Key::from(&[0u8; 64]);
-            log::warn!("Heads up! No session key was found in your `config.yaml`. We're using a temporary key for now, but for production, you'll want to set a secure `secret_key`.");
-            let store = session::RuntimeSessionStore::Cookie(StdArc::new(
-                CookieSessionStore::default(),
-            ));
-            (store, secret_key)
-        };
-
     Ok((
         health_actor_addr,
         renderer_data,
         interpreters_addr,
         template_renderer_addr,
         actix_web_threads,
-        runtime_store,
-        runtime_secret,
     ))
 }
 
+// A static build throws its sessions away, so it gets cookies with a random key instead of
+// connecting to Redis or insisting on a proper `secret_key`.
+async fn configure_sessions(static_build: bool) -> (session::RuntimeSessionStore, Key) {
+    use std::sync::Arc as StdArc;
+    if static_build {
+        return (session::RuntimeSessionStore::Cookie(StdArc::new(CookieSessionStore::default())), Key::generate());
+    }
+    if let Some(session_config) = &config::CONFIG.session {
+        let secret_key_bytes = session_config.secret_key.as_bytes();
+        let secret_key = match Key::try_from(secret_key_bytes) {
+            Ok(key) => key,
+            Err(e) => {
+                println!("Your `secret_key` in `config.yaml` is not long enough. It needs to be at least 64 characters long for security. Please generate a new, longer key.");
+                println!("Details: {}", e);
+                std::process::exit(1);
+            }
+        };
+        // Validation already made sure these are long enough.
+        let old_keys = session_config
+            .old_secret_keys
+            .iter()
+            .flatten()
+            .filter_map(|old_key| Key::try_from(old_key.as_bytes()).ok())
+            .collect();
+        session::set_secret_keys(session_config.cookie_name.clone(), secret_key.clone(), old_keys);
+        let store = match session_config.backend {
+            config::SessionBackend::Cookie => session::RuntimeSessionStore::Cookie(
+                StdArc::new(CookieSessionStore::default()),
+            ),
+            config::SessionBackend::Memory => {
+                session::RuntimeSessionStore::InMemory(session::InMemoryBackend::new())
+            }
+            config::SessionBackend::Redis => {
+                let redis_url = session_config
+                    .redis_url
+                    .as_ref()
+                    .expect("redis_url is required for redis session backend");
+                let redis_pool_size = session_config.redis_pool_size.unwrap_or(10) as usize;
+                let redis_pool = match session::redis_pool(redis_url, redis_pool_size) {
+                    Ok(pool) => pool,
+                    Err(e) => {
+                        println!("Oh no! `session.redis_url` can't be used: {}", e);
+                        std::process::exit(1);
+                    }
+                };
+                let store = match RedisSessionStore::new_pooled(redis_pool.clone()).await {
+                    Ok(store) => store,
+                    Err(e) => {
+                        println!("Oh no! Couldn't set up Redis sessions: {}", e);
+                        std::process::exit(1);
+                    }
+                };
+                let health = session::RedisHealth::new(session_config.redis_fallback.unwrap_or_default());
+                let attempts = session_config.redis_connect_attempts.unwrap_or(5);
+                if !session::wait_for_redis(&redis_pool, &health, attempts).await {
+                    log::warn!("Heads up! Starting without Redis; sessions will be back once it answers.");
+                }
+                let store = session::ResilientRedisStore::new(store, redis_pool.clone(), health.clone());
+                session::watch_redis(redis_pool, health);
+                session::RuntimeSessionStore::Redis(store)
+            }
+        };
+        let store = match session_config.migrate_from {
+            Some(from) => match previous_session_store(session_config, from).await {
+                Some(previous) => {
+                    log::info!("✨ Moving sessions from {} to {} as their users come back.", from.name(), session_config.backend.name());
+                    session::RuntimeSessionStore::Migrating(session::MigratingStore::new(store, previous))
+                }
+                None => store,
+            },
+            None => store,
+        };
+        (store, secret_key)
+    } else {
+        let secret_key = Key::from(&[0u8; 64]);
+        log::warn!("Heads up! No session key was found in your `config.yaml`. We're using a temporary key for now, but for production, you'll want to set a secure `secret_key`.");
+        let store = session::RuntimeSessionStore::Cookie(StdArc::new(
+            CookieSessionStore::default(),
+        ));
+        (store, secret_key)
+    }
+}
+
+// The store `session.migrate_from` names, read from until every session has moved.
+async fn previous_session_store(
+    session_config: &config::SessionConfig,
+    from: config::SessionBackend,
+) -> Option<session::PreviousStore> {
+    match from {
+        config::SessionBackend::Cookie => Some(session::PreviousStore::Cookie),
+        config::SessionBackend::Redis => {
+            let pool = session_config.redis_url.as_deref().and_then(|url| session::redis_pool(url, 2).ok())?;
+            match RedisSessionStore::new_pooled(pool).await {
+                Ok(store) => Some(session::PreviousStore::Redis(store)),
+                Err(e) => {
+                    log::error!("Oh no! Couldn't read the old Redis sessions, so they won't be moved: {}", e);
+                    None
+                }
+            }
+        }
+        // Validation refuses this one: memory sessions don't outlive the server.
+        config::SessionBackend::Memory => None,
+    }
+}
+
 async fn dev_ws(req: HttpRequest, stream: web::Payload, srv: web::Data<Addr<WsServer>>) -> Result<actix_web::HttpResponse, Error> {
     ws::start(DevWebSocket::new(srv.get_ref().clone()), &req, stream)
 }
 
-async fn run_prod_server() -> std::io::Result<actix_web::dev::Server> {
+// `static_build` is `noventa ssg`, which only crawls this server once: no Redis, cluster
+// events, admin dashboard or rate limits, and always a plain TCP port for the crawler.
+async fn run_prod_server(static_build: bool) -> std::io::Result<actix_web::dev::Server> {
     let (
         health_actor_addr,
         renderer_data,
         _,
         _,
         actix_web_threads,
-        runtime_store,
-        runtime_secret,
-    ) = configure_server(false).await?;
+    ) = configure_server(false)?;
+    let (runtime_store, runtime_secret) = configure_sessions(static_build).await;
+    let admin_token = if static_build { None } else { admin::token() };
+    if !static_build {
+        scaling::warn_at_startup(&config::CONFIG);
+    }
+
+    // The admin dashboard can reload routes too, so it needs the router as well.
+    let reloadable = !static_build && (config::CONFIG.reload_pages.unwrap_or(false) || admin_token.is_some());
+    let router_addr = reloadable.then(|| {
+        let router_addr = RouterActor::new().start();
+        #[cfg(unix)]
+        reload_pages_on_sighup(router_addr.clone());
+        router_addr
+    });
+    if !static_build {
+        cluster::listen(router_addr.clone());
+    }
+    let image_processor = config::CONFIG.images.is_some().then(images::start_processor);
+    // Only for `/_noventa/ws`: there are no files to watch in production.
+    let ws_server = (!static_build && user_channels::enabled()).then(|| WsServer::new().start());
 
     let server = HttpServer::new(move || {
         let noventa_static_route = format!("{}/noventa-static/{{filename:.*}}", config::CONFIG.static_url_prefix.as_deref().unwrap_or("/static"));
         let mut app = App::new()
+            .wrap(actix_web::middleware::from_fn(localized::localized_static))
+            .wrap(actix_web::middleware::from_fn(signed_urls::check_signature))
+            .wrap(actix_web::middleware::from_fn(session::reissue_sessions))
+            .wrap(actix_web::middleware::Condition::new(
+                !static_build,
+                actix_web::middleware::from_fn(rate_limit::rate_limit),
+            ))
+            .wrap(actix_web::middleware::from_fn(admin::maintenance_mode))
+            .wrap(actix_web::middleware::from_fn(session::sessions_unavailable))
+            .wrap(actix_web::middleware::from_fn(security_headers::security_headers))
+            .wrap(actix_web::middleware::Condition::new(
+                !static_build && access_log::enabled(false),
+                actix_web::middleware::from_fn(access_log::access_log),
+            ))
+            .wrap(actix_web::middleware::Condition::new(
+                config::CONFIG.recording.is_some(),
+                actix_web::middleware::from_fn(recording::record),
+            ))
             .wrap(actix_web::middleware::Condition::new(
                 config::CONFIG.compression.unwrap_or(false),
                 actix_web::middleware::Compress::default(),
@@ -491,61 +987,90 @@ async fn run_prod_server() -> std::io::Result<actix_web::dev::Server> {
             .app_data(renderer_data.clone())
             .app_data(web::Data::new(health_actor_addr.clone()))
             .app_data(web::Data::new(false))
-            //.route("/health", web::get().to(routing::health_check))
+            .route("/health", web::get().to(routing::health_check))
+            .route("/health/live", web::get().to(routing::liveness_check))
+            .route("/health/ready", web::get().to(routing::readiness_check))
+            .route("/metrics", web::get().to(routing::metrics))
             .route(&noventa_static_route, web::get().to(serve_embedded_file));
 
-        let pages_dir = config::BASE_PATH.join("pages");
-        let routes = routing::get_compiled_routes(&pages_dir);
-        log::debug!("Registering {} routes in production mode", routes.len());
+        if let Some(router_addr) = &router_addr {
+            // Routes are looked up on every request, so a reload can add or remove pages.
+            let reload_pages = config::CONFIG.reload_pages.unwrap_or(false);
+            let reload_token = admin_token.clone();
+            app = app
+                .app_data(web::Data::new(router_addr.clone()))
+                .configure(|cfg| routing::configure_reload(cfg, reload_pages, reload_token))
+                .default_service(web::route().to(routing::dynamic_route_handler));
+        } else {
+            let pages_dir = config::BASE_PATH.join("pages");
+            let routes = routing::get_compiled_routes(&pages_dir);
+            log::debug!("Registering {} routes in production mode", routes.len());
         
-        for route in routes.iter() {
-            let template_path = route.template_path.to_str().unwrap().to_string();
-            let route_pattern = route.route_pattern.clone();
-            log::debug!("Registering prod route: '{}' -> '{}'", route_pattern, template_path);
-            let route_pattern_clone = route_pattern.clone();
-            let regex_clone = route.regex.clone();
-            let param_names_clone = route.param_names.clone();
-            app = app.route(
-                &route_pattern,
-                web::get().to(
-                    move |req: HttpRequest,
-                          payload: web::Payload,
-                          renderer: web::Data<Recipient<RenderMessage>>,
-                          session: Session| {
-                        let template_path_clone = template_path.clone();
-                        let route_pattern_log = route_pattern_clone.clone();
-                        let regex = regex_clone.clone();
-                        let param_names = param_names_clone.clone();
-                        async move {
-                            // Extract parameters manually using regex, like RouterActor does to support multiple parameters
-                            let path = req.path().to_string();
-                            let params: HashMap<String, String> = if let Some(captures) = regex.captures(&path) {
-                                param_names
-                                    .iter()
-                                    .filter_map(|name| {
-                                        captures
-                                            .name(name)
-                                            .map(|value| (name.clone(), value.as_str().to_string()))
-                                    })
-                                    .collect()
-                            } else {
-                                HashMap::new()
-                            };
+            for route in routes.iter() {
+                let template_path = route.template_path.to_str().unwrap().to_string();
+                let route_pattern = route.route_pattern.clone();
+                log::debug!("Registering prod route: '{}' -> '{}'", route_pattern, template_path);
+                let route_pattern_clone = route_pattern.clone();
+                let regex_clone = route.regex.clone();
+                let param_names_clone = route.param_names.clone();
+                app = app.route(
+                    &route_pattern,
+                    web::get().to(
+                        move |req: HttpRequest,
+                              payload: web::Payload,
+                              renderer: web::Data<Recipient<RenderMessage>>,
+                              session: Session| {
+                            let template_path_clone = template_path.clone();
+                            let route_pattern_log = route_pattern_clone.clone();
+                            let regex = regex_clone.clone();
+                            let param_names = param_names_clone.clone();
+                            async move {
+                                // Extract parameters manually using regex, like RouterActor does to support multiple parameters
+                                let path = req.path().to_string();
+                                let params: HashMap<String, String> = if let Some(captures) = regex.captures(&path) {
+                                    param_names
+                                        .iter()
+                                        .filter_map(|name| {
+                                            captures
+                                                .name(name)
+                                                .map(|value| (name.clone(), value.as_str().to_string()))
+                                        })
+                                        .collect()
+                                } else {
+                                    HashMap::new()
+                                };
                             
-                            log::debug!("Prod handler called for route '{}' with path '{}', params: {:?}", route_pattern_log, path, params);
-                            routing::handle_page_native(
-                                req,
-                                payload,
-                                renderer,
-                                session,
-                                web::Path::from(params),
-                                web::Data::new(template_path_clone),
-                            )
-                            .await
-                        }
-                    },
-                ),
-            );
+                                log::debug!("Prod handler called for route '{}' with path '{}', params: {:?}", route_pattern_log, path, params);
+                                routing::handle_page_native(
+                                    req,
+                                    payload,
+                                    renderer,
+                                    session,
+                                    web::Path::from(params),
+                                    web::Data::new(template_path_clone),
+                                )
+                                .await
+                            }
+                        },
+                    ),
+                );
+            }
+        }
+
+        if let Some(token) = admin_token.clone() {
+            app = app.configure(|cfg| admin::configure(cfg, token));
+        }
+        if oidc::enabled() {
+            app = app.configure(oidc::configure);
+        }
+        if let Some(ws_server) = ws_server.clone() {
+            app = app.configure(|cfg| user_channels::configure(cfg, ws_server));
+        }
+        if let Some(processor) = image_processor.clone() {
+            app = app.configure(|cfg| images::configure(cfg, processor));
+        }
+        if config::CONFIG.resumable_uploads.unwrap_or(false) {
+            app = app.configure(fileupload::configure_resumable);
         }
 
         if let Some(static_path_str) = &config::CONFIG.static_path {
@@ -612,41 +1137,104 @@ async fn run_prod_server() -> std::io::Result<actix_web::dev::Server> {
                 )
                 .build(),
         )
+        .wrap(actix_web::middleware::from_fn(session::accept_old_secret_keys))
+        .wrap(actix_web::middleware::from_fn(session::scoped_sessions))
     })
     .workers(actix_web_threads)
-    .keep_alive(std::time::Duration::from_secs(30))
-    .bind({
-        let port = config::CONFIG.port.unwrap_or(8080);
-        if port > 65535 {
-            println!(
-                "Error: Port number {} is too high. It must be between 0 and 65535.",
-                port
+    .keep_alive(std::time::Duration::from_secs(30));
+
+    let listener = if static_build { Ok(None) } else { prod_listener() };
+    let (server, address) = match listener {
+        Ok(Some(listener)) => {
+            let address = listener.describe();
+            let server = match listener {
+                listener::Listener::Tcp(tcp) => server.listen(tcp)?,
+                #[cfg(unix)]
+                listener::Listener::Unix(unix, _) => server.listen_uds(unix)?,
+            };
+            (server, address)
+        }
+        Ok(None) => {
+            let server = server
+                .bind({
+                    let port = config::CONFIG.port.unwrap_or(8080);
+                    if port > 65535 {
+                        println!(
+                            "Error: Port number {} is too high. It must be between 0 and 65535.",
+                            port
+                        );
+                        std::process::exit(1);
+                    }
+                    (
+                        config::CONFIG.server_address.as_deref().unwrap_or("127.0.0.1"),
+                        port as u16,
+                    )
+                })
+                .map_err(|e| {
+                    if e.kind() == std::io::ErrorKind::AddrInUse {
+                        let port = config::CONFIG.port.unwrap_or(8080) as u16;
+                        println!("Error: The port {} is already in use.", port);
+                        println!("Another application is likely running on this port.");
+                        println!("Please stop the other application or choose a different port.");
+                        std::process::exit(1);
+                    }
+                    e
+                })?;
+            let address = format!(
+                "http://{}:{}",
+                config::CONFIG.server_address.as_deref().unwrap_or("127.0.0.1"),
+                config::CONFIG.port.unwrap_or(8080)
             );
-            std::process::exit(1);
+            (server, address)
         }
-        (
-            config::CONFIG.server_address.as_deref().unwrap_or("127.0.0.1"),
-            port as u16,
-        )
-    })
-    .map_err(|e| {
-        if e.kind() == std::io::ErrorKind::AddrInUse {
-            let port = config::CONFIG.port.unwrap_or(8080) as u16;
-            println!("Error: The port {} is already in use.", port);
-            println!("Another application is likely running on this port.");
-            println!("Please stop the other application or choose a different port.");
+        Err(e) => {
+            println!("Error: Couldn't open the socket to listen on: {}", e);
+            println!("Check `bind` and `unix_socket_mode` in config.yaml, or the systemd socket unit.");
             std::process::exit(1);
         }
-        e
-    })?;
+    };
 
-    logger::print_banner(
-        config::CONFIG.server_address.as_deref().unwrap_or("127.0.0.1"),
-        config::CONFIG.port.unwrap_or(8080) as u16,
-        false,
-    );
+    logger::print_banner(&address, false);
 
-    Ok(server.run())
+    let addrs = server.addrs();
+    let server = server.run();
+    if !static_build {
+        warmup::start(&addrs);
+    }
+    Ok(server)
+}
+
+// `kill -HUP <pid>` picks up newly deployed pages without dropping connections.
+#[cfg(unix)]
+fn reload_pages_on_sighup(router_addr: Addr<RouterActor>) {
+    actix_rt::spawn(async move {
+        let mut hangups = match actix_rt::signal::unix::signal(actix_rt::signal::unix::SignalKind::hangup()) {
+            Ok(hangups) => hangups,
+            Err(e) => {
+                log::warn!("Couldn't listen for SIGHUP, so pages can only be reloaded through /_noventa/reload: {}", e);
+                return;
+            }
+        };
+        while hangups.recv().await.is_some() {
+            routing::reload_pages(&router_addr);
+        }
+    });
+}
+
+// Socket activation wins over `bind`; None means binding `server_address:port` as usual.
+fn prod_listener() -> std::io::Result<Option<listener::Listener>> {
+    if let Some(inherited) = listener::systemd_listener()? {
+        return Ok(Some(inherited));
+    }
+    let Some(bind) = &config::CONFIG.bind else {
+        return Ok(None);
+    };
+    let path = listener::parse_bind(bind).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+    let mode = match &config::CONFIG.unix_socket_mode {
+        Some(mode) => listener::parse_socket_mode(mode).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?,
+        None => listener::DEFAULT_SOCKET_MODE,
+    };
+    listener::unix_listener(&path, mode).map(Some)
 }
 
 async fn serve_embedded_file(path: web::Path<String>) -> HttpResponse {
@@ -667,6 +1255,12 @@ mod tests {
     use std::fs::{self, File};
     use tempfile::tempdir;
 
+    #[actix_rt::test]
+    async fn test_static_build_sessions_skip_redis() {
+        let (store, _) = configure_sessions(true).await;
+        assert!(matches!(store, session::RuntimeSessionStore::Cookie(_)));
+    }
+
     #[test]
     #[ignore]
     fn test_create_new_project() {
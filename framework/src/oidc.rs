@@ -0,0 +1,369 @@
+use crate::config::{self, OidcProviderConfig};
+use actix_session::Session;
+use actix_web::{web, HttpRequest, HttpResponse};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use lazy_static::lazy_static;
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+// Where the identity of a user who logged in through a provider is kept in the session.
+const CLAIMS_KEY: &str = "_noventa_user";
+// The login in progress, between sending the user to the provider and them coming back.
+const PENDING_KEY: &str = "_noventa_oidc";
+const DEFAULT_SCOPES: &[&str] = &["openid", "email", "profile"];
+// How far a provider's clock may be ahead of ours.
+const CLOCK_SKEW_SECS: u64 = 60;
+
+lazy_static! {
+    static ref CLIENT: reqwest::Client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(10))
+        .build()
+        .expect("the HTTP client for login providers");
+    // Each provider's endpoints, read from its discovery document once.
+    static ref DISCOVERED: Mutex<HashMap<String, Discovery>> = Mutex::new(HashMap::new());
+}
+
+#[derive(Deserialize, Clone, Debug)]
+struct Discovery {
+    authorization_endpoint: String,
+    token_endpoint: String,
+    userinfo_endpoint: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct PendingLogin {
+    provider: String,
+    state: String,
+    nonce: String,
+    verifier: String,
+    next: String,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    id_token: String,
+    access_token: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct LoginQuery {
+    next: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct CallbackQuery {
+    code: Option<String>,
+    state: Option<String>,
+    error: Option<String>,
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.route("/auth/{provider}/login", web::get().to(login))
+        .route("/auth/{provider}/callback", web::get().to(callback));
+}
+
+pub fn enabled() -> bool {
+    config::CONFIG.oidc.as_ref().is_some_and(|providers| !providers.is_empty())
+}
+
+fn provider(name: &str) -> Option<&'static OidcProviderConfig> {
+    config::CONFIG.oidc.as_ref()?.get(name)
+}
+
+// The claims of the user who logged in through a provider, for `request.user`.
+pub fn user_claims(session: &Session) -> Option<Value> {
+    session.get::<Value>(CLAIMS_KEY).ok().flatten()
+}
+
+fn random_token() -> String {
+    rand::thread_rng().sample_iter(&Alphanumeric).take(43).map(char::from).collect()
+}
+
+// PKCE (RFC 7636), so a stolen authorization code is useless without the verifier in the session.
+fn code_challenge(verifier: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes()))
+}
+
+// Only paths on this site, so the login can't be used to send users somewhere else.
+fn is_local_path(next: &str) -> bool {
+    next.starts_with('/') && !next.starts_with("//") && !next.starts_with("/\\")
+}
+
+async fn read_json<T: serde::de::DeserializeOwned>(response: reqwest::Result<reqwest::Response>) -> Result<T, String> {
+    let body = response
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| e.to_string())?
+        .bytes()
+        .await
+        .map_err(|e| e.to_string())?;
+    serde_json::from_slice(&body).map_err(|e| e.to_string())
+}
+
+async fn discover(name: &str, provider: &OidcProviderConfig) -> Result<Discovery, String> {
+    if let Some(discovery) = DISCOVERED.lock().unwrap().get(name) {
+        return Ok(discovery.clone());
+    }
+    let url = format!("{}/.well-known/openid-configuration", provider.issuer.trim_end_matches('/'));
+    let discovery: Discovery = read_json(CLIENT.get(&url).send().await).await?;
+    DISCOVERED.lock().unwrap().insert(name.to_string(), discovery.clone());
+    Ok(discovery)
+}
+
+fn redirect_uri(req: &HttpRequest, name: &str, provider: &OidcProviderConfig) -> String {
+    if let Some(url) = &provider.redirect_url {
+        return url.clone();
+    }
+    let client = crate::proxy::client_info(req, &crate::proxy::TRUSTED_PROXIES);
+    format!("{}://{}/auth/{}/callback", client.scheme, client.host, name)
+}
+
+fn authorization_url(endpoint: &str, provider: &OidcProviderConfig, redirect_uri: &str, pending: &PendingLogin) -> String {
+    let scopes = match &provider.scopes {
+        Some(scopes) => scopes.join(" "),
+        None => DEFAULT_SCOPES.join(" "),
+    };
+    let query = serde_urlencoded::to_string([
+        ("response_type", "code"),
+        ("client_id", provider.client_id.as_str()),
+        ("redirect_uri", redirect_uri),
+        ("scope", scopes.as_str()),
+        ("state", pending.state.as_str()),
+        ("nonce", pending.nonce.as_str()),
+        ("code_challenge", code_challenge(&pending.verifier).as_str()),
+        ("code_challenge_method", "S256"),
+    ])
+    .unwrap_or_default();
+    let separator = if endpoint.contains('?') { '&' } else { '?' };
+    format!("{}{}{}", endpoint, separator, query)
+}
+
+fn see_other(location: &str) -> HttpResponse {
+    HttpResponse::SeeOther().append_header(("Location", location)).finish()
+}
+
+async fn login(req: HttpRequest, session: Session, name: web::Path<String>, query: web::Query<LoginQuery>) -> HttpResponse {
+    let Some(provider) = provider(&name) else {
+        return HttpResponse::NotFound().finish();
+    };
+    let discovery = match discover(&name, provider).await {
+        Ok(discovery) => discovery,
+        Err(e) => {
+            log::error!("Oh no! Couldn't read the `{}` login provider's configuration: {}", name, e);
+            return HttpResponse::BadGateway().body("We couldn't reach the login provider. Please try again.");
+        }
+    };
+    let pending = PendingLogin {
+        provider: name.to_string(),
+        state: random_token(),
+        nonce: random_token(),
+        verifier: random_token(),
+        next: query.next.clone().filter(|next| is_local_path(next)).unwrap_or_else(|| "/".to_string()),
+    };
+    let url = authorization_url(&discovery.authorization_endpoint, provider, &redirect_uri(&req, &name, provider), &pending);
+    if let Err(e) = session.insert(PENDING_KEY, &pending) {
+        log::error!("Couldn't keep the login in the session: {}", e);
+        return HttpResponse::InternalServerError().finish();
+    }
+    see_other(&url)
+}
+
+// The payload of an ID token. It came straight from the token endpoint over TLS, which OpenID
+// Connect accepts in place of checking its signature; the claims still have to be ours.
+fn id_token_claims(id_token: &str) -> Result<Map<String, Value>, String> {
+    let payload = id_token.split('.').nth(1).ok_or("the ID token isn't a JWT")?;
+    let bytes = URL_SAFE_NO_PAD.decode(payload.trim_end_matches('=')).map_err(|e| e.to_string())?;
+    serde_json::from_slice(&bytes).map_err(|e| e.to_string())
+}
+
+fn check_claims(claims: &Map<String, Value>, provider: &OidcProviderConfig, nonce: &str, now: u64) -> Result<(), String> {
+    let issuer = claims.get("iss").and_then(Value::as_str).unwrap_or_default();
+    if issuer.trim_end_matches('/') != provider.issuer.trim_end_matches('/') {
+        return Err(format!("the ID token was issued by '{}'", issuer));
+    }
+    let audience_ok = match claims.get("aud") {
+        Some(Value::String(audience)) => *audience == provider.client_id,
+        Some(Value::Array(audiences)) => audiences.iter().any(|audience| audience.as_str() == Some(&provider.client_id)),
+        _ => false,
+    };
+    if !audience_ok {
+        return Err("the ID token is for another client".to_string());
+    }
+    if claims.get("nonce").and_then(Value::as_str) != Some(nonce) {
+        return Err("the ID token's nonce doesn't match this login".to_string());
+    }
+    if claims.get("exp").and_then(Value::as_u64).is_none_or(|exp| exp + CLOCK_SKEW_SECS < now) {
+        return Err("the ID token has expired".to_string());
+    }
+    if claims.get("sub").and_then(Value::as_str).is_none_or(str::is_empty) {
+        return Err("the ID token doesn't say who logged in".to_string());
+    }
+    Ok(())
+}
+
+async fn exchange_code(
+    provider: &OidcProviderConfig,
+    discovery: &Discovery,
+    code: &str,
+    redirect_uri: &str,
+    pending: &PendingLogin,
+) -> Result<Map<String, Value>, String> {
+    let mut form = vec![
+        ("grant_type", "authorization_code"),
+        ("code", code),
+        ("redirect_uri", redirect_uri),
+        ("client_id", provider.client_id.as_str()),
+        ("code_verifier", pending.verifier.as_str()),
+    ];
+    if let Some(secret) = &provider.client_secret {
+        form.push(("client_secret", secret.as_str()));
+    }
+    let response = CLIENT.post(&discovery.token_endpoint).form(&form).send().await.map_err(|e| e.to_string())?;
+    if !response.status().is_success() {
+        return Err(format!("the token endpoint answered {}: {}", response.status(), response.text().await.unwrap_or_default()));
+    }
+    let tokens: TokenResponse = read_json(Ok(response)).await?;
+    let mut claims = id_token_claims(&tokens.id_token)?;
+    let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    check_claims(&claims, provider, &pending.nonce, now)?;
+
+    // The ID token may only carry `sub`; the userinfo endpoint fills in email, name and the rest.
+    if let (Some(endpoint), Some(access_token)) = (&discovery.userinfo_endpoint, &tokens.access_token) {
+        match fetch_userinfo(endpoint, access_token).await {
+            Ok(userinfo) if userinfo.get("sub") == claims.get("sub") => {
+                for (claim, value) in userinfo {
+                    claims.entry(claim).or_insert(value);
+                }
+            }
+            Ok(_) => log::warn!("Heads up! The userinfo endpoint described someone else; using the ID token alone."),
+            Err(e) => log::warn!("Heads up! Couldn't read the userinfo endpoint ({}); using the ID token alone.", e),
+        }
+    }
+    Ok(claims)
+}
+
+async fn fetch_userinfo(endpoint: &str, access_token: &str) -> Result<Map<String, Value>, String> {
+    read_json(CLIENT.get(endpoint).bearer_auth(access_token).send().await).await
+}
+
+async fn callback(req: HttpRequest, session: Session, name: web::Path<String>, query: web::Query<CallbackQuery>) -> HttpResponse {
+    let Some(provider) = provider(&name) else {
+        return HttpResponse::NotFound().finish();
+    };
+    let pending = session.remove_as::<PendingLogin>(PENDING_KEY).and_then(Result::ok);
+    let Some(pending) = pending.filter(|pending| pending.provider == *name && query.state.as_ref() == Some(&pending.state)) else {
+        return HttpResponse::BadRequest().body("This login link has expired. Please log in again.");
+    };
+    if let Some(error) = &query.error {
+        log::info!("The `{}` login provider sent the user back with '{}'.", name, error);
+        return see_other(crate::auth::login_url());
+    }
+    let Some(code) = &query.code else {
+        return HttpResponse::BadRequest().body("The login provider didn't send a code.");
+    };
+
+    let claims = match discover(&name, provider).await {
+        Ok(discovery) => exchange_code(provider, &discovery, code, &redirect_uri(&req, &name, provider), &pending).await,
+        Err(e) => Err(e),
+    };
+    let claims = match claims {
+        Ok(claims) => claims,
+        Err(e) => {
+            log::error!("Oh no! Logging in with `{}` failed: {}", name, e);
+            return HttpResponse::BadGateway().body("We couldn't finish logging you in. Please try again.");
+        }
+    };
+
+    // `provider:sub` is who they are to the rest of the app, and to `auth.user_loader`.
+    let user_id = format!("{}:{}", name, claims.get("sub").and_then(Value::as_str).unwrap_or_default());
+    let previous = session.get::<String>(crate::auth::LOGIN_KEY).ok().flatten();
+    let stored = session
+        .insert(CLAIMS_KEY, &claims)
+        .and_then(|_| session.insert(crate::auth::user_key(), user_id))
+        .and_then(|_| session.insert(crate::auth::ROLES_KEY, crate::auth::claim_roles(&claims)))
+        .and_then(|_| session.insert(crate::auth::LOGIN_KEY, crate::user_channels::new_login()));
+    if let Err(e) = stored {
+        log::error!("Couldn't keep the login in the session: {}", e);
+        return HttpResponse::InternalServerError().finish();
+    }
+    if let Some(previous) = previous {
+        crate::user_channels::logged_out(previous);
+    }
+    // A new login gets a new session id.
+    session.renew();
+    see_other(&pending.next)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn provider() -> OidcProviderConfig {
+        OidcProviderConfig {
+            issuer: "https://id.example.com/".to_string(),
+            client_id: "app".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_code_challenge() {
+        // The example from RFC 7636, appendix B.
+        assert_eq!(code_challenge("dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk"), "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM");
+    }
+
+    #[test]
+    fn test_is_local_path() {
+        assert!(is_local_path("/account?tab=orders"));
+        assert!(!is_local_path("//evil.example.com"));
+        assert!(!is_local_path("/\\evil.example.com"));
+        assert!(!is_local_path("https://evil.example.com"));
+    }
+
+    #[test]
+    fn test_authorization_url() {
+        let pending = PendingLogin {
+            provider: "example".to_string(),
+            state: "s".to_string(),
+            nonce: "n".to_string(),
+            verifier: "v".repeat(43),
+            next: "/".to_string(),
+        };
+        let url = authorization_url("https://id.example.com/authorize", &provider(), "http://localhost/auth/example/callback", &pending);
+        assert!(url.starts_with("https://id.example.com/authorize?response_type=code&client_id=app&redirect_uri=http%3A%2F%2Flocalhost%2Fauth%2Fexample%2Fcallback&scope=openid+email+profile&state=s&nonce=n&code_challenge="));
+        assert!(url.ends_with("&code_challenge_method=S256"));
+    }
+
+    #[test]
+    fn test_id_token_claims() {
+        let payload = URL_SAFE_NO_PAD.encode(r#"{"sub":"42","email":"ann@example.com"}"#);
+        let claims = id_token_claims(&format!("header.{}.signature", payload)).unwrap();
+        assert_eq!(claims["sub"], "42");
+        assert!(id_token_claims("not-a-jwt").is_err());
+    }
+
+    #[test]
+    fn test_check_claims() {
+        let good = json_claims("https://id.example.com", Value::from("app"), "n", 1000);
+        assert!(check_claims(&good, &provider(), "n", 1000).is_ok());
+        let listed = json_claims("https://id.example.com", Value::from(vec!["other", "app"]), "n", 1000);
+        assert!(check_claims(&listed, &provider(), "n", 1000).is_ok());
+
+        let wrong_issuer = json_claims("https://evil.example.com", Value::from("app"), "n", 1000);
+        assert!(check_claims(&wrong_issuer, &provider(), "n", 1000).unwrap_err().contains("issued by"));
+        let wrong_audience = json_claims("https://id.example.com", Value::from("other"), "n", 1000);
+        assert!(check_claims(&wrong_audience, &provider(), "n", 1000).unwrap_err().contains("another client"));
+        assert!(check_claims(&good, &provider(), "replayed", 1000).unwrap_err().contains("nonce"));
+        assert!(check_claims(&good, &provider(), "n", 2000).unwrap_err().contains("expired"));
+    }
+
+    fn json_claims(issuer: &str, audience: Value, nonce: &str, exp: u64) -> Map<String, Value> {
+        serde_json::json!({ "iss": issuer, "aud": audience, "nonce": nonce, "exp": exp, "sub": "42" }).as_object().unwrap().clone()
+    }
+}
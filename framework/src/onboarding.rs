@@ -0,0 +1,139 @@
+use crate::config::Config;
+use minijinja::Environment;
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use std::path::Path;
+
+// What `noventa dev` shows at / until the project has a page there: how far along the project is,
+// from what's actually in it, and what to do next.
+const TEMPLATE: &str = include_str!("templates/dev_mode_index.html");
+pub const DOCS_URL: &str = "https://noventa.dev/docs";
+
+static ENV: Lazy<Environment<'static>> = Lazy::new(|| {
+    let mut env = Environment::new();
+    env.add_template("dev_mode_index.html", TEMPLATE).expect("the dev index template is valid");
+    env
+});
+
+#[derive(Serialize, Debug)]
+pub struct Step {
+    pub title: &'static str,
+    pub done: bool,
+    // What there is, once it's done; what to do, until then.
+    pub detail: String,
+    // A command that does it for you.
+    pub command: Option<String>,
+}
+
+fn has_html(dir: &Path) -> bool {
+    walkdir::WalkDir::new(dir)
+        .into_iter()
+        .filter_map(Result::ok)
+        .any(|entry| entry.path().extension().is_some_and(|extension| extension == "html"))
+}
+
+pub fn checklist(root: &Path, config: &Config) -> Vec<Step> {
+    let mut components: Vec<String> = crate::components::scan_project_components(root)
+        .map(|components| components.into_iter().map(|component| component.id.replace('/', ".")).collect())
+        .unwrap_or_default();
+    components.sort();
+    let layouts = has_html(&root.join("layouts"));
+    let secret_key_set = config.session.as_ref().is_some_and(|session| !session.secret_key.contains("REPLACE-ME"));
+
+    vec![
+        Step {
+            title: "Add a home page",
+            done: false,
+            detail: "Nothing answers at / yet. Create pages/index.html and it replaces this page.".to_string(),
+            command: Some("noventa new:page /".to_string()),
+        },
+        Step {
+            title: "Create a component",
+            done: !components.is_empty(),
+            detail: match components.len() {
+                0 => "Components are a template and the Python that loads its data, used in pages as {{ component(\"hello\") }}.".to_string(),
+                1 => format!("You have one: {}.", components[0]),
+                count => format!("You have {}: {}.", count, components.join(", ")),
+            },
+            command: components.is_empty().then(|| "noventa new:component hello".to_string()),
+        },
+        Step {
+            title: "Add a layout",
+            done: layouts,
+            detail: if layouts {
+                "Pages can {% extends %} the layouts in layouts/.".to_string()
+            } else {
+                "Put the HTML every page shares in layouts/ and {% extends \"layouts/main.html\" %} it from your pages.".to_string()
+            },
+            command: None,
+        },
+        Step {
+            title: "Set a session secret key",
+            done: secret_key_set,
+            detail: match &config.session {
+                _ if secret_key_set => "Sessions are signed with your own key.".to_string(),
+                Some(_) => "`session.secret_key` in config.yaml is still the placeholder. Replace it with a long random string.".to_string(),
+                None => "Add a `session` section with a `secret_key` to config.yaml to remember visitors between requests.".to_string(),
+            },
+            command: None,
+        },
+        Step {
+            title: "Connect a database",
+            done: config.database.is_some(),
+            detail: if config.database.is_some() {
+                "`db` in your _logic.py functions is connected to it.".to_string()
+            } else {
+                "Set `database` in config.yaml, e.g. sqlite:///./noventa.db, to get a `db` in your _logic.py functions.".to_string()
+            },
+            command: None,
+        },
+    ]
+}
+
+pub fn render(root: &Path, config: &Config) -> String {
+    let steps = checklist(root, config);
+    // The pages there are, to open; ones that need parameters can't be linked to.
+    let pages: Vec<String> = crate::route_table::build_route_table(root)
+        .rows
+        .into_iter()
+        .filter(|row| row.params.is_empty())
+        .map(|row| row.route_pattern)
+        .collect();
+    let context = minijinja::context! {
+        done => steps.iter().filter(|step| step.done).count(),
+        steps => steps,
+        pages => pages,
+        docs_url => DOCS_URL,
+    };
+    ENV.get_template("dev_mode_index.html").and_then(|template| template.render(context)).unwrap_or_else(|e| {
+        log::error!("Oh no! The welcome page couldn't be rendered: {}", e);
+        "Welcome to Noventa. Create pages/index.html to get started.".to_string()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_checklist_follows_the_project() {
+        let project = tempdir().unwrap();
+        let steps = checklist(project.path(), &Config::default());
+        assert_eq!(steps.iter().filter(|step| step.done).count(), 0);
+
+        std::fs::create_dir_all(project.path().join("components/hello")).unwrap();
+        std::fs::write(project.path().join("components/hello/hello_template.html"), "<p>Hi</p>").unwrap();
+        std::fs::create_dir_all(project.path().join("pages/about")).unwrap();
+        std::fs::write(project.path().join("pages/about/index.html"), "{{ component('hello') }}").unwrap();
+        let config = Config { database: Some("sqlite:///./noventa.db".to_string()), ..Default::default() };
+        let steps = checklist(project.path(), &config);
+        let done: Vec<&str> = steps.iter().filter(|step| step.done).map(|step| step.title).collect();
+        assert_eq!(done, vec!["Create a component", "Connect a database"]);
+        assert_eq!(steps[1].detail, "You have one: hello.");
+
+        let html = render(project.path(), &config);
+        assert!(html.contains("2 of 5"));
+        assert!(html.contains("Your pages") && html.contains("about"));
+    }
+}
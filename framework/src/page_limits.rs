@@ -0,0 +1,87 @@
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+// How long a request waits for a turn on a `max_concurrency` page before it gets a 503.
+const QUEUE_TIMEOUT: Duration = Duration::from_secs(10);
+// Past this many waiting requests per slot, new ones are turned away right away.
+const MAX_QUEUED_PER_SLOT: usize = 8;
+
+struct Slots {
+    limit: usize,
+    semaphore: Arc<Semaphore>,
+    waiting: Arc<()>,
+}
+
+lazy_static! {
+    static ref SLOTS: Mutex<HashMap<String, Slots>> = Mutex::new(HashMap::new());
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Rejected {
+    QueueFull,
+    TimedOut,
+}
+
+// A turn on the page; the next waiting request goes once it's dropped.
+pub struct Turn {
+    _permit: OwnedSemaphorePermit,
+}
+
+fn slots_for(template_path: &str, limit: usize) -> (Arc<Semaphore>, Arc<()>) {
+    let mut slots = SLOTS.lock().unwrap();
+    let entry = slots.entry(template_path.to_string()).or_insert_with(|| Slots {
+        limit,
+        semaphore: Arc::new(Semaphore::new(limit)),
+        waiting: Arc::new(()),
+    });
+    // The page changed its limit (dev mode, or a redeploy). Requests already in keep their turns.
+    if entry.limit != limit {
+        *entry = Slots { limit, semaphore: Arc::new(Semaphore::new(limit)), waiting: Arc::new(()) };
+    }
+    (entry.semaphore.clone(), entry.waiting.clone())
+}
+
+// Waits for one of the page's `limit` turns, so a heavy page can't tie up every Python interpreter.
+pub async fn wait_for_turn(template_path: &str, limit: usize) -> Result<Turn, Rejected> {
+    let (semaphore, waiting) = slots_for(template_path, limit);
+    if let Ok(permit) = semaphore.clone().try_acquire_owned() {
+        return Ok(Turn { _permit: permit });
+    }
+    // Everyone holding a clone of `waiting` is in the queue, plus the map's own.
+    if Arc::strong_count(&waiting) - 1 > limit * MAX_QUEUED_PER_SLOT {
+        return Err(Rejected::QueueFull);
+    }
+    match tokio::time::timeout(QUEUE_TIMEOUT, semaphore.acquire_owned()).await {
+        Ok(Ok(permit)) => Ok(Turn { _permit: permit }),
+        _ => Err(Rejected::TimedOut),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[actix_rt::test]
+    async fn test_requests_past_the_limit_wait_their_turn() {
+        let first = wait_for_turn("pages/report.html", 1).await.unwrap();
+        let waiting = actix_rt::spawn(async { wait_for_turn("pages/report.html", 1).await.is_ok() });
+        actix_rt::time::sleep(Duration::from_millis(50)).await;
+        assert!(!waiting.is_finished());
+        drop(first);
+        assert!(waiting.await.unwrap());
+    }
+
+    #[actix_rt::test]
+    async fn test_full_queue_is_turned_away() {
+        let _turn = wait_for_turn("pages/export.html", 1).await.unwrap();
+        let mut queued = Vec::new();
+        for _ in 0..MAX_QUEUED_PER_SLOT {
+            queued.push(actix_rt::spawn(wait_for_turn("pages/export.html", 1)));
+        }
+        actix_rt::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(wait_for_turn("pages/export.html", 1).await.err(), Some(Rejected::QueueFull));
+    }
+}
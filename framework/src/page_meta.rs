@@ -0,0 +1,377 @@
+use crate::auth::Requirement;
+use crate::config;
+use lazy_static::lazy_static;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+static EXTENDS_TAG: Lazy<Regex> = Lazy::new(|| Regex::new(r"\{%-?\s*extends\s").unwrap());
+static BLOCK_TAG: Lazy<Regex> = Lazy::new(|| Regex::new(r"\{%-?\s*block\s").unwrap());
+
+// Pages declare things about themselves in the `{# ... #}` comments they start with, e.g.
+// `{# login_required #}`, `{# requires: role=admin #}` or `{# max_concurrency: 2 #}`, or in a
+// YAML (`---`) or TOML (`+++`) frontmatter block above everything else.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PageMeta {
+    pub login_required: bool,
+    // Roles and permissions the logged-in user must have; implies a login.
+    pub requires: Vec<Requirement>,
+    // Only links made by `url_for_signed()` open the page.
+    pub signed_url_required: bool,
+    // At most this many requests render the page at once; the rest wait their turn.
+    pub max_concurrency: Option<usize>,
+    // `timeout: 10`: seconds the page gets to render, instead of `render_timeout` or a minute.
+    pub render_timeout: Option<u64>,
+    // `methods: [GET, POST]`; other methods get a 405. None answers them all.
+    pub methods: Option<Vec<String>>,
+    // `cache:` as a Cache-Control header for the page's GET responses.
+    pub cache_control: Option<String>,
+    // The whole frontmatter, as `page.meta` in templates.
+    pub frontmatter: Map<String, Value>,
+    // `{% fragment "name" cache=... %}`: Cache-Control for that fragment when it's requested alone.
+    pub fragment_cache: HashMap<String, String>,
+    // Locales with their own version of the page, like `about.de.html` for `about.html`.
+    pub locales: Vec<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Format {
+    Yaml,
+    Toml,
+}
+
+// The frontmatter block at the very top of a template, what's after it, and how many lines it took.
+fn split_frontmatter(source: &str) -> Option<(Format, &str, &str, usize)> {
+    let (format, fence) = if source.starts_with("---") {
+        (Format::Yaml, "---")
+    } else if source.starts_with("+++") {
+        (Format::Toml, "+++")
+    } else {
+        return None;
+    };
+    let mut lines = source.split_inclusive('\n');
+    let opening = lines.next()?;
+    if opening.trim_end() != fence {
+        return None;
+    }
+    let block_start = opening.len();
+    let mut offset = block_start;
+    for (index, line) in lines.enumerate() {
+        if line.trim_end() == fence {
+            return Some((format, &source[block_start..offset], &source[offset + line.len()..], index + 2));
+        }
+        offset += line.len();
+    }
+    None
+}
+
+// TOML values as the JSON ones templates see; dates and times are written out as strings.
+fn toml_to_json(value: toml::Value) -> Value {
+    match value {
+        toml::Value::String(string) => Value::String(string),
+        toml::Value::Integer(number) => Value::from(number),
+        toml::Value::Float(number) => Value::from(number),
+        toml::Value::Boolean(flag) => Value::Bool(flag),
+        toml::Value::Datetime(datetime) => Value::String(datetime.to_string()),
+        toml::Value::Array(values) => Value::Array(values.into_iter().map(toml_to_json).collect()),
+        toml::Value::Table(table) => Value::Object(table.into_iter().map(|(key, value)| (key, toml_to_json(value))).collect()),
+    }
+}
+
+// The frontmatter as `key: value` pairs, or why it can't be read.
+fn parse_frontmatter(format: Format, block: &str) -> Result<Map<String, Value>, String> {
+    if block.trim().is_empty() {
+        return Ok(Map::new());
+    }
+    match format {
+        Format::Yaml => match serde_yaml::from_str::<Value>(block) {
+            Ok(Value::Object(map)) => Ok(map),
+            Ok(_) => Err("it isn't a list of `key: value` pairs".to_string()),
+            Err(e) => Err(e.to_string()),
+        },
+        Format::Toml => match toml::from_str::<toml::Table>(block) {
+            Ok(table) => Ok(table.into_iter().map(|(key, value)| (key, toml_to_json(value))).collect()),
+            Err(e) => Err(e.message().to_string()),
+        },
+    }
+}
+
+// What minijinja gets to see: the frontmatter turns into blank lines, so error line numbers still
+// match the file, and a page with a `layout:` and no `{% extends %}` is put inside that layout.
+pub fn template_source(source: &str) -> String {
+    let Some((format, block, rest, lines)) = split_frontmatter(source) else {
+        return source.to_string();
+    };
+    // `parse` already complained about frontmatter that can't be read.
+    let layout = parse_frontmatter(format, block)
+        .ok()
+        .and_then(|frontmatter| frontmatter.get("layout").and_then(Value::as_str).map(str::to_string));
+    let blank = "\n".repeat(lines);
+    match layout {
+        Some(layout) if !EXTENDS_TAG.is_match(rest) => {
+            let extends = format!("{{% extends {:?} %}}", layout);
+            if BLOCK_TAG.is_match(rest) {
+                format!("{}{}{}", extends, blank, rest)
+            } else {
+                // A page without blocks of its own is the layout's `content` block.
+                format!("{}{{% block content %}}{}{}{{% endblock %}}", extends, blank, rest)
+            }
+        }
+        _ => format!("{}{}", blank, rest),
+    }
+}
+
+fn apply_frontmatter(template_path: &str, frontmatter: &Map<String, Value>, meta: &mut PageMeta) {
+    let flag = |key: &str| frontmatter.get(key).and_then(Value::as_bool).unwrap_or(false);
+    meta.login_required |= flag("login_required");
+    meta.signed_url_required |= flag("signed_url_required");
+    if let Some(limit) = frontmatter.get("max_concurrency") {
+        match limit.as_u64() {
+            Some(limit) if limit > 0 => meta.max_concurrency = Some(limit as usize),
+            _ => log::warn!("Heads up! {} says `max_concurrency: {}`, which isn't a number above 0, so it's ignored.", template_path, limit),
+        }
+    }
+    if let Some(timeout) = frontmatter.get("timeout") {
+        match timeout.as_u64() {
+            Some(timeout) if timeout > 0 => meta.render_timeout = Some(timeout),
+            _ => log::warn!("Heads up! {} says `timeout: {}`, which isn't a number of seconds above 0, so it's ignored.", template_path, timeout),
+        }
+    }
+
+    // `auth: true` needs a login; `auth: role=admin` (or a list of those) needs the roles too.
+    let requirements = match frontmatter.get("auth") {
+        Some(Value::Bool(login)) => {
+            meta.login_required |= *login;
+            Vec::new()
+        }
+        Some(Value::String(requirement)) => vec![requirement.clone()],
+        Some(Value::Array(requirements)) => requirements.iter().map(|r| r.as_str().unwrap_or_default().to_string()).collect(),
+        Some(other) => vec![other.to_string()],
+        None => Vec::new(),
+    };
+    for requirement in requirements {
+        match crate::auth::parse_requirements(&requirement) {
+            Ok(parsed) => meta.requires.extend(parsed),
+            Err(e) => {
+                log::error!("Oh no! {} has `auth: {}`, but {}. Nobody can see it until that's fixed.", template_path, requirement, e);
+                meta.requires.push(Requirement::Role(String::new()));
+            }
+        }
+    }
+
+    meta.methods = match frontmatter.get("methods") {
+        Some(Value::String(method)) => Some(vec![method.to_uppercase()]),
+        Some(Value::Array(methods)) => Some(methods.iter().filter_map(Value::as_str).map(str::to_uppercase).collect()),
+        _ => None,
+    };
+
+    meta.cache_control = frontmatter.get("cache").and_then(|cache| cache_control(template_path, cache));
+    meta.frontmatter = frontmatter.clone();
+}
+
+// A `cache` setting as a Cache-Control header: seconds, `false`, or the header itself.
+pub fn cache_control(template_path: &str, cache: &Value) -> Option<String> {
+    match cache {
+        Value::Number(seconds) => Some(format!("public, max-age={}", seconds)),
+        Value::Bool(false) => Some("no-store".to_string()),
+        Value::String(cache_control) => Some(cache_control.clone()),
+        other => {
+            log::warn!("Heads up! {} says `cache: {}`. Use a number of seconds, `false`, or a Cache-Control value.", template_path, other);
+            None
+        }
+    }
+}
+
+lazy_static! {
+    // Each page's declarations (by template path), read once per page outside dev mode.
+    static ref PAGES: Mutex<HashMap<String, PageMeta>> = Mutex::new(HashMap::new());
+}
+
+// The `{# ... #}` comments a template starts with, before anything else.
+fn leading_comments(source: &str) -> Vec<&str> {
+    let mut comments = Vec::new();
+    let mut rest = source.trim_start();
+    while let Some(after) = rest.strip_prefix("{#") {
+        let Some(end) = after.find("#}") else {
+            break;
+        };
+        comments.push(after[..end].trim());
+        rest = after[end + 2..].trim_start();
+    }
+    comments
+}
+
+pub fn parse(template_path: &str, source: &str) -> PageMeta {
+    let mut meta = PageMeta::default();
+    let source = match split_frontmatter(source) {
+        Some((format, block, rest, _)) => {
+            match parse_frontmatter(format, block) {
+                Ok(frontmatter) => apply_frontmatter(template_path, &frontmatter, &mut meta),
+                Err(e) => {
+                    // It may have said who can see the page; better nobody gets in than everybody.
+                    log::error!("Oh no! The frontmatter of {} can't be read: {}. Nobody can see it until that's fixed.", template_path, e);
+                    meta.requires.push(Requirement::Role(String::new()));
+                }
+            }
+            rest
+        }
+        None => source,
+    };
+    meta.fragment_cache = crate::fragments::cache_settings(template_path, source);
+    for comment in leading_comments(source) {
+        if comment.split_whitespace().any(|word| word == "login_required") {
+            meta.login_required = true;
+        }
+        if comment.split_whitespace().any(|word| word == "signed_url_required") {
+            meta.signed_url_required = true;
+        }
+        if let Some(value) = comment.strip_prefix("requires:") {
+            match crate::auth::parse_requirements(value) {
+                Ok(requirements) => meta.requires.extend(requirements),
+                Err(e) => {
+                    // Better nobody gets in than everybody.
+                    log::error!("Oh no! {} has `requires: {}`, but {}. Nobody can see it until that's fixed.", template_path, value.trim(), e);
+                    meta.requires.push(Requirement::Role(String::new()));
+                }
+            }
+        }
+        if let Some(value) = comment.strip_prefix("max_concurrency:") {
+            match value.trim().parse::<usize>() {
+                Ok(limit) if limit > 0 => meta.max_concurrency = Some(limit),
+                _ => log::warn!("Heads up! {} says `max_concurrency: {}`, which isn't a number above 0, so it's ignored.", template_path, value.trim()),
+            }
+        }
+        if let Some(value) = comment.strip_prefix("timeout:") {
+            match value.trim().parse::<u64>() {
+                Ok(timeout) if timeout > 0 => meta.render_timeout = Some(timeout),
+                _ => log::warn!("Heads up! {} says `timeout: {}`, which isn't a number of seconds above 0, so it's ignored.", template_path, value.trim()),
+            }
+        }
+    }
+    meta
+}
+
+pub fn for_page(template_path: &str, dev_mode: bool) -> PageMeta {
+    if !dev_mode && let Some(meta) = PAGES.lock().unwrap().get(template_path) {
+        return meta.clone();
+    }
+    let mut meta = std::fs::read_to_string(config::BASE_PATH.join(template_path))
+        .map(|source| parse(template_path, &source))
+        .unwrap_or_default();
+    meta.locales = crate::localized::variants(&config::BASE_PATH.join(template_path));
+    if !dev_mode {
+        PAGES.lock().unwrap().insert(template_path.to_string(), meta.clone());
+    }
+    meta
+}
+
+// Pages were redeployed; what they declare may have changed with them.
+pub fn clear() {
+    PAGES.lock().unwrap().clear();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_login_required() {
+        assert!(parse("a.html", "{# login_required #}\n<h1>Account</h1>").login_required);
+        assert!(parse("a.html", "\n{# Settings page #}\n{# login_required #}{% extends \"layout.html\" %}").login_required);
+        assert!(!parse("a.html", "<h1>Home</h1>\n{# login_required #}").login_required);
+        assert!(!parse("a.html", "{# not login_required_yet #}").login_required);
+        assert!(!parse("a.html", "{# login_required").login_required);
+    }
+
+    #[test]
+    fn test_signed_url_required() {
+        assert!(parse("a.html", "{# signed_url_required #}<h1>Unsubscribe</h1>").signed_url_required);
+        assert!(!parse("a.html", "{# login_required #}").signed_url_required);
+    }
+
+    #[test]
+    fn test_requires() {
+        let meta = parse("a.html", "{# requires: role=admin #}\n{# requires: permission=reports.view #}");
+        assert_eq!(meta.requires, vec![Requirement::Role("admin".to_string()), Requirement::Permission("reports.view".to_string())]);
+        assert!(!crate::auth::meets(&["admin".to_string()], &parse("a.html", "{# requires: admin #}").requires));
+    }
+
+    #[test]
+    fn test_max_concurrency() {
+        assert_eq!(parse("a.html", "{# login_required #}{# max_concurrency: 2 #}").max_concurrency, Some(2));
+        assert_eq!(parse("a.html", "{# max_concurrency: 0 #}").max_concurrency, None);
+        assert_eq!(parse("a.html", "{# max_concurrency: lots #}").max_concurrency, None);
+        assert_eq!(parse("a.html", "<p>{# max_concurrency: 2 #}</p>").max_concurrency, None);
+    }
+
+    #[test]
+    fn test_timeout() {
+        assert_eq!(parse("a.html", "{# timeout: 10 #}").render_timeout, Some(10));
+        assert_eq!(parse("a.html", "---\ntimeout: 5\n---\n<h1>Report</h1>").render_timeout, Some(5));
+        assert_eq!(parse("a.html", "{# timeout: 0 #}").render_timeout, None);
+        assert_eq!(parse("a.html", "{# timeout: soon #}").render_timeout, None);
+    }
+
+    #[test]
+    fn test_yaml_frontmatter() {
+        let source = "---\ntitle: Pricing\nauth: role=admin\nmethods: [get, post]\ncache: 300\n---\n{# max_concurrency: 2 #}\n<h1>{{ page.meta.title }}</h1>";
+        let meta = parse("a.html", source);
+        assert_eq!(meta.frontmatter["title"], "Pricing");
+        assert_eq!(meta.requires, vec![Requirement::Role("admin".to_string())]);
+        assert_eq!(meta.methods, Some(vec!["GET".to_string(), "POST".to_string()]));
+        assert_eq!(meta.cache_control.as_deref(), Some("public, max-age=300"));
+        assert_eq!(meta.max_concurrency, Some(2));
+
+        let meta = parse("a.html", "---\nauth: true\ncache: false\n---\n");
+        assert!(meta.login_required);
+        assert_eq!(meta.cache_control.as_deref(), Some("no-store"));
+        assert_eq!(parse("a.html", "<hr>\n---\ntitle: x\n---").frontmatter, Map::new());
+    }
+
+    #[test]
+    fn test_malformed_frontmatter_blocks_everyone() {
+        for source in ["---\nauth: role=admin\ntitle: [unclosed\n---\n<h1>Admin</h1>", "---\n- just\n- a list\n---\n", "+++\nauth = \"role=admin\nlogin_required = true\n+++\n"] {
+            let meta = parse("a.html", source);
+            assert_eq!(meta.requires, vec![Requirement::Role(String::new())], "{}", source);
+            assert!(!crate::auth::meets(&[], &meta.requires));
+            assert!(!crate::auth::meets(&["admin".to_string()], &meta.requires));
+        }
+    }
+
+    #[test]
+    fn test_toml_frontmatter() {
+        let meta = parse("a.html", "+++\ntitle = \"About us\" # shown in the tab\ncache = \"private, max-age=60\"\n\n[seo]\nkeywords = [\"team\", \"history\"]\n+++\n<h1></h1>");
+        assert_eq!(meta.frontmatter["title"], "About us");
+        assert_eq!(meta.frontmatter["seo"]["keywords"][1], "history");
+        assert_eq!(meta.cache_control.as_deref(), Some("private, max-age=60"));
+
+        let source = "+++\ntitle = \"\"\"\nTwo\nlines\"\"\"\nauthor = { name = \"Ada\", email = \"ada@example.com\" }\npublished = 2024-05-01\n\n[seo.social]\nimage = \"/og.png\"\n\n[[links]]\nhref = \"/a\"\n\n[[links]]\nhref = \"/b\"\n+++\n";
+        let meta = parse("a.html", source);
+        assert_eq!(meta.frontmatter["title"], "Two\nlines");
+        assert_eq!(meta.frontmatter["author"]["email"], "ada@example.com");
+        assert_eq!(meta.frontmatter["published"], "2024-05-01");
+        assert_eq!(meta.frontmatter["seo"]["social"]["image"], "/og.png");
+        assert_eq!(meta.frontmatter["links"][1]["href"], "/b");
+        assert!(meta.requires.is_empty());
+    }
+
+    #[test]
+    fn test_template_source() {
+        assert_eq!(template_source("<h1>Home</h1>"), "<h1>Home</h1>");
+        assert_eq!(template_source("---\ntitle: Home\n---\n<h1>Home</h1>"), "\n\n\n<h1>Home</h1>");
+        assert_eq!(
+            template_source("---\nlayout: layouts/base.html\n---\n<h1>Home</h1>"),
+            "{% extends \"layouts/base.html\" %}{% block content %}\n\n\n<h1>Home</h1>{% endblock %}"
+        );
+        assert_eq!(
+            template_source("---\nlayout: layouts/base.html\n---\n{% block main %}x{% endblock %}"),
+            "{% extends \"layouts/base.html\" %}\n\n\n{% block main %}x{% endblock %}"
+        );
+        assert_eq!(
+            template_source("---\nlayout: layouts/base.html\n---\n{% extends \"layouts/other.html\" %}"),
+            "\n\n\n{% extends \"layouts/other.html\" %}"
+        );
+    }
+}
@@ -0,0 +1,30 @@
+use std::path::Path;
+
+// Template names, component ids and Python modules always use `/`, even on Windows where paths
+// come back with `\`. Names that aren't valid UTF-8 are made readable instead of panicking.
+pub fn to_slash(path: &Path) -> String {
+    path.to_string_lossy().replace('\\', "/")
+}
+
+// `path` relative to `root` with `/` separators, or all of `path` when it lives elsewhere.
+pub fn relative_name(path: &Path, root: &Path) -> String {
+    to_slash(path.strip_prefix(root).unwrap_or(path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_slash() {
+        assert_eq!(to_slash(Path::new("components\\cards\\post")), "components/cards/post");
+        assert_eq!(to_slash(&Path::new("pages").join("blog").join("index.html")), "pages/blog/index.html");
+    }
+
+    #[test]
+    fn test_relative_name() {
+        let root = Path::new("project");
+        assert_eq!(relative_name(&root.join("pages").join("index.html"), root), "pages/index.html");
+        assert_eq!(relative_name(Path::new("elsewhere/index.html"), root), "elsewhere/index.html");
+    }
+}
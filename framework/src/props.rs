@@ -0,0 +1,163 @@
+use minijinja::value::ValueKind;
+use minijinja::Value;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::path::Path;
+
+// A component says which arguments it takes in a `component.yaml` next to its template:
+//
+//     props:
+//       title: string                   # required
+//       size: { type: int, default: 3 }
+//       tags: { type: list, required: false }
+pub const FILE: &str = "component.yaml";
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum PropType {
+    #[serde(alias = "str")]
+    String,
+    #[serde(alias = "integer")]
+    Int,
+    #[serde(alias = "number")]
+    Float,
+    #[serde(alias = "boolean")]
+    Bool,
+    List,
+    #[serde(alias = "map")]
+    Dict,
+    #[default]
+    Any,
+}
+
+impl PropType {
+    fn accepts(self, value: &Value) -> bool {
+        match self {
+            PropType::String => value.kind() == ValueKind::String,
+            PropType::Int => value.is_integer(),
+            PropType::Float => value.kind() == ValueKind::Number,
+            PropType::Bool => value.kind() == ValueKind::Bool,
+            PropType::List => matches!(value.kind(), ValueKind::Seq | ValueKind::Iterable),
+            PropType::Dict => value.kind() == ValueKind::Map,
+            PropType::Any => true,
+        }
+    }
+}
+
+impl fmt::Display for PropType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            PropType::String => "a string",
+            PropType::Int => "an int",
+            PropType::Float => "a number",
+            PropType::Bool => "a bool",
+            PropType::List => "a list",
+            PropType::Dict => "a dict",
+            PropType::Any => "anything",
+        };
+        f.write_str(name)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum PropSpec {
+    Type(PropType),
+    Full {
+        #[serde(rename = "type", default)]
+        kind: PropType,
+        required: Option<bool>,
+        default: Option<serde_yaml::Value>,
+    },
+}
+
+#[derive(Deserialize)]
+struct PropsFile {
+    #[serde(default)]
+    props: BTreeMap<String, PropSpec>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Prop {
+    pub name: String,
+    pub kind: PropType,
+    // Props with a default aren't required unless they say so.
+    pub required: bool,
+    pub default: Option<Value>,
+}
+
+pub fn load(path: &Path) -> Result<Vec<Prop>, String> {
+    let source = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+    parse(&source)
+}
+
+fn parse(source: &str) -> Result<Vec<Prop>, String> {
+    let file: PropsFile = serde_yaml::from_str(source).map_err(|e| e.to_string())?;
+    Ok(file
+        .props
+        .into_iter()
+        .map(|(name, spec)| match spec {
+            PropSpec::Type(kind) => Prop { name, kind, required: true, default: None },
+            PropSpec::Full { kind, required, default } => Prop {
+                name,
+                kind,
+                required: required.unwrap_or(default.is_none()),
+                default: default.map(|value| Value::from_serialize(&value)),
+            },
+        })
+        .collect())
+}
+
+fn describe(value: &Value) -> String {
+    match value.kind() {
+        ValueKind::String => format!("the string {:?}", value.as_str().unwrap_or_default()),
+        ValueKind::Seq | ValueKind::Iterable => "a list".to_string(),
+        ValueKind::Map => "a dict".to_string(),
+        _ => value.to_string(),
+    }
+}
+
+// What's wrong with the arguments a component was called with. Missing ones that have a default get it.
+pub fn check(props: &[Prop], kwargs: &mut HashMap<String, Value>) -> Vec<String> {
+    let mut problems = Vec::new();
+    for prop in props {
+        match kwargs.get(&prop.name).filter(|value| !value.is_undefined() && !value.is_none()) {
+            Some(value) if !prop.kind.accepts(value) => {
+                problems.push(format!("`{}` should be {}, not {}", prop.name, prop.kind, describe(value)));
+            }
+            Some(_) => {}
+            None => {
+                if let Some(default) = &prop.default {
+                    kwargs.insert(prop.name.clone(), default.clone());
+                } else if prop.required {
+                    problems.push(format!("`{}` is missing", prop.name));
+                }
+            }
+        }
+    }
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_props() {
+        let props = parse("props:\n  title: string\n  size: { type: int, default: 3 }\n  tags: { type: list, required: false }\n  price: number\n").unwrap();
+        assert_eq!(props.len(), 4);
+
+        let mut kwargs: HashMap<String, Value> =
+            [("title", Value::from("Hi")), ("price", Value::from(9))].into_iter().map(|(k, v)| (k.to_string(), v)).collect();
+        assert!(check(&props, &mut kwargs).is_empty());
+        assert_eq!(kwargs["size"], Value::from(3));
+        assert!(!kwargs.contains_key("tags"));
+
+        let mut kwargs: HashMap<String, Value> =
+            [("size", Value::from("big")), ("price", Value::from(1.5))].into_iter().map(|(k, v)| (k.to_string(), v)).collect();
+        assert_eq!(check(&props, &mut kwargs), vec!["`size` should be an int, not the string \"big\"", "`title` is missing"]);
+
+        assert!(parse("props:\n  title: text\n").is_err());
+    }
+}
@@ -0,0 +1,330 @@
+use crate::config;
+use lazy_static::lazy_static;
+use actix_web::HttpRequest;
+use std::net::IpAddr;
+
+lazy_static! {
+    // Entries were checked when config.yaml was loaded, so anything unparsable here is skipped.
+    pub static ref TRUSTED_PROXIES: TrustedProxies =
+        TrustedProxies::new(config::CONFIG.trusted_proxies.as_deref().unwrap_or_default());
+}
+
+// Written in config.yaml to trust whatever connects over the unix socket (see `bind`).
+const UNIX_SOCKET: &str = "unix";
+
+// Peers allowed to tell us the client's real address, scheme and host.
+#[derive(Debug, Default)]
+pub struct TrustedProxies {
+    networks: Vec<(IpAddr, u8)>,
+    unix: bool,
+}
+
+// `10.0.0.1`, `10.0.0.0/8` or `fd00::/8`.
+pub fn parse_network(entry: &str) -> Result<(IpAddr, u8), String> {
+    let invalid = || format!("`trusted_proxies` has '{}', which isn't an IP address, a CIDR range or `unix`.", entry);
+    let (address, prefix) = match entry.split_once('/') {
+        Some((address, prefix)) => (address, Some(prefix)),
+        None => (entry, None),
+    };
+    let address: IpAddr = address.trim().parse().map_err(|_| invalid())?;
+    let max_prefix = if address.is_ipv4() { 32 } else { 128 };
+    let prefix = match prefix {
+        Some(prefix) => prefix.trim().parse::<u8>().ok().filter(|p| *p <= max_prefix).ok_or_else(invalid)?,
+        None => max_prefix,
+    };
+    Ok((address, prefix))
+}
+
+pub fn validate_entry(entry: &str) -> Result<(), String> {
+    if entry == UNIX_SOCKET {
+        Ok(())
+    } else {
+        parse_network(entry).map(|_| ())
+    }
+}
+
+fn in_network(ip: IpAddr, network: IpAddr, prefix: u8) -> bool {
+    match (ip, network) {
+        (IpAddr::V4(ip), IpAddr::V4(network)) => {
+            let mask = u32::MAX.checked_shl(32 - prefix as u32).unwrap_or(0);
+            u32::from(ip) & mask == u32::from(network) & mask
+        }
+        (IpAddr::V6(ip), IpAddr::V6(network)) => {
+            let mask = u128::MAX.checked_shl(128 - prefix as u32).unwrap_or(0);
+            u128::from(ip) & mask == u128::from(network) & mask
+        }
+        _ => false,
+    }
+}
+
+impl TrustedProxies {
+    pub fn new(entries: &[String]) -> Self {
+        let mut trusted = Self::default();
+        for entry in entries {
+            if entry == UNIX_SOCKET {
+                trusted.unix = true;
+            } else if let Ok(network) = parse_network(entry) {
+                trusted.networks.push(network);
+            }
+        }
+        trusted
+    }
+
+    pub fn trusts(&self, ip: IpAddr) -> bool {
+        let ip = ip.to_canonical();
+        self.networks.iter().any(|(network, prefix)| in_network(ip, *network, *prefix))
+    }
+
+    fn trusts_hop(&self, hop: &str) -> bool {
+        parse_hop(hop).is_some_and(|ip| self.trusts(ip))
+    }
+}
+
+// Strips the quotes, brackets and port a Forwarded/X-Forwarded-For hop may carry.
+fn parse_hop(hop: &str) -> Option<IpAddr> {
+    let hop = hop.trim().trim_matches('"');
+    if let Ok(ip) = hop.parse() {
+        return Some(ip);
+    }
+    if let Some(rest) = hop.strip_prefix('[') {
+        return rest.split(']').next()?.parse().ok();
+    }
+    hop.rsplit_once(':').and_then(|(ip, _)| ip.parse().ok())
+}
+
+fn clean_hop(hop: &str) -> String {
+    parse_hop(hop).map(|ip| ip.to_string()).unwrap_or_else(|| hop.trim().trim_matches('"').to_string())
+}
+
+// RFC 7239: `Forwarded: for=192.0.2.60;proto=https;host=example.com, for=10.0.0.1`.
+fn parse_forwarded(value: &str) -> Vec<Vec<(String, String)>> {
+    value
+        .split(',')
+        .map(|element| {
+            element
+                .split(';')
+                .filter_map(|pair| pair.split_once('='))
+                .map(|(key, value)| (key.trim().to_lowercase(), value.trim().trim_matches('"').to_string()))
+                .collect()
+        })
+        .collect()
+}
+
+fn header_values(req: &HttpRequest, name: &str) -> Vec<String> {
+    req.headers()
+        .get_all(name)
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty())
+        .collect()
+}
+
+// Scheme, host and client address as the browser saw them.
+#[derive(Debug, PartialEq)]
+pub struct ClientInfo {
+    pub scheme: String,
+    pub host: String,
+    pub remote_addr: Option<String>,
+}
+
+fn direct_client_info(req: &HttpRequest) -> ClientInfo {
+    let scheme = req
+        .uri()
+        .scheme_str()
+        .map(str::to_string)
+        .unwrap_or_else(|| if req.app_config().secure() { "https" } else { "http" }.to_string());
+    let host = req
+        .uri()
+        .authority()
+        .map(|a| a.to_string())
+        .or_else(|| req.headers().get("host").and_then(|h| h.to_str().ok()).map(str::to_string))
+        .unwrap_or_else(|| req.app_config().host().to_string());
+    ClientInfo {
+        scheme,
+        host,
+        remote_addr: req.peer_addr().map(|addr| addr.ip().to_canonical().to_string()),
+    }
+}
+
+// Walks back from the closest hop; the first address we don't trust is the client. Returns how many
+// hops from the right the walk stopped at. A hop without an address stops it too.
+fn walk_hops(hops: &[Option<String>], trusted: &TrustedProxies, info: &mut ClientInfo) -> usize {
+    let mut stop = 0;
+    for (position, hop) in hops.iter().rev().enumerate() {
+        stop = position;
+        let Some(hop) = hop else {
+            break;
+        };
+        info.remote_addr = Some(clean_hop(hop));
+        if !trusted.trusts_hop(hop) {
+            break;
+        }
+    }
+    stop
+}
+
+// Forwarding headers are only believed when the connection comes from a trusted proxy; anyone else could forge them.
+pub fn client_info(req: &HttpRequest, trusted: &TrustedProxies) -> ClientInfo {
+    let mut info = direct_client_info(req);
+    let peer_trusted = match req.peer_addr() {
+        Some(peer) => trusted.trusts(peer.ip()),
+        // No peer address means the request came in over a unix socket.
+        None => trusted.unix,
+    };
+    if !peer_trusted {
+        return info;
+    }
+
+    let forwarded: Vec<Vec<(String, String)>> = header_values(req, "forwarded")
+        .iter()
+        .flat_map(|value| parse_forwarded(value))
+        .collect();
+    // Every proxy appends what it saw, so scheme and host come from the proxy where the walk stops,
+    // the outermost one we trust. Anything further left is whatever the client sent.
+    let (proto, host) = if forwarded.is_empty() {
+        let hops: Vec<Option<String>> = header_values(req, "x-forwarded-for").into_iter().map(Some).collect();
+        let stop = walk_hops(&hops, trusted, &mut info);
+        // Proxies that pass a single value on instead of appending their own are believed as they are.
+        let pick = |values: Vec<String>| values.iter().rev().nth(stop).or(values.last()).cloned();
+        (pick(header_values(req, "x-forwarded-proto")), pick(header_values(req, "x-forwarded-host")))
+    } else {
+        let field = |element: &Vec<(String, String)>, name: &str| {
+            element.iter().find(|(key, _)| key == name).map(|(_, value)| value.clone())
+        };
+        let hops: Vec<Option<String>> = forwarded.iter().map(|element| field(element, "for")).collect();
+        let stop = walk_hops(&hops, trusted, &mut info);
+        let element = &forwarded[forwarded.len() - 1 - stop];
+        (field(element, "proto"), field(element, "host"))
+    };
+
+    if let Some(proto) = proto.filter(|p| p == "http" || p == "https") {
+        info.scheme = proto;
+    }
+    if let Some(host) = host.filter(|h| !h.is_empty()) {
+        info.host = host;
+    }
+    info
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    fn trusted(entries: &[&str]) -> TrustedProxies {
+        TrustedProxies::new(&entries.iter().map(|e| e.to_string()).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn test_parse_network() {
+        assert_eq!(parse_network("10.0.0.0/8").unwrap(), ("10.0.0.0".parse().unwrap(), 8));
+        assert_eq!(parse_network("::1").unwrap(), ("::1".parse().unwrap(), 128));
+        assert!(parse_network("10.0.0.0/33").is_err());
+        assert!(parse_network("loadbalancer").is_err());
+        assert!(validate_entry("unix").is_ok());
+    }
+
+    #[test]
+    fn test_trusts() {
+        let proxies = trusted(&["10.0.0.0/8", "fd00::/8", "192.168.1.5"]);
+        assert!(proxies.trusts("10.1.2.3".parse().unwrap()));
+        assert!(proxies.trusts("::ffff:10.1.2.3".parse().unwrap()));
+        assert!(proxies.trusts("fd12::1".parse().unwrap()));
+        assert!(proxies.trusts("192.168.1.5".parse().unwrap()));
+        assert!(!proxies.trusts("192.168.1.6".parse().unwrap()));
+        assert!(!proxies.trusts("11.0.0.1".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_untrusted_peer_headers_are_ignored() {
+        let req = TestRequest::get()
+            .uri("/login")
+            .peer_addr("203.0.113.9:5000".parse().unwrap())
+            .insert_header(("host", "example.com"))
+            .insert_header(("x-forwarded-for", "1.2.3.4"))
+            .insert_header(("x-forwarded-proto", "https"))
+            .insert_header(("x-forwarded-host", "evil.com"))
+            .to_http_request();
+
+        let info = client_info(&req, &trusted(&["10.0.0.0/8"]));
+        assert_eq!(
+            info,
+            ClientInfo { scheme: "http".to_string(), host: "example.com".to_string(), remote_addr: Some("203.0.113.9".to_string()) }
+        );
+    }
+
+    #[test]
+    fn test_x_forwarded_headers_from_trusted_proxy() {
+        let req = TestRequest::get()
+            .uri("/login")
+            .peer_addr("10.0.0.2:5000".parse().unwrap())
+            .insert_header(("host", "internal:8080"))
+            // A client-supplied value, the real client, then another proxy of ours.
+            .insert_header(("x-forwarded-for", "6.6.6.6, 198.51.100.7, 10.0.0.3"))
+            .insert_header(("x-forwarded-proto", "https"))
+            .insert_header(("x-forwarded-host", "example.com"))
+            .to_http_request();
+
+        let info = client_info(&req, &trusted(&["10.0.0.0/8"]));
+        assert_eq!(info.remote_addr.as_deref(), Some("198.51.100.7"));
+        assert_eq!(info.scheme, "https");
+        assert_eq!(info.host, "example.com");
+    }
+
+    #[test]
+    fn test_forwarded_header_wins() {
+        let req = TestRequest::get()
+            .uri("/")
+            .peer_addr("127.0.0.1:5000".parse().unwrap())
+            .insert_header(("forwarded", "for=\"[2001:db8::1]:4711\";proto=https;host=example.com"))
+            .insert_header(("x-forwarded-for", "1.2.3.4"))
+            .to_http_request();
+
+        let info = client_info(&req, &trusted(&["127.0.0.1"]));
+        assert_eq!(info.remote_addr.as_deref(), Some("2001:db8::1"));
+        assert_eq!(info.scheme, "https");
+        assert_eq!(info.host, "example.com");
+    }
+
+    #[test]
+    fn test_forwarded_host_comes_from_the_nearest_trusted_hop() {
+        let req = TestRequest::get()
+            .uri("/")
+            .peer_addr("127.0.0.1:5000".parse().unwrap())
+            .insert_header(("forwarded", "host=evil, for=10.0.0.3;host=real"))
+            .to_http_request();
+
+        let info = client_info(&req, &trusted(&["127.0.0.1"]));
+        assert_eq!(info.remote_addr.as_deref(), Some("10.0.0.3"));
+        assert_eq!(info.host, "real");
+    }
+
+    #[test]
+    fn test_multi_valued_x_forwarded_host_and_proto() {
+        let req = TestRequest::get()
+            .uri("/")
+            .peer_addr("10.0.0.2:5000".parse().unwrap())
+            .insert_header(("x-forwarded-for", "198.51.100.7, 10.0.0.3"))
+            // The client's own value, our edge proxy's, then the inner proxy's.
+            .insert_header(("x-forwarded-host", "evil.com, example.com, internal:8080"))
+            .insert_header(("x-forwarded-proto", "http, https, http"))
+            .to_http_request();
+
+        let info = client_info(&req, &trusted(&["10.0.0.0/8"]));
+        assert_eq!(info.remote_addr.as_deref(), Some("198.51.100.7"));
+        assert_eq!(info.host, "example.com");
+        assert_eq!(info.scheme, "https");
+    }
+
+    #[test]
+    fn test_unix_socket_peers() {
+        let req = TestRequest::get()
+            .uri("/")
+            .insert_header(("x-forwarded-for", "198.51.100.7"))
+            .to_http_request();
+
+        assert_eq!(client_info(&req, &trusted(&[])).remote_addr, None);
+        assert_eq!(client_info(&req, &trusted(&["unix"])).remote_addr.as_deref(), Some("198.51.100.7"));
+    }
+}
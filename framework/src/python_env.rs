@@ -0,0 +1,221 @@
+use crate::config::PythonConfig;
+use serde::Deserialize;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+// Asked of a Python install to learn where its standard library and packages live.
+const INSPECT_SCRIPT: &str = "import json, sys, sysconfig
+stdlib = sysconfig.get_paths()['stdlib']
+print(json.dumps({
+    'executable': sys.executable,
+    'version': list(sys.version_info[:3]),
+    'home': sys.base_prefix,
+    'exec_home': sys.base_exec_prefix,
+    'site': [p for p in sys.path if p and not p.startswith(stdlib) and not p.endswith('.zip')],
+}))";
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct PythonInstall {
+    pub executable: PathBuf,
+    pub version: (u32, u32, u32),
+    pub home: String,
+    pub exec_home: String,
+    // site-packages and friends, including a virtualenv's.
+    pub site: Vec<String>,
+}
+
+impl PythonInstall {
+    pub fn minor_version(&self) -> (u32, u32) {
+        (self.version.0, self.version.1)
+    }
+}
+
+pub fn inspect(executable: &Path) -> Result<PythonInstall, String> {
+    // -E so a PYTHONHOME meant for us doesn't change what it reports.
+    let output = Command::new(executable)
+        .args(["-E", "-c", INSPECT_SCRIPT])
+        .output()
+        .map_err(|e| format!("couldn't run {}: {}", executable.display(), e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "{} failed: {}",
+            executable.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    serde_json::from_slice(&output.stdout)
+        .map_err(|e| format!("{} didn't say where its files are: {}", executable.display(), e))
+}
+
+// The version of the libpython this binary loaded. Safe before the interpreter starts.
+pub fn library_version() -> Option<(u32, u32)> {
+    // SAFETY: Py_GetVersion returns a static string and doesn't need an initialized interpreter.
+    let version = unsafe { std::ffi::CStr::from_ptr(pyo3::ffi::Py_GetVersion()) };
+    parse_version(&version.to_string_lossy())
+}
+
+// "3.10.12 (main, ...)" -> (3, 10)
+fn parse_version(text: &str) -> Option<(u32, u32)> {
+    let mut numbers = text.split(|c: char| !c.is_ascii_digit()).map(|part| part.parse::<u32>());
+    Some((numbers.next()?.ok()?, numbers.next()?.ok()?))
+}
+
+// PYTHONHOME and PYTHONPATH for the embedded interpreter to behave like `install`.
+fn environment(install: &PythonInstall, python: &PythonConfig, existing_path: Option<OsString>) -> Vec<(&'static str, OsString)> {
+    let home = match &python.home {
+        Some(home) => home.clone(),
+        // Windows takes a single folder; elsewhere `prefix:exec_prefix` when they differ.
+        None if cfg!(windows) || install.home == install.exec_home => install.home.clone(),
+        None => format!("{}:{}", install.home, install.exec_home),
+    };
+    let mut path: Vec<PathBuf> = install.site.iter().map(PathBuf::from).collect();
+    if let Some(existing) = existing_path {
+        path.extend(std::env::split_paths(&existing));
+    }
+    let mut vars = vec![("PYTHONHOME", OsString::from(home))];
+    if let Ok(path) = std::env::join_paths(path)
+        && !path.is_empty()
+    {
+        vars.push(("PYTHONPATH", path));
+    }
+    vars
+}
+
+// Points the embedded interpreter at the install named in config.yaml. It has to run before
+// anything starts the interpreter, so main calls it first for the commands that use Python.
+pub fn prepare(python: Option<&PythonConfig>) {
+    // The pip launcher sets PYTHONHOME itself, and so may whoever runs us.
+    if std::env::var_os("PYTHONHOME").is_some() {
+        return;
+    }
+    let Some(python) = python else {
+        return;
+    };
+    let vars = match &python.executable {
+        Some(executable) => match inspect(Path::new(executable)) {
+            Ok(install) => {
+                if let Some(linked) = library_version()
+                    && install.minor_version() != linked
+                {
+                    println!(
+                        "Heads up! `python.executable` is Python {}.{}, but noventa runs Python {}.{}, so its packages may not load. Run `noventa doctor --fix-python` to find a match.",
+                        install.version.0, install.version.1, linked.0, linked.1
+                    );
+                }
+                environment(&install, python, std::env::var_os("PYTHONPATH"))
+            }
+            Err(e) => {
+                println!("Oh no! `python.executable` can't be used: {}. Run `noventa doctor --fix-python` to pick another.", e);
+                return;
+            }
+        },
+        None => match &python.home {
+            Some(home) => vec![("PYTHONHOME", OsString::from(home))],
+            None => return,
+        },
+    };
+    for (name, value) in vars {
+        // SAFETY: main calls this before starting any other thread.
+        unsafe { std::env::set_var(name, value) };
+    }
+}
+
+// Where `doctor --fix-python` looks, best guesses first.
+fn candidates(configured: Option<&str>, linked: Option<(u32, u32)>) -> Vec<PathBuf> {
+    let mut candidates: Vec<PathBuf> = configured.map(PathBuf::from).into_iter().collect();
+    if let Some(venv) = std::env::var_os("VIRTUAL_ENV") {
+        let venv = PathBuf::from(venv);
+        candidates.push(if cfg!(windows) { venv.join("Scripts").join("python.exe") } else { venv.join("bin").join("python") });
+    }
+    if let Some((major, minor)) = linked {
+        candidates.push(PathBuf::from(format!("python{}.{}", major, minor)));
+    }
+    candidates.push(PathBuf::from("python3"));
+    candidates.push(PathBuf::from("python"));
+    candidates
+}
+
+// Finds a Python install matching the libpython we run on and saves it to config.yaml.
+pub fn fix(root: &Path, configured: Option<&str>) -> Result<PythonInstall, String> {
+    let linked = library_version();
+    let mut seen = Vec::new();
+    for candidate in candidates(configured, linked) {
+        let Ok(install) = inspect(&candidate) else {
+            continue;
+        };
+        if linked.is_none_or(|linked| install.minor_version() == linked) {
+            save_executable(&root.join("config.yaml"), &install.executable).map_err(|e| {
+                format!("found {} but couldn't save it to config.yaml: {}", install.executable.display(), e)
+            })?;
+            return Ok(install);
+        }
+        seen.push(format!("{} ({}.{})", install.executable.display(), install.version.0, install.version.1));
+    }
+    let wanted = linked.map(|(major, minor)| format!("Python {}.{}", major, minor)).unwrap_or_else(|| "Python".to_string());
+    if seen.is_empty() {
+        Err(format!("no {} install was found", wanted))
+    } else {
+        Err(format!("no {} install was found, only {}", wanted, seen.join(", ")))
+    }
+}
+
+fn save_executable(config_path: &Path, executable: &Path) -> std::io::Result<()> {
+    // JSON strings are valid YAML and keep Windows backslashes intact.
+    let value = serde_json::to_string(&executable.to_string_lossy()).unwrap_or_default();
+    crate::config::set_value(config_path, "python", "executable", &value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_parse_version() {
+        assert_eq!(parse_version("3.10.12 (main, Jun 11 2023, 05:26:28) [GCC 11.4.0]"), Some((3, 10)));
+        assert_eq!(parse_version("nonsense"), None);
+    }
+
+    #[test]
+    fn test_environment() {
+        let install = PythonInstall {
+            executable: PathBuf::from("/srv/app/.venv/bin/python"),
+            version: (3, 10, 12),
+            home: "/usr".to_string(),
+            exec_home: "/usr".to_string(),
+            site: vec!["/srv/app/.venv/lib/python3.10/site-packages".to_string()],
+        };
+        let vars = environment(&install, &PythonConfig::default(), None);
+        assert_eq!(vars[0], ("PYTHONHOME", OsString::from("/usr")));
+        assert_eq!(vars[1], ("PYTHONPATH", OsString::from("/srv/app/.venv/lib/python3.10/site-packages")));
+
+        // `python.home` wins over what the executable reports.
+        let python = PythonConfig { home: Some("/opt/python".to_string()), ..Default::default() };
+        assert_eq!(environment(&install, &python, None)[0], ("PYTHONHOME", OsString::from("/opt/python")));
+    }
+
+    #[test]
+    fn test_save_executable() {
+        let dir = tempdir().unwrap();
+        let config_path = dir.path().join("config.yaml");
+        std::fs::write(&config_path, "port: 8080\n").unwrap();
+
+        save_executable(&config_path, Path::new("/usr/bin/python3.10")).unwrap();
+        assert_eq!(std::fs::read_to_string(&config_path).unwrap(), "port: 8080\n\npython:\n  executable: \"/usr/bin/python3.10\"\n");
+
+        // An existing setting is replaced in place.
+        std::fs::write(&config_path, "python:\n  home: /usr\n  executable: old\nport: 8080\n").unwrap();
+        save_executable(&config_path, Path::new("/opt/bin/python")).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&config_path).unwrap(),
+            "python:\n  home: /usr\n  executable: \"/opt/bin/python\"\nport: 8080\n"
+        );
+    }
+
+    #[test]
+    fn test_inspect_reports_missing_executables() {
+        let error = inspect(Path::new("/no/such/python")).unwrap_err();
+        assert!(error.contains("/no/such/python"));
+    }
+}
@@ -0,0 +1,120 @@
+use crate::cluster::{Cluster, CLUSTER};
+use crate::config::{self, RateLimitConfig};
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::HttpResponse;
+use dashmap::DashMap;
+use deadpool_redis::redis;
+use lazy_static::lazy_static;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const DEFAULT_WINDOW_SECS: u64 = 60;
+// Past this many clients, counters from earlier windows are dropped.
+const LOCAL_CLEANUP_THRESHOLD: usize = 10_000;
+
+lazy_static! {
+    // Client address -> (window, requests in it), used without a cluster or while Redis is down.
+    static ref LOCAL_COUNTS: DashMap<String, (u64, u64)> = DashMap::new();
+}
+
+// So a Redis outage is logged once, not on every request.
+static REDIS_FAILING: AtomicBool = AtomicBool::new(false);
+
+fn count_locally(client: &str, window: u64) -> u64 {
+    if LOCAL_COUNTS.len() > LOCAL_CLEANUP_THRESHOLD {
+        LOCAL_COUNTS.retain(|_, (counted_window, _)| *counted_window == window);
+    }
+    let mut entry = LOCAL_COUNTS.entry(client.to_string()).or_insert((window, 0));
+    if entry.0 != window {
+        *entry = (window, 0);
+    }
+    entry.1 += 1;
+    entry.1
+}
+
+// One counter per client and window, shared by every instance. It expires with the window.
+async fn count_in_redis(cluster: &Cluster, client: &str, window: u64, window_secs: u64) -> Result<u64, String> {
+    let mut connection = cluster.pool().get().await.map_err(|e| e.to_string())?;
+    let key = cluster.key(&format!("ratelimit:{}:{}", client, window));
+    let (count,): (u64,) = redis::pipe()
+        .atomic()
+        .incr(&key, 1)
+        .expire(&key, window_secs as i64)
+        .ignore()
+        .query_async(&mut connection)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(count)
+}
+
+async fn count_request(client: &str, window: u64, window_secs: u64) -> u64 {
+    if let Some(cluster) = CLUSTER.as_ref() {
+        match count_in_redis(cluster, client, window, window_secs).await {
+            Ok(count) => {
+                if REDIS_FAILING.swap(false, Ordering::SeqCst) {
+                    log::info!("✨ Redis is back; rate limits are shared across instances again.");
+                }
+                return count;
+            }
+            Err(e) => {
+                if !REDIS_FAILING.swap(true, Ordering::SeqCst) {
+                    log::warn!("Rate limiting can't reach Redis ({}), so each instance counts on its own for now.", e);
+                }
+            }
+        }
+    }
+    count_locally(client, window)
+}
+
+// Seconds until the client may try again, or None while it's under the limit.
+fn over_limit(count: u64, config: &RateLimitConfig, now: u64) -> Option<u64> {
+    let window_secs = config.window_secs.unwrap_or(DEFAULT_WINDOW_SECS);
+    (count > config.requests).then(|| window_secs - now % window_secs)
+}
+
+// Counts requests per client address (see `trusted_proxies`) in fixed windows.
+pub async fn rate_limit(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, actix_web::Error> {
+    let Some(limits) = &config::CONFIG.rate_limit else {
+        return next.call(req).await.map(ServiceResponse::map_into_left_body);
+    };
+
+    let client = crate::proxy::client_info(req.request(), &crate::proxy::TRUSTED_PROXIES)
+        .remote_addr
+        .unwrap_or_else(|| "unix".to_string());
+    let window_secs = limits.window_secs.unwrap_or(DEFAULT_WINDOW_SECS);
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default();
+    let count = count_request(&client, now / window_secs, window_secs).await;
+
+    if let Some(retry_after) = over_limit(count, limits, now) {
+        let res = HttpResponse::TooManyRequests()
+            .insert_header(("Retry-After", retry_after.to_string()))
+            .body("Too many requests. Please slow down and try again in a moment.");
+        return Ok(req.into_response(res).map_into_right_body());
+    }
+    next.call(req).await.map(ServiceResponse::map_into_left_body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_count_locally_resets_each_window() {
+        assert_eq!(count_locally("198.51.100.1", 10), 1);
+        assert_eq!(count_locally("198.51.100.1", 10), 2);
+        assert_eq!(count_locally("198.51.100.2", 10), 1);
+        assert_eq!(count_locally("198.51.100.1", 11), 1);
+    }
+
+    #[test]
+    fn test_over_limit() {
+        let config = RateLimitConfig { requests: 2, window_secs: Some(60) };
+        assert_eq!(over_limit(2, &config, 125), None);
+        assert_eq!(over_limit(3, &config, 125), Some(55));
+    }
+}
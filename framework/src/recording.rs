@@ -0,0 +1,264 @@
+use crate::config;
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{File, OpenOptions};
+use std::io::{LineWriter, Write};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Instant;
+
+const DEFAULT_FILE: &str = "recordings/requests.jsonl";
+// Recording stops once the file is this big.
+const MAX_FILE_BYTES: u64 = 64 * 1024 * 1024;
+// Past this many clients, the oldest think-time clocks are forgotten.
+const MAX_CLIENTS: usize = 10_000;
+// Framework endpoints and files, which aren't the traffic a load test is about.
+const SKIPPED_PREFIXES: &[&str] = &["/_noventa/", "/health", "/metrics", "/devws", "/img/", "/auth/"];
+
+// What kind of value a parameter had, so a replay can make up a similar one.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ParamKind {
+    Int,
+    Uuid,
+    Text,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct RecordedRequest {
+    // Tells one recorded client from another. It's derived with a salt that only lives as long as
+    // the server, so it can't be traced back to a session or an address.
+    pub client: String,
+    // Since this client's previous request; 0 for its first.
+    pub think_time_ms: u64,
+    pub method: String,
+    // The route pattern, e.g. `/users/{id}`.
+    pub route: String,
+    #[serde(default)]
+    pub params: BTreeMap<String, ParamKind>,
+    #[serde(default)]
+    pub query: BTreeMap<String, ParamKind>,
+    pub status: u16,
+    pub duration_ms: u64,
+}
+
+struct Recorder {
+    writer: Option<LineWriter<File>>,
+    written: u64,
+    last_seen: HashMap<String, Instant>,
+    salt: [u8; 16],
+}
+
+lazy_static! {
+    static ref RECORDER: Mutex<Recorder> = Mutex::new(Recorder {
+        writer: None,
+        written: 0,
+        last_seen: HashMap::new(),
+        salt: rand::random(),
+    });
+}
+
+fn settings() -> Option<&'static config::RecordingConfig> {
+    config::CONFIG.recording.as_ref()
+}
+
+fn file_path() -> PathBuf {
+    let file = settings().and_then(|recording| recording.file.as_deref()).unwrap_or(DEFAULT_FILE);
+    config::BASE_PATH.join(file)
+}
+
+fn client_hash(salt: &[u8], client_key: &str) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(salt);
+    hasher.update(client_key.as_bytes());
+    hasher.finalize().into()
+}
+
+// The same client is always in or out of the sample, so its whole visit is recorded.
+fn is_sampled(hash: &[u8; 32], sample_rate: f64) -> bool {
+    let position = u64::from_be_bytes(hash[..8].try_into().unwrap()) as f64 / u64::MAX as f64;
+    position < sample_rate
+}
+
+pub fn param_kind(value: &str) -> ParamKind {
+    if !value.is_empty() && value.chars().all(|c| c.is_ascii_digit()) {
+        ParamKind::Int
+    } else if uuid::Uuid::parse_str(value).is_ok() {
+        ParamKind::Uuid
+    } else {
+        ParamKind::Text
+    }
+}
+
+// The dev server routes every page through one handler, so there's no pattern to read. Segments
+// that look like ids stand in for one instead.
+fn generalize_path(path: &str) -> (String, BTreeMap<String, ParamKind>) {
+    let mut params = BTreeMap::new();
+    let segments: Vec<String> = path
+        .split('/')
+        .map(|segment| match param_kind(segment) {
+            ParamKind::Text => segment.to_string(),
+            kind => {
+                let name = format!("param{}", params.len() + 1);
+                params.insert(name.clone(), kind);
+                format!("{{{}}}", name)
+            }
+        })
+        .collect();
+    (segments.join("/"), params)
+}
+
+fn is_skipped(path: &str) -> bool {
+    let static_prefix = config::CONFIG.static_url_prefix.as_deref().unwrap_or("/static");
+    SKIPPED_PREFIXES.iter().any(|prefix| path.starts_with(prefix)) || path.starts_with(static_prefix)
+}
+
+impl Recorder {
+    fn write(&mut self, request: &RecordedRequest) {
+        if self.written >= MAX_FILE_BYTES {
+            return;
+        }
+        if self.writer.is_none() {
+            let path = file_path();
+            let opened = path
+                .parent()
+                .map_or(Ok(()), std::fs::create_dir_all)
+                .and_then(|_| OpenOptions::new().create(true).append(true).open(&path));
+            match opened {
+                Ok(file) => {
+                    self.written = file.metadata().map_or(0, |metadata| metadata.len());
+                    self.writer = Some(LineWriter::new(file));
+                }
+                Err(e) => {
+                    log::error!("Oh no! Couldn't open {} to record requests: {}. Recording is off.", path.display(), e);
+                    self.written = MAX_FILE_BYTES;
+                    return;
+                }
+            }
+        }
+        let Ok(line) = serde_json::to_string(request) else {
+            return;
+        };
+        if let Some(writer) = self.writer.as_mut()
+            && writeln!(writer, "{}", line).is_ok()
+        {
+            self.written += line.len() as u64 + 1;
+            if self.written >= MAX_FILE_BYTES {
+                log::warn!("Heads up! {} reached {} MB, so no more requests are recorded.", file_path().display(), MAX_FILE_BYTES / 1024 / 1024);
+            }
+        }
+    }
+
+    // Milliseconds since this client's previous request.
+    fn think_time(&mut self, client: &str, now: Instant) -> u64 {
+        if self.last_seen.len() >= MAX_CLIENTS {
+            let cutoff = now - std::time::Duration::from_secs(30 * 60);
+            self.last_seen.retain(|_, seen| *seen > cutoff);
+            if self.last_seen.len() >= MAX_CLIENTS {
+                self.last_seen.clear();
+            }
+        }
+        let previous = self.last_seen.insert(client.to_string(), now);
+        previous.map_or(0, |previous| now.duration_since(previous).as_millis() as u64)
+    }
+}
+
+// Writes the shape of a sample of requests to `recording.file` for `noventa loadtest --replay`.
+pub async fn record(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let Some(recording) = settings() else {
+        return next.call(req).await;
+    };
+    if is_skipped(req.path()) {
+        return next.call(req).await;
+    }
+    // The session cookie tells visitors apart best; without one, it's their address.
+    let session_cookie = config::CONFIG.session.as_ref().map_or("noventa_session", |session| session.cookie_name.as_str());
+    let client_key = match req.cookie(session_cookie) {
+        Some(cookie) => cookie.value().to_string(),
+        None => crate::proxy::client_info(req.request(), &crate::proxy::TRUSTED_PROXIES).remote_addr.unwrap_or_default(),
+    };
+    let salt = RECORDER.lock().unwrap().salt;
+    let hash = client_hash(&salt, &client_key);
+    if !is_sampled(&hash, recording.sample_rate) {
+        return next.call(req).await;
+    }
+
+    let started = Instant::now();
+    let method = req.method().to_string();
+    let query = serde_urlencoded::from_str::<Vec<(String, String)>>(req.query_string())
+        .unwrap_or_default()
+        .into_iter()
+        .map(|(name, value)| (name, param_kind(&value)))
+        .collect();
+    let res = next.call(req).await?;
+
+    let (route, params) = match res.request().match_pattern() {
+        Some(pattern) => {
+            let params = res.request().match_info().iter().map(|(name, value)| (name.to_string(), param_kind(value))).collect();
+            (pattern, params)
+        }
+        None => generalize_path(res.request().path()),
+    };
+    let client = hash[..6].iter().map(|byte| format!("{:02x}", byte)).collect::<String>();
+    let mut recorder = RECORDER.lock().unwrap();
+    let request = RecordedRequest {
+        think_time_ms: recorder.think_time(&client, started),
+        client,
+        method,
+        route,
+        params,
+        query,
+        status: res.status().as_u16(),
+        duration_ms: started.elapsed().as_millis() as u64,
+    };
+    recorder.write(&request);
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_param_kind() {
+        assert_eq!(param_kind("42"), ParamKind::Int);
+        assert_eq!(param_kind("9b2c6f0e-8f3a-4c47-9d1a-0f1e2d3c4b5a"), ParamKind::Uuid);
+        assert_eq!(param_kind("hello"), ParamKind::Text);
+        assert_eq!(param_kind(""), ParamKind::Text);
+    }
+
+    #[test]
+    fn test_generalize_path() {
+        let (route, params) = generalize_path("/users/42/orders/9b2c6f0e-8f3a-4c47-9d1a-0f1e2d3c4b5a");
+        assert_eq!(route, "/users/{param1}/orders/{param2}");
+        assert_eq!(params["param1"], ParamKind::Int);
+        assert_eq!(params["param2"], ParamKind::Uuid);
+        assert_eq!(generalize_path("/about").0, "/about");
+    }
+
+    #[test]
+    fn test_sampling_follows_the_client() {
+        let hash = client_hash(b"salt", "session-a");
+        assert_eq!(hash, client_hash(b"salt", "session-a"));
+        assert_ne!(hash, client_hash(b"other salt", "session-a"));
+        assert!(is_sampled(&hash, 1.0));
+        assert!(!is_sampled(&hash, 0.0));
+    }
+
+    #[test]
+    fn test_think_time() {
+        let mut recorder = Recorder { writer: None, written: 0, last_seen: HashMap::new(), salt: [0; 16] };
+        let start = Instant::now();
+        assert_eq!(recorder.think_time("a", start), 0);
+        assert_eq!(recorder.think_time("a", start + std::time::Duration::from_millis(1500)), 1500);
+        assert_eq!(recorder.think_time("b", start), 0);
+    }
+}
@@ -0,0 +1,199 @@
+use crate::actors::session_manager::{DeleteSessionValue, GetSessionValue, SessionManagerActor, SetSessionValue};
+use actix::Addr;
+use minijinja::{Environment, Value};
+use pyo3::exceptions::{PyTypeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+
+// `from noventa_responses import redirect` in a `_logic.py` or `_page.py`:
+//
+//     return redirect("/orders", flash="Your order is on its way")
+//
+// is `{"_redirect": "/orders", "_redirect_status": 302, "_flash": ...}`, so it goes wherever a plain
+// `{"_redirect": ...}` does. The flash is kept in the session until a page shows it with
+// {% for message in flashed_messages() %}.
+
+// What `{"_redirect": url}` on its own has always been sent with.
+pub const DEFAULT_STATUS: u16 = 303;
+const STATUSES: [u16; 5] = [301, 302, 303, 307, 308];
+// The session key holding the flashes not shown yet.
+const FLASH_KEY: &str = "_noventa_flash";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Redirect {
+    pub url: String,
+    pub status: u16,
+    pub flash: Option<serde_json::Value>,
+}
+
+// Browsers remember these and don't ask again.
+pub fn is_permanent(status: u16) -> bool {
+    status == 301 || status == 308
+}
+
+// The redirect a Python function answered with, if it did.
+pub fn from_context(context: &Value) -> Option<Redirect> {
+    let url = context.get_attr("_redirect").ok()?.as_str()?.to_string();
+    let requested = context.get_attr("_redirect_status").ok().filter(|status| !status.is_undefined() && !status.is_none());
+    let status = match requested.as_ref().map(|status| u16::try_from(status.clone())) {
+        None => DEFAULT_STATUS,
+        Some(Ok(status)) if STATUSES.contains(&status) => status,
+        Some(_) => {
+            log::warn!("Heads up! `_redirect_status` {} isn't a redirect, so {} went out with a {} instead.", requested.unwrap(), url, DEFAULT_STATUS);
+            DEFAULT_STATUS
+        }
+    };
+    let flash = context.get_attr("_flash").ok().filter(|flash| !flash.is_undefined() && !flash.is_none());
+    Some(Redirect { url, status, flash: flash.and_then(|flash| serde_json::to_value(flash).ok()) })
+}
+
+impl std::fmt::Display for Redirect {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "redirected to {} with a {}", self.url, self.status)
+    }
+}
+
+impl std::error::Error for Redirect {}
+
+impl Redirect {
+    // Stops the render where the component answered with it. The page and every component around it
+    // pass the error on, and the renderer sends the redirect instead of a page.
+    pub fn into_error(self) -> minijinja::Error {
+        minijinja::Error::new(minijinja::ErrorKind::InvalidOperation, "a component redirected").with_source(self)
+    }
+
+    // Puts the flash in the session for the page the visitor is sent to.
+    pub async fn keep_flash(&self, session_manager: &Addr<SessionManagerActor>) {
+        let Some(flash) = &self.flash else {
+            return;
+        };
+        let key = FLASH_KEY.to_string();
+        let mut flashes = match session_manager.send(GetSessionValue { key: key.clone() }).await {
+            Ok(Ok(Some(serde_json::Value::Array(flashes)))) => flashes,
+            _ => Vec::new(),
+        };
+        flashes.push(flash.clone());
+        let stored = session_manager.send(SetSessionValue { key, value: serde_json::Value::Array(flashes) }).await;
+        if !matches!(stored, Ok(Ok(()))) {
+            log::warn!("Heads up! The flash for {} couldn't be kept in the session, so it won't be shown.", self.url);
+        }
+    }
+}
+
+// The redirect that stopped a render, if that's what stopped it.
+pub fn from_error(error: &minijinja::Error) -> Option<&Redirect> {
+    let mut source = std::error::Error::source(error);
+    while let Some(error) = source {
+        if let Some(redirect) = error.downcast_ref::<Redirect>() {
+            return Some(redirect);
+        }
+        source = error.source();
+    }
+    None
+}
+
+// The flashes waiting in the session, which are then forgotten.
+pub async fn take_flashes(session_manager: &Addr<SessionManagerActor>) -> Value {
+    let Ok(Ok(Some(flashes))) = session_manager.send(GetSessionValue { key: FLASH_KEY.to_string() }).await else {
+        return Value::from(Vec::<Value>::new());
+    };
+    let _ = session_manager.send(DeleteSessionValue { key: FLASH_KEY.to_string() }).await;
+    Value::from_serialize(flashes)
+}
+
+// `flashed_messages()` in any template: what `take_flashes` gave the render. Until the renderer has
+// taken them, it's an empty list and `wanted` is called so it does; every call after that, in the
+// same request, gives the same ones.
+pub fn add_flash_global(env: &mut Environment<'static>, taken: Option<Value>, wanted: impl Fn() + Send + Sync + 'static) {
+    env.add_function("flashed_messages", move || -> Value {
+        match &taken {
+            Some(flashes) => flashes.clone(),
+            None => {
+                wanted();
+                Value::from(Vec::<Value>::new())
+            }
+        }
+    });
+}
+
+#[pyfunction]
+#[pyo3(name = "redirect", signature = (url, status=302, flash=None))]
+fn py_redirect<'py>(py: Python<'py>, url: String, status: u16, flash: Option<Bound<'py, PyAny>>) -> PyResult<Bound<'py, PyDict>> {
+    if !STATUSES.contains(&status) {
+        return Err(PyValueError::new_err(format!("redirect() takes 301, 302, 303, 307 or 308 as its status, not {}", status)));
+    }
+    let answer = PyDict::new(py);
+    answer.set_item("_redirect", url)?;
+    answer.set_item("_redirect_status", status)?;
+    if let Some(flash) = flash.filter(|flash| !flash.is_none()) {
+        if is_permanent(status) {
+            return Err(PyValueError::new_err("A permanent redirect is remembered by the browser, which then doesn't ask again, so it can't carry a flash. Use 302 or 303."));
+        }
+        pythonize::depythonize::<serde_json::Value>(&flash).map_err(|e| {
+            PyTypeError::new_err(format!("A flash must be JSON-serializable (a string, or a dict like {{\"level\": \"error\", \"text\": ...}}): {}", e))
+        })?;
+        answer.set_item("_flash", flash)?;
+    }
+    Ok(answer)
+}
+
+pub fn register_python_module(py: Python) -> PyResult<()> {
+    let module = PyModule::new(py, "noventa_responses")?;
+    module.add_function(wrap_pyfunction!(py_redirect, &module)?)?;
+    py.import("sys")?.getattr("modules")?.set_item("noventa_responses", module)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::CString;
+
+    #[test]
+    fn test_from_context() {
+        let plain = Value::from_serialize(serde_json::json!({ "_redirect": "/login" }));
+        assert_eq!(from_context(&plain), Some(Redirect { url: "/login".to_string(), status: 303, flash: None }));
+        let moved = Value::from_serialize(serde_json::json!({ "_redirect": "/new", "_redirect_status": 308 }));
+        assert_eq!(from_context(&moved).unwrap().status, 308);
+        let wrong = Value::from_serialize(serde_json::json!({ "_redirect": "/new", "_redirect_status": 200 }));
+        assert_eq!(from_context(&wrong).unwrap().status, DEFAULT_STATUS);
+        assert_eq!(from_context(&Value::from_serialize(serde_json::json!({ "title": "Hi" }))), None);
+    }
+
+    #[test]
+    fn test_from_error() {
+        let mut env = Environment::new();
+        env.add_function("component", |name: String| -> Result<Value, minijinja::Error> {
+            match name.as_str() {
+                "guard" => Err(Redirect { url: "/login".to_string(), status: 302, flash: None }.into_error()),
+                _ => Ok(Value::from_safe_string(format!("<p>{}</p>", name))),
+            }
+        });
+        env.add_template("layout.html", "{% block body %}{% endblock %}").unwrap();
+        env.add_template("page.html", "{% extends 'layout.html' %}{% block body %}{{ component('a') }}{% include 'guarded.html' %}{% endblock %}").unwrap();
+        env.add_template("guarded.html", "{% for i in [1] %}{{ component('guard') }}{% endfor %}").unwrap();
+
+        let error = env.get_template("page.html").unwrap().render(()).unwrap_err();
+        assert_eq!(from_error(&error).map(|redirect| redirect.url.as_str()), Some("/login"));
+        let error = env.render_str("{{ nope() }}", ()).unwrap_err();
+        assert_eq!(from_error(&error), None);
+    }
+
+    #[test]
+    fn test_redirect() {
+        Python::attach(|py| {
+            register_python_module(py).unwrap();
+            let globals = PyDict::new(py);
+            let code = CString::new("from noventa_responses import redirect\nanswer = redirect('/orders', flash='Sent')\n").unwrap();
+            py.run(&code, Some(&globals), None).unwrap();
+            let answer: serde_json::Value = pythonize::depythonize(&globals.get_item("answer").unwrap().unwrap()).unwrap();
+            assert_eq!(answer, serde_json::json!({ "_redirect": "/orders", "_redirect_status": 302, "_flash": "Sent" }));
+            let answer = Value::from_serialize(&answer);
+            assert_eq!(from_context(&answer).unwrap().flash, Some(serde_json::json!("Sent")));
+
+            for bad in ["redirect('/orders', status=200)", "redirect('/orders', status=301, flash='Sent')"] {
+                let code = CString::new(format!("from noventa_responses import redirect\n{}\n", bad)).unwrap();
+                assert!(py.run(&code, None, None).is_err(), "{}", bad);
+            }
+        });
+    }
+}
@@ -0,0 +1,116 @@
+use dashmap::DashMap;
+use lazy_static::lazy_static;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+// What each render in flight is running in Python right now, so a render that runs out of time can
+// say what it was still waiting for instead of just "Timeout".
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+static NEXT_STEP: AtomicU64 = AtomicU64::new(1);
+
+lazy_static! {
+    static ref RUNNING: DashMap<u64, Vec<Step>> = DashMap::new();
+}
+
+#[derive(Clone, Debug)]
+pub struct Step {
+    id: u64,
+    // e.g. `components.cart.cart_logic`.
+    pub module_path: String,
+    pub function_name: String,
+    pub component: Option<String>,
+    pub started: Instant,
+}
+
+impl Step {
+    pub fn file_path(&self) -> String {
+        format!("{}.py", self.module_path.replace('.', "/"))
+    }
+}
+
+// A render's id for `HttpRequestInfo::render_id`. 0 is a request that isn't tracked.
+pub fn next_id() -> u64 {
+    NEXT_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+// Forgets the step when it's dropped, once the function has returned.
+pub struct Running {
+    render_id: u64,
+    step: u64,
+}
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        if self.render_id == 0 {
+            return;
+        }
+        RUNNING.remove_if_mut(&self.render_id, |_, steps| {
+            steps.retain(|step| step.id != self.step);
+            steps.is_empty()
+        });
+    }
+}
+
+pub fn enter(render_id: u64, module_path: &str, function_name: &str, component: Option<&str>) -> Running {
+    let step = NEXT_STEP.fetch_add(1, Ordering::Relaxed);
+    if render_id != 0 {
+        RUNNING.entry(render_id).or_default().push(Step {
+            id: step,
+            module_path: module_path.to_string(),
+            function_name: function_name.to_string(),
+            component: component.map(str::to_string),
+            started: Instant::now(),
+        });
+    }
+    Running { render_id, step }
+}
+
+// What the render is running, longest-running first.
+pub fn running(render_id: u64) -> Vec<Step> {
+    let mut steps = RUNNING.get(&render_id).map(|steps| steps.clone()).unwrap_or_default();
+    steps.sort_by_key(|step| step.started);
+    steps
+}
+
+// e.g. "pages/report.html didn't finish rendering within 10s. Still running: `load_template_context`
+// in components/chart/chart_logic.py (component `chart`), for 9.8s."
+pub fn timeout_message(template_path: &str, timeout: Duration, steps: &[Step]) -> String {
+    let given_up = format!("{} didn't finish rendering within {}s.", template_path, timeout.as_secs_f64());
+    if steps.is_empty() {
+        return format!("{} Nothing was running in Python by then, so it was waiting for a free interpreter or rendering its templates.", given_up);
+    }
+    let running: Vec<String> = steps
+        .iter()
+        .map(|step| {
+            let component = step.component.as_ref().map(|name| format!(" (component `{}`)", name)).unwrap_or_default();
+            format!("`{}` in {}{}, for {:.1}s", step.function_name, step.file_path(), component, step.started.elapsed().as_secs_f64())
+        })
+        .collect();
+    format!("{} Still running: {}.", given_up, running.join("; "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_running() {
+        let render_id = next_id();
+        let page = enter(render_id, "pages.report_page", "load_page_context", None);
+        let chart = enter(render_id, "components.chart.chart_logic", "load_template_context", Some("chart"));
+        assert_eq!(running(render_id).iter().map(|step| step.function_name.as_str()).collect::<Vec<_>>(), ["load_page_context", "load_template_context"]);
+
+        drop(page);
+        let message = timeout_message("pages/report.html", Duration::from_secs(10), &running(render_id));
+        assert!(message.starts_with("pages/report.html didn't finish rendering within 10s. Still running: `load_template_context` in components/chart/chart_logic.py (component `chart`), for"), "{}", message);
+
+        drop(chart);
+        assert!(running(render_id).is_empty());
+        assert!(!RUNNING.contains_key(&render_id));
+        assert!(timeout_message("pages/report.html", Duration::from_secs(10), &[]).contains("Nothing was running in Python"));
+
+        drop(enter(0, "pages.report_page", "load_page_context", None));
+        assert!(running(0).is_empty());
+    }
+}
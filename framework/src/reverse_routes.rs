@@ -0,0 +1,150 @@
+use crate::config;
+use lazy_static::lazy_static;
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use std::sync::RwLock;
+
+lazy_static! {
+    // Page names (`blog/[slug]`, the template's path in pages/ without `.html`) to route patterns.
+    static ref INDEX: RwLock<Option<HashMap<String, String>>> = RwLock::new(None);
+}
+
+fn build_index(pages_dir: &Path) -> HashMap<String, String> {
+    let (routes, _) = crate::routing::resolve_routes(pages_dir);
+    let mut index = HashMap::new();
+    for route in routes {
+        let Ok(relative) = route.template_path.strip_prefix(pages_dir) else {
+            continue;
+        };
+        let name = crate::paths::to_slash(relative);
+        let name = name.strip_suffix(".html").unwrap_or(&name).to_string();
+        // `blog/index` can also be called `blog`, and the home page `/`.
+        if let Some(dir) = name.strip_suffix("index") {
+            let dir = dir.trim_end_matches('/');
+            index.entry(if dir.is_empty() { "/".to_string() } else { dir.to_string() }).or_insert_with(|| route.route_pattern.clone());
+        }
+        index.insert(name, route.route_pattern);
+    }
+    index
+}
+
+// Pages were added, moved or redeployed.
+pub fn clear() {
+    *INDEX.write().unwrap() = None;
+}
+
+fn route_pattern(target: &str, rebuild: bool) -> Option<String> {
+    if rebuild || INDEX.read().unwrap().is_none() {
+        *INDEX.write().unwrap() = Some(build_index(&config::BASE_PATH.join("pages")));
+    }
+    INDEX.read().unwrap().as_ref().and_then(|index| index.get(target.trim_start_matches('/').trim_end_matches(".html")).or_else(|| index.get(target)).cloned())
+}
+
+// The route of a page as `url_for` names it, e.g. `/blog/{slug}` for `blog/[slug]`.
+pub fn page_route(target: &str) -> Option<String> {
+    route_pattern(target, false).or_else(|| route_pattern(target, true))
+}
+
+// Fills the pattern's `{name}` segments from `params`; the params left over become the query string.
+fn build_url(route_pattern: &str, params: &BTreeMap<String, String>) -> Result<String, String> {
+    let mut used = Vec::new();
+    let mut segments = Vec::new();
+    for segment in route_pattern.split('/').filter(|segment| !segment.is_empty()) {
+        match segment.strip_prefix('{').and_then(|rest| rest.strip_suffix('}')) {
+            Some(param) => {
+                // `[post_id]` pages have a `{post-id}` route; either spelling is accepted.
+                let name = param.replace('-', "_");
+                let value = params.get(&name).or_else(|| params.get(param)).ok_or_else(|| format!("it needs a value for `{}`", name))?;
+                used.push(name);
+                used.push(param.to_string());
+                segments.push(crate::object_storage::uri_encode(value, true));
+            }
+            None => segments.push(segment.to_string()),
+        }
+    }
+    let mut url = format!("/{}", segments.join("/"));
+    let query: Vec<(&String, &String)> = params.iter().filter(|(name, _)| !used.contains(name)).collect();
+    if !query.is_empty() {
+        url.push('?');
+        url.push_str(&serde_urlencoded::to_string(query).unwrap_or_default());
+    }
+    Ok(url)
+}
+
+// `url_for("blog/[slug]", {"slug": "hello"})` is `/blog/hello`. Unknown pages and missing params
+// are logged and give `#`, so one stale link doesn't take the whole page down.
+pub fn url_for(target: &str, params: &BTreeMap<String, String>) -> String {
+    let Some(pattern) = page_route(target) else {
+        log::warn!("Heads up! url_for('{}') doesn't match any page in pages/. Did it move?", target);
+        return "#".to_string();
+    };
+    build_url(&pattern, params).unwrap_or_else(|e| {
+        log::warn!("Heads up! url_for('{}') can't build a link: {}.", target, e);
+        "#".to_string()
+    })
+}
+
+// Like `url_for`, but `target` can also be a route as `noventa routes` lists it, e.g. `/blog/{slug}`,
+// and what's wrong is returned instead of logged.
+pub fn url_for_route(target: &str, params: &BTreeMap<String, String>) -> Result<String, String> {
+    if target.starts_with('/') {
+        return build_url(target, params);
+    }
+    let pattern = route_pattern(target, true).ok_or_else(|| format!("'{}' isn't a page in pages/", target))?;
+    build_url(&pattern, params)
+}
+
+// `{{ url_for("blog/[slug]", slug=post.slug) }}`. The parts are percent-encoded, so it's safe as is.
+pub fn url_for_function(target: String, kwargs: minijinja::value::Kwargs) -> Result<minijinja::Value, minijinja::Error> {
+    let mut params = BTreeMap::new();
+    for name in kwargs.args() {
+        let value: minijinja::Value = kwargs.get(name)?;
+        params.insert(name.to_string(), value.to_string());
+    }
+    Ok(minijinja::Value::from_safe_string(url_for(&target, &params)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    fn params(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect()
+    }
+
+    #[test]
+    fn test_build_index() {
+        let dir = tempdir().unwrap();
+        let pages = dir.path().join("pages");
+        fs::create_dir_all(pages.join("blog/[slug]")).unwrap();
+        fs::write(pages.join("index.html"), "").unwrap();
+        fs::write(pages.join("blog/index.html"), "").unwrap();
+        fs::write(pages.join("blog/[slug]/index.html"), "").unwrap();
+        fs::write(pages.join("about_us.html"), "").unwrap();
+
+        let index = build_index(&pages);
+        assert_eq!(index["index"], "/");
+        assert_eq!(index["/"], "/");
+        assert_eq!(index["blog"], "/blog");
+        assert_eq!(index["blog/[slug]"], "/blog/{slug}");
+        assert_eq!(index["blog/[slug]/index"], "/blog/{slug}");
+        assert_eq!(index["about_us"], "/about-us");
+    }
+
+    #[test]
+    fn test_build_url() {
+        assert_eq!(build_url("/blog/{slug}", &params(&[("slug", "hello world")])).unwrap(), "/blog/hello%20world");
+        assert_eq!(build_url("/users/{user-id}", &params(&[("user_id", "7"), ("tab", "posts")])).unwrap(), "/users/7?tab=posts");
+        assert_eq!(build_url("/", &params(&[])).unwrap(), "/");
+        assert!(build_url("/blog/{slug}", &params(&[])).unwrap_err().contains("`slug`"));
+    }
+
+    #[test]
+    fn test_url_for_route() {
+        assert_eq!(url_for_route("/blog/{slug}", &params(&[("slug", "hello"), ("page", "2")])).unwrap(), "/blog/hello?page=2");
+        assert_eq!(url_for_route("/blog/hello", &params(&[])).unwrap(), "/blog/hello");
+        assert!(url_for_route("no/such/page", &params(&[])).unwrap_err().contains("isn't a page"));
+    }
+}
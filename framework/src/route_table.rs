@@ -0,0 +1,201 @@
+use crate::routing::{resolve_routes, CompiledRoute, RouteConflict};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use std::path::Path;
+
+static COMPONENT_NAME_REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r#"component\s*\(\s*["']([^"']+)["']"#).unwrap());
+
+pub struct RouteRow {
+    pub route_pattern: String,
+    pub template: String,
+    pub params: Vec<String>,
+    pub methods: Vec<String>,
+}
+
+// A static route that wins over a dynamic one for a specific URL, e.g. /blog/new vs /blog/{slug}.
+pub struct ShadowedRoute {
+    pub dynamic_route: String,
+    pub static_route: String,
+}
+
+pub struct RouteTable {
+    pub rows: Vec<RouteRow>,
+    pub conflicts: Vec<RouteConflict>,
+    pub shadowed: Vec<ShadowedRoute>,
+}
+
+// Pages accept POST when they (or the components they render) contain a form that can trigger an action.
+fn accepts_post(template_path: &Path, components_dir: &Path) -> bool {
+    let source = std::fs::read_to_string(template_path).unwrap_or_default();
+    if source.contains("<form") {
+        return true;
+    }
+    COMPONENT_NAME_REGEX.captures_iter(&source).any(|caps| {
+        let component_dir = components_dir.join(caps[1].replace('.', "/"));
+        std::fs::read_dir(component_dir)
+            .map(|entries| {
+                entries.filter_map(Result::ok).any(|entry| {
+                    entry.path().extension().and_then(|e| e.to_str()) == Some("html")
+                        && std::fs::read_to_string(entry.path()).is_ok_and(|c| c.contains("<form"))
+                })
+            })
+            .unwrap_or(false)
+    })
+}
+
+fn find_shadowed(routes: &[CompiledRoute]) -> Vec<ShadowedRoute> {
+    let mut shadowed = Vec::new();
+    for dynamic in routes.iter().filter(|r| !r.param_names.is_empty()) {
+        for other in routes.iter().filter(|r| r.param_names.is_empty()) {
+            if dynamic.regex.is_match(&other.route_pattern) {
+                shadowed.push(ShadowedRoute {
+                    dynamic_route: dynamic.route_pattern.clone(),
+                    static_route: other.route_pattern.clone(),
+                });
+            }
+        }
+    }
+    shadowed
+}
+
+pub fn build_route_table(root: &Path) -> RouteTable {
+    let pages_dir = root.join("pages");
+    let components_dir = root.join("components");
+    let (routes, conflicts) = resolve_routes(&pages_dir);
+
+    let rows = routes
+        .iter()
+        .map(|route| {
+            let template = route.template_path.strip_prefix(root).unwrap_or(&route.template_path).display().to_string();
+            // `methods:` in the frontmatter is what routing enforces, so it's what the page accepts.
+            let source = std::fs::read_to_string(&route.template_path).unwrap_or_default();
+            let methods = crate::page_meta::parse(&template, &source).methods.unwrap_or_else(|| {
+                let mut methods = vec!["GET".to_string()];
+                if accepts_post(&route.template_path, &components_dir) {
+                    methods.push("POST".to_string());
+                }
+                methods
+            });
+            RouteRow {
+                route_pattern: route.route_pattern.clone(),
+                template,
+                params: route.param_names.clone(),
+                methods,
+            }
+        })
+        .collect();
+
+    RouteTable {
+        rows,
+        shadowed: find_shadowed(&routes),
+        conflicts,
+    }
+}
+
+pub fn print_route_table(table: &RouteTable) {
+    let headers = ["ROUTE", "TEMPLATE", "PARAMS", "METHODS"];
+    let cells: Vec<[String; 4]> = table
+        .rows
+        .iter()
+        .map(|row| {
+            [
+                row.route_pattern.clone(),
+                row.template.clone(),
+                if row.params.is_empty() { "-".to_string() } else { row.params.join(", ") },
+                row.methods.join(", "),
+            ]
+        })
+        .collect();
+
+    let mut widths = headers.map(|h| h.len());
+    for row in &cells {
+        for (i, cell) in row.iter().enumerate() {
+            widths[i] = widths[i].max(cell.chars().count());
+        }
+    }
+
+    let print_row = |row: [&str; 4]| {
+        println!(
+            "{:<w0$}  {:<w1$}  {:<w2$}  {}",
+            row[0], row[1], row[2], row[3],
+            w0 = widths[0], w1 = widths[1], w2 = widths[2]
+        );
+    };
+    print_row(headers);
+    for row in &cells {
+        print_row([&row[0], &row[1], &row[2], &row[3]]);
+    }
+
+    if table.rows.is_empty() {
+        println!("\nNo pages found. Add an .html file to the pages folder to create your first route.");
+    }
+
+    for shadowed in &table.shadowed {
+        println!(
+            "\nNote: '{}' is served by its own page, so '{}' never receives that URL.",
+            shadowed.static_route, shadowed.dynamic_route
+        );
+    }
+
+    for conflict in &table.conflicts {
+        println!(
+            "\nConflict: {} ({}) clashes with {}, which is the one that gets registered.",
+            conflict.route_pattern,
+            conflict.template_path.display(),
+            conflict.conflicts_with.display()
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_build_route_table() {
+        let dir = tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("pages/blog")).unwrap();
+        fs::create_dir_all(root.join("components/signup")).unwrap();
+        fs::write(root.join("pages/index.html"), "{{ component('signup') }}").unwrap();
+        fs::write(root.join("pages/blog/new.html"), "<p>new</p>").unwrap();
+        fs::write(root.join("pages/blog/[slug].html"), "<p>post</p>").unwrap();
+        fs::write(root.join("pages/contact.html"), "---\nmethods: [post]\n---\n<form method=\"post\"></form>").unwrap();
+        fs::write(root.join("components/signup/signup_template.html"), "<form method=\"post\"></form>").unwrap();
+
+        let table = build_route_table(root);
+        assert_eq!(table.rows.len(), 4);
+        assert!(table.conflicts.is_empty());
+
+        let index = table.rows.iter().find(|r| r.route_pattern == "/").unwrap();
+        assert_eq!(index.methods, vec!["GET", "POST"]);
+        assert_eq!(index.template, "pages/index.html");
+
+        let post = table.rows.iter().find(|r| r.route_pattern == "/blog/{slug}").unwrap();
+        assert_eq!(post.params, vec!["slug"]);
+        assert_eq!(post.methods, vec!["GET"]);
+
+        let contact = table.rows.iter().find(|r| r.route_pattern == "/contact").unwrap();
+        assert_eq!(contact.methods, vec!["POST"]);
+
+        assert_eq!(table.shadowed.len(), 1);
+        assert_eq!(table.shadowed[0].static_route, "/blog/new");
+        assert_eq!(table.shadowed[0].dynamic_route, "/blog/{slug}");
+    }
+
+    #[test]
+    fn test_build_route_table_reports_conflicts() {
+        let dir = tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("pages/conflict")).unwrap();
+        fs::write(root.join("pages/conflict.html"), "").unwrap();
+        fs::write(root.join("pages/conflict/index.html"), "").unwrap();
+
+        let table = build_route_table(root);
+        assert_eq!(table.rows.len(), 1);
+        assert_eq!(table.conflicts.len(), 1);
+    }
+}
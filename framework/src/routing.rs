@@ -1,18 +1,21 @@
-use crate::actors::health::{GetSystemHealth, HealthActor};
+use crate::actors::health::{self, GetSystemHealth, HealthActor, ReportRequest};
 use crate::actors::page_renderer::{HttpRequestInfo, RenderMessage, RenderOutput};
-use crate::actors::router::{MatchRoute, RouterActor};
+use crate::actors::router::{MatchRoute, ReloadRoutes, RouterActor};
 use crate::actors::session_manager::SessionManagerActor;
+use crate::fileupload::{BodyError, BodyLimits};
 use actix::{Actor, Addr, Recipient};
 use actix_multipart::Multipart;
 use actix_session::Session;
-use actix_web::{web, HttpRequest, HttpResponse, Responder};
-use futures_util::stream::StreamExt;
+use actix_web::{web, HttpMessage, HttpRequest, HttpResponse, Responder};
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use regex::Regex;
 use walkdir::WalkDir;
 
+// How long browsers keep a 301 or 308.
+const PERMANENT_REDIRECT_CACHE: &str = "public, max-age=86400";
+
 #[derive(Debug, Clone)]
 pub struct CompiledRoute {
     pub regex: Regex,
@@ -21,11 +24,22 @@ pub struct CompiledRoute {
     pub route_pattern: String,
 }
 
-pub fn get_compiled_routes(pages_dir: &Path) -> Vec<CompiledRoute> {
+// Two page files that resolve to the same route prefix. The first one found wins.
+#[derive(Debug, Clone)]
+pub struct RouteConflict {
+    pub route_pattern: String,
+    pub template_path: PathBuf,
+    pub conflicts_with: PathBuf,
+}
+
+// Scans the pages directory and compiles every route, collecting conflicts instead of failing on them.
+pub fn resolve_routes(pages_dir: &Path) -> (Vec<CompiledRoute>, Vec<RouteConflict>) {
     let mut routes: Vec<(String, PathBuf)> = WalkDir::new(pages_dir)
         .into_iter()
         .filter_map(Result::ok)
         .filter(|e| e.path().is_file() && e.path().extension().and_then(|s| s.to_str()) == Some("html"))
+        // `about.de.html` is served at `/about`, in place of `about.html`.
+        .filter(|e| !crate::localized::is_variant(e.path()))
         .map(|e| {
             let path = e.path().to_path_buf();
             let route = path_to_route(&path, pages_dir);
@@ -34,8 +48,8 @@ pub fn get_compiled_routes(pages_dir: &Path) -> Vec<CompiledRoute> {
         .collect();
 
     routes.sort_by(|(a, _), (b, _)| {
-        let a_parts = Path::new(a).components().count();
-        let b_parts = Path::new(b).components().count();
+        let a_parts = url_segments(a).count();
+        let b_parts = url_segments(b).count();
         let a_is_dynamic = a.contains('{');
         let b_is_dynamic = b.contains('{');
 
@@ -43,37 +57,48 @@ pub fn get_compiled_routes(pages_dir: &Path) -> Vec<CompiledRoute> {
     });
 
     let mut final_routes = Vec::new();
-    let mut registered_routes = HashMap::new();
+    let mut conflicts = Vec::new();
+    let mut registered_routes: HashMap<String, PathBuf> = HashMap::new();
 
     for (route_pattern, template_path) in routes {
         let route_key = route_pattern.split('{').next().unwrap_or("").to_string();
-        if registered_routes.contains_key(&route_key) {
-            panic!(
-                "Route conflict detected: {}. A route with a similar path has already been registered.",
-                route_pattern
-            );
+        if let Some(existing) = registered_routes.get(&route_key) {
+            conflicts.push(RouteConflict {
+                route_pattern,
+                template_path,
+                conflicts_with: existing.clone(),
+            });
+            continue;
         }
-        registered_routes.insert(route_key, route_pattern.contains('{'));
+        registered_routes.insert(route_key, template_path.clone());
 
         log::debug!("Route registered: {} -> {}", route_pattern, template_path.display());
         final_routes.push(compile_route(route_pattern, template_path));
     }
 
-    final_routes
+    (final_routes, conflicts)
+}
+
+pub fn get_compiled_routes(pages_dir: &Path) -> Vec<CompiledRoute> {
+    let (routes, conflicts) = resolve_routes(pages_dir);
+    if let Some(conflict) = conflicts.first() {
+        panic!(
+            "Route conflict detected: {}. A route with a similar path has already been registered.",
+            conflict.route_pattern
+        );
+    }
+    routes
+}
+
+// Route patterns are URLs, so they're split on `/` rather than read as (platform) paths.
+fn url_segments(route: &str) -> impl Iterator<Item = &str> {
+    route.split('/').filter(|segment| !segment.is_empty())
 }
 
 fn compile_route(route_pattern: String, template_path: PathBuf) -> CompiledRoute {
     let mut param_names = Vec::new();
     
-    let parts: Vec<String> = std::path::Path::new(&route_pattern)
-        .components()
-        .filter_map(|comp| {
-            let part = comp.as_os_str().to_string_lossy();
-            if part == "/" {
-                return None;
-            }
-            Some(part.to_string())
-        })
+    let parts: Vec<String> = url_segments(&route_pattern)
         .map(|part| {
             if part.starts_with('{') && part.ends_with('}') {
                 let param_name = &part[1..part.len() - 1];
@@ -81,7 +106,7 @@ fn compile_route(route_pattern: String, template_path: PathBuf) -> CompiledRoute
                 param_names.push(sanitized_name.clone());
                 format!(r"(?P<{}>[^/]+)", sanitized_name)
             } else {
-                regex::escape(&part)
+                regex::escape(part)
             }
         })
         .collect();
@@ -106,7 +131,7 @@ pub fn get_routes(_pages_dir: &Path) -> Vec<(String, PathBuf)> {
     vec![]
 }
 
-fn path_to_route(path: &Path, base_dir: &Path) -> String {
+pub fn path_to_route(path: &Path, base_dir: &Path) -> String {
     let relative_path = match path.strip_prefix(base_dir) {
         Ok(p) => p,
         Err(_) => return String::new(),
@@ -146,36 +171,45 @@ fn path_to_route(path: &Path, base_dir: &Path) -> String {
 async fn parse_request_body(
     req: &HttpRequest,
     mut payload: web::Payload,
-) -> (serde_json::Map<String, serde_json::Value>, HashMap<String, crate::actors::page_renderer::FilePart>) {
-    if req.method() == actix_web::http::Method::POST {
-        let content_type = req.headers().get("content-type").map(|v| v.to_str().unwrap_or("")).unwrap_or("");
-        if content_type.starts_with("multipart/form-data") {
-            let multipart = Multipart::new(req.headers(), payload);
-            crate::fileupload::handle_multipart(multipart).await
-        } else {
-            let mut body = web::BytesMut::new();
-            while let Some(chunk) = payload.next().await {
-                let chunk = chunk.unwrap();
-                body.extend_from_slice(&chunk);
-            }
-            let form_data = if let Ok(parsed) = serde_urlencoded::from_bytes::<HashMap<String, String>>(&body) {
-                parsed.into_iter().map(|(k, v)| (k, serde_json::Value::String(v))).collect()
-            } else {
-                serde_json::Map::new()
-            };
-            (form_data, HashMap::new())
-        }
+) -> Result<
+    (serde_json::Map<String, serde_json::Value>, HashMap<String, crate::actors::page_renderer::FilePart>),
+    BodyError,
+> {
+    if req.method() != actix_web::http::Method::POST {
+        return Ok((serde_json::Map::new(), HashMap::new()));
+    }
+
+    let limits = BodyLimits::from_config(&crate::config::CONFIG);
+    // Refuse what the client already told us is too big before reading any of it.
+    let content_length = req.headers().get("content-length").and_then(|v| v.to_str().ok()).and_then(|v| v.parse::<usize>().ok());
+    if content_length.is_some_and(|length| length > limits.max_request_size) {
+        return Err(BodyError::TooLarge(format!(
+            "The request is larger than the {} bytes allowed by `max_request_size`.",
+            limits.max_request_size
+        )));
+    }
+
+    let content_type = req.headers().get("content-type").map(|v| v.to_str().unwrap_or("")).unwrap_or("");
+    if content_type.starts_with("multipart/form-data") {
+        let multipart = Multipart::new(req.headers(), payload);
+        crate::fileupload::handle_multipart(multipart, &limits).await
     } else {
-        (serde_json::Map::new(), HashMap::new())
+        let body = crate::fileupload::read_body(&mut payload, &limits).await?;
+        let form_data = if let Ok(parsed) = serde_urlencoded::from_bytes::<HashMap<String, String>>(&body) {
+            parsed.into_iter().map(|(k, v)| (k, serde_json::Value::String(v))).collect()
+        } else {
+            serde_json::Map::new()
+        };
+        Ok((form_data, HashMap::new()))
     }
 }
 
-fn build_http_request_info(
+pub fn build_http_request_info(
     req: &HttpRequest,
     form_data: serde_json::Map<String, serde_json::Value>,
     files: HashMap<String, crate::actors::page_renderer::FilePart>,
     path_params: HashMap<String, String>,
-    _session: Option<&Session>,
+    session: Option<&Session>,
 ) -> HttpRequestInfo {
     let headers = req
         .headers()
@@ -186,9 +220,8 @@ fn build_http_request_info(
     let query_params: HashMap<String, String> =
         serde_urlencoded::from_str(req.query_string()).unwrap_or_default();
 
-    let scheme = req.connection_info().scheme().to_string();
-    let host = req.connection_info().host().to_string();
-    let remote_addr = req.connection_info().realip_remote_addr().map(|s| s.to_string());
+    let crate::proxy::ClientInfo { scheme, host, remote_addr } =
+        crate::proxy::client_info(req, &crate::proxy::TRUSTED_PROXIES);
     let full_path = if req.query_string().is_empty() {
         req.path().to_string()
     } else {
@@ -263,6 +296,74 @@ fn build_http_request_info(
         range: get_header_value("range"),
         referrer: get_header_value("referer"),
         remote_user: get_header_value("remote-user"),
+        user: session.and_then(crate::oidc::user_claims),
+        csp_nonce: req.extensions().get::<crate::security_headers::CspNonce>().map(|nonce| nonce.0.clone()),
+        is_signed: req.extensions().get::<crate::signed_urls::SignedUrl>().is_some(),
+        fragment: crate::fragments::requested(req),
+        deadline: None,
+        render_id: 0,
+        trace: None,
+        component_only: req.headers().contains_key("x-noventa-partial"),
+    }
+}
+
+fn redirect_response(req: &HttpRequest, status: u16, url: String) -> HttpResponse {
+    if req.headers().contains_key("X-Requested-With") {
+        // It's an XHR request, send 200 OK with a custom header
+        return HttpResponse::Ok()
+            .append_header(("X-Noventa-Redirect", url))
+            .finish();
+    }
+    let status = actix_web::http::StatusCode::from_u16(status)
+        .ok()
+        .filter(|status| status.is_redirection())
+        .unwrap_or(actix_web::http::StatusCode::SEE_OTHER);
+    let mut response = HttpResponse::build(status);
+    response.append_header(("Location", url));
+    if crate::redirects::is_permanent(status.as_u16()) {
+        // Without a Cache-Control, browsers keep a permanent redirect forever, and one sent by
+        // mistake can't be taken back. A day at a time, it can.
+        response.append_header((actix_web::http::header::CACHE_CONTROL, PERMANENT_REDIRECT_CACHE));
+    }
+    response.finish()
+}
+
+// Renders `auth.forbidden_page` like any other page, but with a 403 and without the request's body.
+async fn forbidden_response(
+    req: &HttpRequest,
+    renderer: &web::Data<Recipient<RenderMessage>>,
+    session: Session,
+    path_params: HashMap<String, String>,
+) -> HttpResponse {
+    const FORBIDDEN: &str = "You don't have permission to see this page.";
+    let Some(page) = crate::config::CONFIG.auth.as_ref().and_then(|auth| auth.forbidden_page.clone()) else {
+        return HttpResponse::Forbidden().body(FORBIDDEN);
+    };
+    let mut request_info = build_http_request_info(req, serde_json::Map::new(), HashMap::new(), path_params, Some(&session));
+    // The forbidden page goes out whole, even where the request asked for one of the page's fragments.
+    request_info.fragment = None;
+    let render_msg = RenderMessage {
+        template_path: page.clone(),
+        request_info: Arc::new(request_info),
+        session_manager: SessionManagerActor::new(session).start(),
+    };
+    match renderer.send(render_msg).await {
+        Ok(Ok(RenderOutput::Html(html))) => {
+            let mut response = crate::compressed_pages::html_response(req, html).await;
+            *response.status_mut() = actix_web::http::StatusCode::FORBIDDEN;
+            response
+        }
+        Ok(Ok(RenderOutput::Redirect(status, url))) => redirect_response(req, status, url),
+        Ok(Ok(RenderOutput::Status(_, html))) | Ok(Ok(RenderOutput::Component(html))) => HttpResponse::Forbidden().content_type("text/html").body(html),
+        Ok(Ok(RenderOutput::Stream(_))) => HttpResponse::Forbidden().body(FORBIDDEN),
+        Ok(Ok(RenderOutput::Shed)) => crate::actors::load_shedding::shed_response(),
+        Ok(Err(mut detailed_error)) => {
+            log::error!("Oh no! The forbidden page {} failed to render, so a plain 403 went out instead.", page);
+            detailed_error.route = Some(req.path().to_string());
+            crate::errors::record_error(&detailed_error);
+            HttpResponse::Forbidden().body(FORBIDDEN)
+        }
+        Err(_) => HttpResponse::Forbidden().body(FORBIDDEN),
     }
 }
 
@@ -275,8 +376,73 @@ pub async fn handle_page(
     path_params: HashMap<String, String>,
     dev_mode: bool,
 ) -> HttpResponse {
-    let (form_data, files) = parse_request_body(&req, payload).await;
-    let request_info = build_http_request_info(&req, form_data, files, path_params, Some(&session));
+    // Carries on the caller's trace if it sent a `traceparent`.
+    let traceparent = req.headers().get("traceparent").and_then(|value| value.to_str().ok());
+    let mut span = crate::telemetry::start_request(traceparent, format!("{} {}", req.method(), template_path));
+    span.set_attribute("http.request.method", req.method());
+    span.set_attribute("url.path", req.path());
+    span.set_attribute("http.route", &template_path);
+    if let Some(request_id) = req.extensions().get::<crate::access_log::RequestId>() {
+        span.set_attribute("http.request.id", &request_id.0);
+    }
+    let started = std::time::Instant::now();
+    let route = template_path.clone();
+    let page_meta = crate::page_meta::for_page(&template_path, dev_mode);
+    // `methods: [GET]` in the frontmatter turns other methods away; HEAD goes wherever GET does.
+    if let Some(methods) = &page_meta.methods {
+        let method = req.method().as_str();
+        let allowed = methods.iter().any(|m| m == method || (method == "HEAD" && m == "GET"));
+        if !allowed {
+            return HttpResponse::MethodNotAllowed().append_header(("Allow", methods.join(", "))).finish();
+        }
+    }
+    // `{# login_required #}` pages send visitors who aren't logged in to the login page first.
+    if (page_meta.login_required || !page_meta.requires.is_empty()) && !crate::auth::is_logged_in(&session) {
+        return redirect_response(&req, crate::redirects::DEFAULT_STATUS, crate::auth::login_redirect(&req));
+    }
+    // `{# signed_url_required #}` pages only open from links made by `url_for_signed()`.
+    if page_meta.signed_url_required && req.extensions().get::<crate::signed_urls::SignedUrl>().is_none() {
+        return HttpResponse::Forbidden().body("This page can only be opened from a signed link.");
+    }
+    // `{# requires: role=admin #}` pages turn away logged-in users without that role.
+    if !crate::auth::meets(&crate::auth::roles(&session), &page_meta.requires) {
+        log::info!("Turned away {} {}: the user doesn't have what {} requires.", req.method(), req.path(), template_path);
+        return forbidden_response(&req, &renderer, session, path_params).await;
+    }
+
+    let (form_data, files) = match parse_request_body(&req, payload).await {
+        Ok(body) => body,
+        Err(e) => {
+            log::warn!("Rejected the body of {} {}: {:?}", req.method(), req.path(), e);
+            return e.to_response();
+        }
+    };
+    let mut request_info = build_http_request_info(&req, form_data, files, path_params, Some(&session));
+    let fragment = request_info.fragment.clone();
+    // `about.de.html` stands in for `about.html` for visitors who prefer German.
+    let template_path = match crate::localized::pick(&page_meta.locales, &crate::template_filters::request_locale(&request_info.accept_languages)) {
+        Some(locale) => crate::localized::variant_path(&template_path, locale),
+        None => template_path,
+    };
+
+    // `{# max_concurrency: N #}` pages render N at a time; the rest wait here, not in the interpreter pool.
+    let _turn = match page_meta.max_concurrency {
+        Some(limit) => match crate::page_limits::wait_for_turn(&template_path, limit).await {
+            Ok(turn) => Some(turn),
+            Err(rejected) => {
+                log::warn!("Turned away {} {}: {} is at its max_concurrency of {} ({:?}).", req.method(), req.path(), template_path, limit, rejected);
+                return HttpResponse::ServiceUnavailable()
+                    .append_header(("Retry-After", "5"))
+                    .body("This page is busy right now. Please try again in a few seconds.");
+            }
+        },
+        None => None,
+    };
+    // The clock starts once it's the page's turn.
+    let render_timeout = crate::actors::page_renderer::render_timeout(page_meta.render_timeout);
+    request_info.deadline = Some(crate::actors::page_renderer::now_ms() + render_timeout.as_millis() as u64);
+    request_info.render_id = crate::render_progress::next_id();
+    request_info.trace = span.context();
 
     let session_manager = SessionManagerActor::new(session).start();
 
@@ -286,25 +452,57 @@ pub async fn handle_page(
         session_manager,
     };
 
-    match renderer.send(render_msg).await {
+    let mut python_error = false;
+    let mut response = match renderer.send(render_msg).await {
         Ok(Ok(render_output)) => match render_output {
-            RenderOutput::Html(html) => HttpResponse::Ok().content_type("text/html").body(html),
-            RenderOutput::Redirect(url) => {
-                if req.headers().contains_key("X-Requested-With") {
-                    // It's an XHR request, send 200 OK with a custom header
-                    HttpResponse::Ok()
-                        .append_header(("X-Noventa-Redirect", url))
-                        .finish()
-                } else {
-                    // It's a regular request, send a 303 redirect
-                    HttpResponse::SeeOther()
-                        .append_header(("Location", url))
-                        .finish()
+            RenderOutput::Html(html) => {
+                let cacheable = req.method() == actix_web::http::Method::GET || req.method() == actix_web::http::Method::HEAD;
+                // A fragment on its own can be cached apart from its page: by its arguments, with its own
+                // `cache=` or else the page's.
+                let etag = fragment.as_deref().filter(|_| cacheable).map(|fragment| crate::fragments::etag(fragment, req.query_string(), &html));
+                let cache_control = fragment.as_ref().and_then(|fragment| page_meta.fragment_cache.get(fragment)).or(page_meta.cache_control.as_ref());
+                let mut response = match &etag {
+                    Some(etag) if crate::fragments::not_modified(&req, etag) => HttpResponse::NotModified().finish(),
+                    _ => crate::compressed_pages::html_response(&req, html).await,
+                };
+                let headers = response.headers_mut();
+                if !page_meta.locales.is_empty() {
+                    headers.append(actix_web::http::header::VARY, actix_web::http::header::HeaderValue::from_static("Accept-Language"));
+                }
+                if let Some(etag) = etag
+                    && let Ok(value) = actix_web::http::header::HeaderValue::from_str(&etag)
+                {
+                    headers.insert(actix_web::http::header::ETAG, value);
+                    headers.append(actix_web::http::header::VARY, actix_web::http::header::HeaderValue::from_static("X-Fragment"));
+                }
+                // `cache:` in the frontmatter, for what every visitor gets the same.
+                if let Some(cache_control) = cache_control
+                    && cacheable
+                    && let Ok(value) = actix_web::http::header::HeaderValue::from_str(cache_control)
+                {
+                    headers.insert(actix_web::http::header::CACHE_CONTROL, value);
                 }
+                response
             }
+            // Not cached: a page that's gone or not there yet can be back with a 200 next time.
+            RenderOutput::Status(code, html) => {
+                let mut response = crate::compressed_pages::html_response(&req, html).await;
+                *response.status_mut() = actix_web::http::StatusCode::from_u16(code).unwrap_or(actix_web::http::StatusCode::OK);
+                if !page_meta.locales.is_empty() {
+                    response.headers_mut().append(actix_web::http::header::VARY, actix_web::http::header::HeaderValue::from_static("Accept-Language"));
+                }
+                response
+            }
+            RenderOutput::Redirect(status, url) => redirect_response(&req, status, url),
+            // frontend.js swaps it in where the component was.
+            RenderOutput::Component(html) => HttpResponse::Ok().content_type("text/html").append_header(("X-Noventa-Partial", "component")).body(html),
+            RenderOutput::Stream(stream) => crate::streaming::response(stream),
+            RenderOutput::Shed => crate::actors::load_shedding::shed_response(),
         },
         Ok(Err(mut detailed_error)) => {
+            python_error = matches!(detailed_error.error_source, Some(crate::errors::ErrorSource::Python(_)));
             detailed_error.route = Some(req.path().to_string());
+            crate::errors::record_error(&detailed_error);
             if dev_mode {
                 let html = crate::templates::render_structured_debug_error(&detailed_error);
                 HttpResponse::Ok().content_type("text/html").body(html)
@@ -317,7 +515,21 @@ pub async fn handle_page(
             log::error!("A mailbox error occurred: {}. This might indicate a problem with the server's internal communication.", e);
             HttpResponse::InternalServerError().finish()
         }
+    };
+    response.extensions_mut().insert(crate::access_log::Route(route.clone()));
+    if let Some(health_actor) = req.app_data::<web::Data<Addr<HealthActor>>>() {
+        health_actor.do_send(ReportRequest {
+            route,
+            status: response.status().as_u16(),
+            duration_ms: started.elapsed().as_secs_f64() * 1000.0,
+            python_error,
+        });
     }
+    span.set_attribute("http.response.status_code", response.status().as_u16());
+    if response.status().is_server_error() {
+        span.set_error(response.status());
+    }
+    response
 }
 pub async fn health_check(health_actor: web::Data<Addr<HealthActor>>) -> impl Responder {
     match health_actor.send(GetSystemHealth).await {
@@ -329,6 +541,59 @@ pub async fn health_check(health_actor: web::Data<Addr<HealthActor>>) -> impl Re
     }
 }
 
+// The same numbers for Prometheus to scrape.
+pub async fn metrics(health_actor: web::Data<Addr<HealthActor>>) -> HttpResponse {
+    match health_actor.send(GetSystemHealth).await {
+        Ok(health) => HttpResponse::Ok().content_type("text/plain; version=0.0.4").body(health::prometheus(&health)),
+        Err(e) => {
+            log::error!("Could not retrieve system health: {}. The health check actor might be experiencing issues.", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+// `noventa dev` only: which pages are slow or failing, as a page to keep open while clicking around.
+pub async fn stats_dashboard(req: HttpRequest, health_actor: web::Data<Addr<HealthActor>>) -> HttpResponse {
+    match health_actor.send(GetSystemHealth).await {
+        Ok(health) => {
+            let nonce = req.extensions().get::<crate::security_headers::CspNonce>().map(|nonce| nonce.0.clone()).unwrap_or_default();
+            HttpResponse::Ok()
+                .content_type("text/html")
+                .insert_header(("Cache-Control", "no-store"))
+                .body(crate::templates::render_stats_dashboard(&health, &nonce))
+        }
+        Err(e) => {
+            log::error!("Could not retrieve system health: {}. The health check actor might be experiencing issues.", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+// `noventa dev` only: the errors it ran into, for when nobody was looking at the terminal or the page.
+pub async fn error_history(req: HttpRequest) -> HttpResponse {
+    let nonce = req.extensions().get::<crate::security_headers::CspNonce>().map(|nonce| nonce.0.clone()).unwrap_or_default();
+    HttpResponse::Ok()
+        .content_type("text/html")
+        .insert_header(("Cache-Control", "no-store"))
+        .body(crate::templates::render_error_history(&crate::errors::error_history(), &nonce))
+}
+
+// For load balancers and orchestrators: 503 while this instance can't serve pages properly, or is warming up.
+pub async fn readiness_check() -> HttpResponse {
+    let readiness = crate::readiness::check().await;
+    if readiness.ready {
+        HttpResponse::Ok().json(readiness)
+    } else {
+        HttpResponse::ServiceUnavailable().json(readiness)
+    }
+}
+
+// For liveness probes: the process is up and answering. Restarting it wouldn't help with anything
+// /health/ready reports.
+pub async fn liveness_check() -> HttpResponse {
+    HttpResponse::Ok().json(serde_json::json!({ "live": true }))
+}
+
 pub async fn dynamic_route_handler(
     req: HttpRequest,
     payload: web::Payload,
@@ -347,8 +612,8 @@ pub async fn dynamic_route_handler(
             let dev_mode = req.app_data::<web::Data<bool>>().map_or(false, |d| *d.get_ref());
             if dev_mode && req.path() == "/" {
                 // In dev mode, if no / page is found, show a welcome page
-                const DEV_MODE_INDEX: &str = include_str!("templates/dev_mode_index.html");
-                HttpResponse::Ok().content_type("text/html").body(DEV_MODE_INDEX)
+                let html = crate::onboarding::render(std::path::Path::new("."), &crate::config::CONFIG);
+                HttpResponse::Ok().content_type("text/html").body(html)
             } else {
                 HttpResponse::NotFound().finish()
             }
@@ -357,6 +622,50 @@ pub async fn dynamic_route_handler(
     }
 }
 
+// Rescans pages/ and components/ so `noventa serve` with `reload_pages` picks up a content deploy.
+pub fn reload_pages(router: &Addr<RouterActor>) {
+    router.do_send(ReloadRoutes);
+    crate::actors::template_renderer::invalidate_pages();
+    crate::page_meta::clear();
+    log::info!("✨ Pages reloaded. New and changed templates are live.");
+}
+
+// Only direct connections over loopback count as the server itself. Anything that came through a
+// proxy could be from anywhere, and so could a unix socket connection, since a proxy may sit in front
+// of it without adding forwarding headers.
+fn is_local_request(req: &HttpRequest) -> bool {
+    let proxied = ["forwarded", "x-forwarded-for", "x-real-ip"]
+        .iter()
+        .any(|header| req.headers().contains_key(*header));
+    !proxied && req.peer_addr().is_some_and(|peer| peer.ip().to_canonical().is_loopback())
+}
+
+pub async fn reload_pages_handler(
+    req: HttpRequest,
+    router: web::Data<Addr<RouterActor>>,
+    token: Option<web::Data<crate::admin::AdminToken>>,
+) -> HttpResponse {
+    let has_token = token.is_some_and(|token| crate::admin::is_authorized(&req, &token));
+    if !has_token && !is_local_request(&req) {
+        return HttpResponse::Forbidden().body("Pages can only be reloaded from the server itself or with the admin token.");
+    }
+    reload_pages(&router);
+    HttpResponse::Ok().json(serde_json::json!({ "reloaded": true }))
+}
+
+// POST /_noventa/reload exists only with `reload_pages` on. The admin token, if there is one, lets
+// callers that aren't on this machine reload too.
+pub fn configure_reload(cfg: &mut web::ServiceConfig, enabled: bool, admin_token: Option<String>) {
+    if !enabled {
+        return;
+    }
+    let mut resource = web::resource("/_noventa/reload").route(web::post().to(reload_pages_handler));
+    if let Some(token) = admin_token {
+        resource = resource.app_data(web::Data::new(crate::admin::AdminToken(token)));
+    }
+    cfg.service(resource);
+}
+
 pub async fn handle_page_native(
     req: HttpRequest,
     payload: web::Payload,
@@ -367,7 +676,7 @@ pub async fn handle_page_native(
 ) -> HttpResponse {
     let dev_mode = req.app_data::<web::Data<bool>>().map_or(false, |d| *d.get_ref());
     let full_template_path = template_path.get_ref().clone();
-    let template_path_str = std::path::Path::new(&full_template_path).strip_prefix(&*crate::config::BASE_PATH).unwrap_or(std::path::Path::new(&full_template_path)).to_str().unwrap().to_string();
+    let template_path_str = crate::paths::relative_name(std::path::Path::new(&full_template_path), &crate::config::BASE_PATH);
     handle_page(req, payload, renderer, session, template_path_str, path_params.into_inner(), dev_mode).await
 }
 
@@ -378,6 +687,88 @@ mod tests {
     use tempfile::tempdir;
     use std::collections::HashMap;
 
+    #[test]
+    fn test_is_local_request() {
+        use actix_web::test::TestRequest;
+
+        let local = TestRequest::post().peer_addr("127.0.0.1:5000".parse().unwrap()).to_http_request();
+        assert!(is_local_request(&local));
+        let unix_socket = TestRequest::post().to_http_request();
+        assert!(!is_local_request(&unix_socket));
+        let remote = TestRequest::post().peer_addr("203.0.113.9:5000".parse().unwrap()).to_http_request();
+        assert!(!is_local_request(&remote));
+        let proxied = TestRequest::post()
+            .peer_addr("127.0.0.1:5000".parse().unwrap())
+            .insert_header(("x-forwarded-for", "203.0.113.9"))
+            .to_http_request();
+        assert!(!is_local_request(&proxied));
+    }
+
+    #[actix_rt::test]
+    async fn test_reload_endpoint() {
+        use actix_web::test::{call_service, init_service, TestRequest};
+        use actix_web::App;
+
+        let router = RouterActor::new().start();
+        let app = |enabled: bool| {
+            let router = router.clone();
+            init_service(
+                App::new()
+                    .app_data(web::Data::new(router))
+                    .configure(move |cfg| configure_reload(cfg, enabled, Some("s3cret".to_string())))
+                    .default_service(web::route().to(HttpResponse::NotFound)),
+            )
+        };
+
+        // Off unless `reload_pages` says so, even with an admin token.
+        let disabled = app(false).await;
+        let req = TestRequest::post()
+            .uri("/_noventa/reload")
+            .peer_addr("127.0.0.1:5000".parse().unwrap())
+            .insert_header(("authorization", "Bearer s3cret"))
+            .to_request();
+        assert_eq!(call_service(&disabled, req).await.status(), 404);
+
+        let enabled = app(true).await;
+        let req = TestRequest::post().uri("/_noventa/reload").peer_addr("127.0.0.1:5000".parse().unwrap()).to_request();
+        assert_eq!(call_service(&enabled, req).await.status(), 200);
+        // No peer address: the unix socket, maybe behind a proxy.
+        let req = TestRequest::post().uri("/_noventa/reload").to_request();
+        assert_eq!(call_service(&enabled, req).await.status(), 403);
+        let req = TestRequest::post()
+            .uri("/_noventa/reload")
+            .insert_header(("authorization", "Bearer wrong"))
+            .to_request();
+        assert_eq!(call_service(&enabled, req).await.status(), 403);
+        let req = TestRequest::post()
+            .uri("/_noventa/reload")
+            .peer_addr("203.0.113.9:5000".parse().unwrap())
+            .insert_header(("authorization", "Bearer s3cret"))
+            .to_request();
+        assert_eq!(call_service(&enabled, req).await.status(), 200);
+    }
+
+    #[test]
+    fn test_redirect_response() {
+        use actix_web::http::header::{CACHE_CONTROL, LOCATION};
+        use actix_web::test::TestRequest;
+
+        let request = TestRequest::get().to_http_request();
+        let found = redirect_response(&request, 302, "/orders".to_string());
+        assert_eq!(found.status().as_u16(), 302);
+        assert_eq!(found.headers().get(LOCATION).unwrap(), "/orders");
+        assert!(found.headers().get(CACHE_CONTROL).is_none());
+        let moved = redirect_response(&request, 308, "/new".to_string());
+        assert_eq!(moved.status().as_u16(), 308);
+        assert_eq!(moved.headers().get(CACHE_CONTROL).unwrap(), PERMANENT_REDIRECT_CACHE);
+        assert_eq!(redirect_response(&request, 200, "/".to_string()).status().as_u16(), 303);
+
+        let swup = TestRequest::get().insert_header(("X-Requested-With", "swup")).to_http_request();
+        let navigated = redirect_response(&swup, 301, "/new".to_string());
+        assert_eq!(navigated.status().as_u16(), 200);
+        assert_eq!(navigated.headers().get("X-Noventa-Redirect").unwrap(), "/new");
+    }
+
     #[test]
     fn test_path_to_route() {
         let base_dir = Path::new("/tmp/pages");
@@ -492,11 +883,7 @@ mod tests {
                         .collect();
 
                     // Get relative path from pages_dir
-                    let template_path_str = if route.template_path.starts_with(pages_dir) {
-                        route.template_path.strip_prefix(pages_dir).unwrap().to_str().unwrap().to_string()
-                    } else {
-                        route.template_path.to_str().unwrap().to_string()
-                    };
+                    let template_path_str = crate::paths::relative_name(&route.template_path, pages_dir);
                     Some((template_path_str, params))
                 } else {
                     None
@@ -517,11 +904,7 @@ mod tests {
                         .collect();
 
                     // Simulate what RouterActor does - strip BASE_PATH, but for test use pages_dir
-                    let template_path_str = if route.template_path.starts_with(pages_dir) {
-                        route.template_path.strip_prefix(pages_dir).unwrap().to_str().unwrap().to_string()
-                    } else {
-                        route.template_path.to_str().unwrap().to_string()
-                    };
+                    let template_path_str = crate::paths::relative_name(&route.template_path, pages_dir);
                     Some((template_path_str, params))
                 } else {
                     None
@@ -559,6 +942,7 @@ mod tests {
             .insert_header(("x-forwarded-for", "192.168.1.1, 10.0.0.1"))
             .insert_header(("referer", "https://example.com/previous"))
             .insert_header(("x-real-ip", "203.0.113.1"))
+            .peer_addr("203.0.113.9:5000".parse().unwrap())
             .to_http_request();
 
         let form_data = {
@@ -627,8 +1011,8 @@ mod tests {
         assert!(request_info.access_route.contains(&"192.168.1.1".to_string()));
         assert!(request_info.access_route.contains(&"10.0.0.1".to_string()));
 
-        // Verify remote address extraction
-        assert_eq!(request_info.remote_addr, Some("192.168.1.1".to_string()));
+        // No trusted proxies are configured, so the forwarded headers don't change the client address
+        assert_eq!(request_info.remote_addr, Some("203.0.113.9".to_string()));
     }
 
     #[test]
@@ -658,6 +1042,11 @@ mod tests {
         let route = compile_route("/posts/{post-id}".to_string(), PathBuf::from("posts/[post-id].html"));
         assert_eq!(route.param_names, vec!["post_id"]);
         assert!(route.regex.is_match("/posts/abc-123"));
+
+        // Test the root route
+        let route = compile_route("/".to_string(), PathBuf::from("index.html"));
+        assert!(route.regex.is_match("/"));
+        assert!(!route.regex.is_match("/about"));
     }
 
     #[actix_rt::test]
@@ -0,0 +1,114 @@
+use crate::config::{Config, SessionBackend, StateBackend, UploadBackendKind};
+
+// A setting that keeps state inside one process, which breaks once a load balancer spreads
+// requests over several instances.
+#[derive(Debug, PartialEq)]
+pub struct ScalingIssue {
+    pub setting: &'static str,
+    pub problem: &'static str,
+    pub fix: &'static str,
+}
+
+// `multi_instance: true` says so outright; a `cluster` section implies it.
+pub fn expects_multiple_instances(config: &Config) -> bool {
+    config.multi_instance.unwrap_or(false) || config.cluster.is_some()
+}
+
+pub fn multi_instance_issues(config: &Config) -> Vec<ScalingIssue> {
+    let mut issues = Vec::new();
+    let clustered = config.cluster.is_some();
+
+    if config.session.as_ref().is_some_and(|session| matches!(session.backend, SessionBackend::Memory)) {
+        issues.push(ScalingIssue {
+            setting: "session.backend",
+            problem: "sessions live in one instance's memory, so users are logged out whenever another instance answers",
+            fix: "Use `session.backend: redis` (or `cookie`) so every instance sees the same sessions.",
+        });
+    }
+    let state_in_memory = match config.component_state.as_ref().and_then(|state| state.backend) {
+        Some(backend) => backend == StateBackend::Memory,
+        None => !clustered,
+    };
+    if state_in_memory && config.component_state.is_some() {
+        issues.push(ScalingIssue {
+            setting: "component_state",
+            problem: "the `state` of components lives in one instance's memory, so it's lost whenever another instance answers",
+            fix: "Use `component_state.backend: redis` so every instance sees the same state.",
+        });
+    }
+    if config.resumable_uploads.unwrap_or(false) {
+        issues.push(ScalingIssue {
+            setting: "resumable_uploads",
+            problem: "the chunks of one upload land in `temp_dir` of whichever instance receives them",
+            fix: "Point `temp_dir` at a volume every instance shares, or send /_noventa/uploads/ to a single instance.",
+        });
+    }
+    let uploads_on_disk = config.upload_storage.as_ref().is_none_or(|storage| storage.backend == UploadBackendKind::Disk);
+    if uploads_on_disk && config.temp_dir.is_none() {
+        issues.push(ScalingIssue {
+            setting: "upload_storage",
+            problem: "big uploads go to this machine's temp directory, which containers often lose or can't write to",
+            fix: "Set `upload_storage.backend: s3` to stream them to a bucket every instance can read.",
+        });
+    }
+    if !clustered && config.rate_limit.is_some() {
+        issues.push(ScalingIssue {
+            setting: "rate_limit",
+            problem: "each instance counts on its own, so clients get the limit once per instance",
+            fix: "Add a `cluster` section with a `redis_url` to share the counters.",
+        });
+    }
+    if !clustered && config.admin.as_ref().is_some_and(|admin| admin.token.is_some()) {
+        issues.push(ScalingIssue {
+            setting: "admin",
+            problem: "cache clears, reloads and maintenance mode only reach the instance that got the request",
+            fix: "Add a `cluster` section with a `redis_url` so every instance follows along.",
+        });
+    }
+    if !clustered && config.reload_pages.unwrap_or(false) {
+        issues.push(ScalingIssue {
+            setting: "reload_pages",
+            problem: "a reload only refreshes the page cache of the instance that received it",
+            fix: "Add a `cluster` section with a `redis_url`, or reload every instance.",
+        });
+    }
+    issues
+}
+
+// Called by `noventa serve`; quiet unless the config says several instances will run.
+pub fn warn_at_startup(config: &Config) {
+    if !expects_multiple_instances(config) {
+        return;
+    }
+    for issue in multi_instance_issues(config) {
+        log::warn!("`{}` isn't safe with several instances: {}. {}", issue.setting, issue.problem, issue.fix);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{AdminConfig, ClusterConfig, RateLimitConfig, UploadStorageConfig};
+
+    fn settings(issues: &[ScalingIssue]) -> Vec<&str> {
+        issues.iter().map(|issue| issue.setting).collect()
+    }
+
+    #[test]
+    fn test_multi_instance_issues() {
+        let mut config: Config = serde_yaml::from_str(
+            "session:\n  backend: memory\n  secret_key: x\n  cookie_name: s\n  cookie_secure: false\n  cookie_http_only: true\n  cookie_path: /\n",
+        )
+        .unwrap();
+        config.rate_limit = Some(RateLimitConfig { requests: 10, window_secs: None });
+        config.admin = Some(AdminConfig { token: Some("0123456789abcdef0123456789abcdef".to_string()) });
+        assert!(!expects_multiple_instances(&config));
+        assert_eq!(settings(&multi_instance_issues(&config)), ["session.backend", "upload_storage", "rate_limit", "admin"]);
+
+        // Redis behind `cluster` takes care of the counters and admin actions.
+        config.cluster = Some(ClusterConfig { redis_url: "redis://127.0.0.1".to_string(), channel: None });
+        config.upload_storage = Some(UploadStorageConfig { backend: UploadBackendKind::S3, ..Default::default() });
+        assert!(expects_multiple_instances(&config));
+        assert_eq!(settings(&multi_instance_issues(&config)), ["session.backend"]);
+    }
+}
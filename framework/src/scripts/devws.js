@@ -5,16 +5,165 @@ const socketUrl = protocol + window.location.hostname + port + '/devws';
 
 let socket;
 
+// Keep the untouched console for our own messages so they aren't forwarded back to the server.
+const devConsole = {
+    log: console.log.bind(console),
+    error: console.error.bind(console),
+};
+
+function formatLogArg(arg) {
+    if (arg instanceof Error) {
+        return arg.stack || arg.message;
+    }
+    if (typeof arg === 'object' && arg !== null) {
+        try {
+            return JSON.stringify(arg);
+        } catch (e) {
+            return String(arg);
+        }
+    }
+    return String(arg);
+}
+
+function forwardToTerminal(level, args) {
+    if (!socket || socket.readyState !== WebSocket.OPEN) {
+        return;
+    }
+    try {
+        socket.send(JSON.stringify({
+            type: 'console',
+            level: level,
+            message: Array.prototype.map.call(args, formatLogArg).join(' '),
+        }));
+    } catch (e) {
+        // Never let log forwarding break the page.
+    }
+}
+
+['log', 'info', 'warn', 'error', 'debug'].forEach(function(level) {
+    const original = console[level].bind(console);
+    console[level] = function() {
+        forwardToTerminal(level, arguments);
+        original.apply(null, arguments);
+    };
+});
+
+window.addEventListener('error', function(event) {
+    forwardToTerminal('error', ['Uncaught', event.error || event.message, 'at ' + event.filename + ':' + event.lineno]);
+});
+
+window.addEventListener('unhandledrejection', function(event) {
+    forwardToTerminal('error', ['Unhandled promise rejection:', event.reason]);
+});
+
+if (window.fetch) {
+    const originalFetch = window.fetch.bind(window);
+    window.fetch = function(input, init) {
+        const method = (init && init.method) || (input && input.method) || 'GET';
+        const url = typeof input === 'string' ? input : (input && input.url) || String(input);
+        return originalFetch(input, init).then(function(response) {
+            if (!response.ok) {
+                forwardToTerminal('warn', ['Network request returned', response.status + ':', method, url]);
+            }
+            return response;
+        }, function(error) {
+            forwardToTerminal('error', ['Network request failed:', method, url, error]);
+            throw error;
+        });
+    };
+}
+
+const OVERLAY_ID = 'noventa-error-overlay';
+
+function hideErrorOverlay() {
+    const overlay = document.getElementById(OVERLAY_ID);
+    if (overlay) {
+        overlay.remove();
+    }
+}
+
+function errorDetails(error) {
+    const source = error.error_source || {};
+    if (source.Python) {
+        return {
+            kind: 'Python Error',
+            message: source.Python.message || error.message,
+            code: source.Python.source_code,
+            traceback: source.Python.traceback,
+        };
+    }
+    if (source.Template) {
+        return {
+            kind: 'Template Error',
+            message: source.Template.detail || error.message,
+            code: source.Template.source_code,
+            traceback: source.Template.traceback,
+        };
+    }
+    return { kind: 'Error', message: error.message };
+}
+
+function showErrorOverlay(error) {
+    hideErrorOverlay();
+    const details = errorDetails(error);
+
+    const overlay = document.createElement('div');
+    overlay.id = OVERLAY_ID;
+    overlay.style.cssText = 'position:fixed;inset:0;z-index:2147483647;background:rgba(0,0,0,0.85);color:#f8f8f2;font-family:ui-monospace,Menlo,monospace;font-size:14px;overflow:auto;padding:32px;box-sizing:border-box;';
+
+    const close = document.createElement('button');
+    close.textContent = 'Dismiss (Esc)';
+    close.style.cssText = 'float:right;background:#444;color:#fff;border:0;padding:6px 12px;cursor:pointer;border-radius:4px;';
+    close.onclick = hideErrorOverlay;
+    overlay.appendChild(close);
+
+    const title = document.createElement('h2');
+    title.textContent = details.kind + (error.component ? ' in component ' + error.component.name : '');
+    title.style.cssText = 'color:#ff5555;margin-top:0;';
+    overlay.appendChild(title);
+
+    const message = document.createElement('p');
+    message.textContent = details.message;
+    message.style.cssText = 'font-size:16px;white-space:pre-wrap;';
+    overlay.appendChild(message);
+
+    if (error.file_path) {
+        const location = document.createElement('p');
+        location.textContent = error.file_path + (error.line ? ':' + error.line : '');
+        location.style.cssText = 'color:#8be9fd;';
+        overlay.appendChild(location);
+    }
+
+    [details.code, details.traceback].forEach(function(text) {
+        if (text) {
+            const pre = document.createElement('pre');
+            pre.textContent = text;
+            pre.style.cssText = 'background:#282a36;padding:16px;border-radius:4px;white-space:pre-wrap;';
+            overlay.appendChild(pre);
+        }
+    });
+
+    document.body.appendChild(overlay);
+}
+
+document.addEventListener('keydown', function(event) {
+    if (event.key === 'Escape') {
+        hideErrorOverlay();
+    }
+});
+
 function connect() {
     socket = new WebSocket(socketUrl);
 
     socket.onopen = function(e) {
-        console.log(`[open] Connection established to ${socketUrl}`);
+        devConsole.log(`[open] Connection established to ${socketUrl}`);
     };
 
     socket.onmessage = function(event) {
         if (event.data === 'reload') {
-            console.log("[devws.js] Received reload message. Triggering swup navigation.");
+            // A file changed; if it's still broken the server will push a fresh error.
+            hideErrorOverlay();
+            devConsole.log("[devws.js] Received reload message. Triggering swup navigation.");
             if (window.swup) {
                 window.swup.navigate(window.location.href, {
                     cache: false,
@@ -25,20 +174,32 @@ function connect() {
             } else {
                 window.location.reload();
             }
+            return;
+        }
+
+        let payload;
+        try {
+            payload = JSON.parse(event.data);
+        } catch (e) {
+            return;
+        }
+        if (payload.type === 'error') {
+            devConsole.error('[devws.js] Render error:', payload.error.message);
+            showErrorOverlay(payload.error);
         }
     };
 
     socket.onclose = function(event) {
         if (event.wasClean) {
-            console.log(`[close] Connection closed cleanly, code=${event.code} reason=${event.reason}`);
+            devConsole.log(`[close] Connection closed cleanly, code=${event.code} reason=${event.reason}`);
         } else {
-            console.log('[close] Connection died. Attempting to reconnect in 3 seconds...');
+            devConsole.log('[close] Connection died. Attempting to reconnect in 3 seconds...');
             setTimeout(connect, 3000);
         }
     };
 
     socket.onerror = function(error) {
-        console.log(`[error] WebSocket error:`, error);
+        devConsole.log(`[error] WebSocket error:`, error);
         socket.close();
     };
 }
@@ -60,10 +60,124 @@ document.addEventListener('DOMContentLoaded', () => {
                 }
             });
 
+            // Resumable uploads report their progress over a websocket as `noventa:upload-progress`
+            // events, with {received, size, complete} in `detail`, dispatched on `target`.
+            const watchUpload = (id, target) => {
+                const scheme = window.location.protocol === 'https:' ? 'wss' : 'ws';
+                const socket = new WebSocket(`${scheme}://${window.location.host}/_noventa/uploads/${id}/events`);
+                socket.onmessage = (event) => {
+                    target.dispatchEvent(new CustomEvent('noventa:upload-progress', {
+                        bubbles: true,
+                        detail: JSON.parse(event.data),
+                    }));
+                };
+                return socket;
+            };
+            window.noventa = Object.assign(window.noventa || {}, { watchUpload });
+
+            // Files in <input type="file" data-resumable> are sent in chunks before the form, resuming
+            // after dropped connections, and the form submits the upload id in their place.
+            const uploadResumableFiles = async (form, formData) => {
+                for (const input of form.querySelectorAll('input[type="file"][data-resumable]')) {
+                    const file = input.files[0];
+                    if (!file) {
+                        continue;
+                    }
+                    const chunkSize = parseInt(input.dataset.chunkSize, 10) || 5 * 1024 * 1024;
+                    const created = await fetch('/_noventa/uploads', {
+                        method: 'POST',
+                        headers: { 'Content-Type': 'application/json' },
+                        body: JSON.stringify({
+                            filename: file.name,
+                            content_type: file.type || 'application/octet-stream',
+                            size: file.size,
+                        }),
+                    });
+                    if (!created.ok) {
+                        throw new Error((await created.json()).error || created.statusText);
+                    }
+                    const { id } = await created.json();
+                    watchUpload(id, input);
+
+                    let offset = 0;
+                    let retries = 0;
+                    while (offset < file.size) {
+                        let response;
+                        try {
+                            response = await fetch(`/_noventa/uploads/${id}`, {
+                                method: 'PATCH',
+                                headers: { 'Upload-Offset': String(offset) },
+                                body: file.slice(offset, offset + chunkSize),
+                            });
+                        } catch (e) {
+                            if (++retries > 5) {
+                                throw e;
+                            }
+                            await new Promise(resolve => setTimeout(resolve, 1000 * retries));
+                            const status = await fetch(`/_noventa/uploads/${id}`).catch(() => null);
+                            if (status && status.ok) {
+                                offset = (await status.json()).received;
+                            }
+                            continue;
+                        }
+                        // 409 means part of the chunk already arrived; the header says where to carry on.
+                        if (!response.ok && response.status !== 409) {
+                            throw new Error((await response.json()).error || response.statusText);
+                        }
+                        offset = parseInt(response.headers.get('Upload-Offset'), 10);
+                        retries = 0;
+                    }
+                    formData.set(input.name, id);
+                }
+            };
+
+            // Swaps what's between a component's <!--noventa-component:...--> markers for `html`, which
+            // comes with markers of its own. False when the component isn't on the page.
+            const replaceComponent = (key, html) => {
+                const walker = document.createTreeWalker(document.body, NodeFilter.SHOW_COMMENT);
+                let start = null;
+                while (walker.nextNode()) {
+                    if (walker.currentNode.data === `noventa-component:${key}`) {
+                        start = walker.currentNode;
+                        break;
+                    }
+                }
+                if (!start) {
+                    return false;
+                }
+                // Components inside it have markers too; the end is the one that closes this one.
+                let depth = 0;
+                let end = start.nextSibling;
+                while (end) {
+                    if (end.nodeType === Node.COMMENT_NODE && end.data.startsWith('noventa-component:')) {
+                        depth++;
+                    } else if (end.nodeType === Node.COMMENT_NODE && end.data === '/noventa-component') {
+                        if (depth === 0) {
+                            break;
+                        }
+                        depth--;
+                    }
+                    end = end.nextSibling;
+                }
+                if (!end) {
+                    return false;
+                }
+                const range = document.createRange();
+                range.setStartBefore(start);
+                range.setEndAfter(end);
+                range.deleteContents();
+                range.insertNode(range.createContextualFragment(html));
+                return true;
+            };
+
             document.addEventListener('click', event => {
                 const button = event.target.closest('button[type="submit"], input[type="submit"]');
                 if (button) {
                     const form = button.form || button.closest('form');
+                    // Downloads are left to the browser, which saves them while they arrive.
+                    if (form && form.hasAttribute('data-download')) {
+                        return;
+                    }
                     if (form) {
                         event.preventDefault();
                         const formData = new FormData(form);
@@ -75,17 +189,45 @@ document.addEventListener('DOMContentLoaded', () => {
                             swup.navigate(`${url}?${params.toString()}`);
                         } else {
                             swup.isPost = true;
-                            fetch(url, {
+                            const headers = { 'X-Requested-With': 'swup' };
+                            // Posting to a component on this page: only that component needs to come back.
+                            const component = `${formData.get('component_id')}:${formData.get('component_instance')}`;
+                            if (!form.getAttribute('action') && formData.has('component_instance')) {
+                                headers['X-Noventa-Partial'] = component;
+                            }
+                            uploadResumableFiles(form, formData).then(() => fetch(url, {
                                 method: 'POST',
                                 body: formData,
-                                headers: {
-                                    'X-Requested-With': 'swup',
+                                headers: headers,
+                            })).then(async response => {
+                                if (handleRedirect(response)) {
+                                    return;
+                                }
+                                const disposition = response.headers.get('Content-Disposition') || '';
+                                if (/^attachment/i.test(disposition)) {
+                                    // A download from a form without data-download, saved once it's all here.
+                                    swup.isPost = false;
+                                    const filename = /filename="?([^";]+)"?/i.exec(disposition);
+                                    const link = document.createElement('a');
+                                    link.href = URL.createObjectURL(await response.blob());
+                                    link.download = filename ? filename[1] : '';
+                                    link.click();
+                                    setTimeout(() => URL.revokeObjectURL(link.href), 1000);
+                                    return;
                                 }
-                            }).then(response => {
-                                if (!handleRedirect(response)) {
-                                    return response.text();
+                                if (response.headers.get('X-Noventa-Partial') === 'component') {
+                                    const html = await response.text();
+                                    swup.isPost = false;
+                                    if (!replaceComponent(component, html)) {
+                                        swup.navigate(window.location.href, { cache: false });
+                                    }
+                                    return;
                                 }
+                                return response.text();
                             }).then(html => {
+                                if (html === undefined) {
+                                    return;
+                                }
                                 swup.cache.set(window.location.href, { 
                                     url: window.location.href, 
                                     html: html 
@@ -93,6 +235,10 @@ document.addEventListener('DOMContentLoaded', () => {
 
                                 // Now navigate to it — swup will use the cached version
                                 swup.navigate(window.location.href);
+                            }).catch(e => {
+                                swup.isPost = false;
+                                console.error('The form could not be sent:', e);
+                                form.dispatchEvent(new CustomEvent('noventa:submit-error', { bubbles: true, detail: e }));
                             });
                         }
                     } else {
@@ -60,12 +60,95 @@ def deep_convert(data):
 
 import sys
 
+from inspect import Parameter, signature
+
+# `def load_page_context(request)` only asks for the request: functions get as many of
+# (request, session, db) as they take.
+def positional_args(user_func, args, kwargs):
+    try:
+        params = signature(user_func).parameters.values()
+    except (TypeError, ValueError):
+        return args
+    if any(p.kind == Parameter.VAR_POSITIONAL for p in params):
+        return args
+    positional = [p for p in params if p.kind in (Parameter.POSITIONAL_ONLY, Parameter.POSITIONAL_OR_KEYWORD) and p.name not in kwargs]
+    return args[:len(positional)]
+
 def call_user_function(user_func, *args, **kwargs):
     try:
-        result = user_func(*args, **kwargs)
+        result = user_func(*positional_args(user_func, args, kwargs), **kwargs)
         return deep_convert(result)
     except Exception as e:
         exc_type, exc_value, exc_tb = sys.exc_info()
         # Re-raise with original traceback preserved
         raise e.with_traceback(exc_tb)
-"#;
\ No newline at end of file
+
+# The functions of the project's context_processors.py, in the order they're defined. What they return
+# is merged, later ones winning, and available in every page and component of the request.
+def run_context_processors(module, *args):
+    from inspect import isfunction
+    context = {}
+    for name, func in vars(module).items():
+        if name.startswith("_") or not isfunction(func) or func.__module__ != module.__name__:
+            continue
+        result = call_user_function(func, *args)
+        if result is None:
+            continue
+        if not isinstance(result, dict):
+            raise TypeError(f"context processor {name}() must return a dict, not {type(result).__name__}")
+        context.update(result)
+    return context
+
+# Components opt into server-side state by taking a `state` argument.
+def takes_state(user_func):
+    try:
+        return "state" in signature(user_func).parameters
+    except (TypeError, ValueError):
+        return False
+
+# `action_add_todo` is checked against `AddTodoForm` in the component's _models.py.
+def form_model(models, action_name):
+    words = action_name.removeprefix("action_").split("_")
+    return getattr(models, "".join(word.capitalize() for word in words) + "Form", None)
+
+# Runs the action with the validated form as `form`. An invalid form doesn't run it; the component is
+# rendered again with `form.errors` (messages by field, `__all__` for the whole form) and `form.values`.
+def call_action(user_func, models, *args, **kwargs):
+    model = form_model(models, user_func.__name__)
+    if model is None:
+        return call_user_function(user_func, *args, **kwargs)
+    from pydantic import ValidationError
+    try:
+        form = model.model_validate(kwargs)
+    except ValidationError as e:
+        errors = {}
+        for error in e.errors():
+            field = ".".join(str(part) for part in error["loc"]) or "__all__"
+            errors.setdefault(field, []).append(error["msg"])
+        values = {name: value for name, value in kwargs.items() if name in model.model_fields}
+        return {"form": {"valid": False, "errors": errors, "values": values}}
+    result = call_user_function(user_func, *args, **{**kwargs, "form": form})
+    if isinstance(result, dict):
+        result.setdefault("form", {"valid": True, "errors": {}, "values": {}})
+    return result
+"#;
+pub const TEMPLATE_HELPERS_PY: &str = r#"
+# `from noventa_templates import template_filter, template_global` in the project's template_helpers.py.
+# The decorators only tag the function; Noventa finds the tagged ones and registers them with the templates.
+
+def _tag(kind, func, name, pure):
+    func._noventa_template_helper = (kind, name or func.__name__, pure)
+    return func
+
+def template_filter(func=None, *, name=None, pure=False):
+    """{{ value|name(args) }}. `pure=True` filters always give the same result for the same arguments, so it's cached."""
+    if func is not None:
+        return _tag("filter", func, name, pure)
+    return lambda func: _tag("filter", func, name, pure)
+
+def template_global(func=None, *, name=None):
+    """{{ name(args) }}, available in every template."""
+    if func is not None:
+        return _tag("global", func, name, False)
+    return lambda func: _tag("global", func, name, False)
+"#;
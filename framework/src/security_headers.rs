@@ -0,0 +1,158 @@
+use crate::config::{self, SecurityHeadersConfig};
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{HeaderMap, HeaderName, HeaderValue};
+use actix_web::middleware::Next;
+use actix_web::HttpMessage;
+use rand::RngCore;
+
+// Replaced with the request's nonce in `security_headers.content_security_policy`.
+pub const NONCE_PLACEHOLDER: &str = "{nonce}";
+
+const DEFAULT_REFERRER_POLICY: &str = "strict-origin-when-cross-origin";
+const DEFAULT_FRAME_ANCESTORS: &str = "'self'";
+
+// Stored in the request extensions so the renderer can tag the scripts it injects.
+#[derive(Clone, Debug)]
+pub struct CspNonce(pub String);
+
+pub fn generate_nonce() -> String {
+    let mut bytes = [0u8; 16];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// Every header the config asks for. An empty string (or `false`) turns a default header off.
+pub fn headers_for(config: &SecurityHeadersConfig, nonce: &str) -> Vec<(&'static str, String)> {
+    let mut headers = Vec::new();
+
+    if let Some(max_age) = config.hsts_max_age {
+        let mut value = format!("max-age={}", max_age);
+        if config.hsts_include_subdomains.unwrap_or(false) {
+            value.push_str("; includeSubDomains");
+        }
+        headers.push(("strict-transport-security", value));
+    }
+    if config.content_type_options.unwrap_or(true) {
+        headers.push(("x-content-type-options", "nosniff".to_string()));
+    }
+    let referrer_policy = config.referrer_policy.as_deref().unwrap_or(DEFAULT_REFERRER_POLICY);
+    if !referrer_policy.is_empty() {
+        headers.push(("referrer-policy", referrer_policy.to_string()));
+    }
+
+    let mut policy: Vec<String> = config
+        .content_security_policy
+        .as_deref()
+        .unwrap_or_default()
+        .split(';')
+        .map(|directive| directive.trim().replace(NONCE_PLACEHOLDER, nonce))
+        .filter(|directive| !directive.is_empty())
+        .collect();
+    let frame_ancestors = config.frame_ancestors.as_deref().unwrap_or(DEFAULT_FRAME_ANCESTORS);
+    if !frame_ancestors.is_empty() && !policy.iter().any(|directive| directive.starts_with("frame-ancestors")) {
+        policy.push(format!("frame-ancestors {}", frame_ancestors));
+    }
+    if !policy.is_empty() {
+        headers.push(("content-security-policy", policy.join("; ")));
+    }
+
+    headers
+}
+
+// Headers the page already set win, so a single response can still opt out.
+fn apply(headers: &mut HeaderMap, values: Vec<(&'static str, String)>) {
+    for (name, value) in values {
+        let name = HeaderName::from_static(name);
+        if headers.contains_key(&name) {
+            continue;
+        }
+        match HeaderValue::from_str(&value) {
+            Ok(value) => {
+                headers.insert(name, value);
+            }
+            Err(_) => log::warn!("Skipping the {} header because '{}' isn't a valid header value.", name, value),
+        }
+    }
+}
+
+pub async fn security_headers(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let nonce = generate_nonce();
+    req.extensions_mut().insert(CspNonce(nonce.clone()));
+
+    let mut res = next.call(req).await?;
+    let config = config::CONFIG.security_headers.clone().unwrap_or_default();
+    apply(res.headers_mut(), headers_for(&config, &nonce));
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{test as actix_test, web, App, HttpRequest, HttpResponse};
+
+    #[test]
+    fn test_default_headers() {
+        let headers = headers_for(&SecurityHeadersConfig::default(), "abc");
+        assert_eq!(
+            headers,
+            vec![
+                ("x-content-type-options", "nosniff".to_string()),
+                ("referrer-policy", "strict-origin-when-cross-origin".to_string()),
+                ("content-security-policy", "frame-ancestors 'self'".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_configured_headers() {
+        let config = SecurityHeadersConfig {
+            hsts_max_age: Some(31536000),
+            hsts_include_subdomains: Some(true),
+            content_type_options: Some(false),
+            referrer_policy: Some(String::new()),
+            frame_ancestors: Some("'none'".to_string()),
+            content_security_policy: Some("default-src 'self'; script-src 'self' 'nonce-{nonce}';".to_string()),
+        };
+        let headers = headers_for(&config, "abc");
+        assert_eq!(
+            headers,
+            vec![
+                ("strict-transport-security", "max-age=31536000; includeSubDomains".to_string()),
+                (
+                    "content-security-policy",
+                    "default-src 'self'; script-src 'self' 'nonce-abc'; frame-ancestors 'none'".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_generate_nonce() {
+        let nonce = generate_nonce();
+        assert_eq!(nonce.len(), 32);
+        assert_ne!(nonce, generate_nonce());
+    }
+
+    #[actix_rt::test]
+    async fn test_middleware_sets_nonce_and_headers() {
+        async fn page(req: HttpRequest) -> HttpResponse {
+            let nonce = req.extensions().get::<CspNonce>().map(|n| n.0.clone()).unwrap_or_default();
+            HttpResponse::Ok().body(nonce)
+        }
+        let app = actix_test::init_service(
+            App::new()
+                .wrap(actix_web::middleware::from_fn(security_headers))
+                .route("/", web::get().to(page)),
+        )
+        .await;
+
+        let res = actix_test::call_service(&app, actix_test::TestRequest::get().uri("/").to_request()).await;
+        assert_eq!(res.headers().get("x-content-type-options").unwrap(), "nosniff");
+        let body = actix_test::read_body(res).await;
+        assert_eq!(body.len(), 32);
+    }
+}
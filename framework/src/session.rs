@@ -2,10 +2,66 @@ use actix_session::storage::{
     CookieSessionStore, LoadError, RedisSessionStore, SaveError, SessionKey, SessionStore,
     UpdateError,
 };
+use crate::config::{RedisFallback, SessionScope};
+use actix_web::body::{EitherBody, MessageBody};
 use actix_web::cookie::time::Duration;
+use actix_web::cookie::{Cookie, CookieJar, Key, SameSite};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{self, HeaderValue};
+use actix_web::HttpMessage;
+use actix_session::SessionExt;
+use actix_web::middleware::Next;
+use actix_web::HttpResponse;
+use deadpool_redis::{redis, Pool, Runtime};
+use once_cell::sync::OnceCell;
+use pyo3::prelude::*;
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
 
+// How often a reachable Redis is checked, and the longest wait between tries while it's down.
+const REDIS_CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(5);
+const REDIS_MAX_BACKOFF: std::time::Duration = std::time::Duration::from_secs(30);
+
+// Put in a session found in the old backend, so `reissue_sessions` knows to move it.
+const MIGRATED_MARKER: &str = "_noventa_migrated";
+// Shorter ids from `session.id_generator` are too easy to guess.
+const MIN_SESSION_ID_LENGTH: usize = 32;
+
+fn id_generator() -> Option<String> {
+    crate::config::CONFIG.session.as_ref().and_then(|session| session.id_generator.clone())
+}
+
+fn checked_session_id(id: String) -> Result<SessionKey, String> {
+    if id.len() < MIN_SESSION_ID_LENGTH {
+        return Err(format!("it returned an id shorter than {} characters", MIN_SESSION_ID_LENGTH));
+    }
+    if !id.bytes().all(|b| b.is_ascii_graphic() && b != b';' && b != b',') {
+        return Err("it returned an id that can't go in a cookie".to_string());
+    }
+    SessionKey::try_from(id).map_err(|e| e.to_string())
+}
+
+// The id of a new session: from `session.id_generator` when there is one, random otherwise. A
+// generator that fails is logged and the session gets a random id, so nobody is kept out.
+async fn new_session_key() -> SessionKey {
+    let Some(generator) = id_generator() else {
+        return actix_session::storage::generate_session_key();
+    };
+    let generated = actix_web::web::block(move || {
+        let (module, function) = generator.rsplit_once('.').unwrap_or_default();
+        Python::attach(|py| py.import(module)?.getattr(function)?.call0()?.extract::<String>()).map_err(|e| e.to_string())
+    })
+    .await;
+    match generated.map_err(|e| e.to_string()).and_then(|id| id).and_then(checked_session_id) {
+        Ok(session_key) => session_key,
+        Err(e) => {
+            log::error!("Oh no! `session.id_generator` failed, so the session got a random id: {}", e);
+            actix_session::storage::generate_session_key()
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct InMemoryBackend {
     sessions: Arc<Mutex<HashMap<String, HashMap<String, String>>>>,
@@ -31,7 +87,7 @@ impl SessionStore for InMemoryBackend {
         session_state: HashMap<String, String>,
         _ttl: &Duration,
     ) -> Result<SessionKey, SaveError> {
-        let session_key = actix_session::storage::generate_session_key();
+        let session_key = new_session_key().await;
         let key = session_key.as_ref().to_string();
         let mut sessions = self.sessions.lock().unwrap();
         sessions.insert(key, session_state);
@@ -63,12 +119,501 @@ impl SessionStore for InMemoryBackend {
     }
 }
 
+// A pool with timeouts, so an unreachable Redis fails a request quickly instead of hanging it.
+pub fn redis_pool(redis_url: &str, size: usize) -> Result<Pool, String> {
+    let timeout = Some(std::time::Duration::from_secs(2));
+    let mut redis_cfg = deadpool_redis::Config::from_url(redis_url);
+    redis_cfg.pool = Some(deadpool_redis::PoolConfig {
+        max_size: size,
+        timeouts: deadpool_redis::Timeouts { wait: timeout, create: timeout, recycle: timeout },
+        ..Default::default()
+    });
+    redis_cfg.create_pool(Some(Runtime::Tokio1)).map_err(|e| e.to_string())
+}
+
+// Whether the Redis session backend is reachable, shared by the store, the request guard and /health/ready.
+pub struct RedisHealth {
+    up: AtomicBool,
+    fallback: RedisFallback,
+}
+
+impl RedisHealth {
+    pub fn new(fallback: RedisFallback) -> Arc<Self> {
+        Arc::new(RedisHealth { up: AtomicBool::new(true), fallback })
+    }
+
+    pub fn is_up(&self) -> bool {
+        self.up.load(Ordering::SeqCst)
+    }
+
+    // Logs only when the state changes, so an outage isn't reported on every request.
+    fn mark_down(&self, reason: &str) {
+        if self.up.swap(false, Ordering::SeqCst) {
+            let consequence = match self.fallback {
+                RedisFallback::Error => "pages answer with \"try again soon\"",
+                RedisFallback::ReadOnly => "pages get empty sessions and changes to them aren't kept",
+            };
+            log::error!("Oh no! Lost the connection to Redis for sessions: {}. Until it's back, {}.", reason, consequence);
+        }
+    }
+
+    fn mark_up(&self) {
+        if !self.up.swap(true, Ordering::SeqCst) {
+            log::info!("✨ Redis is back; sessions are stored again.");
+        }
+    }
+}
+
+// The Redis health of this server, once its sessions are stored in Redis.
+static REDIS_HEALTH: OnceCell<Arc<RedisHealth>> = OnceCell::new();
+
+async fn ping(pool: &Pool) -> Result<(), String> {
+    let mut connection = pool.get().await.map_err(|e| e.to_string())?;
+    redis::cmd("PING")
+        .query_async::<String>(&mut connection)
+        .await
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+// Tries Redis up to `attempts` times, waiting longer each time. Returns whether it answered.
+pub async fn wait_for_redis(pool: &Pool, health: &RedisHealth, attempts: u32) -> bool {
+    let mut delay = std::time::Duration::from_millis(500);
+    for attempt in 1..=attempts {
+        match ping(pool).await {
+            Ok(()) => {
+                health.mark_up();
+                return true;
+            }
+            Err(e) if attempt < attempts => {
+                log::warn!("Redis isn't answering yet ({}), trying again in {:?} ({} of {}).", e, delay, attempt, attempts);
+                actix_rt::time::sleep(delay).await;
+                delay = (delay * 2).min(REDIS_MAX_BACKOFF);
+            }
+            Err(e) => health.mark_down(&e),
+        }
+    }
+    false
+}
+
+// Keeps checking Redis in the background so sessions come back on their own after an outage.
+pub fn watch_redis(pool: Pool, health: Arc<RedisHealth>) {
+    let _ = REDIS_HEALTH.set(health.clone());
+    actix_rt::spawn(async move {
+        let mut delay = REDIS_CHECK_INTERVAL;
+        loop {
+            actix_rt::time::sleep(delay).await;
+            match ping(&pool).await {
+                Ok(()) => {
+                    health.mark_up();
+                    delay = REDIS_CHECK_INTERVAL;
+                }
+                Err(e) => {
+                    health.mark_down(&e);
+                    delay = (delay * 2).min(REDIS_MAX_BACKOFF);
+                }
+            }
+        }
+    });
+}
+
+// "ok", or how sessions are getting by without Redis. Not ready means requests get the error page.
+pub fn readiness() -> (bool, &'static str) {
+    match REDIS_HEALTH.get() {
+        Some(health) if !health.is_up() => match health.fallback {
+            RedisFallback::Error => (false, "unavailable"),
+            RedisFallback::ReadOnly => (true, "read-only"),
+        },
+        _ => (true, "ok"),
+    }
+}
+
+// With `redis_fallback: error`, answers 503 while Redis is down instead of failing somewhere in the page.
+// Health checks and the admin dashboard still get through.
+pub async fn sessions_unavailable(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, actix_web::Error> {
+    let (ready, _) = readiness();
+    if ready || req.path().starts_with("/health/") || req.path().starts_with("/_noventa/") {
+        return next.call(req).await.map(ServiceResponse::map_into_left_body);
+    }
+    let response = HttpResponse::ServiceUnavailable()
+        .insert_header(("Retry-After", REDIS_CHECK_INTERVAL.as_secs().to_string()))
+        .content_type("text/html")
+        .body("<!DOCTYPE html><title>Back soon</title><h1>We'll be right back</h1><p>This site is having a hiccup. Please try again in a few seconds.</p>");
+    Ok(req.into_response(response).map_into_right_body())
+}
+
+// Redis-backed sessions that keep pages working while Redis is unreachable. Loads come back
+// empty, so the session middleware never fails the request; what happens next is up to the fallback.
+#[derive(Clone)]
+pub struct ResilientRedisStore {
+    store: RedisSessionStore,
+    // The store's own pool, for sessions whose id comes from `session.id_generator`.
+    pool: Pool,
+    health: Arc<RedisHealth>,
+}
+
+impl ResilientRedisStore {
+    pub fn new(store: RedisSessionStore, pool: Pool, health: Arc<RedisHealth>) -> Self {
+        ResilientRedisStore { store, pool, health }
+    }
+
+    fn read_only(&self) -> bool {
+        self.health.fallback == RedisFallback::ReadOnly
+    }
+
+    // Saves a new session under `session_key`, like the store's own `save` does under a random one.
+    // False when the id is taken.
+    async fn save_as(&self, session_key: &SessionKey, session_state: &HashMap<String, String>, ttl: &Duration) -> Result<bool, anyhow::Error> {
+        let body = serde_json::to_string(session_state)?;
+        let mut connection = self.pool.get().await?;
+        let set: Option<String> = redis::cmd("SET")
+            .arg(session_key.as_ref())
+            .arg(body)
+            .arg("NX")
+            .arg("EX")
+            .arg(ttl.whole_seconds())
+            .query_async(&mut connection)
+            .await?;
+        Ok(set.is_some())
+    }
+}
+
+impl SessionStore for ResilientRedisStore {
+    async fn load(&self, session_key: &SessionKey) -> Result<Option<HashMap<String, String>>, LoadError> {
+        if !self.health.is_up() {
+            return Ok(None);
+        }
+        match self.store.load(session_key).await {
+            Err(LoadError::Other(e)) => {
+                self.health.mark_down(&e.to_string());
+                Ok(None)
+            }
+            result => result,
+        }
+    }
+
+    async fn save(&self, session_state: HashMap<String, String>, ttl: &Duration) -> Result<SessionKey, SaveError> {
+        if self.health.is_up() && id_generator().is_some() {
+            let session_key = new_session_key().await;
+            match self.save_as(&session_key, &session_state, ttl).await {
+                Ok(true) => return Ok(session_key),
+                // Taken: the store picks a random one instead.
+                Ok(false) => log::warn!("Heads up! `session.id_generator` returned an id that's already in use."),
+                Err(e) => self.health.mark_down(&e.to_string()),
+            }
+        }
+        if self.health.is_up() {
+            match self.store.save(session_state, ttl).await {
+                Err(SaveError::Other(e)) => self.health.mark_down(&e.to_string()),
+                result => return result,
+            }
+        }
+        if self.read_only() {
+            // The cookie gets a key that loads nothing, so the next request starts over.
+            Ok(new_session_key().await)
+        } else {
+            Err(SaveError::Other(anyhow::anyhow!("Redis is unreachable")))
+        }
+    }
+
+    async fn update(
+        &self,
+        mut session_key: SessionKey,
+        session_state: HashMap<String, String>,
+        ttl: &Duration,
+    ) -> Result<SessionKey, UpdateError> {
+        if self.health.is_up() {
+            let key = session_key.as_ref().to_string();
+            match self.store.update(session_key, session_state, ttl).await {
+                Err(UpdateError::Other(e)) => self.health.mark_down(&e.to_string()),
+                result => return result,
+            }
+            session_key = SessionKey::try_from(key).map_err(|e| UpdateError::Other(e.into()))?;
+        }
+        if self.read_only() {
+            Ok(session_key)
+        } else {
+            Err(UpdateError::Other(anyhow::anyhow!("Redis is unreachable")))
+        }
+    }
+
+    async fn update_ttl(&self, session_key: &SessionKey, ttl: &Duration) -> Result<(), anyhow::Error> {
+        if !self.health.is_up() {
+            return Ok(());
+        }
+        self.store.update_ttl(session_key, ttl).await.or_else(|e| {
+            self.health.mark_down(&e.to_string());
+            Ok(())
+        })
+    }
+
+    async fn delete(&self, session_key: &SessionKey) -> Result<(), anyhow::Error> {
+        if !self.health.is_up() {
+            return Ok(());
+        }
+        self.store.delete(session_key).await.or_else(|e| {
+            self.health.mark_down(&e.to_string());
+            Ok(())
+        })
+    }
+}
+
+// Where sessions were kept before `session.migrate_from` switched backends.
+#[derive(Clone)]
+pub enum PreviousStore {
+    // The session key is the session itself, already decrypted by the middleware.
+    Cookie,
+    Redis(RedisSessionStore),
+}
+
+impl PreviousStore {
+    async fn load(&self, session_key: &SessionKey) -> Option<HashMap<String, String>> {
+        match self {
+            PreviousStore::Cookie => serde_json::from_str(session_key.as_ref()).ok(),
+            PreviousStore::Redis(store) => store.load(session_key).await.ok().flatten(),
+        }
+    }
+}
+
+// Reads sessions from the new backend, falling back to the old one for sessions that haven't
+// moved yet. Those are marked, and `reissue_sessions` saves them to the new backend under a new id.
+#[derive(Clone)]
+pub struct MigratingStore {
+    current: Box<RuntimeSessionStore>,
+    previous: PreviousStore,
+}
+
+impl MigratingStore {
+    pub fn new(current: RuntimeSessionStore, previous: PreviousStore) -> Self {
+        MigratingStore { current: Box::new(current), previous }
+    }
+}
+
+impl SessionStore for MigratingStore {
+    async fn load(&self, session_key: &SessionKey) -> Result<Option<HashMap<String, String>>, LoadError> {
+        match self.current.load(session_key).await {
+            Ok(Some(state)) => return Ok(Some(state)),
+            // A cookie store reading a key that isn't a cookie session fails to deserialize it.
+            Ok(None) | Err(LoadError::Deserialization(_)) => {}
+            Err(e) => return Err(e),
+        }
+        Ok(self.previous.load(session_key).await.map(|mut state| {
+            state.insert(MIGRATED_MARKER.to_string(), "true".to_string());
+            state
+        }))
+    }
+
+    async fn save(&self, session_state: HashMap<String, String>, ttl: &Duration) -> Result<SessionKey, SaveError> {
+        self.current.save(session_state, ttl).await
+    }
+
+    async fn update(
+        &self,
+        session_key: SessionKey,
+        session_state: HashMap<String, String>,
+        ttl: &Duration,
+    ) -> Result<SessionKey, UpdateError> {
+        self.current.update(session_key, session_state, ttl).await
+    }
+
+    async fn update_ttl(&self, session_key: &SessionKey, ttl: &Duration) -> Result<(), anyhow::Error> {
+        self.current.update_ttl(session_key, ttl).await
+    }
+
+    async fn delete(&self, session_key: &SessionKey) -> Result<(), anyhow::Error> {
+        self.current.delete(session_key).await
+    }
+}
+
+// The key cookies are signed with, and the ones it replaced (`session.old_secret_keys`).
+pub struct SecretKeys {
+    cookie_name: String,
+    current: Key,
+    old: Vec<Key>,
+}
+
+static SECRET_KEYS: OnceCell<SecretKeys> = OnceCell::new();
+
+// Lets `accept_old_secret_keys` take cookies signed with `old`. Nothing to do without old keys.
+pub fn set_secret_keys(cookie_name: String, current: Key, old: Vec<Key>) {
+    if !old.is_empty() {
+        let _ = SECRET_KEYS.set(SecretKeys { cookie_name, current, old });
+    }
+}
+
+// Put on requests whose session cookie was signed with an old key, so `reissue_sessions` re-signs it.
+struct SignedWithOldKey;
+
+impl SecretKeys {
+    // The session cookie's value encrypted with the current key, if it was encrypted with an old one.
+    fn re_sign(&self, cookie: &Cookie<'static>) -> Option<String> {
+        let mut jar = CookieJar::new();
+        if jar.private(&self.current).decrypt(cookie.clone()).is_some() {
+            return None;
+        }
+        let value = self.old.iter().find_map(|key| jar.private(key).decrypt(cookie.clone()))?.value().to_string();
+        jar.private_mut(&self.current).add(Cookie::new(self.cookie_name.clone(), value));
+        jar.get(&self.cookie_name).map(|cookie| cookie.value().to_string())
+    }
+
+    // The Cookie header with the session cookie re-signed, or None when it doesn't need to be.
+    fn rewrite_header(&self, header: &str) -> Option<String> {
+        let mut re_signed = false;
+        let cookies: Vec<String> = header
+            .split(';')
+            .map(str::trim)
+            .filter(|part| !part.is_empty())
+            .map(|part| match Cookie::parse_encoded(part.to_string()) {
+                Ok(cookie) if cookie.name() == self.cookie_name => match self.re_sign(&cookie) {
+                    Some(value) => {
+                        re_signed = true;
+                        Cookie::new(self.cookie_name.clone(), value).encoded().to_string()
+                    }
+                    None => part.to_string(),
+                },
+                _ => part.to_string(),
+            })
+            .collect();
+        re_signed.then(|| cookies.join("; "))
+    }
+}
+
+// Runs before the session middleware, so rotating `secret_key` doesn't log everyone out: a cookie
+// signed with one of `old_secret_keys` is handed to it re-signed with the new key.
+pub async fn accept_old_secret_keys(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    if let Some(keys) = SECRET_KEYS.get() {
+        let header = req
+            .headers()
+            .get_all(header::COOKIE)
+            .filter_map(|value| value.to_str().ok())
+            .collect::<Vec<_>>()
+            .join("; ");
+        if let Some(rewritten) = keys.rewrite_header(&header)
+            && let Ok(value) = HeaderValue::from_str(&rewritten)
+        {
+            req.headers_mut().insert(header::COOKIE, value);
+            req.extensions_mut().insert(SignedWithOldKey);
+        }
+    }
+    next.call(req).await
+}
+
+// The `session.scopes` entry covering `path`; the longest prefix wins.
+fn scope_for<'a>(scopes: &'a [SessionScope], path: &str) -> Option<&'a SessionScope> {
+    scopes
+        .iter()
+        .filter(|scope| {
+            let prefix = scope.path_prefix.trim_end_matches('/');
+            path == prefix || path.strip_prefix(prefix).is_some_and(|rest| rest.starts_with('/'))
+        })
+        .max_by_key(|scope| scope.path_prefix.trim_end_matches('/').len())
+}
+
+// The Cookie header as the session middleware should see it inside a scope: the scope's cookie
+// under the main cookie's name, and the main cookie itself left out.
+fn scoped_cookie_header(header: &str, cookie_name: &str, scope: &SessionScope) -> String {
+    header
+        .split(';')
+        .map(str::trim)
+        .filter(|part| !part.is_empty())
+        .filter_map(|part| match Cookie::parse_encoded(part.to_string()) {
+            Ok(cookie) if cookie.name() == cookie_name => None,
+            Ok(cookie) if cookie.name() == scope.cookie_name => Some(Cookie::new(cookie_name.to_string(), cookie.value().to_string()).encoded().to_string()),
+            _ => Some(part.to_string()),
+        })
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+// The session middleware's Set-Cookie, moved to the scope's cookie with the scope's attributes.
+fn scoped_set_cookie(header: &str, cookie_name: &str, scope: &SessionScope) -> Option<String> {
+    let mut cookie = Cookie::parse_encoded(header.to_string()).ok()?;
+    if cookie.name() != cookie_name {
+        return None;
+    }
+    cookie.set_name(scope.cookie_name.clone());
+    cookie.set_path(scope.cookie_path.clone().unwrap_or_else(|| scope.path_prefix.trim_end_matches('/').to_string()));
+    if let Some(secure) = scope.cookie_secure {
+        cookie.set_secure(secure);
+    }
+    if let Some(http_only) = scope.cookie_http_only {
+        cookie.set_http_only(http_only);
+    }
+    match scope.cookie_same_site.as_deref().map(str::to_lowercase).as_deref() {
+        Some("strict") => cookie.set_same_site(SameSite::Strict),
+        Some("lax") => cookie.set_same_site(SameSite::Lax),
+        Some("none") => cookie.set_same_site(SameSite::None),
+        _ => {}
+    }
+    // Removal cookies keep expiring right away.
+    if let Some(max_age) = scope.cookie_max_age
+        && cookie.max_age() != Some(Duration::ZERO)
+    {
+        cookie.set_max_age(Duration::seconds(max_age));
+    }
+    Some(cookie.encoded().to_string())
+}
+
+// Runs outside the session middleware. Under a `session.scopes` prefix the scope's cookie is handed
+// to it as the main session cookie, and what it sets goes back into the scope's cookie, so the
+// two sessions never see each other.
+pub async fn scoped_sessions(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let Some(session) = crate::config::CONFIG.session.as_ref() else {
+        return next.call(req).await;
+    };
+    let Some(scope) = scope_for(session.scopes.as_deref().unwrap_or_default(), req.path()) else {
+        return next.call(req).await;
+    };
+    let header = req.headers().get_all(header::COOKIE).filter_map(|value| value.to_str().ok()).collect::<Vec<_>>().join("; ");
+    match HeaderValue::from_str(&scoped_cookie_header(&header, &session.cookie_name, scope)) {
+        Ok(value) => {
+            req.headers_mut().insert(header::COOKIE, value);
+        }
+        Err(_) => {
+            req.headers_mut().remove(header::COOKIE);
+        }
+    }
+
+    let mut res = next.call(req).await?;
+    let set_cookies: Vec<HeaderValue> = res.headers().get_all(header::SET_COOKIE).cloned().collect();
+    res.headers_mut().remove(header::SET_COOKIE);
+    for value in set_cookies {
+        let scoped = value.to_str().ok().and_then(|header| scoped_set_cookie(header, &session.cookie_name, scope));
+        let value = scoped.and_then(|scoped| HeaderValue::from_str(&scoped).ok()).unwrap_or(value);
+        res.headers_mut().append(header::SET_COOKIE, value);
+    }
+    Ok(res)
+}
+
+// Renewing a session makes the middleware save it (to the new backend, for a migrated one),
+// signed with the current key, and send the browser the new cookie.
+pub async fn reissue_sessions(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let session = req.get_session();
+    let migrated = session.remove(MIGRATED_MARKER).is_some();
+    if migrated || req.extensions().contains::<SignedWithOldKey>() {
+        session.renew();
+    }
+    next.call(req).await
+}
 
 #[derive(Clone)]
 pub enum RuntimeSessionStore {
     Cookie(Arc<CookieSessionStore>),
     InMemory(InMemoryBackend),
-    Redis(RedisSessionStore),
+    Redis(ResilientRedisStore),
+    Migrating(MigratingStore),
 }
 
 
@@ -78,6 +623,7 @@ impl SessionStore for RuntimeSessionStore {
             RuntimeSessionStore::Cookie(s) => s.load(session_key).await,
             RuntimeSessionStore::InMemory(s) => s.load(session_key).await,
             RuntimeSessionStore::Redis(s) => s.load(session_key).await,
+            RuntimeSessionStore::Migrating(s) => Box::pin(s.load(session_key)).await,
         }
     }
 
@@ -90,6 +636,7 @@ impl SessionStore for RuntimeSessionStore {
             RuntimeSessionStore::Cookie(s) => s.save(session_state, ttl).await,
             RuntimeSessionStore::InMemory(s) => s.save(session_state, ttl).await,
             RuntimeSessionStore::Redis(s) => s.save(session_state, ttl).await,
+            RuntimeSessionStore::Migrating(s) => Box::pin(s.save(session_state, ttl)).await,
         }
     }
 
@@ -103,6 +650,7 @@ impl SessionStore for RuntimeSessionStore {
             RuntimeSessionStore::Cookie(s) => s.update(session_key, session_state, ttl).await,
             RuntimeSessionStore::InMemory(s) => s.update(session_key, session_state, ttl).await,
             RuntimeSessionStore::Redis(s) => s.update(session_key, session_state, ttl).await,
+            RuntimeSessionStore::Migrating(s) => Box::pin(s.update(session_key, session_state, ttl)).await,
         }
     }
 
@@ -111,6 +659,7 @@ impl SessionStore for RuntimeSessionStore {
             RuntimeSessionStore::Cookie(s) => s.update_ttl(session_key, ttl).await,
             RuntimeSessionStore::InMemory(s) => s.update_ttl(session_key, ttl).await,
             RuntimeSessionStore::Redis(s) => s.update_ttl(session_key, ttl).await,
+            RuntimeSessionStore::Migrating(s) => Box::pin(s.update_ttl(session_key, ttl)).await,
         }
     }
 
@@ -119,6 +668,7 @@ impl SessionStore for RuntimeSessionStore {
             RuntimeSessionStore::Cookie(s) => s.delete(session_key).await,
             RuntimeSessionStore::InMemory(s) => s.delete(session_key).await,
             RuntimeSessionStore::Redis(s) => s.delete(session_key).await,
+            RuntimeSessionStore::Migrating(s) => Box::pin(s.delete(session_key)).await,
         }
     }
 }
@@ -128,6 +678,124 @@ mod tests {
     use super::*;
     use actix_web::cookie::time::Duration;
 
+    fn encrypted(key: &Key, value: &str) -> Cookie<'static> {
+        let mut jar = CookieJar::new();
+        jar.private_mut(key).add(Cookie::new("s", value.to_string()));
+        jar.get("s").unwrap().clone()
+    }
+
+    #[test]
+    fn test_old_secret_keys_are_re_signed() {
+        let (current, old) = (Key::generate(), Key::generate());
+        let keys = SecretKeys { cookie_name: "s".to_string(), current: current.clone(), old: vec![Key::generate(), old.clone()] };
+
+        let header = format!("theme=dark; {}", encrypted(&old, "session-key").encoded());
+        let rewritten = keys.rewrite_header(&header).unwrap();
+        let (theme, session) = rewritten.split_once("; ").unwrap();
+        assert_eq!(theme, "theme=dark");
+        let cookie = Cookie::parse_encoded(session.to_string()).unwrap();
+        assert_eq!(CookieJar::new().private(&current).decrypt(cookie).unwrap().value(), "session-key");
+
+        // Cookies already signed with the current key, or with no key we know, are left alone.
+        assert_eq!(keys.rewrite_header(&encrypted(&current, "session-key").encoded().to_string()), None);
+        assert_eq!(keys.rewrite_header(&encrypted(&Key::generate(), "session-key").encoded().to_string()), None);
+        assert_eq!(keys.rewrite_header("theme=dark"), None);
+    }
+
+    fn admin_scope() -> SessionScope {
+        serde_yaml::from_str("path_prefix: /admin/\ncookie_name: admin_session\ncookie_secure: true\ncookie_same_site: strict\ncookie_max_age: 3600").unwrap()
+    }
+
+    #[test]
+    fn test_scope_for() {
+        let scopes = vec![admin_scope(), serde_yaml::from_str("path_prefix: /admin/reports\ncookie_name: reports").unwrap()];
+        assert_eq!(scope_for(&scopes, "/admin").map(|s| s.cookie_name.as_str()), Some("admin_session"));
+        assert_eq!(scope_for(&scopes, "/admin/users/7").map(|s| s.cookie_name.as_str()), Some("admin_session"));
+        assert_eq!(scope_for(&scopes, "/admin/reports/q3").map(|s| s.cookie_name.as_str()), Some("reports"));
+        assert!(scope_for(&scopes, "/administrators").is_none());
+        assert!(scope_for(&scopes, "/").is_none());
+    }
+
+    #[test]
+    fn test_scoped_cookies() {
+        let scope = admin_scope();
+        assert_eq!(scoped_cookie_header("theme=dark; s=public; admin_session=secret", "s", &scope), "theme=dark; s=secret");
+        assert_eq!(scoped_cookie_header("s=public", "s", &scope), "");
+
+        let set = scoped_set_cookie("s=secret; HttpOnly; SameSite=Lax; Path=/; Max-Age=604800", "s", &scope).unwrap();
+        let cookie = Cookie::parse_encoded(set).unwrap();
+        assert_eq!((cookie.name(), cookie.value(), cookie.path()), ("admin_session", "secret", Some("/admin")));
+        assert_eq!((cookie.secure(), cookie.http_only(), cookie.same_site()), (Some(true), Some(true), Some(SameSite::Strict)));
+        assert_eq!(cookie.max_age(), Some(Duration::hours(1)));
+        assert_eq!(scoped_set_cookie("theme=dark", "s", &scope), None);
+    }
+
+    #[test]
+    fn test_checked_session_id() {
+        assert!(checked_session_id("tenant-7.".to_string() + &"a".repeat(32)).is_ok());
+        assert!(checked_session_id("short".to_string()).unwrap_err().contains("shorter than 32"));
+        assert!(checked_session_id(format!("{};evil", "a".repeat(32))).is_err());
+    }
+
+    // Nothing listens on port 1, so every command fails right away.
+    async fn unreachable_redis(fallback: RedisFallback) -> (Pool, ResilientRedisStore) {
+        let pool = deadpool_redis::Config::from_url("redis://127.0.0.1:1/")
+            .create_pool(Some(deadpool_redis::Runtime::Tokio1))
+            .unwrap();
+        let store = RedisSessionStore::new_pooled(pool.clone()).await.unwrap();
+        (pool.clone(), ResilientRedisStore::new(store, pool, RedisHealth::new(fallback)))
+    }
+
+    #[actix_rt::test]
+    async fn test_migrating_store_reads_the_old_cookie_sessions() {
+        let store = MigratingStore::new(RuntimeSessionStore::InMemory(InMemoryBackend::new()), PreviousStore::Cookie);
+        let ttl = Duration::days(1);
+
+        // A cookie session's key is its JSON; it's found and marked for moving.
+        let old_cookie = SessionKey::try_from(r#"{"user_id":"7"}"#.to_string()).unwrap();
+        let state = store.load(&old_cookie).await.unwrap().unwrap();
+        assert_eq!(state.get("user_id").map(String::as_str), Some("7"));
+        assert!(state.contains_key(MIGRATED_MARKER));
+
+        // Sessions already in the new backend come back as they are.
+        let mut moved = HashMap::new();
+        moved.insert("user_id".to_string(), "7".to_string());
+        let key = store.save(moved.clone(), &ttl).await.unwrap();
+        assert_eq!(store.load(&key).await.unwrap(), Some(moved));
+        assert_eq!(store.load(&SessionKey::try_from("b".repeat(64)).unwrap()).await.unwrap(), None);
+    }
+
+    #[actix_rt::test]
+    async fn test_wait_for_redis_gives_up() {
+        let (pool, store) = unreachable_redis(RedisFallback::Error).await;
+        assert!(!wait_for_redis(&pool, &store.health, 1).await);
+        assert!(!store.health.is_up());
+    }
+
+    #[actix_rt::test]
+    async fn test_read_only_fallback_keeps_requests_working() {
+        let (_pool, store) = unreachable_redis(RedisFallback::ReadOnly).await;
+        let ttl = Duration::days(1);
+        let key = SessionKey::try_from("a".repeat(64)).unwrap();
+
+        assert_eq!(store.load(&key).await.unwrap(), None);
+        assert!(!store.health.is_up());
+        let mut state = HashMap::new();
+        state.insert("user".to_string(), "1".to_string());
+        assert!(store.save(state.clone(), &ttl).await.is_ok());
+        assert!(store.update(key, state, &ttl).await.is_ok());
+    }
+
+    #[actix_rt::test]
+    async fn test_error_fallback_refuses_writes() {
+        let (_pool, store) = unreachable_redis(RedisFallback::Error).await;
+        let key = SessionKey::try_from("a".repeat(64)).unwrap();
+
+        // Loads stay empty so the session middleware doesn't fail; the guard shows the error page.
+        assert_eq!(store.load(&key).await.unwrap(), None);
+        assert!(store.save(HashMap::new(), &Duration::days(1)).await.is_err());
+    }
+
     #[actix_rt::test]
     async fn test_in_memory_backend() {
         let backend = InMemoryBackend::new();
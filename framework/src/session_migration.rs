@@ -0,0 +1,160 @@
+use crate::config::{self, SessionBackend, SessionConfig};
+use crate::session;
+use deadpool_redis::{redis, Pool};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct ExportedSession {
+    pub key: String,
+    // Seconds left before it expires, if it does.
+    pub ttl: Option<i64>,
+    pub state: HashMap<String, String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct SessionExport {
+    pub sessions: Vec<ExportedSession>,
+}
+
+// Switches `session.backend` to `to` and sets `session.migrate_from`, so sessions move over as
+// their users come back instead of everyone being logged out.
+pub fn migrate(root: &Path, session: &SessionConfig, from: SessionBackend, to: SessionBackend) -> Result<(), String> {
+    if from == to {
+        return Err("--from and --to are the same backend".to_string());
+    }
+    if from == SessionBackend::Memory {
+        return Err("memory sessions only live in the running server, so there's nothing to move; switching logs everyone out".to_string());
+    }
+    if session.backend != from && session.backend != to {
+        return Err(format!("config.yaml keeps sessions in {}, not {}", session.backend.name(), from.name()));
+    }
+    if session.redis_url.is_none() && (from == SessionBackend::Redis || to == SessionBackend::Redis) {
+        return Err("set `session.redis_url` in config.yaml first".to_string());
+    }
+    let config_path = root.join("config.yaml");
+    let quoted = |backend: SessionBackend| format!("\"{}\"", backend.name());
+    config::set_value(&config_path, "session", "backend", &quoted(to))
+        .and_then(|_| config::set_value(&config_path, "session", "migrate_from", &quoted(from)))
+        .map_err(|e| format!("couldn't update config.yaml: {}", e))
+}
+
+// actix-session stores each session under its bare 64 character key.
+fn is_session_key(key: &str) -> bool {
+    key.len() == 64 && key.chars().all(|c| c.is_ascii_alphanumeric())
+}
+
+fn redis_sessions_pool(session: &SessionConfig) -> Result<Pool, String> {
+    let uses_redis = session.backend == SessionBackend::Redis || session.migrate_from == Some(SessionBackend::Redis);
+    match &session.redis_url {
+        Some(url) if uses_redis => session::redis_pool(url, 2),
+        _ => Err("only Redis sessions can be exported or imported; cookie sessions live in each browser".to_string()),
+    }
+}
+
+pub async fn export(session: &SessionConfig) -> Result<SessionExport, String> {
+    let pool = redis_sessions_pool(session)?;
+    let mut connection = pool.get().await.map_err(|e| e.to_string())?;
+    let mut export = SessionExport::default();
+    let mut cursor: u64 = 0;
+    loop {
+        let (next, keys): (u64, Vec<String>) = redis::cmd("SCAN")
+            .arg(cursor)
+            .arg("COUNT")
+            .arg(500)
+            .query_async(&mut connection)
+            .await
+            .map_err(|e| e.to_string())?;
+        for key in keys.into_iter().filter(|key| is_session_key(key)) {
+            let (value, ttl): (Option<String>, i64) = redis::pipe()
+                .get(&key)
+                .ttl(&key)
+                .query_async(&mut connection)
+                .await
+                .map_err(|e| e.to_string())?;
+            // Anything else that happens to have a key like ours isn't a session.
+            if let Some(state) = value.and_then(|value| serde_json::from_str(&value).ok()) {
+                export.sessions.push(ExportedSession { key, ttl: (ttl > 0).then_some(ttl), state });
+            }
+        }
+        if next == 0 {
+            break;
+        }
+        cursor = next;
+    }
+    Ok(export)
+}
+
+// Returns how many sessions were imported and how many were skipped because they already exist.
+pub async fn import(session: &SessionConfig, export: &SessionExport) -> Result<(usize, usize), String> {
+    if session.backend != SessionBackend::Redis {
+        return Err("sessions can only be imported when `session.backend` is redis".to_string());
+    }
+    let pool = redis_sessions_pool(session)?;
+    let mut connection = pool.get().await.map_err(|e| e.to_string())?;
+    let (mut imported, mut skipped) = (0, 0);
+    for exported in &export.sessions {
+        if !is_session_key(&exported.key) {
+            return Err(format!("'{}' isn't a session key", exported.key));
+        }
+        let state = serde_json::to_string(&exported.state).map_err(|e| e.to_string())?;
+        let mut set = redis::cmd("SET");
+        set.arg(&exported.key).arg(state).arg("NX");
+        if let Some(ttl) = exported.ttl {
+            set.arg("EX").arg(ttl);
+        }
+        // NX leaves sessions that are already there alone; they're newer than the export.
+        let created: Option<String> = set.query_async(&mut connection).await.map_err(|e| e.to_string())?;
+        if created.is_some() {
+            imported += 1;
+        } else {
+            skipped += 1;
+        }
+    }
+    Ok((imported, skipped))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn session_config(backend: SessionBackend) -> SessionConfig {
+        serde_yaml::from_str(&format!(
+            "backend: {}\nsecret_key: key\ncookie_name: s\ncookie_secure: false\ncookie_http_only: true\ncookie_path: /\nredis_url: redis://127.0.0.1/",
+            backend.name()
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_migrate_updates_config() {
+        let dir = tempdir().unwrap();
+        let config_path = dir.path().join("config.yaml");
+        std::fs::write(&config_path, "session:\n  # Where sessions live.\n  backend: \"cookie\"\n  cookie_name: s\nport: 8080\n").unwrap();
+
+        migrate(dir.path(), &session_config(SessionBackend::Cookie), SessionBackend::Cookie, SessionBackend::Redis).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&config_path).unwrap(),
+            "session:\n  migrate_from: \"cookie\"\n  # Where sessions live.\n  backend: \"redis\"\n  cookie_name: s\nport: 8080\n"
+        );
+    }
+
+    #[test]
+    fn test_migrate_refuses_what_cant_move() {
+        let dir = tempdir().unwrap();
+        let memory = session_config(SessionBackend::Memory);
+        assert!(migrate(dir.path(), &memory, SessionBackend::Memory, SessionBackend::Redis).is_err());
+        let cookie = session_config(SessionBackend::Cookie);
+        assert!(migrate(dir.path(), &cookie, SessionBackend::Redis, SessionBackend::Memory).is_err());
+        assert!(migrate(dir.path(), &cookie, SessionBackend::Cookie, SessionBackend::Cookie).is_err());
+    }
+
+    #[test]
+    fn test_is_session_key() {
+        assert!(is_session_key(&"a1".repeat(32)));
+        assert!(!is_session_key("noventa:ratelimit:127.0.0.1:1"));
+        assert!(!is_session_key(&"a-".repeat(32)));
+    }
+}
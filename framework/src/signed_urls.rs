@@ -0,0 +1,232 @@
+use crate::config;
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::{HttpMessage, HttpResponse};
+use hmac::{Hmac, Mac};
+use once_cell::sync::Lazy;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::{PyDict, PyModule};
+use sha2::Sha256;
+use std::collections::BTreeMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const DEFAULT_EXPIRES_IN: u64 = 3600;
+
+// Put on requests whose link was signed and hasn't expired; `{# signed_url_required #}` pages check it.
+#[derive(Clone, Copy)]
+pub struct SignedUrl;
+
+static KEY: Lazy<Vec<u8>> = Lazy::new(|| match &config::CONFIG.session {
+    // Its own key, so a signed link can never pass for a session cookie or the other way around.
+    Some(session) => {
+        let mut mac = Hmac::<Sha256>::new_from_slice(session.secret_key.as_bytes()).expect("HMAC takes keys of any length");
+        mac.update(b"noventa signed urls");
+        mac.finalize().into_bytes().to_vec()
+    }
+    None => {
+        log::warn!("Heads up! There's no `session.secret_key` to sign links with, so signed links stop working when the server restarts.");
+        rand::random::<[u8; 32]>().to_vec()
+    }
+});
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_secs())
+}
+
+fn signature(key: &[u8], path: &str, query: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC takes keys of any length");
+    mac.update(path.as_bytes());
+    mac.update(b"?");
+    mac.update(query.as_bytes());
+    mac.finalize().into_bytes().iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// A path on this site, like `/downloads/{id}`, or a page as `url_for` names it, like `downloads/[id]`.
+// Links are checked against request paths, which always start with `/`, so anything else could
+// be signed but never opened.
+fn route_pattern(route: &str) -> Result<String, String> {
+    if route.starts_with('/') {
+        return Ok(route.to_string());
+    }
+    crate::reverse_routes::page_route(route)
+        .ok_or_else(|| format!("url_for_signed() takes a path on this site, like '/downloads/{{id}}', or a page in pages/, not '{}'.", route))
+}
+
+// Fills the route's `{name}` parts from `params`; the rest of them go in the query string.
+fn build_path(route: &str, params: &BTreeMap<String, String>) -> (String, Vec<(String, String)>) {
+    let mut path = route.to_string();
+    let mut query = Vec::new();
+    for (name, value) in params {
+        // `[post_id]` pages have a `{post-id}` route; either spelling is filled.
+        let placeholders = [format!("{{{}}}", name), format!("{{{}}}", name.replace('_', "-"))];
+        match placeholders.iter().find(|placeholder| path.contains(placeholder.as_str())) {
+            Some(placeholder) => path = path.replace(placeholder.as_str(), &crate::object_storage::uri_encode(value, true)),
+            None => query.push((name.clone(), value.clone())),
+        }
+    }
+    (path, query)
+}
+
+fn sign(key: &[u8], route: &str, params: &BTreeMap<String, String>, expires_at: u64) -> String {
+    let (path, mut query) = build_path(route, params);
+    query.push(("expires".to_string(), expires_at.to_string()));
+    let query = serde_urlencoded::to_string(&query).unwrap_or_default();
+    let signature = signature(key, &path, &query);
+    format!("{}?{}&signature={}", path, query, signature)
+}
+
+// `/downloads/{id}` with `{"id": 7}` becomes `/downloads/7?expires=...&signature=...`, good for `expires_in` seconds.
+pub fn url_for_signed(route: &str, params: &BTreeMap<String, String>, expires_in: u64) -> Result<String, String> {
+    Ok(sign(&KEY, &route_pattern(route)?, params, now() + expires_in))
+}
+
+#[derive(Debug, PartialEq)]
+enum Invalid {
+    BadSignature,
+    Expired,
+}
+
+fn verify(key: &[u8], path: &str, query: &str, now: u64) -> Result<(), Invalid> {
+    let mut signature_given = None;
+    let signed: Vec<&str> = query
+        .split('&')
+        .filter(|pair| match pair.strip_prefix("signature=") {
+            Some(value) => {
+                signature_given = Some(value);
+                false
+            }
+            None => true,
+        })
+        .collect();
+    let signed = signed.join("&");
+    let expected = signature(key, path, &signed);
+    let matches = signature_given.is_some_and(|given| crate::admin::tokens_match(given.as_bytes(), expected.as_bytes()));
+    if !matches {
+        return Err(Invalid::BadSignature);
+    }
+    let expires_at = serde_urlencoded::from_str::<Vec<(String, String)>>(&signed)
+        .unwrap_or_default()
+        .into_iter()
+        .find(|(name, _)| name == "expires")
+        .and_then(|(_, value)| value.parse::<u64>().ok());
+    match expires_at {
+        Some(expires_at) if expires_at >= now => Ok(()),
+        _ => Err(Invalid::Expired),
+    }
+}
+
+// Checks links carrying a `signature` before any route sees them. A good one marks the request as signed.
+pub async fn check_signature(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, actix_web::Error> {
+    let has_signature = req.query_string().split('&').any(|pair| pair.starts_with("signature="));
+    if !has_signature {
+        return next.call(req).await.map(ServiceResponse::map_into_left_body);
+    }
+    match verify(&KEY, req.path(), req.query_string(), now()) {
+        Ok(()) => {
+            req.extensions_mut().insert(SignedUrl);
+            next.call(req).await.map(ServiceResponse::map_into_left_body)
+        }
+        Err(Invalid::Expired) => {
+            let response = HttpResponse::Gone().body("This link has expired. Please ask for a new one.");
+            Ok(req.into_response(response).map_into_right_body())
+        }
+        Err(Invalid::BadSignature) => {
+            let response = HttpResponse::Forbidden().body("This link isn't valid.");
+            Ok(req.into_response(response).map_into_right_body())
+        }
+    }
+}
+
+fn params_from(value: &minijinja::Value) -> Result<BTreeMap<String, String>, minijinja::Error> {
+    if value.is_undefined() || value.is_none() {
+        return Ok(BTreeMap::new());
+    }
+    let mut params = BTreeMap::new();
+    for name in value.try_iter()? {
+        let param = value.get_item(&name)?;
+        params.insert(name.to_string(), param.to_string());
+    }
+    Ok(params)
+}
+
+// `{{ url_for_signed("/downloads/{id}", {"id": file.id}, expires_in=600) }}`.
+// Everything that came from `params` is percent-encoded, so the URL doesn't need HTML escaping.
+pub fn url_for_signed_function(
+    route: String,
+    params: Option<minijinja::Value>,
+    kwargs: minijinja::value::Kwargs,
+) -> Result<minijinja::Value, minijinja::Error> {
+    let expires_in: Option<u64> = kwargs.get("expires_in")?;
+    kwargs.assert_all_used()?;
+    let params = params_from(&params.unwrap_or_default())?;
+    let url = url_for_signed(&route, &params, expires_in.unwrap_or(DEFAULT_EXPIRES_IN))
+        .map_err(|e| minijinja::Error::new(minijinja::ErrorKind::InvalidOperation, e))?;
+    Ok(minijinja::Value::from_safe_string(url))
+}
+
+// `from noventa_urls import url_for_signed`.
+#[pyfunction(name = "url_for_signed")]
+#[pyo3(signature = (route, params=None, expires_in=DEFAULT_EXPIRES_IN))]
+fn py_url_for_signed(route: &str, params: Option<Bound<PyDict>>, expires_in: u64) -> PyResult<String> {
+    let mut values = BTreeMap::new();
+    for (name, value) in params.iter().flat_map(|params| params.iter()) {
+        values.insert(name.str()?.to_string(), value.str()?.to_string());
+    }
+    url_for_signed(route, &values, expires_in).map_err(PyValueError::new_err)
+}
+
+pub fn register_python_module(py: Python) -> PyResult<()> {
+    let module = PyModule::new(py, "noventa_urls")?;
+    module.add_function(wrap_pyfunction!(py_url_for_signed, &module)?)?;
+    py.import("sys")?.getattr("modules")?.set_item("noventa_urls", module)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: &[u8] = b"test key";
+
+    fn split(url: &str) -> (&str, &str) {
+        url.split_once('?').unwrap()
+    }
+
+    #[test]
+    fn test_signed_url_round_trip() {
+        let params = BTreeMap::from([("id".to_string(), "a b".to_string()), ("as".to_string(), "pdf".to_string())]);
+        let url = sign(KEY, "/downloads/{id}", &params, 2000);
+        let (path, query) = split(&url);
+        assert_eq!(path, "/downloads/a%20b");
+        assert!(query.starts_with("as=pdf&expires=2000&signature="));
+        assert_eq!(verify(KEY, path, query, 1000), Ok(()));
+        assert_eq!(verify(KEY, path, query, 2001), Err(Invalid::Expired));
+    }
+
+    #[test]
+    fn test_tampered_links_are_rejected() {
+        let url = sign(KEY, "/unsubscribe", &BTreeMap::from([("user".to_string(), "7".to_string())]), 2000);
+        let (path, query) = split(&url);
+        assert_eq!(verify(KEY, path, &query.replace("user=7", "user=8"), 1000), Err(Invalid::BadSignature));
+        assert_eq!(verify(KEY, path, &query.replace("expires=2000", "expires=9999"), 1000), Err(Invalid::BadSignature));
+        assert_eq!(verify(KEY, "/unsubscribe-all", query, 1000), Err(Invalid::BadSignature));
+        assert_eq!(verify(b"other key", path, query, 1000), Err(Invalid::BadSignature));
+        assert_eq!(verify(KEY, path, "user=7&expires=2000", 1000), Err(Invalid::BadSignature));
+    }
+
+    #[test]
+    fn test_routes_must_be_paths_or_pages() {
+        assert_eq!(route_pattern("/downloads/{id}").unwrap(), "/downloads/{id}");
+        // Signed as is, it would be checked against `/downloads/7` and never match.
+        assert!(route_pattern("downloads/{id}").unwrap_err().contains("'downloads/{id}'"));
+        assert!(url_for_signed("no/such/page", &BTreeMap::new(), 60).is_err());
+
+        let params = BTreeMap::from([("post_id".to_string(), "7".to_string())]);
+        let url = sign(KEY, "/posts/{post-id}", &params, 2000);
+        assert!(url.starts_with("/posts/7?expires=2000&signature="), "{}", url);
+    }
+}
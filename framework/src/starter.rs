@@ -0,0 +1,266 @@
+use rand::RngCore;
+use regex::Regex;
+use once_cell::sync::Lazy;
+use std::io::{BufRead, Error, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+// The default starter ships inside the binary, so `noventa new` works without the starter folder on disk.
+static EMBEDDED_VARIABLES: &str = include_str!("../starter/cookiecutter.json");
+static EMBEDDED_FILES: &[(&str, &str)] = &[
+    ("{{cookiecutter.project_name}}/.gitignore", include_str!("../starter/{{cookiecutter.project_name}}/.gitignore")),
+    ("{{cookiecutter.project_name}}/AGENTS.md", include_str!("../starter/{{cookiecutter.project_name}}/AGENTS.md")),
+    ("{{cookiecutter.project_name}}/CLAUDE.md", include_str!("../starter/{{cookiecutter.project_name}}/CLAUDE.md")),
+    ("{{cookiecutter.project_name}}/GEMINI.md", include_str!("../starter/{{cookiecutter.project_name}}/GEMINI.md")),
+    ("{{cookiecutter.project_name}}/__init__.py", include_str!("../starter/{{cookiecutter.project_name}}/__init__.py")),
+    ("{{cookiecutter.project_name}}/components/__init__.py", include_str!("../starter/{{cookiecutter.project_name}}/components/__init__.py")),
+    ("{{cookiecutter.project_name}}/config.yaml", include_str!("../starter/{{cookiecutter.project_name}}/config.yaml")),
+    ("{{cookiecutter.project_name}}/files/.gitkeep", include_str!("../starter/{{cookiecutter.project_name}}/files/.gitkeep")),
+    ("{{cookiecutter.project_name}}/functions/.gitkeep", include_str!("../starter/{{cookiecutter.project_name}}/functions/.gitkeep")),
+    ("{{cookiecutter.project_name}}/functions/__init__.py", include_str!("../starter/{{cookiecutter.project_name}}/functions/__init__.py")),
+    ("{{cookiecutter.project_name}}/layouts/.gitkeep", include_str!("../starter/{{cookiecutter.project_name}}/layouts/.gitkeep")),
+    ("{{cookiecutter.project_name}}/migrations/__init__.py", include_str!("../starter/{{cookiecutter.project_name}}/migrations/__init__.py")),
+    ("{{cookiecutter.project_name}}/migrations/alembic.ini", include_str!("../starter/{{cookiecutter.project_name}}/migrations/alembic.ini")),
+    ("{{cookiecutter.project_name}}/migrations/env.py", include_str!("../starter/{{cookiecutter.project_name}}/migrations/env.py")),
+    ("{{cookiecutter.project_name}}/migrations/script.py.mako", include_str!("../starter/{{cookiecutter.project_name}}/migrations/script.py.mako")),
+    ("{{cookiecutter.project_name}}/migrations/seed/.gitkeep", include_str!("../starter/{{cookiecutter.project_name}}/migrations/seed/.gitkeep")),
+    ("{{cookiecutter.project_name}}/migrations/seed/__init__.py", include_str!("../starter/{{cookiecutter.project_name}}/migrations/seed/__init__.py")),
+    ("{{cookiecutter.project_name}}/migrations/versions/__init__.py", include_str!("../starter/{{cookiecutter.project_name}}/migrations/versions/__init__.py")),
+    ("{{cookiecutter.project_name}}/models/__init__.py", include_str!("../starter/{{cookiecutter.project_name}}/models/__init__.py")),
+    ("{{cookiecutter.project_name}}/pages/.gitkeep", include_str!("../starter/{{cookiecutter.project_name}}/pages/.gitkeep")),
+    ("{{cookiecutter.project_name}}/requirements.txt", include_str!("../starter/{{cookiecutter.project_name}}/requirements.txt")),
+];
+
+// Starters use cookiecutter's layout, so `{{ cookiecutter.project_name }}` works in file names and contents.
+static VARIABLE_REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"\{\{\s*cookiecutter\.(\w+)\s*\}\}").unwrap());
+
+const SECRET_KEY_PLACEHOLDER: &str = "!!!REPLACE-ME-WITH-A-REAL-SECRET-KEY!!!";
+
+struct Variable {
+    name: String,
+    prompt: String,
+    choices: Vec<String>,
+}
+
+// Reads cookiecutter.json, keeping the order of the keys so prompts come out as written.
+fn parse_variables(source: &str) -> Result<Vec<Variable>, Error> {
+    let mapping: serde_yaml::Mapping = serde_yaml::from_str(source)
+        .map_err(|e| Error::new(ErrorKind::InvalidData, format!("cookiecutter.json isn't valid: {}", e)))?;
+    let prompts = mapping.get("__prompts__").and_then(|p| p.as_mapping());
+
+    let mut variables = Vec::new();
+    for (key, value) in &mapping {
+        let Some(name) = key.as_str().filter(|k| !k.starts_with('_')) else {
+            continue;
+        };
+        let choices: Vec<String> = match value {
+            serde_yaml::Value::Sequence(items) => items.iter().filter_map(scalar_to_string).collect(),
+            other => scalar_to_string(other).into_iter().collect(),
+        };
+        let prompt = prompts
+            .and_then(|p| p.get(name))
+            .and_then(|p| p.as_str())
+            .map(str::to_string)
+            .unwrap_or_else(|| name.replace('_', " "));
+        variables.push(Variable { name: name.to_string(), prompt, choices });
+    }
+    Ok(variables)
+}
+
+fn scalar_to_string(value: &serde_yaml::Value) -> Option<String> {
+    match value {
+        serde_yaml::Value::String(s) => Some(s.clone()),
+        serde_yaml::Value::Bool(b) => Some(b.to_string()),
+        serde_yaml::Value::Number(n) => Some(n.to_string()),
+        _ => None,
+    }
+}
+
+fn render(text: &str, context: &[(String, String)]) -> String {
+    VARIABLE_REGEX
+        .replace_all(text, |caps: &regex::Captures| {
+            context
+                .iter()
+                .find(|(name, _)| name == &caps[1])
+                .map(|(_, value)| value.clone())
+                .unwrap_or_else(|| caps[0].to_string())
+        })
+        .into_owned()
+}
+
+// Asks for every variable, falling back to its default when the answer is empty.
+// Defaults may reference earlier answers, e.g. "{{ cookiecutter.project_name }}-db".
+fn ask(variables: &[Variable], no_input: bool, input: &mut impl BufRead) -> Result<Vec<(String, String)>, Error> {
+    let mut context: Vec<(String, String)> = Vec::new();
+    for variable in variables {
+        let choices: Vec<String> = variable.choices.iter().map(|c| render(c, &context)).collect();
+        let default = choices.first().cloned().unwrap_or_default();
+        let value = if no_input {
+            default
+        } else if choices.len() > 1 {
+            println!("{}:", variable.prompt);
+            for (i, choice) in choices.iter().enumerate() {
+                println!("  {} - {}", i + 1, choice);
+            }
+            print!("Choose from 1-{} [1]: ", choices.len());
+            std::io::stdout().flush()?;
+            let mut answer = String::new();
+            input.read_line(&mut answer)?;
+            answer
+                .trim()
+                .parse::<usize>()
+                .ok()
+                .and_then(|n| choices.get(n.wrapping_sub(1)).cloned())
+                .unwrap_or(default)
+        } else {
+            print!("{} [{}]: ", variable.prompt, default);
+            std::io::stdout().flush()?;
+            let mut answer = String::new();
+            input.read_line(&mut answer)?;
+            let answer = answer.trim();
+            if answer.is_empty() { default } else { answer.to_string() }
+        };
+        context.push((variable.name.clone(), value));
+    }
+    Ok(context)
+}
+
+fn generate_secret_key() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// Files as (path relative to the starter, content) pairs.
+type StarterFiles = Vec<(String, Vec<u8>)>;
+
+// Loads cookiecutter.json and the files of the project folder.
+fn load_starter(starter_path: Option<&Path>) -> Result<(String, StarterFiles), Error> {
+    let Some(starter_path) = starter_path else {
+        let files = EMBEDDED_FILES.iter().map(|(p, c)| (p.to_string(), c.as_bytes().to_vec())).collect();
+        return Ok((EMBEDDED_VARIABLES.to_string(), files));
+    };
+
+    let variables = std::fs::read_to_string(starter_path.join("cookiecutter.json")).map_err(|e| {
+        Error::new(e.kind(), format!("No cookiecutter.json found in {}: {}", starter_path.display(), e))
+    })?;
+    let mut files = Vec::new();
+    for entry in WalkDir::new(starter_path).into_iter().filter_map(Result::ok) {
+        let relative = entry.path().strip_prefix(starter_path).unwrap_or(entry.path());
+        let relative_str = relative.to_string_lossy().replace('\\', "/");
+        // Only the templated project folder is copied; cookiecutter.json and hooks/ stay behind.
+        if !entry.file_type().is_file() || !relative_str.starts_with("{{") {
+            continue;
+        }
+        files.push((relative_str, std::fs::read(entry.path())?));
+    }
+    Ok((variables, files))
+}
+
+// Renders a starter into `output_dir` and returns the new project folder.
+pub fn create_project(
+    starter_path: Option<&Path>,
+    output_dir: &Path,
+    no_input: bool,
+    input: &mut impl BufRead,
+) -> Result<PathBuf, Error> {
+    let (variables_source, files) = load_starter(starter_path)?;
+    let context = ask(&parse_variables(&variables_source)?, no_input, input)?;
+
+    let project_root = files
+        .first()
+        .and_then(|(path, _)| path.split('/').next())
+        .map(|dir| render(dir, &context))
+        .ok_or_else(|| Error::new(ErrorKind::NotFound, "The starter doesn't contain a project folder"))?;
+    if project_root.is_empty() || project_root.contains(['/', '\\']) || project_root == ".." {
+        return Err(Error::new(ErrorKind::InvalidInput, format!("'{}' isn't a valid project name", project_root)));
+    }
+    let project_dir = output_dir.join(&project_root);
+    if project_dir.exists() {
+        return Err(Error::new(
+            ErrorKind::AlreadyExists,
+            format!("{} already exists. Pick another project name or remove the folder first.", project_dir.display()),
+        ));
+    }
+
+    let secret_key = generate_secret_key();
+    for (path, content) in &files {
+        let target = output_dir.join(render(path, &context));
+        if let Some(parent) = target.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        // Binary files such as images are copied as they are.
+        let Ok(text) = std::str::from_utf8(content) else {
+            std::fs::write(&target, content)?;
+            continue;
+        };
+        let mut text = render(text, &context);
+        if target.file_name().is_some_and(|n| n == "config.yaml") {
+            text = text.replace(SECRET_KEY_PLACEHOLDER, &secret_key);
+        }
+        std::fs::write(&target, text)?;
+    }
+
+    Ok(project_dir)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_parse_variables_keeps_order_and_prompts() {
+        let variables = parse_variables(EMBEDDED_VARIABLES).unwrap();
+        let names: Vec<&str> = variables.iter().map(|v| v.name.as_str()).collect();
+        assert_eq!(names, vec!["project_name", "project_description"]);
+        assert_eq!(variables[0].prompt, "Enter a name for your project");
+        assert_eq!(variables[0].choices, vec!["noventa-web"]);
+    }
+
+    #[test]
+    fn test_render_leaves_unknown_variables() {
+        let context = vec![("project_name".to_string(), "shop".to_string())];
+        assert_eq!(render("{{cookiecutter.project_name}}/{{ cookiecutter.project_name }}", &context), "shop/shop");
+        assert_eq!(render("{{ cookiecutter.other }} {{ title }}", &context), "{{ cookiecutter.other }} {{ title }}");
+    }
+
+    #[test]
+    fn test_create_project_from_embedded_starter() {
+        let dir = tempdir().unwrap();
+        let mut input = "my-shop\n\n".as_bytes();
+        let project = create_project(None, dir.path(), false, &mut input).unwrap();
+
+        assert_eq!(project, dir.path().join("my-shop"));
+        assert!(project.join("pages/.gitkeep").exists());
+        assert!(project.join("migrations/env.py").exists());
+        let config = std::fs::read_to_string(project.join("config.yaml")).unwrap();
+        assert!(!config.contains(SECRET_KEY_PLACEHOLDER));
+
+        // A second run refuses to touch the existing folder.
+        let mut input = "my-shop\n\n".as_bytes();
+        assert_eq!(
+            create_project(None, dir.path(), false, &mut input).unwrap_err().kind(),
+            ErrorKind::AlreadyExists
+        );
+    }
+
+    #[test]
+    fn test_create_project_from_custom_starter() {
+        let starter = tempdir().unwrap();
+        std::fs::write(
+            starter.path().join("cookiecutter.json"),
+            r#"{"project_name": "site", "db": ["sqlite", "postgres"], "db_name": "{{ cookiecutter.project_name }}_db"}"#,
+        )
+        .unwrap();
+        let project_template = starter.path().join("{{cookiecutter.project_name}}");
+        std::fs::create_dir_all(&project_template).unwrap();
+        std::fs::write(project_template.join("README.md"), "{{ cookiecutter.db }} {{ cookiecutter.db_name }}").unwrap();
+
+        let output = tempdir().unwrap();
+        let mut input = "\n2\n\n".as_bytes();
+        let project = create_project(Some(starter.path()), output.path(), false, &mut input).unwrap();
+        assert_eq!(std::fs::read_to_string(project.join("README.md")).unwrap(), "postgres site_db");
+    }
+}
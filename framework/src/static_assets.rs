@@ -40,13 +40,19 @@ pub static EMBEDDED_FILES: Lazy<HashMap<String, EmbeddedFile>> = Lazy::new(|| {
 
 use crate::config::CONFIG;
 
-pub fn get_script_tags() -> String {
+// ` nonce="..."` for a script tag, or nothing when the request has no nonce.
+pub fn nonce_attribute(nonce: Option<&str>) -> String {
+    nonce.map(|nonce| format!(" nonce=\"{}\"", nonce)).unwrap_or_default()
+}
+
+pub fn get_script_tags(nonce: Option<&str>) -> String {
     let prefix = CONFIG.static_url_prefix.as_deref().unwrap_or("/static");
+    let nonce = nonce_attribute(nonce);
     SCRIPT_ORDER
         .iter()
         .map(|&(_name, content)| {
             let hash = hash_content(content);
-            format!("<script defer src=\"{}/noventa-static/{}\"></script>\n", prefix, hash)
+            format!("<script defer{} src=\"{}/noventa-static/{}\"></script>\n", nonce, prefix, hash)
         })
         .collect::<String>()
 }
\ No newline at end of file
@@ -0,0 +1,107 @@
+use actix_web::http::header::ContentDisposition;
+use actix_web::web::{self, Bytes};
+use actix_web::HttpResponse;
+use minijinja::Value;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyIterator, PyString};
+use std::sync::Arc;
+
+// `return {"_stream": rows(), "_content_type": "text/csv", "_filename": "orders.csv"}` from an action or
+// a `_page.py` sends what the iterator yields while it yields it, instead of rendering the page. Big
+// exports go out without ever being in memory whole.
+pub const STREAM_KEY: &str = "_stream";
+// Items are gathered until there's about this much to send, not sent one by one.
+const BATCH_SIZE: usize = 64 * 1024;
+
+#[derive(Clone)]
+pub struct PythonStream {
+    iterator: Arc<Py<PyAny>>,
+    content_type: String,
+    filename: Option<String>,
+}
+
+impl PythonStream {
+    // The iterator the interpreter took out of `context`, with how to send it from what's left.
+    pub fn new(iterator: Arc<Py<PyAny>>, context: &Value) -> Self {
+        let setting = |key: &str| context.get_attr(key).ok().and_then(|value| value.as_str().map(str::to_string));
+        Self {
+            iterator,
+            content_type: setting("_content_type").unwrap_or_else(|| "text/plain; charset=utf-8".to_string()),
+            filename: setting("_filename"),
+        }
+    }
+}
+
+// Strings and bytes go out as they are; anything else as a line of JSON, for NDJSON exports.
+fn write_item(batch: &mut Vec<u8>, item: &Bound<PyAny>) -> PyResult<()> {
+    if let Ok(text) = item.downcast::<PyString>() {
+        batch.extend_from_slice(text.to_str()?.as_bytes());
+    } else if let Ok(bytes) = item.downcast::<PyBytes>() {
+        batch.extend_from_slice(bytes.as_bytes());
+    } else {
+        let value: serde_json::Value = pythonize::depythonize(item)?;
+        serde_json::to_writer(&mut *batch, &value).map_err(|e| PyValueError::new_err(e.to_string()))?;
+        batch.push(b'\n');
+    }
+    Ok(())
+}
+
+// The next items, about BATCH_SIZE of them, or None once the iterator is done.
+fn next_batch(iterator: &Py<PyAny>) -> PyResult<Option<Bytes>> {
+    Python::attach(|py| {
+        let mut items = iterator.bind(py).downcast::<PyIterator>()?.clone();
+        let mut batch = Vec::new();
+        while batch.len() < BATCH_SIZE {
+            match items.next() {
+                Some(item) => write_item(&mut batch, &item?)?,
+                None => break,
+            }
+        }
+        Ok((!batch.is_empty()).then(|| Bytes::from(batch)))
+    })
+}
+
+pub fn response(stream: PythonStream) -> HttpResponse {
+    let mut response = HttpResponse::Ok();
+    response.content_type(stream.content_type);
+    if let Some(filename) = stream.filename {
+        response.insert_header(ContentDisposition::attachment(filename));
+    }
+    // Read on a blocking thread, one batch each time the connection is ready for more.
+    let body = futures::stream::unfold(Some(stream.iterator), |iterator| async move {
+        let iterator = iterator?;
+        let reading = Arc::clone(&iterator);
+        match web::block(move || next_batch(&reading)).await {
+            Ok(Ok(Some(batch))) => Some((Ok(batch), Some(iterator))),
+            Ok(Ok(None)) => None,
+            Ok(Err(e)) => {
+                log::error!("Oh no! A streamed response broke off because the Python iterator failed: {}", e);
+                Some((Err(actix_web::error::ErrorInternalServerError(e.to_string())), None))
+            }
+            Err(e) => {
+                log::error!("Oh no! A streamed response broke off: {}", e);
+                Some((Err(actix_web::error::ErrorInternalServerError(e.to_string())), None))
+            }
+        }
+    });
+    response.streaming(body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::CString;
+
+    #[test]
+    fn test_next_batch() {
+        let iterator = Python::attach(|py| {
+            let code = CString::new("iter(['id,name\\n', b'1,Ana\\n', {'id': 2}] + ['x' * 40000] * 2)").unwrap();
+            py.eval(&code, None, None).unwrap().unbind()
+        });
+        let first = next_batch(&iterator).unwrap().unwrap();
+        assert!(first.starts_with(b"id,name\n1,Ana\n{\"id\":2}\nxxx"));
+        assert_eq!(first.len(), 14 + 9 + 40000 * 2);
+        assert!(next_batch(&iterator).unwrap().is_none());
+    }
+}
@@ -0,0 +1,317 @@
+use crate::config;
+use once_cell::sync::{Lazy, OnceCell};
+use serde_json::json;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+// Traces of each page render, sent to an OpenTelemetry collector over OTLP/HTTP (JSON): a span for
+// the request, the render, each component and each Python function, so a slow page shows where its
+// time went. Off unless `telemetry.otlp_endpoint` (or OTEL_EXPORTER_OTLP_ENDPOINT) is set.
+//
+// A request's trace travels with its `HttpRequestInfo` and the messages the actors send each other;
+// a `traceparent` header from the caller continues its trace.
+
+const EXPORT_INTERVAL: Duration = Duration::from_secs(2);
+const MAX_BATCH: usize = 512;
+const EXPORT_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TraceContext {
+    pub trace_id: u128,
+    pub span_id: u64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SpanKind {
+    Internal = 1,
+    Server = 2,
+}
+
+#[derive(Debug)]
+struct FinishedSpan {
+    context: TraceContext,
+    parent_span_id: Option<u64>,
+    name: String,
+    kind: SpanKind,
+    start: u128,
+    end: u128,
+    attributes: Vec<(String, String)>,
+    error: Option<String>,
+}
+
+// Where spans go, or None when tracing is off.
+static ENDPOINT: Lazy<Option<String>> = Lazy::new(|| {
+    let endpoint = config::CONFIG
+        .telemetry
+        .as_ref()
+        .and_then(|telemetry| telemetry.otlp_endpoint.clone())
+        .or_else(|| std::env::var("OTEL_EXPORTER_OTLP_ENDPOINT").ok())
+        .filter(|endpoint| !endpoint.trim().is_empty())?;
+    let endpoint = endpoint.trim_end_matches('/');
+    Some(if endpoint.ends_with("/v1/traces") { endpoint.to_string() } else { format!("{}/v1/traces", endpoint) })
+});
+
+static EXPORTER: OnceCell<Sender<FinishedSpan>> = OnceCell::new();
+
+pub fn enabled() -> bool {
+    ENDPOINT.is_some()
+}
+
+fn unix_nanos() -> u128 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_nanos())
+}
+
+fn new_span_id() -> u64 {
+    loop {
+        let id = rand::random::<u64>();
+        if id != 0 {
+            return id;
+        }
+    }
+}
+
+// The trace and parent span of a W3C `traceparent` header, e.g.
+// `00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01`, if the caller sampled it.
+fn parse_traceparent(header: &str) -> Option<Option<TraceContext>> {
+    let mut parts = header.trim().split('-');
+    let (version, trace_id, span_id, flags) = (parts.next()?, parts.next()?, parts.next()?, parts.next()?);
+    if version.len() != 2 || trace_id.len() != 32 || span_id.len() != 16 || flags.len() != 2 {
+        return None;
+    }
+    let trace_id = u128::from_str_radix(trace_id, 16).ok().filter(|id| *id != 0)?;
+    let span_id = u64::from_str_radix(span_id, 16).ok().filter(|id| *id != 0)?;
+    let sampled = u8::from_str_radix(flags, 16).ok()? & 1 == 1;
+    Some(sampled.then_some(TraceContext { trace_id, span_id }))
+}
+
+fn sampled() -> bool {
+    let ratio = config::CONFIG.telemetry.as_ref().and_then(|telemetry| telemetry.sample_ratio).unwrap_or(1.0);
+    ratio >= 1.0 || rand::random::<f64>() < ratio
+}
+
+// A span that's sent when it's dropped. Without a trace it does nothing, so callers don't have to
+// check whether tracing is on.
+pub struct Span {
+    context: Option<TraceContext>,
+    parent_span_id: Option<u64>,
+    name: String,
+    kind: SpanKind,
+    start: u128,
+    attributes: Vec<(String, String)>,
+    error: Option<String>,
+}
+
+impl Span {
+    fn disabled() -> Self {
+        Span { context: None, parent_span_id: None, name: String::new(), kind: SpanKind::Internal, start: 0, attributes: Vec::new(), error: None }
+    }
+
+    fn started(context: TraceContext, parent_span_id: Option<u64>, name: String, kind: SpanKind) -> Self {
+        Span { context: Some(context), parent_span_id, name, kind, start: unix_nanos(), attributes: Vec::new(), error: None }
+    }
+
+    // What the spans started under this one take as their parent.
+    pub fn context(&self) -> Option<TraceContext> {
+        self.context
+    }
+
+    pub fn set_attribute(&mut self, key: &str, value: impl ToString) {
+        if self.context.is_some() {
+            self.attributes.push((key.to_string(), value.to_string()));
+        }
+    }
+
+    pub fn set_error(&mut self, message: impl ToString) {
+        if self.context.is_some() {
+            self.error = Some(message.to_string());
+        }
+    }
+}
+
+impl Drop for Span {
+    fn drop(&mut self) {
+        let Some(context) = self.context else {
+            return;
+        };
+        export(FinishedSpan {
+            context,
+            parent_span_id: self.parent_span_id,
+            name: std::mem::take(&mut self.name),
+            kind: self.kind,
+            start: self.start,
+            end: unix_nanos(),
+            attributes: std::mem::take(&mut self.attributes),
+            error: self.error.take(),
+        });
+    }
+}
+
+// The span of a request handled by this server, continuing the caller's trace if it sent one.
+pub fn start_request(traceparent: Option<&str>, name: impl Into<String>) -> Span {
+    if !enabled() {
+        return Span::disabled();
+    }
+    let parent = match traceparent.map(parse_traceparent) {
+        Some(Some(Some(parent))) => Some(parent),
+        // The caller decided not to record this one.
+        Some(Some(None)) => return Span::disabled(),
+        _ if !sampled() => return Span::disabled(),
+        _ => None,
+    };
+    let trace_id = parent.map_or_else(|| rand::random::<u128>().max(1), |parent| parent.trace_id);
+    Span::started(TraceContext { trace_id, span_id: new_span_id() }, parent.map(|parent| parent.span_id), name.into(), SpanKind::Server)
+}
+
+// A span inside the one `parent` is the context of.
+pub fn child(parent: Option<TraceContext>, name: impl Into<String>) -> Span {
+    match parent {
+        Some(parent) => Span::started(TraceContext { trace_id: parent.trace_id, span_id: new_span_id() }, Some(parent.span_id), name.into(), SpanKind::Internal),
+        None => Span::disabled(),
+    }
+}
+
+fn export(span: FinishedSpan) {
+    let Some(endpoint) = ENDPOINT.as_ref() else {
+        return;
+    };
+    let sender = EXPORTER.get_or_init(|| {
+        let (sender, receiver) = mpsc::channel();
+        log::debug!("Sending traces to {}.", endpoint);
+        let endpoint = endpoint.clone();
+        std::thread::spawn(move || run_exporter(&endpoint, receiver));
+        sender
+    });
+    let _ = sender.send(span);
+}
+
+// Sends what's finished every couple of seconds, or sooner once a batch fills up.
+fn run_exporter(endpoint: &str, receiver: Receiver<FinishedSpan>) {
+    let client = reqwest::blocking::Client::builder().timeout(EXPORT_TIMEOUT).build().unwrap_or_default();
+    let headers = config::CONFIG.telemetry.as_ref().and_then(|telemetry| telemetry.headers.clone()).unwrap_or_default();
+    // Warned about once, not every couple of seconds while the collector is down.
+    let mut failing = false;
+    let mut batch = Vec::new();
+    let mut last_sent = Instant::now();
+    loop {
+        let closed = match receiver.recv_timeout(EXPORT_INTERVAL) {
+            Ok(span) => {
+                batch.push(span);
+                false
+            }
+            Err(RecvTimeoutError::Timeout) => false,
+            Err(RecvTimeoutError::Disconnected) => true,
+        };
+        if batch.is_empty() || (!closed && batch.len() < MAX_BATCH && last_sent.elapsed() < EXPORT_INTERVAL) {
+            if closed {
+                return;
+            }
+            continue;
+        }
+        let spans = std::mem::take(&mut batch);
+        last_sent = Instant::now();
+        let mut request = client
+            .post(endpoint)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(otlp_body(&spans).to_string());
+        for (name, value) in &headers {
+            request = request.header(name, value);
+        }
+        match request.send().map(|response| response.error_for_status()) {
+            Ok(Ok(_)) => failing = false,
+            Ok(Err(e)) | Err(e) => {
+                if !std::mem::replace(&mut failing, true) {
+                    log::warn!("Heads up! {} spans couldn't be sent to {} ({}), so they were dropped.", spans.len(), endpoint, e);
+                }
+            }
+        }
+        if closed {
+            return;
+        }
+    }
+}
+
+// An ExportTraceServiceRequest in OTLP's JSON encoding.
+fn otlp_body(spans: &[FinishedSpan]) -> serde_json::Value {
+    let service_name = config::CONFIG
+        .telemetry
+        .as_ref()
+        .and_then(|telemetry| telemetry.service_name.clone())
+        .unwrap_or_else(|| "noventa".to_string());
+    let attribute = |key: &str, value: &str| json!({ "key": key, "value": { "stringValue": value } });
+    let spans: Vec<serde_json::Value> = spans
+        .iter()
+        .map(|span| {
+            let mut encoded = json!({
+                "traceId": format!("{:032x}", span.context.trace_id),
+                "spanId": format!("{:016x}", span.context.span_id),
+                "name": span.name,
+                "kind": span.kind as u8,
+                "startTimeUnixNano": span.start.to_string(),
+                "endTimeUnixNano": span.end.to_string(),
+                "attributes": span.attributes.iter().map(|(key, value)| attribute(key, value)).collect::<Vec<_>>(),
+                "status": match &span.error {
+                    Some(message) => json!({ "code": 2, "message": message }),
+                    None => json!({ "code": 0 }),
+                },
+            });
+            if let Some(parent) = span.parent_span_id {
+                encoded["parentSpanId"] = json!(format!("{:016x}", parent));
+            }
+            encoded
+        })
+        .collect();
+    json!({
+        "resourceSpans": [{
+            "resource": { "attributes": [attribute("service.name", &service_name)] },
+            "scopeSpans": [{ "scope": { "name": "noventa" }, "spans": spans }],
+        }],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_traceparent() {
+        let parent = parse_traceparent("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01").unwrap().unwrap();
+        assert_eq!(parent, TraceContext { trace_id: 0x4bf92f3577b34da6a3ce929d0e0e4736, span_id: 0x00f067aa0ba902b7 });
+        assert_eq!(parse_traceparent("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-00"), Some(None));
+        assert_eq!(parse_traceparent("00-00000000000000000000000000000000-00f067aa0ba902b7-01"), None);
+        assert_eq!(parse_traceparent("garbage"), None);
+    }
+
+    #[test]
+    fn test_otlp_body() {
+        let root = TraceContext { trace_id: 1, span_id: 2 };
+        let mut component = child(Some(root), "component cart");
+        let context = component.context().unwrap();
+        assert_eq!(context.trace_id, 1);
+        component.set_attribute("noventa.component", "cart");
+        component.set_error("boom");
+        // Sent nowhere: spans without an endpoint are dropped.
+        let finished = FinishedSpan {
+            context,
+            parent_span_id: component.parent_span_id,
+            name: component.name.clone(),
+            kind: component.kind,
+            start: 10,
+            end: 20,
+            attributes: component.attributes.clone(),
+            error: component.error.clone(),
+        };
+        let body = otlp_body(&[finished]);
+        let span = &body["resourceSpans"][0]["scopeSpans"][0]["spans"][0];
+        assert_eq!(span["traceId"], "00000000000000000000000000000001");
+        assert_eq!(span["parentSpanId"], "0000000000000002");
+        assert_eq!(span["name"], "component cart");
+        assert_eq!(span["startTimeUnixNano"], "10");
+        assert_eq!(span["attributes"][0]["value"]["stringValue"], "cart");
+        assert_eq!(span["status"]["code"], 2);
+
+        let mut disabled = child(None, "nothing");
+        disabled.set_attribute("ignored", 1);
+        assert_eq!(disabled.context(), None);
+        assert!(disabled.attributes.is_empty());
+    }
+}
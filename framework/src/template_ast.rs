@@ -0,0 +1,275 @@
+use minijinja::machinery::ast::{CallArg, Expr, Stmt};
+use minijinja::machinery::{parse, WhitespaceConfig};
+use minijinja::syntax::SyntaxConfig;
+use minijinja::Value;
+
+// What a template pulls in, read from its syntax tree instead of its text, so strings with commas,
+// `{% with %}` blocks and calls spread over several lines don't trip it up.
+#[derive(Debug, Default)]
+pub struct TemplateRefs {
+    // Templates it extends, includes or imports, in order. Names only known at render time are left out.
+    pub templates: Vec<String>,
+    // The one it extends, whose blocks it may replace.
+    pub extends: Option<String>,
+    // The ones of those pulled in only under an `{% if %}`, in a loop or in a macro.
+    pub conditional_templates: Vec<String>,
+    // The ones pulled in inside a `{% block %}`.
+    pub block_templates: Vec<String>,
+    pub components: Vec<ComponentCall>,
+    // How many `{% if %}`s, loops and macros the statement being read is in.
+    conditions: usize,
+    blocks: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct ComponentCall {
+    // As written, e.g. `cards.post`.
+    pub name: String,
+    // Keyword arguments with constant values. Ones that depend on variables are only known at render time.
+    pub kwargs: Vec<(String, Value)>,
+    pub line: usize,
+    // Every argument is a constant, so `kwargs` is all of them.
+    pub constant_args: bool,
+    // Under an `{% if %}`, in a loop or in a macro, so it may not be rendered at all, or many times.
+    pub conditional: bool,
+    // In a `{% block %}`, which a template extending this one may replace.
+    pub in_block: bool,
+}
+
+pub fn scan(name: &str, source: &str) -> Result<TemplateRefs, minijinja::Error> {
+    let tree = parse(source, name, SyntaxConfig, WhitespaceConfig::default())?;
+    let mut refs = TemplateRefs::default();
+    refs.stmt(&tree);
+    Ok(refs)
+}
+
+impl TemplateRefs {
+    fn template(&mut self, name: &Expr) {
+        let names: Vec<String> = match name.as_const() {
+            Some(value) if value.as_str().is_some() => vec![value.to_string()],
+            // `{% include ["a.html", "b.html"] %}` tries each.
+            Some(value) => value.try_iter().map(|names| names.filter_map(|name| name.as_str().map(str::to_string)).collect()).unwrap_or_default(),
+            None => {
+                self.expr(name);
+                Vec::new()
+            }
+        };
+        if self.conditions > 0 {
+            self.conditional_templates.extend(names.iter().cloned());
+        }
+        if self.blocks > 0 {
+            self.block_templates.extend(names.iter().cloned());
+        }
+        self.templates.extend(names);
+    }
+
+    fn conditional_stmts(&mut self, stmts: &[Stmt]) {
+        self.conditions += 1;
+        self.stmts(stmts);
+        self.conditions -= 1;
+    }
+
+    fn conditional_expr(&mut self, expr: &Expr) {
+        self.conditions += 1;
+        self.expr(expr);
+        self.conditions -= 1;
+    }
+
+    fn stmts(&mut self, stmts: &[Stmt]) {
+        for stmt in stmts {
+            self.stmt(stmt);
+        }
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Template(s) => self.stmts(&s.children),
+            Stmt::EmitExpr(s) => self.expr(&s.expr),
+            Stmt::EmitRaw(_) => {}
+            Stmt::ForLoop(s) => {
+                self.expr(&s.iter);
+                if let Some(filter_expr) = &s.filter_expr {
+                    self.conditional_expr(filter_expr);
+                }
+                self.conditional_stmts(&s.body);
+                self.conditional_stmts(&s.else_body);
+            }
+            Stmt::IfCond(s) => {
+                self.expr(&s.expr);
+                self.conditional_stmts(&s.true_body);
+                self.conditional_stmts(&s.false_body);
+            }
+            Stmt::WithBlock(s) => {
+                for (_, value) in &s.assignments {
+                    self.expr(value);
+                }
+                self.stmts(&s.body);
+            }
+            Stmt::Set(s) => self.expr(&s.expr),
+            Stmt::SetBlock(s) => self.stmts(&s.body),
+            Stmt::AutoEscape(s) => self.stmts(&s.body),
+            Stmt::FilterBlock(s) => self.stmts(&s.body),
+            Stmt::Block(s) => {
+                self.blocks += 1;
+                self.stmts(&s.body);
+                self.blocks -= 1;
+            }
+            Stmt::Import(s) => self.template(&s.expr),
+            Stmt::FromImport(s) => self.template(&s.expr),
+            Stmt::Extends(s) => {
+                self.extends = s.name.as_const().and_then(|name| name.as_str().map(str::to_string));
+                self.template(&s.name);
+            }
+            Stmt::Include(s) => self.template(&s.name),
+            Stmt::Macro(s) => {
+                for default in &s.defaults {
+                    self.conditional_expr(default);
+                }
+                self.conditional_stmts(&s.body);
+            }
+            Stmt::CallBlock(s) => {
+                self.call_args(&s.call.args);
+                self.conditional_stmts(&s.macro_decl.body);
+            }
+            Stmt::Do(s) => self.call_args(&s.call.args),
+        }
+    }
+
+    fn call_args(&mut self, args: &[CallArg]) {
+        for arg in args {
+            match arg {
+                CallArg::Pos(expr) | CallArg::Kwarg(_, expr) | CallArg::PosSplat(expr) | CallArg::KwargSplat(expr) => self.expr(expr),
+            }
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Var(_) | Expr::Const(_) => {}
+            Expr::Slice(e) => {
+                self.expr(&e.expr);
+                for part in [&e.start, &e.stop, &e.step].into_iter().flatten() {
+                    self.expr(part);
+                }
+            }
+            Expr::UnaryOp(e) => self.expr(&e.expr),
+            Expr::BinOp(e) => {
+                self.expr(&e.left);
+                self.expr(&e.right);
+            }
+            Expr::IfExpr(e) => {
+                self.expr(&e.test_expr);
+                self.conditional_expr(&e.true_expr);
+                if let Some(false_expr) = &e.false_expr {
+                    self.conditional_expr(false_expr);
+                }
+            }
+            Expr::Filter(e) => {
+                if let Some(inner) = &e.expr {
+                    self.expr(inner);
+                }
+                self.call_args(&e.args);
+            }
+            Expr::Test(e) => {
+                self.expr(&e.expr);
+                self.call_args(&e.args);
+            }
+            Expr::GetAttr(e) => self.expr(&e.expr),
+            Expr::GetItem(e) => {
+                self.expr(&e.expr);
+                self.expr(&e.subscript_expr);
+            }
+            Expr::Call(call) => {
+                if let Expr::Var(var) = &call.expr
+                    && var.id == "component"
+                    && let Some(CallArg::Pos(first)) = call.args.first()
+                    && let Some(name) = first.as_const().and_then(|name| name.as_str().map(str::to_string))
+                {
+                    let kwargs = call
+                        .args
+                        .iter()
+                        .filter_map(|arg| match arg {
+                            CallArg::Kwarg(key, value) => Some((key.to_string(), value.as_const()?)),
+                            _ => None,
+                        })
+                        .collect();
+                    let constant_args = call.args[1..].iter().all(|arg| matches!(arg, CallArg::Kwarg(_, value) if value.as_const().is_some()));
+                    self.components.push(ComponentCall {
+                        name,
+                        kwargs,
+                        line: call.span().start_line as usize,
+                        constant_args,
+                        conditional: self.conditions > 0,
+                        in_block: self.blocks > 0,
+                    });
+                }
+                self.expr(&call.expr);
+                self.call_args(&call.args);
+            }
+            Expr::List(e) => {
+                for item in &e.items {
+                    self.expr(item);
+                }
+            }
+            Expr::Map(e) => {
+                for item in e.keys.iter().chain(&e.values) {
+                    self.expr(item);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scan_components_and_templates() {
+        let source = r#"{% extends "layouts/base.html" %}
+{% block content %}
+  {% with title = "Hello, world" %}
+    {{ component("cards.post", title="a, b = c", slug=post.slug, count=3, tags=["x", "y"]) }}
+  {% endwith %}
+  {% for item in items %}{{ component('row', id=item.id) | safe }}{% endfor %}
+  {% include ["partials/a.html", "partials/b.html"] %}
+  {% include "partials/" ~ name %}
+  {% from "macros/forms.html" import field %}
+  {% set sidebar = component("sidebar", on_error="sidebar_error.html") %}
+{% endblock %}"#;
+        let refs = scan("pages/index.html", source).unwrap();
+        assert_eq!(refs.templates, vec!["layouts/base.html", "partials/a.html", "partials/b.html", "macros/forms.html"]);
+
+        let names: Vec<&str> = refs.components.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, vec!["cards.post", "row", "sidebar"]);
+        let post = &refs.components[0];
+        assert_eq!(post.line, 4);
+        let kwargs: Vec<(&str, String)> = post.kwargs.iter().map(|(key, value)| (key.as_str(), value.to_string())).collect();
+        assert_eq!(kwargs, vec![("title", "a, b = c".to_string()), ("count", "3".to_string()), ("tags", "[\"x\", \"y\"]".to_string())]);
+        assert!(refs.components[1].kwargs.is_empty());
+        assert!(!post.constant_args && !post.conditional && post.in_block);
+        assert!(refs.components[1].conditional);
+        assert!(refs.components[2].constant_args);
+        assert!(refs.conditional_templates.is_empty());
+        assert_eq!(refs.block_templates, vec!["partials/a.html", "partials/b.html", "macros/forms.html"]);
+        assert_eq!(refs.extends.as_deref(), Some("layouts/base.html"));
+    }
+
+    #[test]
+    fn test_scan_conditional_calls() {
+        let source = r#"{{ component("header") }}
+{% if user %}{{ component("account") }}{% include "partials/admin.html" %}{% endif %}
+{{ component("promo") if flags.promo }}
+{% macro card() %}{{ component("card") }}{% endmacro %}"#;
+        let refs = scan("layouts/main.html", source).unwrap();
+        let conditional: Vec<(&str, bool)> = refs.components.iter().map(|c| (c.name.as_str(), c.conditional)).collect();
+        assert_eq!(conditional, vec![("header", false), ("account", true), ("promo", true), ("card", true)]);
+        assert_eq!(refs.conditional_templates, vec!["partials/admin.html"]);
+        assert!(!refs.components[0].in_block);
+    }
+
+    #[test]
+    fn test_scan_reports_syntax_errors() {
+        assert!(scan("broken.html", "{% if %}").is_err());
+    }
+}
@@ -0,0 +1,150 @@
+use minijinja::functions::Function;
+use minijinja::value::{FunctionArgs, FunctionResult, Rest, Value};
+use minijinja::{Environment, State};
+use once_cell::sync::Lazy;
+use std::sync::RwLock;
+
+// Names owned by the framework itself. Extensions are installed after Noventa's own functions and
+// filters, so registering one of these would silently replace it; we refuse it up front.
+pub(crate) const RESERVED_FUNCTIONS: &[&str] = &["component", "url_for", "url_for_signed", "srcset", "status"];
+pub(crate) const RESERVED_FILTERS: &[&str] = &["format", "datetime", "timeago", "number", "currency"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ExtensionKind {
+    Filter,
+    Function,
+    Test,
+}
+
+#[derive(Debug, Clone)]
+struct Extension {
+    kind: ExtensionKind,
+    name: String,
+    callable: Value,
+}
+
+// Every filter, function and test registered from Rust lives here. The template
+// renderer installs all of them on the Environment it builds (prod and dev), so
+// registrations made before the server starts are visible to every template.
+static EXTENSIONS: Lazy<RwLock<Vec<Extension>>> = Lazy::new(|| RwLock::new(Vec::new()));
+
+fn register(kind: ExtensionKind, name: &str, callable: Value) -> Result<(), String> {
+    let reserved = match kind {
+        ExtensionKind::Filter => RESERVED_FILTERS,
+        ExtensionKind::Function => RESERVED_FUNCTIONS,
+        ExtensionKind::Test => &[],
+    };
+    if reserved.contains(&name) {
+        return Err(format!("'{}' is reserved by Noventa and can't be registered again", name));
+    }
+
+    let mut extensions = EXTENSIONS.write().map_err(|_| "Template extension registry lock is poisoned".to_string())?;
+    // Registering the same name twice replaces the previous definition.
+    extensions.retain(|e| !(e.kind == kind && e.name == name));
+    extensions.push(Extension {
+        kind,
+        name: name.to_string(),
+        callable,
+    });
+    Ok(())
+}
+
+/// Registers a filter usable as `{{ value|name(...) }}` in every template.
+#[allow(dead_code)] // Nothing in the tree registers one yet.
+pub fn register_filter<F, Rv, Args>(name: &str, f: F) -> Result<(), String>
+where
+    F: Function<Rv, Args>,
+    Rv: FunctionResult,
+    Args: for<'a> FunctionArgs<'a>,
+{
+    register(ExtensionKind::Filter, name, Value::from_function(f))
+}
+
+/// Registers a global function usable as `{{ name(...) }}` in every template.
+#[allow(dead_code)] // Nothing in the tree registers one yet.
+pub fn register_function<F, Rv, Args>(name: &str, f: F) -> Result<(), String>
+where
+    F: Function<Rv, Args>,
+    Rv: FunctionResult,
+    Args: for<'a> FunctionArgs<'a>,
+{
+    register(ExtensionKind::Function, name, Value::from_function(f))
+}
+
+/// Registers a test usable as `{% if value is name %}` in every template.
+#[allow(dead_code)] // Nothing in the tree registers one yet.
+pub fn register_test<F, Rv, Args>(name: &str, f: F) -> Result<(), String>
+where
+    F: Function<Rv, Args>,
+    Rv: FunctionResult,
+    Args: for<'a> FunctionArgs<'a>,
+{
+    register(ExtensionKind::Test, name, Value::from_function(f))
+}
+
+// Installs all registered extensions on the given environment.
+pub fn apply(env: &mut Environment<'static>) {
+    let extensions = match EXTENSIONS.read() {
+        Ok(extensions) => extensions,
+        Err(_) => {
+            log::error!("We couldn't load the custom template extensions because the registry lock is poisoned.");
+            return;
+        }
+    };
+
+    for extension in extensions.iter() {
+        let callable = extension.callable.clone();
+        let forward = move |state: &State, args: Rest<Value>| -> Result<Value, minijinja::Error> {
+            callable.call(state, &args)
+        };
+        match extension.kind {
+            ExtensionKind::Filter => env.add_filter(extension.name.clone(), forward),
+            ExtensionKind::Function => env.add_function(extension.name.clone(), forward),
+            ExtensionKind::Test => env.add_test(extension.name.clone(), forward),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_registered_extensions_are_applied() {
+        register_filter("ext_test_shout", |s: String| s.to_uppercase()).unwrap();
+        register_function("ext_test_add", |a: i64, b: i64| a + b).unwrap();
+        register_test("ext_test_even", |n: i64| n % 2 == 0).unwrap();
+
+        let mut env = Environment::new();
+        apply(&mut env);
+        let rendered = env
+            .render_str(
+                "{{ 'hi'|ext_test_shout }} {{ ext_test_add(2, 3) }} {{ 4 is ext_test_even }}",
+                minijinja::context! {},
+            )
+            .unwrap();
+        assert_eq!(rendered, "HI 5 true");
+    }
+
+    #[test]
+    fn test_reregistering_replaces_previous_definition() {
+        register_function("ext_test_greeting", || "first").unwrap();
+        register_function("ext_test_greeting", || "second").unwrap();
+
+        let mut env = Environment::new();
+        apply(&mut env);
+        let rendered = env.render_str("{{ ext_test_greeting() }}", minijinja::context! {}).unwrap();
+        assert_eq!(rendered, "second");
+    }
+
+    #[test]
+    fn test_reserved_names_are_rejected() {
+        for name in RESERVED_FUNCTIONS {
+            assert!(register_function(name, || "nope").is_err(), "{} should be reserved", name);
+        }
+        for name in RESERVED_FILTERS {
+            assert!(register_filter(name, |s: String| s).is_err(), "{} should be reserved", name);
+        }
+        assert!(register_filter("ext_test_status", |s: String| s).is_ok());
+    }
+}
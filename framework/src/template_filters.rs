@@ -0,0 +1,386 @@
+use chrono::{DateTime, FixedOffset, Local, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use minijinja::value::{Kwargs, Value};
+use minijinja::{Environment, Error, ErrorKind, State};
+use std::fmt::Write;
+
+// Name of the global holding the locale negotiated from the request's Accept-Language header.
+pub const LOCALE_GLOBAL: &str = "request_locale";
+const DEFAULT_LOCALE: &str = "en-US";
+
+// Formatting conventions for the locales we know about. Anything else falls back to en-US.
+struct LocaleFormat {
+    decimal: char,
+    thousands: &'static str,
+    date: &'static str,
+    time: &'static str,
+    currency_after: bool,
+}
+
+fn locale_format(locale: &str) -> LocaleFormat {
+    let locale = locale.to_lowercase();
+    let language = locale.split('-').next().unwrap_or("");
+    if matches!(locale.as_str(), "en-gb" | "en-ie" | "en-au" | "en-nz") {
+        return LocaleFormat { decimal: '.', thousands: ",", date: "%d/%m/%Y", time: "%H:%M", currency_after: false };
+    }
+    match language {
+        "de" => LocaleFormat { decimal: ',', thousands: ".", date: "%d.%m.%Y", time: "%H:%M", currency_after: true },
+        "fr" => LocaleFormat { decimal: ',', thousands: "\u{202f}", date: "%d/%m/%Y", time: "%H:%M", currency_after: true },
+        "es" | "it" | "pt" | "nl" => LocaleFormat { decimal: ',', thousands: ".", date: "%d/%m/%Y", time: "%H:%M", currency_after: true },
+        "ja" | "zh" | "ko" => LocaleFormat { decimal: '.', thousands: ",", date: "%Y/%m/%d", time: "%H:%M", currency_after: false },
+        _ => LocaleFormat { decimal: '.', thousands: ",", date: "%m/%d/%Y", time: "%I:%M %p", currency_after: false },
+    }
+}
+
+// Picks the preferred locale from the parsed Accept-Language values (e.g. ["de-DE", "en;q=0.8"]).
+pub fn request_locale(accept_languages: &[String]) -> String {
+    let mut candidates: Vec<(f32, &str)> = accept_languages
+        .iter()
+        .filter_map(|entry| {
+            let mut parts = entry.split(';');
+            let tag = parts.next()?.trim();
+            if tag.is_empty() || tag == "*" {
+                return None;
+            }
+            let quality = parts
+                .find_map(|p| p.trim().strip_prefix("q="))
+                .and_then(|q| q.parse::<f32>().ok())
+                .unwrap_or(1.0);
+            Some((quality, tag))
+        })
+        .collect();
+    // Stable sort keeps the header order for equal weights.
+    candidates.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(std::cmp::Ordering::Equal));
+    candidates
+        .first()
+        .map(|(_, tag)| tag.to_string())
+        .unwrap_or_else(|| DEFAULT_LOCALE.to_string())
+}
+
+fn current_locale(state: &State) -> String {
+    state
+        .lookup(LOCALE_GLOBAL)
+        .and_then(|v| v.as_str().map(|s| s.to_string()))
+        .unwrap_or_else(|| DEFAULT_LOCALE.to_string())
+}
+
+fn invalid(message: impl Into<String>) -> Error {
+    Error::new(ErrorKind::InvalidOperation, message.into())
+}
+
+#[derive(Clone, Copy)]
+enum Zone {
+    Utc,
+    Local,
+    Offset(FixedOffset),
+    Named(Tz),
+}
+
+impl Zone {
+    fn parse(tz: &str) -> Result<Zone, Error> {
+        match tz {
+            "UTC" | "utc" | "Z" => Ok(Zone::Utc),
+            "local" => Ok(Zone::Local),
+            name => {
+                if let Ok(zone) = name.parse::<Tz>() {
+                    return Ok(Zone::Named(zone));
+                }
+                DateTime::parse_from_str(&format!("2000-01-01T00:00:00{}", name), "%Y-%m-%dT%H:%M:%S%:z")
+                    .map(|dt| Zone::Offset(*dt.offset()))
+                    .map_err(|_| {
+                        invalid(format!(
+                            "unknown time zone '{}', use 'UTC', 'local', an offset like '+02:00' or a name like 'Europe/Berlin'",
+                            tz
+                        ))
+                    })
+            }
+        }
+    }
+
+    // The same instant, shown with the offset the zone had at that moment.
+    fn convert(self, dt: DateTime<FixedOffset>) -> DateTime<FixedOffset> {
+        match self {
+            Zone::Utc => dt.with_timezone(&Utc).fixed_offset(),
+            Zone::Local => dt.with_timezone(&Local).fixed_offset(),
+            Zone::Offset(offset) => dt.with_timezone(&offset),
+            Zone::Named(zone) => dt.with_timezone(&zone).fixed_offset(),
+        }
+    }
+
+    // Reads a wall clock time in this zone. Times repeated when the clocks go back take the first
+    // occurrence; times skipped when they go forward don't exist, so they're an error.
+    fn localize(self, naive: &NaiveDateTime) -> Result<DateTime<FixedOffset>, Error> {
+        let localized = match self {
+            Zone::Utc => Some(Utc.from_utc_datetime(naive).fixed_offset()),
+            Zone::Local => Local.from_local_datetime(naive).earliest().map(|dt| dt.fixed_offset()),
+            Zone::Offset(offset) => offset.from_local_datetime(naive).earliest(),
+            Zone::Named(zone) => zone.from_local_datetime(naive).earliest().map(|dt| dt.fixed_offset()),
+        };
+        localized.ok_or_else(|| invalid(format!("{} doesn't exist in that time zone", naive)))
+    }
+}
+
+// Accepts unix timestamps (seconds) and the common ISO 8601 / RFC 3339 string shapes.
+// Strings without an offset are read as wall clock time in `zone`.
+fn parse_datetime(value: &Value, zone: Zone) -> Result<DateTime<FixedOffset>, Error> {
+    if let Some(s) = value.as_str() {
+        if let Ok(dt) = DateTime::parse_from_rfc3339(s) {
+            return Ok(dt);
+        }
+        for format in ["%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%d %H:%M:%S%.f", "%Y-%m-%dT%H:%M", "%Y-%m-%d %H:%M"] {
+            if let Ok(naive) = NaiveDateTime::parse_from_str(s, format) {
+                return zone.localize(&naive);
+            }
+        }
+        if let Ok(date) = NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+            return zone.localize(&date.and_hms_opt(0, 0, 0).unwrap());
+        }
+        return Err(invalid(format!("'{}' is not a date we understand", s)));
+    }
+
+    let timestamp = f64::try_from(value.clone()).map_err(|_| invalid("expected a timestamp or a date string"))?;
+    let seconds = timestamp.trunc() as i64;
+    let nanos = ((timestamp.fract()) * 1_000_000_000.0) as u32;
+    DateTime::from_timestamp(seconds, nanos)
+        .map(|dt| dt.fixed_offset())
+        .ok_or_else(|| invalid("timestamp is out of range"))
+}
+
+fn zone_argument(kwargs: &Kwargs) -> Result<Option<Zone>, Error> {
+    kwargs.get::<Option<String>>("tz")?.map(|tz| Zone::parse(&tz)).transpose()
+}
+
+// `{{ value|datetime }}`, `{{ value|datetime("date") }}`, `{{ value|datetime("%A %d", tz="Europe/Berlin") }}`
+// `tz` is the zone values without an offset are in, as in `timeago`, and the zone every value is
+// shown in. Without it, values without an offset are UTC and the rest keep their own offset.
+fn datetime_filter(state: &State, value: Value, format: Option<String>, kwargs: Kwargs) -> Result<String, Error> {
+    let zone = zone_argument(&kwargs)?;
+    kwargs.assert_all_used()?;
+    let mut dt = parse_datetime(&value, zone.unwrap_or(Zone::Utc))?;
+    if let Some(zone) = zone {
+        dt = zone.convert(dt);
+    }
+
+    let locale = locale_format(&current_locale(state));
+    let pattern = match format.as_deref() {
+        None | Some("datetime") => format!("{} {}", locale.date, locale.time),
+        Some("date") => locale.date.to_string(),
+        Some("time") => locale.time.to_string(),
+        Some("iso") => "%Y-%m-%dT%H:%M:%S%:z".to_string(),
+        Some(custom) => custom.to_string(),
+    };
+
+    let mut output = String::new();
+    write!(output, "{}", dt.format(&pattern)).map_err(|_| invalid(format!("invalid date format '{}'", pattern)))?;
+    Ok(output)
+}
+
+fn plural(amount: i64, unit: &str) -> String {
+    if amount == 1 {
+        format!("1 {}", unit)
+    } else {
+        format!("{} {}s", amount, unit)
+    }
+}
+
+fn humanize_delta(seconds: i64) -> String {
+    let magnitude = seconds.abs();
+    if magnitude < 45 {
+        return "just now".to_string();
+    }
+    let phrase = match magnitude {
+        s if s < 90 => "1 minute".to_string(),
+        s if s < 3_600 => plural((s + 30) / 60, "minute"),
+        s if s < 86_400 => plural((s + 1_800) / 3_600, "hour"),
+        s if s < 2_592_000 => plural((s + 43_200) / 86_400, "day"),
+        s if s < 31_536_000 => plural((s + 1_296_000) / 2_592_000, "month"),
+        s => plural((s + 15_768_000) / 31_536_000, "year"),
+    };
+    if seconds >= 0 {
+        format!("{} ago", phrase)
+    } else {
+        format!("in {}", phrase)
+    }
+}
+
+// `{{ post.created_at|timeago }}` -> "3 hours ago" / "in 2 days"
+// `tz` is the zone values without an offset are in, as in `datetime`, so DST changes between them count.
+fn timeago_filter(value: Value, now: Option<Value>, kwargs: Kwargs) -> Result<String, Error> {
+    let zone = zone_argument(&kwargs)?.unwrap_or(Zone::Utc);
+    kwargs.assert_all_used()?;
+    let then = parse_datetime(&value, zone)?;
+    let now = match now {
+        Some(now) => parse_datetime(&now, zone)?,
+        None => Utc::now().fixed_offset(),
+    };
+    Ok(humanize_delta(now.signed_duration_since(then).num_seconds()))
+}
+
+fn format_number(value: f64, decimals: usize, locale: &LocaleFormat) -> String {
+    let formatted = format!("{:.*}", decimals, value.abs());
+    let (integer, fraction) = match formatted.split_once('.') {
+        Some((i, f)) => (i, Some(f)),
+        None => (formatted.as_str(), None),
+    };
+
+    let mut grouped = String::new();
+    for (i, digit) in integer.chars().enumerate() {
+        if i > 0 && (integer.len() - i) % 3 == 0 {
+            grouped.push_str(locale.thousands);
+        }
+        grouped.push(digit);
+    }
+
+    let mut result = String::new();
+    if value < 0.0 && formatted.chars().any(|c| c != '0' && c != '.') {
+        result.push('-');
+    }
+    result.push_str(&grouped);
+    if let Some(fraction) = fraction {
+        result.push(locale.decimal);
+        result.push_str(fraction);
+    }
+    result
+}
+
+fn as_number(value: &Value) -> Result<f64, Error> {
+    if let Some(s) = value.as_str() {
+        return s.trim().parse::<f64>().map_err(|_| invalid(format!("'{}' is not a number", s)));
+    }
+    f64::try_from(value.clone()).map_err(|_| invalid("expected a number"))
+}
+
+// `{{ 1234567.891|number(2) }}` -> "1,234,567.89" (en-US) / "1.234.567,89" (de)
+fn number_filter(state: &State, value: Value, decimals: Option<usize>) -> Result<String, Error> {
+    let number = as_number(&value)?;
+    let decimals = decimals.unwrap_or(if number.fract() == 0.0 { 0 } else { 2 });
+    Ok(format_number(number, decimals, &locale_format(&current_locale(state))))
+}
+
+fn currency_symbol(code: &str) -> Option<&'static str> {
+    match code {
+        "USD" => Some("$"),
+        "EUR" => Some("€"),
+        "GBP" => Some("£"),
+        "JPY" | "CNY" => Some("¥"),
+        "BRL" => Some("R$"),
+        "INR" => Some("₹"),
+        _ => None,
+    }
+}
+
+// `{{ 19.9|currency("EUR") }}` -> "€19.90" (en-US) / "19,90 €" (de)
+fn currency_filter(state: &State, value: Value, code: Option<String>) -> Result<String, Error> {
+    let number = as_number(&value)?;
+    let code = code.unwrap_or_else(|| "USD".to_string()).to_uppercase();
+    let decimals = if code == "JPY" { 0 } else { 2 };
+    let locale = locale_format(&current_locale(state));
+    let amount = format_number(number, decimals, &locale);
+    let symbol = currency_symbol(&code).map(|s| s.to_string()).unwrap_or_else(|| format!("{} ", code));
+
+    Ok(if locale.currency_after {
+        format!("{} {}", amount, symbol.trim_end())
+    } else if let Some(unsigned) = amount.strip_prefix('-') {
+        format!("-{}{}", symbol, unsigned)
+    } else {
+        format!("{}{}", symbol, amount)
+    })
+}
+
+pub fn add_to_environment(env: &mut Environment<'static>) {
+    env.add_filter("datetime", datetime_filter);
+    env.add_filter("timeago", timeago_filter);
+    env.add_filter("number", number_filter);
+    env.add_filter("currency", currency_filter);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(locale: &str, source: &str) -> String {
+        let mut env = Environment::new();
+        add_to_environment(&mut env);
+        env.add_global(LOCALE_GLOBAL, locale);
+        env.render_str(source, minijinja::context! {}).unwrap()
+    }
+
+    #[test]
+    fn test_request_locale() {
+        assert_eq!(request_locale(&[]), "en-US");
+        assert_eq!(request_locale(&["de-DE".to_string(), "en;q=0.8".to_string()]), "de-DE");
+        assert_eq!(request_locale(&["en;q=0.5".to_string(), "fr-FR;q=0.9".to_string()]), "fr-FR");
+        assert_eq!(request_locale(&["*".to_string()]), "en-US");
+    }
+
+    #[test]
+    fn test_datetime_filter() {
+        assert_eq!(render("en-US", "{{ 0|datetime('date') }}"), "01/01/1970");
+        assert_eq!(render("de-DE", "{{ '2024-03-05T14:30:00Z'|datetime }}"), "05.03.2024 14:30");
+        assert_eq!(render("en-US", "{{ '2024-03-05'|datetime('%Y') }}"), "2024");
+        assert_eq!(
+            render("en-GB", "{{ '2024-03-05T23:30:00Z'|datetime('datetime', tz='+02:00') }}"),
+            "06/03/2024 01:30"
+        );
+    }
+
+    #[test]
+    fn test_datetime_filter_named_zones() {
+        // Berlin moves from CET to CEST at 01:00 UTC on 31 March 2024 and back at 01:00 UTC on 27 October.
+        assert_eq!(render("en-US", "{{ '2024-03-31T00:30:00Z'|datetime('%H:%M %:z', tz='Europe/Berlin') }}"), "01:30 +01:00");
+        assert_eq!(render("en-US", "{{ '2024-03-31T01:30:00Z'|datetime('%H:%M %:z', tz='Europe/Berlin') }}"), "03:30 +02:00");
+        assert_eq!(render("en-US", "{{ '2024-10-27T00:30:00Z'|datetime('%H:%M %:z', tz='Europe/Berlin') }}"), "02:30 +02:00");
+        assert_eq!(render("en-US", "{{ '2024-10-27T01:30:00Z'|datetime('%H:%M %:z', tz='Europe/Berlin') }}"), "02:30 +01:00");
+        assert_eq!(render("en-US", "{{ '2024-07-04T16:00:00Z'|datetime('time', tz='America/New_York') }}"), "12:00 PM");
+        // Values without an offset are already in `tz`.
+        assert_eq!(render("en-US", "{{ '2024-03-31 03:30'|datetime('%H:%M %:z', tz='Europe/Berlin') }}"), "03:30 +02:00");
+        assert_eq!(render("en-US", "{{ '2024-03-31 03:30'|datetime('%H:%M %:z') }}"), "03:30 +00:00");
+
+        let mut env = Environment::new();
+        add_to_environment(&mut env);
+        assert!(env.render_str("{{ 0|datetime(tz='Mars/Olympus_Mons') }}", ()).is_err());
+    }
+
+    #[test]
+    fn test_timeago_filter() {
+        assert_eq!(render("en-US", "{{ '2024-01-01T00:00:00Z'|timeago('2024-01-01T00:00:10Z') }}"), "just now");
+        assert_eq!(render("en-US", "{{ '2024-01-01T00:00:00Z'|timeago('2024-01-01T03:00:00Z') }}"), "3 hours ago");
+        assert_eq!(render("en-US", "{{ '2024-01-03T00:00:00Z'|timeago('2024-01-01T00:00:00Z') }}"), "in 2 days");
+
+        // Wall clock times in Berlin across the spring and autumn clock changes.
+        assert_eq!(render("en-US", "{{ '2024-03-31 01:30'|timeago('2024-03-31 03:30') }}"), "2 hours ago");
+        assert_eq!(render("en-US", "{{ '2024-03-31 01:30'|timeago('2024-03-31 03:30', tz='Europe/Berlin') }}"), "1 hour ago");
+        assert_eq!(render("en-US", "{{ '2024-10-27 01:30'|timeago('2024-10-27 03:30', tz='Europe/Berlin') }}"), "3 hours ago");
+
+        let mut env = Environment::new();
+        add_to_environment(&mut env);
+        assert!(env.render_str("{{ '2024-03-31 02:30'|timeago('2024-03-31 04:00', tz='Europe/Berlin') }}", ()).is_err());
+    }
+
+    #[test]
+    fn test_datetime_and_timeago_agree_on_tz() {
+        // 01:30 in Berlin on the morning the clocks go forward is 00:30 UTC, an hour before 01:30 UTC.
+        let source = "{{ '2024-03-31 01:30'|datetime('iso', tz='Europe/Berlin') }} \
+            {{ '2024-03-31 01:30'|timeago('2024-03-31T01:30:00Z', tz='Europe/Berlin') }}";
+        assert_eq!(render("en-US", source), "2024-03-31T01:30:00+01:00 1 hour ago");
+        let source = "{{ '2024-03-31 01:30'|datetime('iso') }} {{ '2024-03-31 01:30'|timeago('2024-03-31T01:30:00Z') }}";
+        assert_eq!(render("en-US", source), "2024-03-31T01:30:00+00:00 just now");
+    }
+
+    #[test]
+    fn test_number_filter() {
+        assert_eq!(render("en-US", "{{ 1234567.891|number(2) }}"), "1,234,567.89");
+        assert_eq!(render("de-DE", "{{ 1234567.891|number(2) }}"), "1.234.567,89");
+        assert_eq!(render("en-US", "{{ 1000|number }}"), "1,000");
+        assert_eq!(render("en-US", "{{ -1234.5|number(1) }}"), "-1,234.5");
+    }
+
+    #[test]
+    fn test_currency_filter() {
+        assert_eq!(render("en-US", "{{ 19.9|currency }}"), "$19.90");
+        assert_eq!(render("de-DE", "{{ 1234.5|currency('EUR') }}"), "1.234,50 €");
+        assert_eq!(render("en-US", "{{ -5|currency('GBP') }}"), "-£5.00");
+        assert_eq!(render("en-US", "{{ 1500|currency('JPY') }}"), "¥1,500");
+        assert_eq!(render("en-US", "{{ 10|currency('CHF') }}"), "CHF 10.00");
+    }
+}
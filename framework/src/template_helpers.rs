@@ -0,0 +1,257 @@
+use crate::actors::interpreter::{CallTemplateHelper, ListTemplateHelpers, PythonInterpreterActor};
+use crate::{config, interpreter_queue, template_extensions};
+use crate::errors::{DetailedError, ErrorSource};
+use actix::Addr;
+use minijinja::value::{Kwargs, Rest};
+use minijinja::{Environment, Value};
+use once_cell::sync::Lazy;
+use pyo3::prelude::*;
+use pyo3::types::PyModule;
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::CString;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+
+// The project's `template_helpers.py`, imported like any other module.
+pub const MODULE: &str = "template_helpers";
+// Set by the `noventa_templates` decorators on the functions they register.
+pub const HELPER_ATTRIBUTE: &str = "_noventa_template_helper";
+// Past this many cached results of pure filters, the cache starts over.
+const MAX_PURE_RESULTS: usize = 10_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HelperKind {
+    Filter,
+    Global,
+}
+
+#[derive(Debug, Clone)]
+pub struct TemplateHelper {
+    pub kind: HelperKind,
+    // What templates call it.
+    pub name: String,
+    // What it's called in template_helpers.py.
+    pub attribute: String,
+    pub pure: bool,
+}
+
+// None until the interpreter has been asked; then the helpers of the current template_helpers.py.
+static HELPERS: Lazy<RwLock<Option<Arc<Vec<TemplateHelper>>>>> = Lazy::new(|| RwLock::new(None));
+// Bumped when the Python code changes, so every interpreter imports template_helpers.py again.
+static GENERATION: AtomicU64 = AtomicU64::new(0);
+static PURE_RESULTS: Lazy<Mutex<HashMap<String, Value>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+pub fn generation() -> u64 {
+    GENERATION.load(Ordering::SeqCst)
+}
+
+// The Python code changed: look the helpers up again and forget what the pure ones returned.
+pub fn reload() {
+    GENERATION.fetch_add(1, Ordering::SeqCst);
+    *HELPERS.write().unwrap() = None;
+    PURE_RESULTS.lock().unwrap().clear();
+}
+
+pub async fn helpers(interpreter: &Addr<PythonInterpreterActor>) -> Arc<Vec<TemplateHelper>> {
+    if let Some(helpers) = HELPERS.read().unwrap().as_ref() {
+        return Arc::clone(helpers);
+    }
+    if !config::BASE_PATH.join(format!("{}.py", MODULE)).is_file() {
+        let helpers = Arc::new(Vec::new());
+        *HELPERS.write().unwrap() = Some(Arc::clone(&helpers));
+        return helpers;
+    }
+    let helpers = match interpreter.send(ListTemplateHelpers).await {
+        Ok(Ok(helpers)) => {
+            log::debug!("Found {} template helpers in {}.py.", helpers.len(), MODULE);
+            helpers
+        }
+        Ok(Err(py_err)) => {
+            log::error!("Oh no! {}.py failed to load, so its filters and globals aren't available: {}", MODULE, py_err);
+            Vec::new()
+        }
+        Err(e) => {
+            // Not remembered, so the next render tries again.
+            log::error!("A mailbox error occurred while loading the template helpers: {}", e);
+            return Arc::new(Vec::new());
+        }
+    };
+    let helpers = Arc::new(helpers);
+    *HELPERS.write().unwrap() = Some(Arc::clone(&helpers));
+    helpers
+}
+
+// Helpers are installed after Noventa's own functions and filters, so a helper with one of their
+// names would silently replace the built-in; it's skipped instead.
+pub fn is_reserved(kind: HelperKind, name: &str) -> bool {
+    match kind {
+        HelperKind::Filter => template_extensions::RESERVED_FILTERS.contains(&name),
+        HelperKind::Global => template_extensions::RESERVED_FUNCTIONS.contains(&name),
+    }
+}
+
+// Registers every filter and global from template_helpers.py on `env`. A render can't wait on the
+// interpreter, so what they return comes from `call`: the renderer answers it with what it loaded.
+pub fn apply<F>(env: &mut Environment<'static>, helpers: &[TemplateHelper], call: F)
+where
+    F: Fn(&TemplateHelper, Vec<Value>, Kwargs) -> Result<Value, minijinja::Error> + Clone + Send + Sync + 'static,
+{
+    for helper in helpers {
+        if is_reserved(helper.kind, &helper.name) {
+            log::warn!("Heads up! '{}' in {}.py is reserved by Noventa, so templates can't use it.", helper.name, MODULE);
+            continue;
+        }
+        let call = call.clone();
+        let name = helper.name.clone();
+        let helper = helper.clone();
+        match helper.kind {
+            HelperKind::Filter => env.add_filter(name, move |value: Value, args: Rest<Value>, kwargs: Kwargs| {
+                let mut all_args = vec![value];
+                all_args.extend(args.iter().cloned());
+                call(&helper, all_args, kwargs)
+            }),
+            HelperKind::Global => env.add_function(name, move |args: Rest<Value>, kwargs: Kwargs| {
+                call(&helper, args.to_vec(), kwargs)
+            }),
+        }
+    }
+}
+
+// A call to a helper, for the interpreter.
+pub struct HelperCall {
+    // The helper and its arguments: the same call gets the same key.
+    pub key: String,
+    pure: bool,
+    message: CallTemplateHelper,
+}
+
+pub enum Prepared {
+    // What a pure helper returned for these arguments before.
+    Cached(Value),
+    Call(HelperCall),
+}
+
+pub fn prepare(helper: &TemplateHelper, args: Vec<Value>, kwargs: Kwargs) -> Result<Prepared, minijinja::Error> {
+    let mut named = BTreeMap::new();
+    for name in kwargs.args() {
+        named.insert(name.to_string(), kwargs.get::<Value>(name)?);
+    }
+    let key = serde_json::to_string(&(&helper.name, &args, &named)).map_err(|e| minijinja::Error::new(minijinja::ErrorKind::BadSerialization, e.to_string()))?;
+    if helper.pure
+        && let Some(value) = PURE_RESULTS.lock().unwrap().get(&key)
+    {
+        return Ok(Prepared::Cached(value.clone()));
+    }
+
+    let to_json = |value: &Value| serde_json::to_value(value).map_err(|e| minijinja::Error::new(minijinja::ErrorKind::BadSerialization, e.to_string()));
+    let message = CallTemplateHelper {
+        attribute: helper.attribute.clone(),
+        args: args.iter().map(to_json).collect::<Result<_, _>>()?,
+        kwargs: named.iter().map(|(name, value)| Ok((name.clone(), to_json(value)?))).collect::<Result<_, minijinja::Error>>()?,
+    };
+    Ok(Prepared::Call(HelperCall { key, pure: helper.pure, message }))
+}
+
+// Makes the call. What a pure helper returns is kept for the next time it's called with the same arguments.
+pub async fn run(interpreter: &Addr<PythonInterpreterActor>, call: HelperCall) -> Result<Value, DetailedError> {
+    let value = match interpreter_queue::send(interpreter, call.message).await {
+        Ok(Ok(result)) if result.safe => Value::from_safe_string(result.value.as_str().unwrap_or_default().to_string()),
+        Ok(Ok(result)) => Value::from_serialize(&result.value),
+        Ok(Err(py_err)) => {
+            return Err(DetailedError {
+                error_source: Some(ErrorSource::Python(py_err.clone())),
+                message: py_err.message.clone(),
+                file_path: py_err.filename.clone().unwrap_or_default(),
+                line: py_err.line_number.unwrap_or(0) as u32,
+                column: py_err.column_number.unwrap_or(0) as u32,
+                end_line: py_err.end_line_number.map(|l| l as u32),
+                end_column: py_err.end_column_number.map(|c| c as u32),
+                ..Default::default()
+            });
+        }
+        Err(e) => {
+            log::error!("A mailbox error occurred: {}. This might indicate a problem with the server's internal communication.", e);
+            return Err(DetailedError { message: e.to_string(), file_path: format!("{}.py", MODULE), ..Default::default() });
+        }
+    };
+
+    if call.pure {
+        let mut cached = PURE_RESULTS.lock().unwrap();
+        if cached.len() >= MAX_PURE_RESULTS {
+            cached.clear();
+        }
+        cached.insert(call.key, value.clone());
+    }
+    Ok(value)
+}
+
+// What `run` came to, as the template sees it.
+pub fn template_result(name: &str, outcome: &Result<Value, DetailedError>) -> Result<Value, minijinja::Error> {
+    outcome.clone().map_err(|detailed_error| {
+        minijinja::Error::new(minijinja::ErrorKind::InvalidOperation, format!("the template helper '{}' crashed", name)).with_source(detailed_error)
+    })
+}
+
+// Reads what the decorators put on a function: `(kind, name, pure)`.
+pub fn helper_from(attribute: &str, tag: &Bound<PyAny>) -> PyResult<Option<TemplateHelper>> {
+    let (kind, name, pure): (String, String, bool) = tag.extract()?;
+    let kind = match kind.as_str() {
+        "filter" => HelperKind::Filter,
+        "global" => HelperKind::Global,
+        _ => return Ok(None),
+    };
+    Ok(Some(TemplateHelper { kind, name, attribute: attribute.to_string(), pure }))
+}
+
+// `from noventa_templates import template_filter, template_global`.
+pub fn register_python_module(py: Python) -> PyResult<()> {
+    let code = CString::new(crate::scripts::python_embed::TEMPLATE_HELPERS_PY).unwrap();
+    let filename = CString::new("noventa_templates.py").unwrap();
+    let module_name = CString::new("noventa_templates").unwrap();
+    let module = PyModule::from_code(py, &code, &filename, &module_name)?;
+    py.import("sys")?.getattr("modules")?.set_item("noventa_templates", module)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_built_in_names_are_reserved() {
+        for name in ["format", "datetime", "timeago", "number", "currency"] {
+            assert!(is_reserved(HelperKind::Filter, name), "{} should be reserved", name);
+        }
+        for name in ["component", "url_for", "url_for_signed", "srcset", "status"] {
+            assert!(is_reserved(HelperKind::Global, name), "{} should be reserved", name);
+        }
+        assert!(!is_reserved(HelperKind::Filter, "money"));
+        assert!(!is_reserved(HelperKind::Global, "currency"));
+    }
+
+    #[test]
+    fn test_decorators_tag_helpers() {
+        Python::attach(|py| {
+            register_python_module(py).unwrap();
+            let code = CString::new(
+                "from noventa_templates import template_filter, template_global\n\
+                 @template_filter(pure=True)\n\
+                 def money(value, currency='EUR'):\n    return f'{value:.2f} {currency}'\n\
+                 @template_filter(name='shout')\n\
+                 def upper(value):\n    return value.upper()\n\
+                 @template_global\n\
+                 def site_name():\n    return 'Noventa'\n",
+            )
+            .unwrap();
+            let module = PyModule::from_code(py, &code, &CString::new("helpers_test.py").unwrap(), &CString::new("helpers_test").unwrap()).unwrap();
+            let helper = |attribute: &str| helper_from(attribute, &module.getattr(attribute).unwrap().getattr(HELPER_ATTRIBUTE).unwrap()).unwrap().unwrap();
+
+            let money = helper("money");
+            assert_eq!((money.kind, money.name.as_str(), money.pure), (HelperKind::Filter, "money", true));
+            let upper = helper("upper");
+            assert_eq!((upper.kind, upper.name.as_str(), upper.attribute.as_str(), upper.pure), (HelperKind::Filter, "shout", "upper", false));
+            let site_name = helper("site_name");
+            assert_eq!((site_name.kind, site_name.name.as_str()), (HelperKind::Global, "site_name"));
+            assert_eq!(module.getattr("money").unwrap().call1((3,)).unwrap().extract::<String>().unwrap(), "3.00 EUR");
+        });
+    }
+}
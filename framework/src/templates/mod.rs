@@ -3,11 +3,17 @@ use minijinja::Environment;
 use once_cell::sync::Lazy;
 
 static DEBUG_ERROR_TEMPLATE: &str = include_str!("debug_error.html");
+static STATS_DASHBOARD_TEMPLATE: &str = include_str!("stats_dashboard.html");
+static ERROR_HISTORY_TEMPLATE: &str = include_str!("error_history.html");
 
 static JINJA_ENV: Lazy<Environment<'static>> = Lazy::new(|| {
     let mut env = Environment::new();
     env.add_template("debug_error.html", DEBUG_ERROR_TEMPLATE)
         .unwrap();
+    env.add_template("stats_dashboard.html", STATS_DASHBOARD_TEMPLATE)
+        .unwrap();
+    env.add_template("error_history.html", ERROR_HISTORY_TEMPLATE)
+        .unwrap();
     env
 });
 
@@ -18,28 +24,8 @@ pub fn render_structured_debug_error(detailed_error: &DetailedError) -> String {
     let mut context = std::collections::HashMap::new();
     context.insert("error", minijinja::Value::from_serialize(detailed_error));
 
-    if let Some(error_source) = &detailed_error.error_source {
-
-        let (source_code, line_number) = match error_source {
-            crate::errors::ErrorSource::Python(py_err) => (py_err.source_code.as_ref(), py_err.line_number),
-            crate::errors::ErrorSource::Template(tmpl_err) => (tmpl_err.source_code.as_ref(), Some(tmpl_err.line)),
-        };
-
-        if let (Some(code), Some(line_num)) = (source_code, line_number) {
-            let lines: Vec<_> = code.lines().collect();
-            let start_line = (line_num as isize - 7).max(0) as usize;
-            
-            let numbered_lines: Vec<_> = lines.iter().enumerate().map(|(i, line)| {
-                let num = start_line + i + 1;
-                let is_highlighted = num == line_num;
-                minijinja::context! {
-                    number => num,
-                    content => line,
-                    highlight => is_highlighted,
-                }
-            }).collect();
-            context.insert("code_snippet", minijinja::Value::from(numbered_lines));
-        }
+    if let Some(code_snippet) = code_snippet(detailed_error) {
+        context.insert("code_snippet", code_snippet);
     }
 
     let mut rendered = tmpl
@@ -53,6 +39,57 @@ pub fn render_structured_debug_error(detailed_error: &DetailedError) -> String {
     add_marker_and_scripts(&mut rendered);
     rendered
 }
+
+// The lines around where the error happened, numbered, with its line highlighted.
+fn code_snippet(detailed_error: &DetailedError) -> Option<minijinja::Value> {
+    let (source_code, line_number) = match detailed_error.error_source.as_ref()? {
+        crate::errors::ErrorSource::Python(py_err) => (py_err.source_code.as_ref(), py_err.line_number),
+        crate::errors::ErrorSource::Template(tmpl_err) => (tmpl_err.source_code.as_ref(), Some(tmpl_err.line)),
+    };
+    let (code, line_num) = (source_code?, line_number?);
+    let start_line = (line_num as isize - 7).max(0) as usize;
+
+    let numbered_lines: Vec<_> = code.lines().enumerate().map(|(i, line)| {
+        let num = start_line + i + 1;
+        let is_highlighted = num == line_num;
+        minijinja::context! {
+            number => num,
+            content => line,
+            highlight => is_highlighted,
+        }
+    }).collect();
+    Some(minijinja::Value::from(numbered_lines))
+}
+
+pub fn render_stats_dashboard(health: &crate::actors::health::SystemHealth, nonce: &str) -> String {
+    let tmpl = JINJA_ENV.get_template("stats_dashboard.html").unwrap();
+    tmpl.render(minijinja::context! { health => minijinja::Value::from_serialize(health), nonce => nonce })
+        .unwrap_or_else(|e| {
+            log::error!("Failed to render the stats dashboard: {}", e);
+            "<h1>Internal Server Error</h1><p>Could not render the stats dashboard.</p>".to_string()
+        })
+}
+
+pub fn render_error_history(history: &[crate::errors::PastError], nonce: &str) -> String {
+    let tmpl = JINJA_ENV.get_template("error_history.html").unwrap();
+    let errors: Vec<_> = history
+        .iter()
+        .map(|past| {
+            minijinja::context! {
+                time => past.time,
+                count => past.count,
+                error => minijinja::Value::from_serialize(&past.error),
+                code_snippet => code_snippet(&past.error),
+            }
+        })
+        .collect();
+    tmpl.render(minijinja::context! { errors => errors, nonce => nonce })
+        .unwrap_or_else(|e| {
+            log::error!("Failed to render the error history: {}", e);
+            "<h1>Internal Server Error</h1><p>Could not render the error history.</p>".to_string()
+        })
+}
+
 pub fn render_production_error(detailed_error: &DetailedError) -> String {
     log_production_error(detailed_error);
     "<h1>Internal Server Error</h1><p>An unexpected error occurred.</p>".to_string()
@@ -86,6 +123,8 @@ pub fn log_detailed_error(detailed_error: &DetailedError) {
 
     error_clone.file_path = normalized_path;
 
+    // Kept for `/_noventa/errors`: the channel only reaches whoever is listening right now.
+    crate::errors::remember_error(&error_clone);
     if let Err(e) = ERROR_CHANNEL.send(error_clone.to_json()) {
         log::error!("Failed to send error to ERROR_CHANNEL: {}", e);
     }
@@ -199,6 +238,49 @@ mod tests {
         assert_eq!(result, "<h1>Internal Server Error</h1><p>An unexpected error occurred.</p>");
     }
 
+    #[actix_rt::test]
+    async fn test_render_stats_dashboard() {
+        use crate::actors::health::{GetSystemHealth, HealthActor, ReportRequest};
+        use actix::Actor;
+
+        let health_actor = HealthActor::new().start();
+        health_actor.do_send(ReportRequest { route: "pages/<cart>.html".to_string(), status: 500, duration_ms: 12.5, python_error: true });
+        let health = health_actor.send(GetSystemHealth).await.unwrap();
+        let html = render_stats_dashboard(&health, "abc");
+        assert!(html.contains("<style nonce=\"abc\">"));
+        assert!(html.contains("&lt;cart&gt;.html</code>"), "{}", html);
+        assert!(html.contains("500&times;1"));
+        assert!(html.contains("12.5 ms"));
+    }
+
+    #[test]
+    fn test_render_error_history() {
+        let python_error = PythonError {
+            message: "ZeroDivisionError: division by zero".to_string(),
+            traceback: "Traceback (most recent call last):".to_string(),
+            line_number: Some(3),
+            filename: Some("components/cart/cart_logic.py".to_string()),
+            source_code: Some("def load_template_context(request):\n    items = []\n    return 1 / len(items)".to_string()),
+            ..Default::default()
+        };
+        let history = vec![crate::errors::PastError {
+            time: "2026-10-16 10:04:12".to_string(),
+            count: 3,
+            error: DetailedError {
+                message: "ZeroDivisionError: division by zero".to_string(),
+                route: Some("/cart".to_string()),
+                error_source: Some(ErrorSource::Python(python_error)),
+                ..Default::default()
+            },
+        }];
+        let html = render_error_history(&history, "abc");
+        assert!(html.contains("<style nonce=\"abc\">"));
+        assert!(html.contains("3 times"));
+        assert!(html.contains("<code>&#x2f;cart</code>"));
+        assert!(html.contains("class=\"line highlight\"><span class=\"number\">3</span><pre>    return 1 &#x2f; len(items)</pre>"), "{}", html);
+        assert!(render_error_history(&[], "abc").contains("No errors yet"));
+    }
+
     #[test]
     fn test_add_marker_and_scripts_with_body() {
         let mut html = "<html><body>Hello</body></html>".to_string();
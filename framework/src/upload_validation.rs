@@ -0,0 +1,367 @@
+use crate::actors::page_renderer::FileData;
+use crate::config::UploadValidationConfig;
+use crate::object_storage::BUCKET;
+use std::io::Read;
+use std::path::Path;
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+
+// Enough of the start of a file to find its type and, for JPEGs behind big EXIF blocks, its size.
+pub const SNIFF_BYTES: usize = 128 * 1024;
+const DEFAULT_SCAN_TIMEOUT_SECS: u64 = 30;
+
+#[derive(Debug, PartialEq)]
+pub enum Rejection {
+    // The file breaks a rule or the scanner flagged it; the message is shown to the client.
+    Refused(String),
+    // The scanner couldn't give an answer, so the file isn't let through either.
+    ScanFailed(String),
+}
+
+// The type a file's first bytes reveal. Whatever isn't recognized is text or octet-stream.
+pub fn sniff(head: &[u8]) -> &'static str {
+    let starts = |signature: &[u8]| head.starts_with(signature);
+    let at = |offset: usize, signature: &[u8]| head.get(offset..offset + signature.len()) == Some(signature);
+
+    if starts(b"\x89PNG\r\n\x1a\n") {
+        "image/png"
+    } else if starts(b"\xff\xd8\xff") {
+        "image/jpeg"
+    } else if starts(b"GIF87a") || starts(b"GIF89a") {
+        "image/gif"
+    } else if starts(b"RIFF") && at(8, b"WEBP") {
+        "image/webp"
+    } else if starts(b"RIFF") && at(8, b"WAVE") {
+        "audio/wav"
+    } else if starts(b"RIFF") && at(8, b"AVI ") {
+        "video/x-msvideo"
+    } else if starts(b"BM") && head.len() >= 26 && at(6, b"\0\0\0\0") {
+        "image/bmp"
+    } else if starts(b"\0\0\x01\0") {
+        "image/x-icon"
+    } else if at(4, b"ftyp") {
+        match head.get(8..12) {
+            Some(b"heic") | Some(b"heix") | Some(b"mif1") => "image/heic",
+            Some(b"avif") => "image/avif",
+            Some(b"qt  ") => "video/quicktime",
+            _ => "video/mp4",
+        }
+    } else if starts(b"\x1a\x45\xdf\xa3") {
+        "video/webm"
+    } else if starts(b"OggS") {
+        "audio/ogg"
+    } else if starts(b"fLaC") {
+        "audio/flac"
+    } else if starts(b"ID3") || head.len() >= 2 && head[0] == 0xff && head[1] & 0xe0 == 0xe0 {
+        "audio/mpeg"
+    } else if starts(b"%PDF-") {
+        "application/pdf"
+    } else if starts(b"PK\x03\x04") || starts(b"PK\x05\x06") {
+        "application/zip"
+    } else if starts(b"\x1f\x8b") {
+        "application/gzip"
+    } else if starts(b"7z\xbc\xaf\x27\x1c") {
+        "application/x-7z-compressed"
+    } else if starts(b"Rar!\x1a\x07") {
+        "application/vnd.rar"
+    } else if starts(b"\xd0\xcf\x11\xe0\xa1\xb1\x1a\xe1") {
+        "application/x-ole-storage"
+    } else if starts(b"MZ") {
+        "application/x-msdownload"
+    } else if starts(b"\x7fELF") {
+        "application/x-executable"
+    } else if starts(b"\xcf\xfa\xed\xfe") || starts(b"\xce\xfa\xed\xfe") || starts(b"\xca\xfe\xba\xbe") {
+        "application/x-mach-binary"
+    } else {
+        sniff_text(head)
+    }
+}
+
+fn sniff_text(head: &[u8]) -> &'static str {
+    let head = head.strip_prefix(b"\xef\xbb\xbf").unwrap_or(head);
+    // The head may cut a multi-byte character in half; only a real encoding error means binary.
+    let text = match std::str::from_utf8(head) {
+        Ok(text) => text,
+        Err(e) if e.error_len().is_none() => std::str::from_utf8(&head[..e.valid_up_to()]).unwrap_or_default(),
+        Err(_) => return "application/octet-stream",
+    };
+    if text.contains('\0') {
+        return "application/octet-stream";
+    }
+    let start = text.trim_start().to_ascii_lowercase();
+    if start.starts_with("#!") {
+        "text/x-shellscript"
+    } else if start.starts_with("<svg") || start.starts_with("<?xml") && start.contains("<svg") {
+        "image/svg+xml"
+    } else if start.starts_with("<!doctype html") || start.starts_with("<html") || start.starts_with("<script") {
+        "text/html"
+    } else if start.starts_with("<?xml") {
+        "application/xml"
+    } else {
+        "text/plain"
+    }
+}
+
+pub fn read_head(path: &Path) -> Vec<u8> {
+    let mut head = Vec::new();
+    if let Ok(file) = std::fs::File::open(path) {
+        let _ = file.take(SNIFF_BYTES as u64).read_to_end(&mut head);
+    }
+    head
+}
+
+pub fn sniff_file(path: &Path) -> &'static str {
+    sniff(&read_head(path))
+}
+
+fn u16_be(bytes: &[u8], at: usize) -> Option<u32> {
+    bytes.get(at..at + 2).map(|b| u16::from_be_bytes([b[0], b[1]]) as u32)
+}
+
+fn u16_le(bytes: &[u8], at: usize) -> Option<u32> {
+    bytes.get(at..at + 2).map(|b| u16::from_le_bytes([b[0], b[1]]) as u32)
+}
+
+fn u24_le(bytes: &[u8], at: usize) -> Option<u32> {
+    bytes.get(at..at + 3).map(|b| u32::from_le_bytes([b[0], b[1], b[2], 0]))
+}
+
+fn u32_be(bytes: &[u8], at: usize) -> Option<u32> {
+    bytes.get(at..at + 4).map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+fn i32_le(bytes: &[u8], at: usize) -> Option<i32> {
+    bytes.get(at..at + 4).map(|b| i32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+// Width and height read from the image header, without decoding any pixels.
+pub fn image_dimensions(mime: &str, head: &[u8]) -> Option<(u32, u32)> {
+    match mime {
+        "image/png" => Some((u32_be(head, 16)?, u32_be(head, 20)?)),
+        "image/gif" => Some((u16_le(head, 6)?, u16_le(head, 8)?)),
+        "image/bmp" => Some((i32_le(head, 18)?.unsigned_abs(), i32_le(head, 22)?.unsigned_abs())),
+        "image/webp" => match head.get(12..16)? {
+            b"VP8 " => Some((u16_le(head, 26)? & 0x3fff, u16_le(head, 28)? & 0x3fff)),
+            b"VP8L" => {
+                let b = head.get(21..25)?;
+                let width = 1 + (b[0] as u32 | (b[1] as u32 & 0x3f) << 8);
+                let height = 1 + (b[1] as u32 >> 6 | (b[2] as u32) << 2 | (b[3] as u32 & 0x0f) << 10);
+                Some((width, height))
+            }
+            b"VP8X" => Some((1 + u24_le(head, 24)?, 1 + u24_le(head, 27)?)),
+            _ => None,
+        },
+        "image/jpeg" => jpeg_dimensions(head),
+        _ => None,
+    }
+}
+
+// Walks the JPEG segments up to the first start-of-frame, which holds the size.
+fn jpeg_dimensions(head: &[u8]) -> Option<(u32, u32)> {
+    let mut pos = 2;
+    loop {
+        while *head.get(pos)? != 0xff {
+            pos += 1;
+        }
+        while *head.get(pos)? == 0xff {
+            pos += 1;
+        }
+        let marker = *head.get(pos)?;
+        pos += 1;
+        match marker {
+            0xc0..=0xcf if !matches!(marker, 0xc4 | 0xc8 | 0xcc) => {
+                return Some((u16_be(head, pos + 5)?, u16_be(head, pos + 3)?));
+            }
+            0x01 | 0xd0..=0xd7 => {}
+            _ => pos += u16_be(head, pos)? as usize,
+        }
+    }
+}
+
+fn matches(pattern: &str, mime: &str) -> bool {
+    let pattern = pattern.trim().to_ascii_lowercase();
+    match pattern.strip_suffix("/*") {
+        Some(kind) => mime.split('/').next() == Some(kind),
+        None => pattern == "*" || pattern == mime,
+    }
+}
+
+// Checks a file's type (and an image's size) against the rules for its form field.
+pub fn check(
+    config: &UploadValidationConfig,
+    field: Option<&str>,
+    filename: &str,
+    mime: &str,
+    head: &[u8],
+) -> Result<(), Rejection> {
+    let rules = field.and_then(|field| config.fields.as_ref()?.get(field));
+    let allow = rules.and_then(|rules| rules.allow.as_ref()).or(config.allow.as_ref());
+    let deny = rules.and_then(|rules| rules.deny.as_ref()).or(config.deny.as_ref());
+    let max_width = rules.and_then(|rules| rules.max_width).or(config.max_width);
+    let max_height = rules.and_then(|rules| rules.max_height).or(config.max_height);
+
+    let allowed = allow.is_none_or(|allow| allow.iter().any(|pattern| matches(pattern, mime)));
+    let denied = deny.is_some_and(|deny| deny.iter().any(|pattern| matches(pattern, mime)));
+    if !allowed || denied {
+        return Err(Rejection::Refused(format!("'{}' looks like {}, which isn't accepted here.", filename, mime)));
+    }
+
+    if (max_width.is_some() || max_height.is_some())
+        && matches!(mime, "image/png" | "image/jpeg" | "image/gif" | "image/webp" | "image/bmp")
+    {
+        let (width, height) = image_dimensions(mime, head)
+            .ok_or_else(|| Rejection::Refused(format!("We couldn't read the size of the image '{}'.", filename)))?;
+        if max_width.is_some_and(|max| width > max) || max_height.is_some_and(|max| height > max) {
+            return Err(Rejection::Refused(format!(
+                "The image '{}' is {}x{} pixels, which is larger than allowed.",
+                filename, width, height
+            )));
+        }
+    }
+    Ok(())
+}
+
+async fn feed(stdin: &mut tokio::process::ChildStdin, data: &FileData) -> Result<(), String> {
+    match data {
+        FileData::InMemory(bytes) => stdin.write_all(bytes).await.map_err(|e| e.to_string()),
+        FileData::OnDisk(path) => {
+            let mut file = tokio::fs::File::open(path).await.map_err(|e| e.to_string())?;
+            tokio::io::copy(&mut file, stdin).await.map(|_| ()).map_err(|e| e.to_string())
+        }
+        FileData::Remote(key) => {
+            let bucket = BUCKET.as_ref().ok_or("the upload bucket isn't available")?;
+            let mut response = bucket.open(key).await?;
+            while let Some(chunk) = response.chunk().await.map_err(|e| e.to_string())? {
+                stdin.write_all(&chunk).await.map_err(|e| e.to_string())?;
+            }
+            Ok(())
+        }
+    }
+}
+
+// Runs `scan_command` with the file on stdin before any page code gets to see it.
+pub async fn scan(config: &UploadValidationConfig, filename: &str, data: &FileData) -> Result<(), Rejection> {
+    let Some((program, args)) = config.scan_command.as_ref().and_then(|command| command.split_first()) else {
+        return Ok(());
+    };
+    let timeout = Duration::from_secs(config.scan_timeout.unwrap_or(DEFAULT_SCAN_TIMEOUT_SECS));
+
+    let run = async {
+        let mut child = tokio::process::Command::new(program)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| format!("couldn't start `{}`: {}", program, e))?;
+        if let Some(mut stdin) = child.stdin.take() {
+            // A scanner may stop reading as soon as it has made up its mind.
+            if let Err(e) = feed(&mut stdin, data).await
+                && !e.contains("Broken pipe")
+            {
+                return Err(e);
+            }
+        }
+        child.wait_with_output().await.map_err(|e| e.to_string())
+    };
+
+    let output = tokio::time::timeout(timeout, run)
+        .await
+        .map_err(|_| Rejection::ScanFailed(format!("`{}` took longer than {}s", program, timeout.as_secs())))?
+        .map_err(Rejection::ScanFailed)?;
+    match output.status.code() {
+        Some(0) => Ok(()),
+        Some(1) => {
+            log::warn!(
+                "Refused the upload '{}' because `{}` flagged it: {}",
+                filename,
+                program,
+                String::from_utf8_lossy(&output.stdout).trim()
+            );
+            Err(Rejection::Refused(format!("'{}' didn't pass the security scan.", filename)))
+        }
+        _ => Err(Rejection::ScanFailed(format!(
+            "`{}` exited with {}: {}",
+            program,
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::UploadRulesConfig;
+    use std::collections::HashMap;
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let mut bytes = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR".to_vec();
+        bytes.extend_from_slice(&width.to_be_bytes());
+        bytes.extend_from_slice(&height.to_be_bytes());
+        bytes
+    }
+
+    #[test]
+    fn test_sniff() {
+        assert_eq!(sniff(&png(1, 1)), "image/png");
+        assert_eq!(sniff(b"\xff\xd8\xff\xe0\0\x10JFIF"), "image/jpeg");
+        assert_eq!(sniff(b"%PDF-1.7\n"), "application/pdf");
+        assert_eq!(sniff(b"MZ\x90\0\x03"), "application/x-msdownload");
+        assert_eq!(sniff(b"\0\0\0\x18ftypmp42"), "video/mp4");
+        assert_eq!(sniff(b"<?xml version=\"1.0\"?>\n<svg xmlns=\"http://www.w3.org/2000/svg\">"), "image/svg+xml");
+        assert_eq!(sniff(b"  <!DOCTYPE html><html>"), "text/html");
+        assert_eq!(sniff("hello, wörld".as_bytes()), "text/plain");
+        assert_eq!(sniff(b"\x01\x02\x03\xfe"), "application/octet-stream");
+    }
+
+    #[test]
+    fn test_image_dimensions() {
+        assert_eq!(image_dimensions("image/png", &png(640, 480)), Some((640, 480)));
+        assert_eq!(image_dimensions("image/gif", b"GIF89a\x20\x03\x58\x02"), Some((800, 600)));
+        // SOI, an APP0 segment, then a baseline frame header for 300x200.
+        let jpeg = b"\xff\xd8\xff\xe0\x00\x04ab\xff\xc0\x00\x11\x08\x00\xc8\x01\x2c\x03";
+        assert_eq!(image_dimensions("image/jpeg", jpeg), Some((300, 200)));
+        assert_eq!(image_dimensions("image/jpeg", b"\xff\xd8\xff\xe0\x00\x10"), None);
+    }
+
+    #[test]
+    fn test_check() {
+        let mut fields = HashMap::new();
+        fields.insert(
+            "avatar".to_string(),
+            UploadRulesConfig { allow: Some(vec!["image/png".to_string()]), max_width: Some(512), ..Default::default() },
+        );
+        let config = UploadValidationConfig {
+            allow: Some(vec!["image/*".to_string(), "application/pdf".to_string()]),
+            deny: Some(vec!["image/svg+xml".to_string()]),
+            fields: Some(fields),
+            ..Default::default()
+        };
+
+        assert_eq!(check(&config, Some("doc"), "a.pdf", "application/pdf", b""), Ok(()));
+        assert_eq!(check(&config, Some("doc"), "b.png", "image/png", &png(4000, 4000)), Ok(()));
+        assert!(check(&config, Some("doc"), "c.exe", "application/x-msdownload", b"").is_err());
+        assert!(check(&config, Some("doc"), "d.svg", "image/svg+xml", b"").is_err());
+
+        assert_eq!(check(&config, Some("avatar"), "e.png", "image/png", &png(512, 512)), Ok(()));
+        assert!(check(&config, Some("avatar"), "f.png", "image/png", &png(513, 10)).is_err());
+        assert!(check(&config, Some("avatar"), "g.pdf", "application/pdf", b"").is_err());
+    }
+
+    #[actix_rt::test]
+    async fn test_scan() {
+        let data = FileData::InMemory(b"X5O!P%@AP".to_vec());
+        let command = |script: &str| UploadValidationConfig {
+            scan_command: Some(vec!["sh".to_string(), "-c".to_string(), script.to_string()]),
+            ..Default::default()
+        };
+
+        assert_eq!(scan(&UploadValidationConfig::default(), "a", &data).await, Ok(()));
+        assert_eq!(scan(&command("cat > /dev/null"), "a", &data).await, Ok(()));
+        assert!(matches!(scan(&command("grep -q X5O && exit 1"), "a", &data).await, Err(Rejection::Refused(_))));
+        assert!(matches!(scan(&command("exit 2"), "a", &data).await, Err(Rejection::ScanFailed(_))));
+    }
+}
@@ -0,0 +1,191 @@
+use crate::actors::user_websockets::UserWebSocket;
+use crate::actors::ws_server::WsServer;
+use actix::Addr;
+use actix_session::{Session, SessionExt};
+use actix_web::{web, Error, HttpRequest, HttpResponse};
+use actix_web_actors::ws;
+use lazy_static::lazy_static;
+use pyo3::exceptions::{PyTypeError, PyValueError};
+use pyo3::prelude::*;
+use serde_json::Value;
+use tokio::sync::broadcast;
+
+// `from noventa_channels import broadcast` in Python: `broadcast("user:123", {"unread": 3})` sends the
+// payload to every page user 123 has open with a WebSocket to `/_noventa/ws`. Who a socket belongs to
+// comes from its session, never from the browser, and it's closed when that login ends.
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum UserEvent {
+    // The JSON text for every connection of `user`.
+    Message { user: String, message: String },
+    // The login with this id ended: logged out, or someone else logged in on that session.
+    LoggedOut { login: String },
+}
+
+lazy_static! {
+    // Picked up by WsServer. Only this instance's connections get them; `publish` tells the others.
+    pub static ref USER_CHANNEL: broadcast::Sender<UserEvent> = broadcast::channel(100).0;
+}
+
+// Who a WebSocket connection belongs to, read from the session it was opened with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Identity {
+    // As channels name it: `123` for `user:123`.
+    pub user: String,
+    pub login: String,
+}
+
+pub fn enabled() -> bool {
+    crate::config::CONFIG.auth.is_some()
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig, server: Addr<WsServer>) {
+    cfg.app_data(web::Data::new(server)).route("/_noventa/ws", web::get().to(connect));
+}
+
+// The user id as it's written after `user:`, e.g. `123` or `google:1087` for a login provider's user.
+fn channel_user(id: &Value) -> String {
+    match id {
+        Value::String(id) => id.clone(),
+        id => id.to_string(),
+    }
+}
+
+pub fn identity(session: &Session) -> Option<Identity> {
+    let user = session.get::<Value>(crate::auth::user_key()).ok().flatten().filter(|id| !id.is_null())?;
+    // Sessions logged in before channels existed have no login id, so nothing could close their sockets.
+    let login = session.get::<String>(crate::auth::LOGIN_KEY).ok().flatten()?;
+    Some(Identity { user: channel_user(&user), login })
+}
+
+// A fresh id for a login, stored under `auth::LOGIN_KEY`.
+pub fn new_login() -> String {
+    uuid::Uuid::new_v4().simple().to_string()
+}
+
+// Hands `event` to this instance's connections and, with a `cluster`, to every other instance's.
+fn publish(event: UserEvent) {
+    // Without receivers nobody is connected here, which is fine.
+    let _ = USER_CHANNEL.send(event.clone());
+    crate::cluster::broadcast(event.into());
+}
+
+// Closes every WebSocket opened during `login`.
+pub fn logged_out(login: String) {
+    publish(UserEvent::LoggedOut { login });
+}
+
+fn parse_channel(channel: &str) -> Result<String, String> {
+    match channel.strip_prefix("user:") {
+        Some(user) if !user.is_empty() => Ok(user.to_string()),
+        _ => Err(format!("'{}' isn't a channel. Channels name a logged-in user, like 'user:123'.", channel)),
+    }
+}
+
+// Pages opened from another site can't listen in with the visitor's cookie.
+fn same_origin(req: &HttpRequest) -> bool {
+    let Some(origin) = req.headers().get("origin").and_then(|origin| origin.to_str().ok()) else {
+        return true;
+    };
+    let host = origin.split_once("://").map_or(origin, |(_, host)| host);
+    // Forwarded hosts only count when a trusted proxy sent them.
+    host.eq_ignore_ascii_case(&crate::proxy::client_info(req, &crate::proxy::TRUSTED_PROXIES).host)
+}
+
+async fn connect(req: HttpRequest, stream: web::Payload, server: web::Data<Addr<WsServer>>) -> Result<HttpResponse, Error> {
+    if !same_origin(&req) {
+        return Ok(HttpResponse::Forbidden().finish());
+    }
+    let Some(identity) = identity(&req.get_session()) else {
+        return Ok(HttpResponse::Unauthorized().finish());
+    };
+    ws::start(UserWebSocket::new(server.get_ref().clone(), identity), &req, stream)
+}
+
+#[pyfunction]
+#[pyo3(name = "broadcast")]
+fn py_broadcast(channel: &str, payload: Bound<PyAny>) -> PyResult<()> {
+    let user = parse_channel(channel).map_err(PyValueError::new_err)?;
+    let payload: Value = pythonize::depythonize(&payload).map_err(|e| {
+        PyTypeError::new_err(format!("Broadcast payloads must be JSON-serializable (dicts, lists, strings, numbers, booleans or None): {}", e))
+    })?;
+    let message = serde_json::json!({ "channel": channel, "payload": payload }).to_string();
+    publish(UserEvent::Message { user, message });
+    Ok(())
+}
+
+pub fn register_python_module(py: Python) -> PyResult<()> {
+    let module = PyModule::new(py, "noventa_channels")?;
+    module.add_function(wrap_pyfunction!(py_broadcast, &module)?)?;
+    py.import("sys")?.getattr("modules")?.set_item("noventa_channels", module)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+    use std::ffi::CString;
+
+    #[test]
+    fn test_parse_channel() {
+        assert_eq!(parse_channel("user:123").unwrap(), "123");
+        assert_eq!(parse_channel("user:google:1087").unwrap(), "google:1087");
+        assert!(parse_channel("user:").is_err());
+        assert!(parse_channel("admins").is_err());
+    }
+
+    #[test]
+    fn test_identity() {
+        let session = TestRequest::default().to_http_request().get_session();
+        assert_eq!(identity(&session), None);
+
+        session.insert(crate::auth::user_key(), 123).unwrap();
+        // Logged in, but before login ids existed.
+        assert_eq!(identity(&session), None);
+
+        session.insert(crate::auth::LOGIN_KEY, "abc").unwrap();
+        assert_eq!(identity(&session), Some(Identity { user: "123".to_string(), login: "abc".to_string() }));
+        session.insert(crate::auth::user_key(), "google:1087").unwrap();
+        assert_eq!(identity(&session).unwrap().user, "google:1087");
+    }
+
+    #[test]
+    fn test_same_origin() {
+        let req = TestRequest::default().insert_header(("host", "shop.example")).to_http_request();
+        assert!(same_origin(&req));
+        let req = TestRequest::default().insert_header(("host", "shop.example")).insert_header(("origin", "https://shop.example")).to_http_request();
+        assert!(same_origin(&req));
+        let req = TestRequest::default().insert_header(("host", "shop.example")).insert_header(("origin", "https://evil.example")).to_http_request();
+        assert!(!same_origin(&req));
+        // Anyone can send X-Forwarded-Host; it isn't believed from a peer that isn't a trusted proxy.
+        let req = TestRequest::default()
+            .peer_addr("203.0.113.9:5000".parse().unwrap())
+            .insert_header(("host", "shop.example"))
+            .insert_header(("x-forwarded-host", "evil.example"))
+            .insert_header(("origin", "https://evil.example"))
+            .to_http_request();
+        assert!(!same_origin(&req));
+    }
+
+    #[test]
+    fn test_broadcast() {
+        let mut received = USER_CHANNEL.subscribe();
+        Python::attach(|py| {
+            register_python_module(py).unwrap();
+            let code = CString::new("from noventa_channels import broadcast\nbroadcast('user:channels-test', {'unread': 3})\n").unwrap();
+            py.run(&code, None, None).unwrap();
+            let bad = CString::new("from noventa_channels import broadcast\nbroadcast('everyone', {})\n").unwrap();
+            assert!(py.run(&bad, None, None).is_err());
+        });
+
+        // Other tests broadcast too; only this one's message matters.
+        let message = std::iter::from_fn(|| received.try_recv().ok())
+            .find_map(|event| match event {
+                UserEvent::Message { user, message } if user == "channels-test" => Some(message),
+                _ => None,
+            })
+            .unwrap();
+        let message: Value = serde_json::from_str(&message).unwrap();
+        assert_eq!(message, serde_json::json!({ "channel": "user:channels-test", "payload": { "unread": 3 } }));
+    }
+}
@@ -0,0 +1,81 @@
+use crate::config;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+// False while `warm_routes` are being rendered; /health/ready waits for it.
+static WARM: AtomicBool = AtomicBool::new(true);
+
+pub fn is_warm() -> bool {
+    WARM.load(Ordering::Relaxed)
+}
+
+// Where to reach this server from inside it: `0.0.0.0` and `::` listen on loopback too.
+fn local_url(addr: SocketAddr) -> String {
+    let ip = match addr.ip() {
+        IpAddr::V4(ip) if ip.is_unspecified() => IpAddr::V4(Ipv4Addr::LOCALHOST),
+        IpAddr::V6(ip) if ip.is_unspecified() => IpAddr::V6(Ipv6Addr::LOCALHOST),
+        ip => ip,
+    };
+    format!("http://{}", SocketAddr::new(ip, addr.port()))
+}
+
+// Renders `warm_routes` once against the server that was just started, so the templates are
+// compiled and the Python modules imported before real visitors arrive.
+pub fn start(addrs: &[SocketAddr]) {
+    let routes = config::CONFIG.warm_routes.clone().unwrap_or_default();
+    if routes.is_empty() {
+        return;
+    }
+    let Some(addr) = addrs.first() else {
+        log::warn!("Heads up! `warm_routes` needs a TCP address to reach the server on, and it's only on a unix socket. Skipping the warm-up.");
+        return;
+    };
+    WARM.store(false, Ordering::Relaxed);
+    let base_url = local_url(*addr);
+    actix_rt::spawn(async move {
+        warm_up(&base_url, &routes).await;
+        WARM.store(true, Ordering::Relaxed);
+    });
+}
+
+async fn warm_up(base_url: &str, routes: &[String]) {
+    let client = match reqwest::Client::builder()
+        .timeout(Duration::from_secs(60))
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+    {
+        Ok(client) => client,
+        Err(e) => {
+            log::warn!("Heads up! Couldn't warm up the routes: {}", e);
+            return;
+        }
+    };
+    let started = Instant::now();
+    for route in routes {
+        let route_started = Instant::now();
+        match client.get(format!("{}{}", base_url, route)).send().await {
+            Ok(response) if !response.status().is_success() && !response.status().is_redirection() => {
+                log::warn!("Heads up! Warming up {} got a {}. Is it still in `warm_routes` on purpose?", route, response.status());
+            }
+            Ok(response) => {
+                let _ = response.bytes().await;
+                log::debug!("Warmed up {} in {} ms.", route, route_started.elapsed().as_millis());
+            }
+            Err(e) => log::warn!("Heads up! Couldn't warm up {}: {}", route, e),
+        }
+    }
+    log::info!("✨ Warmed up {} routes in {:.1}s. Ready for visitors.", routes.len(), started.elapsed().as_secs_f64());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_local_url() {
+        assert_eq!(local_url("0.0.0.0:8080".parse().unwrap()), "http://127.0.0.1:8080");
+        assert_eq!(local_url("[::]:8080".parse().unwrap()), "http://[::1]:8080");
+        assert_eq!(local_url("10.0.0.5:3000".parse().unwrap()), "http://10.0.0.5:3000");
+    }
+}
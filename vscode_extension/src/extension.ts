@@ -1,4 +1,6 @@
+import * as fs from 'fs';
 import * as net from 'net';
+import * as path from 'path';
 import * as vscode from 'vscode';
 import {
   LanguageClient,
@@ -12,7 +14,23 @@ let client: LanguageClient;
 export async function activate(context: vscode.ExtensionContext) {
   const config = vscode.workspace.getConfiguration('noventaExtension');
   const enabled = config.get('enable', true);
-  const port = config.get('port', 9090);
+  const configuredPort = config.get('port', 9090);
+
+  // `noventa dev` writes the port it ended up on here when the configured one was taken.
+  const currentPort = (): number => {
+    const folder = vscode.workspace.workspaceFolders?.[0];
+    if (folder) {
+      try {
+        const port = parseInt(fs.readFileSync(path.join(folder.uri.fsPath, '.noventa-lsp-port'), 'utf8'), 10);
+        if (port > 0) {
+          return port;
+        }
+      } catch {
+        // No `noventa dev` has run here yet.
+      }
+    }
+    return configuredPort;
+  };
 
   if (!enabled) {
     return;
@@ -23,6 +41,7 @@ export async function activate(context: vscode.ExtensionContext) {
   const serverOptions: ServerOptions = () => {
     return new Promise((resolve) => {
       const connectToServer = () => {
+        const port = currentPort();
         outputChannel.appendLine(`Attempting to connect to Noventa server on port ${port}...`);
         const socket = net.connect({ port });
 